@@ -34,6 +34,7 @@ Usage
     rad node stop [<option>...]
     rad node logs [-n <lines>]
     rad node connect <nid>@<addr> [<option>...]
+    rad node check [--addr <addr>] [--rid <rid>] [<option>...]
     rad node routing [--rid <rid>] [--nid <nid>] [--json] [<option>...]
     rad node events [--timeout <secs>] [-n <count>] [<option>...]
     rad node config [--addresses]
@@ -47,6 +48,12 @@ Start options
     --path <path>        Start node binary at path (default: radicle-node)
     --verbose, -v        Verbose output
 
+Check options
+
+    --addr <addr>        Dial the node on the given address, eg. its external address
+                         (default: the first listen address)
+    --rid <rid>          Request the given repository (default: a small public repository)
+
 Routing options
 
     --rid <rid>          Show the routing table entries for the given RID
@@ -69,6 +76,10 @@ pub struct Options {
 }
 
 pub enum Operation {
+    Check {
+        addr: Option<Address>,
+        rid: Option<RepoId>,
+    },
     Connect {
         addr: PeerAddr<NodeId, Address>,
         timeout: time::Duration,
@@ -104,6 +115,7 @@ pub enum Operation {
 
 #[derive(Default, PartialEq, Eq)]
 pub enum OperationName {
+    Check,
     Connect,
     Config,
     Db,
@@ -129,6 +141,7 @@ impl Args for Options {
         let mut rid: Option<RepoId> = None;
         let mut json: bool = false;
         let mut addr: Option<PeerAddr<NodeId, Address>> = None;
+        let mut check_addr: Option<Address> = None;
         let mut lines: usize = 60;
         let mut count: usize = usize::MAX;
         let mut timeout = time::Duration::MAX;
@@ -142,6 +155,7 @@ impl Args for Options {
                     return Err(Error::Help.into());
                 }
                 Value(val) if op.is_none() => match val.to_string_lossy().as_ref() {
+                    "check" => op = Some(OperationName::Check),
                     "connect" => op = Some(OperationName::Connect),
                     "db" => op = Some(OperationName::Db),
                     "events" => op = Some(OperationName::Events),
@@ -162,6 +176,14 @@ impl Args for Options {
                     let val = parser.value()?;
                     rid = term::args::rid(&val).ok();
                 }
+                Long("rid") if matches!(op, Some(OperationName::Check)) => {
+                    let val = parser.value()?;
+                    rid = Some(term::args::rid(&val)?);
+                }
+                Long("addr") if matches!(op, Some(OperationName::Check)) => {
+                    let val = parser.value()?;
+                    check_addr = Some(term::args::addr(&val)?);
+                }
                 Long("nid") if matches!(op, Some(OperationName::Routing)) => {
                     let val = parser.value()?;
                     nid = term::args::nid(&val).ok();
@@ -204,6 +226,10 @@ impl Args for Options {
         }
 
         let op = match op.unwrap_or_default() {
+            OperationName::Check => Operation::Check {
+                addr: check_addr,
+                rid,
+            },
             OperationName::Connect => Operation::Connect {
                 addr: addr.ok_or_else(|| {
                     anyhow!("an address of the form `<nid>@<host>:<port>` must be provided")
@@ -234,6 +260,7 @@ pub fn run(options: Options, ctx: impl term::Context) -> anyhow::Result<()> {
     let mut node = Node::new(profile.socket());
 
     match options.op {
+        Operation::Check { addr, rid } => control::check(&node, addr, rid)?,
         Operation::Connect { addr, timeout } => {
             control::connect(&mut node, addr.id, addr.addr, timeout)?
         }
@@ -8,6 +8,7 @@ use localtime::LocalTime;
 
 use radicle::node;
 use radicle::node::{Address, ConnectResult, Handle as _, NodeId};
+use radicle::prelude::RepoId;
 use radicle::Node;
 use radicle::{profile, Profile};
 
@@ -190,6 +191,26 @@ pub fn connect(
     Ok(())
 }
 
+pub fn check(node: &Node, addr: Option<Address>, rid: Option<RepoId>) -> anyhow::Result<()> {
+    let check = node.self_check(addr, rid)?;
+
+    term::success!(
+        "Node is {} on {} and serves {}.",
+        term::format::positive("reachable"),
+        check.addr,
+        term::format::tertiary(check.rid)
+    );
+    term::info!(
+        "Connection took {} ms, handshake {} ms, subscription {} ms and upload {} ms, with {} reference(s) listed.",
+        check.connect,
+        check.handshake,
+        check.subscribe,
+        check.upload,
+        check.refs
+    );
+    Ok(())
+}
+
 pub fn status(node: &Node, profile: &Profile) -> anyhow::Result<()> {
     if node.is_running() {
         let listen = node
@@ -216,6 +237,35 @@ pub fn status(node: &Node, profile: &Profile) -> anyhow::Result<()> {
         return Ok(());
     }
 
+    let announced = node
+        .announced_addrs()?
+        .into_iter()
+        .map(|(addr, label)| format!("{addr} ({label})"))
+        .collect::<Vec<_>>();
+    if !announced.is_empty() {
+        term::info!("Announcing {}.", announced.join(", "));
+    }
+
+    let filter = node.filter()?;
+    term::info!(
+        "Subscription filter is {} bytes, for {} seeded repository(s), with a {:.2}% false positive rate.",
+        filter.size,
+        filter.seeded,
+        filter.false_positive_rate * 100.
+    );
+
+    let health = node.health()?;
+    match health.overall.reason() {
+        Some(reason) => term::info!("Health is {} ({reason}).", health.overall),
+        None => term::info!("Health is {}.", health.overall),
+    }
+    if health.worker_restarts > 0 {
+        term::info!(
+            "Workers recovered from {} panic(s).",
+            health.worker_restarts
+        );
+    }
+
     let sessions = sessions(node)?;
     if let Some(table) = sessions {
         term::blank();
@@ -3,7 +3,7 @@ use std::ffi::OsString;
 use anyhow::anyhow;
 
 use radicle::node::policy;
-use radicle::node::policy::Scope;
+use radicle::node::policy::{RefCategories, Scope};
 use radicle::node::Handle;
 use radicle::{prelude::*, storage, Node};
 use radicle_term::Element as _;
@@ -20,7 +20,7 @@ pub const HELP: Help = Help {
     usage: r#"
 Usage
 
-    rad seed [<rid>] [--[no-]fetch] [--scope <scope>] [<option>...]
+    rad seed [<rid>] [--[no-]fetch] [--scope <scope>] [--[no-]<category>...] [<option>...]
 
     The `seed` command, when no Repository ID (<rid>) is provided, will list the
     repositories being seeded.
@@ -33,10 +33,18 @@ Usage
     On the other hand, with `followed`, only the repository delegates will be followed,
     plus any remote that is explicitly followed via `rad follow <nid>`.
 
+    All references of a seeded repository are fetched by default. To save space, some
+    categories of references can be left out, eg. with `--no-tags --no-notes`. Identity
+    references and the delegates' default branches are always fetched.
+
 Options
 
     --[no-]fetch           Fetch repository after updating seeding policy
     --scope <scope>        Peer follow scope for this repository
+    --[no-]heads           Fetch the repository's branches
+    --[no-]tags            Fetch the repository's tags
+    --[no-]notes           Fetch the repository's git notes
+    --[no-]cobs            Fetch the repository's collaborative objects
     --verbose, -v          Verbose output
     --help                 Print help
 "#,
@@ -48,10 +56,37 @@ pub enum Operation {
         rid: RepoId,
         fetch: bool,
         scope: Scope,
+        categories: Categories,
     },
     List,
 }
 
+/// Changes to the categories of references fetched for a repository.
+#[derive(Debug, Default)]
+pub struct Categories {
+    pub heads: Option<bool>,
+    pub tags: Option<bool>,
+    pub notes: Option<bool>,
+    pub cobs: Option<bool>,
+}
+
+impl Categories {
+    /// Whether any category is changed.
+    pub fn is_empty(&self) -> bool {
+        self.heads.is_none() && self.tags.is_none() && self.notes.is_none() && self.cobs.is_none()
+    }
+
+    /// Apply the changes to the given categories.
+    pub fn apply(&self, categories: RefCategories) -> RefCategories {
+        RefCategories {
+            heads: self.heads.unwrap_or(categories.heads),
+            tags: self.tags.unwrap_or(categories.tags),
+            notes: self.notes.unwrap_or(categories.notes),
+            cobs: self.cobs.unwrap_or(categories.cobs),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Options {
     pub op: Operation,
@@ -66,6 +101,7 @@ impl Args for Options {
         let mut rid: Option<RepoId> = None;
         let mut scope: Option<Scope> = None;
         let mut fetch: Option<bool> = None;
+        let mut categories = Categories::default();
         let mut verbose = false;
 
         while let Some(arg) = parser.next()? {
@@ -83,6 +119,14 @@ impl Args for Options {
                 Long("no-fetch") => {
                     fetch = Some(false);
                 }
+                Long("heads") => categories.heads = Some(true),
+                Long("no-heads") => categories.heads = Some(false),
+                Long("tags") => categories.tags = Some(true),
+                Long("no-tags") => categories.tags = Some(false),
+                Long("notes") => categories.notes = Some(true),
+                Long("no-notes") => categories.notes = Some(false),
+                Long("cobs") => categories.cobs = Some(true),
+                Long("no-cobs") => categories.cobs = Some(false),
                 Long("verbose") | Short('v') => verbose = true,
                 Long("help") | Short('h') => {
                     return Err(Error::Help.into());
@@ -98,6 +142,7 @@ impl Args for Options {
                 rid,
                 fetch: fetch.unwrap_or(true),
                 scope: scope.unwrap_or(Scope::All),
+                categories,
             },
             None => Operation::List,
         };
@@ -111,9 +156,18 @@ pub fn run(options: Options, ctx: impl term::Context) -> anyhow::Result<()> {
     let mut node = radicle::Node::new(profile.socket());
 
     match options.op {
-        Operation::Seed { rid, fetch, scope } => {
+        Operation::Seed {
+            rid,
+            fetch,
+            scope,
+            categories,
+        } => {
             update(rid, scope, &mut node, &profile)?;
 
+            if !categories.is_empty() {
+                update_categories(rid, &categories, &profile)?;
+            }
+
             if fetch && node.is_running() {
                 sync::fetch(
                     rid,
@@ -145,6 +199,44 @@ pub fn update(
     Ok(())
 }
 
+pub fn update_categories(
+    rid: RepoId,
+    categories: &Categories,
+    profile: &Profile,
+) -> Result<(), anyhow::Error> {
+    let mut policies = profile.policies_mut()?;
+    let categories = categories.apply(policies.ref_categories(&rid)?);
+
+    if policies.set_ref_categories(&rid, categories)? {
+        let RefCategories {
+            heads,
+            tags,
+            notes,
+            cobs,
+        } = categories;
+        let fetched = [
+            (heads, "branches"),
+            (tags, "tags"),
+            (notes, "notes"),
+            (cobs, "collaborative objects"),
+        ]
+        .into_iter()
+        .filter_map(|(fetched, name)| fetched.then_some(name))
+        .collect::<Vec<_>>();
+
+        term::success!(
+            "Fetched references updated for {}: {}",
+            term::format::tertiary(rid),
+            if fetched.is_empty() {
+                String::from("identity only")
+            } else {
+                fetched.join(", ")
+            }
+        );
+    }
+    Ok(())
+}
+
 pub fn delete(rid: RepoId, node: &mut Node, profile: &Profile) -> anyhow::Result<()> {
     if project::unseed(rid, node, profile)? {
         term::success!("Seeding policy for {} removed", term::format::tertiary(rid));
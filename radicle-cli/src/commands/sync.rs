@@ -570,7 +570,7 @@ fn fetch_from(
         FetchResult::Success { .. } => {
             spinner.finish();
         }
-        FetchResult::Failed { reason } => {
+        FetchResult::Failed { reason, .. } => {
             spinner.error(reason);
         }
     }
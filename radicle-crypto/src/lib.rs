@@ -66,6 +66,23 @@ where
     }
 }
 
+impl<T> Signer for Arc<T>
+where
+    T: Signer + ?Sized,
+{
+    fn public_key(&self) -> &PublicKey {
+        self.deref().public_key()
+    }
+
+    fn sign(&self, msg: &[u8]) -> Signature {
+        self.deref().sign(msg)
+    }
+
+    fn try_sign(&self, msg: &[u8]) -> Result<Signature, SignerError> {
+        self.deref().try_sign(msg)
+    }
+}
+
 /// Cryptographic signature.
 #[derive(PartialEq, Eq, Hash, Copy, Clone, Serialize, Deserialize)]
 #[serde(into = "String", try_from = "String")]
@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use radicle::git::{Component, Oid, Qualified, RefString};
+use radicle::git::{Component, Namespaced, Oid, Qualified, RefString};
 use radicle::prelude::PublicKey;
 
 use super::refs::{Applied, RefUpdate, Update};
@@ -40,16 +40,17 @@ impl Refdb {
             .into_iter()
             .fold(Applied::default(), |mut ap, update| match update {
                 Update::Direct { name, target, .. } => {
-                    let name = name.into_qualified().into_owned();
-                    let prev = match self.0.insert(name.clone(), target) {
-                        Some(prev) => prev,
-                        None => radicle::git::raw::Oid::zero().into(),
-                    };
-                    ap.updated.push(RefUpdate::Updated {
-                        name: name.to_ref_string(),
-                        old: prev,
-                        new: target,
-                    });
+                    ap.updated.push(self.write(name, target));
+                    ap
+                }
+                // N.b. symbolic references are stored as the object
+                // their target points to.
+                Update::Symbolic { name, target, .. } => {
+                    let oid = *self
+                        .0
+                        .entry(target.name.into_qualified().into_owned())
+                        .or_insert(target.target);
+                    ap.updated.push(self.write(name, oid));
                     ap
                 }
                 Update::Prune { name, .. } => {
@@ -65,6 +66,19 @@ impl Refdb {
             })
     }
 
+    fn write(&mut self, name: Namespaced, target: Oid) -> RefUpdate {
+        let name = name.into_qualified().into_owned();
+        let prev = match self.0.insert(name.clone(), target) {
+            Some(prev) => prev,
+            None => radicle::git::raw::Oid::zero().into(),
+        };
+        RefUpdate::Updated {
+            name: name.to_ref_string(),
+            old: prev,
+            new: target,
+        }
+    }
+
     #[allow(dead_code)]
     pub(crate) fn inspect(&self) {
         if self.0.is_empty() {
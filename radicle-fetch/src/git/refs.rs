@@ -1,2 +1,2 @@
 mod update;
-pub use update::{Applied, Policy, RefUpdate, Update, Updates};
+pub use update::{Applied, Policy, Reason, RefUpdate, Rejected, SymrefTarget, Update, Updates};
@@ -22,6 +22,9 @@ use radicle::git::{Namespaced, Oid, Qualified};
 use radicle::prelude::PublicKey;
 
 pub use radicle::storage::RefUpdate;
+use thiserror::Error;
+
+use crate::git::repository;
 
 /// The set of applied changes from a reference store update.
 #[derive(Debug, Default)]
@@ -29,18 +32,85 @@ pub struct Applied<'a> {
     /// Set of rejected updates if they did not meet the update
     /// requirements, e.g. concurrent change to previous object id,
     /// broke fast-forward policy, etc.
-    pub rejected: Vec<Update<'a>>,
+    pub rejected: Vec<Rejected<'a>>,
     /// Set of successfully updated references.
     pub updated: Vec<RefUpdate>,
+    /// Set of updates that were accepted, but could not be written
+    /// to the reference store.
+    pub failed: Vec<Update<'a>>,
 }
 
 impl<'a> Applied<'a> {
     pub fn append(&mut self, other: &mut Self) {
         self.rejected.append(&mut other.rejected);
         self.updated.append(&mut other.updated);
+        self.failed.append(&mut other.failed);
+    }
+
+    /// Whether only some of the accepted updates were written.
+    pub fn is_partial(&self) -> bool {
+        !self.failed.is_empty()
+    }
+
+    pub fn into_owned<'b>(self) -> Applied<'b> {
+        Applied {
+            rejected: self
+                .rejected
+                .into_iter()
+                .map(Rejected::into_owned)
+                .collect(),
+            updated: self.updated,
+            failed: self.failed.into_iter().map(Update::into_owned).collect(),
+        }
     }
 }
 
+/// An [`Update`] that was not applied, and why.
+#[derive(Debug)]
+pub struct Rejected<'a> {
+    pub update: Update<'a>,
+    pub reason: Reason,
+}
+
+impl<'a> Rejected<'a> {
+    pub fn new(update: Update<'a>, reason: Reason) -> Self {
+        Self { update, reason }
+    }
+
+    pub fn into_owned<'b>(self) -> Rejected<'b> {
+        Rejected {
+            update: self.update.into_owned(),
+            reason: self.reason,
+        }
+    }
+}
+
+/// The reason an [`Update`] was rejected.
+#[derive(Debug, Error)]
+pub enum Reason {
+    /// The target is behind the current tip of the reference.
+    #[error("target is behind the current tip {current}")]
+    Behind { current: Oid },
+    /// The target diverged from the current tip of the reference, and
+    /// the update's [`Policy`] is [`Policy::Reject`].
+    #[error("target diverged from the current tip {current}")]
+    Diverged { current: Oid },
+    /// The reference is a direct reference, and the update's
+    /// [`Policy`] is [`Policy::Reject`].
+    #[error("reference is a direct reference")]
+    TypeChange,
+    /// The reference to delete does not exist.
+    #[error("reference does not exist")]
+    Missing,
+    /// A later update of the same reference was applied instead.
+    #[error("superseded by a later update of the same reference")]
+    Superseded,
+    /// The ancestry of the target could not be checked, eg. because it
+    /// is missing.
+    #[error(transparent)]
+    Ancestry(#[from] repository::error::Ancestry),
+}
+
 /// A set of [`Update`]s that are grouped by which namespace they are
 /// affecting.
 #[derive(Clone, Default, Debug)]
@@ -78,7 +148,8 @@ impl<'a> Updates<'a> {
 }
 
 /// The policy to follow when an [`Update::Direct`] is not a
-/// fast-forward.
+/// fast-forward, or when an [`Update::Symbolic`] would turn a direct
+/// reference into a symbolic one.
 #[derive(Clone, Copy, Debug)]
 pub enum Policy {
     /// Abort the entire transaction.
@@ -115,6 +186,36 @@ pub enum Update<'a> {
         /// [`Qualified`] reference name.
         prev: Either<Oid, Qualified<'a>>,
     },
+    /// Update a symbolic reference, i.e. a reference that points to
+    /// another reference.
+    Symbolic {
+        /// The name of the reference that is being updated.
+        name: Namespaced<'a>,
+        /// The reference that is pointed to.
+        target: SymrefTarget<'a>,
+        /// Policy to apply when the reference exists as a direct
+        /// reference.
+        type_change: Policy,
+    },
+}
+
+/// The target of an [`Update::Symbolic`].
+#[derive(Clone, Debug)]
+pub struct SymrefTarget<'a> {
+    /// The name of the reference that is pointed to.
+    pub name: Namespaced<'a>,
+    /// The object the reference pointed to is created with, if it
+    /// doesn't exist.
+    pub target: Oid,
+}
+
+impl<'a> SymrefTarget<'a> {
+    pub fn into_owned<'b>(self) -> SymrefTarget<'b> {
+        SymrefTarget {
+            name: self.name.into_owned(),
+            target: self.target,
+        }
+    }
 }
 
 impl<'a> Update<'a> {
@@ -122,6 +223,7 @@ impl<'a> Update<'a> {
         match self {
             Update::Direct { name, .. } => name,
             Update::Prune { name, .. } => name,
+            Update::Symbolic { name, .. } => name,
         }
     }
 
@@ -140,6 +242,15 @@ impl<'a> Update<'a> {
                 name: name.into_owned(),
                 prev: prev.map_right(|q| q.into_owned()),
             },
+            Self::Symbolic {
+                name,
+                target,
+                type_change,
+            } => Update::Symbolic {
+                name: name.into_owned(),
+                target: target.into_owned(),
+                type_change,
+            },
         }
     }
 }
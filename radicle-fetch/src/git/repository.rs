@@ -1,10 +1,13 @@
 pub mod error;
+#[cfg(test)]
+pub mod mem;
 
-use either::Either;
-use radicle::git::{self, Namespaced, Oid, Qualified};
+use std::collections::HashMap;
+
+use radicle::git::{self, Namespaced, Oid, Qualified, RefString};
 use radicle::storage::git::Repository;
 
-use super::refs::{Applied, Policy, RefUpdate, Update};
+use super::refs::{Applied, Policy, Reason, RefUpdate, Rejected, SymrefTarget, Update};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Ancestry {
@@ -14,62 +17,191 @@ pub enum Ancestry {
     Diverged,
 }
 
-pub enum Updated<'a> {
-    Accepted(RefUpdate),
-    Rejected(Update<'a>),
+/// A single edit to the references of a repository.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Edit<'a> {
+    /// Point the reference to `target`, creating it if it doesn't exist.
+    Write { name: Namespaced<'a>, target: Oid },
+    /// Delete the reference.
+    Delete { name: Namespaced<'a> },
+    /// Point the reference to the reference `target`, creating it if it
+    /// doesn't exist.
+    Symbolic {
+        name: Namespaced<'a>,
+        target: Namespaced<'a>,
+    },
 }
 
-impl<'a> From<RefUpdate> for Updated<'a> {
-    fn from(up: RefUpdate) -> Self {
-        Updated::Accepted(up)
+impl<'a> Edit<'a> {
+    pub fn refname(&self) -> &Namespaced<'a> {
+        match self {
+            Edit::Write { name, .. } => name,
+            Edit::Delete { name } => name,
+            Edit::Symbolic { name, .. } => name,
+        }
     }
 }
 
-impl<'a> From<Update<'a>> for Updated<'a> {
-    fn from(up: Update<'a>) -> Self {
-        Updated::Rejected(up)
-    }
-}
+/// Read access to the objects of a repository.
+///
+/// This allows the update logic to run against an in-memory object
+/// database, eg. in tests.
+pub trait Odb {
+    /// Check whether the object identified by `oid` exists.
+    fn contains(&self, oid: Oid) -> Result<bool, error::Contains>;
+
+    /// Find the object identified by `oid` and peel it to its
+    /// associated commit `Oid`.
+    ///
+    /// # Errors
+    ///
+    /// - The object was not found
+    /// - The object does not peel to a commit
+    /// - Attempting to find the object fails
+    fn peel(&self, oid: Oid) -> Result<Oid, error::Ancestry>;
 
-pub fn contains(repo: &Repository, oid: Oid) -> Result<bool, error::Contains> {
-    repo.backend
-        .odb()
-        .map(|odb| odb.exists(oid.into()))
-        .map_err(error::Contains)
+    /// Count the commits `new` is ahead and behind of `old`.
+    fn ahead_behind(&self, new: Oid, old: Oid) -> Result<(usize, usize), error::Ancestry>;
+
+    /// Get the parents of the commit identified by `oid`. Objects
+    /// that aren't commits have no parents.
+    fn parents(&self, oid: Oid) -> Result<Vec<Oid>, error::Ancestry>;
+
+    /// Find the best common ancestor of the commits `a` and `b`, if
+    /// they have one.
+    fn merge_base(&self, a: Oid, b: Oid) -> Result<Option<Oid>, error::Ancestry>;
 }
 
-/// Find the object identified by `oid` and peel it to its associated
-/// commit `Oid`.
-///
-/// # Errors
+/// Read and write access to the references of a repository.
 ///
-/// - The object was not found
-/// - The object does not peel to a commit
-/// - Attempting to find the object fails
-fn find_and_peel(repo: &Repository, oid: Oid) -> Result<Oid, error::Ancestry> {
-    match repo.backend.find_object(*oid, None) {
-        Ok(object) => Ok(object
-            .peel(git::raw::ObjectType::Commit)
-            .map_err(|err| error::Ancestry::Peel { oid, err })?
-            .id()
-            .into()),
-        Err(e) if git::is_not_found_err(&e) => Err(error::Ancestry::Missing { oid }),
-        Err(err) => Err(error::Ancestry::Object { oid, err }),
+/// This allows the update logic to run against an in-memory reference
+/// store, eg. in tests.
+pub trait Refdb {
+    /// Resolve `refname` to the `Oid` it points to, if it exists.
+    fn refname_to_id(&self, refname: &Qualified) -> Result<Option<Oid>, error::Resolve>;
+
+    /// Get the name of the reference `name` points to, if it is a
+    /// symbolic reference.
+    fn symbolic_target(&self, name: &Namespaced) -> Result<Option<RefString>, error::Resolve>;
+
+    /// Write `edits` in a single transaction. All the references
+    /// edited are locked before any of them is written, so that if
+    /// locking fails, none of them is written.
+    fn write(&self, edits: &[Edit]) -> Result<(), git::raw::Error>;
+}
+
+impl Odb for Repository {
+    fn contains(&self, oid: Oid) -> Result<bool, error::Contains> {
+        self.backend
+            .odb()
+            .map(|odb| odb.exists(oid.into()))
+            .map_err(error::Contains)
+    }
+
+    fn peel(&self, oid: Oid) -> Result<Oid, error::Ancestry> {
+        match self.backend.find_object(*oid, None) {
+            Ok(object) => Ok(object
+                .peel(git::raw::ObjectType::Commit)
+                .map_err(|err| error::Ancestry::Peel { oid, err })?
+                .id()
+                .into()),
+            Err(e) if git::is_not_found_err(&e) => Err(error::Ancestry::Missing { oid }),
+            Err(err) => Err(error::Ancestry::Object { oid, err }),
+        }
+    }
+
+    fn ahead_behind(&self, new: Oid, old: Oid) -> Result<(usize, usize), error::Ancestry> {
+        self.backend
+            .graph_ahead_behind(*new, *old)
+            .map_err(|err| error::Ancestry::Check { old, new, err })
+    }
+
+    fn parents(&self, oid: Oid) -> Result<Vec<Oid>, error::Ancestry> {
+        match self.backend.find_object(*oid, None) {
+            Ok(object) => Ok(object
+                .as_commit()
+                .map(|c| c.parent_ids().map(Oid::from).collect())
+                .unwrap_or_default()),
+            Err(e) if git::is_not_found_err(&e) => Err(error::Ancestry::Missing { oid }),
+            Err(err) => Err(error::Ancestry::Object { oid, err }),
+        }
     }
+
+    fn merge_base(&self, a: Oid, b: Oid) -> Result<Option<Oid>, error::Ancestry> {
+        match self.backend.merge_base(*a, *b) {
+            Ok(oid) => Ok(Some(oid.into())),
+            Err(e) if git::is_not_found_err(&e) => Ok(None),
+            Err(err) => Err(error::Ancestry::Check {
+                old: a,
+                new: b,
+                err,
+            }),
+        }
+    }
+}
+
+impl Refdb for Repository {
+    fn refname_to_id(&self, refname: &Qualified) -> Result<Option<Oid>, error::Resolve> {
+        use radicle::git::raw::ErrorCode::NotFound;
+
+        match self.backend.refname_to_id(refname.as_ref()) {
+            Ok(oid) => Ok(Some(oid.into())),
+            Err(e) if matches!(e.code(), NotFound) => Ok(None),
+            Err(err) => Err(error::Resolve {
+                name: refname.to_owned(),
+                err,
+            }),
+        }
+    }
+
+    fn symbolic_target(&self, name: &Namespaced) -> Result<Option<RefString>, error::Resolve> {
+        use radicle::git::raw::ErrorCode::NotFound;
+
+        match self.backend.find_reference(name.as_str()) {
+            Ok(r) => Ok(r
+                .symbolic_target()
+                .and_then(|target| RefString::try_from(target).ok())),
+            Err(e) if matches!(e.code(), NotFound) => Ok(None),
+            Err(err) => Err(error::Resolve {
+                name: name.to_owned().into_qualified(),
+                err,
+            }),
+        }
+    }
+
+    fn write(&self, edits: &[Edit]) -> Result<(), git::raw::Error> {
+        let mut tx = self.backend.transaction()?;
+        for edit in edits {
+            tx.lock_ref(edit.refname().as_str())?;
+        }
+        for edit in edits {
+            match edit {
+                Edit::Write { name, target } => {
+                    tx.set_target(name.as_str(), (*target).into(), None, "radicle: update")?
+                }
+                Edit::Delete { name } => tx.remove(name.as_str())?,
+                Edit::Symbolic { name, target } => {
+                    tx.set_symbolic_target(name.as_str(), target.as_str(), None, "radicle: update")?
+                }
+            }
+        }
+        tx.commit()
+    }
+}
+
+pub fn contains<D: Odb>(repo: &D, oid: Oid) -> Result<bool, error::Contains> {
+    repo.contains(oid)
 }
 
-pub fn ancestry(repo: &Repository, old: Oid, new: Oid) -> Result<Ancestry, error::Ancestry> {
-    let old = find_and_peel(repo, old)?;
-    let new = find_and_peel(repo, new)?;
+pub fn ancestry<D: Odb>(repo: &D, old: Oid, new: Oid) -> Result<Ancestry, error::Ancestry> {
+    let old = repo.peel(old)?;
+    let new = repo.peel(new)?;
 
     if old == new {
         return Ok(Ancestry::Equal);
     }
 
-    let (ahead, behind) = repo
-        .backend
-        .graph_ahead_behind(*new, *old)
-        .map_err(|err| error::Ancestry::Check { old, new, err })?;
+    let (ahead, behind) = repo.ahead_behind(new, old)?;
 
     if ahead > 0 && behind == 0 {
         Ok(Ancestry::Ahead)
@@ -80,170 +212,784 @@ pub fn ancestry(repo: &Repository, old: Oid, new: Oid) -> Result<Ancestry, error
     }
 }
 
-pub fn refname_to_id<'a, N>(repo: &Repository, refname: N) -> Result<Option<Oid>, error::Resolve>
+pub fn merge_base<D: Odb>(repo: &D, a: Oid, b: Oid) -> Result<Option<Oid>, error::Ancestry> {
+    repo.merge_base(a, b)
+}
+
+pub fn refname_to_id<'a, R, N>(repo: &R, refname: N) -> Result<Option<Oid>, error::Resolve>
 where
+    R: Refdb,
     N: Into<Qualified<'a>>,
 {
-    use radicle::git::raw::ErrorCode::NotFound;
-
-    let refname = refname.into();
-    match repo.backend.refname_to_id(refname.as_ref()) {
-        Ok(oid) => Ok(Some(oid.into())),
-        Err(e) if matches!(e.code(), NotFound) => Ok(None),
-        Err(err) => Err(error::Resolve {
-            name: refname.to_owned(),
-            err,
-        }),
-    }
+    repo.refname_to_id(&refname.into())
 }
 
-pub fn update<'a, I>(repo: &Repository, updates: I) -> Result<Applied<'a>, error::Update>
+/// The default maximum number of references written per transaction
+/// by [`update_batched`].
+pub const DEFAULT_UPDATE_BATCH_SIZE: usize = 1024;
+
+/// The outcome of checking an update against the repository.
+enum Planned<'a> {
+    /// The update is accepted, with the references it updates and the
+    /// edits it requires.
+    Accepted(Vec<RefUpdate>, Vec<Edit<'a>>),
+    /// The update is rejected.
+    Rejected(Reason),
+}
+
+/// The edits written in a single transaction, and the updates they
+/// apply.
+#[derive(Default)]
+struct Batch<'a> {
+    edits: Vec<Edit<'a>>,
+    updates: Vec<(Update<'a>, Vec<RefUpdate>)>,
+}
+
+/// Apply `updates`, writing at most `batch_size` references per
+/// transaction.
+///
+/// All the updates are checked before anything is written, so an
+/// update aborting leaves the repository untouched. Consecutive updates
+/// of the same namespace are always written in the same transaction,
+/// even if that exceeds `batch_size`.
+///
+/// If there are multiple updates of the same reference, only the last
+/// one is applied, and the others are rejected as
+/// [`Reason::Superseded`].
+///
+/// If writing a transaction fails, the remaining ones are still
+/// written, and the error returned holds the updates that were applied
+/// as well as the ones that weren't.
+pub fn update_batched<'a, R, I>(
+    repo: &R,
+    updates: I,
+    batch_size: usize,
+) -> Result<Applied<'a>, error::Update>
 where
+    R: Refdb + Odb,
     I: IntoIterator<Item = Update<'a>>,
 {
     let mut applied = Applied::default();
-    for up in updates.into_iter() {
-        match up {
-            Update::Direct {
-                name,
-                target,
-                no_ff,
-            } => match direct(repo, name, target, no_ff)? {
-                Updated::Rejected(r) => applied.rejected.push(r),
-                Updated::Accepted(u) => applied.updated.push(u),
-            },
-            Update::Prune { name, prev } => match prune(repo, name, prev)? {
-                Updated::Rejected(r) => applied.rejected.push(r),
-                Updated::Accepted(u) => applied.updated.push(u),
-            },
+    let mut batches = Vec::new();
+    let mut batch = Batch::default();
+    let updates = updates.into_iter().collect::<Vec<_>>();
+    // N.b. a reference can only be edited once per transaction, so we
+    // only keep the last update of each reference.
+    let last = updates
+        .iter()
+        .enumerate()
+        .map(|(i, up)| (up.refname().clone(), i))
+        .collect::<HashMap<_, _>>();
+    let mut latest = Vec::with_capacity(last.len());
+
+    for (i, up) in updates.into_iter().enumerate() {
+        if last.get(up.refname()) == Some(&i) {
+            latest.push(up);
+        } else {
+            applied
+                .rejected
+                .push(Rejected::new(up.into_owned(), Reason::Superseded));
+        }
+    }
+    let mut updates = latest.into_iter().peekable();
+
+    while let Some(first) = updates.next() {
+        let namespace = first.refname().namespace().to_ref_string();
+        let mut group = vec![first];
+        while let Some(up) = updates.next_if(|up| *up.refname().namespace() == *namespace) {
+            group.push(up);
+        }
+        let mut planned = Batch::default();
+
+        for up in group {
+            let name = up.refname();
+            let tip = refname_to_id(repo, name.clone())?;
+            let plan = match &up {
+                Update::Direct { target, no_ff, .. } => direct(repo, name, tip, *target, *no_ff)?,
+                Update::Prune { .. } => prune(name, tip),
+                Update::Symbolic {
+                    target,
+                    type_change,
+                    ..
+                } => {
+                    // N.b. the target is only created here if no other
+                    // update is editing it.
+                    let create = !last.contains_key(&target.name);
+                    symbolic(repo, name, tip, target, *type_change, create)?
+                }
+            };
+            match plan {
+                Planned::Accepted(updates, mut edits) => {
+                    planned.edits.append(&mut edits);
+                    planned.updates.push((up, updates));
+                }
+                Planned::Rejected(reason) => applied.rejected.push(Rejected::new(up, reason)),
+            }
+        }
+        if !batch.edits.is_empty() && batch.edits.len() + planned.edits.len() > batch_size {
+            batches.push(std::mem::take(&mut batch));
+        }
+        batch.edits.append(&mut planned.edits);
+        batch.updates.append(&mut planned.updates);
+    }
+    batches.push(batch);
+
+    let mut error = None;
+    for batch in batches {
+        let result = if batch.edits.is_empty() {
+            Ok(())
+        } else {
+            repo.write(&batch.edits)
+        };
+        match result {
+            Ok(()) => applied
+                .updated
+                .extend(batch.updates.into_iter().flat_map(|(_, updates)| updates)),
+            Err(err) => {
+                log::warn!(
+                    target: "fetch",
+                    "Failed to write {} reference(s): {err}",
+                    batch.edits.len()
+                );
+                applied
+                    .failed
+                    .extend(batch.updates.into_iter().map(|(up, _)| up));
+                error.get_or_insert(err);
+            }
         }
     }
 
-    Ok(applied)
+    match error {
+        None => Ok(applied),
+        Some(err) => Err(error::Update::Commit {
+            applied: Box::new(applied.into_owned()),
+            err,
+        }),
+    }
 }
 
-fn direct<'a>(
-    repo: &Repository,
-    name: Namespaced<'a>,
+/// Check a direct update of `name`, currently pointing to `tip`.
+fn direct<'a, R: Odb>(
+    repo: &R,
+    name: &Namespaced<'a>,
+    tip: Option<Oid>,
     target: Oid,
     no_ff: Policy,
-) -> Result<Updated<'a>, error::Update> {
-    let tip = refname_to_id(repo, name.clone())?;
+) -> Result<Planned<'a>, error::Update> {
+    let write = Edit::Write {
+        name: name.clone(),
+        target,
+    };
     match tip {
         Some(prev) => {
-            let ancestry = ancestry(repo, prev, target)?;
+            let ancestry = match ancestry(repo, prev, target) {
+                Ok(ancestry) => ancestry,
+                Err(e) => return Ok(Planned::Rejected(e.into())),
+            };
 
             match ancestry {
-                Ancestry::Equal => Ok(RefUpdate::Skipped {
-                    name: name.to_ref_string(),
-                    oid: target,
-                }
-                .into()),
-                Ancestry::Ahead => {
-                    // N.b. the update is a fast-forward so we can safely
-                    // pass `force: true`.
-                    repo.backend
-                        .reference(name.as_ref(), target.into(), true, "radicle: update")
-                        .map_err(|err| error::Update::Create {
-                            name: name.to_owned(),
-                            target,
-                            err,
-                        })?;
-                    Ok(RefUpdate::from(name.to_ref_string(), prev, target).into())
-                }
+                Ancestry::Equal => Ok(Planned::Accepted(
+                    vec![RefUpdate::Skipped {
+                        name: name.to_ref_string(),
+                        oid: target,
+                    }],
+                    vec![],
+                )),
+                Ancestry::Ahead => Ok(Planned::Accepted(
+                    vec![RefUpdate::from(name.to_ref_string(), prev, target)],
+                    vec![write],
+                )),
+                // N.b. the update is a non-fast-forward but we allow it.
                 Ancestry::Behind | Ancestry::Diverged if matches!(no_ff, Policy::Allow) => {
-                    // N.b. the update is a non-fast-forward but
-                    // we allow it, so we pass `force: true`.
-                    repo.backend
-                        .reference(name.as_ref(), target.into(), true, "radicle: update")
-                        .map_err(|err| error::Update::Create {
-                            name: name.to_owned(),
-                            target,
-                            err,
-                        })?;
-                    Ok(RefUpdate::from(name.to_ref_string(), prev, target).into())
+                    Ok(Planned::Accepted(
+                        vec![RefUpdate::from(name.to_ref_string(), prev, target)],
+                        vec![write],
+                    ))
                 }
                 // N.b. if the target is behind, we simply reject the update
-                Ancestry::Behind => Ok(Update::Direct {
-                    name,
-                    target,
-                    no_ff,
-                }
-                .into()),
-                Ancestry::Diverged if matches!(no_ff, Policy::Reject) => Ok(Update::Direct {
-                    name,
-                    target,
-                    no_ff,
-                }
-                .into()),
-                Ancestry::Diverged => {
-                    return Err(error::Update::NonFF {
-                        name: name.to_owned(),
-                        new: target,
-                        cur: prev,
-                    })
+                Ancestry::Behind => Ok(Planned::Rejected(Reason::Behind { current: prev })),
+                Ancestry::Diverged if matches!(no_ff, Policy::Reject) => {
+                    Ok(Planned::Rejected(Reason::Diverged { current: prev }))
                 }
+                Ancestry::Diverged => Err(error::Update::NonFF {
+                    name: name.to_owned(),
+                    new: target,
+                    cur: prev,
+                }),
             }
         }
-        None => {
-            // N.b. the reference didn't exist so we pass `force:
-            // false`.
-            repo.backend
-                .reference(name.as_ref(), target.into(), false, "radicle: create")
-                .map_err(|err| error::Update::Create {
-                    name: name.to_owned(),
-                    target,
-                    err,
-                })?;
-            Ok(RefUpdate::Created {
+        None => Ok(Planned::Accepted(
+            vec![RefUpdate::Created {
                 name: name.to_ref_string(),
                 oid: target,
-            }
-            .into())
-        }
+            }],
+            vec![write],
+        )),
     }
 }
 
-fn prune<'a>(
-    repo: &Repository,
-    name: Namespaced<'a>,
-    prev: Either<Oid, Qualified<'a>>,
-) -> Result<Updated<'a>, error::Update> {
-    use radicle::git::raw::ObjectType;
+/// Check the deletion of `name`, currently pointing to `tip`.
+fn prune<'a>(name: &Namespaced<'a>, tip: Option<Oid>) -> Planned<'a> {
+    match tip {
+        Some(oid) => Planned::Accepted(
+            vec![RefUpdate::Deleted {
+                name: name.to_ref_string(),
+                oid,
+            }],
+            vec![Edit::Delete { name: name.clone() }],
+        ),
+        None => Planned::Rejected(Reason::Missing),
+    }
+}
 
-    match find(repo, &name)? {
-        Some(mut r) => {
-            // N.b. peel this reference to whatever object it points to,
-            // presumably a commit, and get its Oid
-            let prev = r
-                .peel(ObjectType::Any)
-                .map_err(error::Update::Peel)?
-                .id()
-                .into();
-            r.delete().map_err(|err| error::Update::Delete {
-                name: name.to_owned(),
-                err,
-            })?;
-            Ok(RefUpdate::Deleted {
+/// Check a symbolic update of `name`, currently pointing to `tip`.
+///
+/// If `create` is set, the reference pointed to is created if it
+/// doesn't exist.
+fn symbolic<'a, R: Refdb>(
+    repo: &R,
+    name: &Namespaced<'a>,
+    tip: Option<Oid>,
+    target: &SymrefTarget<'a>,
+    type_change: Policy,
+    create: bool,
+) -> Result<Planned<'a>, error::Update> {
+    let dst = refname_to_id(repo, target.name.clone())?;
+    let oid = dst.unwrap_or(target.target);
+    let edit = Edit::Symbolic {
+        name: name.clone(),
+        target: target.name.clone(),
+    };
+    let (mut updates, mut edits) = match (repo.symbolic_target(name)?, tip) {
+        (Some(current), _) if current.as_str() == target.name.as_str() => (
+            vec![RefUpdate::Skipped {
                 name: name.to_ref_string(),
-                oid: prev,
+                oid,
+            }],
+            vec![],
+        ),
+        (Some(_), _) | (None, None) => (
+            vec![RefUpdate::from(
+                name.to_ref_string(),
+                tip.unwrap_or(git::raw::Oid::zero().into()),
+                oid,
+            )],
+            vec![edit],
+        ),
+        // N.b. the reference exists, but is a direct reference.
+        (None, Some(prev)) => match type_change {
+            Policy::Allow => (
+                vec![RefUpdate::from(name.to_ref_string(), prev, oid)],
+                vec![edit],
+            ),
+            Policy::Reject => return Ok(Planned::Rejected(Reason::TypeChange)),
+            Policy::Abort => {
+                return Err(error::Update::TypeChange {
+                    name: name.to_owned(),
+                })
             }
-            .into())
-        }
-        None => Ok(Update::Prune { name, prev }.into()),
+        },
+    };
+    if dst.is_none() && create {
+        updates.insert(
+            0,
+            RefUpdate::Created {
+                name: target.name.to_ref_string(),
+                oid,
+            },
+        );
+        edits.insert(
+            0,
+            Edit::Write {
+                name: target.name.clone(),
+                target: oid,
+            },
+        );
     }
+    Ok(Planned::Accepted(updates, edits))
 }
 
-fn find<'a>(
-    repo: &'a Repository,
-    name: &Namespaced<'_>,
-) -> Result<Option<radicle::git::raw::Reference<'a>>, error::Update> {
-    match repo.backend.find_reference(name.as_ref()) {
-        Ok(r) => Ok(Some(r)),
-        Err(e) if matches!(e.code(), radicle::git::raw::ErrorCode::NotFound) => Ok(None),
-        Err(err) => Err(error::Update::Find {
-            name: name.clone().into_owned(),
-            err,
-        }),
+#[cfg(test)]
+mod test {
+    use qcheck_macros::quickcheck;
+    use radicle::git::{qualified, Component};
+    use radicle::test::arbitrary;
+
+    use super::*;
+    use crate::test;
+
+    fn name(remote: &radicle::crypto::PublicKey) -> Namespaced<'static> {
+        qualified!("refs/heads/master").with_namespace(Component::from(remote))
+    }
+
+    fn direct(name: &Namespaced<'static>, target: Oid, no_ff: Policy) -> Update<'static> {
+        Update::Direct {
+            name: name.clone(),
+            target,
+            no_ff,
+        }
+    }
+
+    fn symbolic(
+        name: &Namespaced<'static>,
+        target: &Namespaced<'static>,
+        oid: Oid,
+        type_change: Policy,
+    ) -> Update<'static> {
+        Update::Symbolic {
+            name: name.clone(),
+            target: SymrefTarget {
+                name: target.clone(),
+                target: oid,
+            },
+            type_change,
+        }
+    }
+
+    fn update<'a>(
+        repo: &mem::Repository,
+        updates: impl IntoIterator<Item = Update<'a>>,
+    ) -> Result<Applied<'a>, error::Update> {
+        update_batched(repo, updates, usize::MAX)
+    }
+
+    /// Two references in the namespace of each remote.
+    fn namespaces(remotes: usize) -> Vec<[Namespaced<'static>; 2]> {
+        arbitrary::vec::<radicle::crypto::PublicKey>(remotes)
+            .iter()
+            .map(|remote| {
+                [
+                    name(remote),
+                    qualified!("refs/heads/dev").with_namespace(Component::from(remote)),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_update_ancestry() {
+        let mut repo = mem::Repository::default();
+        let name = name(&arbitrary::gen(1));
+        let base = repo.commit(&[]);
+        let ahead = repo.commit(&[base]);
+        let diverged = repo.commit(&[base]);
+
+        let applied = update(&repo, [direct(&name, base, Policy::Abort)]).unwrap();
+        assert!(matches!(applied.updated[..], [RefUpdate::Created { .. }]));
+
+        let applied = update(&repo, [direct(&name, base, Policy::Abort)]).unwrap();
+        assert!(matches!(applied.updated[..], [RefUpdate::Skipped { .. }]));
+
+        let applied = update(&repo, [direct(&name, ahead, Policy::Abort)]).unwrap();
+        assert!(matches!(applied.updated[..], [RefUpdate::Updated { .. }]));
+        assert_eq!(refname_to_id(&repo, name.clone()).unwrap(), Some(ahead));
+
+        // Going backwards is rejected, unless allowed.
+        let applied = update(&repo, [direct(&name, base, Policy::Reject)]).unwrap();
+        assert!(applied.updated.is_empty());
+        assert!(matches!(
+            applied.rejected[..],
+            [Rejected { reason: Reason::Behind { current }, .. }] if current == ahead
+        ));
+
+        // Diverging is rejected or aborts the transaction, unless allowed.
+        let applied = update(&repo, [direct(&name, diverged, Policy::Reject)]).unwrap();
+        assert!(matches!(
+            applied.rejected[..],
+            [Rejected { reason: Reason::Diverged { current }, .. }] if current == ahead
+        ));
+        assert!(matches!(
+            update(&repo, [direct(&name, diverged, Policy::Abort)]),
+            Err(error::Update::NonFF { .. })
+        ));
+        assert_eq!(refname_to_id(&repo, name.clone()).unwrap(), Some(ahead));
+
+        let applied = update(&repo, [direct(&name, diverged, Policy::Allow)]).unwrap();
+        assert!(matches!(applied.updated[..], [RefUpdate::Updated { .. }]));
+        assert_eq!(refname_to_id(&repo, name).unwrap(), Some(diverged));
+    }
+
+    #[test]
+    fn test_update_linear_history() {
+        let mut repo = mem::Repository::default();
+        let name = name(&arbitrary::gen(1));
+        let mut history = vec![repo.commit(&[])];
+        for _ in 0..8 {
+            let parent = history[history.len() - 1];
+            history.push(repo.commit(&[parent]));
+        }
+
+        for (i, old) in history.iter().enumerate() {
+            for (j, new) in history.iter().enumerate() {
+                update(&repo, [direct(&name, *old, Policy::Allow)]).unwrap();
+
+                let applied = update(&repo, [direct(&name, *new, Policy::Reject)]).unwrap();
+                let expected = if i <= j { *new } else { *old };
+
+                assert_eq!(applied.rejected.len(), usize::from(i > j));
+                assert_eq!(refname_to_id(&repo, name.clone()).unwrap(), Some(expected));
+            }
+        }
+    }
+
+    #[test]
+    fn test_update_prune() {
+        let mut repo = mem::Repository::default();
+        let name = name(&arbitrary::gen(1));
+        let prune = || Update::Prune {
+            name: name.clone(),
+            prev: either::Left(git::raw::Oid::zero().into()),
+        };
+        let oid = repo.commit(&[]);
+
+        let applied = update(&repo, [prune()]).unwrap();
+        assert!(applied.updated.is_empty());
+        assert!(matches!(
+            applied.rejected[..],
+            [Rejected {
+                reason: Reason::Missing,
+                ..
+            }]
+        ));
+
+        update(&repo, [direct(&name, oid, Policy::Abort)]).unwrap();
+        let applied = update(&repo, [prune()]).unwrap();
+        assert!(matches!(
+            applied.updated[..],
+            [RefUpdate::Deleted { oid: deleted, .. }] if deleted == oid
+        ));
+        assert_eq!(refname_to_id(&repo, name.clone()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_update_batched() {
+        let mut repo = mem::Repository::default();
+        let oid = repo.commit(&[]);
+        let namespaces = namespaces(3);
+        let updates = || {
+            namespaces
+                .iter()
+                .flatten()
+                .map(|name| direct(name, oid, Policy::Abort))
+                .collect::<Vec<_>>()
+        };
+
+        let applied = update_batched(&repo, updates(), 3).unwrap();
+        assert_eq!(applied.updated.len(), 6);
+        assert_eq!(
+            repo.transactions(),
+            [2, 2, 2],
+            "Namespaces are not split across transactions"
+        );
+        for name in namespaces.iter().flatten() {
+            assert_eq!(refname_to_id(&repo, name.clone()).unwrap(), Some(oid));
+        }
+
+        // Skipped updates don't need a transaction.
+        let applied = update_batched(&repo, updates(), 4).unwrap();
+        assert!(applied
+            .updated
+            .iter()
+            .all(|up| matches!(up, RefUpdate::Skipped { .. })));
+        assert_eq!(repo.transactions(), [2, 2, 2]);
+
+        // A namespace larger than the batch size is written at once.
+        let oid = repo.commit(&[oid]);
+        let updates = namespaces
+            .iter()
+            .flatten()
+            .map(|name| direct(name, oid, Policy::Abort));
+        update_batched(&repo, updates, 1).unwrap();
+        assert_eq!(repo.transactions(), [2, 2, 2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn test_update_superseded() {
+        let mut repo = mem::Repository::default();
+        let name = name(&arbitrary::gen(1));
+        let base = repo.commit(&[]);
+        let ours = repo.commit(&[base]);
+        let theirs = repo.commit(&[base]);
+        let prune = || Update::Prune {
+            name: name.clone(),
+            prev: either::Left(base),
+        };
+
+        update(&repo, [direct(&name, ours, Policy::Abort)]).unwrap();
+
+        // Only the last update of a reference is checked and applied.
+        let applied = update(
+            &repo,
+            [
+                direct(&name, theirs, Policy::Abort),
+                prune(),
+                direct(&name, base, Policy::Allow),
+            ],
+        )
+        .unwrap();
+        assert!(matches!(
+            applied.updated[..],
+            [RefUpdate::Updated { new, .. }] if new == base
+        ));
+        assert!(matches!(
+            applied.rejected[..],
+            [
+                Rejected {
+                    update: Update::Direct { target, .. },
+                    reason: Reason::Superseded,
+                },
+                Rejected {
+                    update: Update::Prune { .. },
+                    reason: Reason::Superseded,
+                },
+            ] if target == theirs
+        ));
+        assert_eq!(refname_to_id(&repo, name.clone()).unwrap(), Some(base));
+    }
+
+    #[test]
+    fn test_update_rejected_ancestry() {
+        let mut repo = mem::Repository::default();
+        let namespaces = namespaces(1);
+        let [master, dev] = &namespaces[0];
+        let oid = repo.commit(&[]);
+        let missing = arbitrary::oid();
+
+        update(&repo, [direct(master, oid, Policy::Abort)]).unwrap();
+
+        // An update that can't be checked doesn't prevent the others
+        // from being applied.
+        let applied = update(
+            &repo,
+            [
+                direct(master, missing, Policy::Abort),
+                direct(dev, oid, Policy::Abort),
+            ],
+        )
+        .unwrap();
+        assert!(matches!(applied.updated[..], [RefUpdate::Created { .. }]));
+        assert!(matches!(
+            applied.rejected[..],
+            [Rejected {
+                reason: Reason::Ancestry(error::Ancestry::Missing { oid: m }),
+                ..
+            }] if m == missing
+        ));
+        assert_eq!(refname_to_id(&repo, master.clone()).unwrap(), Some(oid));
+        assert_eq!(refname_to_id(&repo, dev.clone()).unwrap(), Some(oid));
+    }
+
+    #[test]
+    fn test_merge_base() {
+        let mut repo = mem::Repository::default();
+        let root = repo.commit(&[]);
+        let base = repo.commit(&[root]);
+        let ours = repo.commit(&[base]);
+        let theirs = repo.commit(&[base]);
+        let unrelated = repo.commit(&[]);
+
+        assert_eq!(merge_base(&repo, ours, theirs).unwrap(), Some(base));
+        assert_eq!(merge_base(&repo, ours, base).unwrap(), Some(base));
+        assert_eq!(merge_base(&repo, ours, unrelated).unwrap(), None);
+    }
+
+    #[test]
+    fn test_update_batched_abort() {
+        let mut repo = mem::Repository::default();
+        let base = repo.commit(&[]);
+        let ours = repo.commit(&[base]);
+        let theirs = repo.commit(&[base]);
+        let namespaces = namespaces(2);
+        let [first, _] = &namespaces[0];
+        let [second, _] = &namespaces[1];
+
+        update(&repo, [direct(second, ours, Policy::Abort)]).unwrap();
+        assert!(matches!(
+            update_batched(
+                &repo,
+                [
+                    direct(first, base, Policy::Abort),
+                    direct(second, theirs, Policy::Abort)
+                ],
+                1
+            ),
+            Err(error::Update::NonFF { .. })
+        ));
+        assert_eq!(
+            refname_to_id(&repo, first.clone()).unwrap(),
+            None,
+            "Nothing is written if an update aborts"
+        );
+        assert_eq!(refname_to_id(&repo, second.clone()).unwrap(), Some(ours));
+    }
+
+    #[test]
+    fn test_update_batched_partial() {
+        let mut repo = mem::Repository::default();
+        let oid = repo.commit(&[]);
+        let namespaces = namespaces(3);
+        repo.lock(&namespaces[1][1]);
+
+        let updates = namespaces
+            .iter()
+            .flatten()
+            .map(|name| direct(name, oid, Policy::Abort));
+        let Err(error::Update::Commit { applied, .. }) = update_batched(&repo, updates, 2) else {
+            panic!("Writing a locked reference fails");
+        };
+        assert!(applied.is_partial());
+        assert_eq!(applied.updated.len(), 4);
+        assert_eq!(applied.failed.len(), 2);
+        assert_eq!(
+            applied
+                .failed
+                .iter()
+                .map(|up| up.refname().clone())
+                .collect::<Vec<_>>(),
+            namespaces[1]
+        );
+        for (i, name) in namespaces.iter().flatten().enumerate() {
+            let expected = (i / 2 != 1).then_some(oid);
+            assert_eq!(refname_to_id(&repo, name.clone()).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_update_symbolic() {
+        let mut repo = mem::Repository::default();
+        let namespaces = namespaces(1);
+        let [master, dev] = &namespaces[0];
+        let head = qualified!("refs/heads/head")
+            .with_namespace(master.namespace())
+            .to_owned();
+        let oid = repo.commit(&[]);
+        let next = repo.commit(&[oid]);
+
+        // The reference pointed to is created if it doesn't exist.
+        let applied = update(&repo, [symbolic(&head, master, oid, Policy::Abort)]).unwrap();
+        assert!(matches!(
+            applied.updated[..],
+            [RefUpdate::Created { .. }, RefUpdate::Created { .. }]
+        ));
+        assert_eq!(refname_to_id(&repo, head.clone()).unwrap(), Some(oid));
+        assert_eq!(
+            repo.symbolic_target(&head).unwrap(),
+            Some(master.to_ref_string())
+        );
+
+        update(&repo, [direct(master, next, Policy::Abort)]).unwrap();
+        assert_eq!(refname_to_id(&repo, head.clone()).unwrap(), Some(next));
+
+        let applied = update(&repo, [symbolic(&head, master, oid, Policy::Abort)]).unwrap();
+        assert!(matches!(
+            applied.updated[..],
+            [RefUpdate::Skipped { oid, .. }] if oid == next
+        ));
+
+        // Turning a direct reference into a symbolic one is subject to
+        // the policy of the update.
+        update(&repo, [direct(dev, oid, Policy::Abort)]).unwrap();
+        let applied = update(&repo, [symbolic(dev, master, oid, Policy::Reject)]).unwrap();
+        assert!(matches!(
+            applied.rejected[..],
+            [Rejected {
+                reason: Reason::TypeChange,
+                ..
+            }]
+        ));
+        assert!(matches!(
+            update(&repo, [symbolic(dev, master, oid, Policy::Abort)]),
+            Err(error::Update::TypeChange { .. })
+        ));
+        assert_eq!(repo.symbolic_target(dev).unwrap(), None);
+
+        let applied = update(&repo, [symbolic(dev, master, oid, Policy::Allow)]).unwrap();
+        assert!(matches!(
+            applied.updated[..],
+            [RefUpdate::Updated { old, new, .. }] if old == oid && new == next
+        ));
+        assert_eq!(
+            repo.symbolic_target(dev).unwrap(),
+            Some(master.to_ref_string())
+        );
+
+        // Writing a direct reference replaces a symbolic one.
+        update(&repo, [direct(dev, oid, Policy::Allow)]).unwrap();
+        assert_eq!(repo.symbolic_target(dev).unwrap(), None);
+        assert_eq!(refname_to_id(&repo, dev.clone()).unwrap(), Some(oid));
+    }
+
+    /// Get the updates reverting the `updated` references.
+    fn revert(updated: &[RefUpdate]) -> Vec<Update<'static>> {
+        updated
+            .iter()
+            .filter_map(|up| {
+                let name = Qualified::from_refstr(up.name())?
+                    .to_namespaced()?
+                    .to_owned();
+                match *up {
+                    RefUpdate::Created { oid, .. } => Some(Update::Prune {
+                        name,
+                        prev: either::Left(oid),
+                    }),
+                    RefUpdate::Updated { old: target, .. }
+                    | RefUpdate::Deleted { oid: target, .. } => {
+                        Some(direct(&name, target, Policy::Allow))
+                    }
+                    RefUpdate::Skipped { .. } => None,
+                }
+            })
+            .collect()
+    }
+
+    /// Get the tip of every reference that can be generated.
+    fn tips(repo: &mem::Repository) -> Vec<Option<Oid>> {
+        test::arbitrary::names()
+            .into_iter()
+            .map(|name| refname_to_id(repo, name).unwrap())
+            .collect()
+    }
+
+    #[quickcheck]
+    fn prop_update_revert(initial: Vec<Update<'static>>, updates: Vec<Update<'static>>) {
+        // Reference updates don't record whether a reference was symbolic, so only
+        // direct references can be reverted.
+        let direct = |updates: Vec<Update<'static>>| {
+            updates
+                .into_iter()
+                .filter(|up| !matches!(up, Update::Symbolic { .. }))
+                .collect::<Vec<_>>()
+        };
+        let (repo, _) = test::arbitrary::repository();
+        // N.b. an aborted update leaves the repository untouched.
+        update(&repo, direct(initial)).ok();
+        let before = tips(&repo);
+
+        if let Ok(applied) = update(&repo, direct(updates)) {
+            update(&repo, revert(&applied.updated)).unwrap();
+        }
+        assert_eq!(tips(&repo), before);
+    }
+
+    #[test]
+    fn test_update_symbolic_target_updated() {
+        let mut repo = mem::Repository::default();
+        let namespaces = namespaces(1);
+        let [master, dev] = &namespaces[0];
+        let oid = repo.commit(&[]);
+        let next = repo.commit(&[oid]);
+
+        // The reference pointed to is only written by its own update.
+        let applied = update(
+            &repo,
+            [
+                symbolic(dev, master, oid, Policy::Abort),
+                direct(master, next, Policy::Abort),
+            ],
+        )
+        .unwrap();
+        assert_eq!(applied.updated.len(), 2);
+        assert_eq!(repo.transactions(), [2]);
+        assert_eq!(refname_to_id(&repo, dev.clone()).unwrap(), Some(next));
     }
 }
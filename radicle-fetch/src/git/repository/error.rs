@@ -1,6 +1,8 @@
 use radicle::git::{ext, raw, Namespaced, Oid, Qualified};
 use thiserror::Error;
 
+use crate::git::refs::Applied;
+
 #[derive(Debug, Error)]
 #[error("could not open Git ODB")]
 pub struct Contains(#[source] pub raw::Error);
@@ -50,22 +52,13 @@ pub struct Scan {
 pub enum Update {
     #[error(transparent)]
     Ancestry(#[from] Ancestry),
-    #[error("failed to create reference from {name} to {target}")]
-    Create {
-        name: Namespaced<'static>,
-        target: Oid,
-        #[source]
-        err: raw::Error,
-    },
-    #[error("failed to delete reference {name}")]
-    Delete {
-        name: Namespaced<'static>,
-        #[source]
-        err: raw::Error,
-    },
-    #[error("failed to find ref {name}")]
-    Find {
-        name: Namespaced<'static>,
+    #[error(
+        "failed to write references, {} update(s) were not applied",
+        .applied.failed.len()
+    )]
+    Commit {
+        /// The updates applied, and the ones that failed.
+        applied: Box<Applied<'static>>,
         #[source]
         err: raw::Error,
     },
@@ -75,8 +68,8 @@ pub enum Update {
         new: Oid,
         cur: Oid,
     },
-    #[error("failed to peel ref to object")]
-    Peel(#[source] raw::Error),
     #[error(transparent)]
     Resolve(#[from] Resolve),
+    #[error("update of {name} would turn it into a symbolic reference")]
+    TypeChange { name: Namespaced<'static> },
 }
@@ -0,0 +1,162 @@
+//! In-memory [`Odb`] and [`Refdb`] test doubles, so that updates can be
+//! tested against synthetic repositories without touching the
+//! filesystem.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use either::Either;
+use radicle::git::{raw, Namespaced, Oid, Qualified, RefString};
+
+use crate::git::mem;
+use crate::git::refs::{Policy, Update};
+
+use super::{error, Edit, Odb, Refdb};
+
+/// An in-memory repository, pairing a commit graph with a [`mem::Refdb`].
+#[derive(Debug, Default)]
+pub struct Repository {
+    /// Commits and their parents.
+    commits: HashMap<Oid, Vec<Oid>>,
+    refdb: RefCell<mem::Refdb>,
+    /// Symbolic references, and the reference they point to.
+    symrefs: RefCell<HashMap<Namespaced<'static>, Namespaced<'static>>>,
+    /// References locked by someone else, which can't be written.
+    locked: HashSet<Namespaced<'static>>,
+    /// The number of edits of each transaction written.
+    transactions: RefCell<Vec<usize>>,
+}
+
+impl Repository {
+    /// Add a commit with the given parents, returning its `Oid`.
+    pub fn commit(&mut self, parents: &[Oid]) -> Oid {
+        let content = format!("{} {:?}", self.commits.len(), parents);
+        let oid = raw::Oid::hash_object(raw::ObjectType::Commit, content.as_bytes())
+            .expect("Repository::commit: hashing does not fail")
+            .into();
+        self.commits.insert(oid, parents.to_vec());
+        oid
+    }
+
+    /// Lock `name`, so that transactions writing it fail.
+    pub fn lock(&mut self, name: &Namespaced) {
+        self.locked.insert(name.to_owned());
+    }
+
+    /// Get the number of edits of each transaction written.
+    pub fn transactions(&self) -> Vec<usize> {
+        self.transactions.borrow().clone()
+    }
+
+    /// Get the commit and all its ancestors.
+    fn ancestors(&self, oid: Oid) -> HashSet<Oid> {
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::from([oid]);
+
+        while let Some(oid) = queue.pop_front() {
+            if visited.insert(oid) {
+                queue.extend(self.commits.get(&oid).into_iter().flatten());
+            }
+        }
+        visited
+    }
+}
+
+impl Odb for Repository {
+    fn contains(&self, oid: Oid) -> Result<bool, error::Contains> {
+        Ok(self.commits.contains_key(&oid))
+    }
+
+    fn peel(&self, oid: Oid) -> Result<Oid, error::Ancestry> {
+        if self.commits.contains_key(&oid) {
+            Ok(oid)
+        } else {
+            Err(error::Ancestry::Missing { oid })
+        }
+    }
+
+    fn ahead_behind(&self, new: Oid, old: Oid) -> Result<(usize, usize), error::Ancestry> {
+        let new = self.ancestors(new);
+        let old = self.ancestors(old);
+
+        Ok((new.difference(&old).count(), old.difference(&new).count()))
+    }
+
+    fn parents(&self, oid: Oid) -> Result<Vec<Oid>, error::Ancestry> {
+        self.commits
+            .get(&oid)
+            .cloned()
+            .ok_or(error::Ancestry::Missing { oid })
+    }
+
+    fn merge_base(&self, a: Oid, b: Oid) -> Result<Option<Oid>, error::Ancestry> {
+        let a = self.ancestors(a);
+        let b = self.ancestors(b);
+
+        // N.b. the best common ancestor has the most ancestors.
+        Ok(a.intersection(&b)
+            .max_by_key(|oid| self.ancestors(**oid).len())
+            .copied())
+    }
+}
+
+impl Refdb for Repository {
+    fn refname_to_id(&self, refname: &Qualified) -> Result<Option<Oid>, error::Resolve> {
+        let target = refname
+            .to_namespaced()
+            .and_then(|name| self.symrefs.borrow().get(&name).cloned());
+        let refdb = self.refdb.borrow();
+
+        Ok(match target {
+            Some(target) => refdb.refname_to_id(target),
+            None => refdb.refname_to_id(refname.clone()),
+        })
+    }
+
+    fn symbolic_target(&self, name: &Namespaced) -> Result<Option<RefString>, error::Resolve> {
+        Ok(self
+            .symrefs
+            .borrow()
+            .get(name)
+            .map(|target| target.to_ref_string()))
+    }
+
+    fn write(&self, edits: &[Edit]) -> Result<(), raw::Error> {
+        if let Some(edit) = edits.iter().find(|e| self.locked.contains(e.refname())) {
+            return Err(raw::Error::from_str(&format!(
+                "failed to lock {}",
+                edit.refname()
+            )));
+        }
+        let mut refdb = self.refdb.borrow_mut();
+        let mut symrefs = self.symrefs.borrow_mut();
+
+        for edit in edits {
+            let prune = Update::Prune {
+                name: edit.refname().clone(),
+                prev: Either::Left(raw::Oid::zero().into()),
+            };
+            symrefs.remove(&edit.refname().to_owned());
+
+            match edit {
+                Edit::Write { name, target } => {
+                    refdb.update([Update::Direct {
+                        name: name.clone(),
+                        target: *target,
+                        no_ff: Policy::Allow,
+                    }]);
+                }
+                Edit::Delete { .. } => {
+                    refdb.update([prune]);
+                }
+                Edit::Symbolic { name, target } => {
+                    refdb.update([prune]);
+                    symrefs.insert(name.to_owned(), target.to_owned());
+                }
+            }
+        }
+        self.transactions.borrow_mut().push(edits.len());
+
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::sync::atomic::{self, AtomicBool};
 use std::sync::Arc;
 
@@ -9,7 +10,9 @@ use radicle::prelude::Doc;
 use radicle::storage::git::Repository;
 use radicle::storage::ReadRepository;
 
-use crate::policy::{Allowed, BlockList};
+use crate::git;
+use crate::policy::{Allowed, BlockList, DeniedRefs};
+use crate::state::Metadata;
 use crate::transport::{ConnectionStream, Transport};
 
 /// The handle used for pulling or cloning changes from a remote peer.
@@ -26,10 +29,31 @@ pub struct Handle<S> {
     /// key in [`crate::pull`], however, we choose to allow the local
     /// peer's key in [`crate::clone`].
     pub(crate) blocked: BlockList,
+    /// References that are never fetched.
+    pub(crate) denied: DeniedRefs,
+    /// The `rad/sigrefs` tips we last fetched, per remote. Fetched
+    /// tips that don't descend from these are reported, and refused
+    /// if [`Handle::refuse_diverged`] is set.
+    pub(crate) known_sigrefs: BTreeMap<PublicKey, Oid>,
+    pub(crate) refuse_diverged: bool,
+    /// The maximum number of references a remote may sign. Remotes
+    /// signing more are not fetched.
+    pub(crate) max_refs: Option<usize>,
+    /// The maximum number of references written per transaction when
+    /// applying the fetched updates.
+    pub(crate) update_batch_size: usize,
+    /// Recover from a missing or invalid local `rad/id` when pulling,
+    /// instead of failing. See [`Handle::with_recovery`].
+    pub(crate) recover: bool,
+    /// Called once the identity and signed references are fetched.
+    pub(crate) on_metadata: Option<MetadataCallback>,
     // Signals to the pack writer to interrupt the process
     pub(crate) interrupt: Arc<AtomicBool>,
 }
 
+/// A callback for the [`Metadata`] of a fetched repository.
+pub type MetadataCallback = Box<dyn FnMut(Metadata<'_>) + Send>;
+
 impl<S> Handle<S> {
     pub fn new(
         local: PublicKey,
@@ -50,10 +74,89 @@ impl<S> Handle<S> {
             allowed: follow,
             transport,
             blocked,
+            denied: DeniedRefs::default(),
+            known_sigrefs: BTreeMap::new(),
+            refuse_diverged: false,
+            max_refs: None,
+            update_batch_size: git::repository::DEFAULT_UPDATE_BATCH_SIZE,
+            recover: false,
+            on_metadata: None,
             interrupt: Arc::new(AtomicBool::new(false)),
         })
     }
 
+    /// Limit the number of threads used to index fetched packfiles.
+    /// By default, all cores are used.
+    pub fn with_pack_threads(mut self, limit: Option<usize>) -> Self
+    where
+        S: ConnectionStream,
+    {
+        self.transport = self.transport.with_thread_limit(limit);
+        self
+    }
+
+    /// Never fetch the references in `denied`, even if they are
+    /// signed by their remote.
+    pub fn with_denied_refs(mut self, denied: DeniedRefs) -> Self {
+        self.denied = denied;
+        self
+    }
+
+    /// Check the fetched `rad/sigrefs` of each remote against the
+    /// tips we last fetched. If `refuse` is set, the updates of
+    /// remotes that were rewound or diverged are not applied.
+    pub fn with_known_sigrefs(mut self, tips: BTreeMap<PublicKey, Oid>, refuse: bool) -> Self {
+        self.known_sigrefs = tips;
+        self.refuse_diverged = refuse;
+        self
+    }
+
+    /// Don't fetch the remotes that sign more than `limit`
+    /// references. By default, there is no limit.
+    pub fn with_max_refs(mut self, limit: Option<usize>) -> Self {
+        self.max_refs = limit;
+        self
+    }
+
+    /// Write at most `size` references per transaction when applying
+    /// the fetched updates, so that large fetches don't hold the locks
+    /// of all their references at once. The updates of a single remote
+    /// are always written together. By default,
+    /// [`git::repository::DEFAULT_UPDATE_BATCH_SIZE`] is used.
+    pub fn with_update_batch_size(mut self, size: Option<usize>) -> Self {
+        self.update_batch_size = size.unwrap_or(git::repository::DEFAULT_UPDATE_BATCH_SIZE);
+        self
+    }
+
+    /// If the local `rad/id` is missing or can't be verified, eg. after
+    /// a partial clone, have [`crate::pull`] fall back to a clone-style
+    /// exchange: all special references are fetched, and the canonical
+    /// identity is reconstructed from the remote's. By default, such
+    /// pulls fail.
+    pub fn with_recovery(mut self, recover: bool) -> Self {
+        self.recover = recover;
+        self
+    }
+
+    /// Abort writing the fetched packfile as soon as `interrupt` is
+    /// set, eg. when the connection to the remote is closed.
+    pub fn with_interrupt(mut self, interrupt: Arc<AtomicBool>) -> Self {
+        self.interrupt = interrupt;
+        self
+    }
+
+    /// Call `callback` once the identity and signed references are
+    /// fetched, and before any data is fetched. When cloning, this lets
+    /// callers show the repository's metadata while the bulk of the
+    /// data is still being fetched.
+    pub fn with_metadata_callback(
+        mut self,
+        callback: impl FnMut(Metadata<'_>) + Send + 'static,
+    ) -> Self {
+        self.on_metadata = Some(Box::new(callback));
+        self
+    }
+
     pub fn is_blocked(&self, key: &PublicKey) -> bool {
         self.blocked.is_blocked(key)
     }
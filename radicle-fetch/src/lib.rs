@@ -8,15 +8,19 @@ pub(crate) mod sigrefs;
 mod refs;
 mod stage;
 mod state;
+#[cfg(test)]
+mod test;
 
 use std::io;
 use std::time::Instant;
 
 use gix_protocol::handshake;
 
-pub use handle::Handle;
-pub use policy::{Allowed, BlockList, Scope};
-pub use state::{FetchLimit, FetchResult};
+pub use handle::{Handle, MetadataCallback};
+pub use policy::{Allowed, BlockList, DeniedRefs, Scope};
+pub use refs::RemoteRef;
+pub use state::{Divergence, FetchLimit, FetchResult, Metadata, RemoteResult, Summary};
+pub use transport::fetch::error::InsufficientSpace;
 pub use transport::Transport;
 
 use radicle::crypto::PublicKey;
@@ -32,24 +36,66 @@ pub enum Error {
         #[source]
         err: io::Error,
     },
+    #[error("failed to list remote references")]
+    LsRefs {
+        #[source]
+        err: io::Error,
+    },
     #[error("failed to load `rad/id`")]
     Identity {
         #[source]
         err: Box<dyn std::error::Error + Send + Sync + 'static>,
     },
     #[error(transparent)]
-    Protocol(#[from] state::error::Protocol),
+    Protocol(state::error::Protocol),
+    #[error(transparent)]
+    InsufficientSpace(#[from] InsufficientSpace),
     #[error("missing `rad/id`")]
     MissingRadId,
     #[error("attempted to replicate from self")]
     ReplicateSelf,
 }
 
+impl Error {
+    /// Check if the fetch failed because of an I/O error, eg. the
+    /// connection to the remote dropped, as opposed to the remote
+    /// sending invalid data.
+    pub fn is_io(&self) -> bool {
+        match self {
+            Self::Handshake { .. } | Self::LsRefs { .. } => true,
+            Self::Protocol(err) => err.is_io(),
+            _ => false,
+        }
+    }
+
+    /// Check if the fetch failed because the fetched updates couldn't
+    /// be written to the local storage.
+    pub fn is_storage(&self) -> bool {
+        match self {
+            Self::Protocol(err) => err.is_storage(),
+            _ => false,
+        }
+    }
+}
+
+impl From<state::error::Protocol> for Error {
+    fn from(err: state::error::Protocol) -> Self {
+        match err.insufficient_space() {
+            Some(err) => Self::InsufficientSpace(err),
+            None => Self::Protocol(err),
+        }
+    }
+}
+
 /// Pull changes from the `remote`.
 ///
 /// It is expected that the local peer has a copy of the repository
 /// and is pulling new changes. If the repository does not exist, then
 /// [`clone`] should be used.
+///
+/// If the local `rad/id` is missing or invalid, and recovery is
+/// enabled with [`Handle::with_recovery`], the exchange is performed
+/// like a clone's instead.
 pub fn pull<S>(
     handle: &mut Handle<S>,
     limit: FetchLimit,
@@ -65,13 +111,24 @@ where
         return Err(Error::ReplicateSelf);
     }
     let handshake = perform_handshake(handle)?;
-    let state = FetchState::default();
+    let (state, refs_at) = if handle.recover && !has_identity(handle) {
+        log::warn!(
+            target: "fetch",
+            "Missing or invalid `rad/id` for {}, recovering from {remote}",
+            handle.repo.id()
+        );
+        // N.b. like a clone, we fetch all special references, since
+        // the ones we have may not be consistent with any identity.
+        (FetchState::default().recovering(), None)
+    } else {
+        (FetchState::default(), refs_at)
+    };
 
     // N.b. ensure that we ignore the local peer's key.
     handle.blocked.extend([local]);
     let result = state
         .run(handle, &handshake, limit, remote, refs_at)
-        .map_err(Error::Protocol);
+        .map_err(Error::from);
 
     log::debug!(
         target: "fetch",
@@ -102,7 +159,7 @@ where
     let state = FetchState::default();
     let result = state
         .run(handle, &handshake, limit, remote, None)
-        .map_err(Error::Protocol);
+        .map_err(Error::from);
     let elapsed = start.elapsed().as_millis();
     let rid = handle.repo.id();
 
@@ -123,6 +180,64 @@ where
     result
 }
 
+/// List the references the `remote` has for this repository, without
+/// fetching any objects.
+///
+/// This only performs the handshake and `ls-refs` steps of the
+/// protocol, which makes it a cheap way of checking whether the
+/// `remote` has anything new for us, before running a [`pull`].
+/// References that aren't valid Radicle references are skipped.
+pub fn ls_remote<S>(handle: &mut Handle<S>, remote: PublicKey) -> Result<Vec<RemoteRef>, Error>
+where
+    S: transport::ConnectionStream,
+{
+    let start = Instant::now();
+    if *handle.local() == remote {
+        return Err(Error::ReplicateSelf);
+    }
+    let handshake = perform_handshake(handle)?;
+    let prefixes = vec![
+        refs::REFS_RAD_ID.as_bstr().into(),
+        bstr::BString::from("refs/namespaces"),
+    ];
+    let refs = handle
+        .transport
+        .ls_refs(prefixes, &handshake)
+        .map_err(|err| Error::LsRefs { err })?
+        .into_iter()
+        .filter_map(|r| match refs::unpack_ref(r) {
+            Ok((name, tip)) => Some(RemoteRef::from(refs::ReceivedRef::new(tip, name))),
+            Err(e) => {
+                log::debug!(target: "fetch", "Skipping reference advertised by {remote}: {e}");
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // N.b. signal to exit the upload-pack sequence, since we're
+    // not going to fetch anything.
+    if let Err(err) = handle.transport.done() {
+        log::warn!(target: "fetch", "Attempted to send done to remote {remote}: {err}");
+    }
+    log::debug!(
+        target: "fetch",
+        "Listed {} references of {} from {remote} ({}ms)",
+        refs.len(),
+        handle.repo.id(),
+        start.elapsed().as_millis()
+    );
+    Ok(refs)
+}
+
+/// Check that the local repository has a canonical `rad/id` that can
+/// be verified.
+fn has_identity<S>(handle: &Handle<S>) -> bool {
+    match git::repository::refname_to_id(&handle.repo, refs::REFS_RAD_ID.clone()) {
+        Ok(Some(tip)) => handle.verified(tip).is_ok(),
+        Ok(None) | Err(_) => false,
+    }
+}
+
 fn perform_handshake<S>(handle: &mut Handle<S>) -> Result<handshake::Outcome, Error>
 where
     S: transport::ConnectionStream,
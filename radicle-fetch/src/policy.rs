@@ -1,8 +1,11 @@
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 
 use radicle::crypto::PublicKey;
+use radicle::git::Namespaced;
+use radicle::node::config::RefPattern;
 use radicle::node::policy::config::Config;
 use radicle::node::policy::store::Read;
+use radicle::node::policy::RefCategories;
 use radicle::prelude::RepoId;
 
 pub use radicle::node::policy::{Policy, Scope};
@@ -69,6 +72,51 @@ impl BlockList {
     }
 }
 
+/// A set of reference name patterns and categories that are never fetched.
+#[derive(Clone, Debug, Default)]
+pub struct DeniedRefs {
+    patterns: Vec<RefPattern>,
+    categories: RefCategories,
+    kept: BTreeSet<Namespaced<'static>>,
+}
+
+impl FromIterator<RefPattern> for DeniedRefs {
+    fn from_iter<T: IntoIterator<Item = RefPattern>>(iter: T) -> Self {
+        Self {
+            patterns: iter.into_iter().collect(),
+            categories: RefCategories::default(),
+            kept: BTreeSet::new(),
+        }
+    }
+}
+
+impl DeniedRefs {
+    /// Also deny the references that aren't in the given fetched `categories`.
+    pub fn with_categories(mut self, categories: RefCategories) -> Self {
+        self.categories = categories;
+        self
+    }
+
+    /// Never deny the given references, eg. the delegates' default branches, which the
+    /// canonical head of a project is computed from.
+    pub fn keeping(mut self, names: impl IntoIterator<Item = Namespaced<'static>>) -> Self {
+        self.kept.extend(names);
+        self
+    }
+
+    pub fn is_denied(&self, name: &Namespaced) -> bool {
+        if self.kept.contains(name) {
+            return false;
+        }
+        self.patterns.iter().any(|p| p.matches(name.as_str()))
+            || !self.categories.is_fetched(name.strip_namespace().as_str())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty() && self.categories == RefCategories::default()
+    }
+}
+
 pub mod error {
     use radicle::node::policy;
     use radicle::prelude::RepoId;
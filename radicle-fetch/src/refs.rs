@@ -128,6 +128,49 @@ impl TryFrom<BString> for ReceivedRefname<'_> {
     }
 }
 
+/// A reference advertised by a remote peer, see [`crate::ls_remote`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RemoteRef {
+    /// The namespace of the reference, or `None` for the canonical
+    /// `refs/rad/id`.
+    pub namespace: Option<PublicKey>,
+    /// The reference name, without its namespace.
+    pub name: Qualified<'static>,
+    /// The tip the reference points to.
+    pub tip: Oid,
+}
+
+impl RemoteRef {
+    /// The fully qualified reference name, including its namespace.
+    pub fn to_qualified(&self) -> Qualified<'static> {
+        match &self.namespace {
+            Some(remote) => self
+                .name
+                .with_namespace(Component::from(remote))
+                .to_owned()
+                .into(),
+            None => self.name.clone(),
+        }
+    }
+}
+
+impl From<ReceivedRef> for RemoteRef {
+    fn from(r: ReceivedRef) -> Self {
+        match r.name {
+            ReceivedRefname::Namespaced { remote, suffix } => Self {
+                namespace: Some(remote),
+                name: suffix.either(|s| Qualified::from(s).to_owned(), |q| q.to_owned()),
+                tip: r.tip,
+            },
+            ReceivedRefname::RadId => Self {
+                namespace: None,
+                name: REFS_RAD_ID.clone(),
+                tip: r.tip,
+            },
+        }
+    }
+}
+
 /// A reference name and the associated tip received during an
 /// exchange with another peer.
 #[derive(Debug)]
@@ -118,6 +118,26 @@ impl RemoteRefs {
                 Ok(acc)
             })
     }
+
+    /// Remove the remotes that sign more than `limit` references,
+    /// returning how many references each of them signs.
+    pub(crate) fn remove_oversized(&mut self, limit: usize) -> BTreeMap<PublicKey, usize> {
+        let oversized = self
+            .0
+            .iter()
+            .map(|(remote, sigrefs)| (*remote, sigrefs.sigrefs.len()))
+            .filter(|(_, count)| *count > limit)
+            .collect::<BTreeMap<_, _>>();
+        self.0.retain(|remote, _| !oversized.contains_key(remote));
+
+        oversized
+    }
+}
+
+impl FromIterator<(PublicKey, SignedRefsAt)> for RemoteRefs {
+    fn from_iter<T: IntoIterator<Item = (PublicKey, SignedRefsAt)>>(iter: T) -> Self {
+        Self(iter.into_iter().collect())
+    }
 }
 
 impl Deref for RemoteRefs {
@@ -136,3 +156,36 @@ impl<'a> IntoIterator for &'a RemoteRefs {
         self.0.iter()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use qcheck::Arbitrary as _;
+
+    use super::*;
+    use crate::test::arbitrary;
+
+    #[test]
+    fn prop_remove_oversized() {
+        let mut g = qcheck::Gen::new(8);
+
+        for _ in 0..32 {
+            let mut remotes = arbitrary::remote_refs(&mut g);
+            let limit = usize::arbitrary(&mut g) % 8;
+            let counts = remotes
+                .iter()
+                .map(|(remote, refs)| (*remote, refs.sigrefs.len()))
+                .collect::<BTreeMap<_, _>>();
+            let removed = remotes.remove_oversized(limit);
+
+            for (remote, count) in counts {
+                if count > limit {
+                    assert_eq!(removed.get(&remote), Some(&count));
+                    assert!(!remotes.contains_key(&remote));
+                } else {
+                    assert!(!removed.contains_key(&remote));
+                    assert!(remotes.contains_key(&remote));
+                }
+            }
+        }
+    }
+}
@@ -34,13 +34,13 @@ use either::Either;
 use gix_protocol::handshake::Ref;
 use nonempty::NonEmpty;
 use radicle::crypto::PublicKey;
-use radicle::git::{refname, Component, Namespaced, Qualified};
+use radicle::git::{refname, Component, Namespaced, Oid, Qualified, RefString};
 use radicle::storage::git::Repository;
-use radicle::storage::refs::{RefsAt, Special};
+use radicle::storage::refs::{RefsAt, Special, Version};
 use radicle::storage::ReadRepository;
 
 use crate::git::refs::{Policy, Update, Updates};
-use crate::policy::BlockList;
+use crate::policy::{BlockList, DeniedRefs};
 use crate::refs::{ReceivedRef, ReceivedRefname};
 use crate::sigrefs;
 use crate::state::FetchState;
@@ -102,10 +102,28 @@ pub mod error {
 ///       to the server.
 ///   5. `prepare_updates`: prepares the set of updates to update the
 ///      refdb (in-memory and production).
+///
+/// If the stage has [`ProtocolStage::want_refs`] and the server
+/// supports them, steps 1. and 4. are replaced by asking for those
+/// references by name in the `fetch`, and steps 2. and 3. are run on
+/// the references the server resolved.
 pub(crate) trait ProtocolStage {
+    /// The data limit of this stage, in bytes.
+    fn limit(&self) -> u64;
+
     /// If and how to perform `ls-refs`.
     fn ls_refs(&self) -> Option<NonEmpty<BString>>;
 
+    /// References to ask for by name when fetching, instead of
+    /// performing `ls-refs`, letting the server resolve their tips.
+    ///
+    /// N.b. the fetch fails if the server does not have any one of
+    /// these references, so only references that must exist should be
+    /// returned.
+    fn want_refs(&self) -> Option<NonEmpty<Qualified<'static>>> {
+        None
+    }
+
     /// Filter a remote-advertised [`Ref`].
     ///
     /// Return `Some` if the ref should be considered, `None` otherwise. This
@@ -159,10 +177,18 @@ pub struct CanonicalId {
 }
 
 impl ProtocolStage for CanonicalId {
+    fn limit(&self) -> u64 {
+        self.limit
+    }
+
     fn ls_refs(&self) -> Option<NonEmpty<BString>> {
         Some(NonEmpty::new(refs::REFS_RAD_ID.as_bstr().into()))
     }
 
+    fn want_refs(&self) -> Option<NonEmpty<Qualified<'static>>> {
+        Some(NonEmpty::new(refs::REFS_RAD_ID.clone()))
+    }
+
     fn ref_filter(&self, r: Ref) -> Option<ReceivedRef> {
         match refs::unpack_ref(r).ok()? {
             (
@@ -244,6 +270,10 @@ pub struct SpecialRefs {
 }
 
 impl ProtocolStage for SpecialRefs {
+    fn limit(&self) -> u64 {
+        self.limit
+    }
+
     fn ls_refs(&self) -> Option<NonEmpty<BString>> {
         match &self.followed {
             policy::Allowed::All => Some(NonEmpty::new("refs/namespaces".into())),
@@ -323,6 +353,10 @@ pub struct SigrefsAt {
 }
 
 impl ProtocolStage for SigrefsAt {
+    fn limit(&self) -> u64 {
+        self.limit
+    }
+
     fn ls_refs(&self) -> Option<NonEmpty<BString>> {
         // N.b. the `Oid`s are known but the `rad/sigrefs` are still
         // asked for to mark them for updating the fetch state.
@@ -406,11 +440,56 @@ pub struct DataRefs {
     pub remotes: sigrefs::RemoteRefs,
     /// The data limit for this stage of fetching.
     pub limit: u64,
+    /// References that are never fetched.
+    pub denied: DeniedRefs,
+    /// Signed references that the remote doesn't have, and are
+    /// therefore not fetched.
+    pub withheld: BTreeSet<Namespaced<'static>>,
+}
+
+impl DataRefs {
+    /// Get the signed references of `remote`, as namespaced
+    /// references, leaving out the ones that are denied or withheld.
+    fn signed<'a>(
+        &'a self,
+        remote: &'a PublicKey,
+        refs: &'a sigrefs::SignedRefsAt,
+    ) -> impl Iterator<Item = (Namespaced<'static>, radicle::git::Oid)> + 'a {
+        refs.iter().filter_map(move |(name, tip)| {
+            let name = Qualified::from_refstr(name)
+                .and_then(|q| refs::ReceivedRefname::remote(*remote, q).to_namespaced())?
+                .to_owned();
+            (!self.denied.is_denied(&name) && !self.withheld.contains(&name))
+                .then_some((name, *tip))
+        })
+    }
+
+    /// Get the signed references that are denied, per remote.
+    pub fn denied(&self) -> Vec<Namespaced<'static>> {
+        if self.denied.is_empty() {
+            return vec![];
+        }
+        self.remotes
+            .iter()
+            .flat_map(|(remote, refs)| {
+                refs.iter().filter_map(move |(name, _)| {
+                    let name = Qualified::from_refstr(name)?
+                        .with_namespace(Component::from(remote))
+                        .to_owned();
+                    self.denied.is_denied(&name).then_some(name)
+                })
+            })
+            .collect()
+    }
 }
 
 impl ProtocolStage for DataRefs {
     // We don't need to ask for refs since we have all reference names
     // and `Oid`s in `rad/sigrefs`.
+    fn limit(&self) -> u64 {
+        self.limit
+    }
+
     fn ls_refs(&self) -> Option<NonEmpty<BString>> {
         None
     }
@@ -435,14 +514,7 @@ impl ProtocolStage for DataRefs {
         let mut wants_haves = WantsHaves::default();
 
         for (remote, loaded) in &self.remotes {
-            wants_haves.add(
-                refdb,
-                loaded.refs.iter().filter_map(|(refname, tip)| {
-                    let refname = Qualified::from_refstr(refname)
-                        .map(|refname| refname.with_namespace(Component::from(remote)))?;
-                    Some((refname, *tip))
-                }),
-            )?;
+            wants_haves.add(refdb, self.signed(remote, loaded))?;
         }
 
         Ok(wants_haves)
@@ -458,49 +530,76 @@ impl ProtocolStage for DataRefs {
 
         for (remote, refs) in &self.remotes {
             let mut signed = HashSet::with_capacity(refs.refs.len());
-            for (name, tip) in refs.iter() {
-                let tracking: Namespaced<'_> = Qualified::from_refstr(name)
-                    .and_then(|q| refs::ReceivedRefname::remote(*remote, q).to_namespaced())
-                    .expect("we checked sigrefs well-formedness in wants_refs already");
+            // N.b. denied and withheld refs are left out, and pruned if
+            // we have them.
+            for (tracking, tip) in self.signed(remote, refs) {
                 signed.insert(tracking.clone());
                 updates.add(
                     *remote,
                     Update::Direct {
                         name: tracking,
-                        target: *tip,
+                        target: tip,
                         no_ff: Policy::Allow,
                     },
                 );
             }
 
             // Prune refs not in signed
-            let prefix_rad = refname!("refs/rad");
-            for (name, target) in repo.references_of(remote)? {
-                // 'rad/' refs are never subject to pruning
-                if name.starts_with(prefix_rad.as_str()) {
-                    continue;
+            let prunes = prunes(remote, repo.references_of(remote)?, |name, namespaced| {
+                if signed.contains(namespaced) {
+                    return true;
                 }
-
-                let name = Qualified::from_refstr(name)
-                    .expect("BUG: reference is guaranteed to be Qualified")
-                    .with_namespace(Component::from(remote));
-
-                if !signed.contains(&name) {
-                    updates.add(
-                        *remote,
-                        Update::Prune {
-                            name,
-                            prev: either::Left(target),
-                        },
+                // Version 2 sigrefs record deletions, so a reference that is neither
+                // signed nor deleted may only be missing due to partial data, and is
+                // kept. Denied and withheld references are always pruned.
+                if refs.refs.version() >= Version::V2
+                    && !refs.refs.is_deleted(name)
+                    && !self.denied.is_denied(namespaced)
+                    && !self.withheld.contains(namespaced)
+                {
+                    log::warn!(
+                        target: "fetch",
+                        "Keeping {namespaced}: not found in signed refs of {remote}, but not deleted"
                     );
+                    return true;
                 }
-            }
+                false
+            });
+            updates.append(*remote, prunes);
         }
 
         Ok(updates)
     }
 }
 
+/// Get the updates pruning the `existing` references of `remote` that
+/// aren't kept, according to `keep`.
+///
+/// `rad/` references are never subject to pruning.
+fn prunes<'a>(
+    remote: &PublicKey,
+    existing: impl IntoIterator<Item = (RefString, Oid)>,
+    mut keep: impl FnMut(&RefString, &Namespaced<'static>) -> bool,
+) -> Vec<Update<'a>> {
+    let prefix_rad = refname!("refs/rad");
+
+    existing
+        .into_iter()
+        .filter(|(name, _)| !name.starts_with(prefix_rad.as_str()))
+        .filter_map(|(name, target)| {
+            let namespaced = Qualified::from_refstr(&name)
+                .expect("BUG: reference is guaranteed to be Qualified")
+                .with_namespace(Component::from(remote))
+                .to_owned();
+
+            (!keep(&name, &namespaced)).then_some(Update::Prune {
+                name: namespaced,
+                prev: either::Left(target),
+            })
+        })
+        .collect()
+}
+
 // N.b. the `delegates` are the delegates of the repository, with the
 // potential removal of the local peer in the case of a `pull`.
 fn special_refs_updates<'a>(
@@ -607,3 +706,37 @@ where
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use qcheck_macros::quickcheck;
+
+    use super::*;
+    use crate::test::arbitrary;
+
+    #[quickcheck]
+    fn prop_prunes(keep: Vec<bool>) {
+        let remote = arbitrary::remotes()[0];
+        let (_, commits) = arbitrary::repository();
+        let existing = arbitrary::refnames()
+            .into_iter()
+            .map(|name| (name.to_ref_string(), commits[0]))
+            .collect::<Vec<_>>();
+        let kept = |i: usize| keep.get(i).copied().unwrap_or_default();
+        let prunes = prunes(&remote, existing.clone(), |name, _| {
+            kept(existing.iter().position(|(n, _)| n == name).unwrap())
+        });
+        let pruned = prunes
+            .iter()
+            .map(|up| up.refname().strip_namespace().to_ref_string())
+            .collect::<Vec<_>>();
+
+        for (i, (name, _)) in existing.iter().enumerate() {
+            let rad = name.starts_with("refs/rad/");
+            assert_eq!(pruned.contains(name), !rad && !kept(i), "{name}");
+        }
+        assert!(prunes
+            .iter()
+            .all(|up| up.refname().namespace() == Component::from(&remote)));
+    }
+}
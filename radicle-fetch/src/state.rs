@@ -1,10 +1,12 @@
 use std::collections::{BTreeMap, BTreeSet};
 use std::time::Instant;
 
+use bstr::BString;
 use gix_protocol::handshake;
 use radicle::crypto::PublicKey;
-use radicle::git::{Oid, Qualified};
+use radicle::git::{Component, Namespaced, Oid, Qualified};
 use radicle::identity::{Did, Doc, DocError};
+use radicle::node::sigrefs::Deviation;
 
 use radicle::prelude::Verified;
 use radicle::storage;
@@ -14,7 +16,7 @@ use radicle::storage::{
 };
 
 use crate::git;
-use crate::git::refs::{Applied, Update};
+use crate::git::refs::{Applied, Reason, Rejected, Update};
 use crate::git::repository;
 use crate::sigrefs::SignedRefsAt;
 use crate::stage;
@@ -35,6 +37,7 @@ pub mod error {
     use radicle::prelude::PublicKey;
     use thiserror::Error;
 
+    use crate::transport::fetch::error::InsufficientSpace;
     use crate::{git, git::repository, handle, sigrefs, stage};
 
     #[derive(Debug, Error)]
@@ -83,6 +86,34 @@ pub mod error {
         Validation(#[from] radicle::storage::Error),
     }
 
+    impl Protocol {
+        /// Check if the fetch failed because of an I/O error, eg. the
+        /// connection to the remote dropped while fetching.
+        pub fn is_io(&self) -> bool {
+            matches!(self, Self::Io(_) | Self::Step(Step::Io(_)))
+        }
+
+        /// Whether the fetched updates couldn't be written to the
+        /// local storage.
+        pub fn is_storage(&self) -> bool {
+            matches!(
+                self,
+                Self::RefdbUpdate(repository::error::Update::Commit { .. })
+            )
+        }
+
+        /// If the fetch failed because the storage ran out of space,
+        /// return by how much.
+        pub fn insufficient_space(&self) -> Option<InsufficientSpace> {
+            match self {
+                Self::Io(err) | Self::Step(Step::Io(err)) => {
+                    err.get_ref()?.downcast_ref::<InsufficientSpace>().copied()
+                }
+                _ => None,
+            }
+        }
+    }
+
     #[derive(Debug, Error)]
     pub enum Canonical {
         #[error(transparent)]
@@ -110,15 +141,106 @@ impl Default for FetchLimit {
     }
 }
 
+/// A remote whose fetched `rad/sigrefs` don't descend from the tip we
+/// last fetched. See [`Handle::with_known_sigrefs`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    /// The remote namespace.
+    pub remote: PublicKey,
+    /// The tip we last fetched.
+    pub known: Oid,
+    /// The tip that was fetched.
+    pub received: Oid,
+    /// How the fetched tip deviates from the known tip.
+    pub deviation: Deviation,
+    /// Whether the updates of the remote were refused.
+    pub refused: bool,
+}
+
+/// A reference of a remote that wasn't updated because the fetched tip
+/// doesn't descend from the local tip, eg. because the remote
+/// force-pushed it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RefDivergence {
+    /// The namespaced reference.
+    pub name: Namespaced<'static>,
+    /// The local tip of the reference.
+    pub local: Oid,
+    /// The tip that was fetched.
+    pub received: Oid,
+    /// The best common ancestor of the local and fetched tips, if any.
+    pub base: Option<Oid>,
+    /// Whether the `rad/sigrefs` of the remote diverged as well. See
+    /// [`Divergence`].
+    pub sigrefs_diverged: bool,
+}
+
+/// The identity and signed references of a repository, available once
+/// the special references were fetched, and before any data is
+/// fetched. See [`Handle::with_metadata_callback`].
+#[derive(Clone, Copy, Debug)]
+pub struct Metadata<'a> {
+    /// The canonical identity document.
+    pub doc: &'a Doc<Verified>,
+    /// The signed references of each remote that is going to be
+    /// fetched.
+    pub sigrefs: &'a BTreeMap<PublicKey, SignedRefsAt>,
+}
+
+/// The outcome of a fetch for a single remote namespace.
+#[derive(Debug, Default)]
+pub struct RemoteResult {
+    /// The `rad/sigrefs` tip the references of the remote were fetched
+    /// at, or `None` if they weren't fetched, eg. because they failed
+    /// validation.
+    pub sigrefs: Option<Oid>,
+    /// The set of applied changes to the namespace of the remote.
+    pub applied: Applied<'static>,
+    /// Any validation errors that were found for the remote.
+    pub validations: sigrefs::Validations,
+    /// The references of the remote that weren't updated because they
+    /// diverged from the local tips.
+    pub diverged: Vec<RefDivergence>,
+}
+
+/// The outcome of a successful fetch, across all remotes.
+#[derive(Debug, Default)]
+pub struct Summary {
+    /// The set of applied changes to the reference store.
+    pub applied: Applied<'static>,
+    /// The set of namespaces that were fetched.
+    pub remotes: BTreeSet<PublicKey>,
+    /// Any validation errors that were found while fetching.
+    pub validations: sigrefs::Validations,
+}
+
+impl FromIterator<(PublicKey, RemoteResult)> for Summary {
+    fn from_iter<T: IntoIterator<Item = (PublicKey, RemoteResult)>>(iter: T) -> Self {
+        iter.into_iter()
+            .fold(Summary::default(), |mut summary, (remote, mut result)| {
+                if result.sigrefs.is_some() {
+                    summary.remotes.insert(remote);
+                }
+                summary.applied.append(&mut result.applied);
+                summary.validations.append(&mut result.validations);
+                summary
+            })
+    }
+}
+
 #[derive(Debug)]
 pub enum FetchResult {
     Success {
-        /// The set of applied changes to the reference store.
-        applied: Applied<'static>,
-        /// The set of namespaces that were fetched.
-        remotes: BTreeSet<PublicKey>,
-        /// Any validation errors that were found while fetching.
-        validations: sigrefs::Validations,
+        /// The outcome of the fetch for each remote that was fetched,
+        /// or failed validation. See [`Summary`] for the outcome
+        /// across all remotes.
+        remotes: BTreeMap<PublicKey, RemoteResult>,
+        /// The remotes whose `rad/sigrefs` diverged from the tips we
+        /// last fetched.
+        divergences: Vec<Divergence>,
+        /// Signed references that weren't fetched because they are
+        /// denied. See [`Handle::with_denied_refs`].
+        denied: Vec<Namespaced<'static>>,
     },
     Failed {
         /// The threshold that needed to be met.
@@ -127,17 +249,50 @@ pub enum FetchResult {
         delegates: BTreeSet<PublicKey>,
         /// Validation errors that were found while fetching.
         validations: sigrefs::Validations,
+        /// The remotes whose `rad/sigrefs` diverged from the tips we
+        /// last fetched.
+        divergences: Vec<Divergence>,
     },
 }
 
 impl FetchResult {
-    pub fn rejected(&self) -> impl Iterator<Item = &Update<'static>> {
+    pub fn rejected(&self) -> impl Iterator<Item = &Rejected<'static>> {
+        match self {
+            Self::Success { remotes, .. } => {
+                either::Either::Left(remotes.values().flat_map(|r| r.applied.rejected.iter()))
+            }
+            Self::Failed { .. } => either::Either::Right(std::iter::empty()),
+        }
+    }
+
+    pub fn validations(&self) -> impl Iterator<Item = &sigrefs::Validation> {
+        match self {
+            Self::Success { remotes, .. } => {
+                either::Either::Left(remotes.values().flat_map(|r| r.validations.iter()))
+            }
+            Self::Failed { validations, .. } => either::Either::Right(validations.iter()),
+        }
+    }
+
+    /// The references that weren't updated because they diverged from
+    /// the local tips, along with their remote.
+    pub fn ref_divergences(&self) -> impl Iterator<Item = (&PublicKey, &RefDivergence)> {
         match self {
-            Self::Success { applied, .. } => either::Either::Left(applied.rejected.iter()),
+            Self::Success { remotes, .. } => either::Either::Left(
+                remotes
+                    .iter()
+                    .flat_map(|(remote, r)| r.diverged.iter().map(move |d| (remote, d))),
+            ),
             Self::Failed { .. } => either::Either::Right(std::iter::empty()),
         }
     }
 
+    pub fn divergences(&self) -> &[Divergence] {
+        match self {
+            Self::Success { divergences, .. } | Self::Failed { divergences, .. } => divergences,
+        }
+    }
+
     pub fn is_success(&self) -> bool {
         match self {
             Self::Success { .. } => true,
@@ -160,9 +315,27 @@ pub struct FetchState {
     sigrefs: SigrefTips,
     /// Seen reference tips, per remote.
     tips: BTreeMap<PublicKey, Vec<Update<'static>>>,
+    /// References advertised by the remote, per `ls-refs` prefix, so
+    /// that each prefix is only listed once per exchange. See
+    /// [`FetchState::ls_refs`].
+    advertised: BTreeMap<BString, Vec<handshake::Ref>>,
+    /// Whether the local `rad/id` is ignored in favour of the one
+    /// fetched from the remote. See [`FetchState::recovering`].
+    recovering: bool,
+    /// Signed references that the remote doesn't have. See
+    /// [`FetchState::withheld`].
+    withheld: BTreeSet<Namespaced<'static>>,
 }
 
 impl FetchState {
+    /// Recover a repository whose local `rad/id` is missing or
+    /// invalid, by anchoring the exchange on the canonical `rad/id` of
+    /// the remote instead.
+    pub fn recovering(mut self) -> Self {
+        self.recovering = true;
+        self
+    }
+
     /// Remove all tips associated with this `remote` in the
     /// `FetchState`.
     pub fn prune(&mut self, remote: &PublicKey) {
@@ -198,6 +371,119 @@ impl FetchState {
         ap
     }
 
+    /// Ask the remote for the references matching `prefixes`, reusing
+    /// the references advertised for earlier `ls-refs` calls where
+    /// possible.
+    ///
+    /// A prefix is served from the cache if it, or a prefix it
+    /// starts with, was listed before, since the advertisement of the
+    /// remote is not expected to change during an exchange. See
+    /// [`FetchState::invalidate_advertised`].
+    pub(crate) fn ls_refs<S>(
+        &mut self,
+        handle: &mut Handle<S>,
+        prefixes: impl IntoIterator<Item = BString>,
+        handshake: &handshake::Outcome,
+    ) -> Result<Vec<handshake::Ref>, std::io::Error>
+    where
+        S: transport::ConnectionStream,
+    {
+        self.ls_refs_cached(prefixes, |missing| {
+            handle.transport.ls_refs(missing, handshake)
+        })
+    }
+
+    /// Get the references matching `prefixes` from the cached
+    /// advertisement, listing the prefixes that aren't cached with
+    /// `ls_refs`, in a single call.
+    fn ls_refs_cached<E>(
+        &mut self,
+        prefixes: impl IntoIterator<Item = BString>,
+        ls_refs: impl FnOnce(Vec<BString>) -> Result<Vec<handshake::Ref>, E>,
+    ) -> Result<Vec<handshake::Ref>, E> {
+        let mut refs = BTreeMap::new();
+        let mut missing = Vec::new();
+
+        for prefix in prefixes {
+            match self
+                .advertised
+                .iter()
+                .find(|(cached, _)| prefix.starts_with(cached))
+            {
+                Some((_, cached)) => refs.extend(
+                    cached
+                        .iter()
+                        .filter(|r| r.unpack().0.starts_with(&prefix))
+                        .map(|r| (r.unpack().0.to_owned(), r.clone())),
+                ),
+                None => missing.push(prefix),
+            }
+        }
+        if !missing.is_empty() {
+            let advertised = ls_refs(missing.clone())?;
+
+            for prefix in missing {
+                let matching = advertised
+                    .iter()
+                    .filter(|r| r.unpack().0.starts_with(&prefix))
+                    .cloned()
+                    .collect();
+                self.advertised.insert(prefix, matching);
+            }
+            refs.extend(advertised.into_iter().map(|r| (r.unpack().0.to_owned(), r)));
+        } else {
+            log::trace!(target: "fetch", "Using cached advertisement of the remote");
+        }
+        Ok(refs.into_values().collect())
+    }
+
+    /// Get the references signed by `remotes` that the remote doesn't
+    /// advertise, eg. because they are denied by its configuration,
+    /// apart from the ones in `kept`.
+    ///
+    /// These can't be fetched from the remote, so they are skipped
+    /// rather than failing the fetch, as if we had denied them.
+    fn withheld<S>(
+        &mut self,
+        handle: &mut Handle<S>,
+        handshake: &handshake::Outcome,
+        remotes: &sigrefs::RemoteRefs,
+        kept: &[Namespaced<'static>],
+    ) -> Result<BTreeSet<Namespaced<'static>>, std::io::Error>
+    where
+        S: transport::ConnectionStream,
+    {
+        let prefixes = remotes
+            .keys()
+            .map(|remote| BString::from(format!("refs/namespaces/{remote}/refs/")));
+        let advertised = self
+            .ls_refs(handle, prefixes, handshake)?
+            .into_iter()
+            .filter_map(|r| refs::unpack_ref(r).ok())
+            .filter_map(|(name, _)| name.to_namespaced())
+            .collect::<BTreeSet<_>>();
+
+        Ok(remotes
+            .iter()
+            .flat_map(|(remote, refs)| {
+                refs.iter().filter_map(move |(name, _)| {
+                    Some(
+                        Qualified::from_refstr(name)?
+                            .with_namespace(Component::from(remote))
+                            .to_owned(),
+                    )
+                })
+            })
+            .filter(|name| !advertised.contains(name) && !kept.contains(name))
+            .collect())
+    }
+
+    /// Forget the references advertised by the remote, so that they
+    /// are listed again by the next [`FetchState::ls_refs`].
+    pub(crate) fn invalidate_advertised(&mut self) {
+        self.advertised.clear();
+    }
+
     pub(crate) fn as_cached<'a, S>(&'a mut self, handle: &'a mut Handle<S>) -> Cached<'a, S> {
         Cached {
             handle,
@@ -219,25 +505,60 @@ impl FetchState {
         S: transport::ConnectionStream,
         F: ProtocolStage,
     {
-        let refs = match step.ls_refs() {
-            Some(refs) => handle
-                .transport
-                .ls_refs(refs.into(), handshake)?
-                .into_iter()
-                .filter_map(|r| step.ref_filter(r))
-                .collect::<Vec<_>>(),
-            None => vec![],
-        };
-        log::trace!(target: "fetch", "Received refs {:?}", refs);
-        step.pre_validate(&refs)?;
-
-        let wants_haves = step.wants_haves(&handle.repo, &refs)?;
-        if !wants_haves.wants.is_empty() {
-            handle
-                .transport
-                .fetch(wants_haves, handle.interrupt.clone(), handshake)?;
-        } else {
-            log::trace!(target: "fetch", "Nothing to fetch")
+        let refs = match step
+            .want_refs()
+            .filter(|_| transport::Transport::<S>::supports_want_ref(handshake))
+        {
+            Some(want_refs) => {
+                let mut wants_haves = transport::WantsHaves::default();
+                wants_haves
+                    .want_refs(&handle.repo, want_refs)
+                    .map_err(stage::error::WantsHaves::from)?
+                    .walk(&handle.repo)
+                    .map_err(stage::error::WantsHaves::from)?;
+                let refs = handle
+                    .transport
+                    .fetch(
+                        wants_haves,
+                        step.limit(),
+                        handle.interrupt.clone(),
+                        handshake,
+                    )?
+                    .into_iter()
+                    .filter_map(|r| step.ref_filter(r))
+                    .collect::<Vec<_>>();
+                log::trace!(target: "fetch", "Received wanted refs {:?}", refs);
+                step.pre_validate(&refs)?;
+                refs
+            }
+            None => {
+                let refs = match step.ls_refs() {
+                    Some(refs) => self
+                        .ls_refs(handle, refs, handshake)?
+                        .into_iter()
+                        .filter_map(|r| step.ref_filter(r))
+                        .collect::<Vec<_>>(),
+                    None => vec![],
+                };
+                log::trace!(target: "fetch", "Received refs {:?}", refs);
+                step.pre_validate(&refs)?;
+
+                let mut wants_haves = step.wants_haves(&handle.repo, &refs)?;
+                if !wants_haves.wants.is_empty() {
+                    wants_haves
+                        .walk(&handle.repo)
+                        .map_err(stage::error::WantsHaves::from)?;
+                    handle.transport.fetch(
+                        wants_haves,
+                        step.limit(),
+                        handle.interrupt.clone(),
+                        handshake,
+                    )?;
+                } else {
+                    log::trace!(target: "fetch", "Nothing to fetch")
+                };
+                refs
+            }
         };
 
         let mut fetched = BTreeSet::new();
@@ -401,7 +722,7 @@ impl FetchState {
         } else {
             anchor.threshold
         };
-        let signed_refs = self.run_special_refs(
+        let mut signed_refs = self.run_special_refs(
             handle,
             handshake,
             delegates.clone(),
@@ -417,10 +738,44 @@ impl FetchState {
             start.elapsed().as_millis()
         );
 
+        if let Some(callback) = handle.on_metadata.as_mut() {
+            callback(Metadata {
+                doc: &anchor,
+                sigrefs: &signed_refs,
+            });
+        }
+
+        // N.b. remotes signing too many references are dropped before
+        // their data is fetched, so that they never reach the refdb.
+        let oversized = match handle.max_refs {
+            Some(limit) => signed_refs
+                .remove_oversized(limit)
+                .into_iter()
+                .map(|(remote, count)| (remote, count, limit))
+                .collect(),
+            None => Vec::new(),
+        };
+        for (remote, _, _) in &oversized {
+            self.prune(remote);
+        }
+
+        // N.b. the remote may not have all the references it serves
+        // the signed refs of, eg. if it denies some of them. These are
+        // skipped, so that we can still fetch the rest from it. The
+        // delegates' default branches are never denied, and are always
+        // asked for.
+        let heads = delegate_heads(&anchor);
+        self.withheld = self.withheld(handle, handshake, &signed_refs, &heads)?;
+        for name in &self.withheld {
+            log::debug!(target: "fetch", "Skipped reference {name}, which {remote} doesn't have");
+        }
+
         let data_refs = stage::DataRefs {
             remote,
             remotes: signed_refs,
             limit: limit.refs,
+            denied: handle.denied.clone().keeping(heads),
+            withheld: self.withheld.clone(),
         };
         self.run_stage(handle, handshake, &data_refs)?;
         log::debug!(
@@ -433,6 +788,8 @@ impl FetchState {
         // N.b. signal to exit the upload-pack sequence
         // We're finished fetching on this side, and all that's left
         // is validation.
+        // N.b. the advertisement is of no use once the exchange is over.
+        self.invalidate_advertised();
         match handle.transport.done() {
             Ok(()) => log::debug!(target: "fetch", "Sent done signal to remote {remote}"),
             Err(err) => {
@@ -443,12 +800,18 @@ impl FetchState {
         // Run validation of signed refs, pruning any offending
         // remotes from the tips, thus not updating the production Git
         // repository.
-        let mut failures = sigrefs::Validations::default();
+        let mut failures = BTreeMap::<PublicKey, sigrefs::Validations>::new();
+        let mut divergences = Vec::new();
+        let denied = data_refs.denied();
+        for name in &denied {
+            log::debug!(target: "fetch", "Skipped denied reference {name}");
+        }
         let signed_refs = data_refs.remotes;
 
         // We may prune fetched remotes, so we keep track of
-        // non-pruned, fetched remotes here.
-        let mut remotes = BTreeSet::new();
+        // non-pruned, fetched remotes here, along with the
+        // `rad/sigrefs` tip they were fetched at.
+        let mut remotes = BTreeMap::new();
 
         // The valid delegates start with all delegates that this peer
         // currently has valid references for
@@ -461,6 +824,24 @@ impl FetchState {
             .collect::<BTreeSet<_>>();
         let mut failed_delegates = BTreeSet::new();
 
+        for (remote, count, limit) in oversized {
+            if delegates.contains(&remote) {
+                log::warn!(target: "fetch", "Pruning delegate {remote} tips, signing {count} refs");
+                valid_delegates.remove(&remote);
+                failed_delegates.insert(remote);
+            } else {
+                log::debug!(target: "fetch", "Pruning non-delegate {remote} tips, signing {count} refs");
+            }
+            failures
+                .entry(remote)
+                .or_default()
+                .push(sigrefs::Validation::TooManyRefs {
+                    remote,
+                    count,
+                    limit,
+                });
+        }
+
         // TODO(finto): this might read better if it got its own
         // private function.
         for remote in signed_refs.keys() {
@@ -474,12 +855,18 @@ impl FetchState {
             match remote {
                 sigrefs::DelegateStatus::NonDelegate { remote, data: None } => {
                     log::debug!(target: "fetch", "Pruning non-delegate {remote} tips, missing 'rad/sigrefs'");
-                    failures.push(sigrefs::Validation::MissingRadSigRefs(remote));
+                    failures
+                        .entry(remote)
+                        .or_default()
+                        .push(sigrefs::Validation::MissingRadSigRefs(remote));
                     self.prune(&remote);
                 }
                 sigrefs::DelegateStatus::Delegate { remote, data: None } => {
                     log::warn!(target: "fetch", "Pruning delegate {remote} tips, missing 'rad/sigrefs'");
-                    failures.push(sigrefs::Validation::MissingRadSigRefs(remote));
+                    failures
+                        .entry(remote)
+                        .or_default()
+                        .push(sigrefs::Validation::MissingRadSigRefs(remote));
                     self.prune(&remote);
                     // This delegate has removed their `rad/sigrefs`.
                     // Technically, we can continue with their
@@ -506,7 +893,16 @@ impl FetchState {
                             continue;
                         }
                     }
+                    if let Some(divergence) = diverged(handle, remote, sigrefs.at)? {
+                        let refused = divergence.refused;
+                        divergences.push(divergence);
+                        if refused {
+                            self.prune(&remote);
+                            continue;
+                        }
+                    }
 
+                    let tip = sigrefs.at;
                     let cache = self.as_cached(handle);
                     if let Some(warns) = sigrefs::validate(&cache, sigrefs)?.as_mut() {
                         log::debug!(
@@ -514,9 +910,9 @@ impl FetchState {
                             "Pruning non-delegate {remote} tips, due to validation failures"
                         );
                         self.prune(&remote);
-                        failures.append(warns);
+                        failures.entry(remote).or_default().append(warns);
                     } else {
-                        remotes.insert(remote);
+                        remotes.insert(remote, tip);
                     }
                 }
                 sigrefs::DelegateStatus::Delegate {
@@ -538,7 +934,19 @@ impl FetchState {
                             });
                         }
                     }
+                    if let Some(divergence) = diverged(handle, remote, sigrefs.at)? {
+                        let refused = divergence.refused;
+                        divergences.push(divergence);
+                        if refused {
+                            log::warn!(target: "fetch", "Pruning delegate {remote} tips, due to diverged `rad/sigrefs`");
+                            self.prune(&remote);
+                            valid_delegates.remove(&remote);
+                            failed_delegates.insert(remote);
+                            continue;
+                        }
+                    }
 
+                    let tip = sigrefs.at;
                     let cache = self.as_cached(handle);
                     let mut fails = Validations::default();
                     // N.b. we only validate the existence of the
@@ -554,10 +962,10 @@ impl FetchState {
                         self.prune(&remote);
                         valid_delegates.remove(&remote);
                         failed_delegates.insert(remote);
-                        failures.append(&mut fails)
+                        failures.entry(remote).or_default().append(&mut fails)
                     } else {
                         valid_delegates.insert(remote);
-                        remotes.insert(remote);
+                        remotes.insert(remote, tip);
                     }
                 }
             }
@@ -572,35 +980,122 @@ impl FetchState {
         // N.b. only apply to Git repository if there are enough valid
         // delegates that pass the threshold.
         if valid_delegates.len() >= threshold {
-            let applied = repository::update(
+            let applied = repository::update_batched(
                 &handle.repo,
                 self.tips
                     .clone()
                     .into_values()
                     .flat_map(|ups| ups.into_iter()),
+                handle.update_batch_size,
             )?;
             log::debug!(target: "fetch", "Applied updates ({}ms)", start.elapsed().as_millis());
+
+            let mut results = BTreeMap::<PublicKey, RemoteResult>::new();
+            for (remote, tip) in remotes {
+                results.entry(remote).or_default().sigrefs = Some(tip);
+            }
+            for (remote, validations) in failures {
+                results.entry(remote).or_default().validations = validations;
+            }
+            for (remote, applied) in by_remote(applied) {
+                let sigrefs_diverged = divergences.iter().any(|d| d.remote == remote);
+                let result = results.entry(remote).or_default();
+
+                result.diverged = applied
+                    .rejected
+                    .iter()
+                    .filter_map(|r| ref_divergence(&handle.repo, r, sigrefs_diverged))
+                    .collect();
+                result.applied = applied;
+            }
             Ok(FetchResult::Success {
-                applied,
-                remotes,
-                validations: failures,
+                remotes: results,
+                divergences,
+                denied,
             })
         } else {
+            let validations =
+                failures
+                    .into_values()
+                    .fold(Validations::default(), |mut all, mut validations| {
+                        all.append(&mut validations);
+                        all
+                    });
             log::debug!(
                 target: "fetch",
                 "Fetch failed: {} failure(s) ({}ms)",
-                failures.len(),
+                validations.len(),
                 start.elapsed().as_millis()
             );
             Ok(FetchResult::Failed {
                 threshold,
                 delegates: failed_delegates,
-                validations: failures,
+                validations,
+                divergences,
             })
         }
     }
 }
 
+/// Split the changes applied to the reference store by the remote
+/// whose namespace they changed.
+fn by_remote(applied: Applied<'static>) -> BTreeMap<PublicKey, Applied<'static>> {
+    let mut remotes = BTreeMap::<PublicKey, Applied>::new();
+    let remote = |name: &str| match radicle::git::parse_ref_namespaced::<PublicKey>(name) {
+        Ok((remote, _)) => Some(remote),
+        Err(e) => {
+            log::error!(target: "fetch", "Failed to get the remote of {name}: {e}");
+            None
+        }
+    };
+    for up in applied.updated {
+        if let Some(remote) = remote(up.name().as_str()) {
+            remotes.entry(remote).or_default().updated.push(up);
+        }
+    }
+    for rejected in applied.rejected {
+        if let Some(remote) = remote(rejected.update.refname().as_str()) {
+            remotes.entry(remote).or_default().rejected.push(rejected);
+        }
+    }
+    for up in applied.failed {
+        if let Some(remote) = remote(up.refname().as_str()) {
+            remotes.entry(remote).or_default().failed.push(up);
+        }
+    }
+    remotes
+}
+
+/// Report the divergence of a rejected update, if it was rejected for
+/// not being a fast-forward of the local tip.
+fn ref_divergence<R: git::repository::Odb>(
+    repo: &R,
+    rejected: &Rejected<'static>,
+    sigrefs_diverged: bool,
+) -> Option<RefDivergence> {
+    let (
+        Update::Direct { name, target, .. },
+        Reason::Behind { current } | Reason::Diverged { current },
+    ) = (&rejected.update, &rejected.reason)
+    else {
+        return None;
+    };
+    let base = match repository::merge_base(repo, *current, *target) {
+        Ok(base) => base,
+        Err(e) => {
+            log::warn!(target: "fetch", "Failed to find the merge base of {name}: {e}");
+            None
+        }
+    };
+    Some(RefDivergence {
+        name: name.clone(),
+        local: *current,
+        received: *target,
+        base,
+        sigrefs_diverged,
+    })
+}
+
 /// A cached version of [`Handle`] by using the underlying
 /// [`FetchState`]'s data for performing lookups.
 pub(crate) struct Cached<'a, S> {
@@ -637,9 +1132,13 @@ impl<'a, S> Cached<'a, S> {
     pub fn canonical(&self) -> Result<Option<Doc<Verified>>, error::Canonical> {
         let tip = self.refname_to_id(refs::REFS_RAD_ID.clone())?;
         let cached_tip = self.canonical_rad_id();
+        let tip = if self.state.recovering {
+            cached_tip.or(tip)
+        } else {
+            tip.or(cached_tip)
+        };
 
-        tip.or(cached_tip)
-            .map(|tip| self.verified(tip).map_err(error::Canonical::from))
+        tip.map(|tip| self.verified(tip).map_err(error::Canonical::from))
             .transpose()
     }
 
@@ -711,18 +1210,81 @@ impl<'a, S> ValidateRepository for Cached<'a, S> {
         }
 
         // The refs that are left in the map, are ones that were signed, but are not
-        // in the repository. If any are left, bail.
+        // in the repository. If any are left, bail, unless they were denied, or the
+        // remote we fetched from doesn't have them.
         for (name, _) in signed.into_iter() {
+            let skipped = Qualified::from_refstr(&name).is_some_and(|q| {
+                let name = q.with_namespace(Component::from(&remote.id));
+                self.handle.denied.is_denied(&name) || self.state.withheld.contains(&name)
+            });
+            if skipped {
+                continue;
+            }
             validations.push(Validation::MissingRef {
                 refname: name,
                 remote: remote.id,
             });
         }
 
+        // N.b. namespace `HEAD`s are never fetched, so we check the
+        // one in storage against the fetched signed refs.
+        if let Some(head) = storage::git::validate_head(&self.handle.repo.backend, remote)? {
+            validations.push(head);
+        }
+
         Ok(validations)
     }
 }
 
+/// Check the `received` `rad/sigrefs` tip of `remote` against the tip
+/// we last fetched, if any.
+///
+/// N.b. the known tip may be missing from the repository, eg. if it
+/// was removed since. Since the fetched tip's history is complete, it
+/// can't descend from the known tip in that case.
+fn diverged<S>(
+    handle: &Handle<S>,
+    remote: PublicKey,
+    received: Oid,
+) -> Result<Option<Divergence>, repository::error::Ancestry> {
+    let Some(known) = handle.known_sigrefs.get(&remote).copied() else {
+        return Ok(None);
+    };
+    let deviation = match repository::ancestry(&handle.repo, known, received) {
+        Ok(repository::Ancestry::Equal | repository::Ancestry::Ahead) => return Ok(None),
+        Ok(repository::Ancestry::Behind) => Deviation::Rewound,
+        Ok(repository::Ancestry::Diverged) => Deviation::Diverged,
+        Err(repository::error::Ancestry::Missing { oid }) if oid == known => Deviation::Diverged,
+        Err(e) => return Err(e),
+    };
+    log::warn!(
+        target: "fetch",
+        "Remote {remote} `rad/sigrefs` {received} deviates from known tip {known}: {deviation:?}"
+    );
+
+    Ok(Some(Divergence {
+        remote,
+        known,
+        received,
+        deviation,
+        refused: handle.refuse_diverged,
+    }))
+}
+
+/// If the repository has a project payload, in `anchor`, then get
+/// the default branch of each delegate, which is fetched even if it
+/// is denied, so that the canonical head can be computed.
+fn delegate_heads(anchor: &Doc<Verified>) -> Vec<Namespaced<'static>> {
+    let Ok(proj) = anchor.project() else {
+        return vec![];
+    };
+    anchor
+        .delegates
+        .iter()
+        .map(|did| radicle::git::refs::storage::branch_of(did, proj.default_branch()))
+        .collect()
+}
+
 /// If the repository has a project payload, in `anchor`, then
 /// validate that the `sigrefs` contains the listed default branch.
 ///
@@ -739,3 +1301,284 @@ fn validate_project_default_branch(
         refname: branch,
     })
 }
+
+#[cfg(test)]
+mod test {
+    use either::Either;
+    use radicle::git::{qualified, Component};
+    use radicle::test::arbitrary;
+
+    use super::*;
+    use crate::git::refs::{Policy, RefUpdate};
+    use crate::git::repository::mem;
+    use crate::policy::{self, BlockList};
+    use crate::test;
+
+    #[test]
+    fn test_summary_by_remote() {
+        let alice = arbitrary::gen::<PublicKey>(1);
+        let bob = arbitrary::gen::<PublicKey>(1);
+        let oid = arbitrary::oid();
+        let name = |remote: &PublicKey| {
+            qualified!("refs/heads/master").with_namespace(Component::from(remote))
+        };
+        let applied = Applied {
+            updated: [alice, bob]
+                .iter()
+                .map(|remote| RefUpdate::Created {
+                    name: name(remote).to_ref_string(),
+                    oid,
+                })
+                .collect(),
+            rejected: vec![Rejected::new(
+                Update::Prune {
+                    name: name(&bob),
+                    prev: Either::Left(oid),
+                },
+                Reason::Missing,
+            )],
+            failed: vec![],
+        };
+
+        let mut applied = by_remote(applied);
+        assert_eq!(applied.len(), 2);
+        assert_eq!(applied[&alice].updated.len(), 1);
+        assert!(applied[&alice].rejected.is_empty());
+        assert_eq!(applied[&bob].updated.len(), 1);
+        assert_eq!(applied[&bob].rejected.len(), 1);
+
+        let summary = [
+            (
+                alice,
+                RemoteResult {
+                    sigrefs: Some(oid),
+                    applied: applied.remove(&alice).unwrap_or_default(),
+                    validations: Validations::default(),
+                    diverged: vec![],
+                },
+            ),
+            (
+                bob,
+                RemoteResult {
+                    sigrefs: None,
+                    applied: applied.remove(&bob).unwrap_or_default(),
+                    validations: Validations(vec![sigrefs::Validation::MissingRadSigRefs(bob)]),
+                    diverged: vec![],
+                },
+            ),
+        ]
+        .into_iter()
+        .collect::<Summary>();
+
+        assert_eq!(summary.remotes, BTreeSet::from([alice]));
+        assert_eq!(summary.applied.updated.len(), 2);
+        assert_eq!(summary.applied.rejected.len(), 1);
+        assert_eq!(summary.validations.len(), 1);
+    }
+
+    #[test]
+    fn test_ls_refs_cached() {
+        let (_, commits) = test::arbitrary::repository();
+        let remotes = test::arbitrary::remotes();
+        let advertised = test::arbitrary::names()
+            .into_iter()
+            .map(|name| handshake::Ref::Direct {
+                full_ref_name: name.as_str().into(),
+                object: git::oid::to_object_id(commits[0]),
+            })
+            .collect::<Vec<_>>();
+        let special_refs = stage::SpecialRefs {
+            blocked: BlockList::from_iter([]),
+            remote: remotes[0],
+            followed: policy::Allowed::All,
+            delegates: BTreeSet::from([remotes[0]]),
+            threshold: 1,
+            limit: 0,
+        };
+        let sigrefs_at = stage::SigrefsAt {
+            blocked: BlockList::from_iter([]),
+            remote: remotes[0],
+            refs_at: remotes
+                .iter()
+                .map(|remote| RefsAt {
+                    remote: *remote,
+                    at: commits[0],
+                })
+                .collect(),
+            delegates: BTreeSet::from([remotes[0]]),
+            limit: 0,
+        };
+        let mut state = FetchState::default();
+        let mut calls = Vec::new();
+        let mut ls_refs = |stage: &dyn ProtocolStage, state: &mut FetchState| {
+            state
+                .ls_refs_cached(stage.ls_refs().unwrap(), |prefixes| {
+                    calls.push(prefixes.clone());
+                    Ok::<_, std::io::Error>(
+                        advertised
+                            .iter()
+                            .filter(|r| prefixes.iter().any(|p| r.unpack().0.starts_with(p)))
+                            .cloned()
+                            .collect(),
+                    )
+                })
+                .unwrap()
+                .len()
+        };
+
+        // The references of all namespaces are listed.
+        assert_eq!(ls_refs(&special_refs, &mut state), advertised.len());
+        // The `rad/sigrefs` of each remote are served from the
+        // advertisement of all namespaces.
+        assert_eq!(ls_refs(&sigrefs_at, &mut state), remotes.len());
+        // Once invalidated, the remote is asked again.
+        state.invalidate_advertised();
+        assert_eq!(ls_refs(&sigrefs_at, &mut state), remotes.len());
+        // Until then, listed prefixes are cached too.
+        assert_eq!(ls_refs(&sigrefs_at, &mut state), remotes.len());
+
+        assert_eq!(
+            calls,
+            vec![
+                vec![BString::from("refs/namespaces")],
+                sigrefs_at.ls_refs().unwrap().into(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ref_divergence() {
+        let mut repo = mem::Repository::default();
+        let base = repo.commit(&[]);
+        let ours = repo.commit(&[base]);
+        let theirs = repo.commit(&[base]);
+        let remote = arbitrary::gen::<PublicKey>(1);
+        let name = qualified!("refs/heads/master").with_namespace(Component::from(&remote));
+        let rejected = |target, reason| {
+            Rejected::new(
+                Update::Direct {
+                    name: name.clone(),
+                    target,
+                    no_ff: Policy::Reject,
+                },
+                reason,
+            )
+        };
+
+        assert_eq!(
+            ref_divergence(
+                &repo,
+                &rejected(theirs, Reason::Diverged { current: ours }),
+                true
+            ),
+            Some(RefDivergence {
+                name: name.clone(),
+                local: ours,
+                received: theirs,
+                base: Some(base),
+                sigrefs_diverged: true,
+            })
+        );
+        assert_eq!(
+            ref_divergence(
+                &repo,
+                &rejected(base, Reason::Behind { current: ours }),
+                false
+            ),
+            Some(RefDivergence {
+                name: name.clone(),
+                local: ours,
+                received: base,
+                base: Some(base),
+                sigrefs_diverged: false,
+            })
+        );
+        assert_eq!(
+            ref_divergence(&repo, &rejected(theirs, Reason::Superseded), false),
+            None
+        );
+    }
+
+    #[test]
+    fn prop_by_remote() {
+        let mut g = qcheck::Gen::new(16);
+
+        for _ in 0..64 {
+            let applied = test::arbitrary::applied(&mut g);
+            let counts = (
+                applied.updated.len(),
+                applied.rejected.len(),
+                applied.failed.len(),
+            );
+            let remotes = by_remote(applied);
+            let namespace = |name: &str| {
+                radicle::git::parse_ref_namespaced::<PublicKey>(name)
+                    .unwrap()
+                    .0
+            };
+
+            for (remote, applied) in &remotes {
+                assert!(applied
+                    .updated
+                    .iter()
+                    .all(|up| namespace(up.name().as_str()) == *remote));
+                assert!(applied
+                    .rejected
+                    .iter()
+                    .all(|r| namespace(r.update.refname().as_str()) == *remote));
+                assert!(applied
+                    .failed
+                    .iter()
+                    .all(|up| namespace(up.refname().as_str()) == *remote));
+            }
+            assert_eq!(
+                remotes.values().fold((0, 0, 0), |(u, r, f), ap| (
+                    u + ap.updated.len(),
+                    r + ap.rejected.len(),
+                    f + ap.failed.len()
+                )),
+                counts
+            );
+        }
+    }
+
+    #[test]
+    fn prop_update_all() {
+        let mut g = qcheck::Gen::new(16);
+
+        for _ in 0..64 {
+            let tips = test::arbitrary::tips(&mut g);
+            let mut state = FetchState::default();
+            state.update_all(tips.clone());
+
+            for (remote, ups) in &tips {
+                assert_eq!(state.tips[remote].len(), ups.len());
+
+                // The in-memory refdb holds the target of the last direct update
+                // of each reference, unless a later symbolic update points to it.
+                for (i, up) in ups.iter().enumerate() {
+                    let later = &ups[i + 1..];
+                    let name = up.refname();
+                    let pointed = later.iter().any(
+                        |up| matches!(up, Update::Symbolic { target, .. } if target.name == *name),
+                    );
+                    if pointed || later.iter().any(|up| up.refname() == name) {
+                        continue;
+                    }
+                    let expected = match up {
+                        Update::Direct { target, .. } => Some(*target),
+                        Update::Prune { .. } => None,
+                        Update::Symbolic { .. } => continue,
+                    };
+                    assert_eq!(state.refs.refname_to_id(name.clone()), expected);
+                }
+            }
+            if let Some((remote, _)) = tips.first() {
+                state.prune(remote);
+
+                assert!(!state.tips.contains_key(remote));
+                assert_eq!(state.tips.len(), tips.len() - 1);
+            }
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! Generators for the fetch layer types.
+//!
+//! Generated values are drawn from small, fixed sets of remotes,
+//! reference names and commits, so that they often refer to the same
+//! references, and so that their targets exist in [`repository`].
+use either::Either;
+use qcheck::{Arbitrary, Gen};
+use radicle::crypto::test::signer::MockSigner;
+use radicle::crypto::{PublicKey, Signer as _};
+use radicle::git::{qualified, Component, Namespaced, Oid, Qualified};
+use radicle::storage::refs::{Refs, SignedRefsAt};
+
+use crate::git::refs::{Applied, Policy, Reason, RefUpdate, Rejected, SymrefTarget, Update};
+use crate::git::repository::mem;
+use crate::sigrefs::RemoteRefs;
+
+/// Number of remotes references are generated for.
+pub const REMOTES: u8 = 3;
+
+/// The signers of the remotes references are generated for.
+pub fn signers() -> Vec<MockSigner> {
+    (1..=REMOTES)
+        .map(|i| MockSigner::from_seed([i; 32]))
+        .collect()
+}
+
+/// The remotes references are generated for.
+pub fn remotes() -> Vec<PublicKey> {
+    signers().iter().map(|s| *s.public_key()).collect()
+}
+
+/// The reference names generated, without namespace.
+pub fn refnames() -> Vec<Qualified<'static>> {
+    vec![
+        qualified!("refs/heads/master"),
+        qualified!("refs/heads/dev"),
+        qualified!("refs/tags/v1.0"),
+        qualified!("refs/notes/commits"),
+        qualified!("refs/cobs/xyz.radicle.issue/1"),
+        qualified!("refs/rad/id"),
+        qualified!("refs/rad/sigrefs"),
+    ]
+}
+
+/// Every namespaced reference name that can be generated.
+pub fn names() -> Vec<Namespaced<'static>> {
+    remotes()
+        .into_iter()
+        .flat_map(|remote| {
+            refnames()
+                .into_iter()
+                .map(move |name| name.with_namespace(Component::from(&remote)))
+        })
+        .collect()
+}
+
+/// A repository holding the commits targeted by generated updates: a
+/// linear history, and a branch diverging from it. Returns the
+/// repository and its commits.
+pub fn repository() -> (mem::Repository, Vec<Oid>) {
+    let mut repo = mem::Repository::default();
+    let mut commits = vec![repo.commit(&[])];
+
+    for _ in 0..4 {
+        let parent = commits[commits.len() - 1];
+        commits.push(repo.commit(&[parent]));
+    }
+    let diverged = repo.commit(&[commits[1]]);
+    commits.push(diverged);
+
+    (repo, commits)
+}
+
+/// A commit of [`repository`].
+pub fn oid(g: &mut Gen) -> Oid {
+    let (_, commits) = repository();
+    *g.choose(&commits).unwrap()
+}
+
+/// A reference name, without namespace.
+pub fn refname(g: &mut Gen) -> Qualified<'static> {
+    g.choose(&refnames()).unwrap().clone()
+}
+
+/// A reference name, in the namespace of one of the [`remotes`].
+pub fn namespaced(g: &mut Gen) -> Namespaced<'static> {
+    g.choose(&names()).unwrap().clone()
+}
+
+/// An update of a reference in the namespace of `remote`. Symbolic
+/// references point to references of the same namespace.
+pub fn update(g: &mut Gen, remote: &PublicKey) -> Update<'static> {
+    let name = refname(g).with_namespace(Component::from(remote));
+    let target = oid(g);
+
+    match g.choose(&[0, 1, 2]).unwrap() {
+        0 => Update::Prune {
+            name,
+            prev: Either::Left(target),
+        },
+        1 => {
+            let symref = refname(g).with_namespace(Component::from(remote));
+            if symref == name {
+                return Update::Prune {
+                    name,
+                    prev: Either::Left(target),
+                };
+            }
+            Update::Symbolic {
+                name,
+                target: SymrefTarget {
+                    name: symref,
+                    target,
+                },
+                type_change: Policy::arbitrary(g),
+            }
+        }
+        _ => Update::Direct {
+            name,
+            target,
+            no_ff: Policy::arbitrary(g),
+        },
+    }
+}
+
+/// A reference update.
+pub fn ref_update(g: &mut Gen) -> RefUpdate {
+    let name = namespaced(g).to_ref_string();
+    let oid = self::oid(g);
+
+    match g.choose(&[0, 1, 2, 3]).unwrap() {
+        0 => RefUpdate::Created { name, oid },
+        1 => RefUpdate::Updated {
+            name,
+            old: self::oid(g),
+            new: oid,
+        },
+        2 => RefUpdate::Deleted { name, oid },
+        _ => RefUpdate::Skipped { name, oid },
+    }
+}
+
+/// A reason for rejecting an update.
+pub fn reason(g: &mut Gen) -> Reason {
+    let current = oid(g);
+
+    match g.choose(&[0, 1, 2, 3, 4]).unwrap() {
+        0 => Reason::Behind { current },
+        1 => Reason::Diverged { current },
+        2 => Reason::TypeChange,
+        3 => Reason::Missing,
+        _ => Reason::Superseded,
+    }
+}
+
+/// The outcome of applying updates. N.b. [`Applied`] isn't `Clone`,
+/// and so can't be [`Arbitrary`].
+pub fn applied(g: &mut Gen) -> Applied<'static> {
+    let size = g.size();
+
+    Applied {
+        rejected: (0..usize::arbitrary(g) % (size + 1))
+            .map(|_| Rejected::new(Update::arbitrary(g), reason(g)))
+            .collect(),
+        updated: (0..usize::arbitrary(g) % (size + 1))
+            .map(|_| ref_update(g))
+            .collect(),
+        failed: (0..usize::arbitrary(g) % (size + 1))
+            .map(|_| Update::arbitrary(g))
+            .collect(),
+    }
+}
+
+/// Updates of the in-memory refdb of a fetch, grouped by remote, as
+/// given to [`crate::state::FetchState::update_all`].
+pub fn tips(g: &mut Gen) -> Vec<(PublicKey, Vec<Update<'static>>)> {
+    let remotes = remotes()
+        .into_iter()
+        .filter(|_| bool::arbitrary(g))
+        .collect::<Vec<_>>();
+
+    remotes
+        .into_iter()
+        .map(|remote| {
+            let ups = (0..usize::arbitrary(g) % (g.size() + 1))
+                .map(|_| update(g, &remote))
+                .collect();
+            (remote, ups)
+        })
+        .collect()
+}
+
+/// The sigrefs of some of the [`remotes`].
+pub fn remote_refs(g: &mut Gen) -> RemoteRefs {
+    let signers = signers()
+        .into_iter()
+        .filter(|_| bool::arbitrary(g))
+        .collect::<Vec<_>>();
+
+    signers
+        .into_iter()
+        .map(|signer| {
+            let sigrefs = Refs::arbitrary(g)
+                .signed(&signer)
+                .expect("remote_refs: signing does not fail");
+            let at = oid(g);
+
+            (*signer.public_key(), SignedRefsAt { sigrefs, at })
+        })
+        .collect()
+}
+
+impl Arbitrary for Policy {
+    fn arbitrary(g: &mut Gen) -> Self {
+        *g.choose(&[Policy::Abort, Policy::Reject, Policy::Allow])
+            .unwrap()
+    }
+}
+
+impl Arbitrary for Update<'static> {
+    fn arbitrary(g: &mut Gen) -> Self {
+        let remote = *g.choose(&remotes()).unwrap();
+        update(g, &remote)
+    }
+}
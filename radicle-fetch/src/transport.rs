@@ -1,5 +1,6 @@
 pub(crate) mod fetch;
 pub(crate) mod ls_refs;
+pub(crate) mod negotiate;
 
 use std::collections::BTreeSet;
 use std::io;
@@ -17,7 +18,6 @@ use gix_transport::Protocol;
 use gix_transport::Service;
 use radicle::git::Oid;
 use radicle::git::Qualified;
-use radicle::storage::git::Repository;
 use thiserror::Error;
 
 use crate::git::oid;
@@ -58,6 +58,7 @@ pub struct Transport<S> {
     git_dir: PathBuf,
     repo: BString,
     stream: S,
+    thread_limit: Option<usize>,
 }
 
 impl<S> Transport<S>
@@ -76,9 +77,16 @@ where
             git_dir,
             repo,
             stream,
+            thread_limit: None,
         }
     }
 
+    /// Limit the number of threads used to index fetched packfiles.
+    pub fn with_thread_limit(mut self, limit: Option<usize>) -> Self {
+        self.thread_limit = limit;
+        self
+    }
+
     /// Perform the handshake with the server side.
     pub(crate) fn handshake(&mut self) -> io::Result<handshake::Outcome> {
         log::trace!(target: "fetch", "Performing handshake for {}", self.repo);
@@ -115,12 +123,16 @@ where
     }
 
     /// Perform the fetch with the server side.
+    ///
+    /// Returns the tips of the [`WantsHaves::want_refs`], as resolved
+    /// by the server.
     pub(crate) fn fetch(
         &mut self,
         wants_haves: WantsHaves,
+        limit: u64,
         interrupt: Arc<AtomicBool>,
         handshake: &handshake::Outcome,
-    ) -> io::Result<()> {
+    ) -> io::Result<Vec<handshake::Ref>> {
         log::trace!(
             target: "fetch",
             "Running fetch wants={:?}, haves={:?}",
@@ -134,12 +146,19 @@ where
                 fetch::PackWriter {
                     git_dir: self.git_dir.clone(),
                     interrupt,
+                    thread_limit: self.thread_limit,
+                    limit,
                 },
                 handshake,
                 Connection::new(read, write, FetchConnection::AllowReuse, self.repo.clone()),
                 &mut progress::Discard,
             )
-            .map_err(io_other)?
+            .map_err(|err| match err {
+                // N.b. don't wrap I/O errors, so that running out of
+                // space can be told apart.
+                fetch::Error::Io(err) => err,
+                err => io_other(err),
+            })?
         };
         let pack_path = out
             .pack
@@ -170,7 +189,17 @@ where
             }
         }
 
-        Ok(())
+        Ok(out.refs)
+    }
+
+    /// Whether the server supports asking for references by name when
+    /// fetching, ie. `want-ref`, which saves an ls-refs round-trip.
+    pub(crate) fn supports_want_ref(handshake: &handshake::Outcome) -> bool {
+        handshake
+            .capabilities
+            .capability("fetch")
+            .and_then(|c| c.supports("ref-in-want"))
+            .unwrap_or(false)
     }
 
     /// Signal to the server side that we are done sending ls-refs and
@@ -284,7 +313,13 @@ pub enum WantsHavesError {
 #[derive(Clone, Default)]
 pub(crate) struct WantsHaves {
     pub wants: BTreeSet<Oid>,
+    /// References we want, by name, which the server resolves to
+    /// their tips. See [`WantsHaves::want_refs`].
+    pub want_refs: BTreeSet<Qualified<'static>>,
     pub haves: BTreeSet<Oid>,
+    /// Our commit graph, walked from the `haves`, used to negotiate
+    /// with the server. See [`WantsHaves::walk`].
+    pub graph: negotiate::Graph,
 }
 
 impl WantsHaves {
@@ -301,6 +336,38 @@ impl WantsHaves {
         self.haves.insert(oid);
     }
 
+    /// Add a set of references to the `want_refs`, marking their
+    /// current `Oid`s, if any, as `haves`.
+    ///
+    /// N.b. the server fails the fetch if any of these references do
+    /// not exist on its side.
+    pub fn want_refs<'a, R, N>(
+        &mut self,
+        repo: &R,
+        refs: impl IntoIterator<Item = N>,
+    ) -> Result<&mut Self, WantsHavesError>
+    where
+        R: repository::Refdb,
+        N: Into<Qualified<'a>>,
+    {
+        for refname in refs {
+            let refname = refname.into();
+            if let Some(oid) = repository::refname_to_id(repo, refname.clone())? {
+                self.have(oid);
+            }
+            self.want_refs.insert(refname.to_owned());
+        }
+        Ok(self)
+    }
+
+    /// Walk our commit graph from the `haves`, so that the server can
+    /// be told about their ancestors during negotiation, in case the
+    /// `haves` themselves are not known to it.
+    pub fn walk<D: repository::Odb>(&mut self, repo: &D) -> Result<(), WantsHavesError> {
+        self.graph = negotiate::Graph::walk(repo, &self.haves, negotiate::MAX_WALK)?;
+        Ok(())
+    }
+
     /// Add a set of references to the `wants` and `haves`.
     ///
     /// For each reference we want to build the range between its
@@ -314,12 +381,13 @@ impl WantsHaves {
     ///
     /// If the reference does not exist, the range is simply marking
     /// the tip as a `want`, iff it does not already exist in the Odb.
-    pub fn add<'a, N>(
+    pub fn add<'a, R, N>(
         &mut self,
-        repo: &Repository,
+        repo: &R,
         refs: impl IntoIterator<Item = (N, Oid)>,
     ) -> Result<&mut Self, WantsHavesError>
     where
+        R: repository::Refdb + repository::Odb,
         N: Into<Qualified<'a>>,
     {
         refs.into_iter().try_fold(self, |acc, (refname, tip)| {
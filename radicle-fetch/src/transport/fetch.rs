@@ -1,7 +1,7 @@
 use std::{
     borrow::Cow,
-    io::{self, BufRead},
-    path::PathBuf,
+    io::{self, BufRead, Read},
+    path::{Path, PathBuf},
     sync::{atomic::AtomicBool, Arc},
 };
 
@@ -19,6 +19,7 @@ use gix_transport::{
     Protocol,
 };
 
+use super::negotiate::Negotiator;
 use super::{agent_name, indicate_end_of_interaction, Connection, WantsHaves};
 
 pub type Error = gix_protocol::fetch::Error;
@@ -34,9 +35,32 @@ pub mod error {
         Io(#[from] io::Error),
         #[error(transparent)]
         Write(#[from] gix_pack::bundle::write::Error),
+        #[error(transparent)]
+        InsufficientSpace(#[from] InsufficientSpace),
+    }
+
+    /// The storage filesystem does not have enough space left to
+    /// write the packfile.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+    #[error("insufficient disk space: {available} byte(s) available, {required} byte(s) required")]
+    pub struct InsufficientSpace {
+        /// Space available in the storage filesystem, in bytes.
+        pub available: u64,
+        /// Space needed to continue writing the packfile, in bytes.
+        pub required: u64,
     }
 }
 
+/// Maximum space required to be available before starting to write
+/// a packfile. Fetches with a lower limit only require their limit.
+pub const MAX_SPACE_RESERVE: u64 = 64 * 1024 * 1024;
+
+/// Number of pack bytes read between two checks of the available
+/// space. At each check, at least twice this amount must still be
+/// available, to leave room for the next chunk of the pack and its
+/// index.
+pub const SPACE_CHECK_INTERVAL: u64 = 4 * 1024 * 1024;
+
 /// Configuration for writing a packfile.
 pub struct PackWriter {
     /// The repository path for writing the packfile to. Note this is
@@ -45,14 +69,24 @@ pub struct PackWriter {
     /// `interrupt` is checked regularly and when true, the whole
     /// operation will stop.
     pub interrupt: Arc<AtomicBool>,
+    /// Maximum number of threads used to index the packfile. If
+    /// `None`, all cores are used.
+    pub thread_limit: Option<usize>,
+    /// The data limit of the fetch, in bytes.
+    pub limit: u64,
 }
 
 impl PackWriter {
     /// Write the packfile read from `pack` to the `objects/pack`
     /// directory.
+    ///
+    /// The available disk space is checked before and while the
+    /// packfile is written, failing with
+    /// [`error::PackWriter::InsufficientSpace`] if it runs out. In
+    /// that case, the partially written packfile is removed.
     pub fn write_pack<P>(
         &self,
-        mut pack: impl BufRead,
+        pack: impl BufRead,
         mut progress: P,
     ) -> Result<pack::bundle::write::Outcome, error::PackWriter>
     where
@@ -61,10 +95,11 @@ impl PackWriter {
     {
         use gix_odb::FindExt as _;
 
+        let objects = self.git_dir.join("objects");
+        check_space(&objects, self.limit.min(MAX_SPACE_RESERVE))?;
+
         let options = pack::bundle::write::Options {
-            // N.b. use all cores. Can make configurable if needed
-            // later.
-            thread_limit: None,
+            thread_limit: self.thread_limit,
             iteration_mode: pack::data::input::Mode::Verify,
             index_version: pack::index::Version::V2,
             object_hash: gix_hash::Kind::Sha1,
@@ -76,19 +111,100 @@ impl PackWriter {
             current_dir: Some(self.git_dir.clone()),
         };
         let thickener = Arc::new(gix_odb::Store::at_opts(
-            self.git_dir.join("objects"),
+            objects.clone(),
             &mut [].into_iter(),
             odb_opts,
         )?);
         let thickener = thickener.to_handle_arc();
-        Ok(pack::Bundle::write_to_directory(
+        let mut pack = SpaceChecked::new(pack, objects.clone());
+        let result = pack::Bundle::write_to_directory(
             &mut pack,
-            Some(&self.git_dir.join("objects").join("pack")),
+            Some(&objects.join("pack")),
             &mut progress,
             &self.interrupt,
             Some(Box::new(move |oid, buf| thickener.find(&oid, buf).ok())),
             options,
-        )?)
+        );
+        // N.b. the pack is written to temporary files which are
+        // removed on failure, so there is nothing to clean up.
+        match (result, pack.exhausted) {
+            (Err(_), Some(err)) => Err(err.into()),
+            (result, _) => Ok(result?),
+        }
+    }
+}
+
+/// Fail with [`error::InsufficientSpace`] if less than `required`
+/// bytes are available in the filesystem of `path`.
+fn check_space(path: &Path, required: u64) -> Result<(), error::InsufficientSpace> {
+    match radicle::io::available_space(path) {
+        Ok(available) if available < required => Err(error::InsufficientSpace {
+            available,
+            required,
+        }),
+        Ok(_) => Ok(()),
+        Err(e) => {
+            // N.b. if we can't tell, let the write fail on its own.
+            log::warn!(target: "fetch", "Failed to get available space of {path:?}: {e}");
+            Ok(())
+        }
+    }
+}
+
+/// Pack reader which checks the available space in the filesystem
+/// of `path` every [`SPACE_CHECK_INTERVAL`] bytes.
+struct SpaceChecked<R> {
+    inner: R,
+    path: PathBuf,
+    /// Bytes read since the last check.
+    unchecked: u64,
+    /// Set when the available space ran out.
+    exhausted: Option<error::InsufficientSpace>,
+}
+
+impl<R> SpaceChecked<R> {
+    fn new(inner: R, path: PathBuf) -> Self {
+        Self {
+            inner,
+            path,
+            unchecked: 0,
+            exhausted: None,
+        }
+    }
+
+    fn check(&mut self) -> io::Result<()> {
+        if self.unchecked < SPACE_CHECK_INTERVAL {
+            return Ok(());
+        }
+        self.unchecked = 0;
+
+        if let Err(err) = check_space(&self.path, SPACE_CHECK_INTERVAL * 2) {
+            self.exhausted = Some(err);
+            return Err(io::Error::new(io::ErrorKind::Other, err));
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for SpaceChecked<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.check()?;
+        let n = self.inner.read(buf)?;
+        self.unchecked += n as u64;
+
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for SpaceChecked<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.check()?;
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.unchecked += amt as u64;
+        self.inner.consume(amt)
     }
 }
 
@@ -96,6 +212,7 @@ impl PackWriter {
 /// server-side.
 pub struct Fetch {
     wants_haves: WantsHaves,
+    negotiator: Negotiator,
     pack_writer: PackWriter,
     out: FetchOut,
 }
@@ -128,7 +245,14 @@ impl<'a> Delegate for &'a mut Fetch {
         let pack = self
             .pack_writer
             .write_pack(input, progress)
-            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+            .map_err(|err| match err {
+                // N.b. wrap the inner error, so that it can be
+                // downcast by the caller.
+                error::PackWriter::InsufficientSpace(err) => {
+                    io::Error::new(io::ErrorKind::Other, err)
+                }
+                err => io::Error::new(io::ErrorKind::Other, err),
+            })?;
         self.out.pack = Some(pack);
         Ok(())
     }
@@ -139,20 +263,47 @@ impl<'a> DelegateBlocking for &'a mut Fetch {
         &mut self,
         _refs: &[handshake::Ref],
         arguments: &mut fetch::Arguments,
-        _previous_response: Option<&fetch::Response>,
+        previous_response: Option<&fetch::Response>,
     ) -> io::Result<fetch::Action> {
         use crate::git::oid;
+        use gix_protocol::fetch::response::Acknowledgement;
 
-        for oid in &self.wants_haves.wants {
-            arguments.want(oid::to_object_id(*oid));
+        match previous_response {
+            // N.b. the `want`s are kept by `arguments` across rounds.
+            None => {
+                for oid in &self.wants_haves.wants {
+                    arguments.want(oid::to_object_id(*oid));
+                }
+                if !self.wants_haves.want_refs.is_empty() && !arguments.can_use_ref_in_want() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "server does not support `want-ref`",
+                    ));
+                }
+                for refname in &self.wants_haves.want_refs {
+                    arguments.want_ref(refname.as_str().into());
+                }
+            }
+            Some(response) => {
+                for ack in response.acknowledgements() {
+                    if let Acknowledgement::Common(oid) = ack {
+                        self.negotiator.ack(oid::to_oid(*oid));
+                    }
+                }
+            }
         }
 
-        for oid in &self.wants_haves.haves {
-            arguments.have(oid::to_object_id(*oid));
+        let (haves, done) = self.negotiator.next_round();
+        for oid in haves {
+            arguments.have(oid::to_object_id(oid));
         }
 
-        // N.b. sends `done` packet
-        Ok(fetch::Action::Cancel)
+        if done {
+            // N.b. sends `done` packet
+            Ok(fetch::Action::Cancel)
+        } else {
+            Ok(fetch::Action::Continue)
+        }
     }
 
     fn prepare_ls_refs(
@@ -172,7 +323,7 @@ impl<'a> DelegateBlocking for &'a mut Fetch {
         _features: &mut Vec<(&str, Option<Cow<'_, str>>)>,
         _refs: &[handshake::Ref],
     ) -> io::Result<fetch::Action> {
-        if self.wants_haves.wants.is_empty() {
+        if self.wants_haves.wants.is_empty() && self.wants_haves.want_refs.is_empty() {
             return Err(io::Error::new(io::ErrorKind::InvalidData, "empty fetch"));
         }
         Ok(fetch::Action::Continue)
@@ -201,6 +352,7 @@ where
     log::trace!(target: "fetch", "Performing fetch");
 
     let mut delegate = Fetch {
+        negotiator: Negotiator::new(wants_haves.graph.clone()),
         wants_haves,
         pack_writer,
         out: FetchOut {
@@ -308,3 +460,31 @@ fn setup_remote_progress<P>(
         }
     }) as gix_transport::client::HandleProgress));
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_space() {
+        let tmp = std::env::temp_dir();
+
+        assert_eq!(check_space(&tmp, 0), Ok(()));
+        assert_eq!(
+            check_space(&tmp, u64::MAX).map_err(|e| e.required),
+            Err(u64::MAX)
+        );
+    }
+
+    #[test]
+    fn test_space_checked_read() {
+        let data = vec![7u8; SPACE_CHECK_INTERVAL as usize * 3 + 1];
+        let mut reader = SpaceChecked::new(io::Cursor::new(&data), std::env::temp_dir());
+        let mut buf = Vec::new();
+
+        reader.read_to_end(&mut buf).unwrap();
+
+        assert_eq!(buf, data);
+        assert_eq!(reader.exhausted, None);
+    }
+}
@@ -0,0 +1,251 @@
+//! Multi-round negotiation of the `have`s sent to the server.
+//!
+//! Instead of sending the tips of our references and giving up, we
+//! walk our commit graph from those tips, sending `have`s in rounds
+//! of increasing size. The server acknowledges the commits we have in
+//! common, which lets us skip their ancestors, and lets the server
+//! compute a minimal pack.
+
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+
+use radicle::git::Oid;
+
+use crate::git::repository::{self, Odb};
+
+/// Number of `have`s sent in the first round.
+pub const INITIAL_ROUND_SIZE: usize = 16;
+/// Maximum number of `have`s sent in a single round.
+pub const MAX_ROUND_SIZE: usize = 256;
+/// Number of `have`s sent without any of them being acknowledged
+/// before we give up negotiating.
+pub const MAX_IN_VAIN: usize = 256;
+/// Maximum number of commits walked from our `have`s.
+pub const MAX_WALK: usize = 1024;
+
+/// Our commit graph, walked from the tips of our `have`s.
+#[derive(Clone, Debug, Default)]
+pub struct Graph {
+    tips: BTreeSet<Oid>,
+    parents: HashMap<Oid, Vec<Oid>>,
+}
+
+impl Graph {
+    /// Walk the commit graph of `repo`, breadth-first, starting at
+    /// `tips`, visiting at most `limit` commits.
+    ///
+    /// N.b. missing parents are treated as the end of the history.
+    pub fn walk<D: Odb>(
+        repo: &D,
+        tips: &BTreeSet<Oid>,
+        limit: usize,
+    ) -> Result<Self, repository::error::Ancestry> {
+        let mut parents = HashMap::new();
+        let mut queue = tips.iter().copied().collect::<VecDeque<_>>();
+
+        while let Some(oid) = queue.pop_front() {
+            if parents.len() >= limit {
+                break;
+            }
+            if parents.contains_key(&oid) {
+                continue;
+            }
+            let ps = match repo.parents(oid) {
+                Ok(ps) => ps,
+                Err(repository::error::Ancestry::Missing { .. }) => vec![],
+                Err(e) => return Err(e),
+            };
+            queue.extend(ps.iter().copied());
+            parents.insert(oid, ps);
+        }
+
+        Ok(Self {
+            tips: tips.clone(),
+            parents,
+        })
+    }
+
+    fn parents(&self, oid: &Oid) -> &[Oid] {
+        self.parents.get(oid).map(|ps| ps.as_slice()).unwrap_or(&[])
+    }
+}
+
+/// The state of the negotiation with the server.
+#[derive(Debug)]
+pub struct Negotiator {
+    graph: Graph,
+    /// Commits left to send, in the order they are sent.
+    queue: VecDeque<Oid>,
+    /// Commits that were queued.
+    queued: HashSet<Oid>,
+    /// Commits acknowledged by the server.
+    common: BTreeSet<Oid>,
+    /// Ancestors of the acknowledged commits, which the server must
+    /// also have.
+    implied: HashSet<Oid>,
+    /// Number of `have`s to send in the next round.
+    round_size: usize,
+    /// Number of `have`s sent since the last acknowledgement, not
+    /// counting the tips.
+    in_vain: usize,
+}
+
+impl Negotiator {
+    pub fn new(graph: Graph) -> Self {
+        let queue = graph.tips.iter().copied().collect::<VecDeque<_>>();
+        let queued = queue.iter().copied().collect();
+        // N.b. all the tips are sent in the first round, as they are
+        // the most likely to be known to the server.
+        let round_size = INITIAL_ROUND_SIZE.max(queue.len());
+
+        Self {
+            graph,
+            queue,
+            queued,
+            common: BTreeSet::new(),
+            implied: HashSet::new(),
+            round_size,
+            in_vain: 0,
+        }
+    }
+
+    /// Record that the server has the commit `oid`, and therefore
+    /// all its ancestors.
+    pub fn ack(&mut self, oid: Oid) {
+        if !self.common.insert(oid) {
+            return;
+        }
+        self.in_vain = 0;
+
+        let mut stack = self.graph.parents(&oid).to_vec();
+        while let Some(oid) = stack.pop() {
+            if self.implied.insert(oid) {
+                stack.extend_from_slice(self.graph.parents(&oid));
+            }
+        }
+    }
+
+    /// Get the `have`s to send in the next round, and whether this is
+    /// the last round.
+    ///
+    /// N.b. since the server doesn't keep state between rounds, the
+    /// commits it acknowledged are sent again in every round.
+    pub fn next_round(&mut self) -> (Vec<Oid>, bool) {
+        let mut haves = self.common.iter().copied().collect::<Vec<_>>();
+        let mut sent = 0;
+        let mut in_vain = 0;
+
+        while sent < self.round_size {
+            let Some(oid) = self.queue.pop_front() else {
+                break;
+            };
+            if self.implied.contains(&oid) || self.common.contains(&oid) {
+                continue;
+            }
+            haves.push(oid);
+            sent += 1;
+
+            if !self.graph.tips.contains(&oid) {
+                in_vain += 1;
+            }
+
+            for parent in self.graph.parents(&oid) {
+                if self.queued.insert(*parent) {
+                    self.queue.push_back(*parent);
+                }
+            }
+        }
+        self.in_vain += in_vain;
+        self.round_size = (self.round_size * 2).clamp(INITIAL_ROUND_SIZE, MAX_ROUND_SIZE);
+
+        let exhausted = self
+            .queue
+            .iter()
+            .all(|oid| self.implied.contains(oid) || self.common.contains(oid));
+        let done = haves.is_empty() || exhausted || self.in_vain >= MAX_IN_VAIN;
+
+        (haves, done)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::git::repository::mem;
+
+    use super::*;
+
+    fn history(repo: &mut mem::Repository, len: usize) -> Vec<Oid> {
+        let mut history = vec![repo.commit(&[])];
+        for _ in 1..len {
+            let parent = history[history.len() - 1];
+            history.push(repo.commit(&[parent]));
+        }
+        history
+    }
+
+    #[test]
+    fn test_negotiate_rounds() {
+        let mut repo = mem::Repository::default();
+        let history = history(&mut repo, 100);
+        let tip = history[history.len() - 1];
+        let graph = Graph::walk(&repo, &BTreeSet::from([tip]), MAX_WALK).unwrap();
+        let mut negotiator = Negotiator::new(graph);
+
+        let (haves, done) = negotiator.next_round();
+        assert!(!done);
+        assert_eq!(haves.len(), INITIAL_ROUND_SIZE);
+        assert_eq!(haves[0], tip);
+
+        // The server has a commit we haven't sent yet.
+        let common = history[80];
+        negotiator.ack(common);
+
+        // Only the commits between the ones we sent and the common
+        // commit are sent, none of its ancestors.
+        let (haves, done) = negotiator.next_round();
+        assert_eq!(haves, vec![common, history[83], history[82], history[81]]);
+        assert!(done, "once the common commit is reached, we are done");
+    }
+
+    #[test]
+    fn test_negotiate_in_vain() {
+        let mut repo = mem::Repository::default();
+        let history = history(&mut repo, MAX_WALK * 2);
+        let tip = history[history.len() - 1];
+        let graph = Graph::walk(&repo, &BTreeSet::from([tip]), MAX_WALK).unwrap();
+        let mut negotiator = Negotiator::new(graph);
+        let mut sent = 0;
+
+        loop {
+            let (haves, done) = negotiator.next_round();
+            sent += haves.len();
+            if done {
+                break;
+            }
+        }
+        assert!(sent >= MAX_IN_VAIN);
+        assert!(sent < MAX_IN_VAIN + MAX_ROUND_SIZE);
+    }
+
+    #[test]
+    fn test_negotiate_tips_first() {
+        let mut repo = mem::Repository::default();
+        let tips = (0..MAX_IN_VAIN * 2)
+            .map(|_| repo.commit(&[]))
+            .collect::<BTreeSet<_>>();
+        let graph = Graph::walk(&repo, &tips, MAX_WALK).unwrap();
+        let mut negotiator = Negotiator::new(graph);
+
+        let (haves, done) = negotiator.next_round();
+        assert_eq!(haves.into_iter().collect::<BTreeSet<_>>(), tips);
+        assert!(done);
+    }
+
+    #[test]
+    fn test_negotiate_empty() {
+        let mut negotiator = Negotiator::new(Graph::default());
+        let (haves, done) = negotiator.next_round();
+
+        assert!(haves.is_empty());
+        assert!(done);
+    }
+}
@@ -45,11 +45,19 @@ async fn node_handler(State(ctx): State<Context>) -> impl IntoResponse {
             None
         }
     };
+    let health = match node.health() {
+        Ok(health) => Some(health),
+        Err(err) => {
+            tracing::error!("Error getting node health: {:#}", err);
+            None
+        }
+    };
     let response = json!({
         "id": node_id.to_string(),
         "version": format!("{}-{}", VERSION, env!("GIT_HEAD")),
         "config": config,
         "state": node_state,
+        "health": health,
     });
 
     Ok::<_, Error>(Json(response))
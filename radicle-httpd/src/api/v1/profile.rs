@@ -96,6 +96,8 @@ mod routes {
                     "routingMaxAge": 604800,
                     "gossipMaxAge": 1209600,
                     "fetchConcurrency": 1,
+                    "streamConcurrency": 2,
+                    "relayHops": 8,
                     "maxOpenFiles": 4096,
                     "rate": {
                       "inbound": {
@@ -109,12 +111,20 @@ mod routes {
                     },
                     "connection": {
                       "inbound": 128,
-                      "outbound": 16
+                      "outbound": 16,
+                      "handshakeTimeout": 30,
+                      "negotiationTimeout": 60,
+                      "git": 32
+                    },
+                    "uploads": {
+                      "maxSize": 10000,
+                      "maxAge": 2592000
                     }
                   },
                   "workers": 8,
                   "policy": "block",
-                  "scope": "all"
+                  "scope": "all",
+                  "replicationFactor": 3
                 }
               },
               "home": seed.profile.path()
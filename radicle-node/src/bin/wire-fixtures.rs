@@ -0,0 +1,21 @@
+//! Regenerate the golden wire message fixtures.
+//!
+//! Only run this after a deliberate change to the wire format, and check the resulting
+//! diff: the fixtures of messages that didn't change must stay the same.
+use std::process;
+
+use radicle_node::test::golden;
+
+fn main() {
+    match golden::write() {
+        Ok(paths) => {
+            for path in paths {
+                println!("{}", path.display());
+            }
+        }
+        Err(e) => {
+            eprintln!("error: failed to write fixtures: {e}");
+            process::exit(1);
+        }
+    }
+}
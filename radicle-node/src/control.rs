@@ -12,7 +12,7 @@ use serde_json as json;
 
 use crate::identity::RepoId;
 use crate::node::NodeId;
-use crate::node::{Command, CommandResult};
+use crate::node::{Command, CommandResult, ErrorKind};
 use crate::runtime;
 use crate::runtime::thread;
 
@@ -49,7 +49,9 @@ where
                     if let Err(e) = command(&stream, handle) {
                         log::error!(target: "control", "Command returned error: {e}");
 
-                        CommandResult::error(e).to_writer(&mut stream).ok();
+                        CommandResult::error_kind(e.kind(), e)
+                            .to_writer(&mut stream)
+                            .ok();
 
                         stream.flush().ok();
                         stream.shutdown(net::Shutdown::Both).ok();
@@ -74,6 +76,17 @@ enum CommandError {
     Io(#[from] io::Error),
 }
 
+impl CommandError {
+    /// Get the kind of error, as reported to the client.
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Serialization(_) => ErrorKind::InvalidInput,
+            Self::Runtime(e) => e.kind(),
+            Self::Io(e) => ErrorKind::from_io(e),
+        }
+    }
+}
+
 fn command<H: Handle<Error = runtime::HandleError> + 'static>(
     stream: &UnixStream,
     mut handle: H,
@@ -116,11 +129,21 @@ where
 
             CommandResult::Okay(config).to_writer(writer)?;
         }
+        Command::Reload => {
+            let diff = handle.reload()?;
+
+            CommandResult::Okay(diff).to_writer(writer)?;
+        }
         Command::ListenAddrs => {
             let addrs = handle.listen_addrs()?;
 
             CommandResult::Okay(addrs).to_writer(writer)?;
         }
+        Command::AnnouncedAddrs => {
+            let addrs = handle.announced_addrs()?;
+
+            CommandResult::Okay(addrs).to_writer(writer)?;
+        }
         Command::Seeds { rid } => {
             let seeds = handle.seeds(rid)?;
 
@@ -163,6 +186,107 @@ where
                 return Err(CommandError::Runtime(e));
             }
         },
+        Command::ResetBackoff { rid } => match handle.reset_backoff(rid) {
+            Ok(result) => {
+                CommandResult::updated(result).to_writer(writer)?;
+            }
+            Err(e) => {
+                return Err(CommandError::Runtime(e));
+            }
+        },
+        Command::SetFetcherPolicy { rid, nid, policy } => {
+            match handle.set_fetcher_policy(rid, nid, policy) {
+                Ok(result) => {
+                    CommandResult::updated(result).to_writer(writer)?;
+                }
+                Err(e) => {
+                    return Err(CommandError::Runtime(e));
+                }
+            }
+        }
+        Command::Fetchers { rid } => {
+            let fetchers = handle.fetchers(rid)?;
+
+            CommandResult::Okay(fetchers).to_writer(writer)?;
+        }
+        Command::Uploads { rid } => {
+            let uploads = handle.uploads(rid)?;
+
+            CommandResult::Okay(uploads).to_writer(writer)?;
+        }
+        Command::Repos => {
+            let repos = handle.repos()?;
+
+            CommandResult::Okay(repos).to_writer(writer)?;
+        }
+        Command::Stats { rid } => {
+            let stats = handle.stats(rid)?;
+
+            CommandResult::Okay(stats).to_writer(writer)?;
+        }
+        Command::Verify { rid } => match handle.verify(rid) {
+            Ok(reports) => {
+                for r in reports {
+                    CommandResult::Okay(r?).to_writer(&mut writer)?;
+                }
+            }
+            Err(e) => return Err(CommandError::Runtime(e)),
+        },
+        Command::Repair { rid } => match handle.repair(rid) {
+            Ok(repair) => {
+                CommandResult::Okay(repair).to_writer(writer)?;
+            }
+            Err(e) => return Err(CommandError::Runtime(e)),
+        },
+        Command::Export { rid, path } => {
+            if let Err(e) = handle.export(rid, path) {
+                return Err(CommandError::Runtime(e));
+            }
+            CommandResult::ok().to_writer(writer)?;
+        }
+        Command::Import { path } => match handle.import(path) {
+            Ok(imported) => {
+                CommandResult::Okay(imported).to_writer(writer)?;
+            }
+            Err(e) => return Err(CommandError::Runtime(e)),
+        },
+        Command::Init { path, opts } => match handle.init(path, opts) {
+            Ok(rid) => {
+                CommandResult::Okay(rid).to_writer(writer)?;
+            }
+            Err(e) => return Err(CommandError::Runtime(e)),
+        },
+        Command::Tasks => {
+            let tasks = handle.tasks()?;
+
+            CommandResult::Okay(tasks).to_writer(writer)?;
+        }
+        Command::Abort { id } => match handle.abort(id) {
+            Ok(aborted) => {
+                CommandResult::updated(aborted).to_writer(writer)?;
+            }
+            Err(e) => return Err(CommandError::Runtime(e)),
+        },
+        Command::Peers { features } => {
+            let peers = handle.peers(features)?;
+
+            CommandResult::Okay(peers).to_writer(writer)?;
+        }
+        Command::Filter => {
+            let status = handle.filter()?;
+
+            CommandResult::Okay(status).to_writer(writer)?;
+        }
+        Command::Health => {
+            let health = handle.health()?;
+
+            CommandResult::Okay(health).to_writer(writer)?;
+        }
+        Command::SelfCheck { addr, rid } => {
+            let check = handle.self_check(addr, rid)?;
+
+            CommandResult::Okay(check).to_writer(writer)?;
+        }
         Command::AnnounceRefs { rid } => {
             let refs = handle.announce_refs(rid)?;
 
@@ -239,7 +363,7 @@ mod tests {
     use crate::identity::RepoId;
     use crate::node::Handle;
     use crate::node::{Alias, Node, NodeId};
-    use crate::service::policy::Scope;
+    use crate::service::policy::{FetcherPolicy, Policy, Scope};
     use crate::test;
 
     #[test]
@@ -317,5 +441,25 @@ mod tests {
         assert!(!handle.follow(peer, Some(Alias::new("alice"))).unwrap());
         assert!(handle.unfollow(peer).unwrap());
         assert!(!handle.unfollow(peer).unwrap());
+
+        assert!(!handle.reset_backoff(proj).unwrap());
+
+        assert!(handle
+            .set_fetcher_policy(proj, peer, Some(Policy::Block))
+            .unwrap());
+        assert!(!handle
+            .set_fetcher_policy(proj, peer, Some(Policy::Block))
+            .unwrap());
+        assert_eq!(
+            handle.fetchers(proj).unwrap(),
+            vec![FetcherPolicy {
+                rid: proj,
+                nid: peer,
+                policy: Policy::Block
+            }]
+        );
+        assert!(handle.set_fetcher_policy(proj, peer, None).unwrap());
+        assert!(!handle.set_fetcher_policy(proj, peer, None).unwrap());
+        assert!(handle.fetchers(proj).unwrap().is_empty());
     }
 }
@@ -4,6 +4,7 @@ pub mod deserializer;
 pub mod runtime;
 pub mod service;
 pub mod signals;
+pub mod signer;
 #[cfg(any(test, feature = "test"))]
 pub mod test;
 #[cfg(test)]
@@ -1,14 +1,18 @@
 use std::io;
+use std::sync::Arc;
 use std::{env, fs, net, path::PathBuf, process};
 
 use anyhow::Context;
 use crossbeam_channel as chan;
+use nonempty::NonEmpty;
 
+use radicle::crypto::ssh::keystore::{Keystore, MemorySigner};
 use radicle::logger;
+use radicle::node::migrate;
 use radicle::prelude::Signer;
 use radicle::profile;
 use radicle::version::Version;
-use radicle_node::crypto::ssh::keystore::{Keystore, MemorySigner};
+use radicle_node::runtime::Identity;
 use radicle_node::signals;
 use radicle_node::Runtime;
 
@@ -27,12 +31,26 @@ Usage
    If you're running a public seed node, make sure to use `--listen` to bind a listening socket to
    eg. `0.0.0.0:8776`, and add your external addresses in your configuration.
 
+   The configuration is reloaded when the node receives `SIGHUP`. Settings that can't be changed
+   while the node is running, eg. the listen addresses, take effect on the next restart.
+
+   Other node identities can be hosted by the same process with `--identity`. Each identity has
+   its own Radicle home, with its keys, configuration, storage and control socket, and runs its
+   own sessions, but they share the service thread and the worker pool. The number of workers,
+   and other process-wide settings, are taken from the configuration of the default home. The
+   command-line `--config`, `--listen` and `--record` options only apply to the default home.
+
 Options
 
     --config             <path>         Config file to use (default ~/.radicle/config.json)
+    --check                             Check for pending migrations of the on-disk state, and exit.
+                                        Exits with a non-zero status if any migration is pending
     --force                             Force start even if an existing control socket is found
+    --identity           <path>         Also host the node identity of the given Radicle home
+                                        (may be specified multiple times)
     --listen             <address>      Address to listen on
-    --log                <level>        Set log level (default: info)
+    --log                <level>        Set log level, overriding the configuration (default: info)
+    --record             <path>         Record inbound protocol sessions to the given file
     --version                           Print program version
     --help                              Print help
 "#;
@@ -41,8 +59,11 @@ Options
 struct Options {
     config: Option<PathBuf>,
     listen: Vec<net::SocketAddr>,
-    log: log::Level,
+    log: Option<log::Level>,
     force: bool,
+    check: bool,
+    record: Option<PathBuf>,
+    identities: Vec<PathBuf>,
 }
 
 impl Options {
@@ -53,13 +74,19 @@ impl Options {
         let mut listen = Vec::new();
         let mut config = None;
         let mut force = false;
-        let mut log = log::Level::Info;
+        let mut check = false;
+        let mut log = None;
+        let mut record = None;
+        let mut identities = Vec::new();
 
         while let Some(arg) = parser.next()? {
             match arg {
                 Long("force") => {
                     force = true;
                 }
+                Long("check") => {
+                    check = true;
+                }
                 Long("config") => {
                     let value = parser.value()?;
                     let path = PathBuf::from(value);
@@ -69,8 +96,16 @@ impl Options {
                     let addr = parser.value()?.parse()?;
                     listen.push(addr);
                 }
+                Long("identity") => {
+                    let value = parser.value()?;
+                    identities.push(PathBuf::from(value));
+                }
                 Long("log") => {
-                    log = parser.value()?.parse()?;
+                    log = Some(parser.value()?.parse()?);
+                }
+                Long("record") => {
+                    let value = parser.value()?;
+                    record = Some(PathBuf::from(value));
                 }
                 Long("help") | Short('h') => {
                     println!("{HELP_MSG}");
@@ -86,9 +121,12 @@ impl Options {
 
         Ok(Self {
             force,
+            check,
             listen,
             log,
             config,
+            record,
+            identities,
         })
     }
 }
@@ -97,11 +135,50 @@ fn execute() -> anyhow::Result<()> {
     let home = profile::home()?;
     let options = Options::from_env()?;
 
-    logger::init(options.log)?;
+    if options.check {
+        let statuses = migrate::check(&home)?;
+        for status in &statuses {
+            println!("{status}");
+        }
+        let ok = statuses
+            .iter()
+            .all(|s| s.pending() == 0 && s.version <= Some(s.latest));
+        process::exit(if ok { 0 } else { 1 });
+    }
+    logger::init(options.log.unwrap_or(log::Level::Info))?;
 
     log::info!(target: "node", "Starting node..");
     log::info!(target: "node", "Version {} ({})", env!("RADICLE_VERSION"), env!("GIT_HEAD"));
-    log::info!(target: "node", "Unlocking node keystore..");
+
+    let default = identity(home, &options, true)?;
+
+    if let Some(level) = default.config.log {
+        logger::set_level(level);
+    }
+    if let Err(e) = radicle::io::set_file_limit(default.config.limits.max_open_files as u64) {
+        log::warn!(target: "node", "Unable to set process open file limit: {e}");
+    }
+    let mut identities = NonEmpty::new(default);
+    for path in &options.identities {
+        identities.push(identity(profile::Home::new(path)?, &options, false)?);
+    }
+    let proxy = net::SocketAddr::new(net::Ipv4Addr::LOCALHOST.into(), 9050);
+    let (notify, signals) = chan::bounded(1);
+    signals::install(notify)?;
+
+    Runtime::host(identities, proxy, signals)?.run()?;
+
+    Ok(())
+}
+
+/// Load the node identity of the given home. The command-line options that override the
+/// configuration only apply to the default identity.
+fn identity(
+    home: profile::Home,
+    options: &Options,
+    default: bool,
+) -> anyhow::Result<Identity<MemorySigner>> {
+    log::info!(target: "node", "Unlocking node keystore in {}..", home.path().display());
 
     let passphrase = profile::env::passphrase();
     let keystore = Keystore::new(&home.keys());
@@ -109,33 +186,54 @@ fn execute() -> anyhow::Result<()> {
 
     log::info!(target: "node", "Node ID is {}", signer.public_key());
 
-    let config = options.config.unwrap_or_else(|| home.config());
-    let mut config = profile::Config::load(&config)?;
-
-    // Add the preferred seeds as persistent peers so that we reconnect to them automatically.
-    config.node.connect.extend(config.preferred_seeds);
+    let loader = {
+        let path = options
+            .config
+            .clone()
+            .filter(|_| default)
+            .unwrap_or_else(|| home.config());
+        let listen = if default {
+            options.listen.clone()
+        } else {
+            Vec::new()
+        };
+        let log = options.log;
+        let record = options.record.clone().filter(|_| default);
 
-    let proxy = net::SocketAddr::new(net::Ipv4Addr::LOCALHOST.into(), 9050);
-    let listen: Vec<std::net::SocketAddr> = if !options.listen.is_empty() {
-        options.listen.clone()
-    } else {
-        config.node.listen.clone()
-    };
+        move || {
+            let config = profile::Config::load(&path)?;
+            let mut node = config.node;
 
-    if let Err(e) = radicle::io::set_file_limit(config.node.limits.max_open_files as u64) {
-        log::warn!(target: "node", "Unable to set process open file limit: {e}");
-    }
+            // Add the preferred seeds as persistent peers so that we reconnect to them automatically.
+            node.connect.extend(config.preferred_seeds);
 
-    let (notify, signals) = chan::bounded(1);
-    signals::install(notify)?;
+            // Command-line options override the configuration.
+            if !listen.is_empty() {
+                node.listen = listen.clone();
+            }
+            if log.is_some() {
+                node.log = log;
+            }
+            if record.is_some() {
+                node.record = record.clone();
+            }
+            Ok::<_, profile::ConfigError>(node)
+        }
+    };
+    let config = loader()?;
+    let listen = config.listen.clone();
 
     if options.force {
         log::debug!(target: "node", "Removing existing control socket..");
         fs::remove_file(home.socket()).ok();
     }
-    Runtime::init(home, config.node, listen, proxy, signals, signer)?.run()?;
-
-    Ok(())
+    Ok(Identity {
+        home,
+        config,
+        listen,
+        signer,
+        loader: Some(Arc::new(loader)),
+    })
 }
 
 fn main() {
@@ -1,6 +1,11 @@
 pub mod handle;
+pub mod locks;
+pub mod selfcheck;
 pub mod thread;
+pub mod watchdog;
+pub mod webhooks;
 
+use std::collections::BTreeSet;
 use std::os::unix::net::UnixListener;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
@@ -9,6 +14,7 @@ use std::{fs, io, net};
 use crossbeam_channel as chan;
 use cyphernet::Ecdh;
 use netservices::resource::NetAccept;
+use nonempty::NonEmpty;
 use radicle_fetch::FetchLimit;
 use reactor::poller::popol;
 use reactor::Reactor;
@@ -27,13 +33,19 @@ use radicle::{cob, git};
 use crate::control;
 use crate::crypto::Signer;
 use crate::node::{routing, NodeId};
+use crate::service::clock::AdjustedClock;
+use crate::service::io::SignRequest;
 use crate::service::message::NodeAnnouncement;
-use crate::service::{gossip, policy, Event};
+use crate::service::{gossip, policy, snapshot, Event};
+use crate::signals::Signal;
+use crate::signer;
 use crate::wire;
+use crate::wire::record::Recorder;
 use crate::wire::{Decode, Wire};
 use crate::worker;
 use crate::{service, LocalTime};
 
+pub use handle::ConfigLoader;
 pub use handle::Error as HandleError;
 pub use handle::Handle;
 
@@ -67,6 +79,9 @@ pub enum Error {
     /// A service error.
     #[error("service error: {0}")]
     Service(#[from] service::Error),
+    /// A migration error.
+    #[error("migration error: {0}")]
+    Migrate(#[from] node::migrate::Error),
     /// An I/O error.
     #[error("i/o error: {0}")]
     Io(#[from] io::Error),
@@ -83,6 +98,9 @@ pub enum Error {
     /// A git version error.
     #[error("git version error: {0}")]
     GitVersion(#[from] git::VersionError),
+    /// The same node identity was given more than once.
+    #[error("node identity {0} is hosted more than once")]
+    DuplicateIdentity(NodeId),
 }
 
 /// Publishes events to subscribers.
@@ -118,21 +136,65 @@ impl<T: Clone> Emitter<T> {
     }
 }
 
-/// Holds join handles to the client threads, as well as a client handle.
-pub struct Runtime {
+/// A node identity to host in a runtime. See [`Runtime::host`].
+pub struct Identity<G> {
+    /// Radicle home of the identity, with its keys, configuration, storage and databases.
+    pub home: Home,
+    /// Node configuration.
+    pub config: service::Config,
+    /// Addresses to listen on. These override the ones in the configuration.
+    pub listen: Vec<net::SocketAddr>,
+    /// Node signer.
+    pub signer: G,
+    /// Loads the configuration when the node is asked to reload it, eg. on `SIGHUP`.
+    /// Without it, the configuration can't be reloaded.
+    pub loader: Option<ConfigLoader>,
+}
+
+/// The parts of a runtime that belong to one of the node identities it hosts.
+pub struct Hosted {
     pub id: NodeId,
     pub home: Home,
     pub control: UnixListener,
     pub handle: Handle,
     pub storage: Storage,
-    pub reactor: Reactor<wire::Control, popol::Poller>,
-    pub pool: worker::Pool,
+    pub daemon: Option<worker::daemon::Daemon>,
+    pub gateway: Option<worker::http::Gateway>,
     pub local_addrs: Vec<net::SocketAddr>,
-    pub signals: chan::Receiver<()>,
+    /// Callbacks run after fetches that update repositories.
+    pub hooks: worker::hooks::Callbacks,
+}
+
+/// The wire of a hosted identity.
+type NodeWire<G> = Wire<node::Database, Storage, G>;
+
+/// The state of a hosted identity, set up before the reactor is started.
+struct Setup<G> {
+    home: Home,
+    config: service::Config,
+    signer: G,
+    loader: Option<ConfigLoader>,
+    storage: Storage,
+    db: node::Database,
+    notifications: notifications::StoreWriter,
+    cobs_cache: cob::cache::StoreWriter,
+    emitter: Emitter<Event>,
+    signing: chan::Receiver<SignRequest>,
+    progress: Arc<watchdog::Progress>,
+    local_addrs: Vec<net::SocketAddr>,
+}
+
+/// Holds join handles to the client threads, as well as a client handle.
+pub struct Runtime {
+    /// The node identities hosted by the runtime. The first one is the default identity.
+    pub hosted: NonEmpty<Hosted>,
+    pub reactor: Reactor<wire::host::Command, popol::Poller>,
+    pub pool: worker::Pool,
+    pub signals: chan::Receiver<Signal>,
 }
 
 impl Runtime {
-    /// Initialize the runtime.
+    /// Initialize the runtime of a single node identity.
     ///
     /// This function spawns threads.
     pub fn init<G: Signer + Ecdh + 'static>(
@@ -140,18 +202,247 @@ impl Runtime {
         config: service::Config,
         listen: Vec<net::SocketAddr>,
         proxy: net::SocketAddr,
-        signals: chan::Receiver<()>,
+        signals: chan::Receiver<Signal>,
         signer: G,
     ) -> Result<Runtime, Error>
     where
         G: Ecdh<Pk = NodeId> + Clone,
     {
+        let identity = Identity {
+            home,
+            config,
+            listen,
+            signer,
+            loader: None,
+        };
+        Self::host(NonEmpty::new(identity), proxy, signals)
+    }
+
+    /// Initialize a runtime hosting several node identities.
+    ///
+    /// Each identity has its own storage, databases, sessions and control socket, but they
+    /// share the service thread, ie. the reactor, and the worker pool. Settings of the
+    /// reactor and of the pool, ie. the number of workers and their niceness, are taken
+    /// from the configuration of the first identity, the default one.
+    ///
+    /// This function spawns threads.
+    pub fn host<G: Signer + Ecdh + 'static>(
+        identities: NonEmpty<Identity<G>>,
+        proxy: net::SocketAddr,
+        signals: chan::Receiver<Signal>,
+    ) -> Result<Runtime, Error>
+    where
+        G: Ecdh<Pk = NodeId> + Clone,
+    {
+        let mut ids = BTreeSet::new();
+        for identity in identities.iter() {
+            let id = *identity.signer.public_key();
+            if !ids.insert(id) {
+                return Err(Error::DuplicateIdentity(id));
+            }
+        }
+        let default = *identities.head.signer.public_key();
+        let capacity = identities.head.config.workers;
+        let niceness = identities.head.config.limits.worker_niceness;
+
+        let (worker_send, worker_recv) = chan::unbounded::<worker::Task>();
+        let mut wires = Vec::with_capacity(identities.len());
+        let mut setups = Vec::with_capacity(identities.len());
+
+        for identity in identities {
+            let (wire, setup) = Self::setup(identity, proxy, worker_send.clone())?;
+
+            wires.push(wire);
+            setups.push(setup);
+        }
+        // Nb. The identities were checked above, so there is at least one wire.
+        let wires = NonEmpty::from_vec(wires).expect("Runtime::host: there is a default identity");
+        let reactor = Reactor::named(
+            wire::Host::new(wires),
+            popol::Poller::new(),
+            thread::name(&default, "service"),
+        )?;
+
+        let mut hosted = Vec::with_capacity(setups.len());
+        let mut workers = Vec::with_capacity(setups.len());
+        for setup in setups.iter() {
+            let id = *setup.signer.public_key();
+            let config = &setup.config;
+            let defaults = worker::Defaults::new(config.policy, config.scope);
+            let mut handle = Handle::new(
+                setup.home.clone(),
+                wire::Controller::new(id, reactor.controller()),
+                setup.emitter.clone(),
+                setup.storage.clone(),
+                defaults.clone(),
+                Arc::new(setup.signer.clone()),
+            );
+            handle.config_loader = setup.loader.clone();
+
+            if !config.webhooks.is_empty() {
+                let webhooks = webhooks::Webhooks::new(config.webhooks.clone(), handle.events());
+                thread::spawn(&id, "webhooks", move || webhooks.run());
+            }
+            thread::spawn(&id, "signer", {
+                let signing = signer::Signing::new(
+                    setup.signer.clone(),
+                    setup.signing.clone(),
+                    handle.clone(),
+                );
+                move || signing.run()
+            });
+            let fetch = worker::FetchConfig {
+                limit: FetchLimit::default(),
+                local: id,
+                expiry: worker::garbage::Expiry::default(),
+                pack_threads: config.limits.pack_threads,
+                blobs: config.blobs,
+                refuse_diverged_sigrefs: config.refuse_diverged_sigrefs,
+                denied_refs: config.deny_refs.iter().cloned().collect(),
+                max_namespace_refs: config.limits.max_namespace_refs,
+                ref_update_batch_size: config.limits.ref_update_batch_size,
+                confirmations: config.confirmations.clone(),
+                upload_limits: worker::upload_pack::Limits::from(&config.limits.uploads),
+            };
+            let mirror_send = if config.mirrors.is_empty() {
+                None
+            } else {
+                let (send, recv) = chan::unbounded();
+                let mirrors = worker::mirror::Mirrors::new(
+                    setup.storage.clone(),
+                    config.mirrors.clone(),
+                    recv,
+                );
+
+                thread::spawn(&id, "mirror", move || mirrors.run());
+                Some(send)
+            };
+            // The hooks thread is always running, since callbacks may be registered at any time.
+            let hooks = worker::hooks::Callbacks::default();
+            let (hooks_send, hooks_recv) = chan::unbounded();
+            let runner = worker::hooks::Hooks::new(config.hooks.clone(), hooks.clone(), hooks_recv);
+            thread::spawn(&id, "hooks", move || runner.run());
+
+            workers.push(worker::Identity {
+                handle: handle.clone(),
+                notifications: setup.notifications.clone(),
+                cache: setup.cobs_cache.clone(),
+                db: setup.db.clone(),
+                config: worker::Config {
+                    storage: Arc::new(setup.storage.clone()),
+                    fetch,
+                    defaults: defaults.clone(),
+                    policies_db: setup.home.node().join(node::POLICIES_DB_FILE),
+                    mirror: mirror_send,
+                    hooks: Some(hooks_send),
+                    updates: Some(storage::updates::Notifier::new(setup.storage.path())),
+                },
+            });
+            let daemon_config = worker::daemon::Config {
+                limits: worker::upload_pack::Limits::from(&config.limits.uploads),
+                storage: setup.storage.clone(),
+                defaults,
+                policies_db: setup.home.node().join(node::POLICIES_DB_FILE),
+                resolver: worker::resolve::Resolver::new(config.repo_aliases.clone()),
+                connections: worker::daemon::Connections::new(config.limits.connection.git),
+            };
+            let daemon = config
+                .git_daemon
+                .map(|addr| worker::daemon::Daemon::bind(id, addr, daemon_config.clone()))
+                .transpose()?;
+            let gateway = config
+                .git_http
+                .map(|addr| worker::http::Gateway::bind(id, addr, daemon_config))
+                .transpose()?;
+            let control = match UnixListener::bind(setup.home.socket()) {
+                Ok(sock) => sock,
+                Err(err) if err.kind() == io::ErrorKind::AddrInUse => {
+                    return Err(Error::AlreadyRunning(setup.home.socket()));
+                }
+                Err(err) => {
+                    return Err(err.into());
+                }
+            };
+
+            hosted.push(Hosted {
+                id,
+                home: setup.home.clone(),
+                control,
+                handle,
+                storage: setup.storage.clone(),
+                daemon,
+                gateway,
+                local_addrs: setup.local_addrs.clone(),
+                hooks,
+            });
+        }
+        let workers =
+            NonEmpty::from_vec(workers).expect("Runtime::host: there is a default identity");
+        let pool = worker::Pool::with(worker_recv.clone(), capacity, niceness, workers)?;
+
+        for (hosted, setup) in hosted.iter_mut().zip(setups) {
+            let config = setup.config;
+
+            hosted.handle.pool = Some(watchdog::Pool::new(
+                config
+                    .watchdog
+                    .as_ref()
+                    .map(|w| w.timeout)
+                    .unwrap_or(node::config::DEFAULT_WATCHDOG_TIMEOUT),
+                pool.progress(),
+                worker_recv.clone(),
+            ));
+            if let Some(config) = config.watchdog {
+                let watchdog = watchdog::Watchdog::new(
+                    config,
+                    hosted.handle.clone(),
+                    setup.progress,
+                    pool.progress(),
+                    worker_recv.clone(),
+                    setup.signing,
+                );
+                thread::spawn(&hosted.id, "watchdog", move || watchdog.run());
+            }
+        }
+        let hosted =
+            NonEmpty::from_vec(hosted).expect("Runtime::host: there is a default identity");
+
+        Ok(Runtime {
+            hosted,
+            reactor,
+            pool,
+            signals,
+        })
+    }
+
+    /// Set up the service and the wire of a node identity, and bind its listeners.
+    fn setup<G: Signer + Ecdh + 'static>(
+        identity: Identity<G>,
+        proxy: net::SocketAddr,
+        worker_send: chan::Sender<worker::Task>,
+    ) -> Result<(NodeWire<G>, Setup<G>), Error>
+    where
+        G: Ecdh<Pk = NodeId> + Clone,
+    {
+        let Identity {
+            home,
+            mut config,
+            listen,
+            signer,
+            loader,
+        } = identity;
         let id = *signer.public_key();
+        // Nb. The listen addresses may have been overridden, eg. on the command line.
+        config.listen = listen.clone();
         let alias = config.alias.clone();
         let node_dir = home.node();
         let network = config.network;
         let rng = fastrand::Rng::new();
         let clock = LocalTime::now();
+
+        log::info!(target: "node", "Checking on-disk state of {id}..");
+        node::migrate::run(&home)?;
+
         let storage = Storage::open(home.storage(), git::UserInfo { alias, key: id })?;
         let scope = config.scope;
         let policy = config.policy;
@@ -185,7 +476,7 @@ impl Runtime {
             .and_then(|ann| {
                 if config.features() == ann.features
                     && config.alias == ann.alias
-                    && config.external_addresses == ann.addresses.as_ref()
+                    && service::gossip::addresses(&config) == ann.addresses
                 {
                     Some(ann)
                 } else {
@@ -226,7 +517,7 @@ impl Runtime {
         let emitter: Emitter<Event> = Default::default();
         let mut service = service::Service::new(
             config.clone(),
-            clock,
+            AdjustedClock::new(clock),
             stores,
             storage.clone(),
             policies,
@@ -235,95 +526,182 @@ impl Runtime {
             announcement,
             emitter.clone(),
         );
+        service.offload_signing();
+
+        let snapshot = node_dir.join(snapshot::SNAPSHOT_FILE);
+        match snapshot::Snapshot::load(&snapshot) {
+            Ok(Some(s)) => {
+                log::info!(target: "node", "Restoring service state from snapshot (timestamp={})..", s.timestamp);
+                service.restore(s);
+            }
+            Ok(None) => {}
+            Err(e) => log::warn!(target: "node", "Ignoring service state snapshot: {e}"),
+        }
         service.initialize(clock)?;
 
-        let (worker_send, worker_recv) = chan::unbounded::<worker::Task>();
-        let mut wire = Wire::new(service, worker_send, signer.clone(), proxy);
+        let (signing_send, signing_recv) = chan::unbounded::<SignRequest>();
+        let mut wire = Wire::new(
+            service,
+            worker_send,
+            signing_send,
+            signer.clone(),
+            proxy,
+            config.bridge.clone(),
+            config.limits.connection.handshake_timeout,
+        )
+        .with_stream_limit(config.limits.stream_concurrency)
+        .with_snapshot(snapshot);
+        if let Some(path) = &config.record {
+            log::info!(target: "node", "Recording inbound sessions to {}..", path.display());
+
+            wire = wire.with_recorder(Recorder::open(path)?);
+        }
         let mut local_addrs = Vec::new();
 
-        for addr in listen {
-            let listener = NetAccept::bind(&addr)?;
+        for addr in listen.iter() {
+            // Nb. the port is reusable, so that rendezvous connections can be made from it.
+            let listener = match NetAccept::bind_reusable(addr) {
+                Ok(listener) => listener,
+                // On dual-stack systems, a socket listening on the unspecified IPv6 address
+                // also accepts IPv4 connections, so binding both on the same port fails.
+                Err(e)
+                    if e.kind() == io::ErrorKind::AddrInUse
+                        && addr.ip().is_unspecified()
+                        && local_addrs.iter().any(|a: &net::SocketAddr| {
+                            a.ip().is_unspecified()
+                                && a.is_ipv6() != addr.is_ipv6()
+                                && a.port() == addr.port()
+                        }) =>
+                {
+                    log::info!(target: "node", "Skipping listen address {addr}: already covered by dual-stack socket");
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
             let local_addr = listener.local_addr();
 
             local_addrs.push(local_addr);
             wire.listen(listener);
         }
-        let reactor = Reactor::named(wire, popol::Poller::new(), thread::name(&id, "service"))?;
-        let handle = Handle::new(home.clone(), reactor.controller(), emitter);
-
-        let nid = *signer.public_key();
-        let fetch = worker::FetchConfig {
-            limit: FetchLimit::default(),
-            local: nid,
-            expiry: worker::garbage::Expiry::default(),
-        };
-        let pool = worker::Pool::with(
-            worker_recv,
-            nid,
-            handle.clone(),
-            notifications,
-            cobs_cache,
-            db,
-            worker::Config {
-                capacity: config.workers,
-                storage: storage.clone(),
-                fetch,
-                policy,
-                scope,
-                policies_db: home.node().join(node::POLICIES_DB_FILE),
+        let progress = wire.progress();
+
+        Ok((
+            wire,
+            Setup {
+                home,
+                config,
+                signer,
+                loader,
+                storage,
+                db,
+                notifications,
+                cobs_cache,
+                emitter,
+                signing: signing_recv,
+                progress,
+                local_addrs,
             },
-        )?;
-        let control = match UnixListener::bind(home.socket()) {
-            Ok(sock) => sock,
-            Err(err) if err.kind() == io::ErrorKind::AddrInUse => {
-                return Err(Error::AlreadyRunning(home.socket()));
-            }
-            Err(err) => {
-                return Err(err.into());
-            }
-        };
-
-        Ok(Runtime {
-            id,
-            home,
-            control,
-            storage,
-            reactor,
-            handle,
-            pool,
-            signals,
-            local_addrs,
-        })
+        ))
     }
 
     pub fn run(self) -> Result<(), Error> {
-        let home = self.home;
+        let default = self.hosted.head.id;
 
-        log::info!(target: "node", "Running node {} in {}..", self.id, home.path().display());
-        log::info!(target: "node", "Binding control socket {}..", home.socket().display());
-
-        thread::spawn(&self.id, "control", {
-            let handle = self.handle.clone();
-            || control::listen(self.control, handle)
-        });
-        let _signals = thread::spawn(&self.id, "signals", move || {
-            if let Ok(()) = self.signals.recv() {
-                log::info!(target: "node", "Termination signal received; shutting down..");
-                self.handle.shutdown().ok();
+        for hosted in self.hosted.iter() {
+            log::info!(target: "node", "Running node {} in {}..", hosted.id, hosted.home.path().display());
+        }
+        let mut handles = Vec::with_capacity(self.hosted.len());
+        let mut homes = Vec::with_capacity(self.hosted.len());
+
+        for hosted in self.hosted {
+            let Hosted {
+                id,
+                home,
+                control,
+                handle,
+                daemon,
+                gateway,
+                ..
+            } = hosted;
+
+            log::info!(target: "node", "Binding control socket {}..", home.socket().display());
+
+            thread::spawn(&id, "control", {
+                let handle = handle.clone();
+                || control::listen(control, handle)
+            });
+            if let Some(daemon) = daemon {
+                thread::spawn(&id, "git-daemon", || {
+                    if let Err(e) = daemon.run() {
+                        log::error!(target: "node", "Git daemon exited with error: {e}");
+                    }
+                });
+            }
+            if let Some(gateway) = gateway {
+                thread::spawn(&id, "git-http", || {
+                    if let Err(e) = gateway.run() {
+                        log::error!(target: "node", "Git HTTP gateway exited with error: {e}");
+                    }
+                });
+            }
+            handles.push((id, handle));
+            homes.push(home);
+        }
+        let signals = self.signals;
+        let _signals = thread::spawn(&default, "signals", move || {
+            while let Ok(signal) = signals.recv() {
+                match signal {
+                    Signal::Hangup => {
+                        log::info!(target: "node", "Hangup signal received; reloading configuration..");
+
+                        for (id, handle) in handles.iter_mut() {
+                            reload(id, handle);
+                        }
+                    }
+                    Signal::Terminate => {
+                        log::info!(target: "node", "Termination signal received; shutting down..");
+                        // Nb. The reactor is shared, so this shuts all identities down.
+                        if let Some((_, handle)) = handles.into_iter().next() {
+                            handle.shutdown().ok();
+                        }
+                        break;
+                    }
+                }
             }
         });
 
         self.pool.run().unwrap();
         self.reactor.join().unwrap();
 
-        // Nb. We don't join the control thread here, as we have no way of notifying it that the
-        // node is shutting down.
-
-        // Remove control socket file, but don't freak out if it's not there anymore.
-        fs::remove_file(home.socket()).ok();
+        // Nb. We don't join the control threads here, as we have no way of notifying them that
+        // the node is shutting down.
 
-        log::debug!(target: "node", "Node shutdown completed for {}", self.id);
+        // Remove control socket files, but don't freak out if they're not there anymore.
+        for home in homes {
+            fs::remove_file(home.socket()).ok();
+        }
+        log::debug!(target: "node", "Node shutdown completed for {default}");
 
         Ok(())
     }
 }
+
+/// Reload the configuration of a hosted identity, and log what changed.
+fn reload(id: &NodeId, handle: &mut Handle) {
+    match handle.reload() {
+        Ok(diff) => {
+            if diff.is_empty() {
+                log::info!(target: "node", "Configuration of {id} is unchanged");
+            }
+            for path in diff.live {
+                log::info!(target: "node", "Configuration setting '{path}' of {id} applied");
+            }
+            for path in diff.restart {
+                log::warn!(target: "node", "Configuration setting '{path}' of {id} changed, but requires a restart");
+            }
+        }
+        Err(e) => {
+            log::error!(target: "node", "Failed to reload configuration of {id}: {e}");
+        }
+    }
+}
@@ -1,27 +1,55 @@
+use std::collections::{BTreeSet, HashMap};
 use std::net;
 use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::{fmt, io, time};
 
 use crossbeam_channel as chan;
-use radicle::node::{ConnectOptions, ConnectResult, Link, Seeds};
+use radicle::crypto::{PublicKey, Signer};
+use radicle::node::config::ConfigDiff;
+use radicle::node::repos::Repo;
+use radicle::node::stats::Stats;
+use radicle::node::uploads::Upload;
+use radicle::node::{
+    address, Address, ConnectOptions, ConnectResult, ErrorKind, Features, FilterStatus,
+    HealthState, InitOptions, Link, NodeHealth, Seeds, SelfCheck, Task, TaskId,
+};
+use radicle::node::{DEFAULT_TIMEOUT, MAX_REPAIR_SEEDS};
+use radicle::storage::git::{bundle, stats, verify};
 use radicle::storage::refs::RefsAt;
-use reactor::poller::popol::PopolWaker;
+use radicle::storage::{ReadRepository as _, ReadStorage as _, RepositoryError};
+use radicle::{git, profile, rad, Storage};
 use thiserror::Error;
 
 use crate::identity::RepoId;
 use crate::node::{Alias, Command, FetchResult};
 use crate::profile::Home;
+use crate::runtime::locks::Locks;
+use crate::runtime::selfcheck;
+use crate::runtime::watchdog;
 use crate::runtime::Emitter;
 use crate::service;
+use crate::service::io::Route;
+use crate::service::message::Announcement;
 use crate::service::policy;
 use crate::service::NodeId;
 use crate::service::{CommandError, Config, QueryState};
 use crate::service::{Event, Events};
 use crate::wire;
 use crate::wire::StreamId;
-use crate::worker::TaskResult;
+use crate::worker::query;
+use crate::worker::{Defaults, TaskResult};
+use crate::LocalTime;
+
+/// How long to wait for peers to be notified of a shutdown.
+const SHUTDOWN_NOTICE_TIMEOUT: time::Duration = time::Duration::from_secs(1);
+/// How long to wait for the service to report its health.
+const HEALTH_TIMEOUT: time::Duration = time::Duration::from_secs(6);
+/// How long the service may take to report its health, before the reactor is considered
+/// degraded.
+const MAX_REACTOR_LATENCY: time::Duration = time::Duration::from_secs(1);
 
 /// An error resulting from a handle method.
 #[derive(Error, Debug)]
@@ -38,6 +66,71 @@ pub enum Error {
     /// An I/O error occured.
     #[error(transparent)]
     Io(#[from] std::io::Error),
+    /// A storage error.
+    #[error("storage error: {0}")]
+    Storage(#[from] radicle::storage::Error),
+    /// A repository error.
+    #[error("repository error: {0}")]
+    Repository(#[from] radicle::storage::RepositoryError),
+    /// A bundle error.
+    #[error("bundle error: {0}")]
+    Bundle(#[from] bundle::Error),
+    /// A configuration error.
+    #[error("configuration error: {0}")]
+    Config(#[from] profile::ConfigError),
+    /// A repository initialization error.
+    #[error("init error: {0}")]
+    Init(#[from] rad::InitError),
+    /// A self-check error.
+    #[error("self-check error: {0}")]
+    SelfCheck(#[from] selfcheck::Error),
+}
+
+/// Loads the node configuration, eg. from the configuration file.
+pub type ConfigLoader = Arc<dyn Fn() -> Result<Config, profile::ConfigError> + Send + Sync>;
+
+impl Error {
+    /// Get the kind of error, as reported to node clients.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::ChannelDisconnected => ErrorKind::Other,
+            Self::Command(CommandError::Storage(e)) => e.kind(),
+            Self::Command(_) => ErrorKind::Other,
+            Self::Timeout => ErrorKind::Timeout,
+            Self::Io(e) => ErrorKind::from_io(e),
+            Self::Storage(e) => e.kind(),
+            Self::Repository(RepositoryError::Storage(e)) => e.kind(),
+            Self::Repository(e) if e.is_not_found() => ErrorKind::NotFound,
+            Self::Repository(_) => ErrorKind::Other,
+            Self::Bundle(e) => match e {
+                bundle::Error::NotFound(_) => ErrorKind::NotFound,
+                bundle::Error::Exists(_) => ErrorKind::AlreadyExists,
+                bundle::Error::Verification(_) => ErrorKind::ValidationFailed,
+                bundle::Error::Io(e) => ErrorKind::from_io(e),
+                bundle::Error::Storage(e) => e.kind(),
+                _ => ErrorKind::Other,
+            },
+            Self::Config(_) => ErrorKind::InvalidInput,
+            Self::Init(e) => match e {
+                rad::InitError::Storage(e) => e.kind(),
+                rad::InitError::Io(e) => ErrorKind::from_io(e),
+                rad::InitError::Git(e) if git::is_not_found_err(e) => ErrorKind::NotFound,
+                rad::InitError::BranchNotFound(_) => ErrorKind::NotFound,
+                rad::InitError::ProjectPayload(_) | rad::InitError::NoDefaultBranch => {
+                    ErrorKind::InvalidInput
+                }
+                _ => ErrorKind::Other,
+            },
+            Self::SelfCheck(e) => match e {
+                selfcheck::Error::Resolve(_, e) | selfcheck::Error::Step(_, e) => {
+                    ErrorKind::from_io(e)
+                }
+                selfcheck::Error::NotListening | selfcheck::Error::NoRepository => {
+                    ErrorKind::NotFound
+                }
+            },
+        }
+    }
 }
 
 impl From<chan::RecvError> for Error {
@@ -63,7 +156,18 @@ impl<T> From<chan::SendError<T>> for Error {
 
 pub struct Handle {
     pub(crate) home: Home,
-    pub(crate) controller: reactor::Controller<wire::Control, PopolWaker>,
+    pub(crate) controller: wire::Controller,
+    pub(crate) storage: Storage,
+    /// Default policy and scope, shared with the workers.
+    pub(crate) defaults: Defaults,
+    /// Loads the configuration when the node is asked to reload it.
+    pub(crate) config_loader: Option<ConfigLoader>,
+    /// Node signer, used to sign the repositories initialized by the node.
+    pub(crate) signer: Arc<dyn Signer>,
+    /// Locks on repositories, shared with the workers.
+    pub(crate) locks: Locks,
+    /// Worker pool, watched to report its health. Set once the pool is created.
+    pub(crate) pool: Option<watchdog::Pool>,
 
     /// Whether a shutdown was initiated or not. Prevents attempting to shutdown twice.
     shutdown: Arc<AtomicBool>,
@@ -76,6 +180,16 @@ impl Handle {
     pub fn events(&self) -> Events {
         Events::from(self.emitter.subscribe())
     }
+
+    /// Publish an event to subscribers.
+    pub(crate) fn emit(&self, event: Event) {
+        self.emitter.emit(event)
+    }
+
+    /// Locks on repositories. See [`Locks`].
+    pub fn locks(&self) -> &Locks {
+        &self.locks
+    }
 }
 
 impl fmt::Debug for Handle {
@@ -89,6 +203,12 @@ impl Clone for Handle {
         Self {
             home: self.home.clone(),
             controller: self.controller.clone(),
+            storage: self.storage.clone(),
+            defaults: self.defaults.clone(),
+            config_loader: self.config_loader.clone(),
+            signer: self.signer.clone(),
+            locks: self.locks.clone(),
+            pool: self.pool.clone(),
             shutdown: self.shutdown.clone(),
             emitter: self.emitter.clone(),
         }
@@ -98,12 +218,21 @@ impl Clone for Handle {
 impl Handle {
     pub fn new(
         home: Home,
-        controller: reactor::Controller<wire::Control, PopolWaker>,
+        controller: wire::Controller,
         emitter: Emitter<Event>,
+        storage: Storage,
+        defaults: Defaults,
+        signer: Arc<dyn Signer>,
     ) -> Self {
         Self {
             home,
             controller,
+            storage,
+            defaults,
+            config_loader: None,
+            signer,
+            locks: Locks::default(),
+            pool: None,
             shutdown: Arc::default(),
             emitter,
         }
@@ -113,10 +242,67 @@ impl Handle {
         self.controller.cmd(wire::Control::Worker(result))
     }
 
+    pub(crate) fn signed(&self, ann: Announcement, route: Route) -> Result<(), io::Error> {
+        self.controller.cmd(wire::Control::Signed(ann, route))
+    }
+
+    /// Get the channel binding of our session with the given peer, if connected.
+    /// See [`wire::Binding`].
+    pub fn binding(&self, nid: NodeId) -> Result<Option<wire::Binding>, Error> {
+        let (sender, receiver) = chan::bounded(1);
+        self.controller.cmd(wire::Control::Binding(nid, sender))?;
+        receiver.recv().map_err(Error::from)
+    }
+
+    /// Relay data to a peer connected to the given bridge, which delivers it on our behalf.
+    /// Returns whether the data was queued. It may still be dropped by the bridge, eg. if
+    /// we're over its bandwidth limit.
+    pub fn relay(&self, via: NodeId, to: NodeId, data: Vec<u8>) -> Result<bool, Error> {
+        let (resp, receiver) = chan::bounded(1);
+        self.controller.cmd(wire::Control::Relay {
+            via,
+            to,
+            data,
+            resp,
+        })?;
+        receiver.recv().map_err(Error::from)
+    }
+
+    /// Subscribe to the data relayed to us by bridges. Data is dropped if the receiver
+    /// doesn't keep up.
+    pub fn relayed(&self) -> Result<chan::Receiver<wire::Delivered>, Error> {
+        let (resp, receiver) = chan::bounded(1);
+        self.controller.cmd(wire::Control::Relayed(resp))?;
+
+        receiver.recv().map_err(Error::from)
+    }
+
+    /// Wake the service thread up.
+    pub(crate) fn wake(&self) -> Result<(), io::Error> {
+        self.controller.cmd(wire::Control::Wake)
+    }
+
     pub fn flush(&mut self, remote: NodeId, stream: StreamId) -> Result<(), io::Error> {
         self.controller.cmd(wire::Control::Flush { remote, stream })
     }
 
+    /// Query the tips of references of a repository from a connected peer, without
+    /// fetching. See [`crate::worker::query`].
+    pub fn query_refs(
+        &self,
+        rid: RepoId,
+        nid: NodeId,
+        refs: Vec<git::RefString>,
+        timeout: time::Duration,
+    ) -> Result<Result<query::Tips, query::Error>, Error> {
+        let (sender, receiver) = chan::bounded(1);
+        self.command(service::Command::QueryRefs(rid, nid, refs, sender))?;
+        receiver.recv_timeout(timeout).map_err(|e| match e {
+            chan::RecvTimeoutError::Timeout => Error::Timeout,
+            chan::RecvTimeoutError::Disconnected => Error::ChannelDisconnected,
+        })
+    }
+
     pub(crate) fn command(&self, cmd: service::Command) -> Result<(), io::Error> {
         self.controller.cmd(wire::Control::User(cmd))
     }
@@ -205,12 +391,38 @@ impl radicle::node::Handle for Handle {
         receiver.recv().map_err(Error::from)
     }
 
+    fn reload(&mut self) -> Result<ConfigDiff, Self::Error> {
+        let Some(loader) = &self.config_loader else {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "configuration reload is not supported",
+            )));
+        };
+        let config = loader()?;
+        let (sender, receiver) = chan::bounded(1);
+        self.command(service::Command::Reload(Box::new(config.clone()), sender))?;
+
+        let diff = receiver.recv()?;
+        if diff.live.iter().any(|path| path == "log") {
+            radicle::logger::set_level(config.log.unwrap_or(log::Level::Info));
+        }
+        self.defaults.set(config.policy, config.scope);
+
+        Ok(diff)
+    }
+
     fn listen_addrs(&self) -> Result<Vec<net::SocketAddr>, Self::Error> {
         let (sender, receiver) = chan::bounded(1);
         self.command(service::Command::ListenAddrs(sender))?;
         receiver.recv().map_err(Error::from)
     }
 
+    fn announced_addrs(&self) -> Result<Vec<(Address, address::Label)>, Self::Error> {
+        let (sender, receiver) = chan::bounded(1);
+        self.command(service::Command::AnnouncedAddrs(sender))?;
+        receiver.recv().map_err(Error::from)
+    }
+
     fn fetch(
         &mut self,
         id: RepoId,
@@ -234,6 +446,293 @@ impl radicle::node::Handle for Handle {
         receiver.recv().map_err(Error::from)
     }
 
+    fn reset_backoff(&mut self, id: RepoId) -> Result<bool, Error> {
+        let (sender, receiver) = chan::bounded(1);
+        self.command(service::Command::ResetBackoff(id, sender))?;
+        receiver.recv().map_err(Error::from)
+    }
+
+    fn set_fetcher_policy(
+        &mut self,
+        id: RepoId,
+        nid: NodeId,
+        policy: Option<policy::Policy>,
+    ) -> Result<bool, Error> {
+        let (sender, receiver) = chan::bounded(1);
+        self.command(service::Command::SetFetcherPolicy(id, nid, policy, sender))?;
+        receiver.recv().map_err(Error::from)
+    }
+
+    fn fetchers(&self, id: RepoId) -> Result<Vec<policy::FetcherPolicy>, Error> {
+        let (sender, receiver) = chan::bounded(1);
+        self.command(service::Command::Fetchers(id, sender))?;
+        receiver.recv().map_err(Error::from)
+    }
+
+    fn uploads(&self, id: Option<RepoId>) -> Result<Vec<Upload>, Error> {
+        let (sender, receiver) = chan::bounded(1);
+        self.command(service::Command::Uploads(id, sender))?;
+        receiver.recv().map_err(Error::from)
+    }
+
+    fn repos(&self) -> Result<Vec<Repo>, Error> {
+        let (sender, receiver) = chan::bounded(1);
+        self.command(service::Command::Repos(sender))?;
+        receiver.recv().map_err(Error::from)
+    }
+
+    fn stats(&self, id: Option<RepoId>) -> Result<Vec<Stats>, Error> {
+        let (sender, receiver) = chan::bounded(1);
+        self.command(service::Command::Stats(id, sender))?;
+        let cached = receiver.recv()?;
+
+        match id {
+            // Repositories that were never fetched, eg. ones created locally, have no cached
+            // statistics. Like integrity checks, these are computed on the calling thread.
+            Some(rid) if cached.is_empty() => Ok(vec![stats::repository(&self.storage, rid)?]),
+            _ => Ok(cached),
+        }
+    }
+
+    fn verify(
+        &self,
+        id: Option<RepoId>,
+    ) -> Result<Box<dyn Iterator<Item = Result<verify::Report, Error>>>, Error> {
+        // Checks run on the calling thread, since they only read from storage.
+        let storage = self.storage.clone();
+        let rids = match id {
+            Some(rid) => vec![rid],
+            None => storage.repository_ids()?,
+        };
+        Ok(Box::new(
+            rids.into_iter()
+                .map(move |rid| Ok(verify::repository(&storage, rid))),
+        ))
+    }
+
+    fn repair(&mut self, id: RepoId) -> Result<verify::Repair, Error> {
+        let report = verify::repository(&self.storage, id);
+        let mut repair = verify::Repair::new(report.clone());
+
+        if report.is_ok() {
+            return Ok(repair);
+        }
+        let (seeds, _) = self.seeds(id)?.partition();
+        if seeds.is_empty() {
+            log::warn!(target: "node", "Unable to repair {id}: no connected seeds");
+            return Ok(repair);
+        }
+        let local = self.nid()?;
+        let repo = self.storage.repository(id)?;
+        // Our own namespace and those of the delegates are never removed, since we may
+        // not be able to get them back. If the delegates are unknown, nothing is removed.
+        let protected = repo
+            .delegates()
+            .map(|ds| ds.into_iter().map(PublicKey::from).collect::<BTreeSet<_>>())
+            .ok();
+        let remotes = report
+            .failures
+            .iter()
+            .filter_map(verify::Failure::remote)
+            .copied()
+            .collect::<BTreeSet<_>>();
+
+        if let Some(protected) = protected {
+            // N.b. the lock is released before fetching, since fetches share it.
+            let _guard = self.locks.write(id);
+
+            for remote in remotes {
+                if remote == local || protected.contains(&remote) {
+                    continue;
+                }
+                repo.remove_remote(&remote)?;
+                repair.removed.push(remote);
+            }
+        }
+
+        for seed in seeds.into_iter().take(MAX_REPAIR_SEEDS) {
+            match self.fetch(id, seed.nid, DEFAULT_TIMEOUT)? {
+                FetchResult::Success { .. } => repair.fetched.push(seed.nid),
+                FetchResult::Failed { reason, .. } => {
+                    log::warn!(target: "node", "Failed to fetch {id} from {} for repair: {reason}", seed.nid);
+                    continue;
+                }
+            }
+            repair.remaining = verify::repository(&self.storage, id).failures;
+
+            if repair.remaining.is_empty() {
+                break;
+            }
+        }
+        let remotes = repo
+            .remote_ids()
+            .map_err(radicle::storage::Error::from)?
+            .collect::<Result<BTreeSet<_>, _>>()
+            .map_err(radicle::storage::Error::from)?;
+
+        repair.lost = repair
+            .removed
+            .iter()
+            .filter(|r| !remotes.contains(r))
+            .copied()
+            .collect();
+        repair.fixed = report
+            .failures
+            .into_iter()
+            .filter(|f| !repair.remaining.contains(f))
+            .filter(|f| f.remote().map_or(true, |r| !repair.lost.contains(r)))
+            .collect();
+
+        Ok(repair)
+    }
+
+    fn export(&self, id: RepoId, path: PathBuf) -> Result<(), Error> {
+        bundle::export(&self.storage, id, &path).map_err(Error::from)
+    }
+
+    fn import(&mut self, path: PathBuf) -> Result<bundle::Imported, Error> {
+        let imported = bundle::import_locked(&self.storage, &path, |rid| self.locks.write(rid))?;
+        self.update_inventory(imported.rid)?;
+
+        Ok(imported)
+    }
+
+    fn init(&mut self, path: PathBuf, opts: InitOptions) -> Result<RepoId, Error> {
+        let repo = git::raw::Repository::open(&path).map_err(rad::InitError::from)?;
+        let name = match opts.name {
+            Some(name) => name,
+            None => {
+                // For bare repositories, eg. `acme.git`, the path is the repository itself.
+                let dir = repo.workdir().unwrap_or(repo.path());
+                dir.file_stem()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            }
+        };
+        let (rid, _, _) = rad::import(
+            &repo,
+            &name,
+            &opts.description,
+            opts.default_branch,
+            opts.visibility,
+            &self.signer,
+            &self.storage,
+        )?;
+        log::info!(target: "node", "Initialized {rid} from {}", path.display());
+
+        self.seed(rid, opts.scope)?;
+        self.update_inventory(rid)?;
+        self.announce_refs(rid)?;
+
+        Ok(rid)
+    }
+
+    fn tasks(&self) -> Result<Vec<Task>, Error> {
+        let (sender, receiver) = chan::bounded(1);
+        self.controller.cmd(wire::Control::Tasks(sender))?;
+        receiver.recv().map_err(Error::from)
+    }
+
+    fn abort(&mut self, id: TaskId) -> Result<bool, Error> {
+        let (sender, receiver) = chan::bounded(1);
+        self.controller.cmd(wire::Control::Abort(id, sender))?;
+        receiver.recv().map_err(Error::from)
+    }
+
+    fn peers(&self, features: Features) -> Result<Vec<NodeId>, Error> {
+        let (sender, receiver) = chan::bounded(1);
+        self.command(service::Command::Peers(features, sender))?;
+        receiver.recv().map_err(Error::from)
+    }
+
+    fn filter(&self) -> Result<FilterStatus, Error> {
+        let (sender, receiver) = chan::bounded(1);
+        self.command(service::Command::Filter(sender))?;
+        receiver.recv().map_err(Error::from)
+    }
+
+    fn self_check(&self, addr: Option<Address>, rid: Option<RepoId>) -> Result<SelfCheck, Error> {
+        // Like integrity checks, self-checks run on the calling thread.
+        let addr = match addr {
+            Some(addr) => addr,
+            None => {
+                let listen = self.listen_addrs()?;
+                let addr = listen.first().ok_or(selfcheck::Error::NotListening)?;
+
+                selfcheck::local(*addr)
+            }
+        };
+        let rid = match rid {
+            Some(rid) => rid,
+            None => {
+                // Request the smallest repository. Repositories without cached statistics,
+                // ie. that were never fetched, are only requested if there are no others.
+                let sizes = self
+                    .stats(None)?
+                    .into_iter()
+                    .map(|s| (s.rid, s.size()))
+                    .collect::<HashMap<_, _>>();
+
+                self.storage
+                    .public_repositories()?
+                    .min_by_key(|rid| sizes.get(rid).copied().unwrap_or(u64::MAX))
+                    .ok_or(selfcheck::Error::NoRepository)?
+            }
+        };
+        selfcheck::run(self.nid()?, addr, rid).map_err(Error::from)
+    }
+
+    fn health(&self) -> Result<NodeHealth, Error> {
+        let (sender, receiver) = chan::bounded(1);
+        let timer = time::Instant::now();
+        let service = self
+            .command(service::Command::Health(sender))
+            .map_err(|e| e.to_string())
+            .and_then(|()| {
+                receiver
+                    .recv_timeout(HEALTH_TIMEOUT)
+                    .map_err(|_| String::from("service is not responding"))
+            });
+        let latency = timer.elapsed();
+        let (workers, restarts) = match &self.pool {
+            Some(pool) => (pool.health(LocalTime::now()), pool.restarts()),
+            None => (HealthState::failed("worker pool is not running"), 0),
+        };
+
+        let health = match service {
+            Ok(service) => {
+                let reactor = if latency > MAX_REACTOR_LATENCY {
+                    HealthState::degraded(format!(
+                        "service took {} ms to respond",
+                        latency.as_millis()
+                    ))
+                } else {
+                    HealthState::Ok
+                };
+                NodeHealth::new(
+                    reactor,
+                    service.gossip,
+                    service.addresses,
+                    workers,
+                    service.maintenance,
+                )
+            }
+            Err(reason) => {
+                // The service subsystems can only be checked by the service.
+                let unknown = HealthState::failed("service is not responding");
+
+                NodeHealth::new(
+                    HealthState::failed(reason),
+                    unknown.clone(),
+                    unknown.clone(),
+                    workers,
+                    unknown,
+                )
+            }
+        };
+        Ok(health.with_worker_restarts(restarts))
+    }
+
     fn seed(&mut self, id: RepoId, scope: policy::Scope) -> Result<bool, Error> {
         let (sender, receiver) = chan::bounded(1);
         self.command(service::Command::Seed(id, scope, sender))?;
@@ -316,6 +815,12 @@ impl radicle::node::Handle for Handle {
             .and_then(|sock| Command::Shutdown.to_writer(sock))
             .ok();
 
+        // Let our peers know we're going away, before shutting down the reactor.
+        let (done, waiting) = chan::bounded(1);
+        if self.controller.cmd(wire::Control::Shutdown(done)).is_ok() {
+            waiting.recv_timeout(SHUTDOWN_NOTICE_TIMEOUT).ok();
+        }
+
         self.controller
             .shutdown()
             .map_err(|_| Error::ChannelDisconnected)
@@ -0,0 +1,252 @@
+//! Advisory locks on repositories.
+//!
+//! Tasks of the node that write to the storage of a repository hold a lock on it while
+//! doing so. Fetches share the lock, since they only add objects and update references,
+//! while maintenance tasks, eg. garbage collection or removing namespaces, and imports
+//! hold it exclusively.
+//!
+//! The locks are advisory: they are only taken by the node, and don't prevent other
+//! processes from accessing storage.
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+
+use radicle::identity::RepoId;
+
+/// State of the lock on a repository.
+#[derive(Debug, Default)]
+struct State {
+    /// Number of tasks sharing the lock.
+    readers: usize,
+    /// Whether the lock is held exclusively.
+    writer: bool,
+    /// Number of tasks waiting to hold the lock exclusively. Tasks waiting to share the
+    /// lock wait for them, so that maintenance isn't starved by frequent fetches.
+    waiting: usize,
+}
+
+impl State {
+    /// Whether the lock is neither held nor waited on.
+    fn is_idle(&self) -> bool {
+        self.readers == 0 && !self.writer && self.waiting == 0
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    repos: Mutex<HashMap<RepoId, State>>,
+    released: Condvar,
+}
+
+/// Reader/writer locks on repositories, shared by the tasks of the node.
+#[derive(Debug, Clone, Default)]
+pub struct Locks(Arc<Inner>);
+
+impl Locks {
+    /// Lock a repository for reading, eg. to apply fetched updates to it. Blocks while
+    /// the repository is locked for writing, or a task is waiting to do so.
+    pub fn read(&self, rid: RepoId) -> Guard {
+        let mut repos = self.repos();
+        loop {
+            let state = repos.entry(rid).or_default();
+            if !state.writer && state.waiting == 0 {
+                state.readers += 1;
+                break;
+            }
+            repos = self.wait(repos);
+        }
+        Guard::new(self.clone(), rid, false)
+    }
+
+    /// Lock a repository for writing, eg. to collect its garbage. Blocks while the
+    /// repository is locked.
+    pub fn write(&self, rid: RepoId) -> Guard {
+        let mut repos = self.repos();
+        repos.entry(rid).or_default().waiting += 1;
+
+        loop {
+            let state = repos.entry(rid).or_default();
+            if !state.writer && state.readers == 0 {
+                state.waiting -= 1;
+                state.writer = true;
+                break;
+            }
+            repos = self.wait(repos);
+        }
+        Guard::new(self.clone(), rid, true)
+    }
+
+    /// Lock a repository for writing, if it isn't locked or waited on.
+    pub fn try_write(&self, rid: RepoId) -> Option<Guard> {
+        let mut repos = self.repos();
+        let state = repos.entry(rid).or_default();
+        if !state.is_idle() {
+            return None;
+        }
+        state.writer = true;
+
+        Some(Guard::new(self.clone(), rid, true))
+    }
+
+    fn release(&self, rid: RepoId, exclusive: bool) {
+        let mut repos = self.repos();
+        if let Some(state) = repos.get_mut(&rid) {
+            if exclusive {
+                state.writer = false;
+            } else {
+                state.readers = state.readers.saturating_sub(1);
+            }
+            if state.is_idle() {
+                repos.remove(&rid);
+            }
+        }
+        drop(repos);
+
+        self.0.released.notify_all();
+    }
+
+    fn repos(&self) -> MutexGuard<'_, HashMap<RepoId, State>> {
+        self.0.repos.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn wait<'a>(
+        &self,
+        repos: MutexGuard<'a, HashMap<RepoId, State>>,
+    ) -> MutexGuard<'a, HashMap<RepoId, State>> {
+        self.0
+            .released
+            .wait(repos)
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A lock on a repository, released when dropped.
+#[derive(Debug)]
+#[must_use]
+pub struct Guard {
+    locks: Locks,
+    rid: RepoId,
+    exclusive: bool,
+}
+
+impl Guard {
+    fn new(locks: Locks, rid: RepoId, exclusive: bool) -> Self {
+        Self {
+            locks,
+            rid,
+            exclusive,
+        }
+    }
+
+    /// The locked repository.
+    pub fn rid(&self) -> RepoId {
+        self.rid
+    }
+
+    /// Whether the repository is locked for writing.
+    pub fn is_exclusive(&self) -> bool {
+        self.exclusive
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        self.locks.release(self.rid, self.exclusive);
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use std::thread;
+    use std::time::Duration;
+
+    use crossbeam_channel as chan;
+    use radicle::test::arbitrary;
+
+    use super::*;
+
+    /// How long to wait before deciding that a task is blocked.
+    const BLOCKED: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn test_read_shared() {
+        let locks = Locks::default();
+        let rid = arbitrary::gen::<RepoId>(1);
+        let other = arbitrary::gen::<RepoId>(2);
+
+        let first = locks.read(rid);
+        let second = locks.read(rid);
+        assert!(!first.is_exclusive());
+        assert!(locks.try_write(rid).is_none());
+        assert!(locks.try_write(other).is_some());
+
+        drop(first);
+        assert!(locks.try_write(rid).is_none());
+        drop(second);
+
+        let guard = locks.try_write(rid).unwrap();
+        assert!(guard.is_exclusive());
+        assert!(locks.try_write(rid).is_none());
+        drop(guard);
+
+        assert!(locks.repos().is_empty());
+    }
+
+    #[test]
+    fn test_write_blocks_read() {
+        let locks = Locks::default();
+        let rid = arbitrary::gen::<RepoId>(1);
+        let (send, recv) = chan::unbounded();
+
+        let guard = locks.write(rid);
+        let reader = thread::spawn({
+            let locks = locks.clone();
+            move || {
+                let _guard = locks.read(rid);
+                send.send(()).unwrap();
+            }
+        });
+        assert!(recv.recv_timeout(BLOCKED).is_err());
+
+        drop(guard);
+        recv.recv_timeout(Duration::from_secs(5)).unwrap();
+        reader.join().unwrap();
+    }
+
+    #[test]
+    fn test_waiting_writer_blocks_read() {
+        let locks = Locks::default();
+        let rid = arbitrary::gen::<RepoId>(1);
+        let (send, recv) = chan::unbounded();
+
+        let guard = locks.read(rid);
+        let writer = thread::spawn({
+            let (locks, send) = (locks.clone(), send.clone());
+            move || {
+                let _guard = locks.write(rid);
+                send.send("write").unwrap();
+                thread::sleep(BLOCKED);
+            }
+        });
+        while locks.repos().get(&rid).map_or(0, |s| s.waiting) == 0 {
+            thread::yield_now();
+        }
+        let reader = thread::spawn({
+            let locks = locks.clone();
+            move || {
+                let _guard = locks.read(rid);
+                send.send("read").unwrap();
+            }
+        });
+        assert!(recv.recv_timeout(BLOCKED).is_err());
+
+        // The writer waiting goes first, once the lock is released.
+        drop(guard);
+        assert_eq!(recv.recv_timeout(Duration::from_secs(5)).unwrap(), "write");
+        assert_eq!(recv.recv_timeout(Duration::from_secs(5)).unwrap(), "read");
+
+        writer.join().unwrap();
+        reader.join().unwrap();
+        assert!(locks.repos().is_empty());
+    }
+}
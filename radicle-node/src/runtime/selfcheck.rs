@@ -0,0 +1,364 @@
+//! Node self-check.
+//!
+//! Connects to a running node as if it were a remote peer, with an ephemeral key, to check
+//! that it is reachable and serves repositories, eg. after configuring a firewall or port
+//! forwarding. The client performs the handshake, subscribes to the gossip of a repository,
+//! and lists the repository's references with `git upload-pack`, which is how fetches start.
+//! Each step is timed.
+use std::io::{Read as _, Write as _};
+use std::net::ToSocketAddrs as _;
+use std::{fmt, io, net, time};
+
+use cyphernet::addr::HostName;
+use netservices::NetSession as _;
+use thiserror::Error;
+
+use radicle::crypto::ssh::keystore::MemorySigner;
+use radicle::node::{Address, SelfCheck};
+
+use crate::deserializer::Deserializer;
+use crate::identity::RepoId;
+use crate::node::NodeId;
+use crate::service::filter::Filter;
+use crate::service::message::{Ping, Subscribe};
+use crate::service::{Message, ZeroBytes};
+use crate::wire::frame::{self, Frame, FrameData, StreamId};
+use crate::wire::protocol::{self, WireSession};
+use crate::{Link, Timestamp};
+
+/// How long to wait for the node to respond, at each step.
+pub const RESPONSE_TIMEOUT: time::Duration = time::Duration::from_secs(9);
+
+/// A self-check error.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// The node has no listen address to dial.
+    #[error("the node is not listening for connections")]
+    NotListening,
+    /// There is no repository to request.
+    #[error("no public repository to request")]
+    NoRepository,
+    /// The address couldn't be resolved.
+    #[error("failed to resolve {0}: {1}")]
+    Resolve(Address, io::Error),
+    /// A step of the check failed.
+    #[error("{0} failed: {1}")]
+    Step(Step, io::Error),
+}
+
+/// A step of the self-check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// Establishing the TCP connection.
+    Connect,
+    /// Authenticating the node.
+    Handshake,
+    /// Subscribing to the node's gossip.
+    Subscribe,
+    /// Listing the references of a repository.
+    Upload,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Connect => write!(f, "connection"),
+            Self::Handshake => write!(f, "handshake"),
+            Self::Subscribe => write!(f, "subscription"),
+            Self::Upload => write!(f, "upload"),
+        }
+    }
+}
+
+/// The address to dial to reach a node listening on the given socket address. Sockets bound
+/// to all interfaces are reached on the loopback interface.
+pub fn local(addr: net::SocketAddr) -> Address {
+    let ip = match addr.ip() {
+        net::IpAddr::V4(ip) if ip.is_unspecified() => net::Ipv4Addr::LOCALHOST.into(),
+        net::IpAddr::V6(ip) if ip.is_unspecified() => net::Ipv6Addr::LOCALHOST.into(),
+        ip => ip,
+    };
+    net::SocketAddr::new(ip, addr.port()).into()
+}
+
+/// Run a self-check of the node `nid`, reachable at `addr`, requesting repository `rid`.
+pub fn run(nid: NodeId, addr: Address, rid: RepoId) -> Result<SelfCheck, Error> {
+    let socket_addr = resolve(&addr).map_err(|e| Error::Resolve(addr.clone(), e))?;
+
+    let timer = time::Instant::now();
+    let connection = connect(socket_addr).map_err(|e| Error::Step(Step::Connect, e))?;
+    let connect = timer.elapsed();
+
+    let timer = time::Instant::now();
+    let mut session = protocol::session(
+        (*addr).clone(),
+        Some(nid),
+        connection,
+        MemorySigner::gen(),
+        false,
+    );
+    session
+        .run_handshake()
+        .map_err(|e| Error::Step(Step::Handshake, e))?;
+    let handshake = timer.elapsed();
+
+    let mut client = Client::new(session);
+
+    let timer = time::Instant::now();
+    client
+        .subscribe(rid)
+        .map_err(|e| Error::Step(Step::Subscribe, e))?;
+    let subscribe = timer.elapsed();
+
+    let timer = time::Instant::now();
+    let refs = client
+        .ls_refs(rid)
+        .map_err(|e| Error::Step(Step::Upload, e))?;
+    let upload = timer.elapsed();
+
+    client.disconnect();
+
+    log::debug!(target: "node", "Self-check of {addr} completed with {refs} reference(s) listed for {rid}");
+
+    Ok(SelfCheck {
+        addr,
+        rid,
+        connect: connect.as_millis() as u64,
+        handshake: handshake.as_millis() as u64,
+        subscribe: subscribe.as_millis() as u64,
+        upload: upload.as_millis() as u64,
+        refs,
+    })
+}
+
+fn resolve(addr: &Address) -> io::Result<net::SocketAddr> {
+    match &addr.host {
+        HostName::Ip(ip) => Ok(net::SocketAddr::new(*ip, addr.port)),
+        HostName::Dns(name) => (name.as_str(), addr.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::ErrorKind::NotFound.into()),
+        _ => Err(io::ErrorKind::Unsupported.into()),
+    }
+}
+
+fn connect(addr: net::SocketAddr) -> io::Result<net::TcpStream> {
+    let connection = net::TcpStream::connect_timeout(&addr, protocol::DEFAULT_DIAL_TIMEOUT)?;
+    connection.set_read_timeout(Some(RESPONSE_TIMEOUT))?;
+    connection.set_write_timeout(Some(RESPONSE_TIMEOUT))?;
+
+    Ok(connection)
+}
+
+/// A packet-line.
+#[derive(Debug, PartialEq, Eq)]
+enum Packet {
+    /// Data packet, without its length prefix.
+    Data(Vec<u8>),
+    /// Delimiter packet.
+    Delim,
+    /// Flush packet.
+    Flush,
+}
+
+/// Client posing as a remote peer, on an established session.
+struct Client {
+    session: WireSession<MemorySigner>,
+    inbox: Deserializer<Frame>,
+    /// Data received on the Git stream that wasn't parsed yet.
+    git: Vec<u8>,
+}
+
+impl Client {
+    fn new(session: WireSession<MemorySigner>) -> Self {
+        Self {
+            session,
+            inbox: Deserializer::default(),
+            git: Vec::new(),
+        }
+    }
+
+    /// Subscribe to the gossip of the given repository, and wait for the node to process
+    /// the subscription. Since the node processes messages in order, this is the case once
+    /// it replies to a ping sent after the subscription.
+    fn subscribe(&mut self, rid: RepoId) -> io::Result<()> {
+        let subscribe = Subscribe {
+            filter: Filter::new([rid]),
+            since: Timestamp::MIN,
+            until: Timestamp::MAX,
+        };
+        let ping = Ping {
+            ponglen: 0,
+            zeroes: ZeroBytes::new(0),
+        };
+        self.send(Frame::gossip(Link::Outbound, Message::Subscribe(subscribe)))?;
+        self.send(Frame::gossip(Link::Outbound, Message::Ping(ping)))?;
+
+        loop {
+            if let FrameData::Gossip(Message::Pong { zeroes }) = self.recv()?.data {
+                if zeroes.is_empty() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// List the references of the given repository with `git upload-pack`, on a new Git
+    /// stream. Returns the number of references listed.
+    fn ls_refs(&mut self, rid: RepoId) -> io::Result<usize> {
+        let stream = StreamId::git(Link::Outbound);
+        let request = format!("git-upload-pack /{}\0\0version=2\0", rid.canonical());
+
+        self.send(Frame::control(
+            Link::Outbound,
+            frame::Control::Open { stream },
+        ))?;
+        self.send(Frame::git(stream, pktline(request.as_bytes())))?;
+
+        // The node first advertises the capabilities of `upload-pack`.
+        let capabilities = self.section(stream)?;
+        if capabilities.first() != Some(&Packet::Data(b"version 2\n".to_vec())) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected capability advertisement",
+            ));
+        }
+        let mut command = pktline(b"command=ls-refs\n");
+        command.extend_from_slice(b"0001");
+        command.extend_from_slice(b"0000");
+
+        self.send(Frame::git(stream, command))?;
+        let refs = self.section(stream)?;
+
+        // Signal the end of our requests, so that `upload-pack` exits.
+        self.send(Frame::control(
+            Link::Outbound,
+            frame::Control::Eof { stream },
+        ))?;
+
+        Ok(refs.len())
+    }
+
+    /// Read the packets received on the given Git stream, up to the next flush packet.
+    fn section(&mut self, stream: StreamId) -> io::Result<Vec<Packet>> {
+        let mut packets = Vec::new();
+
+        loop {
+            while let Some(packet) = self.packet()? {
+                if packet == Packet::Flush {
+                    return Ok(packets);
+                }
+                packets.push(packet);
+            }
+            match self.recv()? {
+                Frame {
+                    stream: s,
+                    data: FrameData::Git(data),
+                    ..
+                } if s == stream => {
+                    self.git.extend_from_slice(&data);
+                }
+                Frame {
+                    data:
+                        FrameData::Control(
+                            frame::Control::Close { stream: s } | frame::Control::Eof { stream: s },
+                        ),
+                    ..
+                } if s == stream => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::ConnectionReset,
+                        "stream closed by the node",
+                    ));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Parse the next packet received on the Git stream, if it was fully received.
+    fn packet(&mut self) -> io::Result<Option<Packet>> {
+        let Some(prefix) = self.git.get(..4) else {
+            return Ok(None);
+        };
+        let len = std::str::from_utf8(prefix)
+            .ok()
+            .and_then(|s| usize::from_str_radix(s, 16).ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid packet-line"))?;
+
+        let packet = match len {
+            0 => Packet::Flush,
+            1 => Packet::Delim,
+            2 | 3 => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "unexpected special packet-line",
+                ))
+            }
+            len if len > self.git.len() => return Ok(None),
+            len => Packet::Data(self.git[4..len].to_vec()),
+        };
+        let consumed = match packet {
+            Packet::Data(_) => len,
+            Packet::Delim | Packet::Flush => 4,
+        };
+        self.git.drain(..consumed);
+
+        Ok(Some(packet))
+    }
+
+    fn send(&mut self, frame: Frame) -> io::Result<()> {
+        self.session.write_all(&frame.to_bytes())?;
+        self.session.flush()
+    }
+
+    fn recv(&mut self) -> io::Result<Frame> {
+        let mut buffer = [0; u16::MAX as usize];
+
+        loop {
+            match self.inbox.deserialize_next() {
+                Ok(Some(frame)) => return Ok(frame),
+                Ok(None) => {}
+                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+            }
+            match self.session.read(&mut buffer)? {
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                n => self.inbox.input(&buffer[..n]),
+            }
+        }
+    }
+
+    fn disconnect(self) {
+        if let Err(e) = self.session.disconnect() {
+            log::debug!(target: "node", "Error disconnecting self-check session: {e}");
+        }
+    }
+}
+
+/// Encode the given data as a packet-line.
+fn pktline(data: &[u8]) -> Vec<u8> {
+    let mut line = format!("{:04x}", data.len() + 4).into_bytes();
+    line.extend_from_slice(data);
+    line
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_local() {
+        assert_eq!(
+            local(([0, 0, 0, 0], 8776).into()),
+            Address::from(net::SocketAddr::from(([127, 0, 0, 1], 8776)))
+        );
+        assert_eq!(
+            local((net::Ipv6Addr::UNSPECIFIED, 8776).into()),
+            Address::from(net::SocketAddr::from((net::Ipv6Addr::LOCALHOST, 8776)))
+        );
+        assert_eq!(
+            local(([192, 168, 1, 2], 8776).into()),
+            Address::from(net::SocketAddr::from(([192, 168, 1, 2], 8776)))
+        );
+    }
+}
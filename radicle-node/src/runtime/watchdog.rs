@@ -0,0 +1,408 @@
+//! Runtime watchdog.
+//!
+//! Watches the service thread, which runs the reactor and the service state machine, and
+//! the worker pool for stalls, ie. no progress being made within the configured timeout.
+//! When a stall is detected, a diagnostic dump is logged, and optionally, the node exits
+//! so that it can be restarted by its supervisor. Threads can't be restarted individually,
+//! since the component state they hold isn't recoverable.
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::{process, thread, time};
+
+use crossbeam_channel as chan;
+use localtime::{LocalDuration, LocalTime};
+
+use radicle::node::{config, HealthState};
+
+use crate::runtime::Handle;
+use crate::service::io::SignRequest;
+use crate::worker;
+
+/// Exit code used when the node is stopped by the watchdog.
+pub const EXIT_CODE: i32 = 70;
+
+/// Progress of a component, shared with the watchdog.
+#[derive(Debug)]
+pub struct Progress {
+    /// Time of the last progress, in milliseconds since the epoch.
+    last: AtomicU64,
+    /// What the component is, or was last doing, and since when.
+    activity: Mutex<Option<(Cow<'static, str>, LocalTime)>>,
+    /// Number of times the component recovered from a panic.
+    restarts: AtomicU64,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self {
+            last: AtomicU64::new(LocalTime::now().as_millis()),
+            activity: Mutex::new(None),
+            restarts: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Progress {
+    /// Record progress.
+    pub fn beat(&self) {
+        self.last
+            .store(LocalTime::now().as_millis(), Ordering::Relaxed);
+    }
+
+    /// Record progress, and what the component is starting to do.
+    pub fn start(&self, activity: impl Into<Cow<'static, str>>) {
+        let now = LocalTime::now();
+
+        self.last.store(now.as_millis(), Ordering::Relaxed);
+        if let Ok(mut a) = self.activity.lock() {
+            *a = Some((activity.into(), now));
+        }
+    }
+
+    /// Record progress, and that the component is idle.
+    pub fn finish(&self) {
+        self.beat();
+
+        if let Ok(mut a) = self.activity.lock() {
+            *a = None;
+        }
+    }
+
+    /// Record that the component recovered from a panic.
+    pub fn restarted(&self) {
+        self.restarts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Number of times the component recovered from a panic.
+    pub fn restarts(&self) -> u64 {
+        self.restarts.load(Ordering::Relaxed)
+    }
+
+    /// Time of the last progress.
+    pub fn last(&self) -> LocalTime {
+        LocalTime::from_millis(self.last.load(Ordering::Relaxed) as u128)
+    }
+
+    /// What the component is, or was last doing, and since when.
+    pub fn activity(&self) -> Option<(String, LocalTime)> {
+        self.activity
+            .lock()
+            .ok()
+            .and_then(|a| a.as_ref().map(|(a, t)| (a.to_string(), *t)))
+    }
+}
+
+/// A stalled component.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stall {
+    /// The service thread hasn't made progress for the given duration.
+    Service { idle: LocalDuration },
+    /// Tasks are queued, but no worker picked up or finished a task for the given duration.
+    Workers { queued: usize, idle: LocalDuration },
+}
+
+impl std::fmt::Display for Stall {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Service { idle } => write!(f, "service thread made no progress in {idle}"),
+            Self::Workers { queued, idle } => write!(
+                f,
+                "worker pool made no progress in {idle} with {queued} task(s) queued"
+            ),
+        }
+    }
+}
+
+/// The worker pool, as watched to report its health.
+#[derive(Debug, Clone)]
+pub struct Pool {
+    timeout: LocalDuration,
+    workers: Vec<Arc<Progress>>,
+    tasks: chan::Receiver<worker::Task>,
+}
+
+impl Pool {
+    /// Watch the given workers, and their task queue.
+    pub fn new(
+        timeout: LocalDuration,
+        workers: Vec<Arc<Progress>>,
+        tasks: chan::Receiver<worker::Task>,
+    ) -> Self {
+        Self {
+            timeout,
+            workers,
+            tasks,
+        }
+    }
+
+    /// Health of the worker pool.
+    pub fn health(&self, now: LocalTime) -> HealthState {
+        health(self.timeout, &self.workers, self.tasks.len(), now)
+    }
+
+    /// Number of times workers recovered from a panic.
+    pub fn restarts(&self) -> u64 {
+        self.workers.iter().map(|w| w.restarts()).sum()
+    }
+}
+
+/// Watches the runtime components for stalls.
+pub struct Watchdog {
+    config: config::Watchdog,
+    handle: Handle,
+    service: Arc<Progress>,
+    workers: Vec<Arc<Progress>>,
+    tasks: chan::Receiver<worker::Task>,
+    signing: chan::Receiver<SignRequest>,
+}
+
+impl Watchdog {
+    /// Create a new watchdog.
+    pub fn new(
+        config: config::Watchdog,
+        handle: Handle,
+        service: Arc<Progress>,
+        workers: Vec<Arc<Progress>>,
+        tasks: chan::Receiver<worker::Task>,
+        signing: chan::Receiver<SignRequest>,
+    ) -> Self {
+        Self {
+            config,
+            handle,
+            service,
+            workers,
+            tasks,
+            signing,
+        }
+    }
+
+    /// Watch the runtime until the service shuts down.
+    pub fn run(self) {
+        let interval = time::Duration::from_millis(
+            (self.config.timeout.as_millis() / 4).clamp(1000, 60 * 1000) as u64,
+        );
+        let mut stalled = false;
+
+        log::debug!(target: "watchdog", "Watching runtime with a timeout of {}..", self.config.timeout);
+
+        loop {
+            // Make sure the service thread has something to do, so that it only lacks
+            // progress if it's stuck.
+            if self.handle.wake().is_err() {
+                break;
+            }
+            thread::sleep(interval);
+
+            let stalls = check(
+                self.config.timeout,
+                &self.service,
+                &self.workers,
+                self.tasks.len(),
+                LocalTime::now(),
+            );
+            if stalls.is_empty() {
+                if stalled {
+                    log::info!(target: "watchdog", "Runtime is making progress again");
+                }
+                stalled = false;
+                continue;
+            }
+            // Only dump the state once per stall, to not flood the logs.
+            if !stalled {
+                for stall in &stalls {
+                    log::error!(target: "watchdog", "Stall detected: {stall}");
+                }
+                self.dump(LocalTime::now());
+            }
+            stalled = true;
+
+            if self.config.exit {
+                log::error!(target: "watchdog", "Exiting, so that the node can be restarted..");
+                process::exit(EXIT_CODE);
+            }
+        }
+        log::debug!(target: "watchdog", "Watchdog exiting..");
+    }
+
+    /// Log the state of the runtime components.
+    fn dump(&self, now: LocalTime) {
+        let service = match self.service.activity() {
+            Some((activity, since)) => format!("{activity} ({} ago)", now - since),
+            None => String::from("idle"),
+        };
+        log::error!(target: "watchdog", "Service: last progress {} ago, last activity: {service}", now - self.service.last());
+        log::error!(
+            target: "watchdog",
+            "Queues: {} worker task(s), {} signing request(s)",
+            self.tasks.len(),
+            self.signing.len()
+        );
+        for (i, worker) in self.workers.iter().enumerate() {
+            let restarts = worker.restarts();
+            match worker.activity() {
+                Some((activity, since)) => {
+                    log::error!(target: "watchdog", "Worker#{i}: {activity} (running for {}, {restarts} restart(s))", now - since)
+                }
+                None => log::error!(target: "watchdog", "Worker#{i}: idle ({restarts} restart(s))"),
+            }
+        }
+    }
+}
+
+/// Check the components for stalls, given the number of queued worker tasks.
+fn check(
+    timeout: LocalDuration,
+    service: &Progress,
+    workers: &[Arc<Progress>],
+    queued: usize,
+    now: LocalTime,
+) -> Vec<Stall> {
+    let mut stalls = Vec::new();
+
+    let idle = now - service.last();
+    if idle > timeout {
+        stalls.push(Stall::Service { idle });
+    }
+    if let Some(stall) = check_workers(timeout, workers, queued, now) {
+        stalls.push(stall);
+    }
+    stalls
+}
+
+/// Check the worker pool for a stall, given the number of queued tasks.
+fn check_workers(
+    timeout: LocalDuration,
+    workers: &[Arc<Progress>],
+    queued: usize,
+    now: LocalTime,
+) -> Option<Stall> {
+    // Long-running tasks aren't a stall as long as no other task is waiting on them.
+    if queued == 0 {
+        return None;
+    }
+    let last = workers.iter().map(|w| w.last()).max().unwrap_or_default();
+    let idle = now - last;
+
+    (idle > timeout).then_some(Stall::Workers { queued, idle })
+}
+
+/// Health of the worker pool, given the number of queued tasks. The pool is degraded when
+/// tasks are waiting on busy workers, and has failed when it's stalled.
+fn health(
+    timeout: LocalDuration,
+    workers: &[Arc<Progress>],
+    queued: usize,
+    now: LocalTime,
+) -> HealthState {
+    if let Some(stall) = check_workers(timeout, workers, queued, now) {
+        return HealthState::failed(stall.to_string());
+    }
+    let busy = workers.iter().filter(|w| w.activity().is_some()).count();
+
+    if queued > 0 && busy == workers.len() {
+        HealthState::degraded(format!(
+            "all {busy} worker(s) are busy, with {queued} task(s) queued"
+        ))
+    } else {
+        HealthState::Ok
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_progress() {
+        let progress = Progress::default();
+        assert!(progress.activity().is_none());
+
+        progress.start("fetch");
+        let (activity, since) = progress.activity().unwrap();
+        assert_eq!(activity, "fetch");
+        assert!(since >= progress.last() - LocalDuration::from_secs(1));
+
+        progress.finish();
+        assert!(progress.activity().is_none());
+
+        assert_eq!(progress.restarts(), 0);
+        progress.restarted();
+        assert_eq!(progress.restarts(), 1);
+    }
+
+    #[test]
+    fn test_check() {
+        let timeout = LocalDuration::from_secs(60);
+        let progress = |secs: u64| {
+            Arc::new(Progress {
+                last: AtomicU64::new(secs * 1000),
+                ..Progress::default()
+            })
+        };
+        let service = progress(100);
+        let workers = vec![progress(10), progress(50)];
+
+        assert_eq!(
+            check(timeout, &service, &workers, 0, LocalTime::from_secs(120)),
+            vec![]
+        );
+        // Queued tasks with no worker progress within the timeout.
+        assert_eq!(
+            check(timeout, &service, &workers, 2, LocalTime::from_secs(120)),
+            vec![Stall::Workers {
+                queued: 2,
+                idle: LocalDuration::from_secs(70)
+            }]
+        );
+        // Busy workers with an empty queue aren't stalled.
+        assert_eq!(
+            check(timeout, &service, &workers, 0, LocalTime::from_secs(200)),
+            vec![Stall::Service {
+                idle: LocalDuration::from_secs(100)
+            }]
+        );
+        workers[0].beat();
+        assert_eq!(
+            check(timeout, &service, &workers, 2, LocalTime::from_secs(200)),
+            vec![Stall::Service {
+                idle: LocalDuration::from_secs(100)
+            }]
+        );
+    }
+
+    #[test]
+    fn test_health() {
+        let timeout = LocalDuration::from_secs(60);
+        let progress = |secs: u64| {
+            Arc::new(Progress {
+                last: AtomicU64::new(secs * 1000),
+                ..Progress::default()
+            })
+        };
+        let workers = vec![progress(10), progress(50)];
+        let now = LocalTime::from_secs(100);
+
+        assert_eq!(health(timeout, &workers, 0, now), HealthState::Ok);
+        // Tasks are queued while a worker is idle.
+        workers[0].start("fetch");
+        assert_eq!(health(timeout, &workers, 1, now), HealthState::Ok);
+        // Tasks are queued on busy workers.
+        workers[1].start("fetch");
+        assert_eq!(
+            health(timeout, &workers, 2, LocalTime::now()),
+            HealthState::degraded("all 2 worker(s) are busy, with 2 task(s) queued")
+        );
+        // No progress within the timeout.
+        assert!(matches!(
+            health(
+                timeout,
+                &workers,
+                2,
+                LocalTime::now() + LocalDuration::from_secs(70)
+            ),
+            HealthState::Failed { .. }
+        ));
+    }
+}
@@ -0,0 +1,527 @@
+//! Webhook notifications.
+//!
+//! Node events are posted as JSON to the configured endpoints, so that external services can
+//! react to them without talking to the control socket. Requests are signed with the
+//! endpoint's secret, if any, and failed deliveries are retried with an exponential backoff.
+//! Events are posted from their own thread, so that slow endpoints don't hold up the node.
+//! Deliveries waiting to be posted or retried are capped, so that an endpoint that's down
+//! doesn't make the node's memory grow; the oldest are dropped first.
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel as chan;
+use hmac::{Hmac, Mac as _};
+use sha2::Sha256;
+
+use radicle::node::config;
+use radicle::node::events::Events;
+
+use crate::service::Event;
+
+/// Delay before the first retry of a failed delivery. Doubles with every attempt.
+pub const RETRY_DELAY: Duration = Duration::from_secs(5);
+/// Maximum delay between retries.
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(10 * 60);
+/// Number of attempts after which a delivery is given up on.
+pub const MAX_ATTEMPTS: u32 = 8;
+/// How long a single request may take.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Maximum number of deliveries waiting for their first attempt.
+pub const MAX_QUEUED: usize = 1024;
+/// Maximum number of deliveries waiting to be retried.
+pub const MAX_RETRIES: usize = 1024;
+/// Header carrying the event type.
+pub const EVENT_HEADER: &str = "X-Radicle-Event";
+/// Header carrying the request body signature.
+pub const SIGNATURE_HEADER: &str = "X-Radicle-Signature";
+
+/// Webhook error.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("endpoint responded with status {0}")]
+    Status(u16),
+    #[error("request failed: {0}")]
+    Transport(#[from] Box<ureq::Transport>),
+}
+
+impl Error {
+    /// Whether the delivery may succeed if retried. Requests refused by the endpoint aren't
+    /// retried, unless it's asking us to slow down.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Status(status) => *status >= 500 || *status == 429,
+            Self::Transport(_) => true,
+        }
+    }
+}
+
+/// An event to be delivered to an endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Delivery {
+    /// Index of the endpoint.
+    ix: usize,
+    /// Event type.
+    event: String,
+    /// Request body.
+    body: Vec<u8>,
+    /// Number of attempts so far.
+    attempts: u32,
+    /// When to retry.
+    at: Instant,
+}
+
+/// Delay before retrying a delivery that failed the given number of times.
+fn backoff(delay: Duration, attempts: u32) -> Duration {
+    delay
+        .saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+        .min(MAX_RETRY_DELAY)
+}
+
+/// Sign a request body with the given secret. Returns the value of the signature header.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    #[allow(clippy::unwrap_used)] // HMAC accepts keys of any size.
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body);
+
+    let mut signature = String::from("sha256=");
+    for b in mac.finalize().into_bytes() {
+        // Writing to a string can't fail.
+        write!(signature, "{b:02x}").ok();
+    }
+    signature
+}
+
+/// Posts node events to webhook endpoints.
+pub struct Webhooks {
+    webhooks: Vec<config::Webhook>,
+    events: Events,
+    agent: ureq::Agent,
+    /// Deliveries waiting for their first attempt, oldest first.
+    queue: VecDeque<Delivery>,
+    /// Deliveries to retry, oldest first.
+    retries: VecDeque<Delivery>,
+    /// Delay before the first retry.
+    retry_delay: Duration,
+}
+
+impl Webhooks {
+    /// Create a new webhook dispatcher. Endpoints that would send events in the clear over
+    /// the network are ignored.
+    pub fn new(webhooks: Vec<config::Webhook>, events: Events) -> Self {
+        let webhooks = webhooks
+            .into_iter()
+            .filter(|w| {
+                if !w.is_secure() {
+                    log::error!(target: "webhooks", "Ignoring webhook {}: only `https` endpoints are supported", w.url);
+                }
+                w.is_secure()
+            })
+            .collect();
+        let agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build();
+
+        Self {
+            webhooks,
+            events,
+            agent,
+            queue: VecDeque::new(),
+            retries: VecDeque::new(),
+            retry_delay: RETRY_DELAY,
+        }
+    }
+
+    /// Use the given delay before the first retry of a failed delivery.
+    pub fn with_retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Post events until the event stream ends. Each iteration takes in the events received
+    /// since the last one, retries the deliveries that are due, and attempts the oldest
+    /// queued delivery. Queued deliveries are still attempted once the stream ended.
+    pub fn run(mut self) {
+        let mut connected = true;
+
+        while connected || !self.queue.is_empty() {
+            if connected {
+                connected = self.receive();
+            }
+            let now = Instant::now();
+            while self.retries.front().is_some_and(|r| r.at <= now) {
+                if let Some(delivery) = self.retries.pop_front() {
+                    self.deliver(delivery);
+                }
+            }
+            if let Some(delivery) = self.queue.pop_front() {
+                self.deliver(delivery);
+            }
+        }
+        log::debug!(target: "webhooks", "Webhook dispatcher shutting down..");
+    }
+
+    /// Take in received events, waiting for one if there's nothing to post until then.
+    /// Returns `false` if the event stream ended.
+    fn receive(&mut self) -> bool {
+        let timeout = if self.queue.is_empty() {
+            self.retries
+                .front()
+                .map(|r| r.at.saturating_duration_since(Instant::now()))
+        } else {
+            Some(Duration::ZERO)
+        };
+        let event = match timeout {
+            Some(timeout) => self.events.recv_timeout(timeout),
+            None => self
+                .events
+                .recv()
+                .map_err(|_| chan::RecvTimeoutError::Disconnected),
+        };
+        match event {
+            Ok(event) => self.dispatch(&event),
+            Err(chan::RecvTimeoutError::Timeout) => return true,
+            Err(chan::RecvTimeoutError::Disconnected) => return false,
+        }
+        // Drain the stream, so that events don't pile up in the channel while we post.
+        loop {
+            match self.events.try_recv() {
+                Ok(event) => self.dispatch(&event),
+                Err(chan::TryRecvError::Empty) => return true,
+                Err(chan::TryRecvError::Disconnected) => return false,
+            }
+        }
+    }
+
+    /// Queue an event for the endpoints that accept it.
+    fn dispatch(&mut self, event: &Event) {
+        let body = match serde_json::to_vec(event) {
+            Ok(body) => body,
+            Err(e) => {
+                log::error!(target: "webhooks", "Failed to serialize event: {e}");
+                return;
+            }
+        };
+        let Some(kind) = event_type(&body) else {
+            return;
+        };
+        for ix in 0..self.webhooks.len() {
+            if self.webhooks[ix].accepts(&kind) {
+                if self.queue.len() >= MAX_QUEUED {
+                    if let Some(dropped) = self.queue.pop_front() {
+                        log::warn!(
+                            target: "webhooks",
+                            "Dropping `{}` event for {}: too many events queued",
+                            dropped.event,
+                            self.webhooks[dropped.ix].url
+                        );
+                    }
+                }
+                self.queue.push_back(Delivery {
+                    ix,
+                    event: kind.clone(),
+                    body: body.clone(),
+                    attempts: 0,
+                    at: Instant::now(),
+                });
+            }
+        }
+    }
+
+    /// Schedule a failed delivery to be retried. Retries are kept in the order they're due; if
+    /// too many are pending, the one due first, which is usually the oldest, is dropped.
+    fn retry(&mut self, delivery: Delivery) {
+        if self.retries.len() >= MAX_RETRIES {
+            if let Some(dropped) = self.retries.pop_front() {
+                log::warn!(
+                    target: "webhooks",
+                    "Dropping retry of `{}` event for {}: too many retries pending",
+                    dropped.event,
+                    self.webhooks[dropped.ix].url
+                );
+            }
+        }
+        let ix = self.retries.partition_point(|r| r.at <= delivery.at);
+        self.retries.insert(ix, delivery);
+    }
+
+    /// Attempt a delivery, scheduling a retry on transient failures.
+    fn deliver(&mut self, mut delivery: Delivery) {
+        let webhook = &self.webhooks[delivery.ix];
+
+        delivery.attempts += 1;
+
+        match post(&self.agent, webhook, &delivery.event, &delivery.body) {
+            Ok(()) => {
+                log::debug!(target: "webhooks", "Posted `{}` event to {}", delivery.event, webhook.url);
+            }
+            Err(e) if e.is_transient() && delivery.attempts < MAX_ATTEMPTS => {
+                let delay = backoff(self.retry_delay, delivery.attempts);
+                log::warn!(
+                    target: "webhooks",
+                    "Failed to post `{}` event to {}, retrying in {}ms: {e}",
+                    delivery.event,
+                    webhook.url,
+                    delay.as_millis()
+                );
+                delivery.at = Instant::now() + delay;
+                self.retry(delivery);
+            }
+            Err(e) => {
+                log::error!(
+                    target: "webhooks",
+                    "Failed to post `{}` event to {} after {} attempt(s), giving up: {e}",
+                    delivery.event,
+                    webhook.url,
+                    delivery.attempts
+                );
+            }
+        }
+    }
+}
+
+/// Get the type of a serialized event.
+fn event_type(body: &[u8]) -> Option<String> {
+    #[derive(serde::Deserialize)]
+    struct Tagged {
+        #[serde(rename = "type")]
+        kind: String,
+    }
+    serde_json::from_slice::<Tagged>(body).ok().map(|t| t.kind)
+}
+
+/// Post an event to an endpoint.
+fn post(
+    agent: &ureq::Agent,
+    webhook: &config::Webhook,
+    event: &str,
+    body: &[u8],
+) -> Result<(), Error> {
+    let mut request = agent
+        .post(&webhook.url)
+        .set("Content-Type", "application/json")
+        .set(EVENT_HEADER, event);
+
+    if let Some(secret) = &webhook.secret {
+        request = request.set(SIGNATURE_HEADER, &sign(secret, body));
+    }
+    match request.send_bytes(body) {
+        Ok(_) => Ok(()),
+        Err(ureq::Error::Status(status, _)) => Err(Error::Status(status)),
+        Err(ureq::Error::Transport(e)) => Err(Error::Transport(Box::new(e))),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use std::io::{BufRead as _, BufReader, Read as _, Write as _};
+    use std::{net, thread};
+
+    use super::*;
+    use crate::test::arbitrary;
+
+    /// A request received by the test server.
+    struct Request {
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    impl Request {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str())
+        }
+    }
+
+    /// Serve one request per given status code, and return the requests received.
+    fn serve(statuses: Vec<u16>) -> (String, thread::JoinHandle<Vec<Request>>) {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut headers = Vec::new();
+                let mut line = String::new();
+
+                reader.read_line(&mut line).unwrap(); // Request line.
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    let Some((k, v)) = line.trim_end().split_once(": ") else {
+                        break;
+                    };
+                    headers.push((k.to_owned(), v.to_owned()));
+                }
+                let len = headers
+                    .iter()
+                    .find(|(k, _)| k.eq_ignore_ascii_case("content-length"))
+                    .map(|(_, v)| v.parse().unwrap())
+                    .unwrap_or(0);
+                let mut body = vec![0; len];
+                reader.read_exact(&mut body).unwrap();
+                reader
+                    .get_mut()
+                    .write_all(
+                        format!("HTTP/1.1 {status} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").as_bytes(),
+                    )
+                    .unwrap();
+
+                requests.push(Request { headers, body });
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(RETRY_DELAY, 1), RETRY_DELAY);
+        assert_eq!(backoff(RETRY_DELAY, 2), RETRY_DELAY * 2);
+        assert_eq!(backoff(RETRY_DELAY, MAX_ATTEMPTS * 4), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn test_sign() {
+        // Test vector from RFC 4231, test case 2.
+        assert_eq!(
+            sign("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_deliver() {
+        let (url, server) = serve(vec![503, 200]);
+        let (send, recv) = chan::unbounded();
+        let webhooks = Webhooks::new(
+            vec![config::Webhook {
+                url,
+                secret: Some(String::from("secret")),
+                events: vec![String::from("peerConnected")],
+            }],
+            Events::from(recv),
+        )
+        .with_retry_delay(Duration::from_millis(10));
+        let nid = arbitrary::gen(1);
+
+        // Not selected, so not posted.
+        send.send(Event::SeedDiscovered {
+            rid: arbitrary::gen(1),
+            nid,
+        })
+        .unwrap();
+        send.send(Event::PeerConnected { nid }).unwrap();
+
+        let dispatcher = thread::spawn(move || webhooks.run());
+        let requests = server.join().unwrap();
+        drop(send);
+        dispatcher.join().unwrap();
+
+        // The first delivery failed, and was retried.
+        assert_eq!(requests.len(), 2);
+        for request in requests {
+            let json: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+
+            assert_eq!(json["type"], "peerConnected");
+            assert_eq!(json["nid"], nid.to_string());
+            assert_eq!(request.header(EVENT_HEADER), Some("peerConnected"));
+            assert_eq!(
+                request.header(SIGNATURE_HEADER),
+                Some(sign("secret", &request.body).as_str())
+            );
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let (_, recv) = chan::unbounded();
+        let mut webhooks = Webhooks::new(
+            vec![config::Webhook {
+                url: String::from("https://localhost/hook"),
+                secret: None,
+                events: vec![],
+            }],
+            Events::from(recv),
+        );
+        let event = |nid| Event::PeerConnected { nid };
+        let first = arbitrary::gen(1);
+
+        webhooks.dispatch(&event(first));
+        for _ in 0..MAX_QUEUED {
+            webhooks.dispatch(&event(arbitrary::gen(1)));
+        }
+        // The oldest event was dropped.
+        assert_eq!(webhooks.queue.len(), MAX_QUEUED);
+        assert!(webhooks
+            .queue
+            .iter()
+            .all(|d| d.body != serde_json::to_vec(&event(first)).unwrap()));
+
+        let now = Instant::now();
+        // Retries scheduled later are due first.
+        for (i, delivery) in webhooks
+            .queue
+            .drain(..)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .enumerate()
+        {
+            webhooks.retry(Delivery {
+                at: now + Duration::from_secs((MAX_QUEUED - i) as u64),
+                ..delivery
+            });
+        }
+        let delivery = webhooks.retries[0].clone();
+        webhooks.retry(Delivery {
+            at: now,
+            ..delivery.clone()
+        });
+        // The retry due first was dropped, and retries are kept in the order they're due.
+        assert_eq!(webhooks.retries.len(), MAX_RETRIES);
+        assert_eq!(webhooks.retries[0].at, now);
+        assert!(webhooks
+            .retries
+            .iter()
+            .zip(webhooks.retries.iter().skip(1))
+            .all(|(a, b)| a.at <= b.at));
+    }
+
+    #[test]
+    fn test_retry_steady_stream() {
+        let (url, server) = serve(vec![503; 32]);
+        let (send, recv) = chan::unbounded();
+        let webhooks = Webhooks::new(
+            vec![config::Webhook {
+                url,
+                secret: None,
+                events: vec![],
+            }],
+            Events::from(recv),
+        )
+        .with_retry_delay(Duration::from_millis(5));
+        let dispatcher = thread::spawn(move || webhooks.run());
+
+        // Events keep arriving more often than retries are due, until the endpoint is done.
+        while !server.is_finished() {
+            send.send(Event::PeerConnected {
+                nid: arbitrary::gen(1),
+            })
+            .unwrap();
+            thread::sleep(Duration::from_millis(1));
+        }
+        let requests = server.join().unwrap();
+        drop(send);
+        dispatcher.join().unwrap();
+
+        // Failed deliveries were retried while events kept arriving.
+        let retried = requests
+            .iter()
+            .enumerate()
+            .filter(|(i, r)| requests[..*i].iter().any(|p| p.body == r.body))
+            .count();
+        assert!(retried > 0);
+    }
+}
@@ -2,18 +2,27 @@
 #![allow(clippy::collapsible_match)]
 #![allow(clippy::collapsible_if)]
 #![warn(clippy::unwrap_used)]
+pub mod announcer;
+pub mod clock;
+pub mod dialer;
 pub mod filter;
 pub mod gossip;
+pub mod heartbeat;
+pub mod inventory;
 pub mod io;
 pub mod limitter;
 pub mod message;
+pub mod observed;
+pub mod replication;
+pub mod scheduler;
 pub mod session;
+pub mod snapshot;
 
 use std::collections::hash_map::Entry;
-use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
-use std::{fmt, net, time};
+use std::{cmp, fmt, net, time};
 
 use crossbeam_channel as chan;
 use fastrand::Rng;
@@ -21,18 +30,30 @@ use localtime::{LocalDuration, LocalTime};
 use log::*;
 use nonempty::NonEmpty;
 
+use radicle::git::{Oid, RefString};
 use radicle::node;
 use radicle::node::address;
 use radicle::node::address::Store as _;
 use radicle::node::address::{AddressBook, KnownAddress};
-use radicle::node::config::PeerConfig;
+use radicle::node::autoseed;
+use radicle::node::autoseed::Store as _;
+use radicle::node::config::{ConfigDiff, PeerConfig};
+use radicle::node::failures;
+use radicle::node::failures::Store as _;
 use radicle::node::refs::Store as _;
+use radicle::node::repos;
+use radicle::node::repos::Store as _;
 use radicle::node::routing::Store as _;
 use radicle::node::seed;
 use radicle::node::seed::Store as _;
-use radicle::node::{ConnectOptions, Penalty, Severity};
-use radicle::storage::refs::SIGREFS_BRANCH;
-use radicle::storage::{Inventory, RepositoryError};
+use radicle::node::stats;
+use radicle::node::stats::Store as _;
+use radicle::node::uploads;
+use radicle::node::uploads::Store as _;
+use radicle::node::{ConnectOptions, ErrorKind, HealthState, Penalty, Severity};
+use radicle::storage::refs;
+use radicle::storage::refs::{SignedRefs, SIGREFS_BRANCH};
+use radicle::storage::{Inventory, ReadRepository as _, RemoteRepository as _, RepositoryError};
 
 use crate::crypto;
 use crate::crypto::{Signer, Verified};
@@ -46,35 +67,51 @@ use crate::prelude::*;
 use crate::runtime::Emitter;
 use crate::service::gossip::Store as _;
 use crate::service::message::{
-    Announcement, AnnouncementMessage, Info, NodeAnnouncement, Ping, RefsAnnouncement, RefsStatus,
+    Announcement, AnnouncementMessage, ConnectTo, Info, NodeAnnouncement, Ping, RefsAnnouncement,
+    RefsStatus, Rendezvous,
 };
 use crate::service::policy::{store::Write, Policy, Scope};
 use crate::storage;
 use crate::storage::{refs::RefsAt, Namespaces, ReadStorage};
 use crate::worker::fetch;
-use crate::worker::FetchError;
+use crate::worker::query;
+use crate::worker::{FetchError, UploadError};
 use crate::Link;
 
 pub use crate::node::events::{Event, Events};
 pub use crate::node::{config::Network, Config, NodeId};
-pub use crate::service::message::{Message, ZeroBytes};
+pub use crate::service::message::{DisconnectCode, Message, ZeroBytes};
 pub use crate::service::session::Session;
 
 pub use radicle::node::policy::config as policy;
 
-use self::io::Outbox;
-use self::limitter::RateLimiter;
+use self::announcer::Announcer;
+use self::clock::Clock;
+use self::dialer::Dialer;
+use self::heartbeat::Heartbeats;
+use self::inventory::Pages;
+use self::io::{Outbox, Route, SignRequest};
+use self::limitter::{RateLimiter, TokenBucket};
 use self::message::InventoryAnnouncement;
+use self::observed::ObservedAddresses;
 use self::policy::NamespacesError;
+use self::replication::Replication;
+use self::scheduler::{FetchScheduler, Scheduled};
+use self::snapshot::Snapshot;
 
 /// How often to run the "idle" task.
 pub const IDLE_INTERVAL: LocalDuration = LocalDuration::from_secs(30);
-/// How often to run the "announce" task.
+/// How often to run the "announce" task, at most. The task refreshes our announcements
+/// when they are due, see [`Announcer`].
 pub const ANNOUNCE_INTERVAL: LocalDuration = LocalDuration::from_mins(60);
 /// How often to run the "sync" task.
 pub const SYNC_INTERVAL: LocalDuration = LocalDuration::from_secs(60);
 /// How often to run the "prune" task.
 pub const PRUNE_INTERVAL: LocalDuration = LocalDuration::from_mins(30);
+/// How often to run the "replication" task.
+pub const REPLICATION_INTERVAL: LocalDuration = LocalDuration::from_mins(10);
+/// How often to take a snapshot of the service state.
+pub const SNAPSHOT_INTERVAL: LocalDuration = LocalDuration::from_mins(1);
 /// Duration to wait on an unresponsive peer before dropping its connection.
 pub const STALE_CONNECTION_TIMEOUT: LocalDuration = LocalDuration::from_mins(2);
 /// How much time should pass after a peer was last active for a *ping* to be sent.
@@ -92,10 +129,20 @@ pub const INITIAL_SUBSCRIBE_BACKLOG_DELTA: LocalDuration = LocalDuration::from_m
 pub const MIN_RECONNECTION_DELTA: LocalDuration = LocalDuration::from_secs(3);
 /// Maximum amount of time to wait before reconnecting to a peer.
 pub const MAX_RECONNECTION_DELTA: LocalDuration = LocalDuration::from_mins(60);
-/// Connection retry delta used for ephemeral peers that failed to connect previously.
-pub const CONNECTION_RETRY_DELTA: LocalDuration = LocalDuration::from_mins(10);
+/// Minimum amount of time to wait before re-fetching a repository that failed to fetch.
+pub const MIN_FETCH_RETRY_DELTA: LocalDuration = LocalDuration::from_mins(1);
+/// Maximum amount of time to wait before re-fetching a repository that failed to fetch.
+pub const MAX_FETCH_RETRY_DELTA: LocalDuration = LocalDuration::from_mins(60 * 24);
 /// How long to wait for a fetch to stall before aborting.
 pub const FETCH_TIMEOUT: time::Duration = time::Duration::from_secs(9);
+/// Minimum amount of time between two changes to the addresses of a node.
+pub const MIN_ADDRESS_CHANGE_DELTA: LocalDuration = LocalDuration::from_mins(10);
+/// How long a bridge has to introduce us to a peer, after we asked it to.
+pub const RENDEZVOUS_TIMEOUT: LocalDuration = LocalDuration::from_mins(1);
+/// Number of rendezvous requests a peer can send us in a burst, when we're a bridge.
+pub const MAX_RENDEZVOUS_BURST: usize = 4;
+/// Rate at which a peer can send us rendezvous requests after a burst, per second.
+pub const RENDEZVOUS_FILL_RATE: f64 = 0.1;
 
 /// Maximum external address limit imposed by message size limits.
 pub use message::ADDRESS_LIMIT;
@@ -156,11 +203,22 @@ pub enum Error {
     Repository(#[from] radicle::storage::RepositoryError),
     #[error("namespaces error: {0}")]
     Namespaces(#[from] NamespacesError),
+    #[error(transparent)]
+    Repos(#[from] repos::Error),
 }
 
 /// A store for all node data.
 pub trait Store:
-    address::Store + gossip::Store + routing::Store + seed::Store + node::refs::Store
+    address::Store
+    + gossip::Store
+    + routing::Store
+    + seed::Store
+    + node::refs::Store
+    + failures::Store
+    + uploads::Store
+    + autoseed::Store
+    + repos::Store
+    + stats::Store
 {
 }
 
@@ -183,8 +241,13 @@ pub enum Command {
     Disconnect(NodeId),
     /// Get the node configuration.
     Config(chan::Sender<Config>),
+    /// Apply the settings of the given configuration that can be changed without
+    /// restarting, and return all the settings that changed.
+    Reload(Box<Config>, chan::Sender<ConfigDiff>),
     /// Get the node's listen addresses.
     ListenAddrs(chan::Sender<Vec<std::net::SocketAddr>>),
+    /// Get the addresses we announce, along with their labels.
+    AnnouncedAddrs(chan::Sender<Vec<(Address, address::Label)>>),
     /// Lookup seeds for the given repository in the routing table.
     Seeds(RepoId, chan::Sender<Seeds>),
     /// Fetch the given repository from the network.
@@ -197,6 +260,32 @@ pub enum Command {
     Follow(NodeId, Option<Alias>, chan::Sender<bool>),
     /// Unfollow the given node.
     Unfollow(NodeId, chan::Sender<bool>),
+    /// Reset the fetch backoff of the given repository.
+    ResetBackoff(RepoId, chan::Sender<bool>),
+    /// Set or clear the fetching policy of a node for the given repository.
+    SetFetcherPolicy(RepoId, NodeId, Option<Policy>, chan::Sender<bool>),
+    /// Get the fetching policies of the given repository.
+    Fetchers(RepoId, chan::Sender<Vec<policy::FetcherPolicy>>),
+    /// Get the fetches served to other nodes, optionally of the given repository only.
+    Uploads(Option<RepoId>, chan::Sender<Vec<uploads::Upload>>),
+    /// Get the stored repositories, with their sync status.
+    Repos(chan::Sender<Vec<repos::Repo>>),
+    /// Get the cached statistics of the given repository, or of all stored repositories.
+    Stats(Option<RepoId>, chan::Sender<Vec<stats::Stats>>),
+    /// Get the connected peers that advertise the given features.
+    Peers(node::Features, chan::Sender<Vec<NodeId>>),
+    /// Get the status of our subscription filter.
+    Filter(chan::Sender<node::FilterStatus>),
+    /// Get the health of the service subsystems.
+    Health(chan::Sender<ServiceHealth>),
+    /// Query the tips of the given references of a repository from a connected peer,
+    /// without fetching.
+    QueryRefs(
+        RepoId,
+        NodeId,
+        Vec<RefString>,
+        chan::Sender<Result<query::Tips, query::Error>>,
+    ),
     /// Query the internal service state.
     QueryState(Arc<QueryState>, chan::Sender<Result<(), CommandError>>),
 }
@@ -210,18 +299,45 @@ impl fmt::Debug for Command {
             Self::Connect(id, addr, opts) => write!(f, "Connect({id}, {addr}, {opts:?})"),
             Self::Disconnect(id) => write!(f, "Disconnect({id})"),
             Self::Config(_) => write!(f, "Config"),
+            Self::Reload(..) => write!(f, "Reload"),
             Self::ListenAddrs(_) => write!(f, "ListenAddrs"),
+            Self::AnnouncedAddrs(_) => write!(f, "AnnouncedAddrs"),
             Self::Seeds(id, _) => write!(f, "Seeds({id})"),
             Self::Fetch(id, node, _, _) => write!(f, "Fetch({id}, {node})"),
             Self::Seed(id, scope, _) => write!(f, "Seed({id}, {scope})"),
             Self::Unseed(id, _) => write!(f, "Unseed({id})"),
             Self::Follow(id, _, _) => write!(f, "Follow({id})"),
             Self::Unfollow(id, _) => write!(f, "Unfollow({id})"),
+            Self::ResetBackoff(id, _) => write!(f, "ResetBackoff({id})"),
+            Self::SetFetcherPolicy(id, nid, policy, _) => {
+                write!(f, "SetFetcherPolicy({id}, {nid}, {policy:?})")
+            }
+            Self::Fetchers(id, _) => write!(f, "Fetchers({id})"),
+            Self::Uploads(id, _) => write!(f, "Uploads({id:?})"),
+            Self::Repos(_) => write!(f, "Repos"),
+            Self::Stats(id, _) => write!(f, "Stats({id:?})"),
+            Self::Peers(features, _) => write!(f, "Peers({features})"),
+            Self::Filter(_) => write!(f, "Filter"),
+            Self::Health(_) => write!(f, "Health"),
+            Self::QueryRefs(id, nid, refs, _) => {
+                write!(f, "QueryRefs({id}, {nid}, {} ref(s))", refs.len())
+            }
             Self::QueryState { .. } => write!(f, "QueryState(..)"),
         }
     }
 }
 
+/// Health of the service subsystems, as reported by the service itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceHealth {
+    /// Gossip with peers.
+    pub gossip: HealthState,
+    /// Address book.
+    pub addresses: HealthState,
+    /// Periodic storage maintenance, eg. pruning.
+    pub maintenance: HealthState,
+}
+
 /// Command-related errors.
 #[derive(thiserror::Error, Debug)]
 pub enum CommandError {
@@ -248,6 +364,19 @@ enum TryFetchError<'a> {
     Namespaces(#[from] NamespacesError),
 }
 
+impl TryFetchError<'_> {
+    /// Get the kind of error, as reported to node clients.
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Self::AlreadyFetching(_) => ErrorKind::AlreadyExists,
+            Self::SessionNotFound | Self::SessionNotConnected => ErrorKind::Disconnected,
+            Self::SessionCapacityReached => ErrorKind::Other,
+            Self::Namespaces(NamespacesError::BlockedPolicy { .. }) => ErrorKind::NotSeeding,
+            Self::Namespaces(_) => ErrorKind::Other,
+        }
+    }
+}
+
 /// Fetch state for an ongoing fetch.
 #[derive(Debug)]
 struct FetchState {
@@ -348,6 +477,56 @@ where
     pub fn refs_mut(&mut self) -> &mut impl node::refs::Store {
         &mut self.0
     }
+
+    /// Get the database as a fetch failure store.
+    pub fn failures(&self) -> &impl failures::Store {
+        &self.0
+    }
+
+    /// Get the database as a fetch failure store, mutably.
+    pub fn failures_mut(&mut self) -> &mut impl failures::Store {
+        &mut self.0
+    }
+
+    /// Get the database as an upload log.
+    pub fn uploads(&self) -> &impl uploads::Store {
+        &self.0
+    }
+
+    /// Get the database as an upload log, mutably.
+    pub fn uploads_mut(&mut self) -> &mut impl uploads::Store {
+        &mut self.0
+    }
+
+    /// Get the database as an auto-seed store.
+    pub fn auto_seeds(&self) -> &impl autoseed::Store {
+        &self.0
+    }
+
+    /// Get the database as an auto-seed store, mutably.
+    pub fn auto_seeds_mut(&mut self) -> &mut impl autoseed::Store {
+        &mut self.0
+    }
+
+    /// Get the database as a repository fetch store.
+    pub fn repos(&self) -> &impl repos::Store {
+        &self.0
+    }
+
+    /// Get the database as a repository fetch store, mutably.
+    pub fn repos_mut(&mut self) -> &mut impl repos::Store {
+        &mut self.0
+    }
+
+    /// Get the database as a repository statistics store.
+    pub fn stats(&self) -> &impl stats::Store {
+        &self.0
+    }
+
+    /// Get the database as a repository statistics store, mutably.
+    pub fn stats_mut(&mut self) -> &mut impl stats::Store {
+        &mut self.0
+    }
 }
 
 impl<D> From<D> for Stores<D> {
@@ -372,7 +551,7 @@ pub struct Service<D, S, G> {
     /// Peer sessions, currently or recently connected.
     sessions: Sessions,
     /// Clock. Tells the time.
-    clock: LocalTime,
+    clock: Box<dyn Clock>,
     /// I/O outbox.
     outbox: Outbox,
     /// Cached local node announcement.
@@ -383,8 +562,16 @@ pub struct Service<D, S, G> {
     fetching: HashMap<RepoId, FetchState>,
     /// Fetch queue.
     queue: VecDeque<QueuedFetch>,
+    /// Fetches restored from a snapshot, resumed once the peer connects.
+    restored: Vec<QueuedFetch>,
     /// Request/connection rate limitter.
     limiter: RateLimiter,
+    /// Outbound connection scheduler.
+    dialer: Dialer,
+    /// Redundancy of seeded repositories.
+    replication: Replication,
+    /// Scheduler of fetches triggered by announcements.
+    scheduler: FetchScheduler,
     /// Current seeded repositories bloom filter.
     filter: Filter,
     /// Last time the service was idle.
@@ -393,8 +580,22 @@ pub struct Service<D, S, G> {
     last_sync: LocalTime,
     /// Last time the service routing table was pruned.
     last_prune: LocalTime,
-    /// Last time the inventory was announced.
-    last_announce: LocalTime,
+    /// Error of the last prune run, if any.
+    prune_error: Option<String>,
+    /// Last time new addresses were stored for a node, to limit address churn.
+    address_changes: HashMap<NodeId, LocalTime>,
+    /// Introductions we asked bridges for, by bridge and peer, and when we asked.
+    rendezvous: HashMap<(NodeId, NodeId), LocalTime>,
+    /// Rendezvous requests each peer can still send us, when we're a bridge.
+    rendezvous_limits: HashMap<NodeId, TokenBucket>,
+    /// Schedules the refreshes of our node and inventory announcements.
+    announcer: Announcer,
+    /// Last time the redundancy of seeded repositories was checked.
+    last_replication: LocalTime,
+    /// Last time our heartbeat was announced.
+    last_heartbeat: LocalTime,
+    /// Last time a snapshot of the service state was taken.
+    last_snapshot: LocalTime,
     /// Last timestamp used for announcements.
     last_timestamp: Timestamp,
     /// Time when the service was initialized, or `None` if it wasn't initialized.
@@ -403,6 +604,15 @@ pub struct Service<D, S, G> {
     emitter: Emitter<Event>,
     /// Local listening addresses.
     listening: Vec<net::SocketAddr>,
+    /// External addresses observed for us by peers.
+    observed: ObservedAddresses,
+    /// Health announced by other nodes.
+    heartbeats: Heartbeats,
+    /// Inventories announced in multiple pages, being reassembled.
+    pages: Pages,
+    /// Whether our announcements are signed off the service thread.
+    /// See [`Service::offload_signing`].
+    offload_signing: bool,
 }
 
 impl<D, S, G> Service<D, S, G>
@@ -416,7 +626,7 @@ where
 
     /// Get the local service time.
     pub fn local_time(&self) -> LocalTime {
-        self.clock
+        self.clock.local_time()
     }
 }
 
@@ -428,7 +638,7 @@ where
 {
     pub fn new(
         config: Config,
-        clock: LocalTime,
+        clock: impl Clock + 'static,
         db: Stores<D>,
         storage: S,
         policies: policy::Config<Write>,
@@ -444,25 +654,132 @@ where
             storage,
             policies,
             signer,
+            dialer: Dialer::new(rng.clone()),
+            replication: Replication::default(),
+            scheduler: FetchScheduler::new(rng.clone()),
             rng,
             node,
-            clock,
+            clock: Box::new(clock),
             db,
             outbox: Outbox::default(),
             limiter: RateLimiter::default(),
             sessions,
             fetching: HashMap::new(),
             queue: VecDeque::new(),
+            restored: Vec::new(),
             filter: Filter::empty(),
             last_idle: LocalTime::default(),
             last_sync: LocalTime::default(),
             last_prune: LocalTime::default(),
+            prune_error: None,
+            address_changes: HashMap::new(),
+            rendezvous: HashMap::new(),
+            rendezvous_limits: HashMap::new(),
             last_timestamp: Timestamp::MIN,
-            last_announce: LocalTime::default(),
+            announcer: Announcer::default(),
+            last_replication: LocalTime::default(),
+            last_heartbeat: LocalTime::default(),
+            last_snapshot: LocalTime::default(),
             started_at: None,
             emitter,
             listening: vec![],
+            observed: ObservedAddresses::default(),
+            heartbeats: Heartbeats::default(),
+            pages: Pages::default(),
+            offload_signing: false,
+        }
+    }
+
+    /// Sign the announcements we broadcast off the service thread, by asking for
+    /// them to be signed with [`Io::Sign`]. Announcements that are part of a reply,
+    /// eg. when a peer connects, are always signed right away.
+    pub fn offload_signing(&mut self) {
+        self.offload_signing = true;
+    }
+
+    /// Restore the state saved in a snapshot: reconnect to the peers we were connected to,
+    /// and resume our fetches once the peers they were from are connected.
+    ///
+    /// Must be called before the service is initialized, so that previous peers are
+    /// preferred over new ones.
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        assert!(
+            self.started_at.is_none(),
+            "Service::restore: service must not be initialized"
+        );
+        let Snapshot {
+            mut peers, fetches, ..
+        } = snapshot;
+
+        if let PeerConfig::Dynamic { target } = self.config.peers {
+            // Most recently active peers first.
+            peers.sort_by_key(|p| cmp::Reverse(p.last_active.to_local_time()));
+
+            for peer in peers.into_iter().take(target) {
+                match self.policies.is_blocked(&peer.nid) {
+                    Ok(false) => {}
+                    Ok(true) => continue,
+                    Err(e) => {
+                        error!(target: "service", "Error reading follow policy of {}: {e}", peer.nid);
+                        continue;
+                    }
+                }
+                debug!(target: "service", "Reconnecting to previous peer {} ({})..", peer.nid, peer.addr);
+                self.connect(peer.nid, peer.addr);
+            }
         }
+        self.restored = fetches
+            .into_iter()
+            .map(|f| QueuedFetch {
+                rid: f.rid,
+                from: f.from,
+                refs_at: f.refs_at,
+                channel: None,
+            })
+            .collect();
+
+        info!(
+            target: "service",
+            "Restored snapshot with {} session(s) and {} fetch(es)",
+            self.sessions.len(),
+            self.restored.len()
+        );
+    }
+
+    /// Take a snapshot of the service state that would otherwise be lost on restart.
+    /// See [`snapshot`].
+    pub fn snapshot(&self) -> Snapshot {
+        let peers = self
+            .sessions
+            .values()
+            .filter(|s| s.link.is_outbound() && (s.is_connected() || s.is_connecting()))
+            .map(|s| snapshot::Peer {
+                nid: s.id,
+                addr: s.addr.clone(),
+                last_active: s.last_active.into(),
+            })
+            .collect();
+        let fetches = self
+            .fetching
+            .iter()
+            .map(|(rid, f)| snapshot::Fetch {
+                rid: *rid,
+                from: f.from,
+                refs_at: f.refs_at.clone(),
+            })
+            .chain(
+                self.queue
+                    .iter()
+                    .chain(&self.restored)
+                    .map(|f| snapshot::Fetch {
+                        rid: f.rid,
+                        from: f.from,
+                        refs_at: f.refs_at.clone(),
+                    }),
+            )
+            .collect();
+
+        Snapshot::new(self.clock.local_time().into(), peers, fetches)
     }
 
     /// Whether the service was started (initialized) and if so, at what time.
@@ -470,6 +787,40 @@ where
         self.started_at
     }
 
+    /// Health of the service subsystems.
+    pub fn health(&self) -> ServiceHealth {
+        let gossip = if let Err(e) = self.db.gossip().last() {
+            HealthState::failed(format!("error accessing gossip store: {e}"))
+        } else if self.sessions.connected().next().is_none() {
+            HealthState::degraded("not connected to any peer")
+        } else {
+            HealthState::Ok
+        };
+        let addresses = match self.db.addresses().nodes() {
+            Err(e) => HealthState::failed(format!("error accessing address book: {e}")),
+            Ok(0) => HealthState::degraded("no known nodes"),
+            Ok(_) => HealthState::Ok,
+        };
+        let maintenance = if let Some(err) = &self.prune_error {
+            HealthState::failed(err.clone())
+        } else {
+            let now = self.clock.local_time();
+            let last = self.last_prune.max(self.started_at.unwrap_or(now));
+
+            if now - last > PRUNE_INTERVAL * 2 {
+                HealthState::degraded(format!("last run {} ago", now - last))
+            } else {
+                HealthState::Ok
+            }
+        };
+
+        ServiceHealth {
+            gossip,
+            addresses,
+            maintenance,
+        }
+    }
+
     /// Return the next i/o action to execute.
     #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Option<io::Io> {
@@ -480,11 +831,23 @@ where
     /// Returns whether or not the repo policy was updated.
     pub fn seed(&mut self, id: &RepoId, scope: Scope) -> Result<bool, policy::Error> {
         let updated = self.policies.seed(id, scope)?;
-        self.filter.insert(id);
+        self.filter_seeded(id)?;
 
         Ok(updated)
     }
 
+    /// Add a seeded repository to our subscription filter.
+    fn filter_seeded(&mut self, id: &RepoId) -> Result<(), policy::Error> {
+        self.filter.insert(id);
+
+        // Keep the false positive rate of the filter bounded as we seed more repositories.
+        if self.filter.is_overloaded() {
+            self.refresh_filter()?;
+            debug!(target: "service", "Resized subscription filter to {} byte(s)", self.filter.size());
+        }
+        Ok(())
+    }
+
     /// Unseed a repository.
     /// Returns whether or not the repo policy was updated.
     /// Note that when unseeding, we don't announce anything to the network. This is because by
@@ -494,16 +857,23 @@ where
         // Nb. This is potentially slow if we have lots of repos. We should probably
         // only re-compute the filter when we've unseeded a certain amount of repos
         // and the filter is really out of date.
-        //
-        // TODO: Share this code with initialization code.
-        self.filter = Filter::new(
-            self.policies
-                .seed_policies()?
-                .filter_map(|t| (t.policy == Policy::Allow).then_some(t.rid)),
-        );
+        self.refresh_filter()?;
+
         Ok(updated)
     }
 
+    /// Re-create the filter of seeded repositories, sized for the number of repositories.
+    fn refresh_filter(&mut self) -> Result<(), policy::Error> {
+        let seeded = self
+            .policies
+            .seed_policies()?
+            .filter_map(|t| (t.policy == Policy::Allow).then_some(t.rid))
+            .collect::<Vec<_>>();
+        self.filter = Filter::new(seeded);
+
+        Ok(())
+    }
+
     /// Find the closest `n` peers by proximity in seeding graphs.
     /// Returns a sorted list from the closest peer to the furthest.
     /// Peers with more seedings in common score score higher.
@@ -537,6 +907,11 @@ where
         &self.policies
     }
 
+    /// Get the mutable node policies.
+    pub fn policies_mut(&mut self) -> &mut policy::Config<Write> {
+        &mut self.policies
+    }
+
     /// Get the local signer.
     pub fn signer(&self) -> &G {
         &self.signer
@@ -567,6 +942,11 @@ where
 
         let nid = self.node_id();
         self.started_at = Some(time);
+        // Don't overwrite the previous snapshot before we had a chance to reconnect.
+        self.last_snapshot = time;
+        // Our announcements are sent to every peer we connect to, so there's nothing to
+        // refresh yet.
+        self.announcer = Announcer::new(time);
 
         // Populate refs database. This is only useful as part of the upgrade process for nodes
         // that have been online since before the refs database was created.
@@ -581,6 +961,19 @@ where
             Err(e) => error!(target: "service", "Error checking refs database: {e}"),
         }
 
+        // Remove gossip messages from the future, eg. received while our clock was wrong.
+        // Otherwise, they would supersede the next messages of the nodes that sent them,
+        // and we'd subscribe to messages from the future.
+        match self
+            .db
+            .gossip_mut()
+            .prune_future((time + MAX_TIME_DELTA).into())
+        {
+            Ok(0) => {}
+            Ok(n) => warn!(target: "service", "Removed {n} gossip message(s) from the future"),
+            Err(e) => error!(target: "service", "Error pruning gossip messages: {e}"),
+        }
+
         // Ensure that our local node is in our address database.
         self.db
             .addresses_mut()
@@ -590,10 +983,10 @@ where
                 self.node.alias.clone(),
                 self.node.work(),
                 self.node.timestamp,
-                self.node
-                    .addresses
-                    .iter()
-                    .map(|a| KnownAddress::new(a.clone(), address::Source::Peer)),
+                self.node.addresses.iter().map(|a| {
+                    KnownAddress::new(a.clone(), address::Source::Peer)
+                        .labeled(Some(address::Label::Manual))
+                }),
             )
             .expect("Service::initialize: error adding local node to address database");
 
@@ -640,18 +1033,15 @@ where
             // If we got here, it likely means a repo was updated while the node was stopped.
             // Therefore, we pre-load a refs announcement for this repo, so that it is included in
             // the historical gossip messages when a node connects and subscribes to this repo.
-            if let Ok((ann, _)) = self.refs_announcement_for(rid, [nid]) {
+            if let Ok((msg, _)) = self.refs_announcement_for(rid, [nid]) {
+                let ann = self.sign_announcement(msg);
                 debug!(target: "service", "Adding refs announcement for {rid} to historical gossip messages..");
-                self.db.gossip_mut().announced(&nid, &ann)?;
+                self.db.gossip_mut().announced(&nid, &ann, &nid)?;
             }
         }
 
         // Setup subscription filter for seeded repos.
-        self.filter = Filter::new(
-            self.policies
-                .seed_policies()?
-                .filter_map(|t| (t.policy == Policy::Allow).then_some(t.rid)),
-        );
+        self.refresh_filter()?;
         // Connect to configured peers.
         let addrs = self.config.connect.clone();
         for (id, addr) in addrs.into_iter().map(|ca| ca.into()) {
@@ -671,11 +1061,11 @@ where
             "Tick +{}",
             now - self.started_at.expect("Service::tick: service must be initialized")
         );
-        self.clock = now;
+        self.clock.set(now);
     }
 
     pub fn wake(&mut self) {
-        let now = self.clock;
+        let now = self.clock.local_time();
 
         trace!(
             target: "service",
@@ -688,9 +1078,14 @@ where
 
             self.keep_alive(&now);
             self.disconnect_unresponsive_peers(&now);
+            self.disconnect_unnegotiated_peers(&now);
             self.maintain_connections();
             self.outbox.wakeup(IDLE_INTERVAL);
             self.last_idle = now;
+        } else if self.dialer.is_due(now) {
+            trace!(target: "service", "Running 'dial' task...");
+
+            self.maintain_connections();
         }
         if now - self.last_sync >= SYNC_INTERVAL {
             trace!(target: "service", "Running 'sync' task...");
@@ -701,23 +1096,46 @@ where
             self.outbox.wakeup(SYNC_INTERVAL);
             self.last_sync = now;
         }
-        if now - self.last_announce >= ANNOUNCE_INTERVAL {
+        let peers = self.sessions.connected().count();
+        let target = self.target_peers();
+        let uptime = now - self.started_at.unwrap_or(now);
+        let due = self.announcer.due(now, peers, target, uptime);
+
+        if !due.is_empty() {
             trace!(target: "service", "Running 'announce' task...");
 
-            if let Err(err) = self
-                .storage
-                .inventory()
-                .and_then(|i| self.announce_inventory(i))
-            {
-                error!(target: "service", "Error announcing inventory: {err}");
+            for kind in due {
+                match kind {
+                    announcer::Kind::Node => {
+                        debug!(target: "service", "Refreshing node announcement..");
+                        self.announce_node(self.node.clone());
+                    }
+                    announcer::Kind::Inventory => {
+                        debug!(target: "service", "Refreshing inventory announcement..");
+
+                        if let Err(err) = self
+                            .storage
+                            .inventory()
+                            .and_then(|i| self.announce_inventory(i))
+                        {
+                            error!(target: "service", "Error announcing inventory: {err}");
+                        }
+                    }
+                }
             }
-            self.outbox.wakeup(ANNOUNCE_INTERVAL);
+            self.outbox.wakeup(
+                self.announcer
+                    .next(now, peers, target, uptime)
+                    .min(ANNOUNCE_INTERVAL),
+            );
         }
         if now - self.last_prune >= PRUNE_INTERVAL {
             trace!(target: "service", "Running 'prune' task...");
+            self.prune_error = None;
 
             if let Err(err) = self.prune_routing_entries(&now) {
                 error!(target: "service", "Error pruning routing entries: {err}");
+                self.prune_error = Some(format!("error pruning routing entries: {err}"));
             }
             if let Err(err) = self
                 .db
@@ -725,14 +1143,61 @@ where
                 .prune((now - self.config.limits.gossip_max_age).into())
             {
                 error!(target: "service", "Error pruning gossip entries: {err}");
+                self.prune_error = Some(format!("error pruning gossip entries: {err}"));
+            }
+            self.heartbeats
+                .prune((now - self.config.limits.gossip_max_age).into());
+            self.pages
+                .prune((now - self.config.limits.gossip_max_age).into());
+            // Fetches restored from a snapshot are only resumed if the peer is back in time.
+            self.restored.clear();
+            self.address_changes
+                .retain(|_, t| now - *t < MIN_ADDRESS_CHANGE_DELTA);
+
+            let limits = &self.config.limits.uploads;
+            if let Err(err) = self
+                .db
+                .uploads_mut()
+                .prune_uploads(&(now - limits.max_age).into(), limits.max_size)
+            {
+                error!(target: "service", "Error pruning upload log: {err}");
+                self.prune_error = Some(format!("error pruning upload log: {err}"));
             }
 
             self.outbox.wakeup(PRUNE_INTERVAL);
             self.last_prune = now;
         }
+        if now - self.last_replication >= REPLICATION_INTERVAL {
+            trace!(target: "service", "Running 'replication' task...");
+
+            if let Err(e) = self.maintain_replication() {
+                error!(target: "service", "Error maintaining repository redundancy: {e}");
+            }
+            self.outbox.wakeup(REPLICATION_INTERVAL);
+            self.last_replication = now;
+        }
+        if let Some(interval) = self.config.heartbeat.as_ref().map(heartbeat::interval) {
+            if now - self.last_heartbeat >= interval {
+                trace!(target: "service", "Running 'heartbeat' task...");
+
+                self.announce_heartbeat();
+                self.outbox.wakeup(interval);
+                self.last_heartbeat = now;
+            }
+        }
+
+        if now - self.last_snapshot >= SNAPSHOT_INTERVAL {
+            trace!(target: "service", "Running 'snapshot' task...");
+
+            self.outbox.snapshot(self.snapshot());
+            self.outbox.wakeup(SNAPSHOT_INTERVAL);
+            self.last_snapshot = now;
+        }
 
         // Always check whether there are persistent peers that need reconnecting.
         self.maintain_persistent();
+        // Always check whether there are scheduled fetches that are due.
+        self.dispatch_fetches();
     }
 
     pub fn command(&mut self, cmd: Command) {
@@ -753,9 +1218,34 @@ where
             Command::Config(resp) => {
                 resp.send(self.config.clone()).ok();
             }
+            Command::Reload(config, resp) => {
+                let diff = self.config.reload(&config);
+
+                if !diff.live.is_empty() {
+                    self.policies
+                        .set_defaults(self.config.policy, self.config.scope);
+
+                    for session in self.sessions.values_mut() {
+                        session.set_limits(self.config.limits.clone());
+                    }
+                }
+                resp.send(diff).ok();
+            }
             Command::ListenAddrs(resp) => {
                 resp.send(self.listening.clone()).ok();
             }
+            Command::AnnouncedAddrs(resp) => {
+                let labels = self.address_labels(&self.node);
+                let addrs = self
+                    .node
+                    .addresses
+                    .iter()
+                    .cloned()
+                    .zip(labels.iter().copied())
+                    .collect();
+
+                resp.send(addrs).ok();
+            }
             Command::Seeds(rid, resp) => match self.seeds(&rid) {
                 Ok(seeds) => {
                     let (connected, disconnected) = seeds.partition();
@@ -771,18 +1261,29 @@ where
                 }
             },
             Command::Fetch(rid, seed, timeout, resp) => {
+                self.scheduler.interacted(rid, self.clock.local_time());
                 self.fetch(rid, seed, timeout, Some(resp));
             }
             Command::Seed(rid, scope, resp) => {
+                self.scheduler.interacted(rid, self.clock.local_time());
                 // Update our seeding policy.
                 let seeded = self
                     .seed(&rid, scope)
                     .expect("Service::command: error seeding repository");
+                // The repository is now seeded explicitly, and no longer counts towards the
+                // auto-seeding budget.
+                if let Err(e) = self.db.auto_seeds_mut().forget_auto_seed(&rid) {
+                    error!(target: "service", "Error forgetting auto-seed of {rid}: {e}");
+                }
                 resp.send(seeded).ok();
 
                 // Let all our peers know that we're interested in this repo from now on.
                 self.outbox.broadcast(
-                    Message::subscribe(self.filter(), self.clock.into(), Timestamp::MAX),
+                    Message::subscribe(
+                        self.filter(),
+                        self.clock.network_time().into(),
+                        Timestamp::MAX,
+                    ),
                     self.sessions.connected().map(|(_, s)| s),
                 );
             }
@@ -790,6 +1291,10 @@ where
                 let updated = self
                     .unseed(&id)
                     .expect("Service::command: error unseeding repository");
+                // Don't seed the repository automatically again.
+                if let Err(e) = self.db.auto_seeds_mut().reject_auto_seed(&id) {
+                    error!(target: "service", "Error rejecting auto-seed of {id}: {e}");
+                }
                 resp.send(updated).ok();
             }
             Command::Follow(id, alias, resp) => {
@@ -806,7 +1311,86 @@ where
                     .expect("Service::command: error unfollowing node");
                 resp.send(updated).ok();
             }
+            Command::ResetBackoff(rid, resp) => {
+                let reset = self
+                    .db
+                    .failures_mut()
+                    .reset(&rid)
+                    .expect("Service::command: error resetting fetch backoff");
+                resp.send(reset > 0).ok();
+            }
+            Command::SetFetcherPolicy(rid, nid, policy, resp) => {
+                let updated = match policy {
+                    Some(policy) => self.policies.set_fetcher_policy(&rid, &nid, policy),
+                    None => self.policies.unset_fetcher_policy(&rid, &nid),
+                }
+                .expect("Service::command: error setting fetcher policy");
+                resp.send(updated).ok();
+            }
+            Command::Fetchers(rid, resp) => {
+                let fetchers = self
+                    .policies
+                    .fetcher_policies(&rid)
+                    .expect("Service::command: error getting fetcher policies");
+                resp.send(fetchers).ok();
+            }
+            Command::Uploads(rid, resp) => {
+                let uploads = self
+                    .db
+                    .uploads()
+                    .uploads(rid.as_ref())
+                    .expect("Service::command: error getting upload log");
+                resp.send(uploads).ok();
+            }
+            Command::Repos(resp) => match self.repos() {
+                Ok(repos) => {
+                    resp.send(repos).ok();
+                }
+                Err(e) => {
+                    error!(target: "service", "Error getting stored repositories: {e}");
+                }
+            },
+            Command::Stats(rid, resp) => {
+                let stats = match rid {
+                    Some(rid) => self.db.stats().stats(&rid).map(Vec::from_iter),
+                    None => self.db.stats().all_stats(),
+                };
+                match stats {
+                    Ok(stats) => {
+                        resp.send(stats).ok();
+                    }
+                    Err(e) => {
+                        error!(target: "service", "Error getting repository statistics: {e}");
+                    }
+                }
+            }
+            Command::Peers(features, resp) => {
+                resp.send(self.peers_with(features).copied().collect()).ok();
+            }
+            Command::QueryRefs(rid, nid, refs, resp) => {
+                self.query_refs(rid, nid, refs, resp);
+            }
+            Command::Filter(resp) => {
+                let filter = self.filter();
+                let seeded = self
+                    .policies
+                    .seed_policies()
+                    .expect("Service::command: error accessing seeding configuration")
+                    .filter(|t| t.policy == Policy::Allow)
+                    .count();
+
+                resp.send(node::FilterStatus {
+                    size: filter.size(),
+                    seeded,
+                    false_positive_rate: filter.false_positive_rate(),
+                })
+                .ok();
+            }
+            Command::Health(resp) => {
+                resp.send(self.health()).ok();
+            }
             Command::AnnounceRefs(id, resp) => {
+                self.scheduler.interacted(id, self.clock.local_time());
                 let doc = match self.storage.get(id) {
                     Ok(Some(doc)) => doc,
                     Ok(None) => {
@@ -842,6 +1426,7 @@ where
                 }
             }
             Command::UpdateInventory(rid, resp) => {
+                self.scheduler.interacted(rid, self.clock.local_time());
                 self.storage.insert(rid);
 
                 let synced = self
@@ -856,6 +1441,36 @@ where
         }
     }
 
+    /// Initiate the scheduled fetches that are due, and schedule a wakeup for the next one.
+    fn dispatch_fetches(&mut self) {
+        let now = self.clock.local_time();
+
+        for Scheduled { rid, from, refs } in self.scheduler.dispatch(now) {
+            if !self.sessions.contains_key(&from) {
+                debug!(target: "service", "Skipping scheduled fetch of {rid}, {from} is no longer connected");
+                continue;
+            }
+            if self.is_backed_off(&rid, &from) {
+                debug!(target: "service", "Skipping scheduled fetch of {rid} from {from}, previous fetches failed");
+                continue;
+            }
+            let scope = match self.policies.seed_policy(&rid) {
+                Ok(entry) if entry.policy == Policy::Allow => entry.scope,
+                Ok(_) => continue,
+                Err(e) => {
+                    error!(target: "service", "Error accessing seeding policy of {rid}: {e}");
+                    continue;
+                }
+            };
+            if self.fetch_refs_at(rid, from, refs, scope, FETCH_TIMEOUT, None) {
+                self.scheduler.fetched(rid, now);
+            }
+        }
+        if let Some(delay) = self.scheduler.schedule(now) {
+            self.outbox.wakeup(delay);
+        }
+    }
+
     /// Initiate an outgoing fetch for some repository, based on another node's announcement.
     /// Returns `true` if the fetch was initiated and `false` if it was skipped.
     fn fetch_refs_at(
@@ -947,6 +1562,7 @@ where
                 if let Some(c) = channel {
                     c.send(FetchResult::Failed {
                         reason: e.to_string(),
+                        kind: e.kind(),
                     })
                     .ok();
                 }
@@ -1028,6 +1644,7 @@ where
                 },
                 Err(e) => FetchResult::Failed {
                     reason: e.to_string(),
+                    kind: e.kind(),
                 },
             };
             if sub.send(result).is_err() {
@@ -1043,11 +1660,24 @@ where
                 namespaces,
                 clone,
                 doc,
+                blobs,
+                summary,
             }) => {
                 info!(target: "service", "Fetched {rid} from {remote} successfully");
+
+                if let Err(e) = self.db.failures_mut().succeeded(&rid, &remote) {
+                    error!(target: "service", "Error clearing fetch failures of {rid}: {e}");
+                }
+                if let Err(e) = self
+                    .db
+                    .repos_mut()
+                    .set_last_fetch(&rid, self.clock.local_time().into())
+                {
+                    error!(target: "service", "Error recording fetch of {rid}: {e}");
+                }
                 // Update our routing table in case this fetch was user-initiated and doesn't
                 // come from an announcement.
-                self.seed_discovered(rid, remote, self.clock.into());
+                self.seed_discovered(rid, remote, self.clock.local_time().into());
 
                 for update in &updated {
                     if update.is_skipped() {
@@ -1060,8 +1690,12 @@ where
                     remote,
                     rid,
                     updated: updated.clone(),
+                    namespaces: summary,
                 });
 
+                if clone {
+                    self.auto_seed_cloned(rid);
+                }
                 // Announce our new inventory if this fetch was a full clone.
                 // Only update and announce inventory for public repositories.
                 if clone && doc.visibility.is_public() {
@@ -1081,10 +1715,33 @@ where
                         error!(target: "service", "Failed to announce new refs: {e}");
                     }
                 }
+                if !blobs.is_empty() {
+                    self.fetch_blobs(rid, remote, blobs);
+                }
             }
             Err(err) => {
                 error!(target: "service", "Fetch failed for {rid} from {remote}: {err}");
 
+                if err.is_not_delegated() {
+                    self.reject_auto_seed(rid);
+                }
+
+                self.emitter.emit(Event::RefsFetchFailed {
+                    remote,
+                    rid,
+                    reason: err.to_string(),
+                    kind: err.kind(),
+                });
+
+                let class = err.classification();
+                if class.is_backed_off() {
+                    self.fetch_failed(rid, remote);
+                }
+                if let Some(severity) = class.penalty() {
+                    if let Err(e) = self.db.addresses_mut().penalize(&remote, severity) {
+                        error!(target: "service", "Error penalizing {remote}: {e}");
+                    }
+                }
                 // For now, we only disconnect the remote in case of timeout. In the future,
                 // there may be other reasons to disconnect.
                 if err.is_timeout() {
@@ -1096,60 +1753,361 @@ where
         self.dequeue_fetch();
     }
 
-    /// Fetches are queued for two reasons:
-    /// 1. The RID was already being fetched.
-    /// 2. The session was already at fetch capacity.
-    pub fn dequeue_fetch(&mut self) {
-        while let Some(QueuedFetch {
-            rid,
-            from,
-            refs_at,
-            channel,
-        }) = self.queue.pop_front()
-        {
-            debug!(target: "service", "Dequeued fetch for {rid} from session {from}..");
-
-            if let Some(refs) = NonEmpty::from_vec(refs_at) {
-                let repo_entry = self
-                    .policies
-                    .seed_policy(&rid)
-                    .expect("Service::dequeue_fetch: error accessing repo seeding configuration");
-
-                // Keep dequeueing if there was nothing to fetch, otherwise break.
-                if self.fetch_refs_at(rid, from, refs, repo_entry.scope, FETCH_TIMEOUT, channel) {
-                    break;
-                }
-            } else {
-                // If no refs are specified, always do a full fetch.
-                self.fetch(rid, from, FETCH_TIMEOUT, channel);
-                break;
+    /// Seed a repository that a followed node is active in, ie. has refs in, if auto-seeding
+    /// is enabled and the budget isn't exhausted. Only repositories without a seeding policy
+    /// of their own, that weren't seeded automatically before, are seeded. Returns whether
+    /// the repository was seeded.
+    fn auto_seed(&mut self, rid: RepoId, refs: &NonEmpty<RefsAt>) -> bool {
+        let Some(budget) = &self.config.auto_seed else {
+            return false;
+        };
+        match (*self.policies).seed_policy(&rid) {
+            Ok(None) => {}
+            Ok(Some(_)) => return false,
+            Err(e) => {
+                error!(target: "service", "Error getting seeding policy of {rid}: {e}");
+                return false;
             }
         }
-    }
-
-    /// Inbound connection attempt.
-    pub fn accepted(&mut self, addr: Address) -> bool {
-        // Always accept trusted connections, even if we already reached
-        // our inbound connection limit.
-        if addr.is_trusted() {
-            return true;
-        }
-        // Check for inbound connection limit.
-        if self.sessions.inbound().count() >= self.config.limits.connection.inbound {
+        let Some(nid) = refs
+            .iter()
+            .map(|r| r.remote)
+            .find(|nid| self.policies.is_following(nid).unwrap_or(false))
+        else {
             return false;
+        };
+        match self.db.auto_seeds().auto_seeded(&rid) {
+            Ok(None) => {}
+            Ok(Some(_)) => return false,
+            Err(e) => {
+                error!(target: "service", "Error getting auto-seed entry of {rid}: {e}");
+                return false;
+            }
         }
-        let host: HostName = addr.into();
-
-        if self
-            .limiter
-            .limit(host.clone(), &self.config.limits.rate.inbound, self.clock)
-        {
-            trace!(target: "service", "Rate limitting inbound connection from {host}..");
+        let usage = match self.db.auto_seeds().auto_seed_usage() {
+            Ok(usage) => usage,
+            Err(e) => {
+                error!(target: "service", "Error getting auto-seed usage: {e}");
+                return false;
+            }
+        };
+        if usage.repos >= budget.max_repos || usage.size >= budget.max_size {
+            debug!(
+                target: "service",
+                "Not auto-seeding {rid}: budget of {} repositories and {} bytes exhausted",
+                budget.max_repos, budget.max_size
+            );
             return false;
         }
-        true
-    }
-
+        // The seeding policy may have been set by another writer, eg. the CLI, since we
+        // checked it. Only seed the repository if it still doesn't have one.
+        let seeded = self
+            .policies
+            .transaction(|policies| {
+                if (**policies).seed_policy(&rid)?.is_some() {
+                    return Ok(false);
+                }
+                policies.seed(&rid, Scope::Followed)
+            })
+            .and_then(|seeded| {
+                if seeded {
+                    self.filter_seeded(&rid)?;
+                }
+                Ok(seeded)
+            });
+        match seeded {
+            Ok(true) => {}
+            Ok(false) => return false,
+            Err(e) => {
+                error!(target: "service", "Error auto-seeding {rid}: {e}");
+                return false;
+            }
+        }
+        let now = self.clock.local_time().into();
+        if let Err(e) = self.db.auto_seeds_mut().auto_seed(&rid, &nid, now) {
+            error!(target: "service", "Error recording auto-seed of {rid}: {e}");
+        }
+        info!(target: "service", "Auto-seeding {rid}, since followed node {nid} is active in it");
+
+        self.emitter.emit(Event::RepoAutoSeeded { rid, nid });
+        self.outbox.broadcast(
+            Message::subscribe(
+                self.filter(),
+                self.clock.network_time().into(),
+                Timestamp::MAX,
+            ),
+            self.sessions.connected().map(|(_, s)| s),
+        );
+        true
+    }
+
+    /// Record the size of a repository seeded automatically, once it's cloned.
+    fn auto_seed_cloned(&mut self, rid: RepoId) {
+        match self.db.auto_seeds().auto_seeded(&rid) {
+            Ok(Some(entry)) if entry.is_pending() => {}
+            Ok(_) => return,
+            Err(e) => {
+                error!(target: "service", "Error getting auto-seed entry of {rid}: {e}");
+                return;
+            }
+        }
+        let size = match radicle::io::disk_usage(&self.storage.path_of(&rid)) {
+            Ok(size) => size,
+            Err(e) => {
+                error!(target: "service", "Error getting the size of {rid}: {e}");
+                return;
+            }
+        };
+        if let Err(e) = self.db.auto_seeds_mut().set_auto_seed_size(&rid, size) {
+            error!(target: "service", "Error recording the size of auto-seeded {rid}: {e}");
+        }
+    }
+
+    /// Stop seeding a repository seeded automatically, eg. because none of its delegates
+    /// are followed.
+    fn reject_auto_seed(&mut self, rid: RepoId) {
+        match self.db.auto_seeds_mut().reject_auto_seed(&rid) {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => {
+                error!(target: "service", "Error rejecting auto-seed of {rid}: {e}");
+                return;
+            }
+        }
+        info!(target: "service", "Rejected auto-seeded {rid}: none of its delegates are followed");
+
+        if let Err(e) = self.unseed(&rid) {
+            error!(target: "service", "Error unseeding {rid}: {e}");
+        }
+    }
+
+    /// Fetch large files referenced by a repository, if the remote serves them.
+    fn fetch_blobs(&mut self, rid: RepoId, remote: NodeId, blobs: Vec<Oid>) {
+        if !self.config.blobs {
+            return;
+        }
+        if !self.has_features(&remote, node::Features::BLOBS) {
+            debug!(target: "service", "Not fetching {} blob(s) of {rid}: {remote} doesn't serve blobs", blobs.len());
+            return;
+        }
+        if !self.sessions.get(&remote).is_some_and(|s| s.is_connected()) {
+            return;
+        }
+        self.outbox.fetch_blobs(remote, rid, blobs);
+    }
+
+    /// Query the tips of references of a repository from a peer, if it answers queries.
+    fn query_refs(
+        &mut self,
+        rid: RepoId,
+        remote: NodeId,
+        refs: Vec<RefString>,
+        reply: chan::Sender<Result<query::Tips, query::Error>>,
+    ) {
+        if !self.sessions.get(&remote).is_some_and(|s| s.is_connected()) {
+            reply.send(Err(query::Error::NotConnected)).ok();
+            return;
+        }
+        if !self.has_features(&remote, node::Features::REF_QUERY) {
+            reply.send(Err(query::Error::Unsupported)).ok();
+            return;
+        }
+        self.outbox.query_refs(remote, rid, refs, reply);
+    }
+
+    /// Called when the tips of references of a repository were queried.
+    pub fn refs_queried(
+        &mut self,
+        rid: RepoId,
+        remote: NodeId,
+        result: Result<query::Tips, query::Error>,
+        reply: chan::Sender<Result<query::Tips, query::Error>>,
+    ) {
+        match &result {
+            Ok(tips) => {
+                debug!(target: "service", "Queried {} reference(s) of {rid} from {remote}", tips.len());
+            }
+            Err(err) => {
+                warn!(target: "service", "Reference query failed for {rid} from {remote}: {err}");
+            }
+        }
+        reply.send(result).ok();
+    }
+
+    /// Called when large files of a repository were fetched.
+    pub fn blobs_fetched(
+        &mut self,
+        rid: RepoId,
+        remote: NodeId,
+        result: Result<Vec<Oid>, FetchError>,
+    ) {
+        match result {
+            Ok(blobs) => {
+                info!(target: "service", "Fetched {} blob(s) of {rid} from {remote}", blobs.len());
+            }
+            Err(err) => {
+                // The blobs that weren't fetched are fetched again with the next fetch
+                // of the repository, resuming where we left off.
+                error!(target: "service", "Blob fetch failed for {rid} from {remote}: {err}");
+            }
+        }
+    }
+
+    /// Called when a remote fetched a repository from us.
+    pub fn uploaded(
+        &mut self,
+        rid: RepoId,
+        remote: NodeId,
+        sent: u64,
+        elapsed: time::Duration,
+        cpu: Option<time::Duration>,
+        result: Result<(), UploadError>,
+    ) {
+        if let Err(err) = &result {
+            info!(target: "service", "Peer {remote} failed to fetch {rid} from us: {err}");
+        } else {
+            info!(target: "service", "Peer {remote} fetched {rid} from us successfully ({sent} byte(s) sent)");
+        }
+        let upload = uploads::Upload {
+            rid,
+            nid: remote,
+            timestamp: self.clock.local_time().into(),
+            sent,
+            elapsed: u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX),
+            cpu: cpu.map(|cpu| u64::try_from(cpu.as_millis()).unwrap_or(u64::MAX)),
+            error: result.err().map(|e| e.to_string()),
+        };
+        if let Err(e) = self.db.uploads_mut().uploaded(&upload) {
+            error!(target: "service", "Error recording upload of {rid} to {remote}: {e}");
+        }
+    }
+
+    /// Record a failed fetch, and back off from fetching the repository from this remote.
+    fn fetch_failed(&mut self, rid: RepoId, remote: NodeId) {
+        let attempts = match self.db.failures().failure(&rid, &remote) {
+            Ok(failure) => failure.map_or(0, |f| f.attempts) + 1,
+            Err(e) => {
+                error!(target: "service", "Error getting fetch failures of {rid}: {e}");
+                return;
+            }
+        };
+        let delay = dialer::backoff(
+            attempts,
+            MIN_FETCH_RETRY_DELTA,
+            MAX_FETCH_RETRY_DELTA,
+            &mut self.rng,
+        );
+        let failure = failures::Failure {
+            attempts,
+            retry_at: (self.clock.local_time() + delay).into(),
+        };
+        debug!(target: "service", "Backing off from fetching {rid} from {remote} for {delay}..");
+
+        if let Err(e) = self.db.failures_mut().failed(&rid, &remote, failure) {
+            error!(target: "service", "Error recording fetch failure of {rid}: {e}");
+        }
+    }
+
+    /// Check whether we're backing off from fetching a repository from a remote, because
+    /// previous fetches failed.
+    fn is_backed_off(&self, rid: &RepoId, remote: &NodeId) -> bool {
+        match self.db.failures().failure(rid, remote) {
+            Ok(Some(failure)) => failure.retry_at.to_local_time() > self.clock.local_time(),
+            Ok(None) => false,
+            Err(e) => {
+                error!(target: "service", "Error getting fetch failures of {rid}: {e}");
+                false
+            }
+        }
+    }
+
+    /// Fetches are queued for two reasons:
+    /// 1. The RID was already being fetched.
+    /// 2. The session was already at fetch capacity.
+    pub fn dequeue_fetch(&mut self) {
+        while let Some(QueuedFetch {
+            rid,
+            from,
+            refs_at,
+            channel,
+        }) = self.queue.pop_front()
+        {
+            debug!(target: "service", "Dequeued fetch for {rid} from session {from}..");
+
+            if let Some(refs) = NonEmpty::from_vec(refs_at) {
+                let repo_entry = self
+                    .policies
+                    .seed_policy(&rid)
+                    .expect("Service::dequeue_fetch: error accessing repo seeding configuration");
+
+                // Keep dequeueing if there was nothing to fetch, otherwise break.
+                if self.fetch_refs_at(rid, from, refs, repo_entry.scope, FETCH_TIMEOUT, channel) {
+                    break;
+                }
+            } else {
+                // If no refs are specified, always do a full fetch.
+                self.fetch(rid, from, FETCH_TIMEOUT, channel);
+                break;
+            }
+        }
+    }
+
+    /// Resume the fetches from the given peer that were restored from a snapshot.
+    fn resume_fetches(&mut self, remote: NodeId) {
+        let (resumed, restored) = std::mem::take(&mut self.restored)
+            .into_iter()
+            .partition::<Vec<_>, _>(|f| f.from == remote);
+        self.restored = restored;
+
+        for QueuedFetch {
+            rid,
+            from,
+            refs_at,
+            channel,
+        } in resumed
+        {
+            let policy = match self.policies.seed_policy(&rid) {
+                Ok(policy) if policy.policy == Policy::Allow => policy,
+                Ok(_) => continue,
+                Err(e) => {
+                    error!(target: "service", "Error accessing seeding policy of {rid}: {e}");
+                    continue;
+                }
+            };
+            debug!(target: "service", "Resuming fetch of {rid} from {from}..");
+
+            if let Some(refs) = NonEmpty::from_vec(refs_at) {
+                self.fetch_refs_at(rid, from, refs, policy.scope, FETCH_TIMEOUT, channel);
+            } else {
+                self.fetch(rid, from, FETCH_TIMEOUT, channel);
+            }
+        }
+    }
+
+    /// Inbound connection attempt.
+    pub fn accepted(&mut self, addr: Address) -> bool {
+        // Always accept trusted connections, even if we already reached
+        // our inbound connection limit.
+        if addr.is_trusted() {
+            return true;
+        }
+        // Check for inbound connection limit.
+        if self.sessions.inbound().count() >= self.config.limits.connection.inbound {
+            return false;
+        }
+        let host: HostName = addr.into();
+
+        if self.limiter.limit(
+            host.clone(),
+            &self.config.limits.rate.inbound,
+            self.clock.local_time(),
+        ) {
+            trace!(target: "service", "Rate limitting inbound connection from {host}..");
+            return false;
+        }
+        true
+    }
+
     pub fn attempted(&mut self, nid: NodeId, addr: Address) {
         debug!(target: "service", "Attempted connection to {nid} ({addr})");
 
@@ -1168,24 +2126,60 @@ where
     }
 
     pub fn connected(&mut self, remote: NodeId, addr: Address, link: Link) {
+        if link.is_inbound() {
+            match self.policies.is_blocked(&remote) {
+                Ok(true) => {
+                    debug!(target: "service", "Disconnecting blocked peer {remote}..");
+                    self.outbox.disconnect(remote, DisconnectReason::Banned);
+                    return;
+                }
+                Ok(false) => {}
+                Err(e) => error!(target: "service", "Error reading follow policy of {remote}: {e}"),
+            }
+            // Nb. The inbound limit is checked when connections are accepted, but there may be
+            // more than one handshake in progress at that time.
+            if !addr.is_trusted()
+                && self.sessions.inbound().count() >= self.config.limits.connection.inbound
+            {
+                debug!(target: "service", "Disconnecting peer {remote}: too many inbound connections");
+                self.outbox
+                    .disconnect(remote, DisconnectReason::TooManyConnections);
+                return;
+            }
+        }
         info!(target: "service", "Connected to {} ({:?})", remote, link);
         self.emitter.emit(Event::PeerConnected { nid: remote });
 
-        let msgs = self.initial(link);
+        let msgs = self.initial(&remote, link);
+        let features = self.features(&remote);
 
         if link.is_outbound() {
             if let Some(peer) = self.sessions.get_mut(&remote) {
-                peer.to_connected(self.clock);
+                peer.to_connected(self.clock.local_time());
+                peer.features = features;
                 self.outbox.write_all(peer, msgs);
+                self.dialer.connected(remote, &peer.addr);
 
-                if let Err(e) =
-                    self.db
-                        .addresses_mut()
-                        .connected(&remote, &peer.addr, self.clock.into())
-                {
+                if let Err(e) = self.db.addresses_mut().connected(
+                    &remote,
+                    &peer.addr,
+                    self.clock.local_time().into(),
+                ) {
                     error!(target: "service", "Error updating address book with connection: {e}");
                 }
+            } else {
+                // We only dial nodes we have a session for, so the handshake must have
+                // authenticated a node other than the one we dialed.
+                warn!(target: "service", "Disconnecting unexpected outbound peer {remote}..");
+                self.outbox
+                    .disconnect(remote, DisconnectReason::UnexpectedNode);
+                return;
+            }
+            // Fetch the under-replicated repositories we connected to this seed for.
+            for rid in self.replication.connected(&remote) {
+                self.fetch(rid, remote, FETCH_TIMEOUT, None);
             }
+            self.resume_fetches(remote);
         } else {
             match self.sessions.entry(remote) {
                 Entry::Occupied(e) => {
@@ -1200,13 +2194,27 @@ where
                         addr,
                         self.config.is_persistent(&remote),
                         self.rng.clone(),
-                        self.clock,
+                        self.clock.local_time(),
                         self.config.limits.clone(),
                     ));
+                    peer.features = features;
                     self.outbox.write_all(peer, msgs);
+                    // Let the peer know what address its connection is coming from.
+                    self.outbox.write(
+                        peer,
+                        Info::ObservedAddress {
+                            addr: peer.addr.clone(),
+                        }
+                        .into(),
+                    );
+                    self.resume_fetches(remote);
                 }
             }
         }
+        // If we don't know the peer's features yet, they are checked once it announces itself.
+        if features != Features::NONE {
+            self.check_features(&remote, features);
+        }
     }
 
     pub fn disconnected(&mut self, remote: NodeId, link: Link, reason: &DisconnectReason) {
@@ -1224,6 +2232,9 @@ where
         }
 
         info!(target: "service", "Disconnected from {} ({})", remote, reason);
+        self.clock.forget(&remote);
+        self.rendezvous_limits.remove(&remote);
+        self.outbox.disconnected(&remote);
         self.emitter.emit(Event::PeerDisconnected {
             nid: remote,
             reason: reason.to_string(),
@@ -1231,7 +2242,9 @@ where
 
         let link = session.link;
         let addr = session.addr.clone();
+        let goodbye = session.disconnect.take();
 
+        self.replication.disconnected(&remote);
         self.fetching.retain(|_, fetching| {
             if fetching.from != remote {
                 return true;
@@ -1240,16 +2253,21 @@ where
             for resp in &fetching.subscribers {
                 resp.send(FetchResult::Failed {
                     reason: format!("disconnected: {reason}"),
+                    kind: ErrorKind::Disconnected,
                 })
                 .ok();
             }
             false
         });
 
-        // Attempt to re-connect to persistent peers.
-        if self.config.peer(&remote).is_some() {
-            let delay = LocalDuration::from_secs(2u64.saturating_pow(session.attempts() as u32))
-                .clamp(MIN_RECONNECTION_DELTA, MAX_RECONNECTION_DELTA);
+        // Attempt to re-connect to persistent peers, unless they banned us.
+        if self.config.peer(&remote).is_some() && goodbye != Some(DisconnectCode::Banned) {
+            let delay = dialer::backoff(
+                session.attempts(),
+                MIN_RECONNECTION_DELTA,
+                MAX_RECONNECTION_DELTA,
+                &mut self.rng,
+            );
 
             // Nb. We always try to reconnect to persistent peers, even when the error appears
             // to not be transient.
@@ -1258,11 +2276,20 @@ where
             debug!(target: "service", "Reconnecting to {remote} in {delay}..");
 
             self.outbox.wakeup(delay);
+
+            // If we couldn't reach the peer, it may be behind NAT. Ask our bridges
+            // to introduce us, in case they are connected to it.
+            if let DisconnectReason::Dial(_) = reason {
+                self.rendezvous(remote);
+            }
         } else {
             debug!(target: "service", "Dropping peer {remote}..");
             self.sessions.remove(&remote);
 
             let severity = match reason {
+                // A peer that tells us why it's going away isn't held responsible for
+                // the disconnection.
+                _ if goodbye.is_some() => Severity::Low,
                 DisconnectReason::Dial(_)
                 | DisconnectReason::Fetch(_)
                 | DisconnectReason::Connection(_) => {
@@ -1277,7 +2304,12 @@ where
                 DisconnectReason::Session(e) => e.severity(),
                 DisconnectReason::Command
                 | DisconnectReason::Conflict
-                | DisconnectReason::SelfConnection => Severity::Low,
+                | DisconnectReason::SelfConnection
+                | DisconnectReason::UnexpectedNode
+                | DisconnectReason::Banned
+                | DisconnectReason::TooManyConnections
+                | DisconnectReason::MissingFeatures(_)
+                | DisconnectReason::Remote(_) => Severity::Low,
             };
 
             if let Err(e) = self
@@ -1287,6 +2319,12 @@ where
             {
                 error!(target: "service", "Error updating address store: {e}");
             }
+            // There's no point in connecting to a peer that banned us.
+            if goodbye == Some(DisconnectCode::Banned) {
+                if let Err(e) = self.db.addresses_mut().ban(&remote) {
+                    error!(target: "service", "Error updating address store: {e}");
+                }
+            }
             // Only re-attempt outbound connections, since we don't care if an inbound connection
             // is dropped.
             if link.is_outbound() {
@@ -1314,6 +2352,7 @@ where
     /// and `false` if it should not.
     pub fn handle_announcement(
         &mut self,
+        relayer: &NodeId,
         relayer_addr: &Address,
         announcement: &Announcement,
     ) -> Result<bool, session::Error> {
@@ -1327,10 +2366,17 @@ where
         } = announcement;
 
         // Ignore our own announcements, in case the relayer sent one by mistake.
+        // If it's numbered beyond what we remember, eg. because our database was reset,
+        // make sure our next announcements are numbered beyond it too.
         if announcer == self.nid() {
+            if let Some(seq) = announcement.seq() {
+                if let Err(e) = self.db.gossip_mut().sequenced(announcer, seq) {
+                    error!(target: "service", "Error updating our announcement sequence number: {e}");
+                }
+            }
             return Ok(false);
         }
-        let now = self.clock;
+        let now = self.clock.network_time();
         let timestamp = message.timestamp();
         // To avoid spamming peers on startup with historical gossip messages,
         // don't relay messages that are too old.
@@ -1340,10 +2386,39 @@ where
             self.config.relay
         };
 
-        // Don't allow messages from too far in the future.
+        // Don't allow messages from too far in the future. Otherwise, a node could announce
+        // a message that supersedes all of its future messages.
         if timestamp.saturating_sub(now.as_millis()) > MAX_TIME_DELTA.as_millis() as u64 {
             return Err(session::Error::InvalidTimestamp(timestamp));
         }
+        // Keep track of how much of the relayer's gossip we received, so that we can subscribe
+        // to the rest only when reconnecting. A peer's own announcements are created on the
+        // spot, and don't tell us how far along its history we are.
+        if announcer != relayer {
+            let delivered = Timestamp::from((*timestamp).min(now.as_millis()));
+            if let Err(e) = self.db.gossip_mut().delivered(relayer, delivered) {
+                error!(target: "service", "Error recording gossip delivered by {relayer}: {e}");
+            }
+        }
+        // Ignore messages that are so old that they would be pruned.
+        if now - timestamp.to_local_time() > self.config.limits.gossip_max_age {
+            debug!(target: "service", "Ignoring expired announcement from {announcer} (t={timestamp})");
+            return Ok(false);
+        }
+        // The inventory announcement a peer sends when connecting is created on the spot,
+        // which tells us what time the peer thinks it is. Only peers we chose to connect to
+        // are trusted with this, since anyone can connect to us as many times as they like.
+        let trusted = self
+            .sessions
+            .get(relayer)
+            .is_some_and(|s| s.link.is_outbound() || self.config.is_persistent(relayer));
+        if announcer == relayer && trusted {
+            if let AnnouncementMessage::Inventory(_) | AnnouncementMessage::InventoryPage(_) =
+                message
+            {
+                self.clock.sample(*relayer, timestamp.to_local_time());
+            }
+        }
 
         // We don't process announcements from nodes we don't know, since the node announcement is
         // what provides DoS protection.
@@ -1353,7 +2428,11 @@ where
         // from the `subscribe` message. This can happen if the cut-off time is after the node
         // announcement timestamp, but before the other announcements. In that case, we simply
         // ignore all announcements of that node until we get a node announcement.
-        if let AnnouncementMessage::Inventory(_) | AnnouncementMessage::Refs(_) = message {
+        if let AnnouncementMessage::Inventory(_)
+        | AnnouncementMessage::Refs(_)
+        | AnnouncementMessage::Heartbeat(_)
+        | AnnouncementMessage::InventoryPage(_) = message
+        {
             match self.db.addresses().get(announcer) {
                 Ok(node) => {
                     if node.is_none() {
@@ -1368,11 +2447,59 @@ where
             }
         }
 
+        // Heartbeats aren't stored with other gossip messages, and are rate-limited per node.
+        if let AnnouncementMessage::Heartbeat(heartbeat) = message {
+            if !self.heartbeats.received(*announcer, heartbeat) {
+                debug!(target: "service", "Ignoring stale or early heartbeat from {announcer} (t={timestamp})");
+                return Ok(false);
+            }
+            return Ok(relay);
+        }
+
+        // Inventory pages aren't stored with other gossip messages either. Every new page
+        // is relayed on its own, while the inventory is only processed once complete.
+        if let AnnouncementMessage::InventoryPage(page) = message {
+            match self.pages.received(*announcer, page) {
+                inventory::Received::Stale => {
+                    debug!(target: "service", "Ignoring stale or invalid inventory page from {announcer} (t={timestamp})");
+                    return Ok(false);
+                }
+                inventory::Received::Partial => {}
+                inventory::Received::Complete(inventory) => {
+                    self.inventory_announced(announcer, &inventory, page.timestamp);
+                }
+            }
+            return Ok(relay);
+        }
+
+        // Check the signed refs we already have before storing or relaying the announcement.
+        // Nb. The announcer signed the announcement, so it's the one held responsible. A peer
+        // relaying it could have checked it too, but may not have had the refs.
+        if let AnnouncementMessage::Refs(message) = message {
+            if !self.verify_refs(message) {
+                if announcer == relayer {
+                    return Err(session::Error::Misbehavior);
+                }
+                warn!(target: "service", "Dropping refs announcement of {announcer} relayed by {relayer}: invalid signed refs");
+
+                for (nid, severity) in [(announcer, Severity::High), (relayer, Severity::Medium)] {
+                    if let Err(e) = self.db.addresses_mut().penalize(nid, severity) {
+                        error!(target: "service", "Error penalizing {nid}: {e}");
+                    }
+                }
+                return Ok(false);
+            }
+        }
+
         // Discard announcement messages we've already seen, otherwise update our last seen time.
-        match self.db.gossip_mut().announced(announcer, announcement) {
+        match self
+            .db
+            .gossip_mut()
+            .announced(announcer, announcement, relayer)
+        {
             Ok(fresh) => {
                 if !fresh {
-                    debug!(target: "service", "Ignoring stale announcement from {announcer} (t={timestamp})");
+                    debug!(target: "service", "Ignoring stale or replayed announcement from {announcer} (t={timestamp})");
                     return Ok(false);
                 }
             }
@@ -1385,61 +2512,24 @@ where
         match message {
             // Process a peer inventory update announcement by (maybe) fetching.
             AnnouncementMessage::Inventory(message) => {
-                self.emitter.emit(Event::InventoryAnnounced {
-                    nid: *announcer,
-                    inventory: message.inventory.to_vec(),
-                    timestamp: message.timestamp,
-                });
-                match self.sync_routing(
-                    message.inventory.iter().cloned(),
-                    *announcer,
+                // Nodes with large inventories also announce them in full to nodes that don't
+                // support inventory pages, as much as fits. Don't let such an announcement
+                // override the inventory we got in pages.
+                if self
+                    .pages
+                    .latest(announcer)
+                    .is_some_and(|latest| *latest >= *message.timestamp)
+                {
+                    debug!(target: "service", "Skipping inventory of {announcer}, already received in pages (t={timestamp})");
+                    return Ok(relay);
+                }
+                if !self.inventory_announced(
+                    announcer,
+                    message.inventory.as_slice(),
                     message.timestamp,
                 ) {
-                    Ok(synced) => {
-                        if synced.is_empty() {
-                            trace!(target: "service", "No routes updated by inventory announcement from {announcer}");
-                            return Ok(false);
-                        }
-                    }
-                    Err(e) => {
-                        error!(target: "service", "Error processing inventory from {announcer}: {e}");
-                        return Ok(false);
-                    }
-                }
-
-                for id in message.inventory.as_slice() {
-                    // TODO: Move this out (good luck with the borrow checker).
-                    if let Some(sess) = self.sessions.get_mut(announcer) {
-                        // If we are connected to the announcer of this inventory, update the peer's
-                        // subscription filter to include all inventory items. This way, we'll
-                        // relay messages relating to the peer's inventory.
-                        if let Some(sub) = &mut sess.subscribe {
-                            sub.filter.insert(id);
-                        }
-
-                        // If we're seeding and connected to the announcer, and we don't have
-                        // the inventory, fetch it from the announcer.
-                        if self.policies.is_seeding(id).expect(
-                            "Service::handle_announcement: error accessing seeding configuration",
-                        ) {
-                            // Only if we do not have the repository locally do we fetch here.
-                            // If we do have it, only fetch after receiving a ref announcement.
-                            match self.storage.contains(id) {
-                                Ok(true) => {
-                                    // Do nothing.
-                                }
-                                Ok(false) => {
-                                    debug!(target: "service", "Missing seeded inventory {id}; initiating fetch..");
-                                    self.fetch(*id, *announcer, FETCH_TIMEOUT, None);
-                                }
-                                Err(e) => {
-                                    error!(target: "service", "Error checking local inventory: {e}");
-                                }
-                            }
-                        }
-                    }
+                    return Ok(false);
                 }
-
                 return Ok(relay);
             }
             AnnouncementMessage::Refs(message) => {
@@ -1499,7 +2589,7 @@ where
                 let repo_entry = self.policies.seed_policy(&message.rid).expect(
                     "Service::handle_announcement: error accessing repo seeding configuration",
                 );
-                if repo_entry.policy != Policy::Allow {
+                if repo_entry.policy != Policy::Allow && !self.auto_seed(message.rid, &refs) {
                     debug!(
                         target: "service",
                         "Ignoring refs announcement from {announcer}: repository {} isn't seeded (t={timestamp})",
@@ -1518,15 +2608,23 @@ where
                     );
                     return Ok(relay);
                 };
-                // Finally, start the fetch.
-                self.fetch_refs_at(
-                    message.rid,
-                    remote.id,
-                    refs,
-                    repo_entry.scope,
-                    FETCH_TIMEOUT,
-                    None,
-                );
+                // Only schedule a fetch if there's something we don't have.
+                match message.is_fresh(self.db.refs()) {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        debug!(target: "service", "Skipping fetch for {}, all refs are already in storage", message.rid);
+                        return Ok(relay);
+                    }
+                    Err(e) => {
+                        error!(target: "service", "Error getting the refs status of {}: {e}", message.rid);
+                        return Ok(relay);
+                    }
+                }
+                // Finally, schedule the fetch.
+                self.scheduler
+                    .queue(message.rid, remote.id, refs, self.clock.local_time());
+                self.dispatch_fetches();
+
                 return Ok(relay);
             }
             AnnouncementMessage::Node(
@@ -1543,11 +2641,66 @@ where
                     features: *features,
                     addresses: addresses.to_vec(),
                 });
+                // Keep track of what our peers support, so that we know what we can send them.
+                if let Some(session) = self.sessions.get_mut(announcer) {
+                    session.features = *features;
+
+                    if self.check_features(announcer, *features) {
+                        return Ok(relay);
+                    }
+                }
+                // Ignore non-routable addresses unless explicitly allowed, or received from a
+                // local network peer. This allows the node to function in a local network.
+                let local = self.config.local_addresses || relayer_addr.is_local();
+                let mut addresses = announcement
+                    .addresses()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|(a, _)| local || a.is_routable())
+                    .collect::<Vec<_>>();
+                // Don't relay announcements that only consist of addresses that are of no use
+                // to other nodes.
+                let mut relay = if addresses.is_empty() && !ann.addresses.is_empty() {
+                    debug!(target: "service", "Ignoring unroutable addresses of node {announcer}");
+                    false
+                } else {
+                    relay
+                };
                 // If this node isn't a seed, we're not interested in adding it
                 // to our address book, but other nodes may be, so we relay the message anyway.
                 if !features.has(Features::SEED) {
                     return Ok(relay);
                 }
+                // Limit how often a node's addresses can change, so that a node can't flood our
+                // address book, or have us relay a stream of new addresses.
+                let known = match self.db.addresses().get(announcer) {
+                    Ok(node) => node.map(|n| n.addrs).unwrap_or_default(),
+                    Err(err) => {
+                        error!(target: "service", "Error reading addresses of {announcer}: {err}");
+                        vec![]
+                    }
+                };
+                let changed = addresses
+                    .iter()
+                    .any(|(a, _)| !known.iter().any(|k| &k.addr == a));
+
+                if changed {
+                    let now = self.clock.local_time();
+
+                    match self.address_changes.get(announcer) {
+                        Some(last) if now - *last < MIN_ADDRESS_CHANGE_DELTA => {
+                            debug!(
+                                target: "service",
+                                "Ignoring new addresses of node {announcer}: addresses changed too recently"
+                            );
+                            addresses.retain(|(a, _)| known.iter().any(|k| &k.addr == a));
+                            relay = false;
+                        }
+                        _ => {
+                            self.address_changes.insert(*announcer, now);
+                        }
+                    }
+                }
 
                 match self.db.addresses_mut().insert(
                     announcer,
@@ -1555,12 +2708,9 @@ where
                     ann.alias.clone(),
                     ann.work(),
                     timestamp,
-                    addresses
-                        .iter()
-                        // Ignore non-routable addresses unless received from a local network
-                        // peer. This allows the node to function in a local network.
-                        .filter(|a| a.is_routable() || relayer_addr.is_local())
-                        .map(|a| KnownAddress::new(a.clone(), address::Source::Peer)),
+                    addresses.into_iter().map(|(a, label)| {
+                        KnownAddress::new(a, address::Source::Peer).labeled(label)
+                    }),
                 ) {
                     Ok(updated) => {
                         // Only relay if we received new information.
@@ -1578,10 +2728,76 @@ where
                     }
                 }
             }
+            // Heartbeats and inventory pages are handled before the gossip store is updated.
+            AnnouncementMessage::Heartbeat(_) | AnnouncementMessage::InventoryPage(_) => {}
         }
         Ok(false)
     }
 
+    /// Process the full inventory of a node, whether it was announced at once or in pages.
+    ///
+    /// Returns `false` if no routes were updated by it.
+    fn inventory_announced(
+        &mut self,
+        announcer: &NodeId,
+        inventory: &[RepoId],
+        timestamp: Timestamp,
+    ) -> bool {
+        self.emitter.emit(Event::InventoryAnnounced {
+            nid: *announcer,
+            inventory: inventory.to_vec(),
+            timestamp,
+        });
+        match self.sync_routing(inventory.iter().cloned(), *announcer, timestamp) {
+            Ok(synced) => {
+                if synced.is_empty() {
+                    trace!(target: "service", "No routes updated by inventory announcement from {announcer}");
+                    return false;
+                }
+            }
+            Err(e) => {
+                error!(target: "service", "Error processing inventory from {announcer}: {e}");
+                return false;
+            }
+        }
+
+        for id in inventory {
+            // TODO: Move this out (good luck with the borrow checker).
+            if let Some(sess) = self.sessions.get_mut(announcer) {
+                // If we are connected to the announcer of this inventory, update the peer's
+                // subscription filter to include all inventory items. This way, we'll
+                // relay messages relating to the peer's inventory.
+                if let Some(sub) = &mut sess.subscribe {
+                    sub.filter.insert(id);
+                }
+
+                // If we're seeding and connected to the announcer, and we don't have
+                // the inventory, fetch it from the announcer.
+                if self
+                    .policies
+                    .is_seeding(id)
+                    .expect("Service::inventory_announced: error accessing seeding configuration")
+                {
+                    // Only if we do not have the repository locally do we fetch here.
+                    // If we do have it, only fetch after receiving a ref announcement.
+                    match self.storage.contains(id) {
+                        Ok(true) => {
+                            // Do nothing.
+                        }
+                        Ok(false) => {
+                            debug!(target: "service", "Missing seeded inventory {id}; initiating fetch..");
+                            self.fetch(*id, *announcer, FETCH_TIMEOUT, None);
+                        }
+                        Err(e) => {
+                            error!(target: "service", "Error checking local inventory: {e}");
+                        }
+                    }
+                }
+            }
+        }
+        true
+    }
+
     pub fn handle_info(&mut self, remote: NodeId, info: &Info) -> Result<(), session::Error> {
         match info {
             // Nb. We don't currently send this message.
@@ -1593,11 +2809,74 @@ where
                     at: *at,
                 });
             }
+            Info::ObservedAddress { addr } => {
+                self.observed_address(remote, addr);
+            }
+            Info::MissingInventory { node } => {
+                // Send the full inventory, which further inventories can be diffs against.
+                self.outbox.inventory_missing(&remote, node);
+
+                let Some(peer) = self.sessions.get(&remote) else {
+                    return Ok(());
+                };
+                match self.db.gossip().inventory(node) {
+                    Ok(Some(ann)) => self.outbox.write(peer, ann.into()),
+                    Ok(None) => {}
+                    Err(e) => {
+                        error!(target: "service", "Error getting inventory of {node} from the gossip store: {e}");
+                    }
+                }
+            }
         }
 
         Ok(())
     }
 
+    /// Handle an address observed for us by a peer. Once enough peers agree on our
+    /// external address, it is included in our node announcement, alongside any
+    /// configured external addresses.
+    fn observed_address(&mut self, remote: NodeId, addr: &Address) {
+        let Some(session) = self.sessions.get(&remote) else {
+            return;
+        };
+        // Only observations from peers we connected to are counted, since inbound
+        // connections are much cheaper to come by for an attacker.
+        if session.link.is_inbound() {
+            return;
+        }
+        let HostName::Ip(ip) = addr.host else {
+            return;
+        };
+        let Some(ip) = self.observed.observe(remote, ip) else {
+            return;
+        };
+        info!(target: "service", "External address {ip} confirmed by peers");
+
+        // Prefer the port we're listening on for the same address family.
+        let Some(port) = self
+            .listening
+            .iter()
+            .find(|a| a.is_ipv6() == ip.is_ipv6())
+            .or(self.listening.first())
+            .map(|a| a.port())
+        else {
+            debug!(target: "service", "Not announcing external address {ip}: not listening");
+            return;
+        };
+        let addr = Address::from(net::SocketAddr::new(ip, port));
+        if self.node.addresses.contains(&addr) {
+            return;
+        }
+        let mut node = self.node.clone();
+        if node.addresses.push(addr.clone()).is_err() {
+            warn!(target: "service", "Not announcing external address {addr}: address limit reached");
+            return;
+        }
+        info!(target: "service", "Announcing external address {addr}..");
+
+        self.announce_node(node);
+    }
+
     pub fn handle_message(
         &mut self,
         remote: &NodeId,
@@ -1607,7 +2886,7 @@ where
             warn!(target: "service", "Session not found for {remote}");
             return Ok(());
         };
-        peer.last_active = self.clock;
+        peer.last_active = self.clock.local_time();
 
         let limit = match peer.link {
             Link::Outbound => &self.config.limits.rate.outbound,
@@ -1615,7 +2894,7 @@ where
         };
         if self
             .limiter
-            .limit(peer.addr.clone().into(), limit, self.clock)
+            .limit(peer.addr.clone().into(), limit, self.clock.local_time())
         {
             trace!(target: "service", "Rate limiting message from {remote} ({})", peer.addr);
             return Ok(());
@@ -1624,6 +2903,35 @@ where
 
         trace!(target: "service", "Received message {:?} from {}", &message, peer.id);
 
+        // Inventory diffs are handled as the announcement they are rebuilt into. If we don't
+        // have the inventory they apply to, we ask for the full inventory instead.
+        let message = match message {
+            Message::InventoryDiff(diff) => {
+                let base = match self.db.gossip().inventory(&diff.node) {
+                    Ok(base) => base,
+                    Err(e) => {
+                        error!(target: "service", "Error getting inventory of {} from the gossip store: {e}", diff.node);
+                        None
+                    }
+                };
+                match base
+                    .as_ref()
+                    .and_then(Announcement::inventory)
+                    .and_then(|base| diff.apply(&base.inventory))
+                {
+                    Some(ann) => Message::Announcement(ann),
+                    None => {
+                        debug!(target: "service", "Missing base inventory {} of {} for diff from {remote}", diff.base, diff.node);
+                        self.outbox
+                            .write(peer, Info::MissingInventory { node: diff.node }.into());
+
+                        return Ok(());
+                    }
+                }
+            }
+            message => message,
+        };
+
         match (&mut peer.state, message) {
             // Process a peer announcement.
             (session::State::Connected { .. }, Message::Announcement(ann)) => {
@@ -1631,18 +2939,56 @@ where
                 let relayer_addr = peer.addr.clone();
                 let announcer = ann.node;
 
+                // The relayer has this inventory, and the one we had before is what we
+                // relay it as a diff against.
+                self.outbox.inventory_received(relayer, &ann);
+                let base = match &ann.message {
+                    AnnouncementMessage::Inventory(_) => self
+                        .db
+                        .gossip()
+                        .inventory(&announcer)
+                        .unwrap_or_else(|e| {
+                            error!(target: "service", "Error getting inventory of {announcer} from the gossip store: {e}");
+                            None
+                        })
+                        .and_then(|ann| ann.inventory().cloned()),
+                    _ => None,
+                };
+
                 // Returning true here means that the message should be relayed.
-                if self.handle_announcement(&relayer_addr, &ann)? {
-                    // Choose peers we should relay this message to.
-                    // 1. Don't relay to the peer who sent us this message.
-                    // 2. Don't relay to the peer who signed this announcement.
+                if self.handle_announcement(&relayer, &relayer_addr, &ann)? {
+                    // Announcements that were relayed over the maximum number of hops are
+                    // processed, but not relayed further.
+                    if ann.hops >= self.config.limits.relay_hops {
+                        debug!(
+                            target: "service",
+                            "Not relaying announcement of {announcer} from {relayer} after {} hop(s)",
+                            ann.hops
+                        );
+                        return Ok(());
+                    }
+                    // Don't relay heartbeats or inventory pages to peers that don't understand
+                    // them. Other peers are chosen by the outbox, see [`Outbox::relay`].
+                    let required = match ann.message {
+                        AnnouncementMessage::Heartbeat(_) => node::Features::HEARTBEAT,
+                        AnnouncementMessage::InventoryPage(_) => node::Features::INVENTORY_PAGES,
+                        _ => node::Features::NONE,
+                    };
                     let relay_to = self
                         .sessions
                         .connected()
-                        .filter(|(id, _)| *id != &relayer && *id != &announcer)
-                        .map(|(_, p)| p);
-
-                    self.outbox.relay(ann, relay_to);
+                        .filter(|(id, _)| {
+                            required == node::Features::NONE || self.has_features(id, required)
+                        })
+                        .map(|(id, _)| *id)
+                        .collect::<Vec<_>>();
+
+                    self.outbox.relay(
+                        ann.relayed(),
+                        &relayer,
+                        relay_to.iter().filter_map(|id| self.sessions.get(id)),
+                        base.as_ref(),
+                    );
 
                     return Ok(());
                 }
@@ -1656,17 +3002,27 @@ where
                 {
                     Ok(anns) => {
                         for ann in anns {
-                            let ann = match ann {
+                            let (ann, relayer) = match ann {
                                 Ok(a) => a,
                                 Err(e) => {
                                     error!(target: "service", "Error reading gossip message from store: {e}");
                                     continue;
                                 }
                             };
-                            // Don't send announcements authored by the remote, back to the remote.
-                            if ann.node == *remote {
+                            // Don't send announcements authored or relayed by the remote, back to
+                            // the remote.
+                            if ann.node == *remote || relayer == *remote {
                                 continue;
                             }
+                            // Announcements of other nodes are relayed, and subject to the same
+                            // hop limit as when they were received.
+                            let ann = if ann.node == *self.signer.public_key() {
+                                ann
+                            } else if ann.hops < self.config.limits.relay_hops {
+                                ann.relayed()
+                            } else {
+                                continue;
+                            };
                             self.outbox.write(peer, ann.into());
                         }
                     }
@@ -1706,13 +3062,33 @@ where
                     if (ponglen as usize) == zeroes.len() {
                         *ping = session::PingState::Ok;
                         // Keep track of peer latency.
-                        latencies.push_back(self.clock - since);
+                        latencies.push_back(self.clock.local_time() - since);
                         if latencies.len() > MAX_LATENCIES {
                             latencies.pop_front();
                         }
                     }
                 }
             }
+            (session::State::Connected { .. }, Message::Rendezvous(Rendezvous { node })) => {
+                let remote = peer.id;
+                self.handle_rendezvous(remote, node);
+            }
+            (session::State::Connected { .. }, Message::ConnectTo(ConnectTo { node, addr })) => {
+                let remote = peer.id;
+                self.handle_connect_to(remote, node, addr);
+            }
+            (session::State::Connected { .. }, Message::Disconnect { reason }) => {
+                // Keep track of the reason, in case the peer closes the connection before we do.
+                peer.disconnect = Some(reason);
+                self.outbox
+                    .disconnect(peer.id, DisconnectReason::Remote(reason));
+            }
+            (session::State::Connected { .. }, Message::InventoryDiff(_)) => {
+                // Nb. Inventory diffs are rebuilt into announcements above.
+            }
+            (session::State::Connected { .. }, Message::Compressed(_)) => {
+                // Nb. Compressed messages are decompressed when decoded.
+            }
             (session::State::Attempted { .. } | session::State::Initial, msg) => {
                 debug!(target: "service", "Ignoring unexpected message {:?} from connecting peer {}", msg, peer.id);
             }
@@ -1764,9 +3140,9 @@ where
     }
 
     /// Set of initial messages to send to a peer.
-    fn initial(&mut self, _link: Link) -> Vec<Message> {
+    fn initial(&mut self, remote: &NodeId, _link: Link) -> Vec<Message> {
         let timestamp = self.timestamp();
-        let now = self.clock();
+        let now = self.clock.network_time();
         let filter = self.filter();
         let inventory = match self.storage.inventory() {
             Ok(i) => i,
@@ -1781,35 +3157,68 @@ where
         // TODO: Only subscribe to outbound connections, otherwise we will consume too
         // much bandwidth.
 
-        // If we've been previously connected to the network, we'll have received gossip messages.
-        // Instead of simply taking the last timestamp we try to ensure we don't miss any
-        // messages due un-synchronized clocks.
+        // If this peer relayed gossip messages to us before, we only ask for the ones we
+        // haven't received from it yet.
+        //
+        // Otherwise, if we've been previously connected to the network, we'll have received
+        // gossip messages. Instead of simply taking the last timestamp we try to ensure we
+        // don't miss any messages due un-synchronized clocks.
         //
         // If this is our first connection to the network, we just ask for a fixed backlog
         // of messages to get us started.
-        let since = match self.db.gossip().last() {
-            Ok(Some(last)) => Timestamp::from(last.to_local_time() - MAX_TIME_DELTA),
-            Ok(None) => (*now - INITIAL_SUBSCRIBE_BACKLOG_DELTA).into(),
+        let delivered = match self.db.gossip().last_delivered(remote) {
+            Ok(delivered) => delivered,
             Err(e) => {
-                error!(target: "service", "Error getting the lastest gossip message from storage: {e}");
-                return vec![];
+                error!(target: "service", "Error getting the gossip delivered by {remote}: {e}");
+                None
+            }
+        };
+        let since = if let Some(delivered) = delivered {
+            delivered
+        } else {
+            match self.db.gossip().last() {
+                Ok(Some(last)) => Timestamp::from(last.to_local_time() - MAX_TIME_DELTA),
+                Ok(None) => (now - INITIAL_SUBSCRIBE_BACKLOG_DELTA).into(),
+                Err(e) => {
+                    error!(target: "service", "Error getting the lastest gossip message from storage: {e}");
+                    return vec![];
+                }
             }
         };
 
         debug!(target: "service", "Subscribing to messages since timestamp {since}..");
 
-        vec![
-            Message::node(self.node.clone(), &self.signer),
-            Message::inventory(gossip::inventory(timestamp, inventory), &self.signer),
-            Message::subscribe(filter, since, Timestamp::MAX),
-        ]
+        // Peers that support inventory pages get all of our inventory, if it doesn't fit in
+        // a single announcement.
+        let inventory = self.ordered_inventory(inventory);
+        let paged = inventory.len() > INVENTORY_LIMIT
+            && self
+                .sessions
+                .get(remote)
+                .is_some_and(|s| s.features.has(Features::INVENTORY_PAGES));
+
+        let mut msgs = vec![self.sign_announcement(self.node.clone()).into()];
+        if paged {
+            for page in gossip::inventory_pages(timestamp, &inventory) {
+                msgs.push(self.sign_announcement(page).into());
+            }
+        } else {
+            msgs.push(
+                self.sign_announcement(gossip::inventory(timestamp, inventory))
+                    .into(),
+            );
+        }
+        msgs.push(Message::subscribe(filter, since, Timestamp::MAX));
+        msgs
     }
 
     /// Try to guess whether we're online or not.
     fn is_online(&self) -> bool {
         self.sessions
             .connected()
-            .filter(|(_, s)| s.addr.is_routable() && s.last_active >= self.clock - IDLE_INTERVAL)
+            .filter(|(_, s)| {
+                s.addr.is_routable() && s.last_active >= self.clock.local_time() - IDLE_INTERVAL
+            })
             .count()
             > 0
     }
@@ -1817,7 +3226,8 @@ where
     /// Update our routing table with our local node's inventory.
     fn sync_inventory(&mut self) -> Result<SyncedRouting, Error> {
         let inventory = self.storage.inventory()?;
-        let result = self.sync_routing(inventory, self.node_id(), self.clock.into())?;
+        let result =
+            self.sync_routing(inventory, self.node_id(), self.clock.local_time().into())?;
 
         Ok(result)
     }
@@ -1876,7 +3286,7 @@ where
         &mut self,
         rid: RepoId,
         remotes: impl IntoIterator<Item = NodeId>,
-    ) -> Result<(Announcement, Vec<RefsAt>), Error> {
+    ) -> Result<(RefsAnnouncement, Vec<RefsAt>), Error> {
         let repo = self.storage.repository(rid)?;
         let timestamp = self.timestamp();
         let mut refs = BoundedVec::<_, REF_REMOTE_LIMIT>::new();
@@ -1894,12 +3304,12 @@ where
             }
         }
 
-        let msg = AnnouncementMessage::from(RefsAnnouncement {
+        let msg = RefsAnnouncement {
             rid,
             refs: refs.clone(),
             timestamp,
-        });
-        Ok((msg.signed(&self.signer), refs.into()))
+        };
+        Ok((msg, refs.into()))
     }
 
     /// Announce our own refs for the given repo.
@@ -1939,37 +3349,33 @@ where
         doc: Doc<Verified>,
         remotes: impl IntoIterator<Item = NodeId>,
     ) -> Result<(Vec<RefsAt>, Timestamp), Error> {
-        let (ann, refs) = self.refs_announcement_for(rid, remotes)?;
-        let timestamp = ann.timestamp();
-        let peers = self.sessions.connected().map(|(_, p)| p);
+        let (msg, refs) = self.refs_announcement_for(rid, remotes)?;
+        let timestamp = msg.timestamp;
+        let nid = self.node_id();
 
         // Update our sync status for our own refs. This is useful for determining if refs were
         // updated while the node was stopped.
         // TODO: Move to `announce_own_refs`.
-        if let Some(refs) = refs.iter().find(|r| r.remote == ann.node) {
+        if let Some(refs) = refs.iter().find(|r| r.remote == nid) {
             info!(
                 target: "service",
                 "Announcing own refs for {rid} to peers ({}) (t={timestamp})..",
                 refs.at
             );
 
-            if let Err(e) = self
-                .db
-                .seeds_mut()
-                .synced(&rid, &ann.node, refs.at, timestamp)
-            {
+            if let Err(e) = self.db.seeds_mut().synced(&rid, &nid, refs.at, timestamp) {
                 error!(target: "service", "Error updating sync status for local node: {e}");
             }
         }
 
-        self.outbox.announce(
-            ann,
-            peers.filter(|p| {
-                // Only announce to peers who are allowed to view this repo.
-                doc.is_visible_to(&p.id)
-            }),
-            self.db.gossip_mut(),
-        );
+        // Private repositories are never announced.
+        if self.is_private(&rid) {
+            debug!(target: "service", "Skipping refs announcement for private repository {rid}");
+            return Ok((refs, timestamp));
+        }
+        // Only announce to peers who are allowed to view this repo.
+        self.send_announcement(msg, Route::Visible(doc));
+
         Ok((refs, timestamp))
     }
 
@@ -1993,6 +3399,177 @@ where
         }
     }
 
+    /// Handle a rendezvous request from a peer, if we're acting as a bridge.
+    fn handle_rendezvous(&mut self, remote: NodeId, node: NodeId) {
+        if self.config.bridge.is_none() {
+            debug!(target: "service", "Ignoring rendezvous request from {remote}: bridging is disabled");
+            return;
+        }
+        if node == remote {
+            return;
+        }
+        let now = self.clock.local_time();
+        let allowed = self
+            .rendezvous_limits
+            .entry(remote)
+            .or_insert_with(|| TokenBucket::new(MAX_RENDEZVOUS_BURST, RENDEZVOUS_FILL_RATE, now))
+            .take_n(1, now);
+        if !allowed {
+            debug!(target: "service", "Rate limiting rendezvous request from {remote}..");
+            return;
+        }
+        let (Some(from), Some(to)) = (self.sessions.get(&remote), self.sessions.get(&node)) else {
+            return;
+        };
+        if !to.is_connected() {
+            debug!(target: "service", "Ignoring rendezvous request from {remote}: {node} is not connected");
+            return;
+        }
+        // Nb. Both peers are sent each other's address as we observe it, which for peers
+        // behind NAT is the external address of the connection.
+        self.outbox.write(
+            from,
+            Message::ConnectTo(ConnectTo {
+                node,
+                addr: to.addr.clone(),
+            }),
+        );
+        self.outbox.write(
+            to,
+            Message::ConnectTo(ConnectTo {
+                node: remote,
+                addr: from.addr.clone(),
+            }),
+        );
+    }
+
+    /// Handle a rendezvous introduction from a bridge, by dialing the given peer.
+    /// Only introductions we asked for are followed, so that bridges can't have us dial
+    /// arbitrary addresses.
+    fn handle_connect_to(&mut self, remote: NodeId, node: NodeId, addr: Address) {
+        if !self.has_features(&remote, node::Features::BRIDGE) {
+            debug!(target: "service", "Ignoring introduction to {node} from {remote}: not a bridge");
+            return;
+        }
+        if node == self.node_id() {
+            return;
+        }
+        let now = self.clock.local_time();
+        match self.rendezvous.remove(&(remote, node)) {
+            Some(since) if now - since < RENDEZVOUS_TIMEOUT => {}
+            _ => {
+                debug!(target: "service", "Ignoring introduction to {node} from {remote}: not requested");
+                return;
+            }
+        }
+        match self.sessions.get_mut(&node) {
+            Some(session) if session.is_disconnected() => {
+                debug!(target: "service", "Reconnecting to {node} ({addr}) via rendezvous with {remote}..");
+                session.to_initial();
+                self.outbox.punch(node, addr);
+            }
+            Some(_) => {
+                debug!(target: "service", "Ignoring introduction to {node} from {remote}: already connected");
+            }
+            None => {
+                debug!(target: "service", "Connecting to {node} ({addr}) via rendezvous with {remote}..");
+                if self.attempt(node, addr.clone()) {
+                    self.outbox.punch(node, addr);
+                }
+            }
+        }
+    }
+
+    /// Ask connected bridges to introduce us to the given peer.
+    fn rendezvous(&mut self, node: NodeId) {
+        let now = self.clock.local_time();
+        let bridges = self
+            .sessions
+            .connected()
+            .map(|(nid, _)| *nid)
+            .filter(|nid| self.has_features(nid, node::Features::BRIDGE))
+            .collect::<Vec<_>>();
+
+        self.rendezvous
+            .retain(|_, since| now - *since < RENDEZVOUS_TIMEOUT);
+
+        for nid in bridges {
+            if let Some(bridge) = self.sessions.get(&nid) {
+                self.outbox
+                    .write(bridge, Message::Rendezvous(Rendezvous { node }));
+                self.rendezvous.insert((nid, node), now);
+            }
+        }
+    }
+
+    /// Spot-check the signed refs a refs announcement points to, for the ones we already have
+    /// in storage. Returns `false` if any of them isn't validly signed by its remote, which
+    /// means the announcement is bogus.
+    fn verify_refs(&self, message: &RefsAnnouncement) -> bool {
+        let Ok(repo) = self.storage.repository(message.rid) else {
+            // We don't have the repository, so there's nothing to check against.
+            return true;
+        };
+        for RefsAt { remote, at } in message.refs.iter() {
+            if repo.commit(*at).is_err() {
+                continue;
+            }
+            match SignedRefs::load_at(*at, *remote, &repo) {
+                Ok(_) => {}
+                Err(e @ (refs::Error::InvalidSignature(_) | refs::Error::Canonical(_))) => {
+                    debug!(target: "service", "Invalid signed refs of {remote} at {at} for {}: {e}", message.rid);
+                    return false;
+                }
+                Err(e) if e.is_not_found() => {
+                    debug!(target: "service", "Commit {at} of {remote} for {} isn't a signed refs commit", message.rid);
+                    return false;
+                }
+                Err(e) => {
+                    error!(target: "service", "Error loading signed refs of {remote} at {at} for {}: {e}", message.rid);
+                }
+            }
+        }
+        true
+    }
+
+    /// Disconnect the given peer if it doesn't advertise the features we require of peers.
+    /// Returns `true` if the peer is being disconnected.
+    fn check_features(&mut self, nid: &NodeId, features: node::Features) -> bool {
+        let missing = features.missing(self.config.required_features);
+        if missing == node::Features::NONE || self.config.is_persistent(nid) {
+            return false;
+        }
+        debug!(target: "service", "Disconnecting {nid}: missing required features {missing}");
+
+        self.outbox
+            .disconnect(*nid, DisconnectReason::MissingFeatures(missing));
+        true
+    }
+
+    /// Get the connected peers that advertise the given features.
+    pub fn peers_with(&self, features: node::Features) -> impl Iterator<Item = &NodeId> {
+        self.sessions
+            .connected()
+            .filter(move |(_, s)| s.features.has(features))
+            .map(|(nid, _)| nid)
+    }
+
+    /// Check whether the given peer advertises the given features, eg. that it's a bridge.
+    fn has_features(&self, nid: &NodeId, features: node::Features) -> bool {
+        self.features(nid).has(features)
+    }
+
+    /// Get the features of a node, as last announced.
+    fn features(&self, nid: &NodeId) -> node::Features {
+        match self.db.addresses().get(nid) {
+            Ok(node) => node.map_or(node::Features::NONE, |n| n.features),
+            Err(e) => {
+                error!(target: "service", "Error looking up node {nid} in address book: {e}");
+                node::Features::NONE
+            }
+        }
+    }
+
     fn reconnect(&mut self, nid: NodeId, addr: Address) -> bool {
         if let Some(sess) = self.sessions.get_mut(&nid) {
             sess.to_initial();
@@ -2014,28 +3591,39 @@ where
             error!(target: "service", "Attempted connection to self");
             return false;
         }
+        if !self.attempt(nid, addr.clone()) {
+            return false;
+        }
+        self.outbox.connect(nid, addr);
+
+        true
+    }
+
+    /// Create a session for an outbound connection attempt to the given peer.
+    /// Returns `false` if we're at our outbound connection limit.
+    fn attempt(&mut self, nid: NodeId, addr: Address) -> bool {
         if self.sessions.outbound().count() >= self.config.limits.connection.outbound {
             error!(target: "service", "Outbound connection limit reached when attempting {nid} ({addr})");
             return false;
         }
         let persistent = self.config.is_persistent(&nid);
-        let timestamp: Timestamp = self.clock.into();
+        let timestamp: Timestamp = self.clock.local_time().into();
 
         if let Err(e) = self.db.addresses_mut().attempted(&nid, &addr, timestamp) {
             error!(target: "service", "Error updating address book with connection attempt: {e}");
         }
+        self.dialer
+            .attempted(nid, addr.clone(), self.clock.local_time());
         self.sessions.insert(
             nid,
             Session::outbound(
                 nid,
-                addr.clone(),
+                addr,
                 persistent,
                 self.rng.clone(),
                 self.config.limits.clone(),
             ),
         );
-        self.outbox.connect(nid, addr);
-
         true
     }
 
@@ -2060,7 +3648,10 @@ where
                             remote: seed.synced_at,
                         }
                     };
-                    seeds.insert(Seed::new(seed.nid, seed.addresses, state, Some(synced)));
+                    let mut seed = Seed::new(seed.nid, seed.addresses, state, Some(synced));
+                    seed.health = self.heartbeats.health(&seed.nid);
+
+                    seeds.insert(seed);
                 }
             }
         }
@@ -2079,11 +3670,63 @@ where
             let addrs = self.db.addresses().addresses_of(&nid)?;
             let state = self.sessions.get(&nid).map(|s| s.state.clone());
 
-            seeds.insert(Seed::new(nid, addrs, state, None));
+            let mut seed = Seed::new(nid, addrs, state, None);
+            seed.health = self.heartbeats.health(&nid);
+
+            seeds.insert(seed);
         }
         Ok(seeds)
     }
 
+    /// Get the stored repositories, with their seeding policy and sync status.
+    fn repos(&self) -> Result<Vec<repos::Repo>, Error> {
+        let factor = self.config.replication_factor;
+        let mut repos = Vec::new();
+
+        for rid in self.storage.repositories()?.into_iter().map(|r| r.rid) {
+            let policy = self.policies.seed_policy(&rid)?;
+            let namespaces = self.storage.repository(rid)?.remote_refs_at()?.len();
+            let fetched = self.db.repos().last_fetch(&rid)?;
+            let announced = self.db.gossip().last_refs(&rid)?;
+            // Seeds are only ever considered synced if we have local refs. Our own sync
+            // status is also tracked, and doesn't count.
+            let replicas = self
+                .seeds(&rid)?
+                .iter()
+                .filter(|s| s.nid != self.node_id() && s.is_synced())
+                .count();
+
+            repos.push(repos::Repo {
+                rid,
+                policy: policy.policy,
+                scope: policy.scope,
+                namespaces,
+                fetched,
+                announced,
+                replicas,
+                replicated: replicas >= factor.max(1),
+            });
+        }
+        Ok(repos)
+    }
+
+    /// Check whether a repository is marked as private in our policies.
+    fn is_private(&self, rid: &RepoId) -> bool {
+        self.policies.is_private(rid).unwrap_or_else(|e| {
+            // Err on the side of caution, and treat the repository as private.
+            error!(target: "service", "Error reading private policy of {rid}: {e}");
+            true
+        })
+    }
+
+    /// Remove private repositories from an inventory, so that it can be announced.
+    fn public(&self, inventory: Inventory) -> Inventory {
+        inventory
+            .into_iter()
+            .filter(|rid| !self.is_private(rid))
+            .collect()
+    }
+
     /// Return a new filter object, based on our seeding policy.
     fn filter(&self) -> Filter {
         if self.config.policy == Policy::Allow {
@@ -2097,7 +3740,7 @@ where
     /// Get a timestamp for using in announcements.
     /// Never returns the same timestamp twice.
     fn timestamp(&mut self) -> Timestamp {
-        let now = Timestamp::from(self.clock);
+        let now = Timestamp::from(self.clock.network_time());
         if *now > *self.last_timestamp {
             self.last_timestamp = now;
         } else {
@@ -2106,6 +3749,132 @@ where
         self.last_timestamp
     }
 
+    /// Sign an announcement of ours, and number it with our next sequence number.
+    ///
+    /// If the sequence number can't be obtained, or if the sequenced announcement wouldn't
+    /// fit in a message, eg. because of a large inventory, the announcement is left
+    /// unsequenced.
+    fn sign_announcement(&mut self, msg: impl Into<AnnouncementMessage>) -> Announcement {
+        self.sign_request(msg, Route::Gossip).sign(&self.signer)
+    }
+
+    /// Sign an announcement of ours and send it to the given peers. If signing is
+    /// offloaded, the announcement is only sent once it's handed back signed.
+    fn send_announcement(&mut self, msg: impl Into<AnnouncementMessage>, route: Route) {
+        let request = self.sign_request(msg, route);
+
+        if self.offload_signing {
+            self.outbox.sign(request);
+        } else {
+            let route = request.route.clone();
+            let ann = request.sign(&self.signer);
+
+            self.signed(ann, route);
+        }
+    }
+
+    /// Prepare an announcement of ours for signing, numbering it with our next sequence
+    /// number. If it can't be obtained, the announcement is left unsequenced.
+    ///
+    /// The addresses of node announcements are labeled, see [`Service::address_labels`].
+    fn sign_request(&mut self, msg: impl Into<AnnouncementMessage>, route: Route) -> SignRequest {
+        let message = msg.into();
+        let labels = match &message {
+            AnnouncementMessage::Node(node) => Some(self.address_labels(node)),
+            _ => None,
+        };
+        let nid = *self.nid();
+        let seq = match self.db.gossip().sequence(&nid) {
+            Ok(seq) => Some(seq.unwrap_or_default().saturating_add(1)),
+            Err(e) => {
+                error!(target: "service", "Error getting our announcement sequence number: {e}");
+                None
+            }
+        };
+        let seq = seq.filter(|seq| match self.db.gossip_mut().sequenced(&nid, *seq) {
+            Ok(_) => true,
+            Err(e) => {
+                error!(target: "service", "Error updating our announcement sequence number: {e}");
+                false
+            }
+        });
+        SignRequest {
+            message,
+            seq,
+            labels,
+            route,
+        }
+    }
+
+    /// Label the addresses of a node announcement of ours: configured addresses as manual,
+    /// and the others as observed by our peers.
+    fn address_labels(&self, node: &NodeAnnouncement) -> BoundedVec<address::Label, ADDRESS_LIMIT> {
+        let configured = gossip::addresses(&self.config);
+        let mut labels = node.addresses.iter().map(|addr| {
+            if configured.contains(addr) {
+                address::Label::Manual
+            } else {
+                address::Label::Observed
+            }
+        });
+        BoundedVec::collect_from(&mut labels)
+    }
+
+    /// Send a signed announcement of ours to the given peers.
+    pub fn signed(&mut self, ann: Announcement, route: Route) {
+        match route {
+            Route::Gossip => {
+                self.outbox.announce(
+                    ann,
+                    self.sessions.connected().map(|(_, p)| p),
+                    self.db.gossip_mut(),
+                );
+            }
+            Route::Visible(doc) => {
+                self.outbox.announce(
+                    ann,
+                    self.sessions
+                        .connected()
+                        .map(|(_, p)| p)
+                        .filter(|p| doc.is_visible_to(&p.id)),
+                    self.db.gossip_mut(),
+                );
+            }
+            Route::Unpaged => {
+                self.outbox.announce(
+                    ann,
+                    self.sessions
+                        .connected()
+                        .map(|(_, p)| p)
+                        .filter(|p| !p.features.has(Features::INVENTORY_PAGES)),
+                    self.db.gossip_mut(),
+                );
+            }
+            Route::Paged => {
+                let peers = self
+                    .sessions
+                    .connected()
+                    .filter(|(id, _)| self.has_features(id, node::Features::INVENTORY_PAGES))
+                    .map(|(id, _)| *id)
+                    .collect::<Vec<_>>();
+
+                self.outbox
+                    .broadcast(ann, peers.iter().filter_map(|id| self.sessions.get(id)));
+            }
+            Route::Heartbeat => {
+                let peers = self
+                    .sessions
+                    .connected()
+                    .filter(|(id, _)| self.has_features(id, node::Features::HEARTBEAT))
+                    .map(|(id, _)| *id)
+                    .collect::<Vec<_>>();
+
+                self.outbox
+                    .broadcast(ann, peers.iter().filter_map(|id| self.sessions.get(id)));
+            }
+        }
+    }
+
     ////////////////////////////////////////////////////////////////////////////
     // Periodic tasks
     ////////////////////////////////////////////////////////////////////////////
@@ -2113,18 +3882,86 @@ where
     /// Announce our inventory to all connected peers.
     fn announce_inventory(&mut self, inventory: Inventory) -> Result<(), storage::Error> {
         let time = self.timestamp();
-        let msg = AnnouncementMessage::from(gossip::inventory(time, inventory));
+        let inventory = self.ordered_inventory(inventory);
 
-        self.outbox.announce(
-            msg.signed(&self.signer),
-            self.sessions.connected().map(|(_, p)| p),
-            self.db.gossip_mut(),
-        );
-        self.last_announce = time.to_local_time();
+        // If our inventory doesn't fit in a single announcement, peers that support inventory
+        // pages get all of it, and other peers get as much of it as fits.
+        if inventory.len() > INVENTORY_LIMIT {
+            for page in gossip::inventory_pages(time, &inventory) {
+                self.send_announcement(page, Route::Paged);
+            }
+            self.send_announcement(gossip::inventory(time, inventory), Route::Unpaged);
+        } else {
+            self.send_announcement(gossip::inventory(time, inventory), Route::Gossip);
+        }
+        self.announcer
+            .announced(announcer::Kind::Inventory, self.clock.local_time());
 
         Ok(())
     }
 
+    /// Get the public part of our inventory, in the order it should be announced in.
+    ///
+    /// Repositories are kept in the order of our previous inventory announcement, with new
+    /// ones last, so that peers can be sent the difference between the two.
+    fn ordered_inventory(&self, inventory: Inventory) -> Vec<RepoId> {
+        let mut inventory = self.public(inventory).into_iter().collect::<Vec<_>>();
+
+        match self.db.gossip().inventory(self.nid()) {
+            Ok(Some(previous)) => {
+                if let Some(previous) = previous.inventory() {
+                    let order = previous
+                        .inventory
+                        .iter()
+                        .enumerate()
+                        .map(|(ix, rid)| (*rid, ix))
+                        .collect::<HashMap<_, _>>();
+                    inventory.sort_by_key(|rid| order.get(rid).copied().unwrap_or(usize::MAX));
+                }
+            }
+            Ok(None) => {}
+            Err(e) => {
+                error!(target: "service", "Error getting our inventory from the gossip store: {e}");
+            }
+        }
+        inventory
+    }
+
+    /// Announce the given node announcement, with a new timestamp, to all connected peers,
+    /// and use it as our node announcement from now on.
+    fn announce_node(&mut self, mut node: NodeAnnouncement) {
+        node.timestamp = self.timestamp();
+
+        let Some(node) = node.solve(0) else {
+            error!(target: "service", "Unable to solve proof-of-work for node announcement");
+            return;
+        };
+        self.node = node;
+        self.send_announcement(self.node.clone(), Route::Gossip);
+        self.announcer
+            .announced(announcer::Kind::Node, self.clock.local_time());
+    }
+
+    /// Number of peers we're aiming to be connected to.
+    fn target_peers(&self) -> usize {
+        match self.config.peers {
+            PeerConfig::Dynamic { target } => target,
+            PeerConfig::Static => self.config.connect.len(),
+        }
+    }
+
+    /// Announce our health to connected peers that understand heartbeats.
+    fn announce_heartbeat(&mut self) {
+        let timestamp = self.timestamp();
+        let Some(config) = &self.config.heartbeat else {
+            return;
+        };
+        let uptime = self.clock.local_time() - self.started_at.unwrap_or(self.clock.local_time());
+        let msg = heartbeat::heartbeat(config, self.storage.path(), uptime, timestamp);
+
+        self.send_announcement(msg, Route::Heartbeat);
+    }
+
     fn prune_routing_entries(&mut self, now: &LocalTime) -> Result<(), routing::Error> {
         let count = self.db.routing().len()?;
         if count <= self.config.limits.routing_max_size {
@@ -2158,6 +3995,25 @@ where
         }
     }
 
+    /// Disconnect peers that haven't subscribed to our gossip messages within the configured
+    /// negotiation timeout, to free up connection slots.
+    fn disconnect_unnegotiated_peers(&mut self, now: &LocalTime) {
+        let timeout = self.config.limits.connection.negotiation_timeout;
+        let unnegotiated = self.sessions.connected().filter(|(_, session)| {
+            session.subscribe.is_none()
+                && matches!(session.state, session::State::Connected { since, .. } if *now - since >= timeout)
+        });
+
+        for (_, session) in unnegotiated {
+            debug!(target: "service", "Disconnecting peer {} that failed to negotiate..", session.id);
+
+            self.outbox.disconnect(
+                session.id,
+                DisconnectReason::Session(session::Error::NegotiationTimeout),
+            );
+        }
+    }
+
     /// Ensure connection health by pinging connected peers.
     fn keep_alive(&mut self, now: &LocalTime) {
         let inactive_sessions = self
@@ -2166,7 +4022,7 @@ where
             .filter(|(_, session)| *now - session.last_active >= KEEP_ALIVE_DELTA)
             .map(|(_, session)| session);
         for session in inactive_sessions {
-            session.ping(self.clock, &mut self.outbox).ok();
+            session.ping(self.clock.local_time(), &mut self.outbox).ok();
         }
     }
 
@@ -2182,6 +4038,12 @@ where
                     .filter(|entry| !self.sessions.contains_key(&entry.node))
                     .filter(|entry| !self.config.external_addresses.contains(&entry.address.addr))
                     .filter(|entry| &entry.node != self.nid())
+                    .filter(|entry| {
+                        self.config.required_features == node::Features::NONE
+                            || self
+                                .features(&entry.node)
+                                .has(self.config.required_features)
+                    })
                     .fold(HashMap::new(), |mut acc, entry| {
                         acc.entry(entry.node)
                             .and_modify(|e: &mut Peer| e.addresses.push(entry.address.clone()))
@@ -2218,6 +4080,10 @@ where
                 Ok(seeds) => {
                     if let Some(connected) = NonEmpty::from_vec(seeds.connected().collect()) {
                         for seed in connected {
+                            if self.is_backed_off(&rid, &seed.nid) {
+                                debug!(target: "service", "Skipping fetch of {rid} from {}, previous fetches failed", seed.nid);
+                                continue;
+                            }
                             self.fetch(rid, seed.nid, FETCH_TIMEOUT, None);
                         }
                     } else {
@@ -2241,13 +4107,69 @@ where
         Ok(())
     }
 
+    /// Check the redundancy of seeded repositories, and connect to more of their seeds if
+    /// they are under-replicated.
+    fn maintain_replication(&mut self) -> Result<(), Error> {
+        let factor = self.config.replication_factor;
+        let now = self.clock.local_time();
+        let inventory = self.storage.inventory()?;
+        let seeded = self
+            .policies
+            .seed_policies()?
+            .filter_map(|t| (t.policy == Policy::Allow).then_some(t.rid))
+            // Repositories we don't have yet are taken care of by the "sync" task.
+            .filter(|rid| inventory.contains(rid))
+            .collect::<Vec<_>>();
+
+        for rid in seeded {
+            let seeds = self.seeds(&rid)?;
+            let reachable = seeds
+                .iter()
+                .filter(|s| s.is_connected() || !s.addrs.is_empty())
+                .count();
+
+            if self.replication.update(rid, reachable) {
+                warn!(target: "service", "Repository {rid} is only available from a single peer");
+            }
+            if reachable >= factor {
+                continue;
+            }
+            debug!(
+                target: "service",
+                "Repository {rid} is under-replicated ({reachable}/{factor} seed(s))"
+            );
+
+            for seed in seeds.iter() {
+                if self.replication.is_pending(&seed.nid) {
+                    // We're already connecting to this seed for another repository.
+                    self.replication.dialed(seed.nid, rid);
+                    continue;
+                }
+                if self.sessions.contains_key(&seed.nid) {
+                    continue;
+                }
+                let Some(ka) = seed
+                    .addrs
+                    .iter()
+                    .find(|ka| self.dialer.is_ready(seed.nid, ka, now))
+                else {
+                    continue;
+                };
+                if self.connect(seed.nid, ka.addr.clone()) {
+                    self.replication.dialed(seed.nid, rid);
+                }
+            }
+        }
+        Ok(())
+    }
+
     fn maintain_connections(&mut self) {
         let PeerConfig::Dynamic { target } = self.config.peers else {
             return;
         };
         trace!(target: "service", "Maintaining connections..");
 
-        let now = self.clock;
+        let now = self.clock.local_time();
         let outbound = self
             .sessions
             .values()
@@ -2257,32 +4179,49 @@ where
         let wanted = target.saturating_sub(outbound);
 
         // Don't connect to more peers than needed.
-        if wanted == 0 {
+        if outbound > target {
+            self.prune_connections(outbound - target);
+            return;
+        } else if wanted == 0 {
             return;
         }
 
-        let available = self
+        let ipv6 = self.has_ipv6();
+        let mut groups = self
+            .sessions
+            .values()
+            .filter(|s| s.link.is_outbound())
+            .filter(|s| s.is_connected() || s.is_connecting())
+            .map(|s| dialer::Group::from(&s.addr))
+            .collect::<HashSet<_>>();
+        let candidates = self
             .available_peers()
             .into_iter()
-            .filter_map(|peer| {
+            .filter_map(|mut peer| {
+                // Try addresses we're likely to be able to reach first, preferring the ones
+                // the peer is most confident in.
+                peer.addresses.sort_by_key(|ka| {
+                    let reachable = match ka.addr.host {
+                        HostName::Ip(net::IpAddr::V6(_)) if !ipv6 => 1,
+                        _ => 0,
+                    };
+                    (reachable, ka.preference())
+                });
                 peer.addresses
                     .into_iter()
-                    .find(|ka| match (ka.last_success, ka.last_attempt) {
-                        // If we succeeded the last time we tried, this is a good address.
-                        // If it's been long enough that we failed to connect, we also try again.
-                        (Some(success), Some(attempt)) => {
-                            success >= attempt || now - attempt >= CONNECTION_RETRY_DELTA
-                        }
-                        // If we haven't succeeded yet, and we waited long enough, we can try this address.
-                        (None, Some(attempt)) => now - attempt >= CONNECTION_RETRY_DELTA,
-                        // If we have no failed attempts for this address, it's worth a try.
-                        (_, None) => true,
-                    })
+                    .find(|ka| self.dialer.is_ready(peer.nid, ka, now))
                     .map(|ka| (peer.nid, ka))
             })
-            .take(wanted)
             .collect::<Vec<_>>();
 
+        // Prefer peers in network groups we're not yet connected to, and only then fall back
+        // to the remaining candidates.
+        let (mut available, rest): (Vec<_>, Vec<_>) = candidates
+            .into_iter()
+            .partition(|(_, ka)| groups.insert(dialer::Group::from(&ka.addr)));
+        available.extend(rest);
+        available.truncate(wanted);
+
         if available.len() < target {
             log::warn!(
                 target: "service",
@@ -2293,6 +4232,57 @@ where
         for (id, ka) in available {
             self.connect(id, ka.addr.clone());
         }
+        // Make sure we try again once the next address we failed to connect to is available.
+        if let Some(delay) = self.dialer.schedule(now) {
+            self.outbox.wakeup(delay);
+        }
+    }
+
+    /// Disconnect excess outbound peers. Peers sharing a network group with other peers are
+    /// disconnected first, and then the most recently connected ones. Persistent peers are
+    /// never disconnected.
+    fn prune_connections(&mut self, excess: usize) {
+        let mut groups = HashMap::<dialer::Group, usize>::new();
+        for (_, session) in self.sessions.outbound() {
+            *groups
+                .entry(dialer::Group::from(&session.addr))
+                .or_default() += 1;
+        }
+        let mut candidates = self
+            .sessions
+            .outbound()
+            .filter(|(_, s)| !s.persistent)
+            .filter_map(|(nid, s)| match &s.state {
+                // Don't interrupt ongoing fetches.
+                session::State::Connected {
+                    since, fetching, ..
+                } if fetching.is_empty() => {
+                    let shared = groups
+                        .get(&dialer::Group::from(&s.addr))
+                        .is_some_and(|n| *n > 1);
+                    Some((*nid, shared, *since))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|(_, shared, since)| (cmp::Reverse(*shared), cmp::Reverse(*since)));
+
+        for (nid, _, _) in candidates.into_iter().take(excess) {
+            debug!(target: "service", "Disconnecting excess outbound peer {nid}..");
+
+            self.outbox
+                .disconnect(nid, DisconnectReason::TooManyConnections);
+        }
+    }
+
+    /// Whether we appear to have IPv6 connectivity, ie. we're listening on an IPv6
+    /// address, or are connected to a peer over IPv6.
+    fn has_ipv6(&self) -> bool {
+        self.listening.iter().any(|a| a.is_ipv6())
+            || self
+                .sessions
+                .connected()
+                .any(|(_, s)| matches!(s.addr.host, HostName::Ip(net::IpAddr::V6(_))))
     }
 
     /// Maintain persistent peer connections.
@@ -2332,9 +4322,9 @@ pub trait ServiceState {
     /// Get a repository from storage.
     fn get(&self, rid: RepoId) -> Result<Option<Doc<Verified>>, RepositoryError>;
     /// Get the clock.
-    fn clock(&self) -> &LocalTime;
+    fn clock(&self) -> &dyn Clock;
     /// Get the clock mutably.
-    fn clock_mut(&mut self) -> &mut LocalTime;
+    fn clock_mut(&mut self) -> &mut dyn Clock;
     /// Get service configuration.
     fn config(&self) -> &Config;
 }
@@ -2357,12 +4347,12 @@ where
         self.storage.get(rid)
     }
 
-    fn clock(&self) -> &LocalTime {
-        &self.clock
+    fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
     }
 
-    fn clock_mut(&mut self) -> &mut LocalTime {
-        &mut self.clock
+    fn clock_mut(&mut self) -> &mut dyn Clock {
+        self.clock.as_mut()
     }
 
     fn config(&self) -> &Config {
@@ -2387,8 +4377,18 @@ pub enum DisconnectReason {
     Conflict,
     /// Connection to self.
     SelfConnection,
+    /// The handshake authenticated a different node than the one we dialed.
+    UnexpectedNode,
     /// User requested disconnect
     Command,
+    /// Peer is blocked by our policy.
+    Banned,
+    /// We have too many connections.
+    TooManyConnections,
+    /// Peer doesn't advertise the given features, which we require.
+    MissingFeatures(node::Features),
+    /// The remote peer is closing the connection, for the given reason.
+    Remote(DisconnectCode),
 }
 
 impl DisconnectReason {
@@ -2405,6 +4405,26 @@ impl DisconnectReason {
             std::io::ErrorKind::ConnectionReset,
         )))
     }
+
+    /// The reason to give the remote peer when closing the connection, if any.
+    pub fn code(&self) -> Option<DisconnectCode> {
+        match self {
+            Self::Banned => Some(DisconnectCode::Banned),
+            Self::TooManyConnections => Some(DisconnectCode::TooManyConnections),
+            Self::Session(_)
+            | Self::Fetch(_)
+            | Self::Command
+            | Self::MissingFeatures(_)
+            | Self::UnexpectedNode => Some(DisconnectCode::Other),
+            // Nb. Either the connection is already broken, the remote is closing it, or
+            // it's a duplicate connection that the remote is also closing.
+            Self::Dial(_)
+            | Self::Connection(_)
+            | Self::Conflict
+            | Self::SelfConnection
+            | Self::Remote(_) => None,
+        }
+    }
 }
 
 impl fmt::Display for DisconnectReason {
@@ -2414,9 +4434,14 @@ impl fmt::Display for DisconnectReason {
             Self::Connection(err) => write!(f, "{err}"),
             Self::Command => write!(f, "command"),
             Self::SelfConnection => write!(f, "self-connection"),
+            Self::UnexpectedNode => write!(f, "unexpected node"),
             Self::Conflict => write!(f, "conflict"),
             Self::Session(err) => write!(f, "{err}"),
             Self::Fetch(err) => write!(f, "fetch: {err}"),
+            Self::Banned => write!(f, "banned"),
+            Self::TooManyConnections => write!(f, "too many connections"),
+            Self::MissingFeatures(missing) => write!(f, "missing features: {missing}"),
+            Self::Remote(code) => write!(f, "closed by remote: {code}"),
         }
     }
 }
@@ -0,0 +1,195 @@
+use localtime::{LocalDuration, LocalTime};
+
+/// How often our node announcement is refreshed, at most.
+pub const NODE_REFRESH_INTERVAL: LocalDuration = LocalDuration::from_mins(60 * 6);
+/// How often our inventory announcement is refreshed, at most.
+pub const INVENTORY_REFRESH_INTERVAL: LocalDuration = LocalDuration::from_mins(60 * 24);
+/// Minimum time between two refreshes of the same announcement.
+pub const MIN_REFRESH_INTERVAL: LocalDuration = LocalDuration::from_mins(10);
+/// How long after joining the network announcements are refreshed more often.
+pub const WARMUP: LocalDuration = LocalDuration::from_mins(60 * 2);
+/// How much more often announcements are refreshed during warmup.
+pub const WARMUP_FACTOR: u32 = 4;
+
+/// An announcement of ours that is refreshed periodically.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Kind {
+    /// Our node announcement.
+    Node,
+    /// Our inventory announcement.
+    Inventory,
+}
+
+impl Kind {
+    /// Refresh interval when we're well connected and past warmup.
+    pub fn interval(&self) -> LocalDuration {
+        match self {
+            Self::Node => NODE_REFRESH_INTERVAL,
+            Self::Inventory => INVENTORY_REFRESH_INTERVAL,
+        }
+    }
+}
+
+/// Schedules the refreshes of our announcements.
+///
+/// Announcements are also sent when what they announce changes, eg. our addresses or our
+/// inventory. Refreshing them makes sure that peers which missed them, or pruned them,
+/// eventually learn about us. Since gossip reaches fewer nodes when we have fewer peers,
+/// and since the network has yet to learn about us when we just joined, announcements
+/// are refreshed more often in both cases.
+#[derive(Debug, Default)]
+pub struct Announcer {
+    /// When our node was last announced.
+    node: Option<LocalTime>,
+    /// When our inventory was last announced.
+    inventory: Option<LocalTime>,
+}
+
+impl Announcer {
+    /// Create a new announcer, given that all announcements were last sent at the given time.
+    pub fn new(time: LocalTime) -> Self {
+        Self {
+            node: Some(time),
+            inventory: Some(time),
+        }
+    }
+
+    /// Record that an announcement was sent.
+    pub fn announced(&mut self, kind: Kind, time: LocalTime) {
+        *self.last_mut(kind) = Some(time);
+    }
+
+    /// Refresh interval of an announcement, given the number of connected peers, the
+    /// target number of peers, and for how long we've been online.
+    pub fn interval(
+        kind: Kind,
+        peers: usize,
+        target: usize,
+        uptime: LocalDuration,
+    ) -> LocalDuration {
+        let target = target.max(1);
+        let peers = peers.clamp(1, target);
+        let mut interval = LocalDuration::from_millis(
+            kind.interval().as_millis() * peers as u128 / target as u128,
+        );
+
+        if uptime < WARMUP {
+            interval = interval / WARMUP_FACTOR;
+        }
+        interval.max(MIN_REFRESH_INTERVAL)
+    }
+
+    /// Get the announcements that are due for a refresh. Nothing is due without peers,
+    /// since there is no one to announce to.
+    pub fn due(
+        &self,
+        now: LocalTime,
+        peers: usize,
+        target: usize,
+        uptime: LocalDuration,
+    ) -> Vec<Kind> {
+        if peers == 0 {
+            return vec![];
+        }
+        [Kind::Node, Kind::Inventory]
+            .into_iter()
+            .filter(|kind| match self.last(*kind) {
+                Some(last) => now - last >= Self::interval(*kind, peers, target, uptime),
+                None => true,
+            })
+            .collect()
+    }
+
+    /// Time until the next refresh is due, assuming the number of peers doesn't change.
+    pub fn next(
+        &self,
+        now: LocalTime,
+        peers: usize,
+        target: usize,
+        uptime: LocalDuration,
+    ) -> LocalDuration {
+        [Kind::Node, Kind::Inventory]
+            .into_iter()
+            .map(|kind| {
+                let interval = Self::interval(kind, peers, target, uptime);
+                match self.last(kind) {
+                    Some(last) if now - last < interval => {
+                        LocalDuration::from_millis(interval.as_millis() - (now - last).as_millis())
+                    }
+                    _ => LocalDuration::from_secs(0),
+                }
+            })
+            .min()
+            .unwrap_or(MIN_REFRESH_INTERVAL)
+            .max(MIN_REFRESH_INTERVAL)
+    }
+
+    fn last(&self, kind: Kind) -> Option<LocalTime> {
+        match kind {
+            Kind::Node => self.node,
+            Kind::Inventory => self.inventory,
+        }
+    }
+
+    fn last_mut(&mut self, kind: Kind) -> &mut Option<LocalTime> {
+        match kind {
+            Kind::Node => &mut self.node,
+            Kind::Inventory => &mut self.inventory,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_interval() {
+        let uptime = WARMUP + LocalDuration::from_mins(1);
+
+        assert_eq!(
+            Announcer::interval(Kind::Inventory, 8, 8, uptime),
+            INVENTORY_REFRESH_INTERVAL
+        );
+        assert_eq!(
+            Announcer::interval(Kind::Inventory, 16, 8, uptime),
+            INVENTORY_REFRESH_INTERVAL
+        );
+        assert_eq!(
+            Announcer::interval(Kind::Inventory, 2, 8, uptime),
+            INVENTORY_REFRESH_INTERVAL / 4
+        );
+        assert_eq!(
+            Announcer::interval(Kind::Node, 8, 8, LocalDuration::from_mins(1)),
+            NODE_REFRESH_INTERVAL / WARMUP_FACTOR
+        );
+        assert_eq!(
+            Announcer::interval(Kind::Node, 1, 100, LocalDuration::from_mins(1)),
+            MIN_REFRESH_INTERVAL
+        );
+    }
+
+    #[test]
+    fn test_due() {
+        let start = LocalTime::from_secs(1706000000);
+        let uptime = WARMUP;
+        let mut announcer = Announcer::new(start);
+
+        assert_eq!(announcer.due(start, 8, 8, uptime), vec![]);
+        assert_eq!(announcer.next(start, 8, 8, uptime), NODE_REFRESH_INTERVAL);
+
+        let now = start + NODE_REFRESH_INTERVAL;
+        assert_eq!(announcer.due(now, 8, 8, uptime), vec![Kind::Node]);
+        assert_eq!(announcer.due(now, 0, 8, uptime), vec![]);
+
+        announcer.announced(Kind::Node, now);
+        assert_eq!(announcer.due(now, 8, 8, uptime), vec![]);
+        assert_eq!(announcer.next(now, 8, 8, uptime), NODE_REFRESH_INTERVAL);
+        // With fewer peers, the inventory is due sooner.
+        assert_eq!(announcer.due(now, 2, 8, uptime), vec![Kind::Inventory]);
+        assert_eq!(
+            Announcer::default().due(start, 1, 8, uptime),
+            vec![Kind::Node, Kind::Inventory]
+        );
+    }
+}
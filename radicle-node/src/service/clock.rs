@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use localtime::{LocalDuration, LocalTime};
+use radicle::node::NodeId;
+
+/// Minimum number of peer samples needed before our clock is adjusted.
+pub const MIN_CLOCK_SAMPLES: usize = 5;
+/// Maximum adjustment made to our clock. If our peers disagree with us by more than this,
+/// we assume it's them, and don't adjust our clock at all.
+///
+/// This is kept well below [`super::MAX_TIME_DELTA`], so that peers agreeing on a wrong time
+/// can't push our announcements out of the window accepted by the rest of the network.
+pub const MAX_CLOCK_ADJUSTMENT: LocalDuration = LocalDuration::from_mins(10);
+
+/// Source of time for the service.
+///
+/// Time is driven by the runtime, which sets the local time on every tick, and by our
+/// peers, which tell us what time they think it is.
+pub trait Clock: fmt::Debug + Send {
+    /// Local time, as last set.
+    fn local_time(&self) -> LocalTime;
+    /// Network-adjusted time. Used to timestamp our announcements, and to check the
+    /// staleness of announcements we receive. Never goes backwards.
+    fn network_time(&self) -> LocalTime;
+    /// Set the local time.
+    fn set(&mut self, now: LocalTime);
+    /// Record the time of a connected peer, as reported by the peer.
+    fn sample(&mut self, _nid: NodeId, _time: LocalTime) {}
+    /// Forget the time reported by a peer, eg. after it disconnected.
+    fn forget(&mut self, _nid: &NodeId) {}
+
+    /// Let time pass.
+    fn elapse(&mut self, duration: LocalDuration) {
+        self.set(self.local_time() + duration);
+    }
+}
+
+/// A clock that ignores our peers, and only uses local time.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LocalClock {
+    now: LocalTime,
+}
+
+impl LocalClock {
+    /// Create a new local clock, set to the given time.
+    pub fn new(now: LocalTime) -> Self {
+        Self { now }
+    }
+}
+
+impl Clock for LocalClock {
+    fn local_time(&self) -> LocalTime {
+        self.now
+    }
+
+    fn network_time(&self) -> LocalTime {
+        self.now
+    }
+
+    fn set(&mut self, now: LocalTime) {
+        self.now = now;
+    }
+}
+
+/// A clock adjusted to network time.
+///
+/// Network time is our local time, corrected by the median offset between our clock and
+/// the clocks of our peers. Each peer has a single sample, so that a peer can't skew our
+/// clock by sending us many messages.
+#[derive(Debug, Default, Clone)]
+pub struct AdjustedClock {
+    /// Local time.
+    local: LocalTime,
+    /// Network time, as last computed.
+    network: LocalTime,
+    /// Offset of each peer's clock from ours, in milliseconds.
+    offsets: HashMap<NodeId, i128>,
+}
+
+impl AdjustedClock {
+    /// Create a new adjusted clock, set to the given time.
+    pub fn new(now: LocalTime) -> Self {
+        Self {
+            local: now,
+            network: now,
+            offsets: HashMap::new(),
+        }
+    }
+
+    /// Offset between our clock and network time, in milliseconds.
+    pub fn offset(&self) -> i128 {
+        if self.offsets.len() < MIN_CLOCK_SAMPLES {
+            return 0;
+        }
+        let mut offsets = self.offsets.values().copied().collect::<Vec<_>>();
+        offsets.sort_unstable();
+
+        let median = offsets[offsets.len() / 2];
+        if median.unsigned_abs() > MAX_CLOCK_ADJUSTMENT.as_millis() {
+            log::warn!(
+                target: "service",
+                "Peers disagree with our clock by {}s; please check your system time",
+                median / 1000
+            );
+            return 0;
+        }
+        median
+    }
+
+    /// Update network time. Network time never goes backwards: if it would, it stays where
+    /// it is until the adjusted time catches up.
+    fn adjust(&mut self) {
+        let millis = self.local.as_millis() as i128 + self.offset();
+        let adjusted = LocalTime::from_millis(millis.max(0) as u128);
+
+        self.network = self.network.max(adjusted);
+    }
+}
+
+impl Clock for AdjustedClock {
+    fn local_time(&self) -> LocalTime {
+        self.local
+    }
+
+    fn network_time(&self) -> LocalTime {
+        self.network
+    }
+
+    fn set(&mut self, now: LocalTime) {
+        self.local = now;
+        self.adjust();
+    }
+
+    /// Only the first sample of a peer is kept, until the peer is forgotten.
+    fn sample(&mut self, nid: NodeId, time: LocalTime) {
+        if self.offsets.contains_key(&nid) {
+            return;
+        }
+        let offset = time.as_millis() as i128 - self.local.as_millis() as i128;
+
+        self.offsets.insert(nid, offset);
+        self.adjust();
+    }
+
+    fn forget(&mut self, nid: &NodeId) {
+        if self.offsets.remove(nid).is_some() {
+            self.adjust();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::service::MAX_TIME_DELTA;
+
+    #[test]
+    fn test_adjusted_clock() {
+        let t = LocalTime::from_secs(1_700_000_000);
+        let ahead = t + LocalDuration::from_mins(10);
+        let mut clock = AdjustedClock::new(t);
+
+        // Not enough samples to adjust our clock.
+        for i in 1..MIN_CLOCK_SAMPLES as u8 {
+            clock.sample(NodeId::from([i; 32]), ahead);
+        }
+        assert_eq!(clock.network_time(), t);
+
+        // Once we have enough samples, our clock is adjusted to the median.
+        clock.sample(NodeId::from([MIN_CLOCK_SAMPLES as u8; 32]), ahead);
+        assert_eq!(clock.network_time(), ahead);
+        assert_eq!(clock.local_time(), t);
+
+        // Peers only get one sample.
+        clock.sample(NodeId::from([1; 32]), t + LocalDuration::from_mins(60));
+        assert_eq!(clock.network_time(), ahead);
+
+        // Time passes.
+        clock.elapse(LocalDuration::from_secs(1));
+        assert_eq!(clock.network_time(), ahead + LocalDuration::from_secs(1));
+
+        // Network time doesn't go backwards when peers disconnect.
+        clock.forget(&NodeId::from([1; 32]));
+        assert_eq!(clock.network_time(), ahead + LocalDuration::from_secs(1));
+        clock.elapse(LocalDuration::from_mins(10));
+        assert_eq!(
+            clock.network_time(),
+            ahead + LocalDuration::from_secs(1),
+            "Network time waits for local time to catch up"
+        );
+        clock.elapse(LocalDuration::from_secs(1));
+        assert_eq!(
+            clock.network_time(),
+            t + LocalDuration::from_mins(10) + LocalDuration::from_secs(2)
+        );
+    }
+
+    #[test]
+    fn test_adjusted_clock_adversarial() {
+        let t = LocalTime::from_secs(1_700_000_000);
+        let max = MAX_CLOCK_ADJUSTMENT.as_millis();
+
+        for mins in [1, 9, 10, 11, 59, 61, 600] {
+            let mut clock = AdjustedClock::new(t);
+            let skew = LocalDuration::from_mins(mins);
+
+            // Our peers all agree on a time that is ahead of ours.
+            for i in 0..MIN_CLOCK_SAMPLES as u8 * 2 {
+                clock.sample(NodeId::from([i; 32]), t + skew);
+            }
+            let adjustment = clock.network_time() - clock.local_time();
+
+            assert!(
+                adjustment.as_millis() <= max,
+                "{mins}m skew adjusts at most {max}ms"
+            );
+            // Announcements timestamped with our adjusted time, and those of nodes with the
+            // correct time are still accepted by each other.
+            assert!(adjustment < MAX_TIME_DELTA);
+        }
+    }
+
+    #[test]
+    fn test_adjusted_clock_too_far() {
+        let t = LocalTime::from_secs(1_700_000_000);
+        let mut clock = AdjustedClock::new(t);
+
+        for i in 0..MIN_CLOCK_SAMPLES as u8 {
+            clock.sample(
+                NodeId::from([i; 32]),
+                t + MAX_CLOCK_ADJUSTMENT + LocalDuration::from_secs(1),
+            );
+        }
+        assert_eq!(clock.network_time(), t);
+    }
+}
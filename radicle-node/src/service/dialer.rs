@@ -0,0 +1,265 @@
+use std::net;
+
+use cyphernet::addr::HostName;
+use localtime::{LocalDuration, LocalTime};
+use radicle::node::{Address, KnownAddress, NodeId};
+
+use crate::collections::RandomMap;
+use crate::service::Rng;
+
+/// Minimum time to wait before dialing an address that we failed to connect to.
+pub const MIN_RETRY_DELAY: LocalDuration = LocalDuration::from_mins(1);
+/// Maximum time to wait before dialing an address that we failed to connect to.
+pub const MAX_RETRY_DELAY: LocalDuration = LocalDuration::from_mins(60);
+/// Maximum number of unsuccessful attempts on an address before we stop dialing it.
+pub const MAX_DIAL_ATTEMPTS: usize = 8;
+
+/// Exponential backoff delay for the given number of attempts, with jitter.
+///
+/// The delay doubles with every attempt, starting at `min`. Up to half of it is added
+/// at random, so that peers which lost connectivity at the same time don't all retry at
+/// the same time. The delay never exceeds `max`.
+pub fn backoff(
+    attempts: usize,
+    min: LocalDuration,
+    max: LocalDuration,
+    rng: &mut Rng,
+) -> LocalDuration {
+    let exp = attempts.saturating_sub(1).min(u32::BITS as usize - 1) as u32;
+    let delay = min
+        .as_millis()
+        .saturating_mul(2u128.saturating_pow(exp))
+        .min(max.as_millis()) as u64;
+    let jitter = rng.u64(0..=delay / 2);
+
+    LocalDuration::from_millis(delay.saturating_add(jitter) as u128).min(max)
+}
+
+/// Network group of an address.
+///
+/// Peers in the same group are likely to be on the same network, or run by the same
+/// operator. Spreading outbound connections across groups makes it harder for a single
+/// party to control all of them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Group {
+    /// IPv4 addresses, by `/16` prefix.
+    Ipv4([u8; 2]),
+    /// IPv6 addresses, by `/32` prefix.
+    Ipv6([u8; 4]),
+    /// Other addresses, by host name.
+    Host(String),
+}
+
+impl From<&Address> for Group {
+    fn from(addr: &Address) -> Self {
+        match &addr.host {
+            HostName::Ip(net::IpAddr::V4(ip)) => {
+                let [a, b, ..] = ip.octets();
+                Self::Ipv4([a, b])
+            }
+            HostName::Ip(net::IpAddr::V6(ip)) => {
+                let [a, b, c, d, ..] = ip.octets();
+                Self::Ipv6([a, b, c, d])
+            }
+            other => Self::Host(other.to_string()),
+        }
+    }
+}
+
+/// Dialing state of an address.
+#[derive(Debug, Clone)]
+struct Backoff {
+    /// Unsuccessful attempts so far.
+    attempts: usize,
+    /// When we can next try the address.
+    retry_at: LocalTime,
+}
+
+/// Schedules outbound connection attempts to known peer addresses.
+///
+/// Every attempt on an address pushes back the next attempt on it exponentially, until
+/// the address is either connected to successfully, or it reaches the maximum number of
+/// attempts, after which it isn't dialed anymore.
+#[derive(Debug)]
+pub struct Dialer {
+    /// Addresses we've attempted and haven't successfully connected to yet.
+    addresses: RandomMap<(NodeId, Address), Backoff>,
+    /// Time of the next scheduled retry, if any.
+    wakeup: Option<LocalTime>,
+    /// Source of entropy, for jitter.
+    rng: Rng,
+}
+
+impl Dialer {
+    /// Create a new dialer.
+    pub fn new(rng: Rng) -> Self {
+        Self {
+            addresses: RandomMap::with_hasher(rng.clone().into()),
+            wakeup: None,
+            rng,
+        }
+    }
+
+    /// Record a connection attempt to an address. Returns the time at which the address
+    /// can be tried again, should this attempt fail.
+    pub fn attempted(&mut self, nid: NodeId, addr: Address, now: LocalTime) -> LocalTime {
+        let entry = self
+            .addresses
+            .entry((nid, addr))
+            .or_insert_with(|| Backoff {
+                attempts: 0,
+                retry_at: now,
+            });
+        entry.attempts += 1;
+        entry.retry_at = now
+            + backoff(
+                entry.attempts,
+                MIN_RETRY_DELAY,
+                MAX_RETRY_DELAY,
+                &mut self.rng,
+            );
+        entry.retry_at
+    }
+
+    /// Record a successful connection to an address. This resets its backoff.
+    pub fn connected(&mut self, nid: NodeId, addr: &Address) {
+        self.addresses.remove(&(nid, addr.clone()));
+    }
+
+    /// Check whether an address can be dialed.
+    ///
+    /// Addresses we haven't tried since we started are checked against the address book,
+    /// so that we don't redial a failing address right after a restart.
+    pub fn is_ready(&self, nid: NodeId, ka: &KnownAddress, now: LocalTime) -> bool {
+        if let Some(backoff) = self.addresses.get(&(nid, ka.addr.clone())) {
+            return backoff.attempts < MAX_DIAL_ATTEMPTS && now >= backoff.retry_at;
+        }
+        match (ka.last_success, ka.last_attempt) {
+            // If we succeeded the last time we tried, this is a good address.
+            (Some(success), Some(attempt)) if success >= attempt => true,
+            // Otherwise, wait at least as long as we would after a first failed attempt.
+            (_, Some(attempt)) => now - attempt >= MIN_RETRY_DELAY,
+            // If we have no failed attempts for this address, it's worth a try.
+            (_, None) => true,
+        }
+    }
+
+    /// Schedule a wakeup for the next address that can be retried. Returns the time to
+    /// wait until then, or `None` if there's nothing to retry, or a wakeup is already
+    /// scheduled by then.
+    pub fn schedule(&mut self, now: LocalTime) -> Option<LocalDuration> {
+        let next = self
+            .addresses
+            .values()
+            .filter(|b| b.attempts < MAX_DIAL_ATTEMPTS && b.retry_at > now)
+            .map(|b| b.retry_at)
+            .min()?;
+
+        if let Some(wakeup) = self.wakeup {
+            if wakeup > now && wakeup <= next {
+                return None;
+            }
+        }
+        self.wakeup = Some(next);
+
+        Some(next - now)
+    }
+
+    /// Check whether a scheduled retry is due. Returns `true` only once per wakeup.
+    pub fn is_due(&mut self, now: LocalTime) -> bool {
+        match self.wakeup {
+            Some(wakeup) if now >= wakeup => {
+                self.wakeup = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use radicle::node::address;
+
+    #[test]
+    fn test_backoff() {
+        let mut rng = fastrand::Rng::with_seed(1);
+        let min = LocalDuration::from_secs(10);
+        let max = LocalDuration::from_secs(100);
+
+        for _ in 0..64 {
+            let first = backoff(1, min, max, &mut rng);
+            assert!(first >= min && first <= LocalDuration::from_secs(15));
+
+            let third = backoff(3, min, max, &mut rng);
+            assert!(third >= LocalDuration::from_secs(40) && third <= LocalDuration::from_secs(60));
+
+            let last = backoff(usize::MAX, min, max, &mut rng);
+            assert_eq!(last, max);
+        }
+    }
+
+    #[test]
+    fn test_group() {
+        let group = |s: &str| Group::from(&s.parse::<Address>().expect("valid address"));
+
+        assert_eq!(group("88.12.4.1:8776"), group("88.12.200.7:8776"));
+        assert_ne!(group("88.12.4.1:8776"), group("88.13.4.1:8776"));
+
+        let ipv6 = |segments: [u16; 8]| {
+            Group::from(&Address::from(std::net::SocketAddr::from((
+                net::Ipv6Addr::from(segments),
+                8776,
+            ))))
+        };
+        assert_eq!(
+            ipv6([0x2001, 0xdb8, 0, 0, 0, 0, 0, 1]),
+            ipv6([0x2001, 0xdb8, 0xffff, 0, 0, 0, 0, 1])
+        );
+        assert_ne!(
+            ipv6([0x2001, 0xdb8, 0, 0, 0, 0, 0, 1]),
+            ipv6([0x2001, 0xdb9, 0, 0, 0, 0, 0, 1])
+        );
+
+        assert_ne!(
+            group("seed.radicle.xyz:8776"),
+            group("seed.radicle.garden:8776")
+        );
+    }
+
+    #[test]
+    fn test_dialer() {
+        let mut dialer = Dialer::new(fastrand::Rng::with_seed(1));
+        let nid = NodeId::from([1; 32]);
+        let ka = KnownAddress::new(
+            Address::from(std::net::SocketAddr::from(([8, 8, 8, 8], 8776))),
+            address::Source::Peer,
+        );
+        let mut now = LocalTime::from_secs(1_000_000);
+
+        assert!(dialer.is_ready(nid, &ka, now));
+        assert_eq!(dialer.schedule(now), None);
+
+        for _ in 1..MAX_DIAL_ATTEMPTS {
+            let retry_at = dialer.attempted(nid, ka.addr.clone(), now);
+            assert!(!dialer.is_ready(nid, &ka, now));
+            assert!(!dialer.is_due(now));
+            assert_eq!(dialer.schedule(now), Some(retry_at - now));
+            assert_eq!(dialer.schedule(now), None, "A wakeup is already scheduled");
+
+            now = retry_at;
+            assert!(dialer.is_due(now));
+            assert!(!dialer.is_due(now));
+            assert!(dialer.is_ready(nid, &ka, now));
+        }
+        // The address was attempted too many times.
+        dialer.attempted(nid, ka.addr.clone(), now);
+        assert!(!dialer.is_ready(nid, &ka, now + MAX_RETRY_DELAY));
+        assert_eq!(dialer.schedule(now), None);
+
+        // A successful connection resets the backoff.
+        dialer.connected(nid, &ka.addr);
+        assert!(dialer.is_ready(nid, &ka, now));
+    }
+}
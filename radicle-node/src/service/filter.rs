@@ -43,15 +43,7 @@ impl Filter {
     pub fn new(ids: impl IntoIterator<Item = RepoId>) -> Self {
         let iterator = ids.into_iter();
         let (min, _) = iterator.size_hint();
-        let size = bloomy::bloom::optimal_bits(min, FILTER_FP_RATE) / 8;
-        let size = if size > FILTER_SIZE_M {
-            FILTER_SIZE_L
-        } else if size > FILTER_SIZE_S {
-            FILTER_SIZE_M
-        } else {
-            FILTER_SIZE_S
-        };
-        let mut bloom = BloomFilter::with_size(size);
+        let mut bloom = BloomFilter::with_size(Self::size_for(min));
 
         for id in iterator {
             bloom.insert(&id);
@@ -68,6 +60,49 @@ impl Filter {
     pub fn size(&self) -> usize {
         self.0.bits() / 8
     }
+
+    /// Smallest valid filter size, in bytes, that can store the given number of items
+    /// within the target false positive rate, or the largest size if none can.
+    pub fn size_for(items: usize) -> usize {
+        let size = bloomy::bloom::optimal_bits(items, FILTER_FP_RATE) / 8;
+
+        FILTER_SIZES
+            .into_iter()
+            .find(|s| *s >= size)
+            .unwrap_or(FILTER_SIZE_L)
+    }
+
+    /// Approximate number of items in the filter.
+    pub fn items(&self) -> usize {
+        self.0.count()
+    }
+
+    /// Probability that an item which isn't in the filter matches it, given the bits set.
+    pub fn false_positive_rate(&self) -> f64 {
+        let ones = self
+            .0
+            .as_bytes()
+            .iter()
+            .map(|b| b.count_ones() as usize)
+            .sum::<usize>();
+
+        (ones as f64 / self.0.bits() as f64).powi(self.0.hashes() as i32)
+    }
+
+    /// Expected false positive rate of a filter of the given size in bytes, once it
+    /// stores the given number of items.
+    pub fn expected_false_positive_rate(size: usize, items: usize) -> f64 {
+        let bits = (size * 8) as f64;
+        let hashes = FILTER_HASHES as f64;
+
+        (1. - (-hashes * items as f64 / bits).exp()).powf(hashes)
+    }
+
+    /// Whether the filter should be re-created with a larger size, because it stores
+    /// more items than its size allows within the target false positive rate.
+    pub fn is_overloaded(&self) -> bool {
+        self.false_positive_rate() > FILTER_FP_RATE && Self::size_for(self.items()) > self.size()
+    }
 }
 
 impl Deref for Filter {
@@ -146,8 +181,40 @@ mod test {
         let f = Filter::new(ids.iter().cloned());
         assert_eq!(f.size(), FILTER_SIZE_L);
 
+        assert_eq!(Filter::size_for(0), FILTER_SIZE_S);
+        assert_eq!(Filter::size_for(855), FILTER_SIZE_S);
+        assert_eq!(Filter::size_for(856), FILTER_SIZE_M);
+        assert_eq!(Filter::size_for(100_000), FILTER_SIZE_L);
+
         // Just checking that iterators over hash sets give correct size hints.
         let hs = arbitrary::set::<RepoId>(42..=42);
         assert_eq!(hs.iter().size_hint(), (42, Some(42)));
     }
+
+    #[test]
+    fn test_false_positive_rate() {
+        let ids = arbitrary::vec::<RepoId>(3000);
+        let expected = Filter::expected_false_positive_rate(FILTER_SIZE_S, 855);
+        assert!((expected - FILTER_FP_RATE).abs() < 0.001, "{expected}");
+
+        assert_eq!(Filter::empty().false_positive_rate(), 0.);
+        assert_eq!(Filter::default().false_positive_rate(), 1.);
+
+        let mut f = Filter::new(ids.iter().cloned().take(100));
+        assert!(f.false_positive_rate() < FILTER_FP_RATE);
+        assert!(!f.is_overloaded());
+
+        // Items added to a small filter push its false positive rate over the target.
+        for id in &ids {
+            f.insert(id);
+        }
+        assert_eq!(f.size(), FILTER_SIZE_S);
+        assert!(f.false_positive_rate() > FILTER_FP_RATE);
+        assert!(f.is_overloaded());
+
+        let f = Filter::new(ids.iter().cloned());
+        assert_eq!(f.size(), FILTER_SIZE_M);
+        assert!(f.false_positive_rate() < FILTER_FP_RATE);
+        assert!(!f.is_overloaded());
+    }
 }
@@ -1,5 +1,6 @@
 pub mod store;
 
+use super::message::{InventoryPage, INVENTORY_PAGES_LIMIT};
 use super::*;
 
 pub use store::Error;
@@ -8,11 +9,7 @@ pub use store::Store;
 pub fn node(config: &Config, timestamp: Timestamp) -> NodeAnnouncement {
     let features = config.features();
     let alias = config.alias.clone();
-    let addresses: BoundedVec<_, ADDRESS_LIMIT> = config
-        .external_addresses
-        .clone()
-        .try_into()
-        .expect("external addresses are within the limit");
+    let addresses = addresses(config);
 
     NodeAnnouncement {
         features,
@@ -23,6 +20,26 @@ pub fn node(config: &Config, timestamp: Timestamp) -> NodeAnnouncement {
     }
 }
 
+/// Addresses to announce: the configured external addresses, followed by the routable
+/// addresses we're listening on, if any.
+pub fn addresses(config: &Config) -> BoundedVec<Address, ADDRESS_LIMIT> {
+    let mut addresses = config.external_addresses.clone();
+    for addr in &config.listen {
+        let addr = Address::from(*addr);
+        if addr.is_routable() && !addr.is_trusted() && !addresses.contains(&addr) {
+            addresses.push(addr);
+        }
+    }
+    if addresses.len() > ADDRESS_LIMIT {
+        error!(
+            target: "service",
+            "address announcement limit ({}) exceeded, other nodes will see only some of your addresses",
+            addresses.len()
+        );
+    }
+    BoundedVec::truncate(addresses)
+}
+
 pub fn inventory(
     timestamp: Timestamp,
     inventory: impl IntoIterator<Item = RepoId>,
@@ -31,7 +48,7 @@ pub fn inventory(
     if inventory.len() > INVENTORY_LIMIT {
         error!(
             target: "service",
-            "inventory announcement limit ({}) exceeded, other nodes will see only some of your projects",
+            "inventory announcement limit ({}) exceeded, nodes that don't support inventory pages will see only some of your projects",
             inventory.len()
         );
     }
@@ -41,3 +58,26 @@ pub fn inventory(
         timestamp,
     }
 }
+
+/// Split an inventory into pages, for nodes that support them. See [`InventoryPage`].
+pub fn inventory_pages(timestamp: Timestamp, inventory: &[RepoId]) -> Vec<InventoryPage> {
+    let limit = INVENTORY_LIMIT * INVENTORY_PAGES_LIMIT as usize;
+    if inventory.len() > limit {
+        error!(
+            target: "service",
+            "inventory page limit ({limit}) exceeded, other nodes will see only some of your projects",
+        );
+    }
+    let chunks = inventory[..inventory.len().min(limit)].chunks(INVENTORY_LIMIT);
+    let pages = chunks.len() as u16;
+
+    chunks
+        .enumerate()
+        .map(|(page, chunk)| InventoryPage {
+            inventory: BoundedVec::truncate(chunk.to_vec()),
+            page: page as u16,
+            pages,
+            timestamp,
+        })
+        .collect()
+}
@@ -6,9 +6,10 @@ use sqlite as sql;
 use thiserror::Error;
 
 use crate::node::{Database, NodeId};
-use crate::prelude::{Filter, Timestamp};
+use crate::prelude::{Filter, RepoId, Timestamp};
 use crate::service::message::{
-    Announcement, AnnouncementMessage, InventoryAnnouncement, NodeAnnouncement, RefsAnnouncement,
+    Announcement, AnnouncementMessage, InventoryAnnouncement, Labels, NodeAnnouncement,
+    RefsAnnouncement, Sequence,
 };
 use crate::wire;
 use crate::wire::Decode;
@@ -23,22 +24,59 @@ pub enum Error {
     UnitOverflow(#[from] TryFromIntError),
 }
 
+/// An announcement, along with the node it was received from.
+pub type Relayed = (Announcement, NodeId);
+
 /// A database that has access to historical gossip messages.
 /// Keeps track of the latest received gossip messages for each node.
 /// Grows linearly with the number of nodes on the network.
 pub trait Store {
-    /// Prune announcements older than the cutoff time.
+    /// Prune announcements older than the cutoff time, along with the delivery timestamps
+    /// of peers that didn't relay anything since.
     fn prune(&mut self, cutoff: Timestamp) -> Result<usize, Error>;
 
+    /// Prune announcements newer than the horizon, ie. from the future.
+    fn prune_future(&mut self, horizon: Timestamp) -> Result<usize, Error>;
+
     /// Get the timestamp of the last announcement in the store.
     fn last(&self) -> Result<Option<Timestamp>, Error>;
 
-    /// Process an announcement for the given node.
-    /// Returns `true` if the timestamp was updated or the announcement wasn't there before.
-    fn announced(&mut self, nid: &NodeId, ann: &Announcement) -> Result<bool, Error>;
+    /// Get the timestamp of the latest refs announcement of the given repository, from any
+    /// node.
+    fn last_refs(&self, rid: &RepoId) -> Result<Option<Timestamp>, Error>;
+
+    /// Process an announcement for the given node, received from the given relayer.
+    /// Returns `true` if the announcement superseded the one we had, or wasn't there before.
+    ///
+    /// If both announcements are sequenced, the one with the highest sequence number wins,
+    /// otherwise the most recent one does. Sequenced announcements with the same sequence
+    /// number are ordered by timestamp, and then by signature, so that all nodes keep the
+    /// same announcement.
+    fn announced(
+        &mut self,
+        nid: &NodeId,
+        ann: &Announcement,
+        relayer: &NodeId,
+    ) -> Result<bool, Error>;
+
+    /// Get the highest announcement sequence number seen from the given node.
+    fn sequence(&self, nid: &NodeId) -> Result<Option<u64>, Error>;
+
+    /// Record an announcement sequence number of the given node.
+    /// Returns `true` if it's the highest seen from that node.
+    fn sequenced(&mut self, nid: &NodeId, seq: u64) -> Result<bool, Error>;
+
+    /// Record that the given peer relayed us an announcement with the given timestamp.
+    fn delivered(&mut self, peer: &NodeId, timestamp: Timestamp) -> Result<(), Error>;
+
+    /// Get the latest timestamp of the announcements relayed to us by the given peer.
+    fn last_delivered(&self, peer: &NodeId) -> Result<Option<Timestamp>, Error>;
+
+    /// Get the latest inventory announcement of the given node.
+    fn inventory(&self, nid: &NodeId) -> Result<Option<Announcement>, Error>;
 
     /// Get all the latest gossip messages of all nodes, filtered by inventory filter and
-    /// announcement timestamps.
+    /// announcement timestamps, along with the node each was received from.
     ///
     /// # Panics
     ///
@@ -49,7 +87,7 @@ pub trait Store {
         filter: &'a Filter,
         from: Timestamp,
         to: Timestamp,
-    ) -> Result<Box<dyn Iterator<Item = Result<Announcement, Error>> + 'a>, Error>;
+    ) -> Result<Box<dyn Iterator<Item = Result<Relayed, Error>> + 'a>, Error>;
 }
 
 impl Store for Database {
@@ -60,6 +98,26 @@ impl Store for Database {
 
         stmt.bind((1, &cutoff))?;
         stmt.next()?;
+        drop(stmt);
+
+        let pruned = self.db.change_count();
+        let mut stmt = self
+            .db
+            .prepare("DELETE FROM `gossip-delivered` WHERE timestamp < ?1")?;
+
+        stmt.bind((1, &cutoff))?;
+        stmt.next()?;
+
+        Ok(pruned)
+    }
+
+    fn prune_future(&mut self, horizon: Timestamp) -> Result<usize, Error> {
+        let mut stmt = self
+            .db
+            .prepare("DELETE FROM `announcements` WHERE timestamp > ?1")?;
+
+        stmt.bind((1, &horizon))?;
+        stmt.next()?;
 
         Ok(self.db.change_count())
     }
@@ -78,17 +136,49 @@ impl Store for Database {
         Ok(None)
     }
 
-    fn announced(&mut self, nid: &NodeId, ann: &Announcement) -> Result<bool, Error> {
+    fn last_refs(&self, rid: &RepoId) -> Result<Option<Timestamp>, Error> {
+        let mut stmt = self.db.prepare(
+            "SELECT MAX(timestamp) AS latest FROM `announcements`
+             WHERE repo = ?1 AND type = ?2",
+        )?;
+        stmt.bind((1, rid))?;
+        stmt.bind((2, &GossipType::Refs))?;
+
+        if let Some(Ok(row)) = stmt.into_iter().next() {
+            return match row.try_read::<Option<i64>, _>(0)? {
+                Some(i) => Ok(Some(Timestamp::from(u64::try_from(i)?))),
+                None => Ok(None),
+            };
+        }
+        Ok(None)
+    }
+
+    fn announced(
+        &mut self,
+        nid: &NodeId,
+        ann: &Announcement,
+        relayer: &NodeId,
+    ) -> Result<bool, Error> {
         let mut stmt = self.db.prepare(
-            "INSERT INTO `announcements` (node, repo, type, message, signature, timestamp)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            "INSERT INTO `announcements` (node, repo, type, message, signature, timestamp, seq, seq_signature, relayer, labels, hops)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
              ON CONFLICT DO UPDATE
-             SET message = ?4, signature = ?5, timestamp = ?6
-             WHERE timestamp < ?6",
+             SET message = ?4, signature = ?5, timestamp = ?6, seq = ?7, seq_signature = ?8, relayer = ?9, labels = ?10, hops = ?11
+             WHERE CASE
+               WHEN seq IS NOT NULL AND ?7 IS NOT NULL
+                 THEN seq < ?7 OR (seq = ?7 AND (
+                   timestamp < ?6 OR (timestamp = ?6 AND signature < ?5)
+                 ))
+               ELSE timestamp < ?6
+             END",
         )?;
         stmt.bind((1, nid))?;
 
         match &ann.message {
+            // Heartbeats are short-lived, and are tracked by the service instead.
+            AnnouncementMessage::Heartbeat(_) => return Ok(false),
+            // Inventory pages are reassembled by the service instead.
+            AnnouncementMessage::InventoryPage(_) => return Ok(false),
             AnnouncementMessage::Node(msg) => {
                 stmt.bind((2, sql::Value::String(String::new())))?;
                 stmt.bind((3, &GossipType::Node))?;
@@ -107,19 +197,119 @@ impl Store for Database {
         }
         stmt.bind((5, &ann.signature))?;
         stmt.bind((6, &ann.message.timestamp()))?;
+
+        if let Some(Sequence { seq, signature }) = &ann.sequence {
+            stmt.bind((7, i64::try_from(*seq)?))?;
+            stmt.bind((8, signature))?;
+        } else {
+            stmt.bind((7, sql::Value::Null))?;
+            stmt.bind((8, sql::Value::Null))?;
+        }
+        stmt.bind((9, relayer))?;
+        stmt.bind((10, ann.labels.as_ref()))?;
+        stmt.bind((11, i64::from(ann.hops)))?;
+        stmt.next()?;
+        drop(stmt);
+
+        let fresh = self.db.change_count() > 0;
+        if fresh {
+            if let Some(seq) = ann.seq() {
+                self.sequenced(nid, seq)?;
+            }
+        }
+        Ok(fresh)
+    }
+
+    fn sequence(&self, nid: &NodeId) -> Result<Option<u64>, Error> {
+        let mut stmt = self
+            .db
+            .prepare("SELECT seq FROM `sequences` WHERE node = ?1")?;
+        stmt.bind((1, nid))?;
+
+        if let Some(row) = stmt.into_iter().next() {
+            let seq = row?.try_read::<i64, _>("seq")?;
+            return Ok(Some(u64::try_from(seq)?));
+        }
+        Ok(None)
+    }
+
+    fn sequenced(&mut self, nid: &NodeId, seq: u64) -> Result<bool, Error> {
+        let mut stmt = self.db.prepare(
+            "INSERT INTO `sequences` (node, seq) VALUES (?1, ?2)
+             ON CONFLICT DO UPDATE SET seq = ?2 WHERE seq < ?2",
+        )?;
+        stmt.bind((1, nid))?;
+        stmt.bind((2, i64::try_from(seq)?))?;
         stmt.next()?;
 
         Ok(self.db.change_count() > 0)
     }
 
+    fn delivered(&mut self, peer: &NodeId, timestamp: Timestamp) -> Result<(), Error> {
+        let mut stmt = self.db.prepare(
+            "INSERT INTO `gossip-delivered` (peer, timestamp) VALUES (?1, ?2)
+             ON CONFLICT DO UPDATE SET timestamp = ?2 WHERE timestamp < ?2",
+        )?;
+        stmt.bind((1, peer))?;
+        stmt.bind((2, &timestamp))?;
+        stmt.next()?;
+
+        Ok(())
+    }
+
+    fn last_delivered(&self, peer: &NodeId) -> Result<Option<Timestamp>, Error> {
+        let mut stmt = self
+            .db
+            .prepare("SELECT timestamp FROM `gossip-delivered` WHERE peer = ?1")?;
+        stmt.bind((1, peer))?;
+
+        if let Some(row) = stmt.into_iter().next() {
+            return Ok(Some(row?.try_read::<Timestamp, _>("timestamp")?));
+        }
+        Ok(None)
+    }
+
+    fn inventory(&self, nid: &NodeId) -> Result<Option<Announcement>, Error> {
+        let mut stmt = self.db.prepare(
+            "SELECT message, signature, seq, seq_signature, hops
+             FROM `announcements`
+             WHERE node = ?1 AND type = ?2",
+        )?;
+        stmt.bind((1, nid))?;
+        stmt.bind((2, &GossipType::Inventory))?;
+
+        if let Some(row) = stmt.into_iter().next() {
+            let row = row?;
+            let sequence = match row.read::<Option<i64>, _>("seq") {
+                Some(seq) => Some(Sequence {
+                    seq: u64::try_from(seq)?,
+                    signature: row.read::<Signature, _>("seq_signature"),
+                }),
+                None => None,
+            };
+            return Ok(Some(Announcement {
+                node: *nid,
+                message: row.read::<InventoryAnnouncement, _>("message").into(),
+                signature: row.read::<Signature, _>("signature"),
+                sequence,
+                labels: None,
+                hops: u8::try_from(row.read::<i64, _>("hops"))?,
+            }));
+        }
+        Ok(None)
+    }
+
     fn filtered<'a>(
         &'a self,
         filter: &'a Filter,
         from: Timestamp,
         to: Timestamp,
-    ) -> Result<Box<dyn Iterator<Item = Result<Announcement, Error>> + 'a>, Error> {
+    ) -> Result<Box<dyn Iterator<Item = Result<Relayed, Error>> + 'a>, Error> {
+        // Announcements stored before relayers were recorded are treated as received from
+        // their announcer.
         let mut stmt = self.db.prepare(
-            "SELECT node, type, message, signature, timestamp
+            "SELECT node, type, message, signature, timestamp, seq, seq_signature, labels, hops,
+                    COALESCE(relayer, node) AS relayer
              FROM announcements
              WHERE timestamp >= ?1 and timestamp < ?2
              ORDER BY timestamp, node, type",
@@ -151,17 +341,36 @@ impl Store for Database {
                     };
                     let signature = row.read::<Signature, _>("signature");
                     let timestamp = row.read::<Timestamp, _>("timestamp");
+                    let sequence = match row.read::<Option<i64>, _>("seq") {
+                        Some(seq) => Some(Sequence {
+                            seq: u64::try_from(seq)?,
+                            signature: row.read::<Signature, _>("seq_signature"),
+                        }),
+                        None => None,
+                    };
+                    let labels = match &row["labels"] {
+                        sql::Value::Null => None,
+                        value => Some(Labels::try_from(value)?),
+                    };
+                    let hops = u8::try_from(row.read::<i64, _>("hops"))?;
+                    let relayer = row.read::<NodeId, _>("relayer");
 
                     debug_assert_eq!(timestamp, message.timestamp());
 
-                    Ok(Announcement {
-                        node,
-                        message,
-                        signature,
-                    })
+                    Ok((
+                        Announcement {
+                            node,
+                            message,
+                            signature,
+                            sequence,
+                            labels,
+                            hops,
+                        },
+                        relayer,
+                    ))
                 })
                 .filter(|ann| match ann {
-                    Ok(a) => a.matches(filter),
+                    Ok((a, _)) => a.matches(filter),
                     Err(_) => true,
                 }),
         ))
@@ -237,6 +446,29 @@ impl sql::BindableWithIndex for &InventoryAnnouncement {
     }
 }
 
+impl TryFrom<&sql::Value> for Labels {
+    type Error = sql::Error;
+
+    fn try_from(value: &sql::Value) -> Result<Self, Self::Error> {
+        match value {
+            sql::Value::Binary(bytes) => {
+                let mut reader = io::Cursor::new(bytes);
+                Labels::decode(&mut reader).map_err(wire::Error::into)
+            }
+            _ => Err(sql::Error {
+                code: None,
+                message: Some("sql: invalid type for address labels".to_owned()),
+            }),
+        }
+    }
+}
+
+impl sql::BindableWithIndex for &Labels {
+    fn bind<I: sql::ParameterIndex>(self, stmt: &mut sql::Statement<'_>, i: I) -> sql::Result<()> {
+        wire::serialize(self).bind(stmt, i)
+    }
+}
+
 impl From<wire::Error> for sql::Error {
     fn from(other: wire::Error) -> Self {
         sql::Error {
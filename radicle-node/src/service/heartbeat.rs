@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use localtime::LocalDuration;
+use radicle::io::available_space;
+use radicle::node::config::Heartbeat;
+use radicle::node::{Health, NodeId, Timestamp};
+
+use crate::service::message::HeartbeatAnnouncement;
+
+/// Minimum time between two heartbeats of the same node. Heartbeats received more often
+/// are dropped, and ours are never sent more often than this.
+pub const MIN_HEARTBEAT_INTERVAL: LocalDuration = LocalDuration::from_mins(10);
+
+/// Tracks the health announced by other nodes.
+///
+/// Unlike other announcements, heartbeats aren't kept in the gossip store, since they
+/// are only useful while they're recent. We only remember the latest heartbeat of
+/// each node.
+#[derive(Debug, Default)]
+pub struct Heartbeats {
+    received: HashMap<NodeId, HeartbeatAnnouncement>,
+}
+
+impl Heartbeats {
+    /// Record a heartbeat received from a node.
+    ///
+    /// Returns `false` if the heartbeat is stale, or came too soon after the previous
+    /// heartbeat of that node, in which case it shouldn't be relayed.
+    pub fn received(&mut self, nid: NodeId, heartbeat: &HeartbeatAnnouncement) -> bool {
+        if let Some(last) = self.received.get(&nid) {
+            if *heartbeat.timestamp <= *last.timestamp
+                || heartbeat.timestamp.to_local_time() - last.timestamp.to_local_time()
+                    < MIN_HEARTBEAT_INTERVAL
+            {
+                return false;
+            }
+        }
+        self.received.insert(nid, heartbeat.clone());
+
+        true
+    }
+
+    /// Get the health last announced by a node, if any.
+    pub fn health(&self, nid: &NodeId) -> Option<Health> {
+        self.received.get(nid).map(|h| h.health())
+    }
+
+    /// Forget heartbeats older than the cutoff. Returns the number of heartbeats pruned.
+    pub fn prune(&mut self, cutoff: Timestamp) -> usize {
+        let len = self.received.len();
+        self.received.retain(|_, h| *h.timestamp >= *cutoff);
+
+        len - self.received.len()
+    }
+}
+
+/// Create a heartbeat announcing our health.
+pub fn heartbeat(
+    config: &Heartbeat,
+    storage: &Path,
+    uptime: LocalDuration,
+    timestamp: Timestamp,
+) -> HeartbeatAnnouncement {
+    let storage_free = match available_space(storage) {
+        Ok(space) => space,
+        Err(e) => {
+            log::error!(target: "service", "Error getting available space of storage: {e}");
+            0
+        }
+    };
+
+    HeartbeatAnnouncement {
+        uptime: uptime.as_secs(),
+        storage_free,
+        max_repo_size: config.max_repo_size.unwrap_or_default(),
+        timestamp,
+    }
+}
+
+/// Interval at which to send heartbeats, given our configuration.
+pub fn interval(config: &Heartbeat) -> LocalDuration {
+    config.interval.max(MIN_HEARTBEAT_INTERVAL)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use localtime::LocalTime;
+
+    #[test]
+    fn test_heartbeats_rate_limit() {
+        let mut heartbeats = Heartbeats::default();
+        let nid = NodeId::from([1; 32]);
+        let t = LocalTime::from_secs(1_700_000_000);
+        let heartbeat = |time: LocalTime| HeartbeatAnnouncement {
+            uptime: 0,
+            storage_free: 1024,
+            max_repo_size: 0,
+            timestamp: time.into(),
+        };
+
+        assert!(heartbeats.received(nid, &heartbeat(t)));
+        assert!(
+            !heartbeats.received(nid, &heartbeat(t)),
+            "Duplicates are dropped"
+        );
+        assert!(
+            !heartbeats.received(nid, &heartbeat(t + LocalDuration::from_mins(1))),
+            "Heartbeats sent too often are dropped"
+        );
+        assert!(heartbeats.received(nid, &heartbeat(t + MIN_HEARTBEAT_INTERVAL)));
+        assert!(
+            !heartbeats.received(nid, &heartbeat(t)),
+            "Older heartbeats are dropped"
+        );
+
+        let health = heartbeats.health(&nid).expect("health is known");
+        assert_eq!(health.storage_free, 1024);
+        assert_eq!(health.max_repo_size, None);
+
+        assert_eq!(heartbeats.prune((t + MIN_HEARTBEAT_INTERVAL).into()), 0);
+        assert_eq!(
+            heartbeats.prune((t + MIN_HEARTBEAT_INTERVAL + LocalDuration::from_secs(1)).into()),
+            1
+        );
+        assert!(heartbeats.health(&nid).is_none());
+    }
+
+    #[test]
+    fn test_available_space() {
+        let tmp = tempfile::tempdir().expect("temporary directory can be created");
+
+        assert!(available_space(tmp.path()).expect("space can be queried") > 0);
+    }
+}
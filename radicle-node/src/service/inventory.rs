@@ -0,0 +1,199 @@
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+
+use radicle::node::{NodeId, Timestamp};
+
+use crate::identity::RepoId;
+use crate::service::message::InventoryPage;
+
+/// Outcome of receiving an inventory page.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Received {
+    /// The page is invalid, was already received, or is part of an inventory older than
+    /// the latest one of that node. It shouldn't be relayed.
+    Stale,
+    /// The page is new, but other pages of the inventory are still missing.
+    Partial,
+    /// The page completes the inventory, which is returned in page order.
+    Complete(Vec<RepoId>),
+}
+
+/// Inventory of a node, of which some pages were received.
+#[derive(Debug)]
+struct Pending {
+    timestamp: Timestamp,
+    pages: u16,
+    received: BTreeMap<u16, Vec<RepoId>>,
+}
+
+impl Pending {
+    fn new(page: &InventoryPage) -> Self {
+        Self {
+            timestamp: page.timestamp,
+            pages: page.pages,
+            received: BTreeMap::new(),
+        }
+    }
+}
+
+/// Reassembles the inventories that nodes announce in multiple pages.
+///
+/// Like heartbeats, inventory pages aren't kept in the gossip store. We only keep the
+/// pages of the latest inventory of each node until it's complete, and the time of the
+/// latest complete inventory of each node, so that pages of older ones are dropped.
+#[derive(Debug, Default)]
+pub struct Pages {
+    pending: HashMap<NodeId, Pending>,
+    complete: HashMap<NodeId, Timestamp>,
+}
+
+impl Pages {
+    /// Record an inventory page received from a node.
+    ///
+    /// A page of a newer inventory than the one being reassembled replaces it.
+    pub fn received(&mut self, nid: NodeId, page: &InventoryPage) -> Received {
+        if !page.is_valid() {
+            return Received::Stale;
+        }
+        if let Some(latest) = self.complete.get(&nid) {
+            if *page.timestamp <= **latest {
+                return Received::Stale;
+            }
+        }
+        let pending = match self.pending.entry(nid) {
+            Entry::Occupied(e) if *e.get().timestamp > *page.timestamp => {
+                return Received::Stale;
+            }
+            Entry::Occupied(mut e) => {
+                if *e.get().timestamp < *page.timestamp {
+                    e.insert(Pending::new(page));
+                }
+                e.into_mut()
+            }
+            Entry::Vacant(e) => e.insert(Pending::new(page)),
+        };
+        if pending.pages != page.pages || pending.received.contains_key(&page.page) {
+            return Received::Stale;
+        }
+        pending.received.insert(page.page, page.inventory.to_vec());
+
+        if pending.received.len() < pending.pages as usize {
+            return Received::Partial;
+        }
+        let Some(pending) = self.pending.remove(&nid) else {
+            return Received::Stale;
+        };
+        self.complete.insert(nid, pending.timestamp);
+
+        Received::Complete(pending.received.into_values().flatten().collect())
+    }
+
+    /// Get the time of the latest complete inventory of a node, if any.
+    pub fn latest(&self, nid: &NodeId) -> Option<Timestamp> {
+        self.complete.get(nid).copied()
+    }
+
+    /// Forget inventories older than the cutoff. Returns the number of incomplete
+    /// inventories pruned.
+    pub fn prune(&mut self, cutoff: Timestamp) -> usize {
+        let len = self.pending.len();
+        self.pending.retain(|_, p| *p.timestamp >= *cutoff);
+        self.complete.retain(|_, t| **t >= *cutoff);
+
+        len - self.pending.len()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+    use crate::prelude::BoundedVec;
+    use crate::test::arbitrary;
+
+    fn page(inventory: &[RepoId], page: u16, pages: u16, timestamp: u64) -> InventoryPage {
+        InventoryPage {
+            inventory: BoundedVec::try_from(inventory.to_vec()).unwrap(),
+            page,
+            pages,
+            timestamp: Timestamp::from(timestamp),
+        }
+    }
+
+    #[test]
+    fn test_inventory_pages_reassembly() {
+        let mut pages = Pages::default();
+        let nid = NodeId::from([1; 32]);
+        let rids = arbitrary::vec::<RepoId>(6);
+
+        assert_eq!(
+            pages.received(nid, &page(&rids[4..], 2, 3, 1)),
+            Received::Partial
+        );
+        assert_eq!(
+            pages.received(nid, &page(&rids[..2], 0, 3, 1)),
+            Received::Partial
+        );
+        assert_eq!(
+            pages.received(nid, &page(&rids[..2], 0, 3, 1)),
+            Received::Stale,
+            "Duplicate pages are dropped"
+        );
+        assert_eq!(
+            pages.received(nid, &page(&rids[2..4], 1, 4, 1)),
+            Received::Stale,
+            "Pages must agree on the number of pages"
+        );
+        assert_eq!(
+            pages.received(nid, &page(&rids[2..4], 1, 3, 1)),
+            Received::Complete(rids.clone()),
+            "Inventories are reassembled in page order"
+        );
+        assert_eq!(pages.latest(&nid), Some(Timestamp::from(1)));
+        assert_eq!(
+            pages.received(nid, &page(&rids[..2], 0, 3, 1)),
+            Received::Stale,
+            "Pages of a complete inventory are dropped"
+        );
+    }
+
+    #[test]
+    fn test_inventory_pages_superseded() {
+        let mut pages = Pages::default();
+        let nid = NodeId::from([1; 32]);
+        let rids = arbitrary::vec::<RepoId>(4);
+
+        assert_eq!(
+            pages.received(nid, &page(&rids[..2], 0, 2, 1)),
+            Received::Partial
+        );
+        assert_eq!(
+            pages.received(nid, &page(&rids[..1], 0, 2, 2)),
+            Received::Partial
+        );
+        assert_eq!(
+            pages.received(nid, &page(&rids[2..], 1, 2, 1)),
+            Received::Stale,
+            "Pages of an older inventory are dropped"
+        );
+        assert_eq!(
+            pages.received(nid, &page(&rids[1..], 1, 2, 2)),
+            Received::Complete(rids.clone())
+        );
+        assert_eq!(
+            pages.received(nid, &page(&rids, 0, 1, 1)),
+            Received::Stale,
+            "Inventories older than the latest one are dropped"
+        );
+        assert_eq!(
+            pages.received(nid, &page(&rids, 1, 1, 3)),
+            Received::Stale,
+            "Invalid pages are dropped"
+        );
+        assert_eq!(pages.prune(Timestamp::from(3)), 0);
+        assert_eq!(
+            pages.received(nid, &page(&rids, 0, 1, 1)),
+            Received::Complete(rids)
+        );
+    }
+}
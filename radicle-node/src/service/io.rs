@@ -1,15 +1,26 @@
-use std::collections::VecDeque;
-use std::time;
+use std::collections::{HashMap, VecDeque};
+use std::{io, time};
 
+use crossbeam_channel as chan;
 use log::*;
+use radicle::crypto::Verified;
+use radicle::git::Oid;
+use radicle::identity::Doc;
 use radicle::storage::refs::RefsAt;
 
+use crate::node::{address, Features};
 use crate::prelude::*;
 use crate::service::session::Session;
 use crate::service::Link;
+use crate::wire::Encode as _;
+use crate::worker::query;
 
 use super::gossip;
-use super::message::{Announcement, AnnouncementMessage};
+use super::message::{
+    Announcement, AnnouncementMessage, Compressed, InventoryAnnouncement, InventoryDiff,
+    InventoryHash, ADDRESS_LIMIT,
+};
+use super::snapshot::Snapshot;
 
 /// I/O operation to execute at the network/wire level.
 #[derive(Debug)]
@@ -18,6 +29,9 @@ pub enum Io {
     Write(NodeId, Vec<Message>),
     /// Connect to a peer.
     Connect(NodeId, Address),
+    /// Connect to a peer we were introduced to by a bridge, from the port we're listening
+    /// on, so that the connection can get through a NAT that maps that port.
+    Punch(NodeId, Address),
     /// Disconnect from a peer.
     Disconnect(NodeId, DisconnectReason),
     /// Fetch repository data from a peer.
@@ -30,9 +44,89 @@ pub enum Io {
         refs_at: Option<Vec<RefsAt>>,
         /// Fetch timeout.
         timeout: time::Duration,
+        /// Whether to recover from a missing or invalid local `rad/id`.
+        recover: bool,
+    },
+    /// Fetch large files referenced by a repository from a peer.
+    FetchBlobs {
+        /// Repo the blobs belong to.
+        rid: RepoId,
+        /// Remote node being fetched from.
+        remote: NodeId,
+        /// Blobs to fetch.
+        blobs: Vec<Oid>,
+    },
+    /// Query the tips of references of a repository from a peer.
+    QueryRefs {
+        /// Repo the references belong to.
+        rid: RepoId,
+        /// Remote node being queried.
+        remote: NodeId,
+        /// References to query.
+        refs: Vec<radicle::git::RefString>,
+        /// Where to send the tips of the references.
+        reply: chan::Sender<Result<query::Tips, query::Error>>,
     },
     /// Ask for a wakeup in a specified amount of time.
     Wakeup(LocalDuration),
+    /// Sign an announcement of ours off the service thread. The signed announcement
+    /// is handed back with [`crate::service::Service::signed`].
+    Sign(Box<SignRequest>),
+    /// Save a snapshot of the service state.
+    Snapshot(Box<Snapshot>),
+}
+
+/// Peers that an announcement of ours is sent to once it's signed.
+#[derive(Debug, Clone)]
+pub enum Route {
+    /// Gossip to all connected peers.
+    Gossip,
+    /// Gossip to connected peers that the repository is visible to.
+    Visible(Doc<Verified>),
+    /// Send to connected peers that understand heartbeats.
+    Heartbeat,
+    /// Gossip to connected peers that don't understand inventory pages.
+    Unpaged,
+    /// Send to connected peers that understand inventory pages.
+    Paged,
+}
+
+/// An announcement of ours to sign.
+#[derive(Debug, Clone)]
+pub struct SignRequest {
+    /// Announcement to sign.
+    pub message: AnnouncementMessage,
+    /// Sequence number to number the announcement with.
+    pub seq: Option<u64>,
+    /// Labels of the announced addresses, if it's a node announcement.
+    pub labels: Option<BoundedVec<address::Label, ADDRESS_LIMIT>>,
+    /// Peers to send the announcement to.
+    pub route: Route,
+}
+
+impl SignRequest {
+    /// Sign the announcement.
+    ///
+    /// If the numbered announcement wouldn't fit in a message, eg. because of a large
+    /// inventory, it's left unsequenced.
+    pub fn sign<G: Signer>(self, signer: &G) -> Announcement {
+        let mut ann = self.message.signed(signer);
+        if let Some(labels) = self.labels {
+            ann = ann.labeled(labels, signer);
+        }
+        let Some(seq) = self.seq else {
+            return ann;
+        };
+        let sequenced = ann.clone().sequenced(seq, signer);
+
+        if Message::from(sequenced.clone())
+            .encode(&mut io::sink())
+            .is_err()
+        {
+            return ann;
+        }
+        sequenced
+    }
 }
 
 /// Interface to the network.
@@ -40,6 +134,9 @@ pub enum Io {
 pub struct Outbox {
     /// Outgoing I/O queue.
     io: VecDeque<Io>,
+    /// Latest inventory of each node that each connected peer is known to have, since
+    /// we sent it or received it from them. Inventories are sent as diffs against these.
+    inventories: HashMap<NodeId, HashMap<NodeId, InventoryHash>>,
 }
 
 impl Outbox {
@@ -48,12 +145,19 @@ impl Outbox {
         self.io.push_back(Io::Connect(id, addr));
     }
 
+    /// Connect to a peer introduced by a bridge. See [`Io::Punch`].
+    pub fn punch(&mut self, id: NodeId, addr: Address) {
+        self.io.push_back(Io::Punch(id, addr));
+    }
+
     /// Disconnect a peer.
     pub fn disconnect(&mut self, id: NodeId, reason: DisconnectReason) {
         self.io.push_back(Io::Disconnect(id, reason));
     }
 
     pub fn write(&mut self, remote: &Session, msg: Message) {
+        let msg = Self::adapt(remote, msg);
+        self.sent(remote, &msg);
         msg.log(log::Level::Debug, &remote.id, Link::Outbound);
         trace!(target: "service", "Write {:?} to {}", &msg, remote);
 
@@ -67,9 +171,20 @@ impl Outbox {
         peers: impl Iterator<Item = &'a Session>,
         gossip: &mut impl gossip::Store,
     ) {
+        // Our previous inventory, which peers may get the new one as a diff against.
+        let base = match &ann.message {
+            AnnouncementMessage::Inventory(_) => gossip
+                .inventory(&ann.node)
+                .unwrap_or_else(|e| {
+                    error!(target: "service", "Error getting our inventory from the gossip store: {e}");
+                    None
+                })
+                .and_then(|ann| ann.inventory().cloned()),
+            _ => None,
+        };
         // Store our announcement so that it can be retrieved from us later, just like
         // announcements we receive from peers.
-        if let Err(e) = gossip.announced(&ann.node, &ann) {
+        if let Err(e) = gossip.announced(&ann.node, &ann, &ann.node) {
             error!(target: "service", "Error updating our gossip store with announced message: {e}");
         }
 
@@ -92,14 +207,83 @@ impl Outbox {
                     );
                 }
             } else {
-                self.write(peer, ann.clone().into());
+                self.write_announcement(peer, ann.clone(), base.as_ref());
             }
         }
     }
 
+    /// Write an announcement to a peer. Inventory announcements are written as a diff
+    /// against the given base inventory of the same node, if the peer is known to have
+    /// it and supports diffs, otherwise the full announcement is written.
+    fn write_announcement(
+        &mut self,
+        peer: &Session,
+        ann: Announcement,
+        base: Option<&InventoryAnnouncement>,
+    ) {
+        let diff = base
+            .filter(|_| peer.features.has(Features::INVENTORY_DIFF))
+            .filter(|base| self.has_inventory(&peer.id, &ann.node, base.hash()))
+            .and_then(|base| InventoryDiff::new(&ann, &base.inventory));
+
+        match (diff, &ann.message) {
+            (Some(diff), AnnouncementMessage::Inventory(inv)) => {
+                self.write(peer, diff.into());
+                self.inventory_known(peer.id, ann.node, inv.hash());
+            }
+            _ => self.write(peer, ann.into()),
+        }
+    }
+
+    /// Record that a peer has the given inventory announcement.
+    pub fn inventory_received(&mut self, peer: NodeId, ann: &Announcement) {
+        if let AnnouncementMessage::Inventory(inv) = &ann.message {
+            self.inventory_known(peer, ann.node, inv.hash());
+        }
+    }
+
+    /// Forget the inventory of a node we thought a peer had, eg. because the peer told us
+    /// it doesn't have it. Inventories of that node are then sent in full to the peer.
+    pub fn inventory_missing(&mut self, peer: &NodeId, node: &NodeId) {
+        if let Some(inventories) = self.inventories.get_mut(peer) {
+            inventories.remove(node);
+        }
+    }
+
+    /// Forget what a peer is known to have, once it disconnected.
+    pub fn disconnected(&mut self, peer: &NodeId) {
+        self.inventories.remove(peer);
+    }
+
+    fn inventory_known(&mut self, peer: NodeId, node: NodeId, hash: InventoryHash) {
+        self.inventories.entry(peer).or_default().insert(node, hash);
+    }
+
+    fn has_inventory(&self, peer: &NodeId, node: &NodeId, hash: InventoryHash) -> bool {
+        self.inventories
+            .get(peer)
+            .and_then(|inventories| inventories.get(node))
+            == Some(&hash)
+    }
+
+    /// Keep track of the inventories sent to a peer.
+    fn sent(&mut self, peer: &Session, msg: &Message) {
+        match msg {
+            Message::Announcement(ann) => self.inventory_received(peer.id, ann),
+            Message::Compressed(Compressed { message, .. }) => self.sent(peer, message),
+            _ => {}
+        }
+    }
+
     pub fn write_all(&mut self, remote: &Session, msgs: impl IntoIterator<Item = Message>) {
-        let msgs = msgs.into_iter().collect::<Vec<_>>();
+        let msgs = msgs
+            .into_iter()
+            .map(|msg| Self::adapt(remote, msg))
+            .collect::<Vec<_>>();
 
+        for msg in &msgs {
+            self.sent(remote, msg);
+        }
         for (ix, msg) in msgs.iter().enumerate() {
             trace!(
                 target: "service",
@@ -114,6 +298,14 @@ impl Outbox {
         self.io.push_back(Io::Write(remote.id, msgs));
     }
 
+    pub fn sign(&mut self, request: SignRequest) {
+        self.io.push_back(Io::Sign(Box::new(request)));
+    }
+
+    pub fn snapshot(&mut self, snapshot: Snapshot) {
+        self.io.push_back(Io::Snapshot(Box::new(snapshot)));
+    }
+
     pub fn wakeup(&mut self, after: LocalDuration) {
         self.io.push_back(Io::Wakeup(after));
     }
@@ -138,11 +330,41 @@ impl Outbox {
             debug!(target: "service", "Fetch initiated for {rid} with {peer} (all remotes)..");
         }
 
+        // N.b. only fetches of all remotes can recover the identity of a repository, since
+        // recovering requires fetching all special references.
+        let recover = refs_at.is_none();
+
         self.io.push_back(Io::Fetch {
             rid,
             refs_at,
             remote: peer.id,
             timeout,
+            recover,
+        });
+    }
+
+    /// Fetch large files referenced by a repository from a peer.
+    pub fn fetch_blobs(&mut self, remote: NodeId, rid: RepoId, blobs: Vec<Oid>) {
+        debug!(target: "service", "Fetch initiated for {} blob(s) of {rid} with {remote}..", blobs.len());
+
+        self.io.push_back(Io::FetchBlobs { rid, remote, blobs });
+    }
+
+    /// Query the tips of references of a repository from a peer.
+    pub fn query_refs(
+        &mut self,
+        remote: NodeId,
+        rid: RepoId,
+        refs: Vec<radicle::git::RefString>,
+        reply: chan::Sender<Result<query::Tips, query::Error>>,
+    ) {
+        debug!(target: "service", "Query initiated for {} reference(s) of {rid} with {remote}..", refs.len());
+
+        self.io.push_back(Io::QueryRefs {
+            rid,
+            remote,
+            refs,
+            reply,
         });
     }
 
@@ -158,22 +380,75 @@ impl Outbox {
         }
     }
 
-    /// Relay a message to interested peers.
-    pub fn relay<'a>(&mut self, ann: Announcement, peers: impl IntoIterator<Item = &'a Session>) {
-        if let AnnouncementMessage::Refs(msg) = &ann.message {
-            let id = msg.rid;
-            let peers = peers.into_iter().filter(|p| {
-                if let Some(subscribe) = &p.subscribe {
-                    subscribe.filter.contains(&id)
-                } else {
-                    // If the peer did not send us a `subscribe` message, we don't
-                    // relay any messages to them.
-                    false
+    /// Relay an announcement received from `relayer` to interested peers.
+    ///
+    /// Announcements are never relayed back to the peer they were received from, nor to the
+    /// node that signed them. Peers that subscribed only get announcements within the time
+    /// range of their subscription, and refs announcements only if they match their filter.
+    /// Inventory and node announcements aren't filtered by repository, since all nodes need
+    /// them to route fetches.
+    ///
+    /// Inventory announcements are relayed as diffs against the given base inventory,
+    /// where possible. See [`Outbox::write_announcement`].
+    pub fn relay<'a>(
+        &mut self,
+        ann: Announcement,
+        relayer: &NodeId,
+        peers: impl IntoIterator<Item = &'a Session>,
+        base: Option<&InventoryAnnouncement>,
+    ) {
+        let timestamp = ann.timestamp();
+        let peers = peers
+            .into_iter()
+            .filter(|p| p.id != *relayer && p.id != ann.node)
+            .filter(|p| match &p.subscribe {
+                Some(subscribe) => {
+                    (*subscribe.since..*subscribe.until).contains(&*timestamp)
+                        && ann.matches(&subscribe.filter)
                 }
-            });
-            self.broadcast(ann, peers);
+                // If the peer did not send us a `subscribe` message, we don't relay
+                // refs announcements to them.
+                None => !matches!(ann.message, AnnouncementMessage::Refs(_)),
+            })
+            .collect::<Vec<_>>();
+
+        for peer in peers {
+            self.write_announcement(peer, ann.clone(), base);
+        }
+    }
+
+    /// Adapt a message to the features supported by the peer.
+    fn adapt(remote: &Session, msg: Message) -> Message {
+        let msg = match msg {
+            Message::Announcement(mut ann) => {
+                if ann.sequence.is_some() && !remote.features.has(Features::SEQUENCE) {
+                    ann = ann.unsequenced();
+                }
+                if ann.labels.is_some() && !remote.features.has(Features::ADDRESS_LABELS) {
+                    ann = ann.unlabeled();
+                }
+                // Peers that don't understand hop counts relay announcements without
+                // bounds, as they did before hop counts were introduced.
+                if !remote.features.has(Features::RELAY_HOPS) {
+                    ann.hops = 0;
+                }
+                Message::Announcement(ann)
+            }
+            Message::InventoryDiff(mut diff) => {
+                if !remote.features.has(Features::SEQUENCE) {
+                    diff.sequence = None;
+                }
+                if !remote.features.has(Features::RELAY_HOPS) {
+                    diff.hops = 0;
+                }
+                Message::InventoryDiff(diff)
+            }
+            msg => msg,
+        };
+        if remote.features.has(Features::COMPRESSION) {
+            msg.compressed()
         } else {
-            self.broadcast(ann, peers);
+            msg
         }
     }
 
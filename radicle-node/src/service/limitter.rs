@@ -67,7 +67,7 @@ pub struct TokenBucket {
 }
 
 impl TokenBucket {
-    fn new(tokens: usize, rate: f64, now: LocalTime) -> Self {
+    pub fn new(tokens: usize, rate: f64, now: LocalTime) -> Self {
         Self {
             rate,
             capacity: tokens as f64,
@@ -85,10 +85,22 @@ impl TokenBucket {
     }
 
     fn take(&mut self, now: LocalTime) -> bool {
+        self.take_n(1, now)
+    }
+
+    /// Check whether the given number of tokens is available, without taking them.
+    pub fn has_n(&mut self, n: usize, now: LocalTime) -> bool {
+        self.refill(now);
+        self.tokens >= n as f64
+    }
+
+    /// Take the given number of tokens, if available.
+    /// Returns `false` and takes nothing if there aren't enough tokens.
+    pub fn take_n(&mut self, n: usize, now: LocalTime) -> bool {
         self.refill(now);
 
-        if self.tokens >= 1.0 {
-            self.tokens -= 1.0;
+        if self.tokens >= n as f64 {
+            self.tokens -= n as f64;
             true
         } else {
             false
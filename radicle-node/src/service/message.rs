@@ -1,5 +1,8 @@
+use std::collections::HashSet;
 use std::{fmt, io, mem};
 
+use cyphernet::{Digest, Sha256};
+use localtime::LocalDuration;
 use nonempty::NonEmpty;
 use radicle::git;
 use radicle::storage::refs::RefsAt;
@@ -7,7 +10,7 @@ use radicle::storage::refs::RefsAt;
 use crate::crypto;
 use crate::identity::RepoId;
 use crate::node;
-use crate::node::{Address, Alias};
+use crate::node::{address, Address, Alias};
 use crate::prelude::BoundedVec;
 use crate::service::filter::Filter;
 use crate::service::{Link, NodeId, Timestamp};
@@ -20,6 +23,8 @@ pub const ADDRESS_LIMIT: usize = 16;
 pub const REF_REMOTE_LIMIT: usize = 1024;
 /// Maximum number of inventory which can be announced to other nodes.
 pub const INVENTORY_LIMIT: usize = 2973;
+/// Maximum number of pages an inventory can be announced in. See [`InventoryPage`].
+pub const INVENTORY_PAGES_LIMIT: u16 = 32;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 // TODO: We should check the length and charset when deserializing.
@@ -164,6 +169,19 @@ pub struct RefsAnnouncement {
     pub timestamp: Timestamp,
 }
 
+impl RefsAnnouncement {
+    /// Check whether this announcement has any refs we don't already have, according
+    /// to the given refs database.
+    pub fn is_fresh<D: node::refs::Store>(&self, db: &D) -> Result<bool, storage::Error> {
+        let Some(refs) = NonEmpty::from_vec(self.refs.to_vec()) else {
+            return Ok(false);
+        };
+        let status = RefsStatus::new(self.rid, refs, db)?;
+
+        Ok(!status.want.is_empty())
+    }
+}
+
 /// Track the status of `RefsAt` within a given repository.
 #[derive(Default)]
 pub struct RefsStatus {
@@ -232,6 +250,191 @@ pub struct InventoryAnnouncement {
     pub timestamp: Timestamp,
 }
 
+impl InventoryAnnouncement {
+    /// Hash of the announced inventory.
+    pub fn hash(&self) -> InventoryHash {
+        InventoryHash::new(&self.inventory)
+    }
+}
+
+/// Hash of an inventory, which inventory diffs refer to as their base.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InventoryHash([u8; Sha256::OUTPUT_LEN]);
+
+impl InventoryHash {
+    /// Hash an inventory. The order of the repositories matters.
+    pub fn new(inventory: &[RepoId]) -> Self {
+        Self(Sha256::digest_concat(inventory.iter().map(wire::serialize)))
+    }
+
+    /// Get the hash bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<[u8; Sha256::OUTPUT_LEN]> for InventoryHash {
+    fn from(hash: [u8; Sha256::OUTPUT_LEN]) -> Self {
+        Self(hash)
+    }
+}
+
+impl fmt::Display for InventoryHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in self.0 {
+            write!(f, "{b:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Inventory announcement, sent as the difference with a previous inventory of the
+/// same node, which the receiver is known to have.
+///
+/// Large seeds announce thousands of repositories, of which only a few change between
+/// two announcements. The receiver rebuilds the full announcement, which is what the
+/// signatures are over, by removing the `removed` repositories from the base inventory
+/// and appending the `added` ones. The announcement is thus only sent as a diff if it
+/// can be rebuilt that way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InventoryDiff {
+    /// Node identifier.
+    pub node: NodeId,
+    /// Hash of the inventory this diff applies to.
+    pub base: InventoryHash,
+    /// Repositories removed from the base inventory.
+    pub removed: BoundedVec<RepoId, INVENTORY_LIMIT>,
+    /// Repositories added to the base inventory.
+    pub added: BoundedVec<RepoId, INVENTORY_LIMIT>,
+    /// Time of announcement.
+    pub timestamp: Timestamp,
+    /// Signature over the full inventory announcement.
+    pub signature: crypto::Signature,
+    /// Sequence of the full inventory announcement, if any.
+    pub sequence: Option<Sequence>,
+    /// Number of hops the diff was relayed over. See [`Announcement::hops`].
+    pub hops: u8,
+}
+
+impl InventoryDiff {
+    /// Express an inventory announcement as a diff against the given base inventory.
+    ///
+    /// Returns `None` if the announcement isn't an inventory announcement, if it can't be
+    /// rebuilt from the diff, eg. because repositories were re-ordered, or if the diff
+    /// isn't smaller than the announcement.
+    pub fn new(ann: &Announcement, base: &[RepoId]) -> Option<Self> {
+        let AnnouncementMessage::Inventory(msg) = &ann.message else {
+            return None;
+        };
+        let inventory = msg.inventory.as_slice();
+        let current = inventory.iter().collect::<HashSet<_>>();
+        let (retained, removed): (Vec<RepoId>, Vec<RepoId>) =
+            base.iter().partition(|rid| current.contains(rid));
+        let added = inventory.get(retained.len()..)?;
+
+        if inventory[..retained.len()] != retained[..] {
+            return None;
+        }
+        if removed.len() + added.len() >= inventory.len() {
+            return None;
+        }
+        Some(Self {
+            node: ann.node,
+            base: InventoryHash::new(base),
+            removed: BoundedVec::truncate(removed),
+            added: BoundedVec::truncate(added.to_vec()),
+            timestamp: msg.timestamp,
+            signature: ann.signature,
+            sequence: ann.sequence,
+            hops: ann.hops,
+        })
+    }
+
+    /// Rebuild the full inventory announcement, given the base inventory.
+    ///
+    /// Returns `None` if the given inventory isn't the base of this diff, or if the
+    /// rebuilt inventory exceeds the size limit.
+    pub fn apply(&self, base: &[RepoId]) -> Option<Announcement> {
+        if InventoryHash::new(base) != self.base {
+            return None;
+        }
+        let removed = self.removed.iter().collect::<HashSet<_>>();
+        let inventory = base
+            .iter()
+            .filter(|rid| !removed.contains(rid))
+            .chain(self.added.iter())
+            .cloned()
+            .collect::<Vec<_>>();
+
+        Some(Announcement {
+            node: self.node,
+            message: InventoryAnnouncement {
+                inventory: BoundedVec::try_from(inventory).ok()?,
+                timestamp: self.timestamp,
+            }
+            .into(),
+            signature: self.signature,
+            sequence: self.sequence,
+            labels: None,
+            hops: self.hops,
+        })
+    }
+}
+
+/// Page of an inventory too large to fit in a single [`InventoryAnnouncement`].
+///
+/// Every page is signed on its own, so that it can be verified and relayed as soon as it's
+/// received. The pages of an inventory share the same timestamp, which is how receivers
+/// reassemble them: the inventory is only processed once all of its pages are received.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InventoryPage {
+    /// Part of the node inventory.
+    pub inventory: BoundedVec<RepoId, INVENTORY_LIMIT>,
+    /// Index of this page, starting from zero.
+    pub page: u16,
+    /// Number of pages the inventory is split into.
+    pub pages: u16,
+    /// Time of announcement.
+    pub timestamp: Timestamp,
+}
+
+impl InventoryPage {
+    /// Check that the page index is within the announced number of pages, and that
+    /// the number of pages is within [`INVENTORY_PAGES_LIMIT`].
+    pub fn is_valid(&self) -> bool {
+        self.page < self.pages && self.pages <= INVENTORY_PAGES_LIMIT
+    }
+}
+
+/// Node announcing its health, so that other nodes can pick healthy seeds to clone from.
+///
+/// Heartbeats are short-lived: they are only relayed to peers that advertise support for
+/// them, and are never replayed to subscribers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeartbeatAnnouncement {
+    /// How long the node has been running for, in seconds.
+    pub uptime: u64,
+    /// Free space in the node's storage, in bytes.
+    pub storage_free: u64,
+    /// Size of the largest repository the node accepts, in bytes, or zero if there
+    /// is no limit.
+    pub max_repo_size: u64,
+    /// Time of announcement.
+    pub timestamp: Timestamp,
+}
+
+impl HeartbeatAnnouncement {
+    /// Get the health of the announcing node.
+    pub fn health(&self) -> node::Health {
+        node::Health {
+            uptime: LocalDuration::from_secs(self.uptime),
+            storage_free: self.storage_free,
+            max_repo_size: (self.max_repo_size > 0).then_some(self.max_repo_size),
+            timestamp: self.timestamp,
+        }
+    }
+}
+
 /// Node announcing information to a connected peer.
 ///
 /// This should not be relayed and should be used to send an
@@ -241,6 +444,12 @@ pub enum Info {
     /// Tell a node that sent a refs announcement that it was already synced at the given `Oid`,
     /// for this particular `rid`.
     RefsAlreadySynced { rid: RepoId, at: git::Oid },
+    /// Tell a node which address we observe its connection coming from. This lets nodes
+    /// learn their external address without having to configure it.
+    ObservedAddress { addr: Address },
+    /// Tell a node that sent an inventory diff that we don't have the inventory it
+    /// applies to, so that it sends the full inventory instead.
+    MissingInventory { node: NodeId },
 }
 
 /// Announcement messages are messages that are relayed between peers.
@@ -252,6 +461,10 @@ pub enum AnnouncementMessage {
     Node(NodeAnnouncement),
     /// Refs announcement.
     Refs(RefsAnnouncement),
+    /// Heartbeat announcement.
+    Heartbeat(HeartbeatAnnouncement),
+    /// Inventory page announcement.
+    InventoryPage(InventoryPage),
 }
 
 impl AnnouncementMessage {
@@ -264,6 +477,9 @@ impl AnnouncementMessage {
             node: *signer.public_key(),
             message: self,
             signature,
+            sequence: None,
+            labels: None,
+            hops: 0,
         }
     }
 
@@ -272,6 +488,8 @@ impl AnnouncementMessage {
             Self::Inventory(InventoryAnnouncement { timestamp, .. }) => *timestamp,
             Self::Refs(RefsAnnouncement { timestamp, .. }) => *timestamp,
             Self::Node(NodeAnnouncement { timestamp, .. }) => *timestamp,
+            Self::Heartbeat(HeartbeatAnnouncement { timestamp, .. }) => *timestamp,
+            Self::InventoryPage(InventoryPage { timestamp, .. }) => *timestamp,
         }
     }
 }
@@ -294,6 +512,18 @@ impl From<RefsAnnouncement> for AnnouncementMessage {
     }
 }
 
+impl From<InventoryPage> for AnnouncementMessage {
+    fn from(ann: InventoryPage) -> Self {
+        Self::InventoryPage(ann)
+    }
+}
+
+impl From<HeartbeatAnnouncement> for AnnouncementMessage {
+    fn from(ann: HeartbeatAnnouncement) -> Self {
+        Self::Heartbeat(ann)
+    }
+}
+
 impl fmt::Debug for AnnouncementMessage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -318,10 +548,71 @@ impl fmt::Debug for AnnouncementMessage {
                     message.rid, message.timestamp, message.refs
                 )
             }
+            Self::Heartbeat(message) => write!(f, "Heartbeat({})", message.timestamp),
+            Self::InventoryPage(message) => write!(
+                f,
+                "InventoryPage({}/{}, {} item(s), {})",
+                message.page + 1,
+                message.pages,
+                message.inventory.len(),
+                message.timestamp
+            ),
         }
     }
 }
 
+/// Sequence number of an announcement.
+///
+/// Nodes number their announcements with a counter that only goes up, so that an
+/// announcement can't be replayed after a newer one, even if the announcer's clock went
+/// backwards. The sequence number has its own signature, so that it can be stripped when
+/// relaying the announcement to nodes that don't support it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sequence {
+    /// Sequence number.
+    pub seq: u64,
+    /// Signature over the announcement message and sequence number.
+    pub signature: crypto::Signature,
+}
+
+impl Sequence {
+    /// Highest valid sequence number. Sequence numbers are stored as signed 64-bit integers.
+    pub const MAX: u64 = i64::MAX as u64;
+
+    /// Payload signed by the sequence signature.
+    pub fn payload(message: &AnnouncementMessage, seq: u64) -> Vec<u8> {
+        let mut payload = wire::serialize(message);
+        payload.extend_from_slice(&seq.to_be_bytes());
+        payload
+    }
+}
+
+/// Labels of the addresses of a node announcement.
+///
+/// Nodes label each address they announce with how they came by it, so that other nodes
+/// can prefer the addresses most likely to be reachable. Like the [`Sequence`], the labels
+/// have their own signature, so that they can be stripped when relaying the announcement
+/// to nodes that don't support them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Labels {
+    /// Label of each announced address, in the same order.
+    pub labels: BoundedVec<address::Label, ADDRESS_LIMIT>,
+    /// Signature over the announcement message and labels.
+    pub signature: crypto::Signature,
+}
+
+impl Labels {
+    /// Payload signed by the labels signature.
+    pub fn payload(
+        message: &AnnouncementMessage,
+        labels: &BoundedVec<address::Label, ADDRESS_LIMIT>,
+    ) -> Vec<u8> {
+        let mut payload = wire::serialize(message);
+        payload.extend_from_slice(&wire::serialize(labels));
+        payload
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Announcement {
     /// Node identifier.
@@ -330,6 +621,13 @@ pub struct Announcement {
     pub message: AnnouncementMessage,
     /// Signature over the announcement.
     pub signature: crypto::Signature,
+    /// Sequence number, if the announcement is sequenced.
+    pub sequence: Option<Sequence>,
+    /// Address labels, if this is a labeled node announcement.
+    pub labels: Option<Labels>,
+    /// Number of hops the announcement was relayed over before reaching us, zero if it was
+    /// received from its announcer. It isn't signed, since relayers increment it.
+    pub hops: u8,
 }
 
 impl Announcement {
@@ -351,16 +649,112 @@ impl Announcement {
     /// Salt used for generating PoW.
     pub const POW_SALT: &'static [u8] = &[b'r', b'a', b'd'];
 
-    /// Verify this announcement's signature.
+    /// Verify this announcement's signatures.
     pub fn verify(&self) -> bool {
         let msg = wire::serialize(&self.message);
-        self.node.verify(msg, &self.signature).is_ok()
+        if self.node.verify(msg, &self.signature).is_err() {
+            return false;
+        }
+        if let Some(Sequence { seq, signature }) = &self.sequence {
+            let payload = Sequence::payload(&self.message, *seq);
+            if self.node.verify(payload, signature).is_err() {
+                return false;
+            }
+        }
+        if let Some(Labels { labels, signature }) = &self.labels {
+            // Only node announcements are labeled, with one label per address.
+            let AnnouncementMessage::Node(node) = &self.message else {
+                return false;
+            };
+            if labels.len() != node.addresses.len() {
+                return false;
+            }
+            let payload = Labels::payload(&self.message, labels);
+            if self.node.verify(payload, signature).is_err() {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Number this announcement with the given sequence number.
+    pub fn sequenced<G: crypto::Signer>(mut self, seq: u64, signer: &G) -> Self {
+        let signature = signer.sign(&Sequence::payload(&self.message, seq));
+        self.sequence = Some(Sequence { seq, signature });
+        self
+    }
+
+    /// Strip the sequence number, eg. for nodes that don't support it.
+    pub fn unsequenced(self) -> Self {
+        Self {
+            sequence: None,
+            ..self
+        }
+    }
+
+    /// Label the addresses of this node announcement with the given labels.
+    pub fn labeled<G: crypto::Signer>(
+        mut self,
+        labels: BoundedVec<address::Label, ADDRESS_LIMIT>,
+        signer: &G,
+    ) -> Self {
+        let signature = signer.sign(&Labels::payload(&self.message, &labels));
+        self.labels = Some(Labels { labels, signature });
+        self
+    }
+
+    /// Strip the address labels, eg. for nodes that don't support them.
+    pub fn unlabeled(self) -> Self {
+        Self {
+            labels: None,
+            ..self
+        }
+    }
+
+    /// Count one more hop, as this announcement is relayed.
+    pub fn relayed(self) -> Self {
+        Self {
+            hops: self.hops.saturating_add(1),
+            ..self
+        }
+    }
+
+    /// Get the announced addresses along with their labels, if this is a node
+    /// announcement.
+    pub fn addresses(&self) -> Option<Vec<(Address, Option<address::Label>)>> {
+        let AnnouncementMessage::Node(node) = &self.message else {
+            return None;
+        };
+        let labels = self.labels.as_ref().map(|l| l.labels.as_slice());
+
+        Some(
+            node.addresses
+                .iter()
+                .enumerate()
+                .map(|(i, addr)| (addr.clone(), labels.and_then(|l| l.get(i)).copied()))
+                .collect(),
+        )
+    }
+
+    /// Get the inventory announced, if this is an inventory announcement.
+    pub fn inventory(&self) -> Option<&InventoryAnnouncement> {
+        match &self.message {
+            AnnouncementMessage::Inventory(inv) => Some(inv),
+            _ => None,
+        }
+    }
+
+    /// Get the announcement sequence number, if any.
+    pub fn seq(&self) -> Option<u64> {
+        self.sequence.map(|s| s.seq)
     }
 
     pub fn matches(&self, filter: &Filter) -> bool {
         match &self.message {
             AnnouncementMessage::Inventory(_) => true,
             AnnouncementMessage::Node(_) => true,
+            AnnouncementMessage::Heartbeat(_) => true,
+            AnnouncementMessage::InventoryPage(_) => true,
             AnnouncementMessage::Refs(RefsAnnouncement { rid, .. }) => filter.contains(rid),
         }
     }
@@ -387,6 +781,10 @@ pub enum Message {
     /// using [`Message::Subscribe`].
     Announcement(Announcement),
 
+    /// Inventory announcement, sent as a diff against a previous inventory of the
+    /// same node. Handled as the [`Message::Announcement`] it is rebuilt into.
+    InventoryDiff(InventoryDiff),
+
     /// Informational message. These messages are sent between peers for information
     /// and do not need to be acted upon. They can be safely ignored, though handling
     /// them can be useful for the user.
@@ -403,6 +801,26 @@ pub enum Message {
         /// The pong payload.
         zeroes: ZeroBytes,
     },
+
+    /// Ask a bridge to introduce us to one of its peers, so that we can attempt
+    /// to connect to each other directly.
+    Rendezvous(Rendezvous),
+
+    /// Sent by a bridge to both peers of a rendezvous at the same time, so that
+    /// they dial each other simultaneously. This gives peers behind NAT a chance
+    /// to connect directly.
+    ConnectTo(ConnectTo),
+
+    /// Message compressed for the wire. Large announcements are sent this way to peers that
+    /// support compression. Compressed messages are decompressed when decoded, and are
+    /// thus never received as such.
+    Compressed(Compressed),
+
+    /// Sent before closing a connection, to let the peer know why it is being closed.
+    Disconnect {
+        /// Reason for closing the connection.
+        reason: DisconnectCode,
+    },
 }
 
 impl PartialOrd for Message {
@@ -430,6 +848,9 @@ impl Message {
             node,
             signature,
             message: message.into(),
+            sequence: None,
+            labels: None,
+            hops: 0,
         }
         .into()
     }
@@ -475,22 +896,120 @@ impl Message {
                         inventory.len()
                     )
                 }
+                AnnouncementMessage::Heartbeat(HeartbeatAnnouncement { timestamp, .. }) => format!(
+                    "{verb} heartbeat announcement of {node} {prep} {remote} (t={timestamp})"
+                ),
+                AnnouncementMessage::InventoryPage(InventoryPage { inventory, page, pages, timestamp }) => format!(
+                    "{verb} inventory page {}/{pages} of {node} with {} item(s) {prep} {remote} (t={timestamp})",
+                    page + 1,
+                    inventory.len()
+                ),
             },
+            Self::InventoryDiff(InventoryDiff { node, removed, added, timestamp, .. }) => format!(
+                "{verb} inventory diff of {node} with {} addition(s) and {} removal(s) {prep} {remote} (t={timestamp})",
+                added.len(),
+                removed.len()
+            ),
             Self::Info(Info::RefsAlreadySynced { rid,  .. }) => {
                 format!(
                     "{verb} `refs-already-synced` info {prep} {remote} for {rid}"
                 )
             },
+            Self::Info(Info::ObservedAddress { addr }) => {
+                format!(
+                    "{verb} `observed-address` info {prep} {remote} ({addr})"
+                )
+            },
+            Self::Info(Info::MissingInventory { node }) => {
+                format!(
+                    "{verb} `missing-inventory` info {prep} {remote} for {node}"
+                )
+            },
             Self::Ping { .. } => format!("{verb} ping {prep} {remote}"),
             Self::Pong { .. } => format!("{verb} pong {prep} {remote}"),
+            Self::Rendezvous(Rendezvous { node }) => {
+                format!("{verb} rendezvous request for {node} {prep} {remote}")
+            }
+            Self::ConnectTo(ConnectTo { node, addr }) => {
+                format!("{verb} connect-to {node} ({addr}) {prep} {remote}")
+            }
             Self::Subscribe(Subscribe { .. }) => {
                 format!("{verb} subscription filter {prep} {remote}")
             }
+            Self::Disconnect { reason } => {
+                format!("{verb} disconnect ({reason}) {prep} {remote}")
+            }
+            Self::Compressed(Compressed { message, .. }) => {
+                return message.log(level, remote, link);
+            }
         };
         log::log!(target: "service", level, "{msg}");
     }
 }
 
+/// A message, along with its compressed encoding. See [`Message::compressed`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Compressed {
+    /// The uncompressed message.
+    pub message: Box<Message>,
+    /// The message encoding, compressed.
+    pub(crate) data: Vec<u8>,
+}
+
+/// A rendezvous request, sent to a bridge.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Rendezvous {
+    /// The peer we'd like to be introduced to.
+    pub node: NodeId,
+}
+
+/// A rendezvous introduction, sent by a bridge.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ConnectTo {
+    /// The peer to connect to.
+    pub node: NodeId,
+    /// The peer's address, as observed by the bridge.
+    pub addr: Address,
+}
+
+/// Reason given to a peer when closing a connection.
+#[repr(u8)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DisconnectCode {
+    /// Any other reason, eg. a protocol violation.
+    Other = 0,
+    /// The node is shutting down.
+    Shutdown = 1,
+    /// The peer is banned and shouldn't attempt to reconnect.
+    Banned = 2,
+    /// The node has too many connections.
+    TooManyConnections = 3,
+}
+
+impl From<u8> for DisconnectCode {
+    fn from(code: u8) -> Self {
+        match code {
+            1 => Self::Shutdown,
+            2 => Self::Banned,
+            3 => Self::TooManyConnections,
+            // Nb. Unknown codes are treated as generic disconnects, so that new
+            // codes can be introduced without breaking older nodes.
+            _ => Self::Other,
+        }
+    }
+}
+
+impl fmt::Display for DisconnectCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Other => write!(f, "other"),
+            Self::Shutdown => write!(f, "shutdown"),
+            Self::Banned => write!(f, "banned"),
+            Self::TooManyConnections => write!(f, "too many connections"),
+        }
+    }
+}
+
 /// A ping message.
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Ping {
@@ -527,6 +1046,12 @@ impl From<Announcement> for Message {
     }
 }
 
+impl From<InventoryDiff> for Message {
+    fn from(diff: InventoryDiff) -> Self {
+        Self::InventoryDiff(diff)
+    }
+}
+
 impl From<Info> for Message {
     fn from(info: Info) -> Self {
         Self::Info(info)
@@ -542,11 +1067,31 @@ impl fmt::Debug for Message {
             Self::Announcement(Announcement { node, message, .. }) => {
                 write!(f, "Announcement({node}, {message:?})")
             }
+            Self::InventoryDiff(InventoryDiff {
+                node,
+                base,
+                removed,
+                added,
+                ..
+            }) => {
+                write!(
+                    f,
+                    "InventoryDiff({node}, {base}, -{}, +{})",
+                    removed.len(),
+                    added.len()
+                )
+            }
             Self::Info(info) => {
                 write!(f, "Info({info:?})")
             }
             Self::Ping(Ping { ponglen, zeroes }) => write!(f, "Ping({ponglen}, {zeroes:?})"),
             Self::Pong { zeroes } => write!(f, "Pong({zeroes:?})"),
+            Self::Rendezvous(Rendezvous { node }) => write!(f, "Rendezvous({node})"),
+            Self::ConnectTo(ConnectTo { node, addr }) => write!(f, "ConnectTo({node}, {addr})"),
+            Self::Disconnect { reason } => write!(f, "Disconnect({reason})"),
+            Self::Compressed(Compressed { message, data }) => {
+                write!(f, "Compressed({message:?}, {} byte(s))", data.len())
+            }
         }
     }
 }
@@ -643,6 +1188,59 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_inventory_page_limit() {
+        // Like full inventories, full pages only fit in a message when they're unsequenced.
+        let msg = Message::from(
+            AnnouncementMessage::from(InventoryPage {
+                inventory: arbitrary::vec(INVENTORY_LIMIT)
+                    .try_into()
+                    .expect("size within bounds limit"),
+                page: INVENTORY_PAGES_LIMIT - 1,
+                pages: INVENTORY_PAGES_LIMIT,
+                timestamp: LocalTime::now().into(),
+            })
+            .signed(&MockSigner::default()),
+        );
+        let buf = wire::serialize(&msg);
+        assert!(buf.len() <= wire::Size::MAX as usize);
+        assert_eq!(wire::deserialize::<Message>(&buf).unwrap(), msg);
+    }
+
+    #[test]
+    fn test_inventory_diff() {
+        let signer = MockSigner::default();
+        let rids = arbitrary::vec::<RepoId>(6);
+        let base = rids[..4].to_vec();
+        let ann = |inventory: &[RepoId]| {
+            AnnouncementMessage::from(InventoryAnnouncement {
+                inventory: inventory.to_vec().try_into().unwrap(),
+                timestamp: Timestamp::EPOCH,
+            })
+            .signed(&signer)
+        };
+
+        // One repository removed, one added.
+        let inventory = [rids[0], rids[2], rids[3], rids[4]];
+        let expected = ann(&inventory);
+        let diff = InventoryDiff::new(&expected, &base).unwrap();
+
+        assert_eq!(diff.removed.as_slice(), &[rids[1]]);
+        assert_eq!(diff.added.as_slice(), &[rids[4]]);
+        assert_eq!(diff.base, InventoryHash::new(&base));
+        assert_eq!(diff.apply(&base), Some(expected.clone()));
+        assert!(diff.apply(&base).unwrap().verify());
+        assert_eq!(diff.apply(&rids[..3]), None);
+
+        // Re-ordered repositories can't be rebuilt from a diff.
+        let inventory = [rids[1], rids[0], rids[2], rids[3]];
+        assert_eq!(InventoryDiff::new(&ann(&inventory), &base), None);
+
+        // Nor is there a point in a diff that isn't smaller.
+        let inventory = [rids[4], rids[5]];
+        assert_eq!(InventoryDiff::new(&ann(&inventory), &base), None);
+    }
+
     #[quickcheck]
     fn prop_refs_announcement_signing(rid: RepoId) {
         let signer = MockSigner::new(&mut fastrand::Rng::new());
@@ -680,4 +1278,56 @@ mod tests {
         assert_eq!(ann.clone().solve(8).unwrap().work(), 9);
         assert_eq!(ann.solve(14).unwrap().work(), 14);
     }
+
+    #[test]
+    fn test_node_announcement_labels_verify() {
+        use address::Label;
+
+        let signer = MockSigner::default();
+        let other = MockSigner::new(&mut fastrand::Rng::new());
+        let addresses = [
+            "1.1.1.1:8776".parse::<Address>().unwrap(),
+            "2.2.2.2:8776".parse::<Address>().unwrap(),
+        ];
+        let ann = AnnouncementMessage::from(NodeAnnouncement {
+            features: node::Features::SEED,
+            timestamp: Timestamp::EPOCH,
+            alias: Alias::new("alice"),
+            addresses: BoundedVec::collect_from(&mut addresses.clone().into_iter()),
+            nonce: 0,
+        })
+        .signed(&signer);
+        let labels = |labels: &[Label]| BoundedVec::collect_from(&mut labels.iter().copied());
+
+        let labeled = ann
+            .clone()
+            .labeled(labels(&[Label::Manual, Label::Observed]), &signer);
+        assert!(labeled.verify());
+        assert_eq!(
+            labeled.addresses().unwrap(),
+            vec![
+                (addresses[0].clone(), Some(Label::Manual)),
+                (addresses[1].clone(), Some(Label::Observed)),
+            ]
+        );
+        assert!(labeled.unlabeled().verify());
+
+        // Every address must be labeled, by the announcer.
+        assert!(!ann
+            .clone()
+            .labeled(labels(&[Label::Manual]), &signer)
+            .verify());
+        assert!(!ann
+            .clone()
+            .labeled(labels(&[Label::Manual, Label::Upnp]), &other)
+            .verify());
+        // Only node announcements are labeled.
+        let inventory = AnnouncementMessage::from(InventoryAnnouncement {
+            inventory: BoundedVec::new(),
+            timestamp: Timestamp::EPOCH,
+        })
+        .signed(&signer);
+        assert_eq!(inventory.addresses(), None);
+        assert!(!inventory.labeled(labels(&[]), &signer).verify());
+    }
 }
@@ -0,0 +1,86 @@
+use std::net;
+
+use radicle::node::{address, NodeId};
+
+use crate::collections::{RandomMap, RandomSet};
+
+/// Number of distinct peers that have to observe the same address for us, before
+/// we consider it confirmed.
+pub const CONFIRMATION_THRESHOLD: usize = 3;
+/// Maximum number of distinct observed addresses we keep track of.
+pub const MAX_OBSERVED_ADDRESSES: usize = 32;
+
+/// External addresses observed for our node by peers.
+///
+/// Each peer gets one vote, and only routable addresses are considered. Since the port
+/// observed by peers is usually an ephemeral port, only IP addresses are tracked.
+#[derive(Debug, Default)]
+pub struct ObservedAddresses {
+    /// Peers that observed each address.
+    observed: RandomMap<net::IpAddr, RandomSet<NodeId>>,
+    /// Addresses that were confirmed.
+    confirmed: RandomSet<net::IpAddr>,
+}
+
+impl ObservedAddresses {
+    /// Record an address observed by a peer.
+    ///
+    /// Returns the address if this observation confirms it for the first time.
+    pub fn observe(&mut self, peer: NodeId, ip: net::IpAddr) -> Option<net::IpAddr> {
+        if !address::is_routable(&ip) {
+            return None;
+        }
+        // A peer only gets to vote for one address.
+        for (other, peers) in self.observed.iter_mut() {
+            if *other != ip {
+                peers.remove(&peer);
+            }
+        }
+        self.observed.retain(|_, peers| !peers.is_empty());
+
+        if !self.observed.contains_key(&ip) && self.observed.len() >= MAX_OBSERVED_ADDRESSES {
+            return None;
+        }
+        let peers = self.observed.entry(ip).or_default();
+        peers.insert(peer);
+
+        if peers.len() >= CONFIRMATION_THRESHOLD && self.confirmed.insert(ip) {
+            return Some(ip);
+        }
+        None
+    }
+
+    /// Check whether an address was confirmed.
+    pub fn is_confirmed(&self, ip: &net::IpAddr) -> bool {
+        self.confirmed.contains(ip)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_observed_addresses() {
+        let mut observed = ObservedAddresses::default();
+        let ip = net::IpAddr::from([88, 12, 4, 1]);
+        let other = net::IpAddr::from([88, 12, 4, 2]);
+        let peers = [[1; 32], [2; 32], [3; 32], [4; 32]].map(NodeId::from);
+
+        assert_eq!(observed.observe(peers[0], ip), None);
+        assert_eq!(observed.observe(peers[0], ip), None); // Same peer, same vote.
+        assert_eq!(observed.observe(peers[1], ip), None);
+        assert_eq!(observed.observe(peers[1], other), None); // Peer changes its vote.
+        assert_eq!(observed.observe(peers[2], ip), None);
+        assert!(!observed.is_confirmed(&ip));
+        assert_eq!(observed.observe(peers[3], ip), Some(ip));
+        assert!(observed.is_confirmed(&ip));
+        assert_eq!(observed.observe(peers[1], ip), None); // Already confirmed.
+
+        // Non-routable addresses are ignored.
+        let local = net::IpAddr::from([192, 168, 1, 1]);
+        for peer in peers {
+            assert_eq!(observed.observe(peer, local), None);
+        }
+    }
+}
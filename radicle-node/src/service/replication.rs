@@ -0,0 +1,81 @@
+use radicle::identity::RepoId;
+use radicle::node::NodeId;
+
+use crate::collections::{RandomMap, RandomSet};
+
+/// Tracks the redundancy of seeded repositories, ie. how many reachable peers seed them.
+///
+/// When a repository is under-replicated, we connect to its seeds and fetch from them
+/// once connected, so that our copy stays in sync with every other copy out there.
+#[derive(Debug, Default)]
+pub struct Replication {
+    /// Repositories that are only available from a single peer.
+    single: RandomSet<RepoId>,
+    /// Seeds we're connecting to, and the repositories to fetch from them.
+    pending: RandomMap<NodeId, RandomSet<RepoId>>,
+}
+
+impl Replication {
+    /// Update the number of reachable seeds of a repository.
+    ///
+    /// Returns `true` if the repository just became available from only a single peer.
+    pub fn update(&mut self, rid: RepoId, reachable: usize) -> bool {
+        if reachable == 1 {
+            self.single.insert(rid)
+        } else {
+            self.single.remove(&rid);
+            false
+        }
+    }
+
+    /// Record that we're connecting to a seed, to fetch the given repository from it.
+    pub fn dialed(&mut self, nid: NodeId, rid: RepoId) {
+        self.pending.entry(nid).or_default().insert(rid);
+    }
+
+    /// Record that a seed is connected. Returns the repositories to fetch from it.
+    pub fn connected(&mut self, nid: &NodeId) -> RandomSet<RepoId> {
+        self.pending.remove(nid).unwrap_or_default()
+    }
+
+    /// Record that a seed disconnected before we could fetch from it.
+    pub fn disconnected(&mut self, nid: &NodeId) {
+        self.pending.remove(nid);
+    }
+
+    /// Check whether we're connecting to a seed.
+    pub fn is_pending(&self, nid: &NodeId) -> bool {
+        self.pending.contains_key(nid)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::arbitrary;
+
+    #[test]
+    fn test_replication() {
+        let mut replication = Replication::default();
+        let rid = arbitrary::gen::<RepoId>(1);
+        let nid = NodeId::from([1; 32]);
+
+        assert!(!replication.update(rid, 0));
+        assert!(replication.update(rid, 1));
+        assert!(!replication.update(rid, 1), "We only warn once");
+        assert!(!replication.update(rid, 2));
+        assert!(replication.update(rid, 1));
+
+        replication.dialed(nid, rid);
+        assert!(replication.is_pending(&nid));
+        assert_eq!(
+            replication.connected(&nid).into_iter().collect::<Vec<_>>(),
+            vec![rid]
+        );
+        assert!(replication.connected(&nid).is_empty());
+
+        replication.dialed(nid, rid);
+        replication.disconnected(&nid);
+        assert!(!replication.is_pending(&nid));
+    }
+}
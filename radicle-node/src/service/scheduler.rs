@@ -0,0 +1,239 @@
+use std::collections::VecDeque;
+
+use localtime::{LocalDuration, LocalTime};
+use nonempty::NonEmpty;
+use radicle::identity::RepoId;
+use radicle::node::NodeId;
+use radicle::storage::refs::RefsAt;
+
+use crate::collections::RandomMap;
+use crate::service::Rng;
+
+/// Minimum time between two fetches of the same repository, triggered by announcements.
+/// Announcements received in the meantime are coalesced into a single fetch.
+pub const FETCH_DEBOUNCE: LocalDuration = LocalDuration::from_secs(3);
+/// Window over which fetches are spread out when there are too many at once.
+pub const FETCH_SPREAD: LocalDuration = LocalDuration::from_secs(10);
+/// Number of fetches that can be dispatched within [`FETCH_SPREAD`] without delay.
+pub const FETCH_BURST: usize = 8;
+/// How long a repository is prioritized for after the user interacted with it.
+pub const INTERACTION_WINDOW: LocalDuration = LocalDuration::from_mins(60);
+
+/// A fetch that is due.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scheduled {
+    /// Repository to fetch.
+    pub rid: RepoId,
+    /// Node to fetch from.
+    pub from: NodeId,
+    /// Announced refs to fetch.
+    pub refs: NonEmpty<RefsAt>,
+}
+
+/// A fetch waiting to be dispatched.
+#[derive(Debug)]
+struct Pending {
+    refs: NonEmpty<RefsAt>,
+    due: LocalTime,
+    priority: bool,
+}
+
+/// Schedules fetches triggered by refs announcements.
+///
+/// Repeated announcements for the same repository are debounced, repositories the user
+/// recently interacted with are fetched first, and bursts of announcements, eg. after
+/// reconnecting to the network, are spread out over time.
+#[derive(Debug)]
+pub struct FetchScheduler {
+    /// Fetches waiting to be dispatched, keyed by repository and announcer.
+    pending: RandomMap<(RepoId, NodeId), Pending>,
+    /// Last time each repository was dispatched.
+    dispatched: RandomMap<RepoId, LocalTime>,
+    /// Last time the user interacted with each repository.
+    interacted: RandomMap<RepoId, LocalTime>,
+    /// Times of recent dispatches, within the spread window.
+    recent: VecDeque<LocalTime>,
+    /// Time of the next scheduled wakeup, if any.
+    wakeup: Option<LocalTime>,
+    /// Source of entropy, for spreading fetches.
+    rng: Rng,
+}
+
+impl FetchScheduler {
+    /// Create a new scheduler.
+    pub fn new(rng: Rng) -> Self {
+        Self {
+            pending: RandomMap::with_hasher(rng.clone().into()),
+            dispatched: RandomMap::with_hasher(rng.clone().into()),
+            interacted: RandomMap::with_hasher(rng.clone().into()),
+            recent: VecDeque::new(),
+            wakeup: None,
+            rng,
+        }
+    }
+
+    /// Record that the user interacted with a repository, eg. by fetching it.
+    pub fn interacted(&mut self, rid: RepoId, now: LocalTime) {
+        self.interacted.insert(rid, now);
+    }
+
+    /// Queue a fetch of announced refs. If a fetch from the same node is already queued,
+    /// the refs are merged into it.
+    pub fn queue(&mut self, rid: RepoId, from: NodeId, refs: NonEmpty<RefsAt>, now: LocalTime) {
+        if let Some(pending) = self.pending.get_mut(&(rid, from)) {
+            for theirs in refs {
+                let ours = pending.refs.iter_mut().find(|r| r.remote == theirs.remote);
+                if let Some(ours) = ours {
+                    *ours = theirs;
+                } else {
+                    pending.refs.push(theirs);
+                }
+            }
+            return;
+        }
+        let priority = self
+            .interacted
+            .get(&rid)
+            .is_some_and(|t| now - *t < INTERACTION_WINDOW);
+        let mut due = self
+            .dispatched
+            .get(&rid)
+            .map_or(now, |t| (*t + FETCH_DEBOUNCE).max(now));
+
+        if !priority {
+            self.recent.retain(|t| now - *t < FETCH_SPREAD);
+
+            let queued = self.pending.values().filter(|p| p.due <= now).count();
+            if self.recent.len() + queued >= FETCH_BURST {
+                let jitter = self.rng.u64(0..=FETCH_SPREAD.as_millis() as u64);
+                due = due + LocalDuration::from_millis(jitter as u128);
+            }
+        }
+        self.pending.insert(
+            (rid, from),
+            Pending {
+                refs,
+                due,
+                priority,
+            },
+        );
+    }
+
+    /// Return the fetches that are due, prioritized ones first. Call [`FetchScheduler::fetched`]
+    /// for each fetch that is initiated.
+    pub fn dispatch(&mut self, now: LocalTime) -> Vec<Scheduled> {
+        let mut due = Vec::new();
+
+        self.pending.retain(|(rid, from), pending| {
+            if pending.due > now {
+                return true;
+            }
+            due.push((
+                pending.priority,
+                pending.due,
+                Scheduled {
+                    rid: *rid,
+                    from: *from,
+                    refs: pending.refs.clone(),
+                },
+            ));
+            false
+        });
+        due.sort_by_key(|(priority, due, _)| (!*priority, *due));
+
+        self.dispatched.retain(|_, t| now - *t < FETCH_DEBOUNCE);
+        self.interacted.retain(|_, t| now - *t < INTERACTION_WINDOW);
+
+        due.into_iter().map(|(_, _, s)| s).collect()
+    }
+
+    /// Record that a fetch was initiated for a dispatched repository. Fetches that were
+    /// skipped, eg. because there was nothing to fetch, don't count towards the debounce
+    /// period.
+    pub fn fetched(&mut self, rid: RepoId, now: LocalTime) {
+        self.dispatched.insert(rid, now);
+        self.recent.push_back(now);
+    }
+
+    /// Schedule a wakeup for the next pending fetch. Returns the time to wait until then,
+    /// or `None` if there's nothing pending, or a wakeup is already scheduled by then.
+    pub fn schedule(&mut self, now: LocalTime) -> Option<LocalDuration> {
+        let next = self
+            .pending
+            .values()
+            .map(|p| p.due)
+            .filter(|due| *due > now)
+            .min()?;
+
+        if let Some(wakeup) = self.wakeup {
+            if wakeup > now && wakeup <= next {
+                return None;
+            }
+        }
+        self.wakeup = Some(next);
+
+        Some(next - now)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::arbitrary;
+
+    #[test]
+    fn test_fetch_scheduler() {
+        let mut scheduler = FetchScheduler::new(fastrand::Rng::with_seed(1));
+        let rid = arbitrary::gen::<RepoId>(1);
+        let from = NodeId::from([1; 32]);
+        let refs = arbitrary::gen::<RefsAt>(1);
+        let mut now = LocalTime::from_secs(1_000_000);
+
+        // The first announcement is fetched right away.
+        scheduler.queue(rid, from, NonEmpty::new(refs), now);
+        assert_eq!(scheduler.dispatch(now).len(), 1);
+        scheduler.fetched(rid, now);
+
+        // Repeated announcements are coalesced until the debounce period is over.
+        let other = arbitrary::gen::<RefsAt>(2);
+        scheduler.queue(rid, from, NonEmpty::new(refs), now);
+        scheduler.queue(rid, from, NonEmpty::new(other), now);
+        assert!(scheduler.dispatch(now).is_empty());
+        assert_eq!(scheduler.schedule(now), Some(FETCH_DEBOUNCE));
+
+        now = now + FETCH_DEBOUNCE;
+        let dispatched = scheduler.dispatch(now);
+        assert_eq!(dispatched.len(), 1);
+        assert_eq!(dispatched[0].refs, NonEmpty::from((refs, vec![other])));
+    }
+
+    #[test]
+    fn test_fetch_scheduler_burst() {
+        let mut scheduler = FetchScheduler::new(fastrand::Rng::with_seed(1));
+        let from = NodeId::from([1; 32]);
+        let refs = arbitrary::gen::<RefsAt>(1);
+        let prioritized = arbitrary::gen::<RepoId>(1);
+        let now = LocalTime::from_secs(1_000_000);
+
+        scheduler.interacted(prioritized, now);
+        for rid in arbitrary::vec::<RepoId>(FETCH_BURST * 4) {
+            scheduler.queue(rid, from, NonEmpty::new(refs), now);
+        }
+        scheduler.queue(prioritized, from, NonEmpty::new(refs), now);
+
+        // Only a burst of fetches is dispatched right away, starting with the prioritized repo.
+        let dispatched = scheduler.dispatch(now);
+        for s in &dispatched {
+            scheduler.fetched(s.rid, now);
+        }
+        assert!(dispatched.len() > FETCH_BURST && dispatched.len() < FETCH_BURST * 2);
+        assert_eq!(dispatched[0].rid, prioritized);
+
+        // The rest is spread out over time.
+        let delay = scheduler.schedule(now).expect("A wakeup is scheduled");
+        assert!(delay <= FETCH_SPREAD);
+
+        let total = dispatched.len() + scheduler.dispatch(now + FETCH_SPREAD).len();
+        assert_eq!(total, FETCH_BURST * 4 + 1);
+    }
+}
@@ -2,7 +2,7 @@ use std::collections::{HashSet, VecDeque};
 use std::fmt;
 
 use crate::node::config::Limits;
-use crate::node::Severity;
+use crate::node::{Features, Severity};
 use crate::service::message;
 use crate::service::message::Message;
 use crate::service::{Address, LocalTime, NodeId, Outbox, RepoId, Rng};
@@ -26,6 +26,9 @@ pub enum Error {
     /// The remote peer timed out.
     #[error("peer timed out")]
     Timeout,
+    /// The remote peer connected, but didn't subscribe to our gossip messages in time.
+    #[error("peer protocol negotiation timed out")]
+    NegotiationTimeout,
 }
 
 impl Error {
@@ -36,6 +39,7 @@ impl Error {
             Self::ProtocolMismatch => Severity::High,
             Self::Misbehavior => Severity::High,
             Self::Timeout => Severity::Low,
+            Self::NegotiationTimeout => Severity::Medium,
         }
     }
 }
@@ -58,6 +62,10 @@ pub struct Session {
     pub subscribe: Option<message::Subscribe>,
     /// Last time a message was received from the peer.
     pub last_active: LocalTime,
+    /// Reason given by the peer for closing the connection, if any.
+    pub disconnect: Option<message::DisconnectCode>,
+    /// Features supported by the peer, as last announced.
+    pub features: Features,
 
     /// Connection attempts. For persistent peers, Tracks
     /// how many times we've attempted to connect. We reset this to zero
@@ -98,6 +106,8 @@ impl Session {
             subscribe: None,
             persistent,
             last_active: LocalTime::default(),
+            disconnect: None,
+            features: Features::NONE,
             attempts: 1,
             rng,
             limits,
@@ -125,12 +135,19 @@ impl Session {
             subscribe: None,
             persistent,
             last_active: time,
+            disconnect: None,
+            features: Features::NONE,
             attempts: 0,
             rng,
             limits,
         }
     }
 
+    /// Change the protocol limits of this session, eg. after the configuration was reloaded.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
     pub fn is_connecting(&self) -> bool {
         matches!(self.state, State::Attempted { .. })
     }
@@ -0,0 +1,150 @@
+//! Service state snapshots.
+//!
+//! Some of the service state only lives in memory: the peers we're connected to, and the
+//! fetches that are queued or running. A snapshot of it is saved periodically and on
+//! shutdown, so that a restarted node can reconnect to the same peers and resume its
+//! fetches, instead of warming up from scratch. The routing table and the gossip messages
+//! we received, which tell us what to subscribe to, are already persisted in the node
+//! database.
+use std::path::Path;
+use std::{fs, io};
+
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+use thiserror::Error;
+
+use radicle::identity::RepoId;
+use radicle::node::{Address, NodeId, Timestamp};
+use radicle::storage::refs::RefsAt;
+
+/// Snapshot file name, in the node directory.
+pub const SNAPSHOT_FILE: &str = "snapshot.json";
+/// Snapshot format version. Snapshots of other versions are ignored.
+pub const VERSION: u32 = 1;
+
+/// Snapshot error.
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("i/o error: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid snapshot: {0}")]
+    Json(#[from] json::Error),
+    #[error("unsupported snapshot version {0}, expected {VERSION}")]
+    Version(u64),
+}
+
+/// Snapshot of the service state that isn't persisted otherwise.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    /// Format version.
+    pub version: u32,
+    /// When the snapshot was taken.
+    pub timestamp: Timestamp,
+    /// Outbound peers we were connected to.
+    pub peers: Vec<Peer>,
+    /// Fetches that were queued or running.
+    pub fetches: Vec<Fetch>,
+}
+
+/// A peer we were connected to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Peer {
+    /// Peer id.
+    pub nid: NodeId,
+    /// Address we connected to.
+    pub addr: Address,
+    /// Last time we received a message from the peer.
+    pub last_active: Timestamp,
+}
+
+/// A fetch that was queued or running.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Fetch {
+    /// Repo to fetch.
+    pub rid: RepoId,
+    /// Peer to fetch from.
+    pub from: NodeId,
+    /// Refs to fetch. Everything is fetched if empty.
+    pub refs_at: Vec<RefsAt>,
+}
+
+impl Snapshot {
+    /// Create a new snapshot.
+    pub fn new(timestamp: Timestamp, peers: Vec<Peer>, fetches: Vec<Fetch>) -> Self {
+        Self {
+            version: VERSION,
+            timestamp,
+            peers,
+            fetches,
+        }
+    }
+
+    /// Load a snapshot from the given path. Returns `None` if there is no snapshot.
+    pub fn load(path: &Path) -> Result<Option<Self>, Error> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        // Check the version before anything else, since the format of other versions
+        // may be different.
+        let value: json::Value = json::from_slice(&bytes)?;
+        match value.get("version").and_then(json::Value::as_u64) {
+            Some(v) if v == VERSION as u64 => {}
+            Some(v) => return Err(Error::Version(v)),
+            None => return Err(Error::Version(0)),
+        }
+        json::from_value(value).map(Some).map_err(Error::from)
+    }
+
+    /// Save the snapshot to the given path. The snapshot is written to a temporary
+    /// file first, and then moved in place, so that a crash never leaves a partial
+    /// snapshot behind.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let tmp = path.with_extension("tmp");
+        let bytes = json::to_vec(self)?;
+
+        fs::write(&tmp, bytes)?;
+        fs::File::open(&tmp)?.sync_all()?;
+        fs::rename(&tmp, path)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+    use radicle::test::arbitrary;
+
+    #[test]
+    fn test_save_load() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join(SNAPSHOT_FILE);
+
+        assert!(Snapshot::load(&path).unwrap().is_none());
+
+        let snapshot = Snapshot::new(
+            Timestamp::from(1706000000000),
+            vec![Peer {
+                nid: arbitrary::gen(1),
+                addr: "seed.radicle.xyz:8776".parse().unwrap(),
+                last_active: Timestamp::from(1705999990000),
+            }],
+            vec![Fetch {
+                rid: arbitrary::gen(1),
+                from: arbitrary::gen(1),
+                refs_at: vec![],
+            }],
+        );
+        snapshot.save(&path).unwrap();
+        assert_eq!(Snapshot::load(&path).unwrap(), Some(snapshot));
+
+        fs::write(&path, r#"{ "version": 2, "peers": {} }"#).unwrap();
+        assert!(matches!(Snapshot::load(&path), Err(Error::Version(2))));
+    }
+}
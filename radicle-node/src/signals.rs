@@ -3,11 +3,20 @@ use std::sync::Mutex;
 
 use crossbeam_channel as chan;
 
+/// Signal received by the process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// `SIGTERM` or `SIGINT`: the process should shut down.
+    Terminate,
+    /// `SIGHUP`: the configuration should be reloaded.
+    Hangup,
+}
+
 /// Signal notifications are sent via this channel.
-static NOTIFY: Mutex<Option<chan::Sender<()>>> = Mutex::new(None);
+static NOTIFY: Mutex<Option<chan::Sender<Signal>>> = Mutex::new(None);
 
-/// Install global signal handlers for `SIGTERM` and `SIGINT`.
-pub fn install(notify: chan::Sender<()>) -> io::Result<()> {
+/// Install global signal handlers for `SIGTERM`, `SIGINT` and `SIGHUP`.
+pub fn install(notify: chan::Sender<Signal>) -> io::Result<()> {
     if let Ok(mut channel) = NOTIFY.try_lock() {
         if channel.is_some() {
             return Err(io::Error::new(
@@ -27,29 +36,30 @@ pub fn install(notify: chan::Sender<()>) -> io::Result<()> {
     Ok(())
 }
 
-/// Install global signal handlers for `SIGTERM` and `SIGINT`.
+/// Install global signal handlers for `SIGTERM`, `SIGINT` and `SIGHUP`.
 ///
 /// # Safety
 ///
 /// Calls `libc` functions safely.
 unsafe fn _install() -> io::Result<()> {
-    if libc::signal(libc::SIGTERM, handler as libc::sighandler_t) == libc::SIG_ERR {
-        return Err(io::Error::last_os_error());
-    }
-    if libc::signal(libc::SIGINT, handler as libc::sighandler_t) == libc::SIG_ERR {
-        return Err(io::Error::last_os_error());
+    for sig in [libc::SIGTERM, libc::SIGINT, libc::SIGHUP] {
+        if libc::signal(sig, handler as libc::sighandler_t) == libc::SIG_ERR {
+            return Err(io::Error::last_os_error());
+        }
     }
     Ok(())
 }
 
 /// Called by `libc` when a signal is received.
 extern "C" fn handler(sig: libc::c_int, _info: *mut libc::siginfo_t, _data: *mut libc::c_void) {
-    if sig != libc::SIGTERM && sig != libc::SIGINT {
-        return;
-    }
+    let signal = match sig {
+        libc::SIGTERM | libc::SIGINT => Signal::Terminate,
+        libc::SIGHUP => Signal::Hangup,
+        _ => return,
+    };
     if let Ok(guard) = NOTIFY.try_lock() {
         if let Some(c) = &*guard {
-            c.try_send(()).ok();
+            c.try_send(signal).ok();
         }
     }
 }
@@ -0,0 +1,42 @@
+//! Node signer.
+//!
+//! Announcements the node broadcasts are signed on a dedicated thread, see [`Signing`].
+use crossbeam_channel as chan;
+
+use radicle::crypto::Signer;
+
+use crate::runtime::Handle;
+use crate::service::io::SignRequest;
+
+/// Signs the announcements we broadcast, and hands them back to the service, so that
+/// signing and solving proof-of-work don't hold up the service thread.
+pub struct Signing<G> {
+    signer: G,
+    requests: chan::Receiver<SignRequest>,
+    handle: Handle,
+}
+
+impl<G: Signer> Signing<G> {
+    /// Create a new signing thread, receiving requests from the service.
+    pub fn new(signer: G, requests: chan::Receiver<SignRequest>, handle: Handle) -> Self {
+        Self {
+            signer,
+            requests,
+            handle,
+        }
+    }
+
+    /// Sign announcements until the service goes away.
+    pub fn run(self) {
+        while let Ok(request) = self.requests.recv() {
+            let route = request.route.clone();
+            let ann = request.sign(&self.signer);
+
+            if let Err(e) = self.handle.signed(ann, route) {
+                log::error!(target: "node", "Unable to hand signed announcement to service: {e}");
+                break;
+            }
+        }
+        log::debug!(target: "node", "Signing thread exiting..");
+    }
+}
@@ -1,8 +1,11 @@
 pub mod arbitrary;
 pub mod environment;
+pub mod expect;
+pub mod golden;
 pub mod gossip;
 pub mod handle;
 pub mod peer;
+pub mod replay;
 pub mod simulator;
 
 pub use radicle::assert_matches;
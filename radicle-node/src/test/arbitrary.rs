@@ -5,12 +5,15 @@ use qcheck::Arbitrary;
 
 use crate::crypto;
 use crate::identity::DocAt;
-use crate::node::Alias;
+use crate::node::address::Label;
+use crate::node::{Address, Alias};
 use crate::prelude::{BoundedVec, NodeId, RepoId, Timestamp};
 use crate::service::filter::{Filter, FILTER_SIZE_L, FILTER_SIZE_M, FILTER_SIZE_S};
 use crate::service::message::{
-    Announcement, Info, InventoryAnnouncement, Message, NodeAnnouncement, Ping, RefsAnnouncement,
-    Subscribe, ZeroBytes,
+    Announcement, AnnouncementMessage, ConnectTo, DisconnectCode, HeartbeatAnnouncement, Info,
+    InventoryAnnouncement, InventoryDiff, InventoryHash, InventoryPage, Labels, Message,
+    NodeAnnouncement, Ping, RefsAnnouncement, Rendezvous, Sequence, Subscribe, ZeroBytes,
+    ADDRESS_LIMIT,
 };
 use crate::wire::MessageType;
 use crate::worker::fetch::FetchResult;
@@ -38,6 +41,8 @@ impl Arbitrary for FetchResult {
             namespaces: HashSet::arbitrary(g),
             clone: bool::arbitrary(g),
             doc: DocAt::arbitrary(g),
+            blobs: vec![],
+            summary: Default::default(),
         }
     }
 }
@@ -53,6 +58,15 @@ impl Arbitrary for Message {
                 MessageType::Subscribe,
                 MessageType::Ping,
                 MessageType::Pong,
+                MessageType::Rendezvous,
+                MessageType::ConnectTo,
+                MessageType::Disconnect,
+                MessageType::HeartbeatAnnouncement,
+                MessageType::SequencedAnnouncement,
+                MessageType::LabeledAnnouncement,
+                MessageType::RelayedAnnouncement,
+                MessageType::InventoryDiff,
+                MessageType::InventoryPage,
             ])
             .unwrap();
 
@@ -65,6 +79,9 @@ impl Arbitrary for Message {
                 }
                 .into(),
                 signature: crypto::Signature::from(<[u8; 64]>::arbitrary(g)),
+                sequence: None,
+                labels: None,
+                hops: 0,
             }
             .into(),
             MessageType::RefsAnnouncement => Announcement {
@@ -76,6 +93,39 @@ impl Arbitrary for Message {
                 }
                 .into(),
                 signature: crypto::Signature::from(<[u8; 64]>::arbitrary(g)),
+                sequence: None,
+                labels: None,
+                hops: 0,
+            }
+            .into(),
+            MessageType::HeartbeatAnnouncement => Announcement {
+                node: NodeId::arbitrary(g),
+                message: HeartbeatAnnouncement {
+                    uptime: u64::arbitrary(g),
+                    storage_free: u64::arbitrary(g),
+                    max_repo_size: u64::arbitrary(g),
+                    timestamp: Timestamp::arbitrary(g),
+                }
+                .into(),
+                signature: crypto::Signature::from(<[u8; 64]>::arbitrary(g)),
+                sequence: None,
+                labels: None,
+                hops: 0,
+            }
+            .into(),
+            MessageType::InventoryPage => Announcement {
+                node: NodeId::arbitrary(g),
+                message: InventoryPage {
+                    inventory: BoundedVec::arbitrary(g),
+                    page: u16::arbitrary(g),
+                    pages: u16::arbitrary(g),
+                    timestamp: Timestamp::arbitrary(g),
+                }
+                .into(),
+                signature: crypto::Signature::from(<[u8; 64]>::arbitrary(g)),
+                sequence: None,
+                labels: None,
+                hops: 0,
             }
             .into(),
             MessageType::NodeAnnouncement => {
@@ -94,13 +144,85 @@ impl Arbitrary for Message {
                     node: NodeId::arbitrary(g),
                     signature,
                     message,
+                    sequence: None,
+                    labels: None,
+                    hops: 0,
                 }
                 .into()
             }
+            MessageType::SequencedAnnouncement => loop {
+                if let Self::Announcement(ann) = Self::arbitrary(g) {
+                    break Announcement {
+                        sequence: Some(Sequence {
+                            seq: u64::arbitrary(g) % Sequence::MAX,
+                            signature: crypto::Signature::from(<[u8; 64]>::arbitrary(g)),
+                        }),
+                        ..ann
+                    }
+                    .into();
+                }
+            },
+            MessageType::LabeledAnnouncement => loop {
+                if let Self::Announcement(
+                    ann @ Announcement {
+                        message: AnnouncementMessage::Node(_),
+                        ..
+                    },
+                ) = Self::arbitrary(g)
+                {
+                    let labels = (0..usize::arbitrary(g) % (ADDRESS_LIMIT + 1))
+                        .map(|_| {
+                            *g.choose(&[Label::Manual, Label::Upnp, Label::Observed])
+                                .unwrap()
+                        })
+                        .collect::<Vec<_>>();
+
+                    break Announcement {
+                        labels: Some(Labels {
+                            labels: BoundedVec::truncate(labels),
+                            signature: crypto::Signature::from(<[u8; 64]>::arbitrary(g)),
+                        }),
+                        ..ann
+                    }
+                    .into();
+                }
+            },
+            MessageType::InventoryDiff => Self::InventoryDiff(InventoryDiff {
+                node: NodeId::arbitrary(g),
+                base: InventoryHash::from(<[u8; 32]>::arbitrary(g)),
+                removed: BoundedVec::arbitrary(g),
+                added: BoundedVec::arbitrary(g),
+                timestamp: Timestamp::arbitrary(g),
+                signature: crypto::Signature::from(<[u8; 64]>::arbitrary(g)),
+                sequence: bool::arbitrary(g).then(|| Sequence {
+                    seq: u64::arbitrary(g) % Sequence::MAX,
+                    signature: crypto::Signature::from(<[u8; 64]>::arbitrary(g)),
+                }),
+                hops: 0,
+            }),
+            MessageType::RelayedAnnouncement => loop {
+                let hops = u8::arbitrary(g).max(1);
+
+                match Self::arbitrary(g) {
+                    Self::Announcement(ann) => break Announcement { hops, ..ann }.into(),
+                    Self::InventoryDiff(diff) => {
+                        break Self::InventoryDiff(InventoryDiff { hops, ..diff })
+                    }
+                    _ => continue,
+                }
+            },
             MessageType::Info => {
-                let message = Info::RefsAlreadySynced {
-                    rid: RepoId::arbitrary(g),
-                    at: oid(),
+                let message = match u8::arbitrary(g) % 3 {
+                    0 => Info::RefsAlreadySynced {
+                        rid: RepoId::arbitrary(g),
+                        at: oid(),
+                    },
+                    1 => Info::ObservedAddress {
+                        addr: Address::arbitrary(g),
+                    },
+                    _ => Info::MissingInventory {
+                        node: NodeId::arbitrary(g),
+                    },
                 };
                 Self::Info(message)
             }
@@ -117,6 +239,26 @@ impl Arbitrary for Message {
             MessageType::Pong => Self::Pong {
                 zeroes: ZeroBytes::new(u16::arbitrary(g).min(Ping::MAX_PONG_ZEROES)),
             },
+            MessageType::Rendezvous => Self::Rendezvous(Rendezvous {
+                node: NodeId::arbitrary(g),
+            }),
+            MessageType::ConnectTo => Self::ConnectTo(ConnectTo {
+                node: NodeId::arbitrary(g),
+                addr: Address::arbitrary(g),
+            }),
+            // Compressed messages are decoded as the message they carry, and are thus never
+            // generated.
+            MessageType::Compressed => unreachable!(),
+            MessageType::Disconnect => Self::Disconnect {
+                reason: *g
+                    .choose(&[
+                        DisconnectCode::Other,
+                        DisconnectCode::Shutdown,
+                        DisconnectCode::Banned,
+                        DisconnectCode::TooManyConnections,
+                    ])
+                    .unwrap(),
+            },
         }
     }
 }
@@ -8,6 +8,7 @@ use std::{
 };
 
 use crossbeam_channel as chan;
+use nonempty::NonEmpty;
 
 use radicle::cob::cache::COBS_DB_FILE;
 use radicle::cob::issue;
@@ -26,7 +27,9 @@ use radicle::node::{ConnectOptions, Handle as _};
 use radicle::profile;
 use radicle::profile::{Home, Profile};
 use radicle::rad;
-use radicle::storage::{ReadStorage as _, RemoteRepository as _, SignRepository as _};
+use radicle::storage::{
+    ReadRepository as _, ReadStorage as _, RemoteRepository as _, SignRepository as _,
+};
 use radicle::test::fixtures;
 use radicle::Storage;
 use radicle::{cli, node};
@@ -36,7 +39,7 @@ use radicle::{git, web};
 use crate::node::NodeId;
 use crate::service::Event;
 use crate::storage::git::transport;
-use crate::{runtime, runtime::Handle, service, Runtime};
+use crate::{runtime, runtime::Handle, service, worker, Runtime};
 
 pub use service::Config;
 
@@ -184,6 +187,10 @@ pub struct NodeHandle<G: Signer + cyphernet::Ecdh + 'static> {
     pub signer: G,
     pub home: Home,
     pub addr: net::SocketAddr,
+    pub git_daemon: Option<net::SocketAddr>,
+    pub git_http: Option<net::SocketAddr>,
+    pub config: Config,
+    pub hooks: worker::hooks::Callbacks,
     pub thread: ManuallyDrop<thread::JoinHandle<Result<(), runtime::Error>>>,
     pub handle: ManuallyDrop<Handle>,
 }
@@ -202,6 +209,36 @@ impl<G: Signer + cyphernet::Ecdh + 'static> Drop for NodeHandle<G> {
     }
 }
 
+impl<G: cyphernet::Ecdh<Pk = NodeId> + Signer + Clone> NodeHandle<G> {
+    /// Restart the node, keeping its home and storage. The restarted node listens on a new
+    /// port, so peers must re-connect to it.
+    pub fn restart(self) -> NodeHandle<G> {
+        log::debug!(target: "test", "Node {} restarting..", self.id);
+
+        let id = self.id;
+        let home = self.home.clone();
+        let signer = self.signer.clone();
+        let storage = self.storage.clone();
+        let config = self.config.clone();
+
+        self.shutdown();
+
+        let policies = home.policies_mut().unwrap();
+        let db = service::Stores::from(home.database_mut().unwrap());
+
+        Node {
+            id,
+            home,
+            signer,
+            storage,
+            config,
+            db,
+            policies,
+        }
+        .spawn()
+    }
+}
+
 impl<G: Signer + cyphernet::Ecdh> NodeHandle<G> {
     /// Connect this node to another node, and wait for the connection to be established both ways.
     pub fn connect(&mut self, remote: &NodeHandle<G>) -> &mut Self {
@@ -236,6 +273,65 @@ impl<G: Signer + cyphernet::Ecdh> NodeHandle<G> {
         self.handle.disconnect(remote.id).unwrap();
     }
 
+    /// Partition this node from another node. Both nodes block each other, so that neither
+    /// of them accepts a connection from the other until [`NodeHandle::heal`] is called.
+    pub fn partition(&mut self, remote: &mut NodeHandle<G>) {
+        log::debug!(target: "test", "Partitioning {} from {}..", self.id, remote.id);
+
+        let local_events = self.handle.events();
+        let remote_events = remote.handle.events();
+        let connected = self
+            .handle
+            .sessions()
+            .unwrap()
+            .iter()
+            .any(|s| s.nid == remote.id && s.is_connected());
+
+        self.block(&remote.id);
+        remote.block(&self.id);
+
+        if connected {
+            self.handle.disconnect(remote.id).unwrap();
+
+            local_events
+                .iter()
+                .find(|e| matches!(e, Event::PeerDisconnected { nid, .. } if nid == &remote.id))
+                .unwrap();
+            remote_events
+                .iter()
+                .find(|e| matches!(e, Event::PeerDisconnected { nid, .. } if nid == &self.id))
+                .unwrap();
+        }
+    }
+
+    /// Heal a partition created with [`NodeHandle::partition`], and re-connect the two nodes.
+    pub fn heal(&mut self, remote: &mut NodeHandle<G>) -> &mut Self {
+        log::debug!(target: "test", "Healing partition between {} and {}..", self.id, remote.id);
+
+        self.home
+            .policies_mut()
+            .unwrap()
+            .unfollow(&remote.id)
+            .unwrap();
+        remote
+            .home
+            .policies_mut()
+            .unwrap()
+            .unfollow(&self.id)
+            .unwrap();
+
+        self.connect(remote)
+    }
+
+    /// Block a node, via the policy database.
+    fn block(&self, nid: &NodeId) {
+        self.home
+            .policies_mut()
+            .unwrap()
+            .set_follow_policy(nid, node::policy::Policy::Block)
+            .unwrap();
+    }
+
     /// Shutdown node.
     pub fn shutdown(self) {
         drop(self)
@@ -442,6 +538,55 @@ impl<G: Signer + cyphernet::Ecdh> NodeHandle<G> {
         Ok(())
     }
 
+    /// Commit to the default branch of a repository in the `NodeHandle`'s storage, as if the
+    /// user had pushed to it. Signs the updated refs and returns the new commit.
+    pub fn commit(&self, rid: RepoId, message: &str) -> git::Oid {
+        let repo = self.storage.repository(rid).unwrap();
+        let (branch, head) = repo.head().unwrap();
+        let branch = branch.with_namespace((&self.id).into());
+        let parent = repo.backend.find_commit(*head).unwrap();
+        let sig = git::raw::Signature::now("radicle", "radicle@localhost").unwrap();
+        let oid = repo
+            .backend
+            .commit(
+                Some(branch.as_str()),
+                &sig,
+                &sig,
+                message,
+                &parent.tree().unwrap(),
+                &[&parent],
+            )
+            .unwrap();
+        repo.sign_refs(&self.signer).unwrap();
+
+        log::debug!(target: "test", "Node {} committed {oid} to {rid}", self.id);
+
+        oid.into()
+    }
+
+    /// Wait until this node has a remote's branch pointing to the given commit.
+    #[track_caller]
+    pub fn has_branch_at(&self, rid: &RepoId, nid: &NodeId, branch: &git::RefStr, oid: git::Oid) {
+        log::debug!(target: "test", "Waiting for {} to have {rid}/{nid}/{branch} at {oid}", self.id);
+        let events = self.handle.events();
+        let branch = git::refs::branch(branch);
+
+        loop {
+            if let Ok(repo) = self.storage.repository(*rid) {
+                if repo.reference_oid(nid, &branch).ok() == Some(oid) {
+                    log::debug!(target: "test", "Node {} has {rid}/{nid}/{branch} at {oid}", self.id);
+                    break;
+                }
+            }
+            events
+                .wait(
+                    |e| matches!(e, Event::RefsFetched { .. }).then_some(()),
+                    time::Duration::from_secs(6),
+                )
+                .unwrap();
+        }
+    }
+
     /// Create an [`issue::Issue`] in the `NodeHandle`'s storage.
     pub fn issue(&self, rid: RepoId, title: &str, desc: &str) -> cob::ObjectId {
         let repo = self.storage.repository(rid).unwrap();
@@ -489,23 +634,39 @@ impl Node<MockSigner> {
 }
 
 impl<G: cyphernet::Ecdh<Pk = NodeId> + Signer + Clone> Node<G> {
+    /// Get the identity of the node, to host it in a runtime. The node listens on a random
+    /// port.
+    pub fn identity(&self) -> runtime::Identity<G> {
+        runtime::Identity {
+            home: self.home.clone(),
+            config: self.config.clone(),
+            listen: vec![([0, 0, 0, 0], 0).into()],
+            signer: self.signer.clone(),
+            loader: None,
+        }
+    }
+
     /// Spawn a node in its own thread.
     pub fn spawn(self) -> NodeHandle<G> {
-        let listen = vec![([0, 0, 0, 0], 0).into()];
         let proxy = net::SocketAddr::new(net::Ipv4Addr::LOCALHOST.into(), 9050);
         let (_, signals) = chan::bounded(1);
-        let rt = Runtime::init(
-            self.home.clone(),
-            self.config,
-            listen,
-            proxy,
-            signals,
-            self.signer.clone(),
-        )
-        .unwrap();
-        let addr = *rt.local_addrs.first().unwrap();
+        let rt = Runtime::host(NonEmpty::new(self.identity()), proxy, signals).unwrap();
+        let addr = *rt.hosted.head.local_addrs.first().unwrap();
+        let git_daemon = rt
+            .hosted
+            .head
+            .daemon
+            .as_ref()
+            .map(|d| d.local_addr().unwrap());
+        let git_http = rt
+            .hosted
+            .head
+            .gateway
+            .as_ref()
+            .map(|g| g.local_addr().unwrap());
         let id = *self.signer.public_key();
-        let handle = ManuallyDrop::new(rt.handle.clone());
+        let hooks = rt.hosted.head.hooks.clone();
+        let handle = ManuallyDrop::new(rt.hosted.head.handle.clone());
         let thread = ManuallyDrop::new(runtime::thread::spawn(&id, "runtime", move || rt.run()));
 
         NodeHandle {
@@ -514,6 +675,10 @@ impl<G: cyphernet::Ecdh<Pk = NodeId> + Signer + Clone> Node<G> {
             signer: self.signer,
             home: self.home,
             addr,
+            git_daemon,
+            git_http,
+            config: self.config,
+            hooks,
             handle,
             thread,
         }
@@ -0,0 +1,184 @@
+//! Fluent assertions over the I/O emitted by a service under test.
+//!
+//! ```ignore
+//! alice.receive(bob.id(), bob.refs_announcement(rid));
+//! alice
+//!     .expect()
+//!     .fetch(rid, bob.id())
+//!     .relayed(eve.id(), expect::refs(rid))
+//!     .not_relayed(bob.id(), expect::refs(rid))
+//!     .done();
+//! ```
+#![allow(dead_code)]
+use std::fmt::Write as _;
+
+use crate::identity::RepoId;
+use crate::prelude::NodeId;
+use crate::service::io::Io;
+use crate::service::message::{
+    Announcement, AnnouncementMessage, Message, NodeAnnouncement, RefsAnnouncement,
+};
+
+/// Snapshot of a service outbox, on which expectations are checked.
+///
+/// Expectations that match something consume it, so that the same I/O can't satisfy two
+/// expectations, and [`Expect::done`] can optionally check that nothing unexpected was
+/// emitted.
+#[derive(Debug)]
+pub struct Expect {
+    io: Vec<Io>,
+}
+
+impl Expect {
+    /// Create a new expectation over the given I/O.
+    pub fn new(io: impl IntoIterator<Item = Io>) -> Self {
+        Self {
+            io: io.into_iter().collect(),
+        }
+    }
+
+    /// Expect a fetch of `rid` from `remote`.
+    #[track_caller]
+    pub fn fetch(mut self, rid: RepoId, remote: NodeId) -> Self {
+        let Some(ix) = self.io.iter().position(
+            |io| matches!(io, Io::Fetch { rid: r, remote: n, .. } if *r == rid && *n == remote),
+        ) else {
+            self.fail(format!("expected a fetch of {rid} from {remote}"));
+        };
+        self.io.remove(ix);
+        self
+    }
+
+    /// Expect no fetch of `rid`, from any remote.
+    #[track_caller]
+    pub fn no_fetch(self, rid: RepoId) -> Self {
+        if self
+            .io
+            .iter()
+            .any(|io| matches!(io, Io::Fetch { rid: r, .. } if *r == rid))
+        {
+            self.fail(format!("expected no fetch of {rid}"));
+        }
+        self
+    }
+
+    /// Expect a message matching `pred` to be written to `remote`.
+    #[track_caller]
+    pub fn message(mut self, remote: NodeId, pred: impl Fn(&Message) -> bool) -> Self {
+        for io in self.io.iter_mut() {
+            if let Io::Write(nid, msgs) = io {
+                if *nid != remote {
+                    continue;
+                }
+                if let Some(ix) = msgs.iter().position(&pred) {
+                    msgs.remove(ix);
+                    return self;
+                }
+            }
+        }
+        self.fail(format!("expected a matching message to {remote}"));
+    }
+
+    /// Expect no message matching `pred` to be written to `remote`.
+    #[track_caller]
+    pub fn no_message(self, remote: NodeId, pred: impl Fn(&Message) -> bool) -> Self {
+        if self.messages(remote).any(pred) {
+            self.fail(format!("expected no matching message to {remote}"));
+        }
+        self
+    }
+
+    /// Expect an announcement matching `pred` to be relayed to `remote`.
+    ///
+    /// This is equivalent to [`Expect::message`], and only exists for readability.
+    #[track_caller]
+    pub fn relayed(self, remote: NodeId, pred: impl Fn(&Message) -> bool) -> Self {
+        self.message(remote, pred)
+    }
+
+    /// Expect no announcement matching `pred` to be relayed to `remote`.
+    #[track_caller]
+    pub fn not_relayed(self, remote: NodeId, pred: impl Fn(&Message) -> bool) -> Self {
+        self.no_message(remote, pred)
+    }
+
+    /// Expect no fetches or messages other than the ones already matched.
+    /// Other I/O, eg. wakeups, is ignored.
+    #[track_caller]
+    pub fn done(self) {
+        if self.io.iter().any(|io| match io {
+            Io::Fetch { .. } => true,
+            Io::Write(_, msgs) => !msgs.is_empty(),
+            _ => false,
+        }) {
+            self.fail(String::from("expected no other fetches or messages"));
+        }
+    }
+
+    /// Messages left to be matched for the given remote.
+    fn messages(&self, remote: NodeId) -> impl Iterator<Item = &Message> {
+        self.io.iter().flat_map(move |io| match io {
+            Io::Write(nid, msgs) if *nid == remote => msgs.as_slice(),
+            _ => &[],
+        })
+    }
+
+    #[track_caller]
+    fn fail(&self, reason: String) -> ! {
+        let mut outbox = String::new();
+        for io in &self.io {
+            writeln!(outbox, "  {io:?}").ok();
+        }
+        panic!("{reason}; unmatched outbox:\n{outbox}");
+    }
+}
+
+/// Match any message.
+pub fn any(_: &Message) -> bool {
+    true
+}
+
+/// Match a refs announcement for the given repository.
+pub fn refs(rid: RepoId) -> impl Fn(&Message) -> bool {
+    move |msg| {
+        matches!(
+            msg,
+            Message::Announcement(Announcement {
+                message: AnnouncementMessage::Refs(RefsAnnouncement { rid: r, .. }),
+                ..
+            }) if *r == rid
+        )
+    }
+}
+
+/// Match a refs announcement for the given repository, signed by the given node.
+pub fn refs_from(rid: RepoId, announcer: NodeId) -> impl Fn(&Message) -> bool {
+    move |msg| refs(rid)(msg) && signed_by(announcer)(msg)
+}
+
+/// Match any announcement signed by the given node.
+pub fn signed_by(announcer: NodeId) -> impl Fn(&Message) -> bool {
+    move |msg| matches!(msg, Message::Announcement(Announcement { node, .. }) if *node == announcer)
+}
+
+/// Match an inventory announcement, inventory diff, or inventory page.
+pub fn inventory(msg: &Message) -> bool {
+    matches!(
+        msg,
+        Message::Announcement(Announcement {
+            message: AnnouncementMessage::Inventory(_) | AnnouncementMessage::InventoryPage(_),
+            ..
+        }) | Message::InventoryDiff(_)
+    )
+}
+
+/// Match a node announcement.
+pub fn node(msg: &Message) -> bool {
+    matches!(
+        msg,
+        Message::Announcement(Announcement {
+            message: AnnouncementMessage::Node(NodeAnnouncement { .. }),
+            ..
+        })
+    )
+}
@@ -0,0 +1,236 @@
+//! Golden wire messages.
+//!
+//! Every [`Message`] variant and announcement type has a fixture: a message built from
+//! fixed keys, identifiers and timestamps, and its encoding, stored as hex under
+//! [`FIXTURES_DIR`]. Tests check that the encodings don't change, so that any change to
+//! the wire format is deliberate. When it is, fixtures are regenerated with:
+//!
+//!   cargo run -p radicle-node --features test --bin wire-fixtures
+//!
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::{fs, io};
+
+use radicle::crypto::test::signer::MockSigner;
+use radicle::crypto::Signer as _;
+use radicle::git;
+use radicle::node::address::Label;
+use radicle::node::{Address, Alias, Features};
+use radicle::storage::refs::RefsAt;
+
+use crate::bounded::BoundedVec;
+use crate::identity::RepoId;
+use crate::service::filter::Filter;
+use crate::service::message::*;
+use crate::service::DisconnectCode;
+use crate::wire;
+use crate::Timestamp;
+
+/// Directory holding the fixtures, relative to the crate root.
+pub const FIXTURES_DIR: &str = "src/wire/fixtures";
+
+/// Path to the fixtures directory.
+pub fn dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(FIXTURES_DIR)
+}
+
+/// Path to the file holding the fixture with the given name.
+pub fn path(name: &str) -> PathBuf {
+    dir().join(name).with_extension("hex")
+}
+
+/// Read the golden encoding of the fixture with the given name.
+pub fn read(name: &str) -> io::Result<Vec<u8>> {
+    let hex = fs::read_to_string(path(name))?;
+
+    decode(hex.trim()).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("fixture `{name}` is not valid hex"),
+        )
+    })
+}
+
+/// Write the encoding of every fixture, overwriting existing ones.
+pub fn write() -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+
+    for (name, msg) in messages() {
+        let path = path(name);
+
+        fs::create_dir_all(dir())?;
+        fs::write(&path, encode(&wire::serialize(&msg)) + "\n")?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// The fixture messages, by name. Names are stable: a fixture is only removed if the
+/// message it encodes is no longer supported.
+pub fn messages() -> Vec<(&'static str, Message)> {
+    let signer = MockSigner::from_seed([0xaa; 32]);
+    let node = *MockSigner::from_seed([0xbb; 32]).public_key();
+    let repo = |n: u8| RepoId::from(git::Oid::from_str(&format!("{n:040x}")).unwrap());
+    let (rid, other) = (repo(1), repo(2));
+    let oid = git::Oid::from_str("f2de534b5e81d7c6e2dcaf58c3dd91573c0a0354").unwrap();
+    let addr = Address::from_str("192.168.1.1:8776").unwrap();
+    let timestamp = Timestamp::from(1_700_000_000_000);
+    let refs = |n: usize| {
+        BoundedVec::collect_from(
+            &mut std::iter::repeat(RefsAt {
+                remote: node,
+                at: oid,
+            })
+            .take(n),
+        )
+    };
+
+    let node_ann = AnnouncementMessage::from(NodeAnnouncement {
+        features: Features::SEED,
+        timestamp,
+        alias: Alias::new("alice"),
+        addresses: BoundedVec::collect_from(&mut [addr.clone()].into_iter()),
+        nonce: 42,
+    })
+    .signed(&signer);
+    let inventory_ann = AnnouncementMessage::from(InventoryAnnouncement {
+        inventory: BoundedVec::collect_from(&mut [rid, other].into_iter()),
+        timestamp,
+    })
+    .signed(&signer);
+    let refs_ann = AnnouncementMessage::from(RefsAnnouncement {
+        rid,
+        refs: refs(1),
+        timestamp,
+    })
+    .signed(&signer);
+    let heartbeat_ann = AnnouncementMessage::Heartbeat(HeartbeatAnnouncement {
+        uptime: 3600,
+        storage_free: 1024 * 1024 * 1024,
+        max_repo_size: 0,
+        timestamp,
+    })
+    .signed(&signer);
+    let page_ann = AnnouncementMessage::InventoryPage(InventoryPage {
+        inventory: BoundedVec::collect_from(&mut [rid].into_iter()),
+        page: 0,
+        pages: 2,
+        timestamp,
+    })
+    .signed(&signer);
+    let diff = {
+        let base = [rid, other, repo(3), repo(4)];
+        let ann = AnnouncementMessage::from(InventoryAnnouncement {
+            inventory: BoundedVec::collect_from(&mut [rid, other, repo(3), repo(5)].into_iter()),
+            timestamp,
+        })
+        .signed(&signer);
+
+        InventoryDiff::new(&ann, &base).unwrap()
+    };
+    // Large enough to be compressed.
+    let compressed = Message::Announcement(
+        AnnouncementMessage::from(RefsAnnouncement {
+            rid,
+            refs: refs(REF_REMOTE_LIMIT),
+            timestamp,
+        })
+        .signed(&signer),
+    )
+    .compressed();
+
+    vec![
+        (
+            "subscribe",
+            Message::subscribe(Filter::new([rid]), timestamp, timestamp + 60_000),
+        ),
+        ("node-announcement", node_ann.clone().into()),
+        (
+            "node-announcement-sequenced",
+            node_ann.clone().sequenced(7, &signer).into(),
+        ),
+        (
+            "node-announcement-labeled",
+            node_ann
+                .labeled(
+                    BoundedVec::collect_from(&mut [Label::Observed].into_iter()),
+                    &signer,
+                )
+                .sequenced(7, &signer)
+                .into(),
+        ),
+        ("inventory-announcement", inventory_ann.into()),
+        ("refs-announcement", refs_ann.clone().into()),
+        (
+            "refs-announcement-sequenced",
+            refs_ann.clone().sequenced(7, &signer).into(),
+        ),
+        (
+            "refs-announcement-relayed",
+            refs_ann.sequenced(7, &signer).relayed().relayed().into(),
+        ),
+        ("heartbeat-announcement", heartbeat_ann.into()),
+        ("inventory-page", page_ann.into()),
+        ("inventory-diff", Message::InventoryDiff(diff.clone())),
+        (
+            "inventory-diff-relayed",
+            Message::InventoryDiff(InventoryDiff { hops: 3, ..diff }),
+        ),
+        (
+            "info-refs-already-synced",
+            Message::Info(Info::RefsAlreadySynced { rid, at: oid }),
+        ),
+        (
+            "info-observed-address",
+            Message::Info(Info::ObservedAddress { addr: addr.clone() }),
+        ),
+        (
+            "info-missing-inventory",
+            Message::Info(Info::MissingInventory { node }),
+        ),
+        (
+            "ping",
+            Message::Ping(Ping {
+                ponglen: 4,
+                zeroes: ZeroBytes::new(8),
+            }),
+        ),
+        (
+            "pong",
+            Message::Pong {
+                zeroes: ZeroBytes::new(4),
+            },
+        ),
+        ("rendezvous", Message::Rendezvous(Rendezvous { node })),
+        ("connect-to", Message::ConnectTo(ConnectTo { node, addr })),
+        (
+            "disconnect",
+            Message::Disconnect {
+                reason: DisconnectCode::Shutdown,
+            },
+        ),
+        ("compressed", compressed),
+    ]
+}
+
+/// Encode bytes as lowercase hex.
+fn encode(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, b| {
+        let _ = write!(hex, "{b:02x}");
+        hex
+    })
+}
+
+/// Decode hex, ignoring line breaks.
+fn decode(hex: &str) -> Option<Vec<u8>> {
+    let digits = hex
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace())
+        .map(|c| c.to_digit(16).map(|d| d as u8))
+        .collect::<Option<Vec<_>>>()?;
+    if digits.len() % 2 != 0 {
+        return None;
+    }
+    Some(digits.chunks(2).map(|d| d[0] << 4 | d[1]).collect())
+}
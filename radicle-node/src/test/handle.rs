@@ -1,13 +1,22 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use std::time;
 
 use radicle::git;
+use radicle::node::repos::Repo;
+use radicle::node::stats::Stats;
+use radicle::node::uploads::Upload;
+use radicle::storage::git::{bundle, verify};
 use radicle::storage::refs::RefsAt;
 
 use crate::identity::RepoId;
-use crate::node::{Alias, Config, ConnectOptions, ConnectResult, Event, FetchResult, Seeds};
+use crate::node::config::ConfigDiff;
+use crate::node::{
+    address, Address, Alias, Config, ConnectOptions, ConnectResult, Event, Features, FetchResult,
+    FilterStatus, HealthState, InitOptions, NodeHealth, Seeds, SelfCheck, Task, TaskId,
+};
 use crate::runtime::HandleError;
 use crate::service::policy;
 use crate::service::NodeId;
@@ -17,6 +26,7 @@ pub struct Handle {
     pub updates: Arc<Mutex<Vec<RepoId>>>,
     pub seeding: Arc<Mutex<HashSet<RepoId>>>,
     pub following: Arc<Mutex<HashSet<NodeId>>>,
+    pub fetchers: Arc<Mutex<BTreeMap<(RepoId, NodeId), policy::Policy>>>,
 }
 
 impl radicle::node::Handle for Handle {
@@ -35,10 +45,18 @@ impl radicle::node::Handle for Handle {
         Ok(vec![])
     }
 
+    fn announced_addrs(&self) -> Result<Vec<(Address, address::Label)>, Self::Error> {
+        Ok(vec![])
+    }
+
     fn config(&self) -> Result<Config, Self::Error> {
         Ok(Config::new(Alias::new("acme")))
     }
 
+    fn reload(&mut self) -> Result<ConfigDiff, Self::Error> {
+        Ok(ConfigDiff::default())
+    }
+
     fn connect(
         &mut self,
         _node: NodeId,
@@ -92,6 +110,115 @@ impl radicle::node::Handle for Handle {
         Ok(self.following.lock().unwrap().remove(&id))
     }
 
+    fn reset_backoff(&mut self, _id: RepoId) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+
+    fn set_fetcher_policy(
+        &mut self,
+        id: RepoId,
+        nid: NodeId,
+        policy: Option<policy::Policy>,
+    ) -> Result<bool, Self::Error> {
+        let mut fetchers = self.fetchers.lock().unwrap();
+
+        Ok(match policy {
+            Some(policy) => fetchers.insert((id, nid), policy) != Some(policy),
+            None => fetchers.remove(&(id, nid)).is_some(),
+        })
+    }
+
+    fn fetchers(&self, id: RepoId) -> Result<Vec<policy::FetcherPolicy>, Self::Error> {
+        Ok(self
+            .fetchers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|((rid, _), _)| *rid == id)
+            .map(|((rid, nid), policy)| policy::FetcherPolicy {
+                rid: *rid,
+                nid: *nid,
+                policy: *policy,
+            })
+            .collect())
+    }
+
+    fn uploads(&self, _id: Option<RepoId>) -> Result<Vec<Upload>, Self::Error> {
+        Ok(vec![])
+    }
+
+    fn repos(&self) -> Result<Vec<Repo>, Self::Error> {
+        Ok(vec![])
+    }
+
+    fn stats(&self, _id: Option<RepoId>) -> Result<Vec<Stats>, Self::Error> {
+        Ok(vec![])
+    }
+
+    fn verify(
+        &self,
+        _id: Option<RepoId>,
+    ) -> Result<Box<dyn Iterator<Item = Result<verify::Report, Self::Error>>>, Self::Error> {
+        Ok(Box::new(std::iter::empty()))
+    }
+
+    fn repair(&mut self, id: RepoId) -> Result<verify::Repair, Self::Error> {
+        Ok(verify::Repair::new(verify::Report {
+            rid: id,
+            failures: vec![],
+        }))
+    }
+
+    fn export(&self, _id: RepoId, _path: PathBuf) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn import(&mut self, _path: PathBuf) -> Result<bundle::Imported, Self::Error> {
+        Err(HandleError::Io(std::io::ErrorKind::Unsupported.into()))
+    }
+
+    fn init(&mut self, _path: PathBuf, _opts: InitOptions) -> Result<RepoId, Self::Error> {
+        Err(HandleError::Io(std::io::ErrorKind::Unsupported.into()))
+    }
+
+    fn tasks(&self) -> Result<Vec<Task>, Self::Error> {
+        Ok(vec![])
+    }
+
+    fn abort(&mut self, _id: TaskId) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
+
+    fn peers(&self, _features: Features) -> Result<Vec<NodeId>, Self::Error> {
+        Ok(vec![])
+    }
+
+    fn filter(&self) -> Result<FilterStatus, Self::Error> {
+        Ok(FilterStatus {
+            size: 0,
+            seeded: 0,
+            false_positive_rate: 0.,
+        })
+    }
+
+    fn health(&self) -> Result<NodeHealth, Self::Error> {
+        Ok(NodeHealth::new(
+            HealthState::Ok,
+            HealthState::Ok,
+            HealthState::Ok,
+            HealthState::Ok,
+            HealthState::Ok,
+        ))
+    }
+
+    fn self_check(
+        &self,
+        _addr: Option<Address>,
+        _rid: Option<RepoId>,
+    ) -> Result<SelfCheck, Self::Error> {
+        Err(HandleError::Io(std::io::ErrorKind::Unsupported.into()))
+    }
+
     fn announce_refs(&mut self, id: RepoId) -> Result<RefsAt, Self::Error> {
         self.updates.lock().unwrap().push(id);
 
@@ -22,6 +22,7 @@ use crate::node;
 use crate::prelude::*;
 use crate::runtime::Emitter;
 use crate::service;
+use crate::service::clock::LocalClock;
 use crate::service::io::Io;
 use crate::service::message::*;
 use crate::service::policy::{Policy, Scope};
@@ -29,6 +30,7 @@ use crate::service::*;
 use crate::storage::git::transport::remote;
 use crate::storage::Inventory;
 use crate::storage::{RemoteId, WriteStorage};
+use crate::test::expect::Expect;
 use crate::test::storage::MockStorage;
 use crate::test::{arbitrary, fixtures, simulator};
 use crate::wire::MessageType;
@@ -178,7 +180,7 @@ where
         let emitter: Emitter<Event> = Default::default();
         let service = Service::new(
             config.config,
-            config.local_time,
+            LocalClock::new(config.local_time),
             config.db,
             storage,
             policies,
@@ -209,7 +211,7 @@ where
             );
 
             self.initialized = true;
-            self.service.initialize(LocalTime::now()).unwrap();
+            self.service.initialize(self.local_time()).unwrap();
             return true;
         }
         false
@@ -222,7 +224,7 @@ where
             "{}: Restarting: id = {}, address = {}",
             self.name, self.id, self.ip
         );
-        self.service.initialize(LocalTime::now()).unwrap();
+        self.service.initialize(self.local_time()).unwrap();
     }
 
     pub fn address(&self) -> Address {
@@ -249,7 +251,7 @@ where
     }
 
     pub fn timestamp(&self) -> Timestamp {
-        (*self.clock()).into()
+        self.clock().network_time().into()
     }
 
     pub fn inventory(&self) -> Inventory {
@@ -475,6 +477,11 @@ where
         iter::from_fn(|| self.service.outbox().next())
     }
 
+    /// Drain the peer's I/O outbox, and return it for checking expectations against.
+    pub fn expect(&mut self) -> Expect {
+        Expect::new(self.outbox())
+    }
+
     /// Get a draining iterator over the peer's I/O outbox, which only returns fetches.
     pub fn fetches(&mut self) -> impl Iterator<Item = (RepoId, NodeId)> + '_ {
         iter::from_fn(|| self.service.outbox().next()).filter_map(|io| {
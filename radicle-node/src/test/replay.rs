@@ -0,0 +1,94 @@
+//! Replay of recorded protocol sessions.
+//!
+//! Recordings made by a running node (see [`crate::wire::record`]) can be fed to a test peer,
+//! to turn sessions observed in the wild into regression tests.
+use log::*;
+
+use radicle::node::ConnectOptions;
+use radicle::storage::WriteStorage;
+
+use crate::crypto::Signer;
+use crate::service::{Command, DisconnectReason};
+use crate::test::peer::Peer;
+use crate::wire::record::{Entry, Event};
+use crate::{Link, LocalDuration, LocalTime};
+
+/// How the time elapsed between recorded events is replayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    /// The peer's clock advances as much as it did when the events were recorded.
+    Original,
+    /// The peer's clock advances by the recorded time divided by the given factor.
+    Accelerated(u32),
+}
+
+impl Timing {
+    /// Scale the time elapsed between two recorded events.
+    fn scale(&self, elapsed: LocalDuration) -> LocalDuration {
+        match self {
+            Self::Original => elapsed,
+            Self::Accelerated(factor) => {
+                LocalDuration::from_millis(elapsed.as_millis() / (*factor).max(1) as u128)
+            }
+        }
+    }
+}
+
+/// Replay recorded session events into a peer.
+///
+/// Sessions are established and ended as they were in the recording, and gossip messages are
+/// delivered to the peer's service. Between events, the peer's clock is advanced according to
+/// the given [`Timing`], so that timers fire as they did when the events were recorded.
+pub fn replay<S, G>(peer: &mut Peer<S, G>, entries: impl IntoIterator<Item = Entry>, timing: Timing)
+where
+    S: WriteStorage + 'static,
+    G: Signer + 'static,
+{
+    let mut last: Option<LocalTime> = None;
+
+    peer.initialize();
+
+    for Entry {
+        time,
+        remote,
+        event,
+    } in entries
+    {
+        if let Some(last) = last {
+            if time > last {
+                peer.elapse(timing.scale(time - last));
+            }
+        }
+        last = Some(time);
+
+        debug!(target: "test", "{}: Replaying {event:?} from {remote}", peer.name);
+
+        match event {
+            Event::Connected {
+                addr,
+                link: Link::Inbound,
+            } => {
+                peer.service.connected(remote, addr, Link::Inbound);
+            }
+            Event::Connected {
+                addr,
+                link: Link::Outbound,
+            } => {
+                peer.service.command(Command::Connect(
+                    remote,
+                    addr.clone(),
+                    ConnectOptions::default(),
+                ));
+                peer.service.attempted(remote, addr.clone());
+                peer.service.connected(remote, addr, Link::Outbound);
+            }
+            Event::Message(msg) => {
+                peer.receive(remote, msg);
+            }
+            Event::Disconnected { link } => {
+                peer.service
+                    .disconnected(remote, link, &DisconnectReason::connection());
+            }
+        }
+    }
+}
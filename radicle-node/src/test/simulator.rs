@@ -504,7 +504,7 @@ impl<S: WriteStorage + 'static, G: Signer> Simulation<S, G> {
                     },
                 );
             }
-            Io::Connect(remote, addr) => {
+            Io::Connect(remote, addr) | Io::Punch(remote, addr) => {
                 assert!(remote != node, "self-connections are not allowed");
 
                 self.inbox.insert(
@@ -627,6 +627,8 @@ impl<S: WriteStorage + 'static, G: Signer> Simulation<S, G> {
                     );
                 }
             }
+            // Simulated peers don't offload signing.
+            Io::FetchBlobs { .. } | Io::QueryRefs { .. } | Io::Sign(_) | Io::Snapshot(_) => {}
             Io::Fetch { rid, remote, .. } => {
                 log::info!(
                     target: "sim",
@@ -661,6 +663,8 @@ impl<S: WriteStorage + 'static, G: Signer> Simulation<S, G> {
                                     namespaces: HashSet::new(),
                                     clone: true,
                                     doc: arbitrary::gen(1),
+                                    blobs: vec![],
+                                    summary: Default::default(),
                                 })),
                             ),
                         },
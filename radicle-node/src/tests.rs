@@ -3,6 +3,7 @@ mod e2e;
 use std::collections::BTreeSet;
 use std::default::*;
 use std::io;
+use std::net;
 use std::sync::Arc;
 use std::time;
 
@@ -24,6 +25,7 @@ use crate::node::config::*;
 use crate::prelude::*;
 use crate::prelude::{LocalDuration, Timestamp};
 use crate::service::filter::Filter;
+use crate::service::gossip::Store as _;
 use crate::service::io::Io;
 use crate::service::message::*;
 use crate::service::ServiceState as _;
@@ -34,14 +36,18 @@ use crate::storage::refs::SIGREFS_BRANCH;
 use crate::storage::ReadStorage;
 use crate::test::arbitrary;
 use crate::test::assert_matches;
+use crate::test::expect;
 use crate::test::fixtures;
 #[allow(unused)]
 use crate::test::logger;
 use crate::test::peer;
 use crate::test::peer::Peer;
+use crate::test::replay::{replay, Timing};
 use crate::test::simulator;
 use crate::test::simulator::{Peer as _, Simulation};
 use crate::test::storage::MockStorage;
+use crate::wire::record;
+use crate::wire::record::{Recorder, Recording};
 use crate::wire::Decode;
 use crate::wire::Encode;
 use crate::worker;
@@ -120,6 +126,34 @@ fn test_disconnecting_unresponsive_peer() {
         .expect("disconnect an unresponsive bob");
 }
 
+#[test]
+fn test_disconnecting_unnegotiated_peer() {
+    let mut alice = Peer::new("alice", [8, 8, 8, 8]);
+    let bob = Peer::new("bob", [9, 9, 9, 9]);
+    let eve = Peer::new("eve", [7, 7, 7, 7]);
+    let timeout = alice.config().limits.connection.negotiation_timeout;
+
+    alice.connect_from(&bob);
+    alice.connect_from(&eve);
+    alice.receive(eve.id(), Message::Subscribe(Subscribe::all()));
+    alice.elapse(timeout + IDLE_INTERVAL);
+
+    let disconnected = alice
+        .outbox()
+        .filter_map(|m| match m {
+            Io::Disconnect(nid, reason) => Some((nid, reason)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    assert_matches!(
+        disconnected.as_slice(),
+        [(nid, DisconnectReason::Session(session::Error::NegotiationTimeout))]
+        if *nid == bob.id(),
+        "bob never subscribed and is disconnected, while eve isn't"
+    );
+}
+
 #[test]
 fn test_redundant_connect() {
     let mut alice = Peer::new("alice", [8, 8, 8, 8]);
@@ -248,6 +282,355 @@ fn test_persistent_peer_connect() {
         .unwrap();
 }
 
+#[test]
+fn test_rendezvous() {
+    use std::collections::HashSet;
+
+    let mut alice = Peer::config(
+        "alice",
+        [7, 7, 7, 7],
+        MockStorage::empty(),
+        peer::Config {
+            config: Config {
+                bridge: Some(BridgeLimits {
+                    peer: RateLimit {
+                        fill_rate: 1024.,
+                        capacity: 4096,
+                    },
+                    total: RateLimit {
+                        fill_rate: 4096.,
+                        capacity: 16384,
+                    },
+                }),
+                ..Config::new(node::Alias::new("alice"))
+            },
+            ..peer::Config::default()
+        },
+    );
+    let eve = Peer::new("eve", [9, 9, 9, 9]);
+    let mut bob = Peer::config(
+        "bob",
+        [8, 8, 8, 8],
+        MockStorage::empty(),
+        peer::Config {
+            config: Config {
+                connect: HashSet::from_iter([(eve.id(), eve.address()).into()]),
+                ..Config::new(node::Alias::new("bob"))
+            },
+            ..peer::Config::default()
+        },
+    );
+
+    alice.connect_from(&bob);
+    alice.connect_from(&eve);
+    alice.receive(bob.id(), Message::Rendezvous(Rendezvous { node: eve.id() }));
+
+    assert!(alice.messages(bob.id()).any(|m| matches!(
+        m,
+        Message::ConnectTo(ConnectTo { node, addr }) if node == eve.id() && addr == eve.address()
+    )));
+    assert!(alice.messages(eve.id()).any(|m| matches!(
+        m,
+        Message::ConnectTo(ConnectTo { node, addr }) if node == bob.id() && addr == bob.address()
+    )));
+
+    // Rendezvous requests are rate limited per peer.
+    for _ in 0..MAX_RENDEZVOUS_BURST {
+        alice.receive(bob.id(), Message::Rendezvous(Rendezvous { node: eve.id() }));
+    }
+    assert_eq!(
+        alice
+            .messages(bob.id())
+            .filter(|m| matches!(m, Message::ConnectTo(_)))
+            .count(),
+        MAX_RENDEZVOUS_BURST - 1
+    );
+
+    // Introductions from peers that aren't bridges are ignored.
+    let carol = Peer::new("carol", [10, 10, 10, 10]);
+    bob.connect_to(&carol);
+    bob.receive(
+        carol.id(),
+        Message::ConnectTo(ConnectTo {
+            node: eve.id(),
+            addr: eve.address(),
+        }),
+    );
+    assert!(!bob
+        .outbox()
+        .any(|o| matches!(o, Io::Punch(nid, _) if nid == eve.id())));
+
+    // Alice advertises herself as a bridge.
+    let timestamp = alice.timestamp();
+    bob.database_mut()
+        .addresses_mut()
+        .insert(
+            &alice.id(),
+            node::Features::SEED | node::Features::BRIDGE,
+            node::Alias::new("alice"),
+            0,
+            timestamp,
+            None,
+        )
+        .unwrap();
+    bob.connect_to(&alice);
+
+    // Introductions we didn't ask for are ignored.
+    bob.receive(
+        alice.id(),
+        Message::ConnectTo(ConnectTo {
+            node: eve.id(),
+            addr: eve.address(),
+        }),
+    );
+    assert!(!bob
+        .outbox()
+        .any(|o| matches!(o, Io::Punch(nid, _) if nid == eve.id())));
+
+    // Bob can't reach Eve, so he asks Alice to introduce them.
+    bob.disconnected(
+        eve.id(),
+        Link::Outbound,
+        &DisconnectReason::Dial(Arc::new(io::Error::from(io::ErrorKind::ConnectionRefused))),
+    );
+    assert!(bob.messages(alice.id()).any(|m| matches!(
+        m,
+        Message::Rendezvous(Rendezvous { node }) if node == eve.id()
+    )));
+    bob.receive(
+        alice.id(),
+        Message::ConnectTo(ConnectTo {
+            node: eve.id(),
+            addr: eve.address(),
+        }),
+    );
+    assert!(bob
+        .outbox()
+        .any(|o| matches!(o, Io::Punch(nid, addr) if nid == eve.id() && addr == eve.address())));
+
+    // Introductions are only followed once.
+    bob.disconnected(eve.id(), Link::Outbound, &DisconnectReason::connection());
+    bob.receive(
+        alice.id(),
+        Message::ConnectTo(ConnectTo {
+            node: eve.id(),
+            addr: eve.address(),
+        }),
+    );
+    assert!(!bob
+        .outbox()
+        .any(|o| matches!(o, Io::Punch(nid, _) if nid == eve.id())));
+}
+
+#[test]
+fn test_observed_address() {
+    let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+    let peers = [
+        Peer::new("bob", [8, 8, 8, 8]),
+        Peer::new("eve", [9, 9, 9, 9]),
+        Peer::new("carol", [10, 10, 10, 10]),
+    ];
+    let observed: node::Address = net::SocketAddr::from(([88, 12, 4, 1], 60141)).into();
+    let external: node::Address = net::SocketAddr::from(([88, 12, 4, 1], 8776)).into();
+
+    alice.listening(net::SocketAddr::from(([0, 0, 0, 0], 8776)));
+
+    for (i, peer) in peers.iter().enumerate() {
+        alice.connect_to(peer);
+        alice.receive(
+            peer.id(),
+            Message::Info(Info::ObservedAddress {
+                addr: observed.clone(),
+            }),
+        );
+        let announced = alice.messages(peer.id()).any(|m| {
+            matches!(
+                m,
+                Message::Announcement(Announcement {
+                    message: AnnouncementMessage::Node(NodeAnnouncement { addresses, .. }),
+                    ..
+                }) if addresses.contains(&external)
+            )
+        });
+        // The address is only announced once enough peers agree on it.
+        assert_eq!(announced, i == observed::CONFIRMATION_THRESHOLD - 1);
+    }
+}
+
+#[test]
+fn test_node_announcement_labels() {
+    use node::address::Label;
+
+    let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+    let mut bob = Peer::new("bob", [8, 8, 8, 8]);
+    let eve = Peer::new("eve", [9, 9, 9, 9]);
+    let carol = Peer::new("carol", [10, 10, 10, 10]);
+    let observed: node::Address = net::SocketAddr::from(([88, 12, 4, 1], 60141)).into();
+    let external: node::Address = net::SocketAddr::from(([88, 12, 4, 1], 8776)).into();
+    let node_announcement = |msgs: Vec<Message>| {
+        msgs.into_iter().find_map(|m| match m {
+            Message::Announcement(
+                ann @ Announcement {
+                    message: AnnouncementMessage::Node(_),
+                    ..
+                },
+            ) => Some(ann),
+            _ => None,
+        })
+    };
+
+    alice.listening(net::SocketAddr::from(([0, 0, 0, 0], 8776)));
+    for peer in [&bob, &eve, &carol] {
+        alice.connect_to(peer);
+    }
+    // Bob tells Alice that he understands address labels.
+    alice.receive(
+        bob.id(),
+        bob.announcement(
+            NodeAnnouncement {
+                features: node::Features::SEED | node::Features::ADDRESS_LABELS,
+                timestamp: bob.timestamp() + 1,
+                alias: node::Alias::new("bob"),
+                addresses: BoundedVec::new(),
+                nonce: 0,
+            }
+            .solve(0)
+            .unwrap(),
+        ),
+    );
+    alice.outbox().for_each(drop);
+
+    for peer in [&bob, &eve, &carol] {
+        alice.receive(
+            peer.id(),
+            Message::Info(Info::ObservedAddress {
+                addr: observed.clone(),
+            }),
+        );
+    }
+
+    // Bob is told which of Alice's addresses was configured, and which was observed.
+    let ann = node_announcement(alice.messages(bob.id()).collect()).unwrap();
+    let addresses = ann.addresses().unwrap();
+    assert!(ann.verify());
+    assert_eq!(addresses.len(), 2);
+    assert_eq!(addresses[0].1, Some(Label::Manual));
+    assert_eq!(addresses[1], (external.clone(), Some(Label::Observed)));
+
+    // Eve doesn't understand labels, and gets the announcement without them.
+    let stripped = node_announcement(alice.messages(eve.id()).collect()).unwrap();
+    assert_eq!(stripped, ann.clone().unlabeled());
+
+    let (sender, receiver) = chan::bounded(1);
+    alice.command(Command::AnnouncedAddrs(sender));
+    assert_eq!(
+        receiver.recv().unwrap(),
+        addresses
+            .iter()
+            .map(|(addr, label)| (addr.clone(), label.unwrap()))
+            .collect::<Vec<_>>()
+    );
+
+    // Bob keeps the labels in his address book.
+    bob.connect_to(&alice);
+    bob.elapse(service::MIN_ADDRESS_CHANGE_DELTA);
+    bob.receive(alice.id(), Message::Announcement(ann));
+
+    let node = bob
+        .database()
+        .addresses()
+        .get(&alice.id())
+        .unwrap()
+        .unwrap();
+    let label = node
+        .addrs
+        .iter()
+        .find(|ka| ka.addr == external)
+        .unwrap()
+        .label;
+    assert_eq!(label, Some(Label::Observed));
+}
+
+#[test]
+fn test_heartbeat() {
+    let mut alice = Peer::config(
+        "alice",
+        [7, 7, 7, 7],
+        MockStorage::empty(),
+        peer::Config {
+            config: Config {
+                heartbeat: Some(Heartbeat {
+                    interval: LocalDuration::from_mins(30),
+                    max_repo_size: Some(1024),
+                }),
+                ..Config::new(node::Alias::new("alice"))
+            },
+            ..peer::Config::default()
+        },
+    );
+    let mut bob = Peer::new("bob", [8, 8, 8, 8]);
+    let eve = Peer::new("eve", [9, 9, 9, 9]);
+    let carol = Peer::new("carol", [10, 10, 10, 10]);
+    let heartbeat = |peer: &mut Peer<MockStorage, MockSigner>, remote: NodeId| {
+        peer.messages(remote).find_map(|m| match m {
+            Message::Announcement(Announcement {
+                message: AnnouncementMessage::Heartbeat(heartbeat),
+                ..
+            }) => Some(heartbeat),
+            _ => None,
+        })
+    };
+    let supports_heartbeats = |peer: &mut Peer<MockStorage, MockSigner>, remote: &Peer<_, _>| {
+        // Peer clocks start when peers are created, so the remote's node announcement,
+        // which doesn't advertise heartbeats, may be newer than our own time.
+        let timestamp = Timestamp::from((*peer.timestamp()).max(*remote.timestamp()));
+        peer.database_mut()
+            .addresses_mut()
+            .insert(
+                &remote.id(),
+                node::Features::SEED | node::Features::HEARTBEAT | node::Features::SEQUENCE,
+                node::Alias::new(remote.name),
+                0,
+                timestamp,
+                None,
+            )
+            .unwrap();
+    };
+
+    // Alice only sends heartbeats to peers that understand them.
+    supports_heartbeats(&mut alice, &bob);
+    alice.connect_to(&bob);
+    alice.connect_to(&eve);
+    alice.elapse(LocalDuration::from_mins(30));
+
+    let msg = heartbeat(&mut alice, bob.id()).expect("Alice sends a heartbeat to Bob");
+    assert_eq!(msg.max_repo_size, 1024);
+    assert!(heartbeat(&mut alice, eve.id()).is_none());
+
+    // Bob relays heartbeats to peers that understand them, but not too often.
+    supports_heartbeats(&mut bob, &alice);
+    supports_heartbeats(&mut bob, &carol);
+    bob.connect_to(&alice);
+    bob.connect_to(&carol);
+
+    let timestamp = bob.timestamp();
+    let ann = |timestamp: Timestamp| {
+        Message::Announcement(
+            AnnouncementMessage::from(HeartbeatAnnouncement {
+                timestamp,
+                ..msg.clone()
+            })
+            .signed(alice.signer()),
+        )
+    };
+    bob.receive(alice.id(), ann(timestamp));
+    assert!(bob.relayed(carol.id()).any(|m| m == ann(timestamp)));
+
+    let early = timestamp + LocalDuration::from_mins(1).as_millis() as u64;
+    bob.receive(alice.id(), ann(early));
+    assert_eq!(bob.relayed(carol.id()).count(), 0);
+}
+
 #[test]
 fn test_inventory_sync() {
     let tmp = tempfile::tempdir().unwrap();
@@ -281,6 +664,75 @@ fn test_inventory_sync() {
     }
 }
 
+#[test]
+fn test_replay_recorded_session() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("session.rec");
+    let bob_signer = MockSigner::default();
+    let bob_storage = fixtures::storage(tmp.path().join("bob"), &bob_signer).unwrap();
+    let bob = Peer::config("bob", [8, 8, 8, 8], bob_storage, peer::Config::default());
+    let projs = bob.storage().inventory().unwrap();
+    let start = bob.local_time();
+
+    // Record a session with Bob, as a node would when receiving his messages.
+    let mut recorder = Recorder::open(&path).unwrap();
+    for (elapsed, event) in [
+        (
+            LocalDuration::from_secs(0),
+            record::Event::Connected {
+                addr: bob.address(),
+                link: Link::Inbound,
+            },
+        ),
+        (
+            LocalDuration::from_secs(1),
+            record::Event::Message(bob.node_announcement()),
+        ),
+        (
+            LocalDuration::from_secs(2),
+            record::Event::Message(Message::inventory(
+                InventoryAnnouncement {
+                    inventory: projs.clone().try_into().unwrap(),
+                    timestamp: bob.timestamp(),
+                },
+                bob.signer(),
+            )),
+        ),
+        (
+            LocalDuration::from_mins(10),
+            record::Event::Disconnected {
+                link: Link::Inbound,
+            },
+        ),
+    ] {
+        recorder.record(start + elapsed, bob.id(), event).unwrap();
+    }
+    drop(recorder);
+
+    for (timing, elapsed) in [
+        (Timing::Original, LocalDuration::from_mins(10)),
+        (Timing::Accelerated(10), LocalDuration::from_mins(1)),
+    ] {
+        let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+        let entries = Recording::open(&path)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        alice.initialize();
+
+        let started = alice.local_time();
+        replay(&mut alice, entries, timing);
+
+        assert_eq!(alice.local_time() - started, elapsed);
+        assert!(alice.sessions().get(&bob.id()).is_none());
+
+        for proj in &projs {
+            let seeds = alice.database().routing().get(proj).unwrap();
+            assert!(seeds.contains(&bob.node_id()), "{timing:?}");
+        }
+    }
+}
+
 #[test]
 fn test_inventory_pruning() {
     struct Test {
@@ -410,6 +862,29 @@ fn test_seeding() {
     assert!(!alice.policies().is_seeding(&proj_id).unwrap());
 }
 
+#[test]
+fn test_seeding_filter_resize() {
+    let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+    let rids = arbitrary::vec::<RepoId>(1000);
+    let status = |alice: &mut Peer<MockStorage, MockSigner>| {
+        let (sender, receiver) = chan::bounded(1);
+        alice.command(Command::Filter(sender));
+        receiver.recv().unwrap()
+    };
+    assert_eq!(status(&mut alice).size, filter::FILTER_SIZE_S);
+    assert_eq!(status(&mut alice).seeded, 0);
+
+    for rid in rids {
+        let (sender, _receiver) = chan::bounded(1);
+        alice.command(Command::Seed(rid, policy::Scope::default(), sender));
+    }
+    // The filter grew with the number of seeded repositories.
+    let status = status(&mut alice);
+    assert_eq!(status.size, filter::FILTER_SIZE_M);
+    assert_eq!(status.seeded, 1000);
+    assert!(status.false_positive_rate < filter::FILTER_FP_RATE);
+}
+
 #[test]
 fn test_inventory_relay_bad_timestamp() {
     let mut alice = Peer::new("alice", [7, 7, 7, 7]);
@@ -436,52 +911,396 @@ fn test_inventory_relay_bad_timestamp() {
 }
 
 #[test]
-fn test_announcement_rebroadcast() {
+fn test_announcement_expired() {
     let mut alice = Peer::new("alice", [7, 7, 7, 7]);
     let bob = Peer::new("bob", [8, 8, 8, 8]);
     let eve = Peer::new("eve", [9, 9, 9, 9]);
+    let max_age = alice.config().limits.gossip_max_age;
+    let announcement = |timestamp: Timestamp| {
+        Message::node(
+            NodeAnnouncement {
+                features: node::Features::SEED,
+                timestamp,
+                alias: node::Alias::new("eve"),
+                addresses: BoundedVec::new(),
+                nonce: 0,
+            }
+            .solve(0)
+            .unwrap(),
+            eve.signer(),
+        )
+    };
 
     alice.connect_to(&bob);
-    alice.connect_from(&eve);
-    alice.outbox().for_each(drop);
+    alice.receive(
+        bob.id(),
+        announcement((alice.local_time() - max_age - LocalDuration::from_secs(1)).into()),
+    );
+    assert!(
+        alice
+            .database()
+            .addresses()
+            .get(&eve.id())
+            .unwrap()
+            .is_none(),
+        "Expired announcements are ignored"
+    );
+    assert!(alice.outbox().all(|o| !matches!(o, Io::Disconnect(..))));
 
-    log::debug!(target: "test", "Receiving gossips..");
+    alice.receive(
+        bob.id(),
+        announcement((alice.local_time() - max_age + LocalDuration::from_secs(1)).into()),
+    );
+    assert!(alice
+        .database()
+        .addresses()
+        .get(&eve.id())
+        .unwrap()
+        .is_some());
+}
 
-    let received = test::gossip::messages(6, alice.local_time(), MAX_TIME_DELTA);
-    for msg in received.iter().cloned() {
-        alice.receive(bob.id(), msg);
-    }
+#[test]
+fn test_node_announcement_addresses() {
+    let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+    let bob = Peer::new("bob", [8, 8, 8, 8]);
+    let carol = Peer::new("carol", [9, 9, 9, 9]);
+    let eve = Peer::new("eve", [10, 10, 10, 10]);
+    let announcement = |timestamp: Timestamp, addrs: &[&str]| {
+        Message::node(
+            NodeAnnouncement {
+                features: node::Features::SEED,
+                timestamp,
+                alias: node::Alias::new("eve"),
+                addresses: addrs
+                    .iter()
+                    .map(|a| a.parse::<Address>().unwrap())
+                    .collect::<Vec<_>>()
+                    .try_into()
+                    .unwrap(),
+                nonce: 0,
+            }
+            .solve(0)
+            .unwrap(),
+            eve.signer(),
+        )
+    };
+    let addresses = |alice: &Peer<_, _>| {
+        alice
+            .database()
+            .addresses()
+            .get(&eve.id())
+            .unwrap()
+            .map(|n| n.addrs.into_iter().map(|a| a.addr.to_string()).collect())
+            .unwrap_or_else(Vec::new)
+    };
+
+    alice.connect_to(&bob);
+    alice.connect_to(&carol);
 
+    // Addresses that aren't routable are dropped, and the announcement isn't relayed.
     alice.receive(
-        eve.id(),
-        Message::Subscribe(Subscribe {
-            filter: Filter::default(),
-            since: Timestamp::MIN,
-            until: Timestamp::MAX,
-        }),
+        bob.id(),
+        announcement(alice.timestamp(), &["10.0.0.1:8776", "224.0.0.1:8776"]),
     );
+    assert!(addresses(&alice).is_empty());
+    assert_eq!(alice.relayed(carol.id()).count(), 0);
 
-    let relayed = alice.messages(eve.id()).collect::<BTreeSet<_>>();
-    let received = received
-        .into_iter()
-        .chain(Some(bob.node_announcement()))
-        .collect::<BTreeSet<_>>();
-
-    assert_eq!(relayed.len(), received.len());
-    assert_eq!(relayed, received);
+    alice.elapse(LocalDuration::from_secs(1));
+    alice.receive(
+        bob.id(),
+        announcement(alice.timestamp(), &["10.0.0.1:8776", "1.1.1.1:8776"]),
+    );
+    assert_eq!(addresses(&alice), vec!["1.1.1.1:8776"]);
+    assert_eq!(alice.relayed(carol.id()).count(), 1);
+
+    // New addresses are ignored if the node's addresses changed too recently.
+    alice.elapse(LocalDuration::from_secs(1));
+    alice.receive(bob.id(), announcement(alice.timestamp(), &["1.0.0.1:8776"]));
+    assert_eq!(addresses(&alice), vec!["1.1.1.1:8776"]);
+    assert_eq!(alice.relayed(carol.id()).count(), 0);
+
+    alice.elapse(service::MIN_ADDRESS_CHANGE_DELTA);
+    alice.receive(bob.id(), announcement(alice.timestamp(), &["1.0.0.1:8776"]));
+    assert_eq!(addresses(&alice).len(), 2);
+    assert_eq!(alice.relayed(carol.id()).count(), 1);
 }
 
 #[test]
-fn test_announcement_rebroadcast_duplicates() {
-    let mut carol = Peer::new("carol", [4, 4, 4, 4]);
+fn test_gossip_from_the_future_pruned() {
     let mut alice = Peer::new("alice", [7, 7, 7, 7]);
     let bob = Peer::new("bob", [8, 8, 8, 8]);
-    let eve = Peer::new("eve", [9, 9, 9, 9]);
-    let rids = arbitrary::set::<RepoId>(3..=3);
+    let timestamp = alice.timestamp() + LocalDuration::from_mins(30).as_millis() as u64;
 
-    carol.init();
     alice.connect_to(&bob);
-    alice.receive(bob.id, carol.node_announcement());
+    alice.receive(
+        bob.id(),
+        Message::inventory(
+            InventoryAnnouncement {
+                inventory: BoundedVec::new(),
+                timestamp,
+            },
+            bob.signer(),
+        ),
+    );
+    assert_eq!(alice.database().gossip().last().unwrap(), Some(timestamp));
+
+    // Alice's clock was ahead, and is now corrected when she restarts. Bob's clock is
+    // a few milliseconds ahead of Alice's, so his announcement must stay within bounds.
+    let now = alice.local_time() - LocalDuration::from_mins(45);
+    alice.clock_mut().set(now);
+    alice.restart();
+
+    // Only Bob's node announcement is left.
+    let last = alice.database().gossip().last().unwrap();
+    assert_matches!(last, Some(t) if *t < *timestamp);
+}
+
+#[test]
+fn test_announcement_replay() {
+    let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+    let bob = Peer::new("bob", [8, 8, 8, 8]);
+    let eve = Peer::new("eve", [9, 9, 9, 9]);
+    let now = alice.local_time();
+    let announcement = |time: LocalTime, seq: u64, alias: &str| -> Announcement {
+        AnnouncementMessage::from(
+            NodeAnnouncement {
+                features: node::Features::SEED | node::Features::SEQUENCE,
+                timestamp: time.into(),
+                alias: node::Alias::new(alias),
+                addresses: BoundedVec::new(),
+                nonce: 0,
+            }
+            .solve(0)
+            .unwrap(),
+        )
+        .signed(eve.signer())
+        .sequenced(seq, eve.signer())
+    };
+    let stored = |alice: &Peer<MockStorage, MockSigner>| {
+        alice
+            .database()
+            .gossip()
+            .filtered(&Filter::default(), Timestamp::MIN, Timestamp::MAX)
+            .unwrap()
+            .map(Result::unwrap)
+            .map(|(ann, _)| ann)
+            .find(|ann| ann.node == eve.id())
+    };
+
+    alice.connect_to(&bob);
+    alice.receive(bob.id(), announcement(now, 2, "eve").into());
+    assert_eq!(stored(&alice).and_then(|a| a.seq()), Some(2));
+    assert_eq!(
+        alice.database().gossip().sequence(&eve.id()).unwrap(),
+        Some(2)
+    );
+
+    // A more recent announcement with a lower sequence number is rejected.
+    let later = now + LocalDuration::from_secs(1);
+    alice.receive(bob.id(), announcement(later, 1, "eve").into());
+    assert_eq!(stored(&alice).and_then(|a| a.seq()), Some(2));
+
+    // Eve's clock went backwards, but her sequence number didn't.
+    let earlier = now - LocalDuration::from_mins(1);
+    alice.receive(bob.id(), announcement(earlier, 3, "eve").into());
+    assert_eq!(stored(&alice).and_then(|a| a.seq()), Some(3));
+    assert_eq!(
+        alice.database().gossip().sequence(&eve.id()).unwrap(),
+        Some(3)
+    );
+    assert!(alice.outbox().all(|o| !matches!(o, Io::Disconnect(..))));
+
+    // Announcements with the same sequence number and timestamp are ordered by signature,
+    // whatever order they're received in.
+    let a = announcement(earlier, 4, "eve");
+    let b = announcement(earlier, 4, "evil");
+    let (loser, winner) = if a.signature.as_ref() < b.signature.as_ref() {
+        (a, b)
+    } else {
+        (b, a)
+    };
+    alice.receive(bob.id(), loser.clone().into());
+    alice.receive(bob.id(), winner.clone().into());
+    alice.receive(bob.id(), loser.into());
+    assert_eq!(stored(&alice), Some(winner));
+}
+
+#[test]
+fn test_announcement_sequence_stripped() {
+    let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+    let bob = Peer::new("bob", [8, 8, 8, 8]);
+    let sequenced = |msgs: &[Message]| {
+        msgs.iter()
+            .filter_map(|m| match m {
+                Message::Announcement(ann) => Some(ann.sequence.is_some()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // Alice doesn't know whether Bob supports sequenced announcements.
+    alice.connect_to(&bob);
+    alice.outbox().for_each(drop);
+    alice.command(Command::AnnounceInventory);
+
+    let msgs = alice.messages(bob.id()).collect::<Vec<_>>();
+    assert_eq!(sequenced(&msgs), vec![false]);
+
+    // Alice's announcements are still numbered.
+    let seq = alice.database().gossip().sequence(&alice.id()).unwrap();
+    assert_matches!(seq, Some(n) if n > 0);
+
+    // Bob tells Alice that he does.
+    alice.receive(
+        bob.id(),
+        bob.announcement(
+            NodeAnnouncement {
+                features: node::Features::SEED | node::Features::SEQUENCE,
+                timestamp: bob.timestamp() + 1,
+                alias: node::Alias::new("bob"),
+                addresses: BoundedVec::new(),
+                nonce: 0,
+            }
+            .solve(0)
+            .unwrap(),
+        ),
+    );
+    alice.command(Command::AnnounceInventory);
+
+    let msgs = alice.messages(bob.id()).collect::<Vec<_>>();
+    assert_eq!(sequenced(&msgs), vec![true]);
+}
+
+#[test]
+fn test_announcement_signing_offloaded() {
+    let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+    let bob = Peer::new("bob", [8, 8, 8, 8]);
+
+    alice.offload_signing();
+    alice.connect_to(&bob);
+    alice.outbox().for_each(drop);
+    alice.command(Command::AnnounceInventory);
+
+    // Nothing is sent until the announcement is signed.
+    let request = alice
+        .outbox()
+        .find_map(|io| match io {
+            Io::Sign(request) => Some(request),
+            _ => None,
+        })
+        .expect("Alice asks for her inventory to be signed");
+    assert_matches!(request.message, AnnouncementMessage::Inventory(_));
+    assert_eq!(alice.messages(bob.id()).count(), 0);
+
+    let route = request.route.clone();
+    let ann = request.sign(alice.signer());
+    alice.signed(ann, route);
+
+    assert_matches!(
+        alice.messages(bob.id()).next(),
+        Some(Message::Announcement(Announcement {
+            message: AnnouncementMessage::Inventory(_),
+            ..
+        }))
+    );
+}
+
+#[test]
+fn test_snapshot_restore() {
+    let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+    let bob = Peer::new("bob", [8, 8, 8, 8]);
+    let rid = arbitrary::gen::<RepoId>(1);
+
+    alice.seed(&rid, policy::Scope::All).unwrap();
+    alice.connect_to(&bob);
+    alice.command(Command::Fetch(
+        rid,
+        bob.id(),
+        DEFAULT_TIMEOUT,
+        chan::bounded(1).0,
+    ));
+    assert_matches!(alice.outbox().last(), Some(Io::Fetch { .. }));
+
+    let snapshot = alice.snapshot();
+    assert_eq!(
+        snapshot.peers.iter().map(|p| p.nid).collect::<Vec<_>>(),
+        vec![bob.id()]
+    );
+    assert_eq!(
+        snapshot
+            .fetches
+            .iter()
+            .map(|f| (f.rid, f.from))
+            .collect::<Vec<_>>(),
+        vec![(rid, bob.id())]
+    );
+
+    // After a restart, Alice reconnects to Bob first, and resumes the fetch once he's back.
+    let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+    alice.seed(&rid, policy::Scope::All).unwrap();
+    alice.restore(snapshot);
+    alice.initialize();
+
+    assert_matches!(
+        alice.outbox().next(),
+        Some(Io::Connect(nid, _)) if nid == bob.id()
+    );
+    alice.attempted(bob.id(), bob.address());
+    alice.connected(bob.id(), bob.address(), Link::Outbound);
+
+    assert_matches!(
+        alice.outbox().find(|o| matches!(o, Io::Fetch { .. })),
+        Some(Io::Fetch { rid: r, remote, .. }) if r == rid && remote == bob.id()
+    );
+}
+
+#[test]
+fn test_announcement_rebroadcast() {
+    let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+    let bob = Peer::new("bob", [8, 8, 8, 8]);
+    let eve = Peer::new("eve", [9, 9, 9, 9]);
+
+    alice.connect_to(&bob);
+    alice.connect_from(&eve);
+    alice.outbox().for_each(drop);
+
+    log::debug!(target: "test", "Receiving gossips..");
+
+    let received = test::gossip::messages(6, alice.local_time(), MAX_TIME_DELTA);
+    for msg in received.iter().cloned() {
+        alice.receive(bob.id(), msg);
+    }
+
+    alice.receive(
+        eve.id(),
+        Message::Subscribe(Subscribe {
+            filter: Filter::default(),
+            since: Timestamp::MIN,
+            until: Timestamp::MAX,
+        }),
+    );
+
+    let relayed = alice.messages(eve.id()).collect::<BTreeSet<_>>();
+    let received = received
+        .into_iter()
+        .chain(Some(bob.node_announcement()))
+        .collect::<BTreeSet<_>>();
+
+    assert_eq!(relayed.len(), received.len());
+    assert_eq!(relayed, received);
+}
+
+#[test]
+fn test_announcement_rebroadcast_duplicates() {
+    let mut carol = Peer::new("carol", [4, 4, 4, 4]);
+    let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+    let bob = Peer::new("bob", [8, 8, 8, 8]);
+    let eve = Peer::new("eve", [9, 9, 9, 9]);
+    let rids = arbitrary::set::<RepoId>(3..=3);
+
+    carol.init();
+    alice.connect_to(&bob);
+    alice.receive(bob.id, carol.node_announcement());
 
     // These are not expected to be relayed.
     let stale = {
@@ -644,6 +1463,156 @@ fn test_announcement_relay() {
     );
 }
 
+#[test]
+fn test_announcement_relay_loop_suppression() {
+    let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+    let bob = Peer::new("bob", [8, 8, 8, 8]);
+    let eve = Peer::new("eve", [9, 9, 9, 9]);
+    let carol = Peer::new("carol", [12, 12, 12, 12]);
+
+    alice.connect_to(&bob);
+    alice.connect_to(&eve);
+    alice.receive(bob.id(), Message::Subscribe(Subscribe::all()));
+    alice.receive(eve.id(), Message::Subscribe(Subscribe::all()));
+    alice.outbox().for_each(drop);
+
+    // Announcements are relayed to subscribers, but not back to the relayer or announcer.
+    alice.receive(bob.id(), bob.inventory_announcement());
+    alice
+        .expect()
+        .relayed(eve.id(), expect::inventory)
+        .not_relayed(bob.id(), expect::any)
+        .done();
+
+    alice.receive(bob.id(), carol.node_announcement());
+    alice
+        .expect()
+        .relayed(eve.id(), expect::node)
+        .not_relayed(bob.id(), expect::any)
+        .done();
+
+    // When Eve relays the same announcement back to us, it isn't relayed again.
+    alice.receive(eve.id(), carol.node_announcement());
+    alice.expect().done();
+
+    // Nor is it sent back to Bob when he subscribes again, since we got it from him.
+    alice.receive(bob.id(), Message::Subscribe(Subscribe::all()));
+    alice
+        .expect()
+        .no_message(bob.id(), expect::signed_by(carol.id()))
+        .no_message(bob.id(), expect::signed_by(bob.id()));
+
+    // But Eve, who didn't send it to us, gets it.
+    alice.receive(eve.id(), Message::Subscribe(Subscribe::all()));
+    alice
+        .expect()
+        .message(eve.id(), expect::signed_by(carol.id()))
+        .message(eve.id(), expect::signed_by(bob.id()));
+}
+
+#[test]
+fn test_announcement_relay_hops() {
+    let mut alice = Peer::config(
+        "alice",
+        [7, 7, 7, 7],
+        MockStorage::empty(),
+        peer::Config {
+            config: Config {
+                limits: Limits {
+                    relay_hops: 2,
+                    ..Limits::default()
+                },
+                ..Config::new(node::Alias::new("alice"))
+            },
+            ..peer::Config::default()
+        },
+    );
+    let bob = Peer::new("bob", [8, 8, 8, 8]);
+    let eve = Peer::new("eve", [9, 9, 9, 9]);
+    let carol = Peer::new("carol", [10, 10, 10, 10]);
+    let mut dave = Peer::new("dave", [11, 11, 11, 11]);
+    let relayed = |msg: Message, hops: u8| match msg {
+        Message::Announcement(ann) => Message::Announcement(Announcement { hops, ..ann }),
+        _ => panic!("not an announcement"),
+    };
+    let hops = |n: u8| move |m: &Message| matches!(m, Message::Announcement(a) if a.hops == n);
+
+    for peer in [&bob, &eve, &carol] {
+        alice.connect_to(peer);
+    }
+    // Eve tells Alice that she understands hop counts.
+    alice.receive(
+        eve.id(),
+        eve.announcement(
+            NodeAnnouncement {
+                features: node::Features::SEED | node::Features::RELAY_HOPS,
+                timestamp: eve.timestamp() + 1,
+                alias: node::Alias::new("eve"),
+                addresses: BoundedVec::new(),
+                nonce: 0,
+            }
+            .solve(0)
+            .unwrap(),
+        ),
+    );
+    alice.receive(bob.id(), dave.node_announcement());
+    alice.outbox().for_each(drop);
+
+    // Announcements are relayed with one more hop, except to peers that don't understand
+    // hop counts.
+    alice.receive(bob.id(), relayed(dave.inventory_announcement(), 1));
+    alice
+        .expect()
+        .relayed(eve.id(), hops(2))
+        .relayed(carol.id(), hops(0))
+        .done();
+
+    // Announcements that were relayed over the maximum number of hops are stored, but not
+    // relayed further.
+    dave.elapse(LocalDuration::from_mins(1));
+    alice.receive(bob.id(), relayed(dave.inventory_announcement(), 2));
+    alice.expect().done();
+
+    let ann = alice
+        .database()
+        .gossip()
+        .inventory(&dave.id())
+        .unwrap()
+        .unwrap();
+    assert_eq!(ann.hops, 2);
+
+    // Nor are they sent to subscribers, unlike announcements that can travel further.
+    alice.receive(eve.id(), Message::Subscribe(Subscribe::all()));
+    alice
+        .expect()
+        .message(eve.id(), |m| {
+            expect::node(m) && expect::signed_by(dave.id())(m) && hops(1)(m)
+        })
+        .no_message(eve.id(), |m| {
+            expect::inventory(m) && expect::signed_by(dave.id())(m)
+        });
+}
+
+#[test]
+fn test_announcement_relay_subscription_range() {
+    let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+    let bob = Peer::new("bob", [8, 8, 8, 8]);
+    let eve = Peer::new("eve", [9, 9, 9, 9]);
+
+    alice.connect_to(&bob);
+    alice.connect_to(&eve);
+    alice.receive(
+        eve.id(),
+        Message::subscribe(Filter::default(), Timestamp::MIN, bob.timestamp()),
+    );
+    alice.outbox().for_each(drop);
+
+    // Eve's subscription ends before Bob's announcements, so they're not relayed to her.
+    alice.receive(bob.id(), bob.inventory_announcement());
+    alice.receive(bob.id(), bob.node_announcement());
+    alice.expect().not_relayed(eve.id(), expect::any).done();
+}
+
 #[test]
 fn test_refs_announcement_relay() {
     let tmp = tempfile::tempdir().unwrap();
@@ -718,6 +1687,124 @@ fn test_refs_announcement_relay() {
     );
 }
 
+#[test]
+fn test_refs_announcement_fetch_and_relay() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut alice = Peer::config(
+        "alice",
+        [7, 7, 7, 7],
+        Storage::open(tmp.path().join("alice"), fixtures::user()).unwrap(),
+        peer::Config::default(),
+    );
+    let eve = Peer::new("eve", [8, 8, 8, 8]);
+    let bob = {
+        let mut rng = fastrand::Rng::new();
+        let signer = MockSigner::new(&mut rng);
+        let storage = fixtures::storage(tmp.path().join("bob"), &signer).unwrap();
+
+        Peer::config(
+            "bob",
+            [9, 9, 9, 9],
+            storage,
+            peer::Config {
+                signer,
+                rng,
+                ..peer::Config::default()
+            },
+        )
+    };
+    let rid = *bob.storage().inventory().unwrap().first().unwrap();
+
+    alice.seed(&rid, policy::Scope::All).unwrap();
+    alice.connect_to(&bob);
+    alice.connect_to(&eve);
+    alice.receive(bob.id(), Message::Subscribe(Subscribe::all()));
+    alice.receive(eve.id(), Message::Subscribe(Subscribe::all()));
+    alice.outbox().for_each(drop);
+
+    alice.receive(bob.id(), bob.refs_announcement(rid));
+    alice
+        .expect()
+        .fetch(rid, bob.id())
+        .relayed(eve.id(), expect::refs_from(rid, bob.id()))
+        .not_relayed(bob.id(), expect::any)
+        .done();
+
+    // The same announcement is neither fetched nor relayed again.
+    alice.receive(bob.id(), bob.refs_announcement(rid));
+    alice
+        .expect()
+        .no_fetch(rid)
+        .not_relayed(eve.id(), expect::any)
+        .done();
+}
+
+#[test]
+fn test_refs_announcement_invalid_sigrefs() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut alice = {
+        let mut rng = fastrand::Rng::new();
+        let signer = MockSigner::new(&mut rng);
+        let storage = fixtures::storage(tmp.path().join("alice"), &signer).unwrap();
+
+        Peer::config(
+            "alice",
+            [7, 7, 7, 7],
+            storage,
+            peer::Config {
+                signer,
+                rng,
+                ..peer::Config::default()
+            },
+        )
+    };
+    let bob = Peer::new("bob", [8, 8, 8, 8]);
+    let eve = Peer::new("eve", [9, 9, 9, 9]);
+    let rid = *alice.storage().inventory().unwrap().first().unwrap();
+    let repo = alice.storage().repository(rid).unwrap();
+    let at = radicle::storage::refs::SignedRefsAt::load(alice.id(), &repo)
+        .unwrap()
+        .unwrap()
+        .at;
+    let announcement = |remote: NodeId| {
+        bob.announcement(RefsAnnouncement {
+            rid,
+            refs: vec![RefsAt { remote, at }].try_into().unwrap(),
+            timestamp: bob.timestamp(),
+        })
+    };
+    let penalty = |alice: &Peer<Storage, MockSigner>, nid: &NodeId| {
+        alice
+            .database()
+            .addresses()
+            .get(nid)
+            .unwrap()
+            .unwrap()
+            .penalty
+    };
+    alice.connect_to(&bob);
+    alice.connect_to(&eve);
+
+    // Alice's signed refs, announced by Bob.
+    alice.receive(eve.id(), announcement(alice.id()));
+    assert_eq!(penalty(&alice, &bob.id()), node::Penalty::default());
+    assert_eq!(penalty(&alice, &eve.id()), node::Penalty::default());
+
+    // Alice's signed refs, claimed to be Bob's, relayed by Eve.
+    alice.receive(eve.id(), announcement(bob.id()));
+    assert!(!alice.outbox().any(|o| matches!(o, Io::Disconnect(..))));
+    assert!(penalty(&alice, &bob.id()) > penalty(&alice, &eve.id()));
+    assert!(penalty(&alice, &eve.id()) > node::Penalty::default());
+
+    // The same announcement, sent by Bob himself.
+    alice.receive(bob.id(), announcement(bob.id()));
+    assert_matches!(
+        alice.outbox().find(|o| matches!(o, Io::Disconnect(..))),
+        Some(Io::Disconnect(nid, DisconnectReason::Session(session::Error::Misbehavior)))
+        if nid == bob.id()
+    );
+}
+
 /// Even if Alice is not tracking Bob, Alice will fetch Bob's refs for a repo she doesn't have.
 #[test]
 fn test_refs_announcement_fetch_trusted_no_inventory() {
@@ -749,14 +1836,320 @@ fn test_refs_announcement_fetch_trusted_no_inventory() {
     let bob_inv = bob.storage().inventory().unwrap();
     let rid = bob_inv.first().unwrap();
 
-    alice.seed(rid, policy::Scope::Followed).unwrap();
+    alice.seed(rid, policy::Scope::Followed).unwrap();
+    alice.connect_to(&bob);
+
+    // Alice receives Bob's refs.
+    alice.receive(bob.id(), bob.refs_announcement(*rid));
+
+    // Alice fetches Bob's refs as this is a new repo.
+    assert_matches!(alice.outbox().next(), Some(Io::Fetch { .. }));
+}
+
+/// Alice seeds the repositories Bob is active in, since she follows him, up to her budget.
+#[test]
+fn test_refs_announcement_auto_seed() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut alice = Peer::config(
+        "alice",
+        [7, 7, 7, 7],
+        Storage::open(tmp.path().join("alice"), fixtures::user()).unwrap(),
+        peer::Config {
+            config: Config {
+                auto_seed: Some(AutoSeed {
+                    max_repos: 1,
+                    max_size: u64::MAX,
+                }),
+                ..Config::test(node::Alias::new("alice"))
+            },
+            ..peer::Config::default()
+        },
+    );
+    let mut bob = {
+        let mut rng = fastrand::Rng::new();
+        let signer = MockSigner::new(&mut rng);
+        let storage = fixtures::storage(tmp.path().join("bob"), &signer).unwrap();
+
+        Peer::config(
+            "bob",
+            [9, 9, 9, 9],
+            storage,
+            peer::Config {
+                signer,
+                rng,
+                ..peer::Config::default()
+            },
+        )
+    };
+    let inventory = bob.storage().inventory().unwrap();
+    let mut inventory = inventory.iter();
+    let first = *inventory.next().unwrap();
+    let second = *inventory.next().unwrap();
+
+    alice.connect_to(&bob);
+    alice.receive(bob.id(), bob.refs_announcement(first));
+    assert!(!alice.policies().is_seeding(&first).unwrap());
+
+    // Once Alice follows Bob, she seeds and fetches the repository.
+    alice.policies_mut().follow(&bob.id(), None).unwrap();
+    bob.elapse(LocalDuration::from_mins(1));
+    alice.receive(bob.id(), bob.refs_announcement(first));
+    assert!(alice.policies().is_seeding(&first).unwrap());
+    assert!(alice
+        .outbox()
+        .any(|o| matches!(o, Io::Fetch { rid, .. } if rid == first)));
+
+    // Her budget is exhausted.
+    alice.receive(bob.id(), bob.refs_announcement(second));
+    assert!(!alice.policies().is_seeding(&second).unwrap());
+
+    // The repository isn't delegated to by any node Alice follows.
+    alice.fetched(
+        first,
+        bob.id(),
+        Err(worker::FetchError::from(
+            worker::fetch::error::Fetch::NotDelegated { rid: first },
+        )),
+    );
+    assert!(!alice.policies().is_seeding(&first).unwrap());
+
+    // Rejected repositories aren't seeded again, and no longer count towards the budget.
+    bob.elapse(LocalDuration::from_mins(1));
+    alice.receive(bob.id(), bob.refs_announcement(first));
+    assert!(!alice.policies().is_seeding(&first).unwrap());
+    alice.receive(bob.id(), bob.refs_announcement(second));
+    assert!(alice.policies().is_seeding(&second).unwrap());
+}
+
+#[test]
+fn test_refs_announcement_fetch_backoff() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut alice = Peer::config(
+        "alice",
+        [7, 7, 7, 7],
+        Storage::open(tmp.path().join("alice"), fixtures::user()).unwrap(),
+        peer::Config::default(),
+    );
+    let mut bob = {
+        let mut rng = fastrand::Rng::new();
+        let signer = MockSigner::new(&mut rng);
+        let storage = fixtures::storage(tmp.path().join("bob"), &signer).unwrap();
+
+        Peer::config(
+            "bob",
+            [9, 9, 9, 9],
+            storage,
+            peer::Config {
+                signer,
+                rng,
+                ..peer::Config::default()
+            },
+        )
+    };
+    let rid = *bob.storage().inventory().unwrap().first().unwrap();
+
+    alice.seed(&rid, policy::Scope::All).unwrap();
+    alice.connect_to(&bob);
+    alice.receive(bob.id(), bob.refs_announcement(rid));
+    assert_matches!(alice.outbox().next(), Some(Io::Fetch { .. }));
+
+    // The fetch fails, eg. because the repository doesn't pass validation.
+    alice.fetched(
+        rid,
+        bob.id(),
+        Err(worker::FetchError::CommandFailed { code: 1 }),
+    );
+    alice.elapse(scheduler::FETCH_DEBOUNCE);
+    alice.outbox().for_each(drop);
+
+    // Alice doesn't retry the fetch on the next announcement.
+    bob.elapse(LocalDuration::from_mins(1));
+    alice.receive(bob.id(), bob.refs_announcement(rid));
+    assert!(!alice.outbox().any(|o| matches!(o, Io::Fetch { .. })));
+
+    // Until the backoff is reset.
+    let (sender, receiver) = chan::bounded(1);
+    alice.command(Command::ResetBackoff(rid, sender));
+    assert!(receiver.recv().unwrap());
+
+    bob.elapse(LocalDuration::from_mins(1));
+    alice.receive(bob.id(), bob.refs_announcement(rid));
+    assert!(alice.outbox().any(|o| matches!(o, Io::Fetch { .. })));
+}
+
+#[test]
+fn test_fetch_failure_classification() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut alice = Peer::config(
+        "alice",
+        [7, 7, 7, 7],
+        Storage::open(tmp.path().join("alice"), fixtures::user()).unwrap(),
+        peer::Config::default(),
+    );
+    let mut bob = {
+        let mut rng = fastrand::Rng::new();
+        let signer = MockSigner::new(&mut rng);
+        let storage = fixtures::storage(tmp.path().join("bob"), &signer).unwrap();
+
+        Peer::config(
+            "bob",
+            [9, 9, 9, 9],
+            storage,
+            peer::Config {
+                signer,
+                rng,
+                ..peer::Config::default()
+            },
+        )
+    };
+    let rid = *bob.storage().inventory().unwrap().first().unwrap();
+    let penalty = |alice: &Peer<Storage, MockSigner>, nid: &NodeId| {
+        alice
+            .database()
+            .addresses()
+            .get(nid)
+            .unwrap()
+            .unwrap()
+            .penalty
+    };
+
+    alice.seed(&rid, policy::Scope::All).unwrap();
+    alice.connect_to(&bob);
+    alice.receive(bob.id(), bob.refs_announcement(rid));
+    assert_matches!(alice.outbox().next(), Some(Io::Fetch { .. }));
+
+    // Network errors are transient: the fetch is retried, and Bob isn't penalized.
+    let before = penalty(&alice, &bob.id());
+    alice.fetched(
+        rid,
+        bob.id(),
+        Err(worker::FetchError::Io(
+            io::ErrorKind::ConnectionReset.into(),
+        )),
+    );
+    assert_eq!(penalty(&alice, &bob.id()), before);
+
+    alice.elapse(scheduler::FETCH_DEBOUNCE);
+    assert!(alice.outbox().any(|o| matches!(o, Io::Fetch { .. })));
+
+    // Misbehavior is held against Bob, and the fetch isn't retried.
+    alice.fetched(
+        rid,
+        bob.id(),
+        Err(worker::FetchError::CommandFailed { code: 1 }),
+    );
+    assert!(penalty(&alice, &bob.id()) > before);
+
+    bob.elapse(LocalDuration::from_mins(1));
+    alice.elapse(scheduler::FETCH_DEBOUNCE);
+    alice.outbox().for_each(drop);
+    alice.receive(bob.id(), bob.refs_announcement(rid));
+    alice.elapse(scheduler::FETCH_DEBOUNCE);
+    assert!(!alice.outbox().any(|o| matches!(o, Io::Fetch { .. })));
+}
+
+#[test]
+fn test_upload_log() {
+    let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+    let bob = Peer::new("bob", [9, 9, 9, 9]);
+    let rid = arbitrary::gen::<RepoId>(1);
+
+    alice.uploaded(
+        rid,
+        bob.id(),
+        4096,
+        time::Duration::from_millis(250),
+        Some(time::Duration::from_millis(40)),
+        Ok(()),
+    );
+    alice.elapse(LocalDuration::from_secs(1));
+    alice.uploaded(
+        rid,
+        bob.id(),
+        0,
+        time::Duration::from_millis(10),
+        None,
+        Err(worker::UploadError::Unauthorized(bob.id(), rid)),
+    );
+
+    let (sender, receiver) = chan::bounded(1);
+    alice.command(Command::Uploads(Some(rid), sender));
+    let uploads = receiver.recv().unwrap();
+
+    assert_eq!(uploads.len(), 2);
+    assert_eq!(uploads[0].nid, bob.id());
+    assert!(uploads[0].error.is_some());
+    assert_eq!(uploads[1].sent, 4096);
+    assert_eq!(uploads[1].elapsed, 250);
+    assert_eq!(uploads[1].cpu, Some(40));
+    assert_eq!(uploads[1].error, None);
+
+    // Entries older than the maximum age are pruned.
+    alice.elapse(alice.config().limits.uploads.max_age + LocalDuration::from_secs(1));
+
+    let (sender, receiver) = chan::bounded(1);
+    alice.command(Command::Uploads(None, sender));
+    assert!(receiver.recv().unwrap().is_empty());
+}
+
+#[test]
+fn test_repos() {
+    let temp = tempfile::tempdir().unwrap();
+    let storage = Storage::open(temp.path(), fixtures::user()).unwrap();
+    let mut alice = Peer::config(
+        "alice",
+        [7, 7, 7, 7],
+        storage,
+        peer::Config {
+            config: Config {
+                replication_factor: 1,
+                ..Config::test(node::Alias::new("alice"))
+            },
+            ..peer::Config::default()
+        },
+    );
+    let bob = Peer::new("bob", [9, 9, 9, 9]);
+    let acme = alice.project("acme", "");
+
+    alice.seed(&acme, policy::Scope::All).unwrap();
     alice.connect_to(&bob);
 
-    // Alice receives Bob's refs.
-    alice.receive(bob.id(), bob.refs_announcement(*rid));
+    let (sender, receiver) = chan::bounded(1);
+    alice.command(Command::Repos(sender));
+    let repos = receiver.recv().unwrap();
+    let repo = repos.iter().find(|r| r.rid == acme).unwrap();
+
+    assert_eq!(repo.policy, policy::Policy::Allow);
+    assert_eq!(repo.scope, policy::Scope::All);
+    assert_eq!(repo.namespaces, 1);
+    assert_eq!(repo.fetched, None);
+    assert_eq!(repo.replicas, 0);
+    assert!(!repo.replicated);
+
+    // Bob announces that he has Alice's refs.
+    let ann = AnnouncementMessage::from(RefsAnnouncement {
+        rid: acme,
+        refs: vec![RefsAt::new(&alice.storage().repository(acme).unwrap(), alice.id).unwrap()]
+            .try_into()
+            .unwrap(),
+        timestamp: bob.timestamp(),
+    });
+    alice.receive(bob.id, Message::Announcement(ann.signed(bob.signer())));
 
-    // Alice fetches Bob's refs as this is a new repo.
-    assert_matches!(alice.outbox().next(), Some(Io::Fetch { .. }));
+    // Alice then fetches from Bob.
+    let (sender, _receiver) = chan::bounded(1);
+    alice.command(Command::Fetch(acme, bob.id, DEFAULT_TIMEOUT, sender));
+    alice.fetched(acme, bob.id, Ok(arbitrary::gen::<fetch::FetchResult>(1)));
+
+    let (sender, receiver) = chan::bounded(1);
+    alice.command(Command::Repos(sender));
+    let repos = receiver.recv().unwrap();
+    let repo = repos.iter().find(|r| r.rid == acme).unwrap();
+
+    assert_eq!(repo.fetched, Some(alice.clock().local_time().into()));
+    assert_eq!(repo.announced, Some(bob.timestamp()));
+    assert_eq!(repo.replicas, 1);
+    assert!(repo.replicated);
 }
 
 /// Alice and Bob both have the same repo.
@@ -1037,6 +2430,214 @@ fn test_inventory_relay() {
     );
 }
 
+#[test]
+fn test_inventory_pages() {
+    let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+    let bob = Peer::new("bob", [8, 8, 8, 8]);
+    let eve = Peer::new("eve", [9, 9, 9, 9]);
+    let carol = Peer::new("carol", [12, 12, 12, 12]);
+    let rids = arbitrary::vec::<RepoId>(3);
+    let now = alice.timestamp();
+    let page = |inventory: &[RepoId], page: u16| {
+        bob.announcement(InventoryPage {
+            inventory: BoundedVec::try_from(inventory.to_vec()).unwrap(),
+            page,
+            pages: 2,
+            timestamp: now,
+        })
+    };
+    let seeds = |alice: &Peer<MockStorage, MockSigner>, rid: &RepoId| {
+        alice.database().routing().get(rid).unwrap()
+    };
+
+    alice.init();
+    alice.wake(); // Run all periodic tasks now so they don't trigger later.
+
+    // Eve understands inventory pages, Carol doesn't.
+    let timestamp = Timestamp::from((*alice.timestamp()).max(*eve.timestamp()));
+    alice
+        .database_mut()
+        .addresses_mut()
+        .insert(
+            &eve.id(),
+            node::Features::SEED | node::Features::INVENTORY_PAGES,
+            node::Alias::new(eve.name),
+            0,
+            timestamp,
+            None,
+        )
+        .unwrap();
+    alice.connect_to(&bob);
+    alice.connect_from(&eve);
+    alice.connect_from(&carol);
+    alice.outbox().for_each(drop);
+
+    // Pages are relayed as soon as they're received, but the inventory is only processed
+    // once all of its pages are.
+    alice.receive(bob.id(), page(&rids[..2], 0));
+    alice
+        .expect()
+        .relayed(eve.id(), expect::inventory)
+        .not_relayed(carol.id(), expect::inventory)
+        .done();
+    assert!(seeds(&alice, &rids[0]).is_empty());
+
+    alice.receive(bob.id(), page(&rids[2..], 1));
+    alice.expect().relayed(eve.id(), expect::inventory).done();
+
+    for rid in &rids {
+        assert!(seeds(&alice, rid).contains(&bob.id()));
+    }
+
+    // Pages of an inventory we already have aren't relayed again.
+    alice.receive(bob.id(), page(&rids[..2], 0));
+    alice.expect().done();
+
+    // The part of the inventory that fits in a single announcement, eg. as received by
+    // nodes that don't support pages, doesn't override the inventory received in pages.
+    alice.receive(
+        bob.id(),
+        Message::inventory(
+            InventoryAnnouncement {
+                inventory: BoundedVec::try_from(rids[..2].to_vec()).unwrap(),
+                timestamp: now,
+            },
+            bob.signer(),
+        ),
+    );
+    assert!(seeds(&alice, &rids[2]).contains(&bob.id()));
+}
+
+#[test]
+fn test_inventory_diff_relay() {
+    // Topology is eve <-> alice <-> bob
+    let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+    let bob = Peer::new("bob", [8, 8, 8, 8]);
+    let eve = Peer::new("eve", [9, 9, 9, 9]);
+    let rids = arbitrary::vec::<RepoId>(4);
+    let now = LocalTime::now().into();
+    let inventory = |inventory: &[RepoId], timestamp: Timestamp| {
+        Message::inventory(
+            InventoryAnnouncement {
+                inventory: inventory.to_vec().try_into().unwrap(),
+                timestamp,
+            },
+            bob.signer(),
+        )
+    };
+
+    alice.init();
+    alice.wake();
+    alice.connect_to(&bob);
+    alice.connect_from(&eve);
+    // Eve understands inventory diffs.
+    alice.receive(
+        eve.id(),
+        Message::node(
+            NodeAnnouncement {
+                features: node::Features::SEED | node::Features::INVENTORY_DIFF,
+                timestamp: Timestamp::from((*alice.timestamp()).max(*eve.timestamp()) + 1),
+                alias: node::Alias::new("eve"),
+                addresses: BoundedVec::new(),
+                nonce: 0,
+            }
+            .solve(0)
+            .unwrap(),
+            eve.signer(),
+        ),
+    );
+    alice.messages(eve.id()).for_each(drop);
+
+    // The first inventory is relayed in full, since Eve doesn't have any inventory of Bob.
+    alice.receive(bob.id(), inventory(&rids[..3], now));
+    assert_matches!(
+        alice.messages(eve.id()).next(),
+        Some(Message::Announcement(Announcement {
+            message: AnnouncementMessage::Inventory(_),
+            ..
+        }))
+    );
+
+    // The next one is relayed as a diff against it.
+    let expected = inventory(&[rids[0], rids[2], rids[3]], now + 1);
+    alice.receive(bob.id(), expected.clone());
+    let diff = alice
+        .messages(eve.id())
+        .find_map(|m| match m {
+            Message::InventoryDiff(diff) => Some(diff),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(diff.removed.as_slice(), &[rids[1]]);
+    assert_eq!(diff.added.as_slice(), &[rids[3]]);
+    assert_eq!(
+        Message::from(diff.apply(&rids[..3]).unwrap()),
+        expected,
+        "The diff rebuilds into the original announcement"
+    );
+
+    // If Eve is missing the base inventory, the full inventory is sent.
+    alice.receive(eve.id(), Info::MissingInventory { node: bob.id() }.into());
+    assert_eq!(alice.messages(eve.id()).next(), Some(expected));
+
+    // Bob doesn't understand diffs, so Eve's inventories are relayed to him in full.
+    let eve_inventory = |inventory: &[RepoId], timestamp: Timestamp| {
+        Message::inventory(
+            InventoryAnnouncement {
+                inventory: inventory.to_vec().try_into().unwrap(),
+                timestamp,
+            },
+            eve.signer(),
+        )
+    };
+    alice.receive(eve.id(), eve_inventory(&rids[..3], now));
+    alice.receive(eve.id(), eve_inventory(&rids[..2], now + 1));
+    assert!(alice
+        .messages(bob.id())
+        .all(|m| !matches!(m, Message::InventoryDiff(_))));
+}
+
+#[test]
+fn test_inventory_diff_received() {
+    let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+    let bob = Peer::new("bob", [8, 8, 8, 8]);
+    let rids = arbitrary::vec::<RepoId>(4);
+    let now = LocalTime::now().into();
+    let announcement = |inventory: &[RepoId], timestamp: Timestamp| {
+        AnnouncementMessage::from(InventoryAnnouncement {
+            inventory: inventory.to_vec().try_into().unwrap(),
+            timestamp,
+        })
+        .signed(bob.signer())
+    };
+    let base = announcement(&rids[..3], now);
+    let next = announcement(&[rids[0], rids[1], rids[3]], now + 1);
+    let diff = InventoryDiff::new(&next, &rids[..3]).unwrap();
+
+    alice.connect_to(&bob);
+    alice.messages(bob.id()).for_each(drop);
+
+    // Without the base inventory, the diff can't be applied.
+    alice.receive(bob.id(), diff.clone().into());
+    assert_eq!(
+        alice.messages(bob.id()).next(),
+        Some(Info::MissingInventory { node: bob.id() }.into())
+    );
+
+    alice.receive(bob.id(), base.into());
+    alice.receive(bob.id(), diff.into());
+    assert_eq!(
+        alice
+            .database()
+            .gossip()
+            .inventory(&bob.id())
+            .unwrap()
+            .as_ref()
+            .and_then(Announcement::inventory),
+        next.inventory()
+    );
+}
+
 #[test]
 fn test_persistent_peer_reconnect_attempt() {
     use std::collections::HashSet;
@@ -1111,35 +2712,256 @@ fn test_persistent_peer_reconnect_success() {
         [7, 7, 7, 7],
         MockStorage::empty(),
         peer::Config {
-            config: Config {
-                connect: HashSet::from_iter([(bob.id, bob.addr()).into()]),
-                ..Config::new(node::Alias::new("alice"))
-            },
+            config: Config {
+                connect: HashSet::from_iter([(bob.id, bob.addr()).into()]),
+                ..Config::new(node::Alias::new("alice"))
+            },
+            ..peer::Config::default()
+        },
+    );
+    alice.connect_to(&bob);
+
+    // A transient error such as this will cause Alice to attempt a reconnection.
+    let error = Arc::new(io::Error::from(io::ErrorKind::ConnectionReset));
+    alice.disconnected(
+        bob.id(),
+        Link::Outbound,
+        &DisconnectReason::Connection(error),
+    );
+    alice.elapse(service::MIN_RECONNECTION_DELTA);
+    alice.elapse(service::MIN_RECONNECTION_DELTA); // Trigger a second wakeup to test idempotence.
+
+    alice
+        .outbox()
+        .find_map(|o| match o {
+            Io::Connect(id, _) => Some(id),
+            _ => None,
+        })
+        .expect("Alice attempts a re-connection");
+
+    alice.attempted(bob.id(), bob.addr());
+    alice.connected(bob.id(), bob.addr(), Link::Outbound);
+}
+
+#[test]
+fn test_disconnect_reason_from_remote() {
+    use std::collections::HashSet;
+
+    let bob = Peer::new("bob", [9, 9, 9, 9]);
+    let eve = Peer::new("eve", [8, 8, 8, 8]);
+    let mut alice = Peer::config(
+        "alice",
+        [7, 7, 7, 7],
+        MockStorage::empty(),
+        peer::Config {
+            config: Config {
+                connect: HashSet::from_iter([(bob.id, bob.addr()).into()]),
+                ..Config::new(node::Alias::new("alice"))
+            },
+            ..peer::Config::default()
+        },
+    );
+    alice.import_addresses([&bob, &eve]);
+    alice.connect_to(&bob);
+    alice.connect_to(&eve);
+
+    // Eve shuts down cleanly, and isn't penalized for it.
+    alice.receive(
+        eve.id(),
+        Message::Disconnect {
+            reason: DisconnectCode::Shutdown,
+        },
+    );
+    assert!(alice.outbox().any(|o| matches!(
+        o,
+        Io::Disconnect(nid, DisconnectReason::Remote(DisconnectCode::Shutdown)) if nid == eve.id()
+    )));
+    alice.disconnected(eve.id(), Link::Outbound, &DisconnectReason::connection());
+
+    let node = alice
+        .database()
+        .addresses()
+        .get(&eve.id())
+        .unwrap()
+        .unwrap();
+    assert_eq!(node.penalty, node::Penalty::default());
+
+    // Bob bans us. Even though he is a persistent peer, we don't reconnect.
+    alice.receive(
+        bob.id(),
+        Message::Disconnect {
+            reason: DisconnectCode::Banned,
+        },
+    );
+    alice.disconnected(bob.id(), Link::Outbound, &DisconnectReason::connection());
+    alice.elapse(service::MAX_RECONNECTION_DELTA);
+
+    assert!(alice.sessions().get(&bob.id()).is_none());
+    assert!(!alice
+        .outbox()
+        .any(|o| matches!(o, Io::Connect(nid, _) if nid == bob.id())));
+    assert!(alice
+        .database()
+        .addresses()
+        .entries()
+        .unwrap()
+        .filter(|e| e.node == bob.id())
+        .all(|e| e.address.banned));
+}
+
+#[test]
+fn test_inbound_connection_limit_reason() {
+    let bob = Peer::new("bob", [9, 9, 9, 9]);
+    let eve = Peer::new("eve", [8, 8, 8, 8]);
+    let mut alice = Peer::config(
+        "alice",
+        [7, 7, 7, 7],
+        MockStorage::empty(),
+        peer::Config {
+            config: Config {
+                limits: Limits {
+                    connection: ConnectionLimits {
+                        inbound: 1,
+                        ..ConnectionLimits::default()
+                    },
+                    ..Limits::default()
+                },
+                ..Config::new(node::Alias::new("alice"))
+            },
+            ..peer::Config::default()
+        },
+    );
+    // Both connections are accepted, since neither handshake is complete.
+    assert!(alice.accepted(bob.addr()));
+    assert!(alice.accepted(eve.addr()));
+
+    alice.connected(bob.id(), bob.addr(), Link::Inbound);
+    alice.connected(eve.id(), eve.addr(), Link::Inbound);
+
+    assert!(alice.sessions().get(&eve.id()).is_none());
+    assert!(alice.outbox().any(|o| matches!(
+        o,
+        Io::Disconnect(nid, DisconnectReason::TooManyConnections) if nid == eve.id()
+    )));
+    assert_eq!(
+        DisconnectReason::TooManyConnections.code(),
+        Some(DisconnectCode::TooManyConnections)
+    );
+}
+
+#[test]
+fn test_required_features() {
+    let bob = Peer::new("bob", [9, 9, 9, 9]);
+    let eve = Peer::new("eve", [8, 8, 8, 8]);
+    let mut alice = Peer::config(
+        "alice",
+        [7, 7, 7, 7],
+        MockStorage::empty(),
+        peer::Config {
+            config: Config {
+                required_features: node::Features::HTTP_GATEWAY,
+                ..Config::new(node::Alias::new("alice"))
+            },
+            ..peer::Config::default()
+        },
+    );
+    assert!(alice.accepted(bob.addr()));
+    assert!(alice.accepted(eve.addr()));
+
+    alice.connected(bob.id(), bob.addr(), Link::Inbound);
+    alice.connected(eve.id(), eve.addr(), Link::Inbound);
+
+    // Features are unknown until the peers announce themselves.
+    assert!(!alice
+        .outbox()
+        .any(|o| matches!(o, Io::Disconnect(_, DisconnectReason::MissingFeatures(_)))));
+
+    alice.receive(bob.id(), bob.node_announcement());
+    alice.receive(
+        eve.id(),
+        Message::node(
+            NodeAnnouncement {
+                features: node::Features::SEED | node::Features::HTTP_GATEWAY,
+                timestamp: eve.timestamp() + 1,
+                alias: node::Alias::new("eve"),
+                addresses: BoundedVec::new(),
+                nonce: 0,
+            }
+            .solve(0)
+            .unwrap(),
+            eve.signer(),
+        ),
+    );
+    let disconnected = alice
+        .outbox()
+        .filter_map(|o| match o {
+            Io::Disconnect(nid, DisconnectReason::MissingFeatures(missing)) => Some((nid, missing)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(disconnected, vec![(bob.id(), node::Features::HTTP_GATEWAY)]);
+    assert_eq!(
+        alice
+            .peers_with(node::Features::HTTP_GATEWAY)
+            .copied()
+            .collect::<Vec<_>>(),
+        vec![eve.id()]
+    );
+}
+
+#[test]
+fn test_reload_config() {
+    let bob = Peer::new("bob", [9, 9, 9, 9]);
+    let eve = Peer::new("eve", [8, 8, 8, 8]);
+    let rid = arbitrary::gen::<RepoId>(1);
+    let config = Config {
+        limits: Limits {
+            connection: ConnectionLimits {
+                inbound: 1,
+                ..ConnectionLimits::default()
+            },
+            ..Limits::default()
+        },
+        policy: policy::Policy::Block,
+        ..Config::new(node::Alias::new("alice"))
+    };
+    let mut alice = Peer::config(
+        "alice",
+        [7, 7, 7, 7],
+        MockStorage::empty(),
+        peer::Config {
+            config,
             ..peer::Config::default()
         },
     );
-    alice.connect_to(&bob);
-
-    // A transient error such as this will cause Alice to attempt a reconnection.
-    let error = Arc::new(io::Error::from(io::ErrorKind::ConnectionReset));
-    alice.disconnected(
-        bob.id(),
-        Link::Outbound,
-        &DisconnectReason::Connection(error),
+    alice.connected(bob.id(), bob.addr(), Link::Inbound);
+    assert!(!alice.accepted(eve.addr()));
+    assert_eq!(
+        alice.policies().seed_policy(&rid).unwrap().policy,
+        policy::Policy::Block
     );
-    alice.elapse(service::MIN_RECONNECTION_DELTA);
-    alice.elapse(service::MIN_RECONNECTION_DELTA); // Trigger a second wakeup to test idempotence.
 
-    alice
-        .outbox()
-        .find_map(|o| match o {
-            Io::Connect(id, _) => Some(id),
-            _ => None,
-        })
-        .expect("Alice attempts a re-connection");
+    let config = alice.config().clone();
+    let mut new = config.clone();
+    new.limits.connection.inbound = 2;
+    new.policy = policy::Policy::Allow;
+    new.listen = vec![net::SocketAddr::from(([0, 0, 0, 0], 8776))];
 
-    alice.attempted(bob.id(), bob.addr());
-    alice.connected(bob.id(), bob.addr(), Link::Outbound);
+    let (sender, receiver) = chan::bounded(1);
+    alice.command(Command::Reload(Box::new(new), sender));
+
+    let diff = receiver.recv().unwrap();
+    assert_eq!(diff.live, vec!["limits.connection.inbound", "policy"]);
+    assert_eq!(diff.restart, vec!["listen"]);
+
+    // Live settings are applied, the others are left as they are.
+    assert!(alice.accepted(eve.addr()));
+    assert_eq!(
+        alice.policies().seed_policy(&rid).unwrap().policy,
+        policy::Policy::Allow
+    );
+    assert_eq!(alice.config().listen, config.listen);
 }
 
 #[test]
@@ -1191,6 +3013,186 @@ fn test_maintain_connections() {
     );
 }
 
+#[test]
+fn test_maintain_connections_address_family() {
+    let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+    let connected = Peer::new("connected", [8, 8, 8, 1]);
+    let bob = Peer::new("bob", [9, 9, 9, 1]);
+    let ipv4: node::Address = net::SocketAddr::from(([9, 9, 9, 1], 8776)).into();
+    let ipv6: node::Address =
+        net::SocketAddr::from((net::Ipv6Addr::new(0x2001, 0x4860, 0, 0, 0, 0, 0, 1), 8776)).into();
+
+    alice.connect_to(&connected);
+
+    let timestamp = alice.timestamp();
+    alice
+        .database_mut()
+        .addresses_mut()
+        .insert(
+            &bob.id(),
+            node::Features::SEED,
+            node::Alias::new("bob"),
+            0,
+            timestamp,
+            [ipv6, ipv4.clone()]
+                .into_iter()
+                .map(|addr| node::KnownAddress::new(addr, node::address::Source::Peer)),
+        )
+        .unwrap();
+    alice.disconnected(
+        connected.id(),
+        Link::Outbound,
+        &DisconnectReason::Session(session::Error::Misbehavior),
+    );
+
+    // Without IPv6 connectivity, the IPv4 address is dialed.
+    assert_matches!(
+        alice.outbox().find(|o| matches!(o, Io::Connect(..))),
+        Some(Io::Connect(id, addr)) if id == bob.id() && addr == ipv4
+    );
+}
+
+#[test]
+fn test_maintain_connections_address_label() {
+    let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+    let connected = Peer::new("connected", [8, 8, 8, 1]);
+    let bob = Peer::new("bob", [9, 9, 9, 1]);
+    let observed: node::Address = net::SocketAddr::from(([9, 9, 9, 1], 8776)).into();
+    let manual: node::Address = net::SocketAddr::from(([9, 9, 9, 2], 8776)).into();
+
+    alice.connect_to(&connected);
+
+    let timestamp = alice.timestamp();
+    alice
+        .database_mut()
+        .addresses_mut()
+        .insert(
+            &bob.id(),
+            node::Features::SEED,
+            node::Alias::new("bob"),
+            0,
+            timestamp,
+            [
+                (observed, node::address::Label::Observed),
+                (manual.clone(), node::address::Label::Manual),
+            ]
+            .into_iter()
+            .map(|(addr, label)| {
+                node::KnownAddress::new(addr, node::address::Source::Peer).labeled(Some(label))
+            }),
+        )
+        .unwrap();
+    alice.disconnected(
+        connected.id(),
+        Link::Outbound,
+        &DisconnectReason::Session(session::Error::Misbehavior),
+    );
+
+    // The address Bob configured himself is dialed first.
+    assert_matches!(
+        alice.outbox().find(|o| matches!(o, Io::Connect(..))),
+        Some(Io::Connect(id, addr)) if id == bob.id() && addr == manual
+    );
+}
+
+#[test]
+fn test_maintain_connections_diversity() {
+    let mut alice = Peer::config(
+        "alice",
+        [7, 7, 7, 7],
+        MockStorage::empty(),
+        peer::Config {
+            config: Config {
+                peers: PeerConfig::Dynamic { target: 2 },
+                ..Config::new(node::Alias::new("alice"))
+            },
+            ..peer::Config::default()
+        },
+    );
+    let connected = Peer::new("connected", [8, 8, 8, 1]);
+    // Peers on the same `/16` network as the connected peer.
+    let neighbours = [
+        Peer::new("neighbour", [8, 8, 8, 2]),
+        Peer::new("neighbour", [8, 8, 8, 3]),
+        Peer::new("neighbour", [8, 8, 8, 4]),
+    ];
+    let bob = Peer::new("bob", [9, 9, 9, 1]);
+
+    alice.connect_to(&connected);
+    alice.import_addresses(&neighbours);
+    alice.import_addresses([&bob]);
+    alice.elapse(IDLE_INTERVAL);
+
+    // Alice prefers a peer on a different network.
+    assert_matches!(
+        alice.outbox().find(|o| matches!(o, Io::Connect(..))),
+        Some(Io::Connect(id, _)) if id == bob.id()
+    );
+}
+
+#[test]
+fn test_maintain_connections_prune() {
+    let mut alice = Peer::config(
+        "alice",
+        [7, 7, 7, 7],
+        MockStorage::empty(),
+        peer::Config {
+            config: Config {
+                peers: PeerConfig::Dynamic { target: 2 },
+                ..Config::new(node::Alias::new("alice"))
+            },
+            ..peer::Config::default()
+        },
+    );
+    let bob = Peer::new("bob", [8, 8, 8, 1]);
+    let eve = Peer::new("eve", [8, 8, 8, 2]);
+    let carol = Peer::new("carol", [9, 9, 9, 1]);
+
+    alice.connect_to(&bob);
+    alice.elapse(LocalDuration::from_secs(1));
+    alice.connect_to(&eve);
+    alice.elapse(LocalDuration::from_secs(1));
+    alice.connect_to(&carol);
+    alice.outbox().for_each(drop);
+    alice.elapse(IDLE_INTERVAL);
+
+    // Eve shares a network with Bob, and connected after him.
+    let disconnected = alice
+        .outbox()
+        .filter_map(|o| match o {
+            Io::Disconnect(nid, DisconnectReason::TooManyConnections) => Some(nid),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(disconnected, vec![eve.id()]);
+}
+
+#[test]
+fn test_announced_addresses() {
+    let external: node::Address = "seed.radicle.xyz:8776".parse().unwrap();
+    let config = Config {
+        external_addresses: vec![external.clone()],
+        listen: vec![
+            net::SocketAddr::from(([0, 0, 0, 0], 8776)),
+            net::SocketAddr::from(([127, 0, 0, 1], 8777)),
+            net::SocketAddr::from(([88, 12, 4, 1], 8776)),
+            net::SocketAddr::from((net::Ipv6Addr::new(0x2001, 0x4860, 0, 0, 0, 0, 0, 1), 8776)),
+        ],
+        ..Config::new(node::Alias::new("alice"))
+    };
+    let addresses = gossip::addresses(&config);
+
+    assert_eq!(
+        addresses.as_ref(),
+        &[
+            external,
+            net::SocketAddr::from(([88, 12, 4, 1], 8776)).into(),
+            net::SocketAddr::from((net::Ipv6Addr::new(0x2001, 0x4860, 0, 0, 0, 0, 0, 1), 8776))
+                .into(),
+        ]
+    );
+}
+
 #[test]
 fn test_maintain_connections_transient() {
     // Peers alice starts out connected to.
@@ -1257,6 +3259,51 @@ fn test_maintain_connections_failed_attempt() {
     assert!(!alice.outbox().any(|o| matches!(o, Io::Connect(_, _))));
 }
 
+#[test]
+fn test_maintain_connections_backoff() {
+    let eve = Peer::new("eve", [9, 9, 9, 9]);
+    let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+    let reason = DisconnectReason::connection();
+
+    // Since the last connection to Eve was successful, Alice attempts her again right away.
+    alice.connect_to(&eve);
+    alice.disconnected(eve.id(), Link::Outbound, &reason);
+
+    let mut previous = LocalDuration::from_secs(0);
+    for _ in 1..dialer::MAX_DIAL_ATTEMPTS {
+        let delay = alice
+            .outbox()
+            .skip_while(|o| !matches!(o, Io::Connect(id, _) if id == &eve.id))
+            .find_map(|o| match o {
+                Io::Wakeup(d) => Some(d),
+                _ => None,
+            })
+            .expect("Alice attempts Eve and schedules a retry");
+        assert!(delay >= dialer::MIN_RETRY_DELAY);
+        assert!(
+            delay >= previous,
+            "The delay between attempts doesn't shrink"
+        );
+
+        alice.attempted(eve.id, eve.addr());
+        alice.disconnected(eve.id(), Link::Outbound, &reason);
+        assert!(!alice.outbox().any(|o| matches!(o, Io::Connect(_, _))));
+
+        alice.elapse(delay);
+        previous = delay;
+    }
+    alice
+        .outbox()
+        .find(|o| matches!(o, Io::Connect(id, _) if id == &eve.id))
+        .expect("Alice attempts Eve one last time");
+    alice.attempted(eve.id, eve.addr());
+
+    // Alice eventually gives up on Eve's address.
+    alice.disconnected(eve.id(), Link::Outbound, &reason);
+    alice.elapse(dialer::MAX_RETRY_DELAY);
+    assert!(!alice.outbox().any(|o| matches!(o, Io::Connect(_, _))));
+}
+
 #[test]
 fn test_seed_repo_subscribe() {
     let mut alice = Peer::new("alice", [7, 7, 7, 7]);
@@ -1278,6 +3325,47 @@ fn test_seed_repo_subscribe() {
     );
 }
 
+#[test]
+fn test_subscribe_since_delivered() {
+    let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+    let bob = Peer::new("bob", [8, 8, 8, 8]);
+    let eve = Peer::new("eve", [9, 9, 9, 9]);
+    let carol = Peer::new("carol", [12, 12, 12, 12]);
+    let reason = DisconnectReason::Session(session::Error::Timeout);
+    let subscribed = |alice: &mut Peer<MockStorage, MockSigner>, peer: &Peer<_, _>| {
+        alice.command(Command::Connect(
+            peer.id(),
+            peer.address(),
+            ConnectOptions::default(),
+        ));
+        alice.attempted(peer.id(), peer.address());
+        alice.connected(peer.id(), peer.address(), Link::Outbound);
+        alice
+            .messages(peer.id())
+            .find_map(|m| match m {
+                Message::Subscribe(Subscribe { since, .. }) => Some(since),
+                _ => None,
+            })
+            .unwrap()
+    };
+
+    alice.connect_to(&bob);
+    alice.connect_to(&eve);
+    // Peer clocks start when peers are created, so time passes for Alice to make sure
+    // Carol's announcement isn't ahead of her clock.
+    alice.elapse(LocalDuration::from_mins(1));
+    alice.receive(bob.id(), bob.inventory_announcement());
+    alice.receive(bob.id(), carol.node_announcement());
+
+    alice.disconnected(bob.id(), Link::Outbound, &reason);
+    alice.disconnected(eve.id(), Link::Outbound, &reason);
+
+    // Bob relayed Carol's announcement, so we only subscribe to what came after it.
+    assert_eq!(subscribed(&mut alice, &bob), carol.timestamp());
+    // Eve didn't relay anything, so we subscribe to everything we could have missed.
+    assert!(*subscribed(&mut alice, &eve) < *carol.timestamp());
+}
+
 #[test]
 fn test_fetch_missing_inventory_on_gossip() {
     let rid = arbitrary::gen::<RepoId>(1);
@@ -1337,6 +3425,124 @@ fn test_fetch_missing_inventory_on_schedule() {
         .unwrap();
 }
 
+#[test]
+fn test_private_repo_not_announced() {
+    let public = arbitrary::gen::<RepoId>(1);
+    let private = arbitrary::gen::<RepoId>(1);
+    let storage = MockStorage::new(vec![
+        (public, arbitrary::gen(1)),
+        (private, arbitrary::gen(1)),
+    ]);
+    let mut alice = Peer::with_storage("alice", [7, 7, 7, 7], storage);
+    let bob = Peer::new("bob", [8, 8, 8, 8]);
+
+    alice.policies_mut().set_private(&private, true).unwrap();
+    alice.connect_to(&bob);
+    alice.elapse(ANNOUNCE_INTERVAL);
+
+    let Some(Message::Announcement(Announcement {
+        message: AnnouncementMessage::Inventory(InventoryAnnouncement { inventory, .. }),
+        ..
+    })) = alice.inventory_announcements(bob.id()).next()
+    else {
+        panic!("Alice must announce her inventory");
+    };
+    assert!(inventory.contains(&public));
+    assert!(!inventory.contains(&private));
+}
+
+#[test]
+fn test_announcement_refresh() {
+    let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+    let bob = Peer::new("bob", [8, 8, 8, 8]);
+    let is_node = |m: &Message| {
+        matches!(
+            m,
+            Message::Announcement(Announcement {
+                message: AnnouncementMessage::Node(_),
+                ..
+            })
+        )
+    };
+    let is_inventory = |m: &Message| {
+        matches!(
+            m,
+            Message::Announcement(Announcement {
+                message: AnnouncementMessage::Inventory(_),
+                ..
+            })
+        )
+    };
+
+    alice.connect_to(&bob);
+    alice.messages(bob.id()).for_each(drop);
+
+    // With a single peer, shortly after joining, both announcements are refreshed
+    // well within an hour.
+    alice.elapse(ANNOUNCE_INTERVAL);
+    let msgs = alice.messages(bob.id()).collect::<Vec<_>>();
+    assert!(msgs.iter().any(is_node));
+    assert!(msgs.iter().any(is_inventory));
+
+    // But not right after they were refreshed.
+    alice.elapse(announcer::MIN_REFRESH_INTERVAL);
+    let msgs = alice.messages(bob.id()).collect::<Vec<_>>();
+    assert!(!msgs.iter().any(is_node));
+    assert!(!msgs.iter().any(is_inventory));
+
+    // Once past warmup, the node announcement is refreshed before the inventory.
+    alice.elapse(announcer::WARMUP);
+    alice.messages(bob.id()).for_each(drop);
+    alice.elapse(announcer::NODE_REFRESH_INTERVAL / 8);
+    let msgs = alice.messages(bob.id()).collect::<Vec<_>>();
+    assert!(msgs.iter().any(is_node));
+    assert!(!msgs.iter().any(is_inventory));
+}
+
+#[test]
+fn test_maintain_replication() {
+    let rid = arbitrary::gen::<RepoId>(1);
+    let storage = MockStorage::new(vec![(rid, arbitrary::gen(1))]);
+    let mut alice = Peer::config(
+        "alice",
+        [7, 7, 7, 7],
+        storage,
+        peer::Config {
+            config: Config {
+                peers: PeerConfig::Static,
+                ..Config::new(node::Alias::new("alice"))
+            },
+            ..peer::Config::default()
+        },
+    );
+    let bob = Peer::new("bob", [8, 8, 8, 8]);
+
+    alice.initialize();
+    alice.seed(&rid, node::policy::Scope::All).unwrap();
+    alice.import_addresses([&bob]);
+    alice
+        .database_mut()
+        .routing_mut()
+        .insert([&rid], bob.id(), bob.timestamp())
+        .unwrap();
+    alice.outbox().for_each(drop);
+    alice.elapse(service::REPLICATION_INTERVAL);
+
+    // Bob is the only seed of the repository, so Alice connects to him.
+    assert_matches!(
+        alice.outbox().find(|o| matches!(o, Io::Connect(..))),
+        Some(Io::Connect(id, _)) if id == bob.id()
+    );
+    alice.attempted(bob.id(), bob.address());
+    alice.connected(bob.id(), bob.address(), Link::Outbound);
+
+    // Once connected, Alice fetches the repository from Bob.
+    alice
+        .outbox()
+        .find(|o| matches!(o, Io::Fetch { rid: other, remote, .. } if other == &rid && remote == &bob.id()))
+        .expect("Alice fetches from Bob");
+}
+
 #[test]
 fn test_queued_fetch_max_capacity() {
     let storage = arbitrary::nonempty_storage(3);
@@ -1455,6 +3661,8 @@ fn test_queued_fetch_from_ann_same_rid() {
             namespaces: [carol.id()].into_iter().collect(),
             clone: false,
             doc: arbitrary::gen(1),
+            blobs: vec![],
+            summary: Default::default(),
         }),
     );
     // Now the 1st fetch is done, but the 2nd and 3rd fetches are redundant.
@@ -1771,3 +3979,82 @@ fn prop_inventory_exchange_dense() {
         .tests(20)
         .quickcheck(property as fn(MockStorage, MockStorage, MockStorage));
 }
+
+#[test]
+fn test_query_refs() {
+    let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+    let bob = Peer::new("bob", [8, 8, 8, 8]);
+    let eve = Peer::new("eve", [9, 9, 9, 9]);
+    let rid = arbitrary::gen::<RepoId>(1);
+    let refs = vec![git::refname!("refs/rad/id")];
+    let query = |alice: &mut Peer<MockStorage, MockSigner>, nid: NodeId| {
+        let (sender, receiver) = chan::bounded(1);
+        alice.command(Command::QueryRefs(rid, nid, refs.clone(), sender));
+        receiver
+    };
+
+    // Bob answers queries, Eve doesn't.
+    alice
+        .database_mut()
+        .addresses_mut()
+        .insert(
+            &bob.id(),
+            node::Features::SEED | node::Features::REF_QUERY,
+            node::Alias::new(bob.name),
+            0,
+            bob.timestamp(),
+            None,
+        )
+        .unwrap();
+
+    // Queries are only sent to connected peers.
+    assert_matches!(
+        query(&mut alice, bob.id()).try_recv(),
+        Ok(Err(worker::query::Error::NotConnected))
+    );
+    alice.connect_to(&bob);
+    alice.connect_to(&eve);
+    alice.outbox().for_each(drop);
+
+    assert_matches!(
+        query(&mut alice, eve.id()).try_recv(),
+        Ok(Err(worker::query::Error::Unsupported))
+    );
+    assert_matches!(alice.outbox().next(), None);
+
+    let _receiver = query(&mut alice, bob.id());
+    assert_matches!(
+        alice.outbox().next(),
+        Some(Io::QueryRefs { rid: r, remote, refs: q, .. })
+            if r == rid && remote == bob.id() && q == refs
+    );
+}
+
+#[test]
+fn test_service_health() {
+    let mut alice = Peer::new("alice", [7, 7, 7, 7]);
+    let bob = Peer::new("bob", [8, 8, 8, 8]);
+
+    let health = alice.health();
+    assert_eq!(
+        health.gossip,
+        node::HealthState::degraded("not connected to any peer")
+    );
+    assert_eq!(
+        health.addresses,
+        node::HealthState::degraded("no known nodes")
+    );
+    assert_eq!(health.maintenance, node::HealthState::Ok);
+
+    alice.import_addresses([&bob]);
+    alice.connect_to(&bob);
+
+    assert_eq!(
+        alice.health(),
+        ServiceHealth {
+            gossip: node::HealthState::Ok,
+            addresses: node::HealthState::Ok,
+            maintenance: node::HealthState::Ok,
+        }
+    );
+}
@@ -1,7 +1,12 @@
-use std::{collections::HashSet, thread, time};
+use std::{collections::HashSet, net, thread, time};
+
+use nonempty::NonEmpty;
 
 use radicle::crypto::{test::signer::MockSigner, Signer};
+use radicle::identity::RepoId;
+use radicle::node::policy::RefCategories;
 use radicle::node::{Alias, ConnectResult, FetchResult, Handle as _, DEFAULT_TIMEOUT};
+use radicle::storage::refs::{RefsAt, Version};
 use radicle::storage::{
     ReadRepository, ReadStorage, RefUpdate, RemoteRepository, SignRepository, ValidateRepository,
     WriteRepository, WriteStorage,
@@ -10,13 +15,16 @@ use radicle::test::fixtures;
 use radicle::{assert_matches, rad};
 use radicle::{git, issue};
 
-use crate::node::config::Limits;
+use crate::node::config::{BridgeLimits, ConnectionLimits, Hook, Limits, RateLimit};
 use crate::node::{Config, ConnectOptions};
+use crate::runtime::{selfcheck, Handle, HandleError};
 use crate::service;
 use crate::service::policy::Scope;
 use crate::storage::git::transport;
+use crate::test::arbitrary;
 use crate::test::environment::{converge, Environment, Node};
 use crate::test::logger;
+use crate::{LocalDuration, Runtime};
 
 #[test]
 //
@@ -70,6 +78,67 @@ fn test_inventory_sync_bridge() {
     assert_eq!(routes.len(), 3);
 }
 
+#[test]
+//
+//     alice -- bridge -- bob
+//
+fn test_relay_via_bridge() {
+    logger::init(log::Level::Debug);
+
+    let tmp = tempfile::tempdir().unwrap();
+
+    let alice = Node::init(tmp.path(), Config::test(Alias::new("alice")));
+    let bob = Node::init(tmp.path(), Config::test(Alias::new("bob")));
+    let bridge = Node::init(
+        tmp.path(),
+        Config {
+            bridge: Some(BridgeLimits {
+                peer: RateLimit {
+                    fill_rate: 1024.,
+                    capacity: 4096,
+                },
+                total: RateLimit {
+                    fill_rate: 4096.,
+                    capacity: 16384,
+                },
+            }),
+            ..Config::test(Alias::new("bridge"))
+        },
+    );
+    let mut alice = alice.spawn();
+    let mut bob = bob.spawn();
+    let bridge = bridge.spawn();
+
+    alice.connect(&bridge);
+    bob.connect(&bridge);
+
+    let relayed = bob.handle.relayed().unwrap();
+    assert!(alice
+        .handle
+        .relay(bridge.id, bob.id, b"hello".to_vec())
+        .unwrap());
+
+    let delivered = relayed.recv_timeout(DEFAULT_TIMEOUT).unwrap();
+    assert_eq!(delivered.from, alice.id);
+    assert_eq!(delivered.via, bridge.id);
+    assert_eq!(delivered.data, b"hello");
+
+    // Data over the peer's limit is dropped by the bridge.
+    assert!(alice
+        .handle
+        .relay(bridge.id, bob.id, vec![0; 8192])
+        .unwrap());
+    // Data relayed via a node we're not connected to isn't queued.
+    assert!(!alice.handle.relay(bob.id, bridge.id, vec![0; 8]).unwrap());
+    assert!(alice
+        .handle
+        .relay(bridge.id, bob.id, b"world".to_vec())
+        .unwrap());
+
+    let delivered = relayed.recv_timeout(DEFAULT_TIMEOUT).unwrap();
+    assert_eq!(delivered.data, b"world");
+}
+
 #[test]
 //
 //     alice -- bob
@@ -174,7 +243,7 @@ fn test_replication() {
 
     let updated = match result {
         FetchResult::Success { updated, .. } => updated,
-        FetchResult::Failed { reason } => {
+        FetchResult::Failed { reason, .. } => {
             panic!("Fetch failed from {}: {reason}", bob.id);
         }
     };
@@ -248,6 +317,372 @@ fn test_replication_ref_in_sigrefs() {
     );
 }
 
+#[test]
+fn test_replication_sigrefs_tombstones() {
+    logger::init(log::Level::Debug);
+
+    let tmp = tempfile::tempdir().unwrap();
+    let alice = Node::init(tmp.path(), Config::test(Alias::new("alice")));
+    let mut bob = Node::init(tmp.path(), Config::test(Alias::new("bob")));
+    let acme = bob.project("acme", "");
+    let feature = git::qualified!("refs/heads/feature");
+    let stray = git::qualified!("refs/heads/stray");
+
+    bob.storage
+        .migrate_sigrefs(&bob.signer, Version::V2)
+        .unwrap();
+    {
+        let repo = bob.storage.repository_mut(acme).unwrap();
+        let head = repo
+            .reference_oid(&bob.id, &git::qualified!("refs/heads/master"))
+            .unwrap();
+
+        repo.raw()
+            .reference(
+                feature.with_namespace((&bob.id).into()).as_str(),
+                *head,
+                false,
+                "test",
+            )
+            .unwrap();
+        repo.sign_refs(&bob.signer).unwrap();
+    }
+
+    let mut alice = alice.spawn();
+    let bob = bob.spawn();
+
+    alice.connect(&bob);
+    converge([&alice, &bob]);
+    alice.handle.seed(acme, Scope::All).unwrap();
+
+    let result = alice.handle.fetch(acme, bob.id, DEFAULT_TIMEOUT).unwrap();
+    assert_matches!(result, FetchResult::Success { .. });
+
+    let repo = alice.storage.repository(acme).unwrap();
+    let head = repo.reference_oid(&bob.id, &feature).unwrap();
+
+    // Bob deletes his branch, and alice ends up with a reference of bob's that
+    // isn't in his signed refs.
+    {
+        let repo = bob.storage.repository_mut(acme).unwrap();
+        repo.reference(&bob.id, &feature).unwrap().delete().unwrap();
+        repo.sign_refs(&bob.signer).unwrap();
+    }
+    repo.raw()
+        .reference(
+            stray.with_namespace((&bob.id).into()).as_str(),
+            *head,
+            false,
+            "test",
+        )
+        .unwrap();
+
+    let result = alice.handle.fetch(acme, bob.id, DEFAULT_TIMEOUT).unwrap();
+    assert_matches!(result, FetchResult::Success { .. });
+
+    // The deleted reference is pruned, but the one that was never signed is kept,
+    // since it may be missing due to partial data.
+    assert!(repo.reference_oid(&bob.id, &feature).is_err());
+    assert!(repo.reference_oid(&bob.id, &stray).is_ok());
+}
+
+#[test]
+fn test_replication_denied_refs() {
+    logger::init(log::Level::Debug);
+
+    let tmp = tempfile::tempdir().unwrap();
+    let alice = Node::init(
+        tmp.path(),
+        Config {
+            deny_refs: vec![String::from("refs/namespaces/*/refs/heads/tmp/*")
+                .try_into()
+                .unwrap()],
+            ..Config::test(Alias::new("alice"))
+        },
+    );
+    let mut bob = Node::init(tmp.path(), Config::test(Alias::new("bob")));
+
+    let acme = bob.project("acme", "");
+    {
+        let repo = bob.storage.repository_mut(acme).unwrap();
+        let head = repo
+            .reference_oid(&bob.id, &git::qualified!("refs/heads/master"))
+            .unwrap();
+        repo.backend
+            .reference(
+                &format!("refs/namespaces/{}/refs/heads/tmp/ci", bob.id),
+                head.into(),
+                false,
+                "test",
+            )
+            .unwrap();
+        repo.sign_refs(&bob.signer).unwrap();
+    }
+
+    let mut alice = alice.spawn();
+    let bob = bob.spawn();
+
+    alice.connect(&bob);
+    converge([&alice, &bob]);
+
+    alice.handle.seed(acme, Scope::All).unwrap();
+    let result = alice.handle.fetch(acme, bob.id, DEFAULT_TIMEOUT).unwrap();
+
+    assert_matches!(result, FetchResult::Success { .. });
+
+    // Alice has Bob's signed refs, except for the denied one.
+    let repo = alice.storage.repository(acme).unwrap();
+    assert!(repo
+        .reference(&bob.id, &git::qualified!("refs/heads/master"))
+        .is_ok());
+    assert!(repo
+        .reference(&bob.id, &git::qualified!("refs/heads/tmp/ci"))
+        .is_err());
+}
+
+#[test]
+fn test_replication_denied_refs_downstream() {
+    logger::init(log::Level::Debug);
+
+    let tmp = tempfile::tempdir().unwrap();
+    let mut alice = Node::init(tmp.path(), Config::test(Alias::new("alice")));
+    let seed = Node::init(
+        tmp.path(),
+        Config {
+            deny_refs: vec![String::from("refs/namespaces/*/refs/heads/tmp/*")
+                .try_into()
+                .unwrap()],
+            ..Config::test(Alias::new("seed"))
+        },
+    );
+    let bob = Node::init(tmp.path(), Config::test(Alias::new("bob")));
+
+    let acme = alice.project("acme", "");
+    let tmp_ci = git::qualified!("refs/heads/tmp/ci");
+    {
+        // The denied branch points to a commit that isn't reachable from any other
+        // reference, so that the seed doesn't have it.
+        let repo = alice.storage.repository_mut(acme).unwrap();
+        let (_, head) = repo.head().unwrap();
+        let parent = repo.backend.find_commit(*head).unwrap();
+        let sig = git::raw::Signature::now("radicle", "radicle@localhost").unwrap();
+        repo.backend
+            .commit(
+                Some(tmp_ci.with_namespace((&alice.id).into()).as_str()),
+                &sig,
+                &sig,
+                "Work in progress",
+                &parent.tree().unwrap(),
+                &[&parent],
+            )
+            .unwrap();
+        repo.sign_refs(&alice.signer).unwrap();
+    }
+
+    let alice = alice.spawn();
+    let mut seed = seed.spawn();
+    let mut bob = bob.spawn();
+
+    seed.connect(&alice);
+    bob.connect(&seed);
+    converge([&alice, &seed, &bob]);
+
+    seed.handle.seed(acme, Scope::All).unwrap();
+    let result = seed.handle.fetch(acme, alice.id, DEFAULT_TIMEOUT).unwrap();
+    assert_matches!(result, FetchResult::Success { .. });
+
+    // Bob fetches from the seed, which serves Alice's signed refs, but doesn't have her
+    // denied branch.
+    bob.handle.seed(acme, Scope::All).unwrap();
+    let result = bob.handle.fetch(acme, seed.id, DEFAULT_TIMEOUT).unwrap();
+    assert_matches!(result, FetchResult::Success { .. });
+
+    let repo = bob.storage.repository(acme).unwrap();
+    assert!(repo
+        .reference(&alice.id, &git::qualified!("refs/heads/master"))
+        .is_ok());
+    assert!(repo.reference(&alice.id, &tmp_ci).is_err());
+    assert!(repo.head().is_ok());
+}
+
+#[test]
+fn test_replication_ref_categories() {
+    logger::init(log::Level::Debug);
+
+    let tmp = tempfile::tempdir().unwrap();
+    let mut alice = Node::init(tmp.path(), Config::test(Alias::new("alice")));
+    let mut bob = Node::init(tmp.path(), Config::test(Alias::new("bob")));
+
+    let acme = bob.project("acme", "");
+    alice
+        .policies
+        .set_ref_categories(
+            &acme,
+            RefCategories {
+                tags: false,
+                notes: false,
+                ..RefCategories::default()
+            },
+        )
+        .unwrap();
+    {
+        let repo = bob.storage.repository_mut(acme).unwrap();
+        let head = repo
+            .reference_oid(&bob.id, &git::qualified!("refs/heads/master"))
+            .unwrap();
+        for name in ["refs/tags/v1.0", "refs/notes/commits"] {
+            repo.backend
+                .reference(
+                    &format!("refs/namespaces/{}/{name}", bob.id),
+                    head.into(),
+                    false,
+                    "test",
+                )
+                .unwrap();
+        }
+        repo.sign_refs(&bob.signer).unwrap();
+    }
+
+    let mut alice = alice.spawn();
+    let bob = bob.spawn();
+
+    alice.connect(&bob);
+    converge([&alice, &bob]);
+
+    alice.handle.seed(acme, Scope::All).unwrap();
+    let result = alice.handle.fetch(acme, bob.id, DEFAULT_TIMEOUT).unwrap();
+
+    assert_matches!(result, FetchResult::Success { .. });
+
+    // Alice has Bob's branches and identity, but not his tags or notes.
+    let repo = alice.storage.repository(acme).unwrap();
+    assert!(repo
+        .reference(&bob.id, &git::qualified!("refs/heads/master"))
+        .is_ok());
+    assert!(repo.identity_doc().is_ok());
+    assert!(repo
+        .reference(&bob.id, &git::qualified!("refs/tags/v1.0"))
+        .is_err());
+    assert!(repo
+        .reference(&bob.id, &git::qualified!("refs/notes/commits"))
+        .is_err());
+}
+
+#[test]
+fn test_replication_ref_categories_no_heads() {
+    logger::init(log::Level::Debug);
+
+    let tmp = tempfile::tempdir().unwrap();
+    let mut alice = Node::init(tmp.path(), Config::test(Alias::new("alice")));
+    let mut bob = Node::init(tmp.path(), Config::test(Alias::new("bob")));
+
+    let acme = bob.project("acme", "");
+    alice
+        .policies
+        .set_ref_categories(
+            &acme,
+            RefCategories {
+                heads: false,
+                ..RefCategories::default()
+            },
+        )
+        .unwrap();
+    {
+        let repo = bob.storage.repository_mut(acme).unwrap();
+        let head = repo
+            .reference_oid(&bob.id, &git::qualified!("refs/heads/master"))
+            .unwrap();
+        repo.backend
+            .reference(
+                &format!("refs/namespaces/{}/refs/heads/feature", bob.id),
+                head.into(),
+                false,
+                "test",
+            )
+            .unwrap();
+        repo.sign_refs(&bob.signer).unwrap();
+    }
+
+    let mut alice = alice.spawn();
+    let bob = bob.spawn();
+
+    alice.connect(&bob);
+    converge([&alice, &bob]);
+
+    alice.handle.seed(acme, Scope::All).unwrap();
+    let result = alice.handle.fetch(acme, bob.id, DEFAULT_TIMEOUT).unwrap();
+
+    assert_matches!(result, FetchResult::Success { .. });
+
+    // Alice has the delegate's default branch, and thus the canonical head, but not
+    // his other branches.
+    let repo = alice.storage.repository(acme).unwrap();
+    assert!(repo
+        .reference(&bob.id, &git::qualified!("refs/heads/feature"))
+        .is_err());
+    let (_, head) = repo.head().unwrap();
+    assert_eq!(
+        head,
+        repo.reference_oid(&bob.id, &git::qualified!("refs/heads/master"))
+            .unwrap()
+    );
+}
+
+#[test]
+fn test_replication_too_many_refs() {
+    logger::init(log::Level::Debug);
+
+    let tmp = tempfile::tempdir().unwrap();
+    let alice = Node::init(
+        tmp.path(),
+        Config {
+            limits: Limits {
+                max_namespace_refs: Some(8),
+                ..Limits::default()
+            },
+            ..Config::test(Alias::new("alice"))
+        },
+    );
+    let mut bob = Node::init(tmp.path(), Config::test(Alias::new("bob")));
+
+    let acme = bob.project("acme", "");
+    {
+        let repo = bob.storage.repository_mut(acme).unwrap();
+        let head = repo
+            .reference_oid(&bob.id, &git::qualified!("refs/heads/master"))
+            .unwrap();
+        for i in 0..8 {
+            repo.backend
+                .reference(
+                    &format!("refs/namespaces/{}/refs/heads/spam/{i}", bob.id),
+                    head.into(),
+                    false,
+                    "test",
+                )
+                .unwrap();
+        }
+        repo.sign_refs(&bob.signer).unwrap();
+    }
+
+    let mut alice = alice.spawn();
+    let bob = bob.spawn();
+
+    alice.connect(&bob);
+    converge([&alice, &bob]);
+
+    alice.handle.seed(acme, Scope::All).unwrap();
+    let result = alice.handle.fetch(acme, bob.id, DEFAULT_TIMEOUT).unwrap();
+
+    // Bob is the only delegate, so the fetch fails without his namespace.
+    assert_matches!(result, FetchResult::Failed { .. });
+
+    let db = alice.home.database().unwrap();
+    let node = radicle::node::address::Store::get(&db, &bob.id)
+        .unwrap()
+        .unwrap();
+    assert!(node.penalty > radicle::node::Penalty::default());
+}
+
 #[test]
 fn test_replication_invalid() {
     let tmp = tempfile::tempdir().unwrap();
@@ -567,6 +1002,211 @@ fn test_clone() {
         .is_ok());
 }
 
+#[test]
+fn test_clone_metadata() {
+    logger::init(log::Level::Debug);
+
+    let tmp = tempfile::tempdir().unwrap();
+    let alice = Node::init(tmp.path(), Config::test(Alias::new("alice")));
+    let mut bob = Node::init(tmp.path(), Config::test(Alias::new("bob")));
+    let acme = bob.project("acme", "");
+
+    let mut alice = alice.spawn();
+    let bob = bob.spawn();
+    let events = alice.handle.events();
+
+    alice.connect(&bob);
+    alice.handle.seed(acme, Scope::All).unwrap();
+
+    let result = alice.handle.fetch(acme, bob.id, DEFAULT_TIMEOUT).unwrap();
+    assert!(result.is_success());
+
+    // The metadata is available before the clone completes.
+    let (doc, sigrefs) = events
+        .wait(
+            |e| match e {
+                service::Event::CloneMetadataFetched {
+                    rid, doc, sigrefs, ..
+                } if *rid == acme => Some((doc.clone(), sigrefs.clone())),
+                service::Event::RefsFetched { rid, .. } if *rid == acme => {
+                    panic!("clone completed before its metadata was fetched")
+                }
+                _ => None,
+            },
+            DEFAULT_TIMEOUT,
+        )
+        .unwrap();
+    let repo = bob.storage.repository(acme).unwrap();
+
+    assert_eq!(doc, repo.identity_doc().unwrap().doc);
+    assert_eq!(
+        sigrefs.get(&bob.id).copied(),
+        Some(RefsAt::new(&repo, bob.id).unwrap().at)
+    );
+}
+
+#[test]
+fn test_git_daemon_clone() {
+    logger::init(log::Level::Debug);
+
+    let tmp = tempfile::tempdir().unwrap();
+    let mut alice = Node::init(
+        tmp.path(),
+        Config {
+            git_daemon: Some(([127, 0, 0, 1], 0).into()),
+            ..Config::test(Alias::new("alice"))
+        },
+    );
+    let acme = alice.project("acme", "");
+    alice.config.repo_aliases.insert(String::from("acme"), acme);
+    let alice = alice.spawn();
+    let addr = alice.git_daemon.unwrap();
+
+    let mirror = tmp.path().join("mirror");
+    let output = std::process::Command::new("git")
+        .args(["-c", "protocol.version=2", "clone", "--mirror"])
+        .arg(format!("git://{addr}/{acme}"))
+        .arg(&mirror)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+
+    let mirror = git::raw::Repository::open_bare(&mirror).unwrap();
+    let refname = git::refs::storage::branch_of(&alice.id, &git::refname!("master"));
+    let expected = alice
+        .storage
+        .repository(acme)
+        .unwrap()
+        .reference_oid(&alice.id, &git::qualified!("refs/heads/master"))
+        .unwrap();
+    assert_eq!(mirror.refname_to_id(refname.as_str()).unwrap(), *expected);
+
+    // Repositories can also be named by alias, or by a prefix of their identifier.
+    for name in [String::from("acme"), acme.canonical()[..8].to_owned()] {
+        let output = std::process::Command::new("git")
+            .args(["-c", "protocol.version=2", "ls-remote"])
+            .arg(format!("git://{addr}/{name}"))
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:?}");
+        assert!(String::from_utf8_lossy(&output.stdout).contains(&expected.to_string()));
+    }
+
+    // Unknown repositories are not served.
+    let output = std::process::Command::new("git")
+        .args(["-c", "protocol.version=2", "ls-remote"])
+        .arg(format!("git://{addr}/{}", arbitrary::gen::<RepoId>(1)))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_git_connection_limit() {
+    logger::init(log::Level::Debug);
+
+    let tmp = tempfile::tempdir().unwrap();
+    let mut alice = Node::init(
+        tmp.path(),
+        Config {
+            git_daemon: Some(([127, 0, 0, 1], 0).into()),
+            git_http: Some(([127, 0, 0, 1], 0).into()),
+            limits: Limits {
+                connection: ConnectionLimits {
+                    git: 1,
+                    ..ConnectionLimits::default()
+                },
+                ..Limits::default()
+            },
+            ..Config::test(Alias::new("alice"))
+        },
+    );
+    let acme = alice.project("acme", "");
+    let alice = alice.spawn();
+    let addr = alice.git_daemon.unwrap();
+    let ls_remote = || {
+        std::process::Command::new("git")
+            .args(["-c", "protocol.version=2", "ls-remote"])
+            .arg(format!("git://{addr}/{acme}"))
+            .output()
+            .unwrap()
+    };
+
+    // A client that doesn't send anything holds its connection slot.
+    let idle = net::TcpStream::connect(addr).unwrap();
+    // N.b. the client may not see the error message, since the connection is closed
+    // without reading its request.
+    assert!(!ls_remote().status.success());
+
+    // The limit is shared with the HTTP gateway.
+    let output = std::process::Command::new("git")
+        .args(["-c", "protocol.version=2", "ls-remote"])
+        .arg(format!(
+            "http://{}/{}.git",
+            alice.git_http.unwrap(),
+            acme.canonical()
+        ))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+
+    // The slot is freed once the client disconnects.
+    drop(idle);
+    let mut attempts = 0;
+    while !ls_remote().status.success() {
+        attempts += 1;
+        assert!(attempts < 50, "connection slot was never freed");
+        thread::sleep(time::Duration::from_millis(100));
+    }
+}
+
+#[test]
+fn test_git_http_clone() {
+    logger::init(log::Level::Debug);
+
+    let tmp = tempfile::tempdir().unwrap();
+    let mut alice = Node::init(
+        tmp.path(),
+        Config {
+            git_http: Some(([127, 0, 0, 1], 0).into()),
+            ..Config::test(Alias::new("alice"))
+        },
+    );
+    let acme = alice.project("acme", "");
+    let alice = alice.spawn();
+    let addr = alice.git_http.unwrap();
+
+    let mirror = tmp.path().join("mirror");
+    let output = std::process::Command::new("git")
+        .args(["-c", "protocol.version=2", "clone", "--mirror"])
+        .arg(format!("http://{addr}/{}.git", acme.canonical()))
+        .arg(&mirror)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+
+    let mirror = git::raw::Repository::open_bare(&mirror).unwrap();
+    let refname = git::refs::storage::branch_of(&alice.id, &git::refname!("master"));
+    let expected = alice
+        .storage
+        .repository(acme)
+        .unwrap()
+        .reference_oid(&alice.id, &git::qualified!("refs/heads/master"))
+        .unwrap();
+    assert_eq!(mirror.refname_to_id(refname.as_str()).unwrap(), *expected);
+
+    // Unknown repositories are not served.
+    let output = std::process::Command::new("git")
+        .args(["-c", "protocol.version=2", "ls-remote"])
+        .arg(format!(
+            "http://{addr}/{}.git",
+            arbitrary::gen::<RepoId>(1).canonical()
+        ))
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
 #[test]
 fn test_fetch_up_to_date() {
     logger::init(log::Level::Debug);
@@ -596,6 +1236,91 @@ fn test_fetch_up_to_date() {
     );
 }
 
+#[test]
+//
+//     alice -- bob
+//
+fn test_query_refs() {
+    logger::init(log::Level::Debug);
+
+    let tmp = tempfile::tempdir().unwrap();
+    let alice = Node::init(tmp.path(), Config::test(Alias::new("alice")));
+    let mut bob = Node::init(tmp.path(), Config::test(Alias::new("bob")));
+    let acme = bob.project("acme", "");
+
+    let mut alice = alice.spawn();
+    let bob = bob.spawn();
+
+    alice.connect(&bob);
+    converge([&alice, &bob]);
+
+    let sigrefs = git::refs::storage::sigrefs(&bob.id);
+    let missing = git::refname!("refs/heads/missing");
+    let tips = alice
+        .handle
+        .query_refs(
+            acme,
+            bob.id,
+            vec![sigrefs.to_ref_string(), missing.clone()],
+            DEFAULT_TIMEOUT,
+        )
+        .unwrap()
+        .unwrap();
+    let expected = bob
+        .storage
+        .repository(acme)
+        .unwrap()
+        .backend
+        .refname_to_id(sigrefs.as_str())
+        .unwrap();
+
+    assert_eq!(tips[&sigrefs.to_ref_string()], Some(expected.into()));
+    assert_eq!(tips[&missing], None);
+}
+
+#[test]
+fn test_fetch_recover_identity() {
+    logger::init(log::Level::Debug);
+
+    let tmp = tempfile::tempdir().unwrap();
+    let alice = Node::init(tmp.path(), Config::test(Alias::new("alice")));
+    let mut bob = Node::init(tmp.path(), Config::test(Alias::new("bob")));
+    let acme = bob.project("acme", "");
+
+    let mut alice = alice.spawn();
+    let bob = bob.spawn();
+
+    alice.connect(&bob);
+    converge([&alice, &bob]);
+
+    let _ = alice.handle.seed(acme, Scope::All).unwrap();
+    let result = alice.handle.fetch(acme, bob.id, DEFAULT_TIMEOUT).unwrap();
+    assert!(result.is_success());
+
+    let repo = alice.storage.repository(acme).unwrap();
+    let refname = radicle::storage::git::CANONICAL_IDENTITY.as_str();
+    let id = repo.identity_head().unwrap();
+    let (_, head) = repo.head().unwrap();
+
+    // The `rad/id` is missing.
+    repo.backend
+        .find_reference(refname)
+        .unwrap()
+        .delete()
+        .unwrap();
+    let result = alice.handle.fetch(acme, bob.id, DEFAULT_TIMEOUT).unwrap();
+    assert!(result.is_success());
+    assert_eq!(repo.backend.refname_to_id(refname).unwrap(), *id);
+
+    // The `rad/id` points to something that isn't an identity.
+    repo.backend
+        .reference(refname, *head, true, "detach identity")
+        .unwrap();
+    let result = alice.handle.fetch(acme, bob.id, DEFAULT_TIMEOUT).unwrap();
+    assert!(result.is_success());
+    assert_eq!(repo.backend.refname_to_id(refname).unwrap(), *id);
+}
+
 #[test]
 fn test_fetch_unseeded() {
     logger::init(log::Level::Debug);
@@ -943,6 +1668,99 @@ fn test_non_fastforward_sigrefs() {
     );
 }
 
+#[test]
+/// Alice loses Bob's `rad/sigrefs`, and is then offered outdated refs of Bob by Eve. Since
+/// Alice remembers the last tip she fetched, the rewind is detected and refused.
+fn test_rewound_sigrefs() {
+    logger::init(log::Level::Debug);
+
+    let tmp = tempfile::tempdir().unwrap();
+
+    let alice = Node::init(
+        tmp.path(),
+        Config {
+            refuse_diverged_sigrefs: true,
+            ..Config::test(Alias::new("alice"))
+        },
+    );
+    let mut bob = Node::init(tmp.path(), Config::test(Alias::new("bob")));
+    let eve = Node::init(tmp.path(), Config::test(Alias::new("eve")));
+
+    let rid = bob.project("acme", "");
+
+    let mut alice = alice.spawn();
+    let bob = bob.spawn();
+    let mut eve = eve.spawn();
+
+    alice.handle.seed(rid, Scope::All).unwrap();
+    eve.handle.seed(rid, Scope::All).unwrap();
+
+    alice.connect(&bob);
+    alice.connect(&eve);
+    eve.connect(&bob);
+
+    converge([&alice, &bob, &eve]);
+
+    eve.handle.fetch(rid, bob.id, DEFAULT_TIMEOUT).unwrap();
+    eve.handle
+        .command(service::Command::Disconnect(bob.id))
+        .unwrap();
+
+    bob.issue(
+        rid,
+        "Rewound sigrefs",
+        "Rewound sigrefs are harshing my vibes",
+    );
+    assert_matches!(
+        alice.handle.fetch(rid, bob.id, DEFAULT_TIMEOUT).unwrap(),
+        FetchResult::Success { .. }
+    );
+    let known = alice
+        .storage
+        .repository(rid)
+        .unwrap()
+        .reference_oid(&bob.id, &radicle::storage::refs::SIGREFS_BRANCH)
+        .unwrap();
+    alice
+        .storage
+        .repository_mut(rid)
+        .unwrap()
+        .reference(&bob.id, &radicle::storage::refs::SIGREFS_BRANCH)
+        .unwrap()
+        .delete()
+        .unwrap();
+
+    let events = alice.handle.events();
+    alice.handle.fetch(rid, eve.id, DEFAULT_TIMEOUT).unwrap();
+    events
+        .wait(
+            |e| match e {
+                service::Event::SigrefsDiverged {
+                    namespace,
+                    known: k,
+                    deviation,
+                    refused,
+                    ..
+                } if namespace == &bob.id => {
+                    assert_eq!(*k, known);
+                    assert_eq!(*deviation, radicle::node::sigrefs::Deviation::Rewound);
+                    assert!(refused);
+                    Some(())
+                }
+                _ => None,
+            },
+            DEFAULT_TIMEOUT,
+        )
+        .unwrap();
+
+    assert!(alice
+        .storage
+        .repository(rid)
+        .unwrap()
+        .reference_oid(&bob.id, &radicle::storage::refs::SIGREFS_BRANCH)
+        .is_err());
+}
+
 #[test]
 fn test_outdated_sigrefs() {
     logger::init(log::Level::Debug);
@@ -1039,6 +1857,120 @@ fn test_outdated_sigrefs() {
     assert_eq!(eves_refs_expected, eves_refs);
 }
 
+#[test]
+fn test_repair() {
+    logger::init(log::Level::Debug);
+
+    let tmp = tempfile::tempdir().unwrap();
+
+    let mut alice = Node::init(tmp.path(), Config::test(Alias::new("alice")));
+    let bob = Node::init(tmp.path(), Config::test(Alias::new("bob")));
+    let rid = alice.project("acme", "");
+
+    let mut alice = alice.spawn();
+    let mut bob = bob.spawn();
+
+    bob.handle.seed(rid, Scope::All).unwrap();
+    alice.connect(&bob);
+    converge([&alice, &bob]);
+
+    bob.handle.fetch(rid, alice.id, DEFAULT_TIMEOUT).unwrap();
+    rad::fork(rid, &bob.signer, &bob.storage).unwrap();
+
+    alice.handle.follow(bob.id, None).unwrap();
+    assert_matches!(
+        alice.handle.fetch(rid, bob.id, DEFAULT_TIMEOUT).unwrap(),
+        FetchResult::Success { .. }
+    );
+    assert!(alice.handle.repair(rid).unwrap().is_ok());
+
+    // Move Bob's default branch back, so that it no longer matches his signed refs.
+    let repo = alice.storage.repository(rid).unwrap();
+    let refname = git::refs::storage::branch_of(&bob.id, &git::refname!("master"));
+    let head = repo.backend.refname_to_id(refname.as_str()).unwrap();
+    let parent = repo
+        .backend
+        .find_commit(head)
+        .unwrap()
+        .parent_id(0)
+        .unwrap();
+
+    repo.backend
+        .reference(refname.as_str(), parent, true, "test")
+        .unwrap();
+
+    let repair = alice.handle.repair(rid).unwrap();
+    assert!(repair.is_ok(), "{repair:?}");
+    assert_eq!(repair.removed, vec![bob.id]);
+    assert_eq!(repair.fetched, vec![bob.id]);
+    assert_eq!(repair.fixed.len(), 1);
+    assert_eq!(
+        repo.backend.refname_to_id(refname.as_str()).unwrap(),
+        head,
+        "Bob's branch was restored"
+    );
+}
+
+#[test]
+fn test_bundle_export_import() {
+    let tmp = tempfile::tempdir().unwrap();
+
+    let mut alice = Node::init(tmp.path(), Config::test(Alias::new("alice")));
+    let bob = Node::init(tmp.path(), Config::test(Alias::new("bob")));
+    let rid = alice.project("acme", "");
+    let bundle = tmp.path().join("acme.bundle");
+
+    let alice = alice.spawn();
+    let mut bob = bob.spawn();
+
+    alice.handle.export(rid, bundle.clone()).unwrap();
+
+    let imported = bob.handle.import(bundle.clone()).unwrap();
+    assert_eq!(imported.rid, rid);
+    assert_eq!(imported.remotes, vec![alice.id]);
+    assert!(bob.storage.contains(&rid).unwrap());
+    assert!(bob.handle.import(bundle).is_err());
+}
+
+#[test]
+fn test_init_from_git_repository() {
+    let tmp = tempfile::tempdir().unwrap();
+
+    let alice = Node::init(tmp.path(), Config::test(Alias::new("alice")));
+    let bob = Node::init(tmp.path(), Config::test(Alias::new("bob")));
+    let (working, head) = fixtures::repository(tmp.path().join("acme"));
+
+    let mut alice = alice.spawn();
+    let mut bob = bob.spawn();
+
+    alice.connect(&bob);
+    converge([&alice, &bob]);
+
+    let rid = alice
+        .handle
+        .init(working.path().to_path_buf(), Default::default())
+        .unwrap();
+    let doc = alice.storage.get(rid).unwrap().unwrap();
+    assert_eq!(doc.project().unwrap().name(), "acme");
+    assert_eq!(doc.delegates.first(), &alice.id.into());
+    assert!(alice
+        .handle
+        .init(working.path().to_path_buf(), Default::default())
+        .is_err());
+
+    bob.handle.seed(rid, Scope::All).unwrap();
+    let result = bob.handle.fetch(rid, alice.id, DEFAULT_TIMEOUT).unwrap();
+    assert!(result.is_success());
+
+    let (_, canonical) = bob
+        .storage
+        .repository(rid)
+        .unwrap()
+        .canonical_head()
+        .unwrap();
+    assert_eq!(canonical, head.into());
+}
+
 #[test]
 fn test_outdated_delegate_sigrefs() {
     logger::init(log::Level::Debug);
@@ -1305,3 +2237,237 @@ fn test_catchup_on_refs_announcements() {
     alice.connect(&seed);
     alice.has_remote_of(&acme, &bob_id);
 }
+
+#[test]
+//
+//     alice -- seed -- bob
+//
+fn test_scenario_partition_restart() {
+    logger::init(log::Level::Debug);
+
+    let tmp = tempfile::tempdir().unwrap();
+    let mut alice = Node::init(tmp.path(), Config::test(Alias::new("alice")));
+    let bob = Node::init(tmp.path(), Config::test(Alias::new("bob")));
+    let seed = Node::init(tmp.path(), Config::test(Alias::new("seed")));
+    let acme = alice.project("acme", "");
+    let master = git::refname!("master");
+
+    let mut alice = alice.spawn();
+    let mut bob = bob.spawn();
+    let mut seed = seed.spawn();
+
+    // Bob and the seed track the project, and clone it.
+    bob.handle.seed(acme, Scope::All).unwrap();
+    seed.handle.seed(acme, Scope::All).unwrap();
+
+    alice.connect(&seed);
+    bob.connect(&seed);
+    seed.has_repository(&acme);
+    bob.handle.fetch(acme, seed.id, DEFAULT_TIMEOUT).unwrap();
+    bob.has_remote_of(&acme, &alice.id);
+
+    // Bob is partitioned from the seed, while Alice pushes a new commit.
+    bob.partition(&mut seed);
+
+    let seed_events = seed.handle.events();
+    let head = alice.commit(acme, "Pushed while Bob was away");
+    alice.handle.announce_refs(acme).unwrap();
+
+    seed_events
+        .wait(
+            |e| {
+                matches!(e, service::Event::RefsFetched { remote, rid, .. }
+                    if *remote == alice.id && *rid == acme)
+                .then_some(())
+            },
+            DEFAULT_TIMEOUT,
+        )
+        .unwrap();
+    seed.has_branch_at(&acme, &alice.id, &master, head);
+    assert_ne!(
+        bob.storage
+            .repository(acme)
+            .unwrap()
+            .reference_oid(&alice.id, &git::refs::branch(&master))
+            .unwrap(),
+        head,
+        "Bob should not have received Alice's commit while partitioned"
+    );
+    // Once the partition is healed, Bob catches up via the seed.
+    bob.heal(&mut seed);
+    assert_matches!(
+        bob.handle.fetch(acme, seed.id, DEFAULT_TIMEOUT).unwrap(),
+        FetchResult::Success { .. }
+    );
+    bob.has_branch_at(&acme, &alice.id, &master, head);
+
+    // The seed is restarted, and keeps its storage.
+    let seed = seed.restart();
+    assert_eq!(
+        seed.storage
+            .repository(acme)
+            .unwrap()
+            .reference_oid(&alice.id, &git::refs::branch(&master))
+            .unwrap(),
+        head
+    );
+    bob.connect(&seed);
+    assert_matches!(
+        bob.handle.fetch(acme, seed.id, DEFAULT_TIMEOUT).unwrap(),
+        FetchResult::Success { updated, .. }
+        if updated.iter().all(|u| u.is_skipped())
+    );
+}
+
+#[test]
+fn test_fetch_hooks() {
+    logger::init(log::Level::Debug);
+
+    let tmp = tempfile::tempdir().unwrap();
+    let mut alice = Node::init(tmp.path(), Config::test(Alias::new("alice")));
+    let mut bob = Node::init(tmp.path(), Config::test(Alias::new("bob")));
+    let acme = alice.project("acme", "");
+    let out = tmp.path().join("hook.json");
+
+    bob.config.hooks = vec![Hook {
+        rid: acme,
+        command: "sh".into(),
+        args: vec!["-c".to_owned(), format!("cat > {}", out.display())],
+        timeout: LocalDuration::from_secs(30),
+    }];
+
+    let alice = alice.spawn();
+    let mut bob = bob.spawn();
+    let (send, recv) = crossbeam_channel::unbounded();
+
+    bob.hooks.register(Some(acme), move |fetched| {
+        send.send(fetched.clone()).ok();
+    });
+    bob.handle.seed(acme, Scope::All).unwrap();
+    bob.connect(&alice);
+
+    let result = bob.handle.fetch(acme, alice.id, DEFAULT_TIMEOUT).unwrap();
+    assert!(result.is_success());
+
+    let fetched = recv.recv_timeout(DEFAULT_TIMEOUT).unwrap();
+    assert_eq!(fetched.rid, acme);
+    assert_eq!(fetched.remote, alice.id);
+    assert!(fetched.clone);
+    assert!(!fetched.updated.is_empty());
+
+    // The command hook runs before the callbacks.
+    let json: serde_json::Value = serde_json::from_slice(&std::fs::read(&out).unwrap()).unwrap();
+    assert_eq!(json["rid"], acme.to_string());
+    assert_eq!(json["remote"], alice.id.to_string());
+    assert_eq!(
+        json["updated"].as_array().unwrap().len(),
+        fetched.updated.len()
+    );
+}
+
+#[test]
+fn test_self_check() {
+    logger::init(log::Level::Debug);
+
+    let tmp = tempfile::tempdir().unwrap();
+    let mut alice = Node::init(tmp.path(), Config::test(Alias::new("alice")));
+    let acme = alice.project("acme", "");
+    let alice = alice.spawn();
+
+    let check = alice.handle.self_check(None, None).unwrap();
+    assert_eq!(check.rid, acme);
+    assert_eq!(check.addr, selfcheck::local(alice.addr));
+    assert!(check.refs > 0);
+
+    // Repositories that aren't served fail the check.
+    let unknown = arbitrary::gen::<RepoId>(1);
+    assert_matches!(
+        alice.handle.self_check(None, Some(unknown)),
+        Err(HandleError::SelfCheck(selfcheck::Error::Step(
+            selfcheck::Step::Upload,
+            _
+        )))
+    );
+}
+
+#[test]
+//
+//     [alice bob] -- eve
+//
+fn test_hosted_identities() {
+    logger::init(log::Level::Debug);
+
+    let tmp = tempfile::tempdir().unwrap();
+    let alice = Node::init(tmp.path(), Config::test(Alias::new("alice")));
+    let mut bob = Node::init(tmp.path(), Config::test(Alias::new("bob")));
+    let eve = Node::init(tmp.path(), Config::test(Alias::new("eve")));
+    let acme = bob.project("acme", "");
+
+    // Alice and Bob are hosted by the same runtime, and share its reactor and workers.
+    let proxy = net::SocketAddr::new(net::Ipv4Addr::LOCALHOST.into(), 9050);
+    let (_, signals) = crossbeam_channel::bounded(1);
+    let rt = Runtime::host(
+        NonEmpty::from((alice.identity(), vec![bob.identity()])),
+        proxy,
+        signals,
+    )
+    .unwrap();
+    let mut alice_handle = rt.hosted.head.handle.clone();
+    let bob_handle = rt.hosted.last().handle.clone();
+    let bob_addr = rt.hosted.last().local_addrs[0];
+    let runtime = thread::spawn(move || rt.run());
+    let mut eve = eve.spawn();
+
+    let connect = |handle: &mut Handle| {
+        let events = handle.events();
+
+        handle
+            .connect(bob.id, bob_addr.into(), ConnectOptions::default())
+            .unwrap();
+        events
+            .wait(
+                |e| {
+                    matches!(e, service::Event::PeerConnected { nid } if *nid == bob.id)
+                        .then_some(())
+                },
+                DEFAULT_TIMEOUT,
+            )
+            .unwrap();
+    };
+
+    // Alice fetches from Bob, with both ends of the session on the same reactor.
+    connect(&mut alice_handle);
+    alice_handle.seed(acme, Scope::All).unwrap();
+    let result = alice_handle.fetch(acme, bob.id, DEFAULT_TIMEOUT).unwrap();
+    assert_matches!(result, FetchResult::Success { .. });
+    assert!(alice
+        .storage
+        .repository(acme)
+        .unwrap()
+        .reference(&bob.id, &git::qualified!("refs/heads/master"))
+        .is_ok());
+
+    // Eve fetches from Bob too.
+    connect(&mut eve.handle);
+    eve.handle.seed(acme, Scope::All).unwrap();
+    let result = eve.handle.fetch(acme, bob.id, DEFAULT_TIMEOUT).unwrap();
+    assert_matches!(result, FetchResult::Success { .. });
+
+    // Each identity has its own sessions.
+    let sessions = |handle: &Handle| {
+        handle
+            .sessions()
+            .unwrap()
+            .into_iter()
+            .filter(|s| s.is_connected())
+            .map(|s| s.nid)
+            .collect::<HashSet<_>>()
+    };
+    assert_eq!(sessions(&alice_handle), HashSet::from([bob.id]));
+    assert_eq!(sessions(&bob_handle), HashSet::from([alice.id, eve.id]));
+
+    // Shutting down one identity stops the runtime.
+    drop(eve);
+    bob_handle.shutdown().unwrap();
+    runtime.join().unwrap().unwrap();
+}
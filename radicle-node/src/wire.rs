@@ -1,11 +1,17 @@
-mod frame;
+pub(crate) mod frame;
+pub mod host;
 mod message;
-mod protocol;
+pub(crate) mod protocol;
+pub mod record;
+mod transport;
 mod varint;
 
 pub use frame::StreamId;
+pub use host::{Controller, Host};
 pub use message::{AddressType, MessageType};
-pub use protocol::{Control, Wire, WireReader, WireSession, WireWriter};
+pub use protocol::{
+    Binding, Control, Delivered, Wire, WireReader, WireSession, WireWriter, MAX_STREAM_FRAME_SIZE,
+};
 
 use std::collections::BTreeMap;
 use std::convert::TryFrom;
@@ -24,6 +30,7 @@ use crate::identity::RepoId;
 use crate::node;
 use crate::node::Alias;
 use crate::prelude::*;
+use crate::service;
 use crate::service::filter;
 use crate::storage::refs::Refs;
 use crate::storage::refs::RefsAt;
@@ -56,18 +63,28 @@ pub enum Error {
     InvalidAlias(#[from] node::AliasError),
     #[error("invalid control message with type `{0}`")]
     InvalidControlMessage(u8),
+    #[error("invalid relay message with type `{0}`")]
+    InvalidRelayMessage(u8),
     #[error("invalid protocol version header `{0:x?}`")]
     InvalidProtocolVersion([u8; 4]),
     #[error("invalid onion address: {0}")]
     InvalidOnionAddr(#[from] tor::OnionAddrDecodeError),
     #[error("unknown address type `{0}`")]
     UnknownAddressType(u8),
+    #[error("unknown address label `{0}`")]
+    UnknownAddressLabel(u8),
     #[error("unknown message type `{0}`")]
     UnknownMessageType(u16),
     #[error("unknown info type `{0}`")]
     UnknownInfoType(u16),
+    #[error("invalid flag `{0}`")]
+    InvalidFlag(u8),
+    #[error("invalid sequence number `{0}`: exceeds the maximum of {max}", max = service::message::Sequence::MAX)]
+    InvalidSequence(u64),
     #[error("unexpected bytes")]
     UnexpectedBytes,
+    #[error("invalid compressed message")]
+    InvalidCompressedMessage,
 }
 
 impl Error {
@@ -515,6 +532,8 @@ impl Decode for node::Features {
     fn decode<R: io::Read + ?Sized>(reader: &mut R) -> Result<Self, Error> {
         let features = u64::decode(reader)?;
 
+        // Nb. Unknown feature bits are kept, so that nodes can relay and store the
+        // announcements of newer nodes without losing information.
         Ok(Self::from(features))
     }
 }
@@ -572,6 +591,15 @@ mod tests {
         assert_eq!(deserialize::<u64>(&serialize(&input)).unwrap(), input);
     }
 
+    #[quickcheck]
+    fn prop_features(input: u64) {
+        let features = node::Features::from(input);
+        let decoded = deserialize::<node::Features>(&serialize(&features)).unwrap();
+
+        assert_eq!(decoded, features);
+        assert_eq!(decoded.unknown(), features.without(node::Features::KNOWN));
+    }
+
     #[quickcheck]
     fn prop_string(input: String) -> qcheck::TestResult {
         if input.len() > u8::MAX as usize {
@@ -2,6 +2,8 @@
 #![warn(clippy::missing_docs_in_private_items)]
 use std::{fmt, io};
 
+use radicle::node::NodeId;
+
 use crate::{wire, wire::varint, wire::varint::VarInt, wire::Message, Link};
 
 /// Protocol version strings all start with the magic sequence `rad`, followed
@@ -15,6 +17,11 @@ const CONTROL_CLOSE: u8 = 1;
 /// Control EOF byte.
 const CONTROL_EOF: u8 = 2;
 
+/// Relay forward byte.
+const RELAY_FORWARD: u8 = 0;
+/// Relay deliver byte.
+const RELAY_DELIVER: u8 = 1;
+
 /// Protocol version.
 #[derive(Debug, PartialEq, Eq)]
 pub struct Version([u8; 4]);
@@ -68,6 +75,10 @@ impl wire::Decode for Version {
 /// +-------+----------------------------------+
 /// | 0b101 | Inbound Git stream               |
 /// +-------+----------------------------------+
+/// | 0b110 | Outbound Relay stream            |
+/// +-------+----------------------------------+
+/// | 0b111 | Inbound Relay stream             |
+/// +-------+----------------------------------+
 ///
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct StreamId(VarInt);
@@ -109,6 +120,12 @@ impl StreamId {
         Self(VarInt::from((StreamKind::Git as u8) << 1 | link))
     }
 
+    /// Create a relay identifier.
+    pub fn relay(link: Link) -> Self {
+        let link = if link.is_outbound() { 0 } else { 1 };
+        Self(VarInt::from((StreamKind::Relay as u8) << 1 | link))
+    }
+
     /// Get the nth identifier while preserving the stream type and initiator.
     pub fn nth(self, n: u64) -> Result<Self, varint::BoundsExceeded> {
         let id = *self.0 + (n << 3);
@@ -157,6 +174,8 @@ pub enum StreamKind {
     Gossip = 0b01,
     /// Git stream, used for replication.
     Git = 0b10,
+    /// Relay stream, used to forward opaque data between peers via a bridge node.
+    Relay = 0b11,
 }
 
 impl TryFrom<u8> for StreamKind {
@@ -167,6 +186,7 @@ impl TryFrom<u8> for StreamKind {
             0b00 => Ok(StreamKind::Control),
             0b01 => Ok(StreamKind::Gossip),
             0b10 => Ok(StreamKind::Git),
+            0b11 => Ok(StreamKind::Relay),
             n => Err(n),
         }
     }
@@ -221,6 +241,15 @@ impl Frame {
         }
     }
 
+    /// Create a 'relay' protocol frame.
+    pub fn relay(link: Link, relay: Relay) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            stream: StreamId::relay(link),
+            data: FrameData::Relay(relay),
+        }
+    }
+
     /// Serialize frame to bytes.
     pub fn to_bytes(&self) -> Vec<u8> {
         wire::serialize(self)
@@ -236,6 +265,79 @@ pub enum FrameData {
     Gossip(Message),
     /// Git frame payload. May contain packet-lines as well as packfile data.
     Git(Vec<u8>),
+    /// Relay frame payload.
+    Relay(Relay),
+}
+
+/// Opaque data relayed between two peers via a bridge node.
+///
+/// A peer asks a bridge to forward data to another peer connected to the bridge,
+/// which the bridge then delivers to that peer, tagged with the originating peer.
+/// The data itself is never interpreted by the bridge.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Relay {
+    /// Ask the bridge to forward data to the given peer.
+    Forward {
+        /// The peer to forward the data to.
+        to: NodeId,
+        /// The data to forward.
+        data: Vec<u8>,
+    },
+    /// Data delivered by the bridge on behalf of the given peer.
+    Deliver {
+        /// The peer the data originates from.
+        from: NodeId,
+        /// The delivered data.
+        data: Vec<u8>,
+    },
+}
+
+impl Relay {
+    /// Get the relayed data.
+    pub fn data(&self) -> &[u8] {
+        match self {
+            Self::Forward { data, .. } | Self::Deliver { data, .. } => data,
+        }
+    }
+}
+
+impl wire::Decode for Relay {
+    fn decode<R: io::Read + ?Sized>(reader: &mut R) -> Result<Self, wire::Error> {
+        let command = u8::decode(reader)?;
+        match command {
+            RELAY_FORWARD => {
+                let to = NodeId::decode(reader)?;
+                let data = varint::payload::decode(reader)?;
+                Ok(Relay::Forward { to, data })
+            }
+            RELAY_DELIVER => {
+                let from = NodeId::decode(reader)?;
+                let data = varint::payload::decode(reader)?;
+                Ok(Relay::Deliver { from, data })
+            }
+            other => Err(wire::Error::InvalidRelayMessage(other)),
+        }
+    }
+}
+
+impl wire::Encode for Relay {
+    fn encode<W: io::Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        let mut n = 0;
+
+        match self {
+            Self::Forward { to, data } => {
+                n += RELAY_FORWARD.encode(writer)?;
+                n += to.encode(writer)?;
+                n += varint::payload::encode(data, writer)?;
+            }
+            Self::Deliver { from, data } => {
+                n += RELAY_DELIVER.encode(writer)?;
+                n += from.encode(writer)?;
+                n += varint::payload::encode(data, writer)?;
+            }
+        }
+        Ok(n)
+    }
 }
 
 /// A control message sent over a control stream.
@@ -337,6 +439,15 @@ impl wire::Decode for Frame {
                 let data = varint::payload::decode(reader)?;
                 Ok(Frame::git(stream, data))
             }
+            Ok(StreamKind::Relay) => {
+                let relay = Relay::decode(reader)?;
+                let frame = Frame {
+                    version,
+                    stream,
+                    data: FrameData::Relay(relay),
+                };
+                Ok(frame)
+            }
             Err(n) => Err(wire::Error::InvalidStreamKind(n)),
         }
     }
@@ -352,6 +463,7 @@ impl wire::Encode for Frame {
             FrameData::Control(ctrl) => ctrl.encode(writer)?,
             FrameData::Git(data) => varint::payload::encode(data, writer)?,
             FrameData::Gossip(msg) => varint::payload::encode(&wire::serialize(msg), writer)?,
+            FrameData::Relay(relay) => relay.encode(writer)?,
         };
 
         Ok(n)
@@ -379,5 +491,25 @@ mod test {
         assert_eq!(StreamId::git(Link::Inbound), StreamId(VarInt(0b101)));
         assert_eq!(StreamId::control(Link::Inbound), StreamId(VarInt(0b001)));
         assert_eq!(StreamId::gossip(Link::Inbound), StreamId(VarInt(0b011)));
+
+        assert_eq!(StreamId(VarInt(0b110)).kind().unwrap(), StreamKind::Relay);
+        assert_eq!(StreamId::relay(Link::Outbound), StreamId(VarInt(0b110)));
+        assert_eq!(StreamId::relay(Link::Inbound), StreamId(VarInt(0b111)));
+    }
+
+    #[test]
+    fn test_relay_frame() {
+        let to = NodeId::from([7; 32]);
+        let frame = Frame::relay(
+            Link::Outbound,
+            Relay::Forward {
+                to,
+                data: b"opaque".to_vec(),
+            },
+        );
+        let decoded: Frame = wire::deserialize(&frame.to_bytes()).unwrap();
+
+        assert_eq!(decoded, frame);
+        assert_eq!(decoded.stream.kind(), Ok(StreamKind::Relay));
     }
 }
@@ -0,0 +1,241 @@
+//! Hosting of several node identities on a single reactor.
+//!
+//! Each identity has its own [`Wire`], with its own service, listeners and sessions. The
+//! [`Host`] is the reactor handler: it hands the events of each resource to the wire that
+//! registered it, and the commands sent with a [`Controller`] to the wire of the identity
+//! they are for.
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use crossbeam_channel as chan;
+use cyphernet::Ecdh;
+use netservices::resource::{ListenerEvent, NetAccept, SessionEvent};
+use nonempty::NonEmpty;
+use reactor::poller::popol::PopolWaker;
+use reactor::{ResourceId, ResourceType, Timestamp};
+
+use radicle::collections::RandomMap;
+use radicle::node::NodeId;
+use radicle::storage::WriteStorage;
+
+use crate::crypto::Signer;
+use crate::service;
+use crate::wire::protocol::Action;
+use crate::wire::transport::Transport;
+use crate::wire::{Control, Wire, WireSession};
+
+/// A command for the wire of one of the hosted identities.
+pub type Command = (NodeId, Control);
+
+/// Sends commands to the wire of one of the identities hosted by a reactor.
+#[derive(Clone)]
+pub struct Controller {
+    local: NodeId,
+    inner: reactor::Controller<Command, PopolWaker>,
+}
+
+impl Controller {
+    /// Create a controller for the wire of the `local` identity.
+    pub fn new(local: NodeId, inner: reactor::Controller<Command, PopolWaker>) -> Self {
+        Self { local, inner }
+    }
+
+    /// Send a command to the wire.
+    pub fn cmd(&self, control: Control) -> Result<(), io::Error> {
+        self.inner.cmd((self.local, control))
+    }
+
+    /// Shut the reactor down. This stops all the identities it hosts.
+    pub fn shutdown(self) -> Result<(), Self> {
+        let local = self.local;
+
+        self.inner
+            .shutdown()
+            .map_err(|inner| Self::new(local, inner))
+    }
+}
+
+/// Reactor handler running the wires of all the hosted identities.
+pub struct Host<D, S, G: Signer + Ecdh> {
+    /// The wire of each identity. The first one is the default identity.
+    wires: NonEmpty<Wire<D, S, G>>,
+    /// The wire owning each registered resource.
+    owners: RandomMap<ResourceId, usize>,
+    /// The wire owning each resource that is being registered.
+    registering: RandomMap<RawFd, usize>,
+    /// The wire whose actions are handed to the reactor first.
+    cursor: usize,
+    /// Signaled once pending actions are processed, if we're shutting down.
+    shutdown: Option<chan::Sender<()>>,
+}
+
+impl<D, S, G> Host<D, S, G>
+where
+    D: service::Store,
+    S: WriteStorage + 'static,
+    G: Signer + Ecdh<Pk = NodeId>,
+{
+    /// Host the given wires. Each must be for a different identity.
+    pub fn new(wires: NonEmpty<Wire<D, S, G>>) -> Self {
+        Self {
+            wires,
+            owners: RandomMap::default(),
+            registering: RandomMap::default(),
+            cursor: 0,
+            shutdown: None,
+        }
+    }
+
+    /// Get the wire owning the given resource.
+    fn owner(&mut self, id: ResourceId) -> Option<&mut Wire<D, S, G>> {
+        let Some(i) = self.owners.get(&id) else {
+            log::error!(target: "wire", "Resource with id={id} is not owned by any hosted identity");
+            return None;
+        };
+        self.wires.get_mut(*i)
+    }
+}
+
+impl<D, S, G> reactor::Handler for Host<D, S, G>
+where
+    D: service::Store + Send,
+    S: WriteStorage + Send + 'static,
+    G: Signer + Ecdh<Pk = NodeId> + Clone + Send,
+{
+    type Listener = NetAccept<WireSession<G>>;
+    type Transport = Transport<WireSession<G>>;
+    type Command = Command;
+
+    fn tick(&mut self, time: Timestamp) {
+        for wire in self.wires.iter_mut() {
+            wire.tick(time);
+        }
+    }
+
+    fn handle_timer(&mut self) {
+        // Timers aren't tied to a resource, so every wire is woken up.
+        for wire in self.wires.iter_mut() {
+            wire.handle_timer();
+        }
+    }
+
+    fn handle_listener_event(
+        &mut self,
+        id: ResourceId,
+        event: ListenerEvent<WireSession<G>>,
+        time: Timestamp,
+    ) {
+        if let Some(wire) = self.owner(id) {
+            wire.handle_listener_event(id, event, time);
+        }
+    }
+
+    fn handle_transport_event(
+        &mut self,
+        id: ResourceId,
+        event: SessionEvent<WireSession<G>>,
+        time: Timestamp,
+    ) {
+        if let Some(wire) = self.owner(id) {
+            wire.handle_transport_event(id, event, time);
+        }
+    }
+
+    fn handle_registered(&mut self, fd: RawFd, id: ResourceId, typ: ResourceType) {
+        let Some(i) = self.registering.remove(&fd) else {
+            log::warn!(target: "wire", "Unknown resource registered with fd={fd} and id={id}");
+            return;
+        };
+        self.owners.insert(id, i);
+
+        if let Some(wire) = self.wires.get_mut(i) {
+            wire.handle_registered(fd, id, typ);
+        }
+    }
+
+    fn handle_command(&mut self, (local, control): Self::Command) {
+        if let Control::Shutdown(done) = control {
+            // The reactor is shared, so every identity lets its peers know.
+            for wire in self.wires.iter_mut() {
+                let (notice, _) = chan::bounded(1);
+                wire.handle_command(Control::Shutdown(notice));
+            }
+            self.shutdown = Some(done);
+
+            return;
+        }
+        match self.wires.iter_mut().find(|w| w.nid() == local) {
+            Some(wire) => wire.handle_command(control),
+            None => {
+                log::error!(target: "wire", "Dropping command for {local}: identity is not hosted")
+            }
+        }
+    }
+
+    fn handle_error(
+        &mut self,
+        err: reactor::Error<NetAccept<WireSession<G>>, Transport<WireSession<G>>>,
+    ) {
+        let id = match &err {
+            reactor::Error::ListenerDisconnect(id, _) => *id,
+            reactor::Error::TransportDisconnect(id, _) => *id,
+            // Polling errors aren't specific to an identity. They are handled by the
+            // default one.
+            reactor::Error::Poll(_) => return self.wires.head.handle_error(err),
+        };
+        if let Some(wire) = self.owner(id) {
+            wire.handle_error(err);
+        }
+        self.owners.remove(&id);
+    }
+
+    fn handover_listener(&mut self, id: ResourceId, listener: Self::Listener) {
+        if let Some(wire) = self.owner(id) {
+            wire.handover_listener(id, listener);
+        }
+        self.owners.remove(&id);
+    }
+
+    fn handover_transport(&mut self, id: ResourceId, transport: Self::Transport) {
+        if let Some(wire) = self.owner(id) {
+            wire.handover_transport(id, transport);
+        }
+        self.owners.remove(&id);
+    }
+}
+
+impl<D, S, G> Iterator for Host<D, S, G>
+where
+    D: service::Store,
+    S: WriteStorage + 'static,
+    G: Signer + Ecdh<Pk = NodeId>,
+{
+    type Item = Action<G>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Take turns, so that one identity's actions don't hold the others' back.
+        let len = self.wires.len();
+        for _ in 0..len {
+            let i = self.cursor;
+            self.cursor = (i + 1) % len;
+
+            let Some(action) = self.wires.get_mut(i).and_then(|w| w.next()) else {
+                continue;
+            };
+            match &action {
+                Action::RegisterListener(listener) => {
+                    self.registering.insert(listener.as_raw_fd(), i);
+                }
+                Action::RegisterTransport(transport) => {
+                    self.registering.insert(transport.as_raw_fd(), i);
+                }
+                _ => {}
+            }
+            return Some(action);
+        }
+        if let Some(done) = self.shutdown.take() {
+            done.send(()).ok();
+        }
+        None
+    }
+}
@@ -1,9 +1,12 @@
+use std::io::{Read as _, Write as _};
 use std::{io, mem, net};
 
 use byteorder::{NetworkEndian, ReadBytesExt};
 use cyphernet::addr::{tor, Addr, HostName, NetAddr};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
 use radicle::git::Oid;
-use radicle::node::Address;
+use radicle::node::{address, Address};
 
 use crate::prelude::*;
 use crate::service::message::*;
@@ -21,6 +24,16 @@ pub enum MessageType {
     Ping = 10,
     Pong = 12,
     Info = 14,
+    Rendezvous = 16,
+    ConnectTo = 18,
+    Disconnect = 20,
+    HeartbeatAnnouncement = 22,
+    SequencedAnnouncement = 24,
+    InventoryDiff = 26,
+    Compressed = 28,
+    InventoryPage = 30,
+    LabeledAnnouncement = 32,
+    RelayedAnnouncement = 34,
 }
 
 impl From<MessageType> for u16 {
@@ -41,6 +54,16 @@ impl TryFrom<u16> for MessageType {
             10 => Ok(MessageType::Ping),
             12 => Ok(MessageType::Pong),
             14 => Ok(MessageType::Info),
+            16 => Ok(MessageType::Rendezvous),
+            18 => Ok(MessageType::ConnectTo),
+            20 => Ok(MessageType::Disconnect),
+            22 => Ok(MessageType::HeartbeatAnnouncement),
+            24 => Ok(MessageType::SequencedAnnouncement),
+            26 => Ok(MessageType::InventoryDiff),
+            28 => Ok(MessageType::Compressed),
+            30 => Ok(MessageType::InventoryPage),
+            32 => Ok(MessageType::LabeledAnnouncement),
+            34 => Ok(MessageType::RelayedAnnouncement),
             _ => Err(other),
         }
     }
@@ -51,20 +74,105 @@ impl Message {
     pub const MAX_SIZE: wire::Size =
         wire::Size::MAX - (mem::size_of::<MessageType>() as wire::Size);
 
+    /// Announcements larger than this many bytes are compressed, when sent to peers that
+    /// support it.
+    pub const COMPRESSION_THRESHOLD: usize = 4 * 1024;
+
     pub fn type_id(&self) -> u16 {
         match self {
             Self::Subscribe { .. } => MessageType::Subscribe,
-            Self::Announcement(Announcement { message, .. }) => match message {
-                AnnouncementMessage::Node(_) => MessageType::NodeAnnouncement,
-                AnnouncementMessage::Inventory(_) => MessageType::InventoryAnnouncement,
-                AnnouncementMessage::Refs(_) => MessageType::RefsAnnouncement,
-            },
+            Self::Announcement(Announcement { hops: 1.., .. })
+            | Self::InventoryDiff(InventoryDiff { hops: 1.., .. }) => {
+                MessageType::RelayedAnnouncement
+            }
+            Self::Announcement(Announcement {
+                labels: Some(_), ..
+            }) => MessageType::LabeledAnnouncement,
+            Self::Announcement(Announcement {
+                sequence: Some(_), ..
+            }) => MessageType::SequencedAnnouncement,
+            Self::Announcement(Announcement { message, .. }) => message.type_id(),
+            Self::InventoryDiff(_) => MessageType::InventoryDiff,
             Self::Info(_) => MessageType::Info,
             Self::Ping { .. } => MessageType::Ping,
             Self::Pong { .. } => MessageType::Pong,
+            Self::Rendezvous(_) => MessageType::Rendezvous,
+            Self::ConnectTo(_) => MessageType::ConnectTo,
+            Self::Disconnect { .. } => MessageType::Disconnect,
+            Self::Compressed(_) => MessageType::Compressed,
         }
         .into()
     }
+
+    /// Compress this message for the wire, if it's an announcement larger than
+    /// [`Message::COMPRESSION_THRESHOLD`], and compressing it makes it smaller.
+    /// Otherwise, the message is returned as-is.
+    pub fn compressed(self) -> Self {
+        if !matches!(self, Self::Announcement(_) | Self::InventoryDiff(_)) {
+            return self;
+        }
+        let mut raw = Vec::new();
+        match self.encode(&mut raw) {
+            Ok(n) if n > Self::COMPRESSION_THRESHOLD => {}
+            _ => return self,
+        }
+        let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        let Ok(data) = encoder.write_all(&raw).and_then(|_| encoder.finish()) else {
+            return self;
+        };
+        // Compressed messages are prefixed with their type and length.
+        if data.len() + 2 * mem::size_of::<wire::Size>() >= raw.len() {
+            return self;
+        }
+        Self::Compressed(Compressed {
+            message: Box::new(self),
+            data,
+        })
+    }
+
+    /// Decode a compressed message, without its type. The decompressed message can't
+    /// itself be compressed.
+    fn decode_compressed<R: io::Read + ?Sized>(reader: &mut R) -> Result<Self, wire::Error> {
+        let len = wire::Size::decode(reader)?;
+        let mut data = vec![0; len as usize];
+        reader.read_exact(&mut data)?;
+
+        // Guard against decompression bombs, by never inflating more than the maximum
+        // size of a message.
+        let mut raw = Vec::new();
+        DeflateDecoder::new(data.as_slice())
+            .take(wire::Size::MAX as u64 + 1)
+            .read_to_end(&mut raw)
+            .map_err(|_| wire::Error::InvalidCompressedMessage)?;
+
+        if raw.len() > wire::Size::MAX as usize {
+            return Err(wire::Error::InvalidSize {
+                expected: wire::Size::MAX as usize,
+                actual: raw.len(),
+            });
+        }
+        if raw.starts_with(&u16::from(MessageType::Compressed).to_be_bytes()) {
+            return Err(wire::Error::InvalidCompressedMessage);
+        }
+        match wire::deserialize(&raw) {
+            // All the data is there, so if some is missing, the message is invalid.
+            Err(wire::Error::Io(_)) => Err(wire::Error::InvalidCompressedMessage),
+            result => result,
+        }
+    }
+}
+
+impl AnnouncementMessage {
+    /// Type of the unsequenced announcement carrying this message.
+    pub fn type_id(&self) -> MessageType {
+        match self {
+            AnnouncementMessage::Node(_) => MessageType::NodeAnnouncement,
+            AnnouncementMessage::Inventory(_) => MessageType::InventoryAnnouncement,
+            AnnouncementMessage::Refs(_) => MessageType::RefsAnnouncement,
+            AnnouncementMessage::Heartbeat(_) => MessageType::HeartbeatAnnouncement,
+            AnnouncementMessage::InventoryPage(_) => MessageType::InventoryPage,
+        }
+    }
 }
 
 impl netservices::Frame for Message {
@@ -131,6 +239,8 @@ impl wire::Encode for AnnouncementMessage {
             Self::Node(ann) => ann.encode(writer),
             Self::Inventory(ann) => ann.encode(writer),
             Self::Refs(ann) => ann.encode(writer),
+            Self::Heartbeat(ann) => ann.encode(writer),
+            Self::InventoryPage(ann) => ann.encode(writer),
         }
     }
 }
@@ -184,12 +294,88 @@ impl wire::Decode for InventoryAnnouncement {
     }
 }
 
+impl wire::Encode for InventoryPage {
+    fn encode<W: io::Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        let mut n = 0;
+
+        n += self.inventory.encode(writer)?;
+        n += self.page.encode(writer)?;
+        n += self.pages.encode(writer)?;
+        n += self.timestamp.encode(writer)?;
+
+        Ok(n)
+    }
+}
+
+impl wire::Decode for InventoryPage {
+    fn decode<R: std::io::Read + ?Sized>(reader: &mut R) -> Result<Self, wire::Error> {
+        let inventory = BoundedVec::decode(reader)?;
+        let page = u16::decode(reader)?;
+        let pages = u16::decode(reader)?;
+        let timestamp = Timestamp::decode(reader)?;
+
+        Ok(Self {
+            inventory,
+            page,
+            pages,
+            timestamp,
+        })
+    }
+}
+
+impl wire::Encode for InventoryHash {
+    fn encode<W: io::Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        writer.write_all(self.as_bytes())?;
+
+        Ok(self.as_bytes().len())
+    }
+}
+
+impl wire::Decode for InventoryHash {
+    fn decode<R: std::io::Read + ?Sized>(reader: &mut R) -> Result<Self, wire::Error> {
+        let hash: [u8; 32] = Decode::decode(reader)?;
+
+        Ok(Self::from(hash))
+    }
+}
+
+impl wire::Encode for HeartbeatAnnouncement {
+    fn encode<W: io::Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        let mut n = 0;
+
+        n += self.uptime.encode(writer)?;
+        n += self.storage_free.encode(writer)?;
+        n += self.max_repo_size.encode(writer)?;
+        n += self.timestamp.encode(writer)?;
+
+        Ok(n)
+    }
+}
+
+impl wire::Decode for HeartbeatAnnouncement {
+    fn decode<R: std::io::Read + ?Sized>(reader: &mut R) -> Result<Self, wire::Error> {
+        let uptime = u64::decode(reader)?;
+        let storage_free = u64::decode(reader)?;
+        let max_repo_size = u64::decode(reader)?;
+        let timestamp = Timestamp::decode(reader)?;
+
+        Ok(Self {
+            uptime,
+            storage_free,
+            max_repo_size,
+            timestamp,
+        })
+    }
+}
+
 /// The type tracking the different variants of [`Info`] for encoding and
 /// decoding purposes.
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum InfoType {
     RefsAlreadySynced = 1,
+    ObservedAddress = 2,
+    MissingInventory = 3,
 }
 
 impl From<InfoType> for u16 {
@@ -204,6 +390,8 @@ impl TryFrom<u16> for InfoType {
     fn try_from(other: u16) -> Result<Self, Self::Error> {
         match other {
             1 => Ok(Self::RefsAlreadySynced),
+            2 => Ok(Self::ObservedAddress),
+            3 => Ok(Self::MissingInventory),
             n => Err(n),
         }
     }
@@ -219,6 +407,8 @@ impl From<&Info> for InfoType {
     fn from(info: &Info) -> Self {
         match info {
             Info::RefsAlreadySynced { .. } => Self::RefsAlreadySynced,
+            Info::ObservedAddress { .. } => Self::ObservedAddress,
+            Info::MissingInventory { .. } => Self::MissingInventory,
         }
     }
 }
@@ -232,6 +422,12 @@ impl wire::Encode for Info {
                 n += rid.encode(writer)?;
                 n += at.encode(writer)?;
             }
+            Info::ObservedAddress { addr } => {
+                n += addr.encode(writer)?;
+            }
+            Info::MissingInventory { node } => {
+                n += node.encode(writer)?;
+            }
         }
 
         Ok(n)
@@ -249,6 +445,16 @@ impl wire::Decode for Info {
 
                 Ok(Self::RefsAlreadySynced { rid, at })
             }
+            Ok(InfoType::ObservedAddress) => {
+                let addr = Address::decode(reader)?;
+
+                Ok(Self::ObservedAddress { addr })
+            }
+            Ok(InfoType::MissingInventory) => {
+                let node = NodeId::decode(reader)?;
+
+                Ok(Self::MissingInventory { node })
+            }
             Err(other) => Err(wire::Error::UnknownInfoType(other)),
         }
     }
@@ -268,14 +474,82 @@ impl wire::Encode for Message {
                 n += since.encode(writer)?;
                 n += until.encode(writer)?;
             }
+            // Relayed messages are prefixed with their hop count, and followed by the
+            // announcement or inventory diff they carry, as it's sent without it.
+            Self::Announcement(ann) if ann.hops > 0 => {
+                n += ann.hops.encode(writer)?;
+                n += Self::Announcement(Announcement {
+                    hops: 0,
+                    ..ann.clone()
+                })
+                .encode(writer)?;
+            }
+            Self::InventoryDiff(diff) if diff.hops > 0 => {
+                n += diff.hops.encode(writer)?;
+                n += Self::InventoryDiff(InventoryDiff {
+                    hops: 0,
+                    ..diff.clone()
+                })
+                .encode(writer)?;
+            }
+            Self::Announcement(
+                ann @ Announcement {
+                    labels: Some(labels),
+                    ..
+                },
+            ) => {
+                // Labeled announcements are prefixed with the address labels, and followed
+                // by the announcement they label, as it's sent without them.
+                n += labels.encode(writer)?;
+                n += Self::Announcement(ann.clone().unlabeled()).encode(writer)?;
+            }
             Self::Announcement(Announcement {
                 node,
                 message,
                 signature,
+                sequence,
+                labels: None,
+                hops: _,
             }) => {
+                // Sequenced announcements are prefixed with the type of the announcement
+                // they carry, and suffixed with the sequence number.
+                if sequence.is_some() {
+                    n += u16::from(message.type_id()).encode(writer)?;
+                }
                 n += node.encode(writer)?;
                 n += message.encode(writer)?;
                 n += signature.encode(writer)?;
+
+                if let Some(Sequence { seq, signature }) = sequence {
+                    n += seq.encode(writer)?;
+                    n += signature.encode(writer)?;
+                }
+            }
+            Self::InventoryDiff(InventoryDiff {
+                node,
+                base,
+                removed,
+                added,
+                timestamp,
+                signature,
+                sequence,
+                hops: _,
+            }) => {
+                n += node.encode(writer)?;
+                n += base.encode(writer)?;
+                n += removed.encode(writer)?;
+                n += added.encode(writer)?;
+                n += timestamp.encode(writer)?;
+                n += signature.encode(writer)?;
+
+                // The sequence number is optional, and prefixed with a flag.
+                if let Some(Sequence { seq, signature }) = sequence {
+                    n += 1u8.encode(writer)?;
+                    n += seq.encode(writer)?;
+                    n += signature.encode(writer)?;
+                } else {
+                    n += 0u8.encode(writer)?;
+                }
             }
             Self::Info(info) => {
                 n += info.encode(writer)?;
@@ -287,6 +561,19 @@ impl wire::Encode for Message {
             Self::Pong { zeroes } => {
                 n += zeroes.encode(writer)?;
             }
+            Self::Rendezvous(Rendezvous { node }) => {
+                n += node.encode(writer)?;
+            }
+            Self::ConnectTo(ConnectTo { node, addr }) => {
+                n += node.encode(writer)?;
+                n += addr.encode(writer)?;
+            }
+            Self::Disconnect { reason } => {
+                n += (*reason as u8).encode(writer)?;
+            }
+            Self::Compressed(Compressed { data, .. }) => {
+                n += data.as_slice().encode(writer)?;
+            }
         }
 
         if n > wire::Size::MAX as usize {
@@ -315,41 +602,54 @@ impl wire::Decode for Message {
                     until,
                 }))
             }
-            Ok(MessageType::NodeAnnouncement) => {
-                let node = NodeId::decode(reader)?;
-                let message = NodeAnnouncement::decode(reader)?.into();
-                let signature = Signature::decode(reader)?;
-
-                Ok(Announcement {
-                    node,
-                    message,
-                    signature,
-                }
-                .into())
+            Ok(
+                t @ (MessageType::NodeAnnouncement
+                | MessageType::InventoryAnnouncement
+                | MessageType::RefsAnnouncement
+                | MessageType::HeartbeatAnnouncement
+                | MessageType::InventoryPage),
+            ) => Ok(Announcement::decode_as(t, reader)?.into()),
+            Ok(MessageType::SequencedAnnouncement) => {
+                Ok(Announcement::decode_sequenced(reader)?.into())
             }
-            Ok(MessageType::InventoryAnnouncement) => {
-                let node = NodeId::decode(reader)?;
-                let message = InventoryAnnouncement::decode(reader)?.into();
-                let signature = Signature::decode(reader)?;
-
-                Ok(Announcement {
-                    node,
-                    message,
-                    signature,
-                }
-                .into())
+            Ok(MessageType::LabeledAnnouncement) => {
+                Ok(Announcement::decode_labeled(reader)?.into())
             }
-            Ok(MessageType::RefsAnnouncement) => {
-                let node = NodeId::decode(reader)?;
-                let message = RefsAnnouncement::decode(reader)?.into();
-                let signature = Signature::decode(reader)?;
-
-                Ok(Announcement {
-                    node,
-                    message,
-                    signature,
+            Ok(MessageType::InventoryDiff) => Ok(Self::InventoryDiff(
+                InventoryDiff::decode_unrelayed(reader)?,
+            )),
+            Ok(MessageType::RelayedAnnouncement) => {
+                let hops = u8::decode(reader)?;
+                let type_id = reader.read_u16::<NetworkEndian>()?;
+                // Relayed messages can't be nested, or compressed.
+                match MessageType::try_from(type_id) {
+                    Ok(
+                        t @ (MessageType::NodeAnnouncement
+                        | MessageType::InventoryAnnouncement
+                        | MessageType::RefsAnnouncement
+                        | MessageType::HeartbeatAnnouncement
+                        | MessageType::InventoryPage),
+                    ) => Ok(Announcement {
+                        hops,
+                        ..Announcement::decode_as(t, reader)?
+                    }
+                    .into()),
+                    Ok(MessageType::SequencedAnnouncement) => Ok(Announcement {
+                        hops,
+                        ..Announcement::decode_sequenced(reader)?
+                    }
+                    .into()),
+                    Ok(MessageType::LabeledAnnouncement) => Ok(Announcement {
+                        hops,
+                        ..Announcement::decode_labeled(reader)?
+                    }
+                    .into()),
+                    Ok(MessageType::InventoryDiff) => Ok(Self::InventoryDiff(InventoryDiff {
+                        hops,
+                        ..InventoryDiff::decode_unrelayed(reader)?
+                    })),
+                    _ => Err(wire::Error::UnknownMessageType(type_id)),
                 }
-                .into())
             }
             Ok(MessageType::Info) => {
                 let info = Info::decode(reader)?;
@@ -364,11 +664,119 @@ impl wire::Decode for Message {
                 let zeroes = ZeroBytes::decode(reader)?;
                 Ok(Self::Pong { zeroes })
             }
+            Ok(MessageType::Rendezvous) => {
+                let node = NodeId::decode(reader)?;
+                Ok(Self::Rendezvous(Rendezvous { node }))
+            }
+            Ok(MessageType::ConnectTo) => {
+                let node = NodeId::decode(reader)?;
+                let addr = Address::decode(reader)?;
+                Ok(Self::ConnectTo(ConnectTo { node, addr }))
+            }
+            Ok(MessageType::Disconnect) => {
+                let reason = DisconnectCode::from(u8::decode(reader)?);
+                Ok(Self::Disconnect { reason })
+            }
+            Ok(MessageType::Compressed) => Self::decode_compressed(reader),
             Err(other) => Err(wire::Error::UnknownMessageType(other)),
         }
     }
 }
 
+impl Announcement {
+    /// Decode an unsequenced announcement of the given type.
+    fn decode_as<R: std::io::Read + ?Sized>(
+        type_id: MessageType,
+        reader: &mut R,
+    ) -> Result<Self, wire::Error> {
+        let node = NodeId::decode(reader)?;
+        let message = match type_id {
+            MessageType::NodeAnnouncement => NodeAnnouncement::decode(reader)?.into(),
+            MessageType::InventoryAnnouncement => InventoryAnnouncement::decode(reader)?.into(),
+            MessageType::RefsAnnouncement => RefsAnnouncement::decode(reader)?.into(),
+            MessageType::HeartbeatAnnouncement => HeartbeatAnnouncement::decode(reader)?.into(),
+            MessageType::InventoryPage => InventoryPage::decode(reader)?.into(),
+            other => return Err(wire::Error::UnknownMessageType(other.into())),
+        };
+        let signature = Signature::decode(reader)?;
+
+        Ok(Self {
+            node,
+            message,
+            signature,
+            sequence: None,
+            labels: None,
+            hops: 0,
+        })
+    }
+
+    /// Decode a sequenced announcement, without its type.
+    fn decode_sequenced<R: std::io::Read + ?Sized>(reader: &mut R) -> Result<Self, wire::Error> {
+        let type_id = reader.read_u16::<NetworkEndian>()?;
+        let mut ann = match MessageType::try_from(type_id) {
+            Ok(
+                t @ (MessageType::NodeAnnouncement
+                | MessageType::InventoryAnnouncement
+                | MessageType::RefsAnnouncement
+                | MessageType::HeartbeatAnnouncement
+                | MessageType::InventoryPage),
+            ) => Announcement::decode_as(t, reader)?,
+            _ => return Err(wire::Error::UnknownMessageType(type_id)),
+        };
+        ann.sequence = Some(Sequence::decode(reader)?);
+
+        Ok(ann)
+    }
+
+    /// Decode a labeled announcement, without its type.
+    fn decode_labeled<R: std::io::Read + ?Sized>(reader: &mut R) -> Result<Self, wire::Error> {
+        let labels = Labels::decode(reader)?;
+        let type_id = reader.read_u16::<NetworkEndian>()?;
+        // Only node announcements are labeled.
+        let mut ann = match MessageType::try_from(type_id) {
+            Ok(t @ MessageType::NodeAnnouncement) => Announcement::decode_as(t, reader)?,
+            Ok(MessageType::SequencedAnnouncement) => Announcement::decode_sequenced(reader)?,
+            _ => return Err(wire::Error::UnknownMessageType(type_id)),
+        };
+        if !matches!(ann.message, AnnouncementMessage::Node(_)) {
+            return Err(wire::Error::UnknownMessageType(
+                ann.message.type_id().into(),
+            ));
+        }
+        ann.labels = Some(labels);
+
+        Ok(ann)
+    }
+}
+
+impl InventoryDiff {
+    /// Decode an inventory diff, without its type. The diff isn't relayed.
+    fn decode_unrelayed<R: std::io::Read + ?Sized>(reader: &mut R) -> Result<Self, wire::Error> {
+        let node = NodeId::decode(reader)?;
+        let base = InventoryHash::decode(reader)?;
+        let removed = BoundedVec::decode(reader)?;
+        let added = BoundedVec::decode(reader)?;
+        let timestamp = Timestamp::decode(reader)?;
+        let signature = Signature::decode(reader)?;
+        let sequence = match u8::decode(reader)? {
+            0 => None,
+            1 => Some(Sequence::decode(reader)?),
+            other => return Err(wire::Error::InvalidFlag(other)),
+        };
+
+        Ok(Self {
+            node,
+            base,
+            removed,
+            added,
+            timestamp,
+            signature,
+            sequence,
+            hops: 0,
+        })
+    }
+}
+
 impl wire::Encode for Address {
     fn encode<W: std::io::Write + ?Sized>(&self, writer: &mut W) -> Result<usize, std::io::Error> {
         let mut n = 0;
@@ -434,6 +842,52 @@ impl wire::Decode for Address {
     }
 }
 
+impl wire::Encode for address::Label {
+    fn encode<W: std::io::Write + ?Sized>(&self, writer: &mut W) -> Result<usize, std::io::Error> {
+        u8::from(*self).encode(writer)
+    }
+}
+
+impl wire::Decode for address::Label {
+    fn decode<R: std::io::Read + ?Sized>(reader: &mut R) -> Result<Self, wire::Error> {
+        let label = reader.read_u8()?;
+
+        address::Label::try_from(label).map_err(wire::Error::UnknownAddressLabel)
+    }
+}
+
+impl wire::Encode for Labels {
+    fn encode<W: std::io::Write + ?Sized>(&self, writer: &mut W) -> Result<usize, std::io::Error> {
+        let mut n = 0;
+
+        n += self.labels.encode(writer)?;
+        n += self.signature.encode(writer)?;
+
+        Ok(n)
+    }
+}
+
+impl wire::Decode for Labels {
+    fn decode<R: std::io::Read + ?Sized>(reader: &mut R) -> Result<Self, wire::Error> {
+        let labels = BoundedVec::decode(reader)?;
+        let signature = Signature::decode(reader)?;
+
+        Ok(Self { labels, signature })
+    }
+}
+
+impl wire::Decode for Sequence {
+    fn decode<R: std::io::Read + ?Sized>(reader: &mut R) -> Result<Self, wire::Error> {
+        let seq = u64::decode(reader)?;
+        if seq > Sequence::MAX {
+            return Err(wire::Error::InvalidSequence(seq));
+        }
+        let signature = Signature::decode(reader)?;
+
+        Ok(Self { seq, signature })
+    }
+}
+
 impl wire::Encode for ZeroBytes {
     fn encode<W: io::Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
         let mut n = (self.len() as u16).encode(writer)?;
@@ -463,6 +917,8 @@ mod tests {
 
     use crate::deserializer::Deserializer;
     use crate::test::arbitrary;
+    use crate::test::assert_matches;
+    use crate::test::golden;
     use crate::wire::{self, Encode};
 
     #[test]
@@ -474,7 +930,7 @@ mod tests {
             refs: BoundedVec::collect_from(&mut refs.into_iter()),
             timestamp: arbitrary::gen(1),
         });
-        let ann = ann.signed(&signer);
+        let ann = ann.signed(&signer).sequenced(u64::MAX, &signer);
         let msg = Message::Announcement(ann);
         let data = wire::serialize(&msg);
 
@@ -508,13 +964,94 @@ mod tests {
             timestamp: arbitrary::gen(1),
             nonce: u64::MAX,
         });
-        let ann = ann.signed(&signer);
+        let ann = ann.signed(&signer).sequenced(u64::MAX, &signer);
         let msg = Message::Announcement(ann);
         let data = wire::serialize(&msg);
 
         assert!(data.len() < wire::Size::MAX as usize);
     }
 
+    /// A refs announcement that compresses well, since all remotes have the same refs.
+    fn compressible() -> Message {
+        let signer = MockSigner::default();
+        let refs = vec![arbitrary::gen::<RefsAt>(1); REF_REMOTE_LIMIT];
+        let ann = AnnouncementMessage::Refs(RefsAnnouncement {
+            rid: arbitrary::gen(1),
+            refs: BoundedVec::collect_from(&mut refs.into_iter()),
+            timestamp: arbitrary::gen(1),
+        });
+        Message::Announcement(ann.signed(&signer))
+    }
+
+    /// Encode the given bytes as a compressed message.
+    fn compressed(raw: &[u8]) -> Vec<u8> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::best());
+        encoder.write_all(raw).unwrap();
+
+        let mut data = Vec::new();
+        u16::from(MessageType::Compressed)
+            .encode(&mut data)
+            .unwrap();
+        encoder
+            .finish()
+            .unwrap()
+            .as_slice()
+            .encode(&mut data)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_compressed() {
+        let msg = compressible();
+        let raw = wire::serialize(&msg);
+        let compressed = msg.clone().compressed();
+        assert_matches!(compressed, Message::Compressed(_));
+
+        let data = wire::serialize(&compressed);
+        assert!(data.len() < raw.len());
+        assert_eq!(wire::deserialize::<Message>(&data).unwrap(), msg);
+    }
+
+    #[test]
+    fn test_compressed_threshold() {
+        let ann = AnnouncementMessage::Refs(RefsAnnouncement {
+            rid: arbitrary::gen(1),
+            refs: BoundedVec::collect_from(&mut [arbitrary::gen::<RefsAt>(1)].into_iter()),
+            timestamp: arbitrary::gen(1),
+        });
+        let msg = Message::Announcement(ann.signed(&MockSigner::default()));
+        assert!(wire::serialize(&msg).len() <= Message::COMPRESSION_THRESHOLD);
+        assert_eq!(msg.clone().compressed(), msg);
+    }
+
+    #[test]
+    fn test_compressed_bomb() {
+        // A ping that inflates to much more than the maximum message size.
+        let mut raw = Vec::new();
+        u16::from(MessageType::Ping).encode(&mut raw).unwrap();
+        raw.resize(wire::Size::MAX as usize * 16, 0);
+
+        assert_matches!(
+            wire::deserialize::<Message>(&compressed(&raw)),
+            Err(wire::Error::InvalidSize { .. })
+        );
+    }
+
+    #[test]
+    fn test_compressed_nested() {
+        let raw = wire::serialize(&compressible().compressed());
+
+        assert_matches!(
+            wire::deserialize::<Message>(&compressed(&raw)),
+            Err(wire::Error::InvalidCompressedMessage)
+        );
+        assert_matches!(
+            wire::deserialize::<Message>(&compressed(&[0xff; 8])),
+            Err(wire::Error::UnknownMessageType(0xffff))
+        );
+    }
+
     #[test]
     fn test_pingpong_encode_max_size() {
         let mut buf = Vec::new();
@@ -553,6 +1090,218 @@ mod tests {
             .expect_err("pong should exceed max message size");
     }
 
+    /// Get the golden encoding of a fixture message.
+    fn golden(name: &str) -> Vec<u8> {
+        golden::read(name).unwrap_or_else(|e| {
+            panic!(
+                "Failed to read fixture `{name}`: {e}; if the wire format was changed on \
+                 purpose, regenerate fixtures with \
+                 `cargo run -p radicle-node --features test --bin wire-fixtures`"
+            )
+        })
+    }
+
+    #[test]
+    fn test_golden_messages() {
+        for (name, msg) in golden::messages() {
+            let data = golden(name);
+            let decoded = wire::deserialize::<Message>(&data)
+                .unwrap_or_else(|e| panic!("Fixture `{name}` failed to decode: {e}"));
+
+            if let Message::Compressed(Compressed { message, .. }) = msg {
+                // Compression output isn't guaranteed to be stable across library
+                // versions, so only check that the golden data decodes to the original.
+                assert_eq!(decoded, *message, "fixture `{name}` decoded differently");
+            } else {
+                assert_eq!(decoded, msg, "fixture `{name}` decoded differently");
+                assert!(
+                    wire::serialize(&msg) == data,
+                    "Fixture `{name}` encoded differently; if the wire format was changed on \
+                     purpose, regenerate fixtures with \
+                     `cargo run -p radicle-node --features test --bin wire-fixtures`"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_golden_messages_coverage() {
+        let fixtures = golden::messages();
+        let types = fixtures
+            .iter()
+            .map(|(name, _)| {
+                let data = golden(name);
+                u16::from_be_bytes([data[0], data[1]])
+            })
+            .collect::<std::collections::BTreeSet<_>>();
+
+        // Every message type has a fixture.
+        for ty in (0..=u8::MAX as u16).filter(|ty| MessageType::try_from(*ty).is_ok()) {
+            assert!(types.contains(&ty), "message type {ty} has no fixture");
+        }
+        // Every fixture file is still in use.
+        for entry in std::fs::read_dir(golden::dir()).unwrap() {
+            let path = entry.unwrap().path();
+            let stem = path.file_stem().unwrap().to_str().unwrap();
+
+            assert!(
+                fixtures.iter().any(|(name, _)| *name == stem),
+                "fixture {} is stale",
+                path.display()
+            );
+        }
+    }
+
+    #[test]
+    fn test_golden_messages_unsequenced() {
+        // Nodes that don't support sequence numbers receive announcements without them,
+        // which must be encoded exactly as before sequence numbers were introduced.
+        for (sequenced, unsequenced) in [
+            ("node-announcement-sequenced", "node-announcement"),
+            ("refs-announcement-sequenced", "refs-announcement"),
+        ] {
+            let Message::Announcement(ann) =
+                wire::deserialize::<Message>(&golden(sequenced)).unwrap()
+            else {
+                panic!("fixture `{sequenced}` is not an announcement");
+            };
+            assert!(ann.verify());
+            assert_eq!(
+                wire::serialize(&Message::Announcement(ann.unsequenced())),
+                golden(unsequenced)
+            );
+        }
+    }
+
+    #[test]
+    fn test_golden_messages_unlabeled() {
+        // Nodes that don't support address labels receive node announcements without them,
+        // which must be encoded exactly as before labels were introduced.
+        let Message::Announcement(ann) =
+            wire::deserialize::<Message>(&golden("node-announcement-labeled")).unwrap()
+        else {
+            panic!("fixture `node-announcement-labeled` is not an announcement");
+        };
+        assert!(ann.verify());
+        assert_eq!(
+            wire::serialize(&Message::Announcement(ann.clone().unlabeled())),
+            golden("node-announcement-sequenced")
+        );
+        assert_eq!(
+            wire::serialize(&Message::Announcement(ann.unlabeled().unsequenced())),
+            golden("node-announcement")
+        );
+    }
+
+    #[test]
+    fn test_golden_messages_unrelayed() {
+        // Nodes that don't support hop counts receive announcements without them, which
+        // must be encoded exactly as before hop counts were introduced.
+        for (relayed, unrelayed) in [
+            ("refs-announcement-relayed", "refs-announcement-sequenced"),
+            ("inventory-diff-relayed", "inventory-diff"),
+        ] {
+            let msg = match wire::deserialize::<Message>(&golden(relayed)).unwrap() {
+                Message::Announcement(ann) => {
+                    assert!(ann.verify());
+                    assert_eq!(ann.hops, 2);
+                    Message::Announcement(Announcement { hops: 0, ..ann })
+                }
+                Message::InventoryDiff(diff) => {
+                    assert_eq!(diff.hops, 3);
+                    Message::InventoryDiff(InventoryDiff { hops: 0, ..diff })
+                }
+                _ => panic!("fixture `{relayed}` is not relayed"),
+            };
+            assert_eq!(wire::serialize(&msg), golden(unrelayed));
+        }
+    }
+
+    #[test]
+    fn test_relayed_announcement_decode() {
+        let ann = AnnouncementMessage::from(RefsAnnouncement {
+            rid: arbitrary::gen(1),
+            refs: BoundedVec::new(),
+            timestamp: Timestamp::from(0),
+        })
+        .signed(&MockSigner::default());
+        let data = wire::serialize(&Message::Announcement(ann.relayed()));
+
+        // Relayed messages can't be nested.
+        let mut bytes = u16::from(MessageType::RelayedAnnouncement)
+            .to_be_bytes()
+            .to_vec();
+        bytes.push(1);
+        bytes.extend(&data);
+
+        assert_matches!(
+            wire::deserialize::<Message>(&bytes),
+            Err(wire::Error::UnknownMessageType(34))
+        );
+        // Nor carry messages other than announcements.
+        let mut bytes = data[..3].to_vec();
+        bytes.extend(wire::serialize(&Message::Pong {
+            zeroes: ZeroBytes::new(0),
+        }));
+
+        assert_matches!(
+            wire::deserialize::<Message>(&bytes),
+            Err(wire::Error::UnknownMessageType(12))
+        );
+    }
+
+    #[test]
+    fn test_labeled_announcement_decode() {
+        let signer = MockSigner::default();
+        let labels = BoundedVec::collect_from(&mut [address::Label::Manual].into_iter());
+        let refs = AnnouncementMessage::from(RefsAnnouncement {
+            rid: arbitrary::gen(1),
+            refs: BoundedVec::new(),
+            timestamp: Timestamp::from(0),
+        })
+        .signed(&signer);
+
+        // Only node announcements can be labeled.
+        for ann in [refs.clone(), refs.sequenced(1, &signer)] {
+            let mut bytes = u16::from(MessageType::LabeledAnnouncement)
+                .to_be_bytes()
+                .to_vec();
+            bytes.extend(wire::serialize(&Labels {
+                labels: labels.clone(),
+                signature: ann.signature,
+            }));
+            bytes.extend(wire::serialize(&Message::Announcement(ann)));
+
+            assert_matches!(
+                wire::deserialize::<Message>(&bytes),
+                Err(wire::Error::UnknownMessageType(_))
+            );
+        }
+    }
+
+    #[test]
+    fn test_sequenced_announcement_decode() {
+        let signer = MockSigner::default();
+        let ann = AnnouncementMessage::from(RefsAnnouncement {
+            rid: arbitrary::gen(1),
+            refs: BoundedVec::new(),
+            timestamp: Timestamp::from(0),
+        })
+        .signed(&signer);
+
+        let msg = Message::Announcement(ann.clone().sequenced(Sequence::MAX, &signer));
+        assert_eq!(
+            wire::deserialize::<Message>(&wire::serialize(&msg)).unwrap(),
+            msg
+        );
+        // Sequence numbers that can't be stored are rejected.
+        let msg = Message::Announcement(ann.sequenced(Sequence::MAX + 1, &signer));
+        assert_matches!(
+            wire::deserialize::<Message>(&wire::serialize(&msg)),
+            Err(wire::Error::InvalidSequence(seq)) if seq == Sequence::MAX + 1
+        );
+    }
+
     #[quickcheck]
     fn prop_message_encode_decode(message: Message) {
         assert_eq!(
@@ -5,6 +5,7 @@
 use std::collections::hash_map::Entry;
 use std::collections::VecDeque;
 use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::{io, net, time};
 
@@ -14,23 +15,33 @@ use cyphernet::addr::{HostName, InetHost, NetAddr};
 use cyphernet::encrypt::noise::{HandshakePattern, Keyset, NoiseState};
 use cyphernet::proxy::socks5;
 use cyphernet::{Digest, EcSk, Ecdh, Sha256};
-use localtime::LocalTime;
-use netservices::resource::{ListenerEvent, NetAccept, NetTransport, SessionEvent};
+use localtime::{LocalDuration, LocalTime};
+use netservices::resource::{ListenerEvent, NetAccept, SessionEvent};
 use netservices::session::{ProtocolArtifact, Socks5Session};
 use netservices::{NetConnection, NetProtocol, NetReader, NetWriter};
 use reactor::{ResourceId, ResourceType, Timestamp};
 
 use radicle::collections::RandomMap;
-use radicle::node::NodeId;
+use radicle::identity::RepoId;
+use radicle::node;
+use radicle::node::config::{BridgeLimits, RateLimit};
+use radicle::node::{NodeId, TaskDirection, TaskId};
 use radicle::storage::WriteStorage;
 
 use crate::crypto::Signer;
 use crate::prelude::Deserializer;
+use crate::runtime::watchdog::Progress;
 use crate::service;
-use crate::service::io::Io;
-use crate::service::{session, DisconnectReason, Service};
+use crate::service::io::{Io, Route, SignRequest};
+use crate::service::limitter::TokenBucket;
+use crate::service::message::Announcement;
+use crate::service::snapshot::Snapshot;
+use crate::service::{session, DisconnectCode, DisconnectReason, Message, Service};
 use crate::wire::frame;
-use crate::wire::frame::{Frame, FrameData, StreamId};
+use crate::wire::frame::{Frame, FrameData, Relay, StreamId};
+use crate::wire::record;
+use crate::wire::record::Recorder;
+use crate::wire::transport::{Backlog, Transport};
 use crate::wire::Encode;
 use crate::worker;
 use crate::worker::{ChannelEvent, FetchRequest, FetchResult, Task, TaskResult};
@@ -52,6 +63,29 @@ pub const DEFAULT_CONNECTION_TIMEOUT: time::Duration = time::Duration::from_secs
 /// Default time to wait when dialing a connection, before the remote is considered unreachable.
 pub const DEFAULT_DIAL_TIMEOUT: time::Duration = time::Duration::from_secs(6);
 
+/// Maximum size of the git data carried by a single stream frame. Larger worker writes are
+/// split, so that frames of other streams can be interleaved with them.
+pub const MAX_STREAM_FRAME_SIZE: usize = 16 * 1024;
+
+/// Maximum amount of stream data handed to a peer's transport per reactor iteration.
+/// The rest stays queued, so that gossip messages aren't stuck behind a bulk transfer.
+pub const MAX_STREAM_FLUSH_SIZE: usize = 256 * 1024;
+
+/// Maximum amount of data buffered by a peer's transport for stream data to be handed to it.
+/// Gossip messages are handed to the transport as soon as they are written, and so never wait
+/// behind more than this amount of stream data, however slow the connection is.
+pub const MAX_TRANSPORT_BACKLOG: usize = 128 * 1024;
+
+/// Time to wait before sending more queued stream data, when the limit was reached.
+pub const STREAM_FLUSH_INTERVAL: LocalDuration = LocalDuration::from_millis(1);
+
+/// Maximum amount of relayed data queued for a peer. Data relayed to a peer that isn't
+/// keeping up is dropped beyond this.
+pub const MAX_RELAY_QUEUE_SIZE: usize = 1024 * 1024;
+
+/// Maximum number of relayed messages buffered for a subscriber that isn't keeping up.
+pub const RELAYED_CHANNEL_CAPACITY: usize = 64;
+
 /// Control message used internally between workers, users, and the service.
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
@@ -60,8 +94,79 @@ pub enum Control {
     User(service::Command),
     /// Message from a worker to the service.
     Worker(TaskResult),
+    /// Announcement of ours, signed by the signing thread.
+    Signed(Announcement, Route),
     /// Flush data in the given stream to the remote.
     Flush { remote: NodeId, stream: StreamId },
+    /// Let connected peers know we're shutting down. Signals the sender once the
+    /// messages are handed to the reactor.
+    Shutdown(chan::Sender<()>),
+    /// Get the fetches and uploads running on workers.
+    Tasks(chan::Sender<Vec<node::Task>>),
+    /// Abort a running fetch or upload by closing its stream.
+    /// Signals whether the task was found.
+    Abort(TaskId, chan::Sender<bool>),
+    /// Get the channel binding of the session with the given peer, if connected.
+    Binding(NodeId, chan::Sender<Option<Binding>>),
+    /// Wake the service thread up. Used by the watchdog.
+    Wake,
+    /// Relay data to a peer via the given bridge.
+    /// Signals whether the data was queued.
+    Relay {
+        via: NodeId,
+        to: NodeId,
+        data: Vec<u8>,
+        resp: chan::Sender<bool>,
+    },
+    /// Subscribe to the data relayed to us by bridges. The subscription is active once the
+    /// receiver is returned.
+    Relayed(chan::Sender<chan::Receiver<Delivered>>),
+}
+
+/// Data delivered to us by a bridge, on behalf of another peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delivered {
+    /// The peer the data originates from.
+    pub from: NodeId,
+    /// The bridge that delivered the data.
+    pub via: NodeId,
+    /// The delivered data.
+    pub data: Vec<u8>,
+}
+
+/// Channel binding of an established session.
+///
+/// This is the hash of the Noise handshake transcript, which covers both static keys
+/// and the ephemeral keys of the session. Both ends of a session derive the same
+/// value, and a man-in-the-middle relaying the connection to another node can't,
+/// since it ends up with two distinct handshakes. Signing the binding thus proves
+/// that the signer is the remote end of *this* session.
+///
+/// Note that the binding doesn't cover the TCP endpoints, since the addresses seen by
+/// either side differ whenever there is a NAT or proxy in between.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Binding([u8; Sha256::OUTPUT_LEN]);
+
+impl Binding {
+    /// Get the binding bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<[u8; Sha256::OUTPUT_LEN]> for Binding {
+    fn from(hash: [u8; Sha256::OUTPUT_LEN]) -> Self {
+        Self(hash)
+    }
+}
+
+impl std::fmt::Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for b in self.0 {
+            write!(f, "{b:02x}")?;
+        }
+        Ok(())
+    }
 }
 
 /// Peer session type.
@@ -72,7 +177,7 @@ pub type WireReader = NetReader<Socks5Session<net::TcpStream>>;
 pub type WireWriter<G> = NetWriter<NoiseState<G, Sha256>, Socks5Session<net::TcpStream>>;
 
 /// Reactor action.
-type Action<G> = reactor::Action<NetAccept<WireSession<G>>, NetTransport<WireSession<G>>>;
+pub(crate) type Action<G> = reactor::Action<NetAccept<WireSession<G>>, Transport<WireSession<G>>>;
 
 /// A worker stream.
 struct Stream {
@@ -82,14 +187,35 @@ struct Stream {
     sent_bytes: usize,
     /// Data received.
     received_bytes: usize,
+    /// Worker task running on this stream.
+    task: TaskId,
+    /// Repository transferred, if known.
+    rid: Option<RepoId>,
+    /// Direction of the transfer.
+    direction: TaskDirection,
+    /// Time at which the stream was opened.
+    since: LocalTime,
+    /// Data written by the worker that is yet to be sent to the remote.
+    queue: VecDeque<ChannelEvent>,
 }
 
 impl Stream {
-    fn new(channels: worker::Channels) -> Self {
+    fn new(
+        channels: worker::Channels,
+        task: TaskId,
+        rid: Option<RepoId>,
+        direction: TaskDirection,
+        since: LocalTime,
+    ) -> Self {
         Self {
             channels,
             sent_bytes: 0,
             received_bytes: 0,
+            task,
+            rid,
+            direction,
+            since,
+            queue: VecDeque::new(),
         }
     }
 }
@@ -104,15 +230,35 @@ struct Streams {
     link: Link,
     /// Sequence number used to compute the next stream id.
     seq: u64,
+    /// Streams with queued data, in the order they will be sent.
+    ready: VecDeque<StreamId>,
+    /// Buffers shared with the stream workers.
+    pool: worker::BufferPool,
+    /// Data buffered by the peer's transport.
+    backlog: Backlog,
+    /// Fetches waiting for a stream, because too many are open.
+    pending: VecDeque<FetchRequest>,
+    /// Relay messages waiting to be sent. These are sent along with stream data, on the
+    /// relay stream.
+    relay: VecDeque<Relay>,
+    /// Amount of relayed data queued.
+    relay_size: usize,
 }
 
 impl Streams {
-    /// Create a new [`Streams`] object, passing the connection link.
-    fn new(link: Link) -> Self {
+    /// Create a new [`Streams`] object, passing the connection link, the buffer pool
+    /// shared with workers and the backlog of the peer's transport.
+    fn new(link: Link, pool: worker::BufferPool, backlog: Backlog) -> Self {
         Self {
             streams: RandomMap::default(),
             link,
             seq: 0,
+            ready: VecDeque::new(),
+            pool,
+            backlog,
+            pending: VecDeque::new(),
+            relay: VecDeque::new(),
+            relay_size: 0,
         }
     }
 
@@ -126,34 +272,173 @@ impl Streams {
         self.streams.get_mut(stream)
     }
 
-    /// Open a new stream.
-    fn open(&mut self) -> (StreamId, worker::Channels) {
+    /// Open a new stream, to fetch from the remote.
+    fn open(
+        &mut self,
+        task: TaskId,
+        rid: Option<RepoId>,
+        since: LocalTime,
+    ) -> (StreamId, worker::Channels) {
         self.seq += 1;
 
         let id = StreamId::git(self.link)
             .nth(self.seq)
             .expect("Streams::open: too many streams");
         let channels = self
-            .register(id)
+            .register(id, task, rid, TaskDirection::Fetch, since)
             .expect("Streams::open: stream was already open");
 
         (id, channels)
     }
 
     /// Register an open stream.
-    fn register(&mut self, stream: StreamId) -> Option<worker::Channels> {
-        let (wire, worker) = worker::Channels::pair(DEFAULT_CHANNEL_TIMEOUT)
+    fn register(
+        &mut self,
+        stream: StreamId,
+        task: TaskId,
+        rid: Option<RepoId>,
+        direction: TaskDirection,
+        since: LocalTime,
+    ) -> Option<worker::Channels> {
+        let (wire, worker) = worker::Channels::pair(DEFAULT_CHANNEL_TIMEOUT, self.pool.clone())
             .expect("Streams::register: fatal: unable to create channels");
 
         match self.streams.entry(stream) {
             Entry::Vacant(e) => {
-                e.insert(Stream::new(worker));
+                e.insert(Stream::new(worker, task, rid, direction, since));
                 Some(wire)
             }
             Entry::Occupied(_) => None,
         }
     }
 
+    /// Find the stream running the given task.
+    fn find(&self, task: TaskId) -> Option<StreamId> {
+        self.streams
+            .iter()
+            .find(|(_, s)| s.task == task)
+            .map(|(id, _)| *id)
+    }
+
+    /// Queue the data written by the stream's worker, to be sent to the remote.
+    /// Returns `false` if the stream is not known.
+    fn flush(&mut self, stream: StreamId) -> bool {
+        let Some(s) = self.streams.get_mut(&stream) else {
+            return false;
+        };
+        s.queue.extend(s.channels.try_iter());
+
+        if !s.queue.is_empty() && !self.ready.contains(&stream) {
+            self.ready.push_back(stream);
+        }
+        true
+    }
+
+    /// Queue a relay message, to be sent to the remote along with stream data.
+    /// Returns `false` if too much relayed data is already queued.
+    fn relay(&mut self, relay: Relay) -> bool {
+        let size = relay.data().len();
+        if self.relay_size + size > MAX_RELAY_QUEUE_SIZE {
+            return false;
+        }
+        let stream = StreamId::relay(self.link);
+
+        self.relay_size += size;
+        self.relay.push_back(relay);
+
+        if !self.ready.contains(&stream) {
+            self.ready.push_back(stream);
+        }
+        true
+    }
+
+    /// Whether any stream has queued data.
+    fn is_ready(&self) -> bool {
+        !self.ready.is_empty()
+    }
+
+    /// Amount of stream data that can be handed to the peer's transport right now.
+    /// This is zero while the transport is busy sending previous data.
+    fn budget(&self) -> usize {
+        MAX_TRANSPORT_BACKLOG
+            .saturating_sub(self.backlog.len())
+            .min(MAX_STREAM_FLUSH_SIZE)
+    }
+
+    /// Take frames from the streams with queued data, one at a time from each stream, until
+    /// `limit` bytes of data were taken or there is no more data.
+    fn frames(&mut self, limit: usize) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        let mut size = 0;
+
+        while size < limit {
+            let Some(stream) = self.ready.pop_front() else {
+                break;
+            };
+            if stream == StreamId::relay(self.link) {
+                // Relay messages are opaque to us, and so are never split.
+                let Some(relay) = self.relay.pop_front() else {
+                    continue;
+                };
+                size += relay.data().len();
+                self.relay_size -= relay.data().len();
+                frames.push(Frame::relay(self.link, relay));
+
+                if !self.relay.is_empty() {
+                    self.ready.push_back(stream);
+                }
+                continue;
+            }
+            let Some(s) = self.streams.get_mut(&stream) else {
+                // Stream was closed in the meantime.
+                continue;
+            };
+            let Some(event) = s.queue.pop_front() else {
+                continue;
+            };
+            let frame = match event {
+                ChannelEvent::Data(mut data) => {
+                    if data.len() > MAX_STREAM_FRAME_SIZE {
+                        let rest = data.split_off(MAX_STREAM_FRAME_SIZE);
+                        s.queue.push_front(ChannelEvent::Data(rest));
+                    }
+                    size += data.len();
+                    s.sent_bytes += data.len();
+
+                    Frame::git(stream, data)
+                }
+                ChannelEvent::Close => Frame::control(self.link, frame::Control::Close { stream }),
+                ChannelEvent::Eof => Frame::control(self.link, frame::Control::Eof { stream }),
+            };
+            frames.push(frame);
+
+            if !s.queue.is_empty() {
+                self.ready.push_back(stream);
+            }
+        }
+        frames
+    }
+
+    /// Return the buffer of a sent frame to the pool, so that workers can reuse it.
+    fn recycle(&self, frame: Frame) {
+        if let FrameData::Git(data) = frame.data {
+            self.pool.put(data);
+        }
+    }
+
+    /// Whether `limit` streams or more are open.
+    fn is_full(&self, limit: usize) -> bool {
+        self.streams.len() >= limit
+    }
+
+    /// Take the next fetch waiting for a stream, if fewer than `limit` streams are open.
+    fn next_pending(&mut self, limit: usize) -> Option<FetchRequest> {
+        if self.is_full(limit) {
+            return None;
+        }
+        self.pending.pop_front()
+    }
+
     /// Unregister an open stream.
     fn unregister(&mut self, stream: &StreamId) -> Option<Stream> {
         self.streams.remove(stream)
@@ -177,6 +462,10 @@ struct Outbound {
     addr: NetAddr<HostName>,
     /// Remote Node ID.
     nid: NodeId,
+    /// Time at which the connection was attempted.
+    since: LocalTime,
+    /// Data buffered by the transport.
+    backlog: Backlog,
 }
 
 /// The initial state of an inbound peer before handshake is completed.
@@ -186,6 +475,10 @@ struct Inbound {
     id: Option<ResourceId>,
     /// Remote address.
     addr: NetAddr<HostName>,
+    /// Time at which the connection was accepted.
+    since: LocalTime,
+    /// Data buffered by the transport.
+    backlog: Backlog,
 }
 
 /// Peer connection state machine.
@@ -197,6 +490,7 @@ enum Peer {
         addr: NetAddr<HostName>,
         link: Link,
         nid: NodeId,
+        binding: Binding,
         inbox: Deserializer<Frame>,
         streams: Streams,
     },
@@ -235,13 +529,75 @@ impl Peer {
     }
 
     /// Connected peer.
-    fn connected(nid: NodeId, addr: NetAddr<HostName>, link: Link) -> Self {
+    fn connected(
+        nid: NodeId,
+        addr: NetAddr<HostName>,
+        link: Link,
+        binding: Binding,
+        pool: worker::BufferPool,
+        backlog: Backlog,
+    ) -> Self {
         Self::Connected {
             link,
             addr,
             nid,
+            binding,
             inbox: Deserializer::default(),
-            streams: Streams::new(link),
+            streams: Streams::new(link, pool, backlog),
+        }
+    }
+}
+
+/// Data relayed on behalf of a peer.
+#[derive(Debug)]
+struct Relayed {
+    /// Bandwidth budget, in bytes.
+    bucket: TokenBucket,
+    /// Total bytes relayed.
+    bytes: usize,
+}
+
+/// Accounting for data relayed between peers, when acting as a bridge.
+#[derive(Debug)]
+struct Bridge {
+    /// Bandwidth limit applied to each peer, in bytes.
+    limit: RateLimit,
+    /// Bandwidth budget shared by all peers, in bytes.
+    total: TokenBucket,
+    /// Relayed data, per originating peer.
+    peers: RandomMap<NodeId, Relayed>,
+}
+
+impl Bridge {
+    /// Create a new bridge with the given limits.
+    fn new(limits: BridgeLimits, now: LocalTime) -> Self {
+        Self {
+            limit: limits.peer,
+            total: TokenBucket::new(limits.total.capacity, limits.total.fill_rate, now),
+            peers: RandomMap::default(),
+        }
+    }
+
+    /// Account for data relayed on behalf of the given peer.
+    /// Returns `false` if the peer, or all peers together, have exceeded their bandwidth limit.
+    fn relay(&mut self, nid: NodeId, bytes: usize, now: LocalTime) -> bool {
+        let relayed = self.peers.entry(nid).or_insert_with(|| Relayed {
+            bucket: TokenBucket::new(self.limit.capacity, self.limit.fill_rate, now),
+            bytes: 0,
+        });
+        if !relayed.bucket.has_n(bytes, now) || !self.total.take_n(bytes, now) {
+            return false;
+        }
+        relayed.bucket.take_n(bytes, now);
+        relayed.bytes += bytes;
+
+        true
+    }
+
+    /// Stop accounting for the given peer.
+    fn remove(&mut self, nid: &NodeId) {
+        if let Some(relayed) = self.peers.remove(nid) {
+            log::debug!(target: "wire", "Relayed {} byte(s) on behalf of {nid}", relayed.bytes);
         }
     }
 }
@@ -306,6 +662,8 @@ pub struct Wire<D, S, G: Signer + Ecdh> {
     service: Service<D, S, G>,
     /// Worker pool interface.
     worker: chan::Sender<Task>,
+    /// Signing thread interface.
+    signing: chan::Sender<SignRequest>,
     /// Used for authentication.
     signer: G,
     /// Internal queue of actions to send to the reactor.
@@ -316,10 +674,34 @@ pub struct Wire<D, S, G: Signer + Ecdh> {
     inbound: RandomMap<RawFd, Inbound>,
     /// Listening addresses that are not yet registered.
     listening: RandomMap<RawFd, net::SocketAddr>,
+    /// Addresses we're listening on. Rendezvous connections are made from these.
+    listen_addrs: Vec<net::SocketAddr>,
     /// Peer (established) sessions.
     peers: Peers,
     /// SOCKS5 proxy address.
     proxy: net::SocketAddr,
+    /// Relayed data accounting, if we're acting as a bridge.
+    bridge: Option<Bridge>,
+    /// How long a peer has to complete the handshake.
+    handshake_timeout: LocalDuration,
+    /// Signaled once pending actions are processed, if we're shutting down.
+    shutdown: Option<chan::Sender<()>>,
+    /// Identifier of the last worker task started.
+    task_seq: TaskId,
+    /// Maximum number of concurrent streams per peer.
+    stream_limit: usize,
+    /// Whether queued stream data was sent during the current reactor iteration.
+    flushed: bool,
+    /// Progress of the service thread, for the watchdog.
+    progress: Arc<Progress>,
+    /// Where service state snapshots are saved, if anywhere.
+    snapshot: Option<PathBuf>,
+    /// Session recorder, if sessions are being recorded.
+    recorder: Option<Recorder>,
+    /// Stream data buffers, shared with workers.
+    pool: worker::BufferPool,
+    /// Subscribers to the data relayed to us.
+    relayed: Vec<chan::Sender<Delivered>>,
 }
 
 impl<D, S, G> Wire<D, S, G>
@@ -331,30 +713,80 @@ where
     pub fn new(
         service: Service<D, S, G>,
         worker: chan::Sender<Task>,
+        signing: chan::Sender<SignRequest>,
         signer: G,
         proxy: net::SocketAddr,
+        bridge: Option<BridgeLimits>,
+        handshake_timeout: LocalDuration,
     ) -> Self {
         assert!(service.started().is_some(), "Service must be initialized");
 
+        let now = service.local_time();
         Self {
             service,
             worker,
+            signing,
             signer,
             proxy,
+            bridge: bridge.map(|limits| Bridge::new(limits, now)),
+            handshake_timeout,
+            shutdown: None,
+            task_seq: 0,
+            stream_limit: node::config::DEFAULT_STREAM_CONCURRENCY,
+            flushed: false,
+            progress: Arc::default(),
+            snapshot: None,
+            recorder: None,
+            pool: worker::BufferPool::default(),
+            relayed: Vec::new(),
             actions: VecDeque::new(),
             inbound: RandomMap::default(),
             outbound: RandomMap::default(),
             listening: RandomMap::default(),
+            listen_addrs: Vec::new(),
             peers: Peers(RandomMap::default()),
         }
     }
 
+    /// Save service state snapshots to the given path.
+    pub fn with_snapshot(mut self, path: PathBuf) -> Self {
+        self.snapshot = Some(path);
+        self
+    }
+
+    /// Limit the number of concurrent streams per peer, in either direction.
+    pub fn with_stream_limit(mut self, limit: usize) -> Self {
+        self.stream_limit = limit;
+        self
+    }
+
+    /// Record inbound sessions with the given recorder.
+    pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Progress of the service thread, for the watchdog.
+    pub fn progress(&self) -> Arc<Progress> {
+        self.progress.clone()
+    }
+
+    /// Our node identity.
+    pub fn nid(&self) -> NodeId {
+        *self.signer.public_key()
+    }
+
     pub fn listen(&mut self, socket: NetAccept<WireSession<G>>) {
         self.listening
             .insert(socket.as_raw_fd(), socket.local_addr());
         self.actions.push_back(Action::RegisterListener(socket));
     }
 
+    /// Record a session event, if sessions are being recorded.
+    fn record(&mut self, remote: NodeId, event: record::Event) {
+        record(&mut self.recorder, self.service.local_time(), remote, event);
+    }
+
     fn disconnect(&mut self, id: ResourceId, reason: DisconnectReason) -> Option<(NodeId, Link)> {
         match self.peers.entry(id) {
             Entry::Vacant(_) => {
@@ -383,6 +815,9 @@ where
                     let link = *link;
 
                     streams.shutdown();
+                    if let Some(bridge) = &mut self.bridge {
+                        bridge.remove(&nid);
+                    }
                     e.insert(Peer::Disconnecting {
                         nid: Some(nid),
                         link,
@@ -432,49 +867,365 @@ where
             log::warn!(target: "wire", "Peer {nid} is not connected; ignoring fetch result");
             return;
         };
+        // Start the next fetch waiting for a stream with this peer, if any.
+        if let Some((_, Peer::Connected { streams, .. })) = self.peers.lookup_mut(&nid) {
+            if let Some(fetch) = streams.next_pending(self.stream_limit) {
+                self.fetch(nid, fetch);
+            }
+        }
 
-        // Only call into the service if we initiated this fetch.
         match task.result {
             FetchResult::Initiator { rid, result } => {
                 self.service.fetched(rid, nid, result);
             }
-            FetchResult::Responder { rid, result } => {
+            FetchResult::Blobs { rid, result } => {
+                self.service.blobs_fetched(rid, nid, result);
+            }
+            FetchResult::Refs { rid, result, reply } => {
+                self.service.refs_queried(rid, nid, result, reply);
+            }
+            FetchResult::Responder {
+                rid,
+                result,
+                sent,
+                elapsed,
+                cpu,
+            } => {
                 if let Some(rid) = rid {
-                    if let Some(err) = result.err() {
-                        log::info!(target: "wire", "Peer {nid} failed to fetch {rid} from us: {err}");
-                    } else {
-                        log::info!(target: "wire", "Peer {nid} fetched {rid} from us successfully");
-                    }
+                    self.service.uploaded(rid, nid, sent, elapsed, cpu, result);
+                } else if let Err(err) = result {
+                    log::info!(target: "wire", "Peer {nid} failed to fetch from us: {err}");
                 }
             }
         }
     }
 
+    /// Open a new stream with a peer, and hand it to the worker pool to run a fetch on.
+    fn fetch(&mut self, remote: NodeId, fetch: FetchRequest) {
+        let Some((fd, Peer::Connected { link, streams, .. })) = self.peers.lookup_mut(&remote)
+        else {
+            // Nb. It's possible that a peer is disconnected while an `Io::Fetch`
+            // is in the service's i/o buffer. Since the service may not purge the
+            // buffer on disconnect, we should just ignore i/o actions that don't
+            // have a connected peer.
+            log::error!(target: "wire", "Peer {remote} is not connected: dropping fetch");
+            return;
+        };
+        if streams.is_full(self.stream_limit) {
+            log::debug!(target: "wire", "Too many streams open with {remote}; queueing fetch..");
+
+            streams.pending.push_back(fetch);
+            return;
+        }
+        self.task_seq += 1;
+
+        let (stream, channels) =
+            streams.open(self.task_seq, fetch.rid(), self.service.local_time());
+
+        log::debug!(target: "wire", "Opened new stream with id {stream} for remote {remote}");
+
+        let link = *link;
+        let task = Task {
+            local: *self.signer.public_key(),
+            fetch,
+            stream,
+            channels,
+        };
+
+        if !self.worker.is_empty() {
+            log::warn!(
+                target: "wire",
+                "Worker pool is busy: {} tasks pending, fetch requests may be delayed", self.worker.len()
+            );
+        }
+        if self.worker.send(task).is_err() {
+            log::error!(target: "wire", "Worker pool is disconnected; cannot send fetch request");
+        }
+        self.actions.push_back(Action::Send(
+            fd,
+            Frame::control(link, frame::Control::Open { stream }).to_bytes(),
+        ));
+    }
+
+    /// Save a snapshot of the service state, if enabled.
+    fn save_snapshot(&self, snapshot: &Snapshot) {
+        let Some(path) = &self.snapshot else {
+            return;
+        };
+        match snapshot.save(path) {
+            Ok(()) => log::trace!(
+                target: "wire",
+                "Saved snapshot with {} peer(s) and {} fetch(es)",
+                snapshot.peers.len(),
+                snapshot.fetches.len()
+            ),
+            Err(e) => {
+                log::error!(target: "wire", "Error saving snapshot to {}: {e}", path.display())
+            }
+        }
+    }
+
+    /// Get the channel binding of the session with the given peer.
+    fn binding(&self, nid: &NodeId) -> Option<Binding> {
+        self.peers.0.values().find_map(|peer| match peer {
+            Peer::Connected {
+                nid: n, binding, ..
+            } if n == nid => Some(*binding),
+            _ => None,
+        })
+    }
+
+    /// Get the tasks running on the streams of connected peers.
+    fn tasks(&self) -> Vec<node::Task> {
+        let now = self.service.local_time();
+
+        self.peers
+            .0
+            .values()
+            .filter_map(|peer| match peer {
+                Peer::Connected { nid, streams, .. } => Some((nid, streams)),
+                Peer::Disconnecting { .. } => None,
+            })
+            .flat_map(|(nid, streams)| {
+                streams.streams.values().map(move |s| node::Task {
+                    id: s.task,
+                    rid: s.rid,
+                    remote: *nid,
+                    direction: s.direction,
+                    elapsed: (now - s.since).as_millis() as u64,
+                    sent: s.sent_bytes,
+                    received: s.received_bytes,
+                })
+            })
+            .collect()
+    }
+
+    /// Abort a task by closing its stream. The worker running the task fails on its next
+    /// read, and reports the failure as usual.
+    fn abort(&mut self, task: TaskId) -> bool {
+        let found = self.peers.0.iter_mut().find_map(|(fd, peer)| match peer {
+            Peer::Connected {
+                nid, link, streams, ..
+            } => streams
+                .find(task)
+                .map(|stream| (*fd, *nid, *link, streams, stream)),
+            Peer::Disconnecting { .. } => None,
+        });
+        let Some((fd, nid, link, streams, stream)) = found else {
+            return false;
+        };
+        let Some(s) = streams.unregister(&stream) else {
+            return false;
+        };
+        log::info!(
+            target: "wire",
+            "Aborting task {task} on stream {stream} of {nid} after {} byte(s) sent and {} byte(s) received",
+            s.sent_bytes, s.received_bytes
+        );
+        s.channels.close().ok();
+
+        self.actions.push_back(Action::Send(
+            fd,
+            Frame::control(link, frame::Control::Close { stream }).to_bytes(),
+        ));
+        true
+    }
+
     fn flush(&mut self, remote: NodeId, stream: StreamId) {
-        let Some((fd, peer)) = self.peers.lookup_mut(&remote) else {
+        let Some((_, peer)) = self.peers.lookup_mut(&remote) else {
             log::warn!(target: "wire", "Peer {remote} is not known; ignoring flush");
             return;
         };
-        let Peer::Connected { streams, link, .. } = peer else {
+        let Peer::Connected { streams, .. } = peer else {
             log::warn!(target: "wire", "Peer {remote} is not connected; ignoring flush");
             return;
         };
-        let Some(s) = streams.get_mut(&stream) else {
+        if !streams.flush(stream) {
             log::debug!(target: "wire", "Stream {stream} cannot be found; ignoring flush");
-            return;
-        };
+        }
+    }
 
-        for data in s.channels.try_iter() {
-            let frame = match data {
-                ChannelEvent::Data(data) => {
-                    s.sent_bytes += data.len();
-                    Frame::git(stream, data)
-                }
-                ChannelEvent::Close => Frame::control(*link, frame::Control::Close { stream }),
-                ChannelEvent::Eof => Frame::control(*link, frame::Control::Eof { stream }),
+    /// Send queued stream data to peers. Streams of the same peer take turns, and the amount
+    /// of data sent per peer is limited, so that no stream can hog the connection.
+    ///
+    /// Stream data is only handed to a peer's transport once it has sent most of what it was
+    /// previously handed. Until then, the data stays queued here, and gossip messages, which
+    /// are handed to the transport right away, get ahead of it.
+    fn flush_streams(&mut self) {
+        let mut pending = false;
+
+        for (fd, peer) in self.peers.0.iter_mut() {
+            let Peer::Connected { streams, .. } = peer else {
+                continue;
             };
+            if !streams.is_ready() {
+                continue;
+            }
+            let budget = streams.budget();
+            if budget == 0 {
+                pending = true;
+                continue;
+            }
+            let mut data = Vec::new();
+            for frame in streams.frames(budget) {
+                frame
+                    .encode(&mut data)
+                    .expect("in-memory writes never fail");
+                streams.recycle(frame);
+            }
+            self.actions.push_back(reactor::Action::Send(*fd, data));
+
+            pending |= streams.is_ready();
+        }
+        if pending {
             self.actions
-                .push_back(reactor::Action::Send(fd, frame.to_bytes()));
+                .push_back(reactor::Action::SetTimer(STREAM_FLUSH_INTERVAL.into()));
+        }
+    }
+
+    fn relay(&mut self, remote: NodeId, relay: Relay) {
+        match relay {
+            Relay::Forward { to, data } => {
+                let Some(bridge) = &mut self.bridge else {
+                    log::debug!(target: "wire", "Ignoring relay request from {remote}: bridging is disabled");
+                    return;
+                };
+                if to == remote {
+                    log::debug!(target: "wire", "Ignoring relay request from {remote} to itself");
+                    return;
+                }
+                let Some((_, Peer::Connected { streams, .. })) = self.peers.lookup_mut(&to) else {
+                    log::debug!(target: "wire", "Dropping {} relayed byte(s) from {remote}: peer {to} is not connected", data.len());
+                    return;
+                };
+                if !bridge.relay(remote, data.len(), self.service.local_time()) {
+                    log::warn!(target: "wire", "Dropping {} relayed byte(s) from {remote}: bandwidth limit exceeded", data.len());
+                    return;
+                }
+                let size = data.len();
+
+                // Relayed data is sent like stream data, so that it takes turns with the
+                // streams of the peer, and waits for its transport to have room for it.
+                if !streams.relay(Relay::Deliver { from: remote, data }) {
+                    log::warn!(target: "wire", "Dropping {size} relayed byte(s) from {remote}: too much data queued for {to}");
+                }
+            }
+            Relay::Deliver { from, data } => {
+                log::debug!(target: "wire", "Received {} relayed byte(s) from {from} via {remote}", data.len());
+
+                let delivered = Delivered {
+                    from,
+                    via: remote,
+                    data,
+                };
+                self.relayed.retain(|s| match s.try_send(delivered.clone()) {
+                    Ok(()) => true,
+                    Err(chan::TrySendError::Full(_)) => {
+                        log::warn!(target: "wire", "Dropping relayed data from {from}: subscriber is not keeping up");
+                        true
+                    }
+                    Err(chan::TrySendError::Disconnected(_)) => false,
+                });
+            }
+        }
+    }
+
+    /// Ask a bridge to relay data to one of its peers.
+    /// Returns `false` if the bridge isn't connected or too much data is queued for it.
+    fn forward(&mut self, via: NodeId, to: NodeId, data: Vec<u8>) -> bool {
+        let Some((_, Peer::Connected { streams, .. })) = self.peers.lookup_mut(&via) else {
+            log::debug!(target: "wire", "Unable to relay data to {to}: bridge {via} is not connected");
+            return false;
+        };
+        streams.relay(Relay::Forward { to, data })
+    }
+
+    /// Connect to a peer, from the given local address if any.
+    fn connect(&mut self, node_id: NodeId, addr: node::Address, local: Option<net::SocketAddr>) {
+        if self.peers.connected().any(|(_, id)| id == &node_id) {
+            log::error!(
+                target: "wire",
+                "Attempt to connect to already connected peer {node_id}"
+            );
+            // FIXME: The problem here is the session will stay in "initial" state,
+            // because it can't transition to attempted.
+            return;
+        }
+        self.service.attempted(node_id, addr.clone());
+
+        match dial::<G>(
+            addr.to_inner(),
+            node_id,
+            self.signer.clone(),
+            self.proxy.into(),
+            false,
+            local,
+        )
+        .and_then(|session| Transport::<WireSession<G>>::with_session(session, Link::Outbound))
+        {
+            Ok(transport) => {
+                self.outbound.insert(
+                    transport.as_raw_fd(),
+                    Outbound {
+                        id: None,
+                        nid: node_id,
+                        addr: addr.to_inner(),
+                        since: self.service.local_time(),
+                        backlog: transport.backlog(),
+                    },
+                );
+                log::debug!(
+                    target: "wire",
+                    "Registering outbound transport for {node_id} (fd={})..",
+                    transport.as_raw_fd()
+                );
+                self.actions
+                    .push_back(reactor::Action::RegisterTransport(transport));
+            }
+            Err(err) => {
+                log::error!(target: "wire", "Error establishing connection to {addr}: {err}");
+
+                self.service.disconnected(
+                    node_id,
+                    Link::Outbound,
+                    &DisconnectReason::Dial(Arc::new(err)),
+                );
+            }
+        }
+    }
+
+    /// Let a connected peer know why we're closing the connection.
+    fn goodbye(&mut self, id: ResourceId, link: Link, code: DisconnectCode) {
+        let mut data = Vec::new();
+        Frame::gossip(link, Message::Disconnect { reason: code })
+            .encode(&mut data)
+            .expect("in-memory writes never fail");
+
+        self.actions.push_back(reactor::Action::Send(id, data));
+    }
+
+    /// Disconnect pending peers that haven't completed the handshake in time.
+    ///
+    /// Outbound peers are reported to the service once their transport is dropped, which
+    /// penalizes their address.
+    fn disconnect_stalled_handshakes(&mut self) {
+        let now = self.service.local_time();
+        let timeout = self.handshake_timeout;
+        let stalled = self
+            .inbound
+            .values_mut()
+            .filter(|p| now - p.since >= timeout)
+            .filter_map(|p| p.id.take())
+            .chain(
+                self.outbound
+                    .values_mut()
+                    .filter(|p| now - p.since >= timeout)
+                    .filter_map(|p| p.id.take()),
+            )
+            .collect::<Vec<_>>();
+
+        for id in stalled {
+            self.disconnect(id, DisconnectReason::Session(session::Error::Timeout));
         }
     }
 
@@ -501,16 +1252,20 @@ where
     G: Signer + Ecdh<Pk = NodeId> + Clone + Send,
 {
     type Listener = NetAccept<WireSession<G>>;
-    type Transport = NetTransport<WireSession<G>>;
+    type Transport = Transport<WireSession<G>>;
     type Command = Control;
 
     fn tick(&mut self, time: Timestamp) {
+        self.progress.beat();
+        self.flushed = false;
         self.service
             .tick(LocalTime::from_millis(time.as_millis() as u128));
     }
 
     fn handle_timer(&mut self) {
+        self.progress.start("timer");
         self.service.wake();
+        self.disconnect_stalled_handshakes();
     }
 
     fn handle_listener_event(
@@ -519,6 +1274,8 @@ where
         event: ListenerEvent<WireSession<G>>,
         _: Timestamp,
     ) {
+        self.progress.start("listener event");
+
         match event {
             ListenerEvent::Accepted(connection) => {
                 let Ok(addr) = connection.remote_addr() else {
@@ -541,7 +1298,7 @@ where
                 }
 
                 let session = accept::<G>(addr.clone(), connection, self.signer.clone());
-                let transport = match NetTransport::with_session(session, Link::Inbound) {
+                let transport = match Transport::with_session(session, Link::Inbound) {
                     Ok(transport) => transport,
                     Err(err) => {
                         log::error!(target: "wire", "Failed to create transport for accepted connection: {err}");
@@ -549,7 +1306,15 @@ where
                     }
                 };
 
-                self.inbound.insert(fd, Inbound { id: None, addr });
+                self.inbound.insert(
+                    fd,
+                    Inbound {
+                        id: None,
+                        addr,
+                        since: self.service.local_time(),
+                        backlog: transport.backlog(),
+                    },
+                );
                 self.actions
                     .push_back(reactor::Action::RegisterTransport(transport))
             }
@@ -563,6 +1328,7 @@ where
         match typ {
             ResourceType::Listener => {
                 if let Some(local_addr) = self.listening.remove(&fd) {
+                    self.listen_addrs.push(local_addr);
                     self.service.listening(local_addr);
                 }
             }
@@ -586,6 +1352,8 @@ where
         event: SessionEvent<WireSession<G>>,
         _: Timestamp,
     ) {
+        self.progress.start("transport event");
+
         match event {
             SessionEvent::Established(fd, ProtocolArtifact { state, .. }) => {
                 // SAFETY: With the NoiseXK protocol, there is always a remote static key.
@@ -597,18 +1365,38 @@ where
 
                     return;
                 }
-                let (addr, link) = if let Some(peer) = self.inbound.remove(&fd) {
-                    (peer.addr, Link::Inbound)
+                let (addr, link, backlog) = if let Some(peer) = self.inbound.remove(&fd) {
+                    (peer.addr, Link::Inbound, peer.backlog)
                 } else if let Some(peer) = self.outbound.remove(&fd) {
-                    assert_eq!(nid, peer.nid);
-                    (peer.addr, Link::Outbound)
+                    if nid != peer.nid {
+                        log::warn!(
+                            target: "wire",
+                            "Handshake with {} (id={id}) authenticated unexpected node {nid}, disconnecting..",
+                            peer.nid
+                        );
+                        // The service only knows about the node we dialed.
+                        self.peers.insert(
+                            id,
+                            Peer::Disconnecting {
+                                nid: Some(peer.nid),
+                                link: Link::Outbound,
+                                reason: DisconnectReason::UnexpectedNode,
+                            },
+                        );
+                        self.actions.push_back(Action::UnregisterTransport(id));
+
+                        return;
+                    }
+                    (peer.addr, Link::Outbound, peer.backlog)
                 } else {
                     log::error!(target: "wire", "Session for {nid} (id={id}) not found");
                     return;
                 };
+                let binding = Binding::from(state.handshake_hash);
+
                 log::debug!(
                     target: "wire",
-                    "Session established with {nid} (id={id}) (fd={fd}) ({})",
+                    "Session established with {nid} (id={id}) (fd={fd}) ({}) (binding={binding})",
                     if link.is_inbound() { "inbound" } else { "outbound" }
                 );
 
@@ -688,21 +1476,43 @@ where
                         // We disconnect the session eagerly because otherwise we will get the new
                         // `connected` event before the `disconnect`, resulting in a duplicate
                         // connection.
+                        self.record(nid, record::Event::Disconnected { link });
                         self.service
                             .disconnected(nid, link, &DisconnectReason::Conflict);
                     }
                 }
                 if !disconnect.contains(&id) {
-                    self.peers
-                        .insert(id, Peer::connected(nid, addr.clone(), link));
+                    self.peers.insert(
+                        id,
+                        Peer::connected(
+                            nid,
+                            addr.clone(),
+                            link,
+                            binding,
+                            self.pool.clone(),
+                            backlog,
+                        ),
+                    );
+                    self.record(
+                        nid,
+                        record::Event::Connected {
+                            addr: addr.clone().into(),
+                            link,
+                        },
+                    );
                     self.service.connected(nid, addr.into(), link);
                 }
             }
             SessionEvent::Data(data) => {
+                // Data to relay to other peers. Since this requires looking up other peers,
+                // it's handled once the frames are deserialized.
+                let mut relayed = Vec::new();
+
                 if let Some(Peer::Connected {
                     nid,
                     inbox,
                     streams,
+                    link,
                     ..
                 }) = self.peers.get_mut(&id)
                 {
@@ -716,12 +1526,31 @@ where
                             })) => {
                                 log::debug!(target: "wire", "Received `open` command for stream {stream} from {nid}");
 
-                                let Some(channels) = streams.register(stream) else {
+                                if streams.is_full(self.stream_limit) {
+                                    log::warn!(target: "wire", "Peer {nid} has too many streams open; refusing stream {stream}");
+
+                                    self.actions.push_back(Action::Send(
+                                        id,
+                                        Frame::control(*link, frame::Control::Close { stream })
+                                            .to_bytes(),
+                                    ));
+                                    continue;
+                                }
+                                self.task_seq += 1;
+
+                                let Some(channels) = streams.register(
+                                    stream,
+                                    self.task_seq,
+                                    None,
+                                    TaskDirection::Upload,
+                                    self.service.local_time(),
+                                ) else {
                                     log::warn!(target: "wire", "Peer attempted to open already-open stream stream {stream}");
                                     continue;
                                 };
 
                                 let task = Task {
+                                    local: *self.signer.public_key(),
                                     fetch: FetchRequest::Responder { remote: *nid },
                                     stream,
                                     channels,
@@ -763,8 +1592,22 @@ where
                                 data: FrameData::Gossip(msg),
                                 ..
                             })) => {
+                                if self.recorder.is_some() {
+                                    record(
+                                        &mut self.recorder,
+                                        self.service.local_time(),
+                                        *nid,
+                                        record::Event::Message(msg.clone()),
+                                    );
+                                }
                                 self.service.received_message(*nid, msg);
                             }
+                            Ok(Some(Frame {
+                                data: FrameData::Relay(relay),
+                                ..
+                            })) => {
+                                relayed.push((*nid, relay));
+                            }
                             Ok(Some(Frame {
                                 stream,
                                 data: FrameData::Git(data),
@@ -801,6 +1644,10 @@ where
                 } else {
                     log::warn!(target: "wire", "Dropping message from unconnected peer (id={id})");
                 }
+
+                for (nid, relay) in relayed {
+                    self.relay(nid, relay);
+                }
             }
             SessionEvent::Terminated(err) => {
                 self.disconnect(id, DisconnectReason::Connection(Arc::new(err)));
@@ -809,17 +1656,62 @@ where
     }
 
     fn handle_command(&mut self, cmd: Self::Command) {
+        self.progress.start("command");
+
         match cmd {
             Control::User(cmd) => self.service.command(cmd),
             Control::Worker(result) => self.worker_result(result),
+            Control::Signed(ann, route) => self.service.signed(ann, route),
             Control::Flush { remote, stream } => self.flush(remote, stream),
+            Control::Tasks(resp) => {
+                resp.send(self.tasks()).ok();
+            }
+            Control::Abort(task, resp) => {
+                resp.send(self.abort(task)).ok();
+            }
+            Control::Binding(nid, resp) => {
+                resp.send(self.binding(&nid)).ok();
+            }
+            Control::Wake => {}
+            Control::Relay {
+                via,
+                to,
+                data,
+                resp,
+            } => {
+                resp.send(self.forward(via, to, data)).ok();
+            }
+            Control::Relayed(resp) => {
+                let (subscriber, receiver) = chan::bounded(RELAYED_CHANNEL_CAPACITY);
+
+                self.relayed.push(subscriber);
+                resp.send(receiver).ok();
+            }
+            Control::Shutdown(done) => {
+                let peers = self
+                    .peers
+                    .active()
+                    .map(|(id, nid, link)| (id, *nid, link))
+                    .collect::<Vec<_>>();
+
+                // Save a snapshot while we still have our sessions.
+                self.save_snapshot(&self.service.snapshot());
+
+                for (id, nid, link) in peers {
+                    self.goodbye(id, link, DisconnectCode::Shutdown);
+                    log::debug!(target: "wire", "Sent shutdown notice to {nid}");
+                }
+                self.shutdown = Some(done);
+            }
         }
     }
 
     fn handle_error(
         &mut self,
-        err: reactor::Error<NetAccept<WireSession<G>>, NetTransport<WireSession<G>>>,
+        err: reactor::Error<NetAccept<WireSession<G>>, Transport<WireSession<G>>>,
     ) {
+        self.progress.start("error");
+
         match err {
             reactor::Error::Poll(err) => {
                 // TODO: This should be a fatal error, there's nothing we can do here.
@@ -841,16 +1733,20 @@ where
                 // the peer from the map.
                 match self.peers.remove(&id) {
                     Some(mut peer) => {
-                        if let Peer::Connected { streams, .. } = &mut peer {
+                        if let Peer::Connected { streams, nid, .. } = &mut peer {
                             streams.shutdown();
+
+                            if let Some(bridge) = &mut self.bridge {
+                                bridge.remove(nid);
+                            }
                         }
 
                         if let Some(id) = peer.id() {
-                            self.service.disconnected(
-                                *id,
-                                peer.link(),
-                                &DisconnectReason::connection(),
-                            );
+                            let link = peer.link();
+
+                            self.record(*id, record::Event::Disconnected { link });
+                            self.service
+                                .disconnected(*id, link, &DisconnectReason::connection());
                         } else {
                             log::debug!(target: "wire", "Inbound disconnection before handshake; ignoring..")
                         }
@@ -866,6 +1762,7 @@ where
     }
 
     fn handover_transport(&mut self, id: ResourceId, transport: Self::Transport) {
+        self.progress.start("transport handover");
         let fd = transport.as_raw_fd();
 
         match self.peers.entry(id) {
@@ -881,6 +1778,12 @@ where
 
                         // If there is no NID, the service is not aware of the peer.
                         if let Some(nid) = nid {
+                            record(
+                                &mut self.recorder,
+                                self.service.local_time(),
+                                *nid,
+                                record::Event::Disconnected { link: *link },
+                            );
                             // In the case of a conflicting connection, there will be two resources
                             // for the peer. However, at the service level, there is only one, and
                             // it is identified by NID.
@@ -938,58 +1841,26 @@ where
                     }
                     self.actions.push_back(reactor::Action::Send(fd, data));
                 }
-                Io::Connect(node_id, addr) => {
-                    if self.peers.connected().any(|(_, id)| id == &node_id) {
-                        log::error!(
-                            target: "wire",
-                            "Attempt to connect to already connected peer {node_id}"
-                        );
-                        // FIXME: The problem here is the session will stay in "initial" state,
-                        // because it can't transition to attempted.
-                        continue;
-                    }
-                    self.service.attempted(node_id, addr.clone());
-
-                    match dial::<G>(
-                        addr.to_inner(),
-                        node_id,
-                        self.signer.clone(),
-                        self.proxy.into(),
-                        false,
-                    )
-                    .and_then(|session| {
-                        NetTransport::<WireSession<G>>::with_session(session, Link::Outbound)
-                    }) {
-                        Ok(transport) => {
-                            self.outbound.insert(
-                                transport.as_raw_fd(),
-                                Outbound {
-                                    id: None,
-                                    nid: node_id,
-                                    addr: addr.to_inner(),
-                                },
-                            );
-                            log::debug!(
-                                target: "wire",
-                                "Registering outbound transport for {node_id} (fd={})..",
-                                transport.as_raw_fd()
-                            );
-                            self.actions
-                                .push_back(reactor::Action::RegisterTransport(transport));
-                        }
-                        Err(err) => {
-                            log::error!(target: "wire", "Error establishing connection to {addr}: {err}");
-
-                            self.service.disconnected(
-                                node_id,
-                                Link::Outbound,
-                                &DisconnectReason::Dial(Arc::new(err)),
-                            );
-                        }
-                    }
+                Io::Connect(node_id, addr) => self.connect(node_id, addr, None),
+                Io::Punch(node_id, addr) => {
+                    // Nb. the connection is made from the port we're listening on, which
+                    // the peer's NAT may already have a mapping for. If we aren't
+                    // listening on an address of the same family, we dial as usual.
+                    let local = match addr.to_inner().connection_addr(self.proxy.into()).host {
+                        InetHost::Ip(ip) => self
+                            .listen_addrs
+                            .iter()
+                            .find(|a| a.is_ipv4() == ip.is_ipv4())
+                            .copied(),
+                        _ => None,
+                    };
+                    self.connect(node_id, addr, local)
                 }
                 Io::Disconnect(nid, reason) => {
-                    if let Some((id, Peer::Connected { .. })) = self.peers.lookup(&nid) {
+                    if let Some((id, Peer::Connected { link, .. })) = self.peers.lookup(&nid) {
+                        if let Some(code) = reason.code() {
+                            self.goodbye(id, *link, code);
+                        }
                         self.disconnect(id, reason);
                     } else {
                         log::warn!(target: "wire", "Peer {nid} is not connected: ignoring disconnect");
@@ -1003,67 +1874,96 @@ where
                     remote,
                     timeout,
                     refs_at,
+                    recover,
                 } => {
                     log::trace!(target: "wire", "Processing fetch for {rid} from {remote}..");
 
-                    let Some((fd, Peer::Connected { link, streams, .. })) =
-                        self.peers.lookup_mut(&remote)
-                    else {
-                        // Nb. It's possible that a peer is disconnected while an `Io::Fetch`
-                        // is in the service's i/o buffer. Since the service may not purge the
-                        // buffer on disconnect, we should just ignore i/o actions that don't
-                        // have a connected peer.
-                        log::error!(target: "wire", "Peer {remote} is not connected: dropping fetch");
-                        continue;
-                    };
-                    let (stream, channels) = streams.open();
-
-                    log::debug!(target: "wire", "Opened new stream with id {stream} for {rid} and remote {remote}");
-
-                    let link = *link;
-                    let task = Task {
-                        fetch: FetchRequest::Initiator {
+                    self.fetch(
+                        remote,
+                        FetchRequest::Initiator {
                             rid,
                             remote,
                             refs_at,
                             timeout,
+                            recover,
                         },
-                        stream,
-                        channels,
-                    };
+                    );
+                }
+                Io::FetchBlobs { rid, remote, blobs } => {
+                    log::trace!(target: "wire", "Processing fetch of blobs for {rid} from {remote}..");
 
-                    if !self.worker.is_empty() {
-                        log::warn!(
-                            target: "wire",
-                            "Worker pool is busy: {} tasks pending, fetch requests may be delayed", self.worker.len()
-                        );
-                    }
-                    if self.worker.send(task).is_err() {
-                        log::error!(target: "wire", "Worker pool is disconnected; cannot send fetch request");
+                    self.fetch(remote, FetchRequest::Blobs { rid, remote, blobs });
+                }
+                Io::QueryRefs {
+                    rid,
+                    remote,
+                    refs,
+                    reply,
+                } => {
+                    log::trace!(target: "wire", "Processing query of references of {rid} from {remote}..");
+
+                    self.fetch(
+                        remote,
+                        FetchRequest::Refs {
+                            rid,
+                            remote,
+                            refs,
+                            reply,
+                        },
+                    );
+                }
+                Io::Sign(request) => {
+                    if self.signing.send(*request).is_err() {
+                        log::error!(target: "wire", "Unable to sign announcement: signing thread is disconnected");
                     }
-                    self.actions.push_back(Action::Send(
-                        fd,
-                        Frame::control(link, frame::Control::Open { stream }).to_bytes(),
-                    ));
                 }
+                Io::Snapshot(snapshot) => self.save_snapshot(&snapshot),
+            }
+        }
+        // Stream data goes out once everything else was sent, and only once per iteration.
+        if self.actions.is_empty() && !self.flushed {
+            self.flushed = true;
+            self.flush_streams();
+        }
+        let action = self.actions.pop_front();
+        if action.is_none() {
+            if let Some(done) = self.shutdown.take() {
+                done.send(()).ok();
             }
         }
-        self.actions.pop_front()
+        action
     }
 }
 
-/// Establish a new outgoing connection.
+/// Record a session event. Recording is stopped if the recording can't be written to.
+fn record(recorder: &mut Option<Recorder>, time: LocalTime, remote: NodeId, event: record::Event) {
+    if let Some(r) = recorder {
+        if let Err(e) = r.record(time, remote, event) {
+            log::error!(target: "wire", "Error recording session event: {e}; stopping recording..");
+            *recorder = None;
+        }
+    }
+}
+
+/// Establish a new outgoing connection, from the given local address, if any.
 pub fn dial<G: Signer + Ecdh<Pk = NodeId>>(
     remote_addr: NetAddr<HostName>,
     remote_id: <G as EcSk>::Pk,
     signer: G,
     proxy_addr: NetAddr<InetHost>,
     force_proxy: bool,
+    local_addr: Option<net::SocketAddr>,
 ) -> io::Result<WireSession<G>> {
     let connection = if force_proxy {
         // Nb. This timeout is currently not used by the underlying library due to the
         // `socket2` library not supporting non-blocking connect with timeout.
         net::TcpStream::connect_nonblocking(proxy_addr, DEFAULT_DIAL_TIMEOUT)?
+    } else if let Some(local_addr) = local_addr {
+        // Nb. This requires the listener bound to the local address to allow port reuse.
+        net::TcpStream::connect_reusable_nonblocking(
+            local_addr.into(),
+            remote_addr.connection_addr(proxy_addr),
+        )?
     } else {
         net::TcpStream::connect_nonblocking(
             remote_addr.connection_addr(proxy_addr),
@@ -1092,7 +1992,7 @@ pub fn accept<G: Signer + Ecdh<Pk = NodeId>>(
 }
 
 /// Create a new [`WireSession`].
-fn session<G: Signer + Ecdh<Pk = NodeId>>(
+pub(crate) fn session<G: Signer + Ecdh<Pk = NodeId>>(
     remote_addr: NetAddr<HostName>,
     remote_id: Option<NodeId>,
     connection: net::TcpStream,
@@ -1157,4 +2057,329 @@ mod test {
         assert!(de.deserialize_next().unwrap().is_none());
         assert!(de.is_empty());
     }
+
+    #[test]
+    fn test_handshake_binding() {
+        use radicle::crypto::ssh::keystore::MemorySigner;
+
+        let alice = MemorySigner::gen();
+        let bob = MemorySigner::gen();
+        let noise = |signer: &MemorySigner, remote: Option<NodeId>| {
+            NoiseState::<_, Sha256>::initialize::<{ Sha256::OUTPUT_LEN }>(
+                NOISE_XK,
+                remote.is_some(),
+                &[],
+                Keyset {
+                    e: MemorySigner::generate_keypair().0,
+                    s: Some(signer.clone()),
+                    re: None,
+                    rs: remote,
+                },
+            )
+        };
+        let handshake = |initiator: &mut NoiseState<_, Sha256>,
+                         responder: &mut NoiseState<_, Sha256>| {
+            let act1 = initiator.advance(&[]).unwrap();
+            let act2 = responder.advance(&act1).unwrap();
+            let act3 = initiator.advance(&act2).unwrap();
+            // The initiator completes once it has written the last message.
+            assert!(initiator.advance(&[]).unwrap().is_empty());
+            assert!(responder.advance(&act3).unwrap().is_empty());
+        };
+        let mut initiator = noise(&alice, Some(*bob.public_key()));
+        let mut responder = noise(&bob, None);
+
+        handshake(&mut initiator, &mut responder);
+
+        assert_eq!(initiator.get_remote_static_key(), Some(*bob.public_key()));
+        assert_eq!(responder.get_remote_static_key(), Some(*alice.public_key()));
+        // Both ends of the session agree on the binding.
+        let binding = Binding::from(initiator.get_handshake_hash().unwrap());
+        assert_eq!(
+            binding,
+            Binding::from(responder.get_handshake_hash().unwrap())
+        );
+
+        // A separate session between the same nodes has a different binding.
+        let mut initiator = noise(&alice, Some(*bob.public_key()));
+        let mut responder = noise(&bob, None);
+
+        handshake(&mut initiator, &mut responder);
+
+        assert_ne!(
+            binding,
+            Binding::from(responder.get_handshake_hash().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_dial_from_listen_port() {
+        use radicle::crypto::ssh::keystore::MemorySigner;
+
+        let listener = NetAccept::<WireSession<MemorySigner>>::bind_reusable(
+            &net::SocketAddr::from(([127, 0, 0, 1], 0)),
+        )
+        .unwrap();
+        let remote = net::TcpListener::bind(net::SocketAddr::from(([127, 0, 0, 1], 0))).unwrap();
+        let proxy = net::SocketAddr::from(([127, 0, 0, 1], 9050));
+
+        let _session = dial(
+            NetAddr::<InetHost>::from(remote.local_addr().unwrap()).into(),
+            NodeId::from([2; 32]),
+            MemorySigner::gen(),
+            proxy.into(),
+            false,
+            Some(listener.local_addr()),
+        )
+        .unwrap();
+        let (conn, _) = remote.accept().unwrap();
+
+        // The connection is made from the port we're listening on.
+        assert_eq!(conn.peer_addr().unwrap(), listener.local_addr());
+    }
+
+    #[test]
+    fn test_bridge_limit() {
+        let mut bridge = Bridge::new(
+            BridgeLimits {
+                peer: RateLimit {
+                    fill_rate: 8.0,
+                    capacity: 16,
+                },
+                total: RateLimit {
+                    fill_rate: 16.0,
+                    capacity: 32,
+                },
+            },
+            LocalTime::from_secs(0),
+        );
+        let alice = NodeId::from([1; 32]);
+        let bob = NodeId::from([2; 32]);
+        let eve = NodeId::from([3; 32]);
+
+        assert!(bridge.relay(alice, 12, LocalTime::from_secs(0)));
+        assert!(!bridge.relay(alice, 8, LocalTime::from_secs(0))); // Over the limit.
+        assert!(bridge.relay(bob, 16, LocalTime::from_secs(0))); // Limits are per peer.
+        assert!(!bridge.relay(eve, 8, LocalTime::from_secs(0))); // Over the total limit.
+        assert!(bridge
+            .peers
+            .get_mut(&eve)
+            .unwrap()
+            .bucket
+            .has_n(16, LocalTime::from_secs(0))); // Nothing taken.
+        assert!(bridge.relay(alice, 8, LocalTime::from_secs(1))); // Refilled (8).
+        assert!(bridge.relay(eve, 8, LocalTime::from_secs(1))); // Refilled (16).
+        assert!(!bridge.relay(eve, 8, LocalTime::from_secs(1))); // Over the total limit.
+        assert_eq!(bridge.peers[&alice].bytes, 20);
+        assert_eq!(bridge.peers[&eve].bytes, 8);
+
+        bridge.remove(&alice);
+        assert!(!bridge.peers.contains_key(&alice));
+    }
+
+    #[test]
+    fn test_stream_abort() {
+        let mut streams = Streams::new(
+            Link::Outbound,
+            worker::BufferPool::default(),
+            Backlog::default(),
+        );
+        let rid = RepoId::from(radicle::git::raw::Oid::zero());
+        let (fetch, _) = streams.open(1, Some(rid), LocalTime::from_secs(0));
+        let (second, worker) = streams.open(2, None, LocalTime::from_secs(0));
+
+        assert_eq!(streams.find(1), Some(fetch));
+        assert_eq!(streams.find(2), Some(second));
+        assert_eq!(streams.find(3), None);
+
+        // Closing the stream lets the worker know it should stop.
+        let s = streams.unregister(&second).unwrap();
+        s.channels.close().unwrap();
+
+        assert!(matches!(
+            worker.try_iter().next(),
+            Some(ChannelEvent::Close)
+        ));
+        assert_eq!(streams.find(2), None);
+        assert_eq!(streams.find(1), Some(fetch));
+    }
+
+    #[test]
+    fn test_stream_limit() {
+        let mut streams = Streams::new(
+            Link::Outbound,
+            worker::BufferPool::default(),
+            Backlog::default(),
+        );
+        let remote = NodeId::from([1; 32]);
+        let (first, _) = streams.open(1, None, LocalTime::from_secs(0));
+        assert!(!streams.is_full(2));
+
+        streams.open(2, None, LocalTime::from_secs(0));
+        assert!(streams.is_full(2));
+
+        // Fetches queued while the streams are full are only started once one is closed.
+        streams
+            .pending
+            .push_back(FetchRequest::Responder { remote });
+        assert!(streams.next_pending(2).is_none());
+
+        streams.unregister(&first).unwrap();
+        assert!(matches!(
+            streams.next_pending(2),
+            Some(FetchRequest::Responder { remote: r }) if r == remote
+        ));
+        assert!(streams.next_pending(2).is_none());
+    }
+
+    #[test]
+    fn test_stream_fairness() {
+        let mut streams = Streams::new(
+            Link::Outbound,
+            worker::BufferPool::default(),
+            Backlog::default(),
+        );
+        let (bulk, bulk_worker) = streams.open(1, None, LocalTime::from_secs(0));
+        let (small, small_worker) = streams.open(2, None, LocalTime::from_secs(0));
+
+        bulk_worker
+            .send(ChannelEvent::Data(vec![1; MAX_STREAM_FRAME_SIZE * 3]))
+            .unwrap();
+        bulk_worker.send(ChannelEvent::Eof).unwrap();
+        small_worker.send(ChannelEvent::Data(vec![2; 8])).unwrap();
+
+        assert!(streams.flush(bulk));
+        assert!(streams.flush(small));
+        assert!(!streams.flush(StreamId::git(Link::Inbound)));
+
+        // The small stream gets its turn right after the first chunk of the bulk stream.
+        let frames = streams.frames(MAX_STREAM_FRAME_SIZE * 2);
+        let ids = frames
+            .iter()
+            .map(|f| match &f.data {
+                FrameData::Git(data) => (f.stream, data.len()),
+                _ => (f.stream, 0),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            vec![
+                (bulk, MAX_STREAM_FRAME_SIZE),
+                (small, 8),
+                (bulk, MAX_STREAM_FRAME_SIZE)
+            ]
+        );
+        assert!(streams.is_ready());
+
+        // The rest is sent on the next turn.
+        let frames = streams.frames(MAX_STREAM_FLUSH_SIZE);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0], Frame::git(bulk, vec![1; MAX_STREAM_FRAME_SIZE]));
+        assert_eq!(
+            frames[1],
+            Frame::control(Link::Outbound, frame::Control::Eof { stream: bulk })
+        );
+        assert!(!streams.is_ready());
+        assert_eq!(
+            streams.get(&bulk).unwrap().sent_bytes,
+            MAX_STREAM_FRAME_SIZE * 3
+        );
+    }
+
+    #[test]
+    fn test_stream_relay() {
+        let backlog = Backlog::default();
+        let mut streams = Streams::new(
+            Link::Outbound,
+            worker::BufferPool::default(),
+            backlog.clone(),
+        );
+        let (stream, worker) = streams.open(1, None, LocalTime::from_secs(0));
+        let bob = NodeId::from([2; 32]);
+
+        worker
+            .send(ChannelEvent::Data(vec![1; MAX_STREAM_FRAME_SIZE * 2]))
+            .unwrap();
+        assert!(streams.flush(stream));
+        assert!(streams.relay(Relay::Forward {
+            to: bob,
+            data: vec![2; 8]
+        }));
+        assert!(!streams.relay(Relay::Forward {
+            to: bob,
+            data: vec![3; MAX_RELAY_QUEUE_SIZE]
+        })); // Too much queued.
+
+        // Relayed data is held back while the transport is busy, like stream data.
+        backlog.set(MAX_TRANSPORT_BACKLOG);
+        assert!(streams.frames(streams.budget()).is_empty());
+
+        // And takes turns with the streams.
+        backlog.set(0);
+        let frames = streams.frames(streams.budget());
+        assert_eq!(
+            frames,
+            vec![
+                Frame::git(stream, vec![1; MAX_STREAM_FRAME_SIZE]),
+                Frame::relay(
+                    Link::Outbound,
+                    Relay::Forward {
+                        to: bob,
+                        data: vec![2; 8]
+                    }
+                ),
+                Frame::git(stream, vec![1; MAX_STREAM_FRAME_SIZE]),
+            ]
+        );
+        assert!(!streams.is_ready());
+        assert_eq!(streams.relay_size, 0);
+    }
+
+    #[test]
+    fn test_stream_buffer_reuse() {
+        let pool = worker::BufferPool::new(1);
+        let mut streams = Streams::new(Link::Outbound, pool.clone(), Backlog::default());
+        let (stream, worker) = streams.open(1, None, LocalTime::from_secs(0));
+
+        let mut buf = pool.get();
+        buf.extend_from_slice(&[1; 8]);
+        worker.send(ChannelEvent::Data(buf)).unwrap();
+        worker.send(ChannelEvent::Data(vec![2; 8])).unwrap();
+        assert!(streams.flush(stream));
+
+        for frame in streams.frames(MAX_STREAM_FLUSH_SIZE) {
+            streams.recycle(frame);
+        }
+        // Only the pooled buffer is kept for reuse.
+        assert_eq!(pool.len(), 1);
+        assert!(pool.get().is_empty());
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn test_stream_backlog() {
+        let backlog = Backlog::default();
+        let mut streams = Streams::new(
+            Link::Outbound,
+            worker::BufferPool::default(),
+            backlog.clone(),
+        );
+        let (stream, worker) = streams.open(1, None, LocalTime::from_secs(0));
+
+        worker
+            .send(ChannelEvent::Data(vec![1; MAX_STREAM_FRAME_SIZE]))
+            .unwrap();
+        assert!(streams.flush(stream));
+        assert_eq!(streams.budget(), MAX_TRANSPORT_BACKLOG);
+
+        // While the transport is busy, stream data is held back.
+        backlog.set(MAX_TRANSPORT_BACKLOG);
+        assert_eq!(streams.budget(), 0);
+        assert!(streams.frames(streams.budget()).is_empty());
+        assert!(streams.is_ready());
+
+        backlog.set(MAX_TRANSPORT_BACKLOG - 1);
+        assert_eq!(streams.frames(streams.budget()).len(), 1);
+        assert!(!streams.is_ready());
+    }
 }
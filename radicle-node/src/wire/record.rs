@@ -0,0 +1,266 @@
+//! Recording of inbound protocol sessions.
+//!
+//! When enabled, every session established with a peer, every gossip message received from it,
+//! and the end of the session are appended to a file, along with the local time at which they
+//! were observed. Recordings can be replayed into a service, to reproduce issues found in the
+//! wild as regression tests.
+//!
+//! Each entry is encoded as the local time in milliseconds, the remote node, an entry type byte,
+//! and the entry data, all using the wire encoding.
+use std::path::Path;
+use std::{fs, io};
+
+use localtime::LocalTime;
+use radicle::node::{Address, NodeId};
+
+use crate::service::Message;
+use crate::wire;
+use crate::wire::{Decode, Encode};
+use crate::Link;
+
+/// Session established entry type.
+const ENTRY_CONNECTED: u8 = 0;
+/// Message received entry type.
+const ENTRY_MESSAGE: u8 = 1;
+/// Session ended entry type.
+const ENTRY_DISCONNECTED: u8 = 2;
+
+/// Inbound link byte.
+const LINK_INBOUND: u8 = 0;
+/// Outbound link byte.
+const LINK_OUTBOUND: u8 = 1;
+
+/// A recorded session event.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A session was established with the remote.
+    Connected { addr: Address, link: Link },
+    /// A gossip message was received from the remote.
+    Message(Message),
+    /// The session with the remote ended.
+    Disconnected { link: Link },
+}
+
+/// A recording entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Local time at which the event was observed.
+    pub time: LocalTime,
+    /// The remote node the event concerns.
+    pub remote: NodeId,
+    /// The recorded event.
+    pub event: Event,
+}
+
+impl Encode for Link {
+    fn encode<W: io::Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        match self {
+            Link::Inbound => LINK_INBOUND.encode(writer),
+            Link::Outbound => LINK_OUTBOUND.encode(writer),
+        }
+    }
+}
+
+impl Decode for Link {
+    fn decode<R: io::Read + ?Sized>(reader: &mut R) -> Result<Self, wire::Error> {
+        match u8::decode(reader)? {
+            LINK_INBOUND => Ok(Link::Inbound),
+            LINK_OUTBOUND => Ok(Link::Outbound),
+            other => Err(wire::Error::InvalidFlag(other)),
+        }
+    }
+}
+
+impl Encode for Entry {
+    fn encode<W: io::Write + ?Sized>(&self, writer: &mut W) -> Result<usize, io::Error> {
+        let mut n = self.time.as_millis().encode(writer)?;
+        n += self.remote.encode(writer)?;
+
+        match &self.event {
+            Event::Connected { addr, link } => {
+                n += ENTRY_CONNECTED.encode(writer)?;
+                n += addr.encode(writer)?;
+                n += link.encode(writer)?;
+            }
+            Event::Message(msg) => {
+                n += ENTRY_MESSAGE.encode(writer)?;
+                n += msg.encode(writer)?;
+            }
+            Event::Disconnected { link } => {
+                n += ENTRY_DISCONNECTED.encode(writer)?;
+                n += link.encode(writer)?;
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl Decode for Entry {
+    fn decode<R: io::Read + ?Sized>(reader: &mut R) -> Result<Self, wire::Error> {
+        let time = LocalTime::from_millis(u64::decode(reader)? as u128);
+        let remote = NodeId::decode(reader)?;
+        let event = match u8::decode(reader)? {
+            ENTRY_CONNECTED => Event::Connected {
+                addr: Address::decode(reader)?,
+                link: Link::decode(reader)?,
+            },
+            ENTRY_MESSAGE => Event::Message(Message::decode(reader)?),
+            ENTRY_DISCONNECTED => Event::Disconnected {
+                link: Link::decode(reader)?,
+            },
+            other => return Err(wire::Error::InvalidFlag(other)),
+        };
+        Ok(Self {
+            time,
+            remote,
+            event,
+        })
+    }
+}
+
+/// Appends session events to a recording file.
+#[derive(Debug)]
+pub struct Recorder {
+    file: io::BufWriter<fs::File>,
+}
+
+impl Recorder {
+    /// Open a recording file for appending, creating it if it doesn't exist.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+
+        Ok(Self {
+            file: io::BufWriter::new(file),
+        })
+    }
+
+    /// Record an event. Entries are flushed immediately, so that a recording is usable even
+    /// if the node doesn't shut down cleanly.
+    pub fn record(&mut self, time: LocalTime, remote: NodeId, event: Event) -> io::Result<()> {
+        use io::Write as _;
+
+        Entry {
+            time,
+            remote,
+            event,
+        }
+        .encode(&mut self.file)?;
+        self.file.flush()
+    }
+}
+
+/// Reads the entries of a recording.
+#[derive(Debug)]
+pub struct Recording<R> {
+    reader: R,
+}
+
+impl Recording<io::BufReader<fs::File>> {
+    /// Open a recording file.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = fs::File::open(path)?;
+
+        Ok(Self::new(io::BufReader::new(file)))
+    }
+}
+
+impl<R: io::Read> Recording<R> {
+    /// Read a recording from the given reader.
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+}
+
+impl<R: io::Read> Iterator for Recording<R> {
+    type Item = Result<Entry, wire::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // An entry always starts with its timestamp. If we can't read it, we're at the end of
+        // the recording; anything else is a truncated entry.
+        let mut time = [0u8; 8];
+        match read_all(&mut self.reader, &mut time) {
+            Ok(0) => return None,
+            Ok(n) if n < time.len() => {
+                return Some(Err(wire::Error::Io(io::ErrorKind::UnexpectedEof.into())))
+            }
+            Ok(_) => {}
+            Err(e) => return Some(Err(e.into())),
+        }
+        let mut reader = io::Read::chain(&time[..], &mut self.reader);
+
+        Some(Entry::decode(&mut reader))
+    }
+}
+
+/// Read as many bytes as possible into the buffer, stopping at the end of the stream.
+fn read_all<R: io::Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match reader.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(k) => n += k,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::arbitrary;
+
+    #[test]
+    fn test_recording_roundtrip() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("session.rec");
+        let remote = arbitrary::gen::<NodeId>(1);
+        let entries = vec![
+            Entry {
+                time: LocalTime::from_millis(1000),
+                remote,
+                event: Event::Connected {
+                    addr: arbitrary::gen(1),
+                    link: Link::Inbound,
+                },
+            },
+            Entry {
+                time: LocalTime::from_millis(1500),
+                remote,
+                event: Event::Message(arbitrary::gen(1)),
+            },
+            Entry {
+                time: LocalTime::from_millis(3000),
+                remote,
+                event: Event::Disconnected {
+                    link: Link::Inbound,
+                },
+            },
+        ];
+
+        let mut recorder = Recorder::open(&path).unwrap();
+        for e in entries.clone() {
+            recorder.record(e.time, e.remote, e.event).unwrap();
+        }
+        drop(recorder);
+
+        let recorded = Recording::open(&path)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(recorded, entries);
+
+        // A truncated entry is an error.
+        let bytes = std::fs::read(&path).unwrap();
+        let mut recording = Recording::new(&bytes[..bytes.len() - 1]);
+        assert!(recording.next().unwrap().is_ok());
+        assert!(recording.next().unwrap().is_ok());
+        assert!(recording.next().unwrap().unwrap_err().is_eof());
+        assert!(recording.next().is_none());
+    }
+}
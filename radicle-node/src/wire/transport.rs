@@ -0,0 +1,130 @@
+//! Peer transport.
+//!
+//! Wraps the network transport to let the wire know how much data is buffered in it, waiting
+//! to be written to the socket. Since everything handed to the transport is sent in order, the
+//! wire holds back bulk stream data while this backlog is large, so that gossip messages only
+//! ever wait behind a bounded amount of stream data.
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::{fmt, io};
+
+use netservices::resource::{NetTransport, SessionEvent};
+use netservices::NetSession;
+use reactor::poller::IoType;
+use reactor::{Io, Resource, WriteAtomic};
+
+use crate::Link;
+
+/// Amount of data buffered by a transport, yet to be written to its socket, in bytes.
+#[derive(Debug, Default, Clone)]
+pub struct Backlog(Arc<AtomicUsize>);
+
+impl Backlog {
+    /// Number of bytes buffered.
+    pub fn len(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Whether nothing is buffered.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Set the number of bytes buffered.
+    pub(super) fn set(&self, len: usize) {
+        self.0.store(len, Ordering::Relaxed);
+    }
+}
+
+/// Peer transport, sharing its [`Backlog`] with the wire.
+pub struct Transport<S: NetSession> {
+    inner: NetTransport<S>,
+    backlog: Backlog,
+}
+
+impl<S: NetSession> Transport<S> {
+    /// Create a transport from an existing session.
+    pub fn with_session(session: S, link: Link) -> io::Result<Self> {
+        NetTransport::with_session(session, link).map(|inner| Self {
+            inner,
+            backlog: Backlog::default(),
+        })
+    }
+
+    /// The transport backlog.
+    pub fn backlog(&self) -> Backlog {
+        self.backlog.clone()
+    }
+
+    /// Update the backlog after the transport buffer may have changed.
+    fn update(&self) {
+        self.backlog.set(self.inner.write_buf_len());
+    }
+}
+
+impl<S: NetSession> fmt::Debug for Transport<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transport")
+            .field("fd", &self.as_raw_fd())
+            .field("backlog", &self.backlog.len())
+            .finish()
+    }
+}
+
+impl<S: NetSession> AsRawFd for Transport<S> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl<S: NetSession> io::Write for Transport<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = self.inner.write(buf);
+        self.update();
+
+        result
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let result = self.inner.flush();
+        self.update();
+
+        result
+    }
+}
+
+impl<S: NetSession> WriteAtomic for Transport<S> {
+    fn is_ready_to_write(&self) -> bool {
+        self.inner.is_ready_to_write()
+    }
+
+    fn empty_write_buf(&mut self) -> io::Result<bool> {
+        let result = self.inner.empty_write_buf();
+        self.update();
+
+        result
+    }
+
+    fn write_or_buf(&mut self, buf: &[u8]) -> io::Result<()> {
+        let result = self.inner.write_or_buf(buf);
+        self.update();
+
+        result
+    }
+}
+
+impl<S: NetSession> Resource for Transport<S> {
+    type Event = SessionEvent<S>;
+
+    fn interests(&self) -> IoType {
+        self.inner.interests()
+    }
+
+    fn handle_io(&mut self, io: Io) -> Option<Self::Event> {
+        let event = self.inner.handle_io(io);
+        self.update();
+
+        event
+    }
+}
@@ -1,44 +1,97 @@
 #![allow(clippy::too_many_arguments)]
+mod blob;
 mod channels;
-mod upload_pack;
 
+pub mod access;
+pub mod backend;
+pub mod daemon;
 pub mod fetch;
 pub mod garbage;
+pub mod hooks;
+pub mod http;
+pub mod mirror;
+pub mod query;
+pub mod resolve;
+pub mod upload_pack;
 
+use std::any::Any;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
-use std::{io, time};
+use std::sync::{Arc, RwLock};
+use std::{io, panic, time};
 
 use crossbeam_channel as chan;
+use nonempty::NonEmpty;
 
 use radicle::identity::RepoId;
-use radicle::node::notifications;
+use radicle::node::{notifications, ErrorKind, Severity};
 use radicle::prelude::NodeId;
+use radicle::storage::blobs;
+use radicle::storage::git::stats;
 use radicle::storage::refs::RefsAt;
-use radicle::storage::{ReadRepository, ReadStorage};
-use radicle::{cob, crypto, Storage};
+use radicle::storage::{updates, RepositoryError};
+use radicle::{cob, crypto, git};
 use radicle_fetch::FetchLimit;
 
+use crate::runtime::watchdog::Progress;
 use crate::runtime::{thread, Handle};
 use crate::service::policy;
 use crate::service::policy::Policy;
 use crate::wire::StreamId;
 
-pub use channels::{ChannelEvent, Channels};
+pub use backend::Backend;
+pub use channels::{BufferPool, ChannelEvent, Channels};
 
-/// Worker pool configuration.
+/// Time to wait before retrying to recover a worker after a panic, the first time.
+pub const MIN_RECOVERY_BACKOFF: time::Duration = time::Duration::from_secs(1);
+/// Maximum time to wait before retrying to recover a worker after a panic.
+pub const MAX_RECOVERY_BACKOFF: time::Duration = time::Duration::from_secs(60);
+
+/// Worker configuration, for one of the node identities hosted by the pool.
 pub struct Config {
-    /// Number of worker threads.
-    pub capacity: usize,
     /// Git storage.
-    pub storage: Storage,
+    pub storage: Arc<dyn Backend>,
     /// Configuration for performing fetched.
     pub fetch: FetchConfig,
-    /// Default policy, if a policy for a specific node or repository was not found.
-    pub policy: Policy,
-    /// Default scope, if a scope for a specific repository was not found.
-    pub scope: policy::Scope,
+    /// Default policy and scope.
+    pub defaults: Defaults,
     /// Path to the policies database.
     pub policies_db: PathBuf,
+    /// Where to send repositories updated by fetches, if any are mirrored.
+    pub mirror: Option<chan::Sender<RepoId>>,
+    /// Where to send the results of fetches that updated repositories, for hooks.
+    pub hooks: Option<chan::Sender<hooks::Fetched>>,
+    /// Where to notify other processes of reference updates applied by fetches.
+    pub updates: Option<updates::Notifier>,
+}
+
+/// Default policy and scope, used if a policy for a specific node or repository was not
+/// found. Shared with the service, so that they can be changed when the node configuration
+/// is reloaded.
+#[derive(Debug, Clone)]
+pub struct Defaults(Arc<RwLock<(Policy, policy::Scope)>>);
+
+impl Defaults {
+    /// Create new defaults.
+    pub fn new(policy: Policy, scope: policy::Scope) -> Self {
+        Self(Arc::new(RwLock::new((policy, scope))))
+    }
+
+    /// Get the current defaults.
+    pub fn get(&self) -> (Policy, policy::Scope) {
+        match self.0.read() {
+            Ok(defaults) => *defaults,
+            Err(e) => *e.into_inner(),
+        }
+    }
+
+    /// Change the defaults.
+    pub fn set(&self, policy: Policy, scope: policy::Scope) {
+        match self.0.write() {
+            Ok(mut defaults) => *defaults = (policy, scope),
+            Err(e) => *e.into_inner() = (policy, scope),
+        }
+    }
 }
 
 /// Error returned by fetch.
@@ -49,7 +102,9 @@ pub enum FetchError {
     #[error(transparent)]
     Io(#[from] io::Error),
     #[error(transparent)]
-    Fetch(#[from] fetch::error::Fetch),
+    Fetch(fetch::error::Fetch),
+    #[error(transparent)]
+    InsufficientSpace(#[from] radicle_fetch::InsufficientSpace),
     #[error(transparent)]
     Handle(#[from] fetch::error::Handle),
     #[error(transparent)]
@@ -60,13 +115,127 @@ pub enum FetchError {
     Policy(#[from] radicle_fetch::policy::error::Policy),
     #[error(transparent)]
     Blocked(#[from] radicle_fetch::policy::error::Blocked),
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+    #[error(transparent)]
+    Blob(#[from] blob::Error),
+    #[error("worker panicked: {0}")]
+    Panicked(String),
+}
+
+impl From<fetch::error::Fetch> for FetchError {
+    fn from(err: fetch::error::Fetch) -> Self {
+        match err {
+            fetch::error::Fetch::Run(radicle_fetch::Error::InsufficientSpace(err)) => {
+                Self::InsufficientSpace(err)
+            }
+            err => Self::Fetch(err),
+        }
+    }
 }
 
 impl FetchError {
+    /// Get the kind of error, as reported to node clients.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Io(e) => ErrorKind::from_io(e),
+            Self::Fetch(fetch::error::Fetch::Run(
+                radicle_fetch::Error::Handshake { err } | radicle_fetch::Error::LsRefs { err },
+            )) => ErrorKind::from_io(err),
+            Self::Fetch(fetch::error::Fetch::Validation { .. }) => ErrorKind::ValidationFailed,
+            Self::InsufficientSpace(_) => ErrorKind::StorageFull,
+            Self::Fetch(fetch::error::Fetch::StorageCopy(e)) => ErrorKind::from_io(e),
+            Self::Fetch(fetch::error::Fetch::Storage(e)) | Self::Storage(e) => e.kind(),
+            Self::Policy(radicle_fetch::policy::error::Policy::BlockedPolicy { .. }) => {
+                ErrorKind::NotSeeding
+            }
+            _ => ErrorKind::Other,
+        }
+    }
+
+    /// Check if the fetched repository was discarded because it isn't delegated to by
+    /// the required nodes. See [`fetch::Handle::with_required_delegates`].
+    pub fn is_not_delegated(&self) -> bool {
+        matches!(
+            self,
+            FetchError::Fetch(fetch::error::Fetch::NotDelegated { .. })
+        )
+    }
+
     /// Check if it's a timeout error.
     pub fn is_timeout(&self) -> bool {
         matches!(self, FetchError::Io(e) if e.kind() == io::ErrorKind::TimedOut)
     }
+
+    /// Classify the error, to decide whether the fetch is retried and whether the remote
+    /// is held responsible.
+    pub fn classification(&self) -> Classification {
+        use fetch::error::Fetch;
+
+        match self {
+            Self::Io(e) if ErrorKind::from_io(e) == ErrorKind::StorageFull => {
+                Classification::Storage
+            }
+            Self::Io(_) => Classification::Network,
+            Self::CommandFailed { .. } => Classification::Misbehavior,
+            Self::Fetch(Fetch::Run(e)) if e.is_io() => Classification::Network,
+            Self::Fetch(Fetch::Run(e)) if e.is_storage() => Classification::Storage,
+            Self::Fetch(Fetch::Run(radicle_fetch::Error::ReplicateSelf)) => Classification::Policy,
+            Self::Fetch(Fetch::Run(_) | Fetch::Validation { .. }) => Classification::Misbehavior,
+            Self::Fetch(Fetch::NotDelegated { .. }) => Classification::Policy,
+            Self::Fetch(
+                Fetch::Git(_)
+                | Fetch::Storage(_)
+                | Fetch::StorageCopy(_)
+                | Fetch::Repository(_)
+                | Fetch::RefsDb(_)
+                | Fetch::Confirmations(_)
+                | Fetch::Cache(_),
+            ) => Classification::Storage,
+            Self::InsufficientSpace(_)
+            | Self::Handle(_)
+            | Self::Storage(_)
+            | Self::PolicyStore(_)
+            | Self::Repository(_)
+            | Self::Panicked(_) => Classification::Storage,
+            Self::Policy(_) | Self::Blocked(_) => Classification::Policy,
+            Self::Blob(blob::Error::Io(_)) => Classification::Network,
+            Self::Blob(blob::Error::Blobs(_)) => Classification::Storage,
+            Self::Blob(blob::Error::ChunkTooLarge(_) | blob::Error::Overflow(_)) => {
+                Classification::Misbehavior
+            }
+        }
+    }
+}
+
+/// Classification of fetch errors, which determines how the service handles a failed fetch.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Classification {
+    /// The connection to the remote failed, eg. it timed out or was dropped. These errors
+    /// are usually transient, and aren't held against the repository or the remote.
+    Network,
+    /// The remote sent invalid data, or otherwise misbehaved. The remote is penalized, and
+    /// the repository isn't fetched from it again until the backoff expires.
+    Misbehavior,
+    /// Our own storage failed, eg. it is full. The remote isn't held responsible.
+    Storage,
+    /// The fetch was refused by our own policies, eg. the repository is blocked.
+    Policy,
+}
+
+impl Classification {
+    /// Whether fetching the repository from the same remote is backed off after a failure.
+    pub fn is_backed_off(&self) -> bool {
+        !matches!(self, Self::Network)
+    }
+
+    /// Severity of the penalty given to the remote after a failure, if any.
+    pub fn penalty(&self) -> Option<Severity> {
+        match self {
+            Self::Misbehavior => Some(Severity::Medium),
+            Self::Network | Self::Storage | Self::Policy => None,
+        }
+    }
 }
 
 /// Error returned by fetch responder.
@@ -78,6 +247,8 @@ pub enum UploadError {
     Io(#[from] io::Error),
     #[error("{0} is not authorized to fetch {1}")]
     Unauthorized(NodeId, RepoId),
+    #[error("{0} is not available for anonymous fetching")]
+    Forbidden(RepoId),
     #[error(transparent)]
     Storage(#[from] radicle::storage::Error),
     #[error(transparent)]
@@ -86,6 +257,12 @@ pub enum UploadError {
     Repository(#[from] radicle::storage::RepositoryError),
     #[error(transparent)]
     PolicyStore(#[from] radicle::node::policy::store::Error),
+    #[error("serving blobs is not enabled")]
+    BlobsDisabled,
+    #[error("upload-pack {0} reached")]
+    Exceeded(upload_pack::Exceeded),
+    #[error("worker panicked: {0}")]
+    Panicked(String),
 }
 
 impl UploadError {
@@ -109,6 +286,31 @@ pub enum FetchRequest {
         refs_at: Option<Vec<RefsAt>>,
         /// Fetch timeout.
         timeout: time::Duration,
+        /// If the local `rad/id` of the repository is missing or invalid, recover it with a
+        /// clone-style exchange instead of failing.
+        recover: bool,
+    },
+    /// Client is fetching large files referenced by the repository identified by
+    /// `rid`, from the peer identified by `remote`.
+    Blobs {
+        /// Repo the blobs belong to.
+        rid: RepoId,
+        /// Remote peer we are interacting with.
+        remote: NodeId,
+        /// Blobs to fetch.
+        blobs: Vec<git::Oid>,
+    },
+    /// Client is querying the tips of references of the repository identified by
+    /// `rid`, from the peer identified by `remote`.
+    Refs {
+        /// Repo the references belong to.
+        rid: RepoId,
+        /// Remote peer we are interacting with.
+        remote: NodeId,
+        /// References to query.
+        refs: Vec<git::RefString>,
+        /// Where to send the tips of the references.
+        reply: chan::Sender<Result<query::Tips, query::Error>>,
     },
     /// Server is responding to a fetch request by uploading the
     /// specified `refspecs` sent by the client.
@@ -121,7 +323,20 @@ pub enum FetchRequest {
 impl FetchRequest {
     pub fn remote(&self) -> NodeId {
         match self {
-            Self::Initiator { remote, .. } | Self::Responder { remote } => *remote,
+            Self::Initiator { remote, .. }
+            | Self::Blobs { remote, .. }
+            | Self::Refs { remote, .. }
+            | Self::Responder { remote } => *remote,
+        }
+    }
+
+    /// The repository fetched, if known. Only known by the initiator.
+    pub fn rid(&self) -> Option<RepoId> {
+        match self {
+            Self::Initiator { rid, .. } | Self::Blobs { rid, .. } | Self::Refs { rid, .. } => {
+                Some(*rid)
+            }
+            Self::Responder { .. } => None,
         }
     }
 }
@@ -135,17 +350,95 @@ pub enum FetchResult {
         /// Fetch result, including remotes fetched.
         result: Result<fetch::FetchResult, FetchError>,
     },
+    Blobs {
+        /// Repo the blobs belong to.
+        rid: RepoId,
+        /// Blobs fetched.
+        result: Result<Vec<git::Oid>, FetchError>,
+    },
+    Refs {
+        /// Repo the references belong to.
+        rid: RepoId,
+        /// Tips of the queried references.
+        result: Result<query::Tips, query::Error>,
+        /// Where to send the result.
+        reply: chan::Sender<Result<query::Tips, query::Error>>,
+    },
     Responder {
         /// Repo requested.
         rid: Option<RepoId>,
         /// Upload result.
         result: Result<(), UploadError>,
+        /// Number of bytes sent.
+        sent: u64,
+        /// Time taken to serve the fetch.
+        elapsed: time::Duration,
+        /// CPU time used by the upload-pack process, if one was spawned.
+        cpu: Option<time::Duration>,
     },
 }
 
+impl FetchResult {
+    /// The result of a request whose processing panicked.
+    fn panicked(request: FetchRequest, reason: String) -> Self {
+        match request {
+            FetchRequest::Initiator { rid, .. } => Self::Initiator {
+                rid,
+                result: Err(FetchError::Panicked(reason)),
+            },
+            FetchRequest::Blobs { rid, .. } => Self::Blobs {
+                rid,
+                result: Err(FetchError::Panicked(reason)),
+            },
+            FetchRequest::Refs { rid, reply, .. } => Self::Refs {
+                rid,
+                result: Err(query::Error::Panicked(reason)),
+                reply,
+            },
+            FetchRequest::Responder { .. } => Self::Responder {
+                rid: None,
+                result: Err(UploadError::Panicked(reason)),
+                sent: 0,
+                elapsed: time::Duration::ZERO,
+                cpu: None,
+            },
+        }
+    }
+
+    /// The result of a request whose stream was closed before it was processed.
+    fn closed(request: FetchRequest) -> Self {
+        let closed = || io::Error::new(io::ErrorKind::ConnectionReset, "stream was closed");
+
+        match request {
+            FetchRequest::Initiator { rid, .. } => Self::Initiator {
+                rid,
+                result: Err(FetchError::Io(closed())),
+            },
+            FetchRequest::Blobs { rid, .. } => Self::Blobs {
+                rid,
+                result: Err(FetchError::Io(closed())),
+            },
+            FetchRequest::Refs { rid, reply, .. } => Self::Refs {
+                rid,
+                result: Err(query::Error::Io(closed())),
+                reply,
+            },
+            FetchRequest::Responder { .. } => Self::Responder {
+                rid: None,
+                result: Err(UploadError::Io(closed())),
+                sent: 0,
+                elapsed: time::Duration::ZERO,
+                cpu: None,
+            },
+        }
+    }
+}
+
 /// Task to be accomplished on a worker thread.
 /// This is either going to be an outgoing or incoming fetch.
 pub struct Task {
+    /// The node identity the task is run for.
+    pub local: NodeId,
     pub fetch: FetchRequest,
     pub stream: StreamId,
     pub channels: Channels,
@@ -168,41 +461,142 @@ pub struct FetchConfig {
     /// Configuration for `git gc` garbage collection. Defaults to `1
     /// hour ago`.
     pub expiry: garbage::Expiry,
+    /// Maximum number of threads used to index a fetched packfile.
+    /// Uses all cores if not set.
+    pub pack_threads: Option<usize>,
+    /// Whether large files referenced by repositories are served and fetched.
+    pub blobs: bool,
+    /// Whether to refuse the updates of remotes whose `rad/sigrefs` diverged from the
+    /// tips we last fetched.
+    pub refuse_diverged_sigrefs: bool,
+    /// References that are never fetched.
+    pub denied_refs: radicle_fetch::DeniedRefs,
+    /// Maximum number of references a remote namespace may sign.
+    pub max_namespace_refs: Option<usize>,
+    /// Maximum number of references written per transaction when applying a fetch.
+    pub ref_update_batch_size: Option<usize>,
+    /// Policies for identity updates requiring confirmation.
+    pub confirmations: radicle::node::config::Confirmations,
+    /// Resource limits of the upload-pack processes serving fetches to remotes.
+    pub upload_limits: upload_pack::Limits,
 }
 
-/// A worker that replicates git objects.
+/// A worker that replicates git objects, for one node identity.
 struct Worker {
     nid: NodeId,
-    storage: Storage,
+    storage: Arc<dyn Backend>,
     fetch_config: FetchConfig,
-    tasks: chan::Receiver<Task>,
     handle: Handle,
     policies: policy::Config<policy::store::Read>,
+    defaults: Defaults,
     notifications: notifications::StoreWriter,
     cache: cob::cache::StoreWriter,
     db: radicle::node::Database,
+    policies_db: PathBuf,
+    progress: Arc<Progress>,
+    mirror: Option<chan::Sender<RepoId>>,
+    hooks: Option<chan::Sender<hooks::Fetched>>,
+    updates: Option<updates::Notifier>,
 }
 
-impl Worker {
-    /// Waits for tasks and runs them. Blocks indefinitely unless there is an error receiving
-    /// the next task.
+/// A worker thread, running the tasks of every node identity hosted by the pool.
+struct Runner {
+    tasks: chan::Receiver<Task>,
+    /// The worker of each identity.
+    workers: BTreeMap<NodeId, Worker>,
+    progress: Arc<Progress>,
+}
+
+impl Runner {
+    /// Waits for tasks and runs them on the worker of the identity they are for. Blocks
+    /// indefinitely unless there is an error receiving the next task.
+    ///
+    /// If processing a task panics, the panic is reported to the service as the result of
+    /// the task, and the worker reloads its policy configuration, which the panic may have
+    /// left inconsistent, before taking the next task. The worker keeps running on the same
+    /// thread, and its other state, eg. the database handles, is reused as is. If reloading
+    /// fails, it is retried with an exponential backoff, so that the pool doesn't lose the
+    /// thread.
     fn run(mut self) -> Result<(), chan::RecvError> {
         loop {
             let task = self.tasks.recv()?;
-            self.process(task);
+            let local = task.local;
+            let Some(worker) = self.workers.get_mut(&local) else {
+                log::error!(target: "worker", "Dropping task for {}: {local} is not hosted", task.fetch.remote());
+                continue;
+            };
+            if !worker.process(task) {
+                continue;
+            }
+            let mut backoff = MIN_RECOVERY_BACKOFF;
+
+            while let Err(e) = worker.recover() {
+                log::error!(target: "worker", "Unable to recover worker after panic, retrying in {}s: {e}", backoff.as_secs());
+
+                std::thread::sleep(backoff);
+                backoff = (backoff * 2).min(MAX_RECOVERY_BACKOFF);
+                // Waiting isn't a sign of the worker being stuck.
+                self.progress.beat();
+            }
         }
     }
+}
 
-    fn process(&mut self, task: Task) {
+impl Worker {
+    /// Reload the policy configuration of the worker, after a panic.
+    fn recover(&mut self) -> Result<(), policy::Error> {
+        let (policy, scope) = self.defaults.get();
+        self.policies =
+            policy::Config::new(policy, scope, policy::Store::reader(&self.policies_db)?);
+
+        Ok(())
+    }
+
+    /// Process a task, and report its result to the service. Returns `true` if processing
+    /// the task panicked.
+    fn process(&mut self, task: Task) -> bool {
         let Task {
             fetch,
             channels,
             stream,
+            ..
         } = task;
+        let (policy, scope) = self.defaults.get();
+        self.policies.set_defaults(policy, scope);
+
         let remote = fetch.remote();
+        self.progress.start(match fetch.rid() {
+            Some(rid) => format!("fetch of {rid} from {remote}"),
+            None => format!("upload to {remote}"),
+        });
         let channels = channels::ChannelsFlush::new(self.handle.clone(), channels, remote, stream);
-        let result = self._process(fetch, stream, channels, self.notifications.clone());
+        let request = fetch.clone();
+        let notifs = self.notifications.clone();
+        let (result, panicked) = if channels.is_closed() {
+            // The stream was closed while the task was queued, eg. because the session
+            // was disconnected. There's nothing left to transfer.
+            log::debug!(target: "worker", "Skipping task for {remote}: stream {stream} is closed");
 
+            (FetchResult::closed(request), false)
+        } else {
+            match panic::catch_unwind(panic::AssertUnwindSafe(|| {
+                self._process(fetch, stream, channels, notifs)
+            })) {
+                Ok(result) => (result, false),
+                Err(payload) => {
+                    let reason = panic_reason(payload.as_ref());
+                    log::error!(target: "worker", "Worker panicked while processing task for {remote} on stream {stream}: {reason}");
+
+                    (FetchResult::panicked(request, reason), true)
+                }
+            }
+        };
+
+        if panicked {
+            // Counted before the result is reported, so that it's accounted for by the time
+            // the service learns about the panic.
+            self.progress.restarted();
+        }
         log::trace!(target: "worker", "Sending response back to service..");
 
         if self
@@ -216,6 +610,9 @@ impl Worker {
         {
             log::error!(target: "worker", "Unable to report fetch result: worker channel disconnected");
         }
+        self.progress.finish();
+
+        panicked
     }
 
     fn _process(
@@ -232,56 +629,204 @@ impl Worker {
                 refs_at,
                 // TODO: nowhere to use this currently
                 timeout: _timeout,
+                recover,
             } => {
                 log::debug!(target: "worker", "Worker processing outgoing fetch for {rid}");
-                let result = self.fetch(rid, remote, refs_at, channels, notifs);
-                FetchResult::Initiator { rid, result }
-            }
-            FetchRequest::Responder { remote } => {
-                log::debug!(target: "worker", "Worker processing incoming fetch for {remote} on stream {stream}..");
+                let result = self.fetch(rid, remote, refs_at, recover, channels, notifs);
 
-                let (mut stream_r, stream_w) = channels.split();
-                let header = match upload_pack::pktline::git_request(&mut stream_r) {
-                    Ok(header) => header,
-                    Err(e) => {
-                        return FetchResult::Responder {
-                            rid: None,
-                            result: Err(e.into()),
+                if let (Ok(r), Some(mirror)) = (&result, &self.mirror) {
+                    if !r.updated.is_empty() {
+                        mirror.send(rid).ok();
+                    }
+                }
+                if let (Ok(r), Some(updates)) = (&result, &self.updates) {
+                    if !r.updated.is_empty() {
+                        let update = updates::Update::new(rid, remote, r.updated.clone());
+                        if let Err(e) = updates.notify(&update) {
+                            log::warn!(target: "worker", "Failed to notify updates of {rid}: {e}");
                         }
                     }
-                };
-                log::debug!(target: "worker", "Spawning upload-pack process for {} on stream {stream}..", header.repo);
-
-                if let Err(e) = self.is_authorized(remote, header.repo) {
-                    return FetchResult::Responder {
-                        rid: Some(header.repo),
-                        result: Err(e),
-                    };
                 }
+                if let (Ok(r), Some(hooks)) = (&result, &self.hooks) {
+                    if !r.updated.is_empty() {
+                        hooks
+                            .send(hooks::Fetched {
+                                rid,
+                                remote,
+                                updated: r.updated.clone(),
+                                clone: r.clone,
+                            })
+                            .ok();
+                    }
+                }
+                FetchResult::Initiator { rid, result }
+            }
+            FetchRequest::Blobs { rid, remote, blobs } => {
+                log::debug!(target: "worker", "Worker processing outgoing fetch of {} blob(s) for {rid}", blobs.len());
+                let result = self.fetch_blobs(rid, remote, &blobs, channels);
+                FetchResult::Blobs { rid, result }
+            }
+            FetchRequest::Refs {
+                rid,
+                remote,
+                refs,
+                reply,
+            } => {
+                log::debug!(target: "worker", "Worker processing query of {} reference(s) of {rid}", refs.len());
+                let (recv, send) = channels.split();
+                let result = query::query(rid, &refs, recv, send);
 
-                let result =
-                    upload_pack::upload_pack(&self.nid, &self.storage, &header, stream_r, stream_w)
-                        .map(|_| ())
-                        .map_err(|e| e.into());
-                log::debug!(target: "worker", "Upload process on stream {stream} exited with result {result:?}");
+                log::debug!(target: "worker", "Query of {rid} from {remote} exited with result {result:?}");
+
+                FetchResult::Refs { rid, result, reply }
+            }
+            FetchRequest::Responder { remote } => {
+                log::debug!(target: "worker", "Worker processing incoming fetch for {remote} on stream {stream}..");
+
+                let start = time::Instant::now();
+                let (rid, cpu, result) = self.upload(remote, stream, &mut channels);
 
                 FetchResult::Responder {
-                    rid: Some(header.repo),
+                    rid,
                     result,
+                    sent: channels.sent(),
+                    elapsed: start.elapsed(),
+                    cpu,
                 }
             }
         }
     }
 
-    fn is_authorized(&self, remote: NodeId, rid: RepoId) -> Result<(), UploadError> {
-        let policy = self.policies.seed_policy(&rid)?.policy;
-        let repo = self.storage.repository(rid)?;
-        let doc = repo.identity_doc()?;
-        if !doc.is_visible_to(&remote) || policy == Policy::Block {
-            Err(UploadError::Unauthorized(remote, rid))
-        } else {
-            Ok(())
+    /// Serve a fetch to a remote. Returns the repository requested, if the request could
+    /// be read, and the CPU time used by the upload-pack process, if one was spawned.
+    fn upload(
+        &mut self,
+        remote: NodeId,
+        stream: StreamId,
+        channels: &mut channels::ChannelsFlush,
+    ) -> (
+        Option<RepoId>,
+        Option<time::Duration>,
+        Result<(), UploadError>,
+    ) {
+        let interrupt = channels.interrupt();
+        let (mut stream_r, stream_w) = channels.split();
+        let header = match upload_pack::pktline::request(&mut stream_r) {
+            Ok(upload_pack::pktline::Request::Git(header)) => header,
+            Ok(upload_pack::pktline::Request::Blobs(rid)) => {
+                let result = self.upload_blobs(remote, rid, stream_r, stream_w);
+                log::debug!(target: "worker", "Blob upload on stream {stream} exited with result {result:?}");
+
+                return (Some(rid), None, result);
+            }
+            Ok(upload_pack::pktline::Request::Refs(rid)) => {
+                let result = self.serve_refs(remote, rid, stream_r, stream_w);
+                log::debug!(target: "worker", "Reference query on stream {stream} exited with result {result:?}");
+
+                return (Some(rid), None, result);
+            }
+            Err(e) => return (None, None, Err(e.into())),
+        };
+        if let Err(e) = self.authorize(remote, header.repo) {
+            return (Some(header.repo), None, Err(e));
         }
+        log::debug!(target: "worker", "Spawning upload-pack process for {} on stream {stream}..", header.repo);
+
+        let (cpu, result) = match upload_pack::upload_pack(
+            &self.nid,
+            self.storage.as_ref(),
+            &header,
+            self.fetch_config.upload_limits,
+            &interrupt,
+            stream_r,
+            stream_w,
+        ) {
+            Ok(usage) => (
+                Some(usage.cpu),
+                usage
+                    .exceeded
+                    .map_or(Ok(()), |e| Err(UploadError::Exceeded(e))),
+            ),
+            Err(e) => (None, Err(e.into())),
+        };
+        log::debug!(target: "worker", "Upload process on stream {stream} exited with result {result:?}");
+
+        (Some(header.repo), cpu, result)
+    }
+
+    fn authorize(&self, remote: NodeId, rid: RepoId) -> Result<(), UploadError> {
+        access::authorize(
+            access::Requester::Peer(remote),
+            rid,
+            self.storage.as_ref(),
+            &self.policies,
+        )
+    }
+
+    fn upload_blobs(
+        &self,
+        remote: NodeId,
+        rid: RepoId,
+        recv: &mut channels::ChannelReader,
+        send: &mut channels::ChannelFlushWriter,
+    ) -> Result<(), UploadError> {
+        if !self.fetch_config.blobs {
+            return Err(UploadError::BlobsDisabled);
+        }
+        self.authorize(remote, rid)?;
+
+        let repo = self.storage.repository(rid)?;
+        let referenced = blobs::referenced(&repo.backend).map_err(RepositoryError::from)?;
+        let served = blob::upload(&repo.blobs(), &referenced, recv, send)?;
+
+        log::debug!(target: "worker", "Served {served} blob(s) of {rid} to {remote}");
+
+        Ok(())
+    }
+
+    fn serve_refs(
+        &self,
+        remote: NodeId,
+        rid: RepoId,
+        recv: &mut channels::ChannelReader,
+        send: &mut channels::ChannelFlushWriter,
+    ) -> Result<(), UploadError> {
+        self.authorize(remote, rid)?;
+
+        let repo = self.storage.repository(rid)?;
+        let served = query::serve(&repo, recv, send)?;
+
+        log::debug!(target: "worker", "Answered query of {served} reference(s) of {rid} from {remote}");
+
+        Ok(())
+    }
+
+    fn fetch_blobs(
+        &mut self,
+        rid: RepoId,
+        remote: NodeId,
+        wanted: &[git::Oid],
+        mut channels: channels::ChannelsFlush,
+    ) -> Result<Vec<git::Oid>, FetchError> {
+        let repo = self.storage.repository(rid)?;
+        let (recv, send) = channels.split();
+        let fetched = blob::download(rid, &repo.blobs(), wanted, recv, send)?;
+
+        log::debug!(target: "worker", "Fetched {}/{} blob(s) of {rid} from {remote}", fetched.len(), wanted.len());
+
+        Ok(fetched)
+    }
+
+    /// Get the blobs referenced by a repository that we don't have yet.
+    fn missing_blobs(&self, rid: RepoId) -> Result<Vec<git::Oid>, RepositoryError> {
+        let repo = self.storage.repository(rid)?;
+        let store = repo.blobs();
+        let missing = blobs::referenced(&repo.backend)?
+            .into_iter()
+            .filter(|oid| !store.contains(oid))
+            .collect();
+
+        Ok(missing)
     }
 
     fn fetch(
@@ -289,6 +834,7 @@ impl Worker {
         rid: RepoId,
         remote: NodeId,
         refs_at: Option<Vec<RefsAt>>,
+        recover: bool,
         channels: channels::ChannelsFlush,
         notifs: notifications::StoreWriter,
     ) -> Result<fetch::FetchResult, FetchError> {
@@ -296,6 +842,14 @@ impl Worker {
             limit,
             local,
             expiry,
+            pack_threads,
+            blobs,
+            refuse_diverged_sigrefs,
+            denied_refs,
+            max_namespace_refs,
+            ref_update_batch_size,
+            confirmations,
+            upload_limits: _,
         } = &self.fetch_config;
         // N.b. if the `rid` is blocked this will return an error, so
         // we won't continue with any further set up of the fetch.
@@ -306,71 +860,226 @@ impl Worker {
         let handle = fetch::Handle::new(
             rid,
             *local,
-            &self.storage,
+            self.storage.as_ref(),
             allowed,
             blocked,
             channels,
             notifs,
+            *pack_threads,
         )?;
-        let result = handle.fetch(
+        let known = match radicle::node::sigrefs::Store::tips(&self.db, &rid) {
+            Ok(tips) => tips,
+            Err(e) => {
+                log::warn!(target: "worker", "Failed to get known `rad/sigrefs` tips of {rid}: {e}");
+                BTreeMap::new()
+            }
+        };
+        let handle = match radicle::node::autoseed::Store::auto_seeded(&self.db, &rid) {
+            // Repositories seeded automatically are only kept if a followed node is
+            // one of their delegates.
+            Ok(Some(entry)) if entry.is_pending() => {
+                let followed = self
+                    .policies
+                    .follow_policies()?
+                    .filter(|f| f.policy == Policy::Allow)
+                    .map(|f| f.nid)
+                    .collect();
+                handle.with_required_delegates(followed)
+            }
+            Ok(_) => handle,
+            Err(e) => {
+                log::warn!(target: "worker", "Failed to get auto-seed entry of {rid}: {e}");
+                handle
+            }
+        };
+        let handle = handle
+            .with_known_sigrefs(known, *refuse_diverged_sigrefs)
+            .with_denied_refs(
+                denied_refs
+                    .clone()
+                    .with_categories(self.policies.ref_categories(&rid)?),
+            )
+            .with_max_refs(*max_namespace_refs)
+            .with_update_batch_size(*ref_update_batch_size)
+            .with_recovery(recover);
+        // N.b. the lock is held while the fetch runs, so that maintenance tasks don't run
+        // while updates are being applied. Fetches of the same repository share it.
+        let guard = self.handle.locks().read(rid);
+        let mut result = handle.fetch(
             rid,
-            &self.storage,
+            self.storage.as_ref(),
             &mut cache,
             &mut self.db,
+            &self.handle,
             *limit,
             remote,
             refs_at,
+            confirmations.policy(&rid),
         )?;
+        drop(guard);
+
+        if *blobs {
+            match self.missing_blobs(rid) {
+                Ok(missing) => result.blobs = missing,
+                Err(e) => log::warn!(target: "worker", "Failed to get missing blobs of {rid}: {e}"),
+            }
+        }
 
-        if let Err(e) = garbage::collect(&self.storage, rid, *expiry) {
-            // N.b. ensure that `git gc` works in debug mode.
-            debug_assert!(false, "`git gc` failed: {e}");
+        // N.b. garbage is only collected if no other task is using the repository, since
+        // `git gc` may remove objects that a concurrent fetch relies on. Skipping it is
+        // fine, as it runs after every fetch.
+        match self.handle.locks().try_write(rid) {
+            Some(_guard) => {
+                if let Err(e) = garbage::collect(self.storage.as_ref(), rid, *expiry) {
+                    // N.b. ensure that `git gc` works in debug mode.
+                    debug_assert!(false, "`git gc` failed: {e}");
 
-            log::warn!(target: "worker", "Failed to run `git gc`: {e}");
+                    log::warn!(target: "worker", "Failed to run `git gc`: {e}");
+                }
+            }
+            None => {
+                log::debug!(target: "worker", "Skipping `git gc` of {rid}: repository is in use")
+            }
+        }
+        // Refresh the repository statistics, now that objects were fetched and collected.
+        let stats = self
+            .storage
+            .repository(rid)
+            .and_then(|repo| Ok(stats::compute(&repo)?));
+        match stats {
+            Ok(stats) => {
+                if let Err(e) = radicle::node::stats::Store::set_stats(&mut self.db, &stats) {
+                    log::warn!(target: "worker", "Failed to cache statistics of {rid}: {e}");
+                }
+            }
+            Err(e) => log::warn!(target: "worker", "Failed to compute statistics of {rid}: {e}"),
         }
         Ok(result)
     }
 }
 
+/// Get the reason of a panic from its payload.
+fn panic_reason(payload: &(dyn Any + Send)) -> String {
+    if let Some(reason) = payload.downcast_ref::<&str>() {
+        reason.to_string()
+    } else if let Some(reason) = payload.downcast_ref::<String>() {
+        reason.clone()
+    } else {
+        String::from("unknown reason")
+    }
+}
+
+/// Set the niceness of the calling thread. Threads spawned by it afterwards, eg. to index
+/// packfiles, inherit it.
+#[cfg(target_os = "linux")]
+fn set_niceness(niceness: i32) -> io::Result<()> {
+    // SAFETY: `setpriority` has no memory safety requirements. On Linux, a `who` of `0`
+    // refers to the calling thread, rather than the whole process.
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, niceness) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_niceness(_niceness: i32) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "thread niceness is only supported on Linux",
+    ))
+}
+
 /// A pool of workers. One thread is allocated for each worker.
 pub struct Pool {
     pool: Vec<thread::JoinHandle<Result<(), chan::RecvError>>>,
+    progress: Vec<Arc<Progress>>,
+}
+
+/// A node identity whose tasks are run by the pool, with the state its workers need.
+pub struct Identity {
+    pub handle: Handle,
+    pub notifications: notifications::StoreWriter,
+    pub cache: cob::cache::StoreWriter,
+    pub db: radicle::node::Database,
+    pub config: Config,
+}
+
+impl Identity {
+    /// Create a worker for this identity.
+    fn worker(&self, progress: Arc<Progress>) -> Result<Worker, policy::Error> {
+        let config = &self.config;
+        let (policy, scope) = config.defaults.get();
+        let policies =
+            policy::Config::new(policy, scope, policy::Store::reader(&config.policies_db)?);
+
+        Ok(Worker {
+            nid: config.fetch.local,
+            handle: self.handle.clone(),
+            storage: config.storage.clone(),
+            fetch_config: config.fetch.clone(),
+            policies,
+            defaults: config.defaults.clone(),
+            notifications: self.notifications.clone(),
+            cache: self.cache.clone(),
+            db: self.db.clone(),
+            progress,
+            mirror: config.mirror.clone(),
+            hooks: config.hooks.clone(),
+            updates: config.updates.clone(),
+            policies_db: config.policies_db.clone(),
+        })
+    }
 }
 
 impl Pool {
-    /// Create a new worker pool with the given parameters.
+    /// Create a new worker pool of `capacity` threads, each running the tasks of all the
+    /// given identities. Threads are named after the first identity.
+    ///
+    /// The niceness of the threads is inherited from the node process if not set.
     pub fn with(
         tasks: chan::Receiver<Task>,
-        nid: NodeId,
-        handle: Handle,
-        notifications: notifications::StoreWriter,
-        cache: cob::cache::StoreWriter,
-        db: radicle::node::Database,
-        config: Config,
+        capacity: usize,
+        niceness: Option<i32>,
+        identities: NonEmpty<Identity>,
     ) -> Result<Self, policy::Error> {
-        let mut pool = Vec::with_capacity(config.capacity);
-        for i in 0..config.capacity {
-            let policies = policy::Config::new(
-                config.policy,
-                config.scope,
-                policy::Store::reader(&config.policies_db)?,
-            );
-            let worker = Worker {
-                nid,
+        let nid = identities.head.config.fetch.local;
+        let mut pool = Vec::with_capacity(capacity);
+        let mut progress = Vec::with_capacity(capacity);
+        for i in 0..capacity {
+            let runner = Runner {
                 tasks: tasks.clone(),
-                handle: handle.clone(),
-                storage: config.storage.clone(),
-                fetch_config: config.fetch.clone(),
-                policies,
-                notifications: notifications.clone(),
-                cache: cache.clone(),
-                db: db.clone(),
+                workers: BTreeMap::new(),
+                progress: Arc::default(),
             };
-            let thread = thread::spawn(&nid, format!("worker#{i}"), || worker.run());
+            let workers = identities
+                .iter()
+                .map(|id| Ok((id.config.fetch.local, id.worker(runner.progress.clone())?)))
+                .collect::<Result<_, policy::Error>>()?;
+            let runner = Runner { workers, ..runner };
+            progress.push(runner.progress.clone());
+
+            let thread = thread::spawn(&nid, format!("worker#{i}"), move || {
+                if let Some(niceness) = niceness {
+                    if let Err(e) = set_niceness(niceness) {
+                        log::warn!(target: "pool", "Unable to set worker niceness to {niceness}: {e}");
+                    }
+                }
+                runner.run()
+            });
 
             pool.push(thread);
         }
-        Ok(Self { pool })
+        Ok(Self { pool, progress })
+    }
+
+    /// Number of times workers recovered from a panic.
+    pub fn restarts(&self) -> u64 {
+        self.progress.iter().map(|p| p.restarts()).sum()
+    }
+
+    /// Progress of each worker, for the watchdog.
+    pub fn progress(&self) -> Vec<Arc<Progress>> {
+        self.progress.clone()
     }
 
     /// Run the worker pool.
@@ -387,3 +1096,307 @@ impl Pool {
         Ok(())
     }
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use std::os::fd::{AsRawFd, RawFd};
+    use std::path::Path;
+
+    use radicle::crypto::test::signer::MockSigner;
+    use radicle::crypto::Signer as _;
+    use radicle::git::UserInfo;
+    use radicle::storage::git::Repository;
+    use radicle::storage::{self, RemoteId};
+    use radicle::test::{arbitrary, fixtures};
+    use radicle::Storage;
+    use reactor::poller::popol;
+    use reactor::{Io, Reactor, Resource, ResourceId, ResourceType, Timestamp, WriteAtomic};
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::profile::Home;
+    use crate::runtime::Emitter;
+    use crate::test::assert_matches;
+    use crate::wire;
+    use crate::worker::backend::Backend;
+    use crate::Link;
+
+    /// Storage that panics when the given repository is opened.
+    struct Panicking {
+        storage: Storage,
+        rid: RepoId,
+    }
+
+    impl Backend for Panicking {
+        fn info(&self) -> &UserInfo {
+            Backend::info(&self.storage)
+        }
+
+        fn path(&self) -> &Path {
+            Backend::path(&self.storage)
+        }
+
+        fn path_of(&self, rid: &RepoId) -> PathBuf {
+            Backend::path_of(&self.storage, rid)
+        }
+
+        fn repository_ids(&self) -> Result<Vec<RepoId>, storage::Error> {
+            Backend::repository_ids(&self.storage)
+        }
+
+        fn contains(&self, rid: &RepoId) -> Result<bool, RepositoryError> {
+            Backend::contains(&self.storage, rid)
+        }
+
+        fn repository(&self, rid: RepoId) -> Result<Repository, RepositoryError> {
+            assert_ne!(rid, self.rid, "repository is corrupted");
+            Backend::repository(&self.storage, rid)
+        }
+
+        fn create(&self, rid: RepoId) -> Result<(Repository, TempDir), RepositoryError> {
+            Backend::create(&self.storage, rid)
+        }
+
+        fn install(&self, tmp: TempDir, rid: &RepoId) -> io::Result<()> {
+            Backend::install(&self.storage, tmp, rid)
+        }
+
+        fn invalidate_sigrefs(&self, rid: &RepoId, remote: &RemoteId) {
+            Backend::invalidate_sigrefs(&self.storage, rid, remote)
+        }
+    }
+
+    /// A resource that is never registered.
+    struct Unused;
+
+    impl AsRawFd for Unused {
+        fn as_raw_fd(&self) -> RawFd {
+            unreachable!()
+        }
+    }
+
+    impl io::Write for Unused {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            unreachable!()
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            unreachable!()
+        }
+    }
+
+    impl WriteAtomic for Unused {
+        fn is_ready_to_write(&self) -> bool {
+            unreachable!()
+        }
+
+        fn empty_write_buf(&mut self) -> io::Result<bool> {
+            unreachable!()
+        }
+
+        fn write_or_buf(&mut self, _buf: &[u8]) -> io::Result<()> {
+            unreachable!()
+        }
+    }
+
+    impl Resource for Unused {
+        type Event = ();
+
+        fn interests(&self) -> reactor::poller::IoType {
+            unreachable!()
+        }
+
+        fn handle_io(&mut self, _io: Io) -> Option<Self::Event> {
+            unreachable!()
+        }
+    }
+
+    /// Stands in for the service, forwarding the results of workers.
+    struct Service(chan::Sender<TaskResult>);
+
+    impl Iterator for Service {
+        type Item = reactor::Action<Unused, Unused>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            None
+        }
+    }
+
+    impl reactor::Handler for Service {
+        type Listener = Unused;
+        type Transport = Unused;
+        type Command = wire::host::Command;
+
+        fn tick(&mut self, _time: Timestamp) {}
+
+        fn handle_timer(&mut self) {}
+
+        fn handle_listener_event(&mut self, _id: ResourceId, _event: (), _time: Timestamp) {}
+
+        fn handle_transport_event(&mut self, _id: ResourceId, _event: (), _time: Timestamp) {}
+
+        fn handle_registered(&mut self, _fd: RawFd, _id: ResourceId, _ty: ResourceType) {}
+
+        fn handle_command(&mut self, (_, cmd): Self::Command) {
+            if let wire::Control::Worker(result) = cmd {
+                self.0.send(result).ok();
+            }
+        }
+
+        fn handle_error(&mut self, _err: reactor::Error<Self::Listener, Self::Transport>) {}
+
+        fn handover_listener(&mut self, _id: ResourceId, _listener: Self::Listener) {}
+
+        fn handover_transport(&mut self, _id: ResourceId, _transport: Self::Transport) {}
+    }
+
+    #[test]
+    fn test_worker_panic() {
+        let tmp = tempfile::tempdir().unwrap();
+        let signer = MockSigner::default();
+        let storage = fixtures::storage(tmp.path().join("storage"), &signer).unwrap();
+        let rid = arbitrary::gen::<RepoId>(1);
+        let remote = arbitrary::gen::<NodeId>(1);
+        let policies_db = tmp.path().join("policies.db");
+        let policies_path = policies_db.clone();
+        let defaults = Defaults::new(Policy::Block, policy::Scope::All);
+        let (results_send, results) = chan::unbounded();
+        let reactor = Reactor::new(Service(results_send), popol::Poller::new()).unwrap();
+        let handle = Handle::new(
+            Home::new(tmp.path().join("home")).unwrap(),
+            wire::Controller::new(*signer.public_key(), reactor.controller()),
+            Emitter::default(),
+            storage.clone(),
+            defaults.clone(),
+            Arc::new(signer.clone()),
+        );
+        let (tasks_send, tasks) = chan::unbounded();
+        let progress = Arc::<Progress>::default();
+
+        policy::Store::open(&policies_db).unwrap();
+
+        let worker = Worker {
+            nid: *signer.public_key(),
+            storage: Arc::new(Panicking {
+                storage: storage.clone(),
+                rid,
+            }),
+            fetch_config: FetchConfig {
+                limit: FetchLimit::default(),
+                local: *signer.public_key(),
+                expiry: garbage::Expiry::default(),
+                pack_threads: None,
+                blobs: true,
+                refuse_diverged_sigrefs: false,
+                denied_refs: radicle_fetch::DeniedRefs::default(),
+                max_namespace_refs: None,
+                ref_update_batch_size: None,
+                confirmations: radicle::node::config::Confirmations::default(),
+                upload_limits: upload_pack::Limits::default(),
+            },
+            handle,
+            policies: policy::Config::new(
+                Policy::Block,
+                policy::Scope::All,
+                policy::Store::reader(&policies_db).unwrap(),
+            ),
+            defaults,
+            notifications: notifications::StoreWriter::memory().unwrap(),
+            cache: cob::cache::StoreWriter::memory().unwrap(),
+            db: radicle::node::Database::memory().unwrap(),
+            policies_db,
+            progress: progress.clone(),
+            mirror: None,
+            hooks: None,
+            updates: None,
+        };
+        let runner = Runner {
+            tasks,
+            workers: BTreeMap::from([(*signer.public_key(), worker)]),
+            progress: progress.clone(),
+        };
+        let worker = std::thread::spawn(move || runner.run());
+        // Keep the other ends of the stream channels, so that the streams stay open.
+        let mut streams = Vec::new();
+        let mut task = |rid| {
+            let (send, recv) = (chan::unbounded(), chan::unbounded());
+            let task = Task {
+                local: *signer.public_key(),
+                fetch: FetchRequest::Blobs {
+                    rid,
+                    remote,
+                    blobs: vec![arbitrary::oid()],
+                },
+                stream: StreamId::git(Link::Outbound),
+                channels: channels::Channels::new(
+                    send.0,
+                    recv.1,
+                    time::Duration::from_secs(1),
+                    channels::BufferPool::new(1),
+                ),
+            };
+            streams.push((send.1, recv.0));
+            task
+        };
+        let result = |results: &chan::Receiver<TaskResult>| {
+            results
+                .recv_timeout(time::Duration::from_secs(6))
+                .unwrap()
+                .result
+        };
+
+        // The panic is reported as the result of the task.
+        tasks_send.send(task(rid)).unwrap();
+        assert_matches!(
+            result(&results),
+            FetchResult::Blobs { rid: r, result: Err(FetchError::Panicked(reason)) }
+                if r == rid && reason.contains("repository is corrupted")
+        );
+        // The worker keeps taking tasks.
+        let other = arbitrary::gen::<RepoId>(1);
+        tasks_send.send(task(other)).unwrap();
+        assert_matches!(
+            result(&results),
+            FetchResult::Blobs { rid: r, result: Err(FetchError::Repository(_)) } if r == other
+        );
+        tasks_send.send(task(rid)).unwrap();
+        assert_matches!(
+            result(&results),
+            FetchResult::Blobs {
+                result: Err(FetchError::Panicked(_)),
+                ..
+            }
+        );
+        assert_eq!(progress.restarts(), 2);
+
+        // The policy configuration can't be reloaded while its database is missing. The
+        // worker waits for it instead of exiting, and then takes tasks again.
+        tasks_send.send(task(other)).unwrap();
+        result(&results);
+        std::fs::remove_file(&policies_path).unwrap();
+        tasks_send.send(task(rid)).unwrap();
+        assert_matches!(
+            result(&results),
+            FetchResult::Blobs {
+                result: Err(FetchError::Panicked(_)),
+                ..
+            }
+        );
+        tasks_send.send(task(other)).unwrap();
+        assert!(results
+            .recv_timeout(time::Duration::from_millis(500))
+            .is_err());
+        assert!(!worker.is_finished());
+
+        policy::Store::open(&policies_path).unwrap();
+        assert_matches!(
+            result(&results),
+            FetchResult::Blobs { rid: r, result: Err(FetchError::Repository(_)) } if r == other
+        );
+        assert_eq!(progress.restarts(), 3);
+
+        drop(tasks_send);
+        worker.join().unwrap().unwrap_err();
+    }
+}
@@ -0,0 +1,165 @@
+//! Authorization of repository upload requests.
+//!
+//! Every frontend serving repositories, ie. the fetch responder, the `git://` daemon and the
+//! HTTP gateway, checks requests with [`authorize`] before reading anything from the repository
+//! or spawning any process.
+use radicle::identity::RepoId;
+use radicle::prelude::NodeId;
+use radicle::storage::ReadRepository;
+
+use crate::service::policy;
+
+use super::{Backend, UploadError};
+
+/// The party requesting an upload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Requester {
+    /// A peer, authenticated by its node ID.
+    Peer(NodeId),
+    /// An anonymous client, eg. of the `git://` daemon or HTTP gateway.
+    Anonymous,
+}
+
+impl Requester {
+    /// The error returned when the request is denied.
+    fn denied(&self, rid: RepoId) -> UploadError {
+        match self {
+            Self::Peer(nid) => UploadError::Unauthorized(*nid, rid),
+            Self::Anonymous => UploadError::Forbidden(rid),
+        }
+    }
+}
+
+/// Check whether `requester` may fetch the repository `rid`.
+///
+/// The repository must be seeded, present in storage, and visible to the requester. Peers must
+/// also be allowed by the repository's serving and fetching policies. Since anonymous clients
+/// aren't authenticated, only repositories that are public, both in their identity document and
+/// in our policies, and that any node may fetch, are served to them.
+///
+/// N.b. so as not to leak whether a repository exists, the same error is returned whatever the
+/// reason for denying the request. The reason is only logged.
+pub fn authorize<T>(
+    requester: Requester,
+    rid: RepoId,
+    storage: &dyn Backend,
+    policies: &policy::Config<T>,
+) -> Result<(), UploadError> {
+    match check(requester, rid, storage, policies) {
+        Ok(true) => Ok(()),
+        Ok(false) => {
+            log::debug!(target: "worker", "Denied upload of {rid} to {requester:?}");
+            Err(requester.denied(rid))
+        }
+        Err(e) => {
+            log::warn!(target: "worker", "Denied upload of {rid} to {requester:?}: {e}");
+            Err(requester.denied(rid))
+        }
+    }
+}
+
+fn check<T>(
+    requester: Requester,
+    rid: RepoId,
+    storage: &dyn Backend,
+    policies: &policy::Config<T>,
+) -> Result<bool, UploadError> {
+    // N.b. policies are checked first, so that storage isn't accessed for repositories
+    // we don't seed.
+    if !policies.is_seeding(&rid)? {
+        return Ok(false);
+    }
+    match requester {
+        Requester::Peer(nid) => {
+            if !policies.is_served_to(&rid, &nid)? || !policies.is_fetch_allowed(&rid, &nid)? {
+                return Ok(false);
+            }
+        }
+        Requester::Anonymous => {
+            if policies.is_private(&rid)? || policies.is_fetch_restricted(&rid)? {
+                return Ok(false);
+            }
+        }
+    }
+    if !storage.contains(&rid)? {
+        return Ok(false);
+    }
+    let doc = storage.repository(rid)?.identity_doc()?;
+
+    match requester {
+        Requester::Peer(nid) => Ok(doc.is_visible_to(&nid)),
+        Requester::Anonymous => Ok(!doc.visibility.is_private()),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+    use radicle::storage::ReadStorage as _;
+    use radicle::test::arbitrary;
+    use radicle::test::fixtures;
+
+    use crate::service::policy::{Policy, Scope};
+
+    #[test]
+    fn test_authorize() {
+        let tmp = tempfile::tempdir().unwrap();
+        let signer = radicle::crypto::test::signer::MockSigner::default();
+        let storage = fixtures::storage(tmp.path().join("storage"), &signer).unwrap();
+        let rid = storage.repositories().unwrap()[0].rid;
+        let missing = arbitrary::gen::<RepoId>(1);
+        let peer = Requester::Peer(arbitrary::gen::<NodeId>(1));
+
+        let mut store = policy::Store::<policy::store::Write>::memory().unwrap();
+        store.seed(&missing, Scope::All).unwrap();
+        let policies = policy::Config::new(Policy::Block, Scope::All, store);
+
+        // Repositories that aren't seeded or don't exist are denied the same way.
+        for rid in [rid, missing] {
+            assert!(matches!(
+                authorize(peer, rid, &storage, &policies),
+                Err(UploadError::Unauthorized(_, r)) if r == rid
+            ));
+            assert!(matches!(
+                authorize(Requester::Anonymous, rid, &storage, &policies),
+                Err(UploadError::Forbidden(r)) if r == rid
+            ));
+        }
+
+        let policies = policy::Config::new(
+            Policy::Allow,
+            Scope::All,
+            policy::Store::<policy::store::Write>::memory().unwrap(),
+        );
+        assert!(authorize(peer, rid, &storage, &policies).is_ok());
+        assert!(authorize(Requester::Anonymous, rid, &storage, &policies).is_ok());
+        assert!(authorize(peer, missing, &storage, &policies).is_err());
+
+        // Repositories that are private in our policies are only served to allowed peers.
+        let mut policies = policies;
+        let allowed = arbitrary::gen::<NodeId>(2);
+        policies.set_private(&rid, true).unwrap();
+        policies.allow_private(&rid, &allowed).unwrap();
+
+        assert!(authorize(Requester::Peer(allowed), rid, &storage, &policies).is_ok());
+        assert!(authorize(peer, rid, &storage, &policies).is_err());
+        assert!(matches!(
+            authorize(Requester::Anonymous, rid, &storage, &policies),
+            Err(UploadError::Forbidden(r)) if r == rid
+        ));
+
+        // Repositories that only some nodes may fetch are only served to those nodes.
+        policies.set_private(&rid, false).unwrap();
+        policies
+            .set_fetcher_policy(&rid, &allowed, Policy::Allow)
+            .unwrap();
+
+        assert!(authorize(Requester::Peer(allowed), rid, &storage, &policies).is_ok());
+        assert!(authorize(peer, rid, &storage, &policies).is_err());
+        assert!(matches!(
+            authorize(Requester::Anonymous, rid, &storage, &policies),
+            Err(UploadError::Forbidden(r)) if r == rid
+        ));
+    }
+}
@@ -0,0 +1,122 @@
+//! Storage backend used by workers.
+//!
+//! Workers and the fetch handle only access storage through the [`Backend`] trait, so that
+//! alternative storage layouts, eg. sharded directories or object store backed repositories,
+//! can be implemented without changes to the protocol code. [`Storage`] is the filesystem
+//! implementation.
+use std::io;
+use std::path::{Path, PathBuf};
+
+use radicle::git::UserInfo;
+use radicle::identity::RepoId;
+use radicle::storage::git::Repository;
+use radicle::storage::{self, ReadStorage, RemoteId, RepositoryError};
+use radicle::Storage;
+use tempfile::TempDir;
+
+/// Repository storage, as accessed by workers.
+pub trait Backend: Send + Sync {
+    /// The local user, ie. the owner of the storage.
+    fn info(&self) -> &UserInfo;
+    /// Root of the storage.
+    fn path(&self) -> &Path;
+    /// Path of the Git directory of a repository. The repository may not exist.
+    fn path_of(&self, rid: &RepoId) -> PathBuf;
+    /// Identifiers of the stored repositories.
+    fn repository_ids(&self) -> Result<Vec<RepoId>, storage::Error>;
+    /// Check whether the repository is in storage.
+    fn contains(&self, rid: &RepoId) -> Result<bool, RepositoryError>;
+    /// Open a stored repository.
+    fn repository(&self, rid: RepoId) -> Result<Repository, RepositoryError>;
+    /// Create a repository in a temporary location, eg. to clone it. The repository is added
+    /// to storage with [`Backend::install`], and discarded if the [`TempDir`] is dropped
+    /// instead.
+    ///
+    /// # Errors
+    ///   - Will fail if the repository is in storage already.
+    fn create(&self, rid: RepoId) -> Result<(Repository, TempDir), RepositoryError>;
+    /// Add a repository created with [`Backend::create`] to storage.
+    ///
+    /// # Errors
+    ///   - Will fail if the repository is in storage already.
+    fn install(&self, tmp: TempDir, rid: &RepoId) -> io::Result<()>;
+    /// Evict the cached signed refs of a remote, after its `rad/sigrefs` were updated.
+    fn invalidate_sigrefs(&self, rid: &RepoId, remote: &RemoteId);
+}
+
+impl Backend for Storage {
+    fn info(&self) -> &UserInfo {
+        ReadStorage::info(self)
+    }
+
+    fn path(&self) -> &Path {
+        ReadStorage::path(self)
+    }
+
+    fn path_of(&self, rid: &RepoId) -> PathBuf {
+        ReadStorage::path_of(self, rid)
+    }
+
+    fn repository_ids(&self) -> Result<Vec<RepoId>, storage::Error> {
+        Storage::repository_ids(self)
+    }
+
+    fn contains(&self, rid: &RepoId) -> Result<bool, RepositoryError> {
+        ReadStorage::contains(self, rid)
+    }
+
+    fn repository(&self, rid: RepoId) -> Result<Repository, RepositoryError> {
+        ReadStorage::repository(self, rid)
+    }
+
+    fn create(&self, rid: RepoId) -> Result<(Repository, TempDir), RepositoryError> {
+        self.lock_repository(rid)
+    }
+
+    fn install(&self, tmp: TempDir, rid: &RepoId) -> io::Result<()> {
+        let to = ReadStorage::path_of(self, rid);
+
+        if to.exists() {
+            log::warn!(target: "worker", "Refusing to move cloned repository {rid} already exists");
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("repository already exists {:?}", to),
+            ));
+        }
+        std::fs::rename(tmp.path(), to)
+    }
+
+    fn invalidate_sigrefs(&self, rid: &RepoId, remote: &RemoteId) {
+        self.cache().invalidate_remote(rid, remote);
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use radicle::test::arbitrary;
+    use radicle::test::fixtures;
+
+    use super::*;
+
+    #[test]
+    fn test_create_install() {
+        let tmp = tempfile::tempdir().unwrap();
+        let signer = radicle::crypto::test::signer::MockSigner::default();
+        let storage = fixtures::storage(tmp.path().join("storage"), &signer).unwrap();
+        let rid = storage.repositories().unwrap()[0].rid;
+        let backend: &dyn Backend = &storage;
+
+        // Stored repositories can't be created again, or installed over.
+        assert!(backend.create(rid).is_err());
+
+        let other = arbitrary::gen::<RepoId>(1);
+        let (_, tmp) = backend.create(other).unwrap();
+        assert!(!backend.path_of(&other).exists());
+        backend.install(tmp, &rid).unwrap_err();
+
+        let (_, tmp) = backend.create(other).unwrap();
+        backend.install(tmp, &other).unwrap();
+        assert!(backend.path_of(&other).exists());
+    }
+}
@@ -0,0 +1,260 @@
+//! Transfer of large files referenced by repositories. See [`radicle::storage::blobs`].
+//!
+//! Blobs are transferred on Git streams. The fetching side opens the stream with a
+//! `rad-blob-upload /<rid>` request packet-line instead of a `git-upload-pack` one,
+//! and then requests blobs one at a time:
+//!
+//! 1. The fetcher sends the blob hash (20 bytes), followed by the offset to resume
+//!    from (8 bytes, big-endian).
+//! 2. The server replies with the size of the blob (8 bytes, big-endian), or
+//!    [`UNAVAILABLE`] if it doesn't have it, or won't serve it.
+//! 3. The server sends the blob data starting at the offset, in chunks of at most
+//!    [`CHUNK_SIZE`] bytes, each prefixed with its length (4 bytes, big-endian). An
+//!    empty chunk marks the end of the blob.
+//!
+//! When the fetcher is done, it signals the end of the stream.
+use std::collections::BTreeSet;
+use std::io::{self, Read, Seek as _, Write};
+
+use radicle::git::Oid;
+use radicle::prelude::RepoId;
+use radicle::storage::blobs::{self, Blobs, CHUNK_SIZE};
+use radicle_fetch::transport::SignalEof;
+
+/// Size sent in place of a blob's size when the blob is not available.
+pub const UNAVAILABLE: u64 = u64::MAX;
+/// Command of the request packet-line used to open a blob transfer.
+pub const COMMAND: &str = "rad-blob-upload";
+
+/// Error returned when downloading blobs.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Blobs(#[from] blobs::Error),
+    #[error("chunk of {0} bytes exceeds the maximum chunk size")]
+    ChunkTooLarge(usize),
+    #[error("received more data than the size of blob {0}")]
+    Overflow(Oid),
+}
+
+/// Serve the blobs requested by a fetcher. Only blobs in the `referenced` set are
+/// served. Returns the number of blobs that were served.
+pub fn upload<R, W>(
+    blobs: &Blobs,
+    referenced: &BTreeSet<Oid>,
+    mut recv: R,
+    mut send: W,
+) -> io::Result<usize>
+where
+    R: Read,
+    W: Write,
+{
+    let mut served = 0;
+    let mut chunk = vec![0; CHUNK_SIZE];
+
+    loop {
+        let mut request = [0u8; 28];
+        match recv.read_exact(&mut request) {
+            Ok(()) => {}
+            // The fetcher is done.
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(served),
+            Err(e) => return Err(e),
+        }
+        let (oid, offset) = request.split_at(20);
+        let oid = Oid::try_from(oid).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let offset = u64::from_be_bytes(offset.try_into().expect("offset is 8 bytes"));
+
+        let blob = if referenced.contains(&oid) {
+            blobs
+                .get(&oid)
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+        } else {
+            None
+        };
+        let Some((mut file, size)) = blob else {
+            log::debug!(target: "worker", "Blob {oid} is not available for upload");
+            send.write_all(&UNAVAILABLE.to_be_bytes())?;
+            continue;
+        };
+        send.write_all(&size.to_be_bytes())?;
+
+        if offset < size {
+            file.seek(io::SeekFrom::Start(offset))?;
+            loop {
+                let n = file.read(&mut chunk[4..])?;
+                if n == 0 {
+                    break;
+                }
+                chunk[..4].copy_from_slice(&(n as u32).to_be_bytes());
+                send.write_all(&chunk[..n + 4])?;
+            }
+        }
+        send.write_all(&0u32.to_be_bytes())?;
+        served += 1;
+    }
+}
+
+/// Download blobs of a repository from a remote. Blobs that were partially downloaded
+/// before are resumed. Returns the blobs that were downloaded successfully.
+pub fn download<R, W>(
+    rid: RepoId,
+    blobs: &Blobs,
+    wanted: &[Oid],
+    mut recv: R,
+    send: &mut W,
+) -> Result<Vec<Oid>, Error>
+where
+    R: Read,
+    W: Write + SignalEof<Error = io::Error>,
+{
+    let mut downloaded = Vec::new();
+    let mut chunk = vec![0; CHUNK_SIZE];
+
+    send.write_all(&request(rid))?;
+
+    for oid in wanted {
+        let mut partial = blobs.download(*oid)?;
+        let mut request = Vec::with_capacity(28);
+        request.extend_from_slice(oid.as_bytes());
+        request.extend_from_slice(&partial.offset().to_be_bytes());
+        send.write_all(&request)?;
+
+        let mut size = [0u8; 8];
+        recv.read_exact(&mut size)?;
+        let size = u64::from_be_bytes(size);
+
+        if size == UNAVAILABLE {
+            log::debug!(target: "worker", "Blob {oid} of {rid} is not available from remote");
+            continue;
+        }
+        if partial.offset() > size {
+            // Our partial download can't be of this blob. The remote doesn't send any
+            // data in that case, so we just start over next time.
+            let mut end = [0u8; 4];
+            recv.read_exact(&mut end)?;
+            partial.discard()?;
+
+            continue;
+        }
+        loop {
+            let mut len = [0u8; 4];
+            recv.read_exact(&mut len)?;
+            let len = u32::from_be_bytes(len) as usize;
+
+            if len == 0 {
+                break;
+            }
+            if len > CHUNK_SIZE {
+                return Err(Error::ChunkTooLarge(len));
+            }
+            recv.read_exact(&mut chunk[..len])?;
+            partial.write(&chunk[..len])?;
+
+            if partial.offset() > size {
+                return Err(Error::Overflow(*oid));
+            }
+        }
+        match partial.finish() {
+            Ok(()) => downloaded.push(*oid),
+            Err(blobs::Error::Mismatch { expected, actual }) => {
+                log::warn!(target: "worker", "Discarding blob {expected} of {rid}: got {actual}");
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    send.eof()?;
+
+    Ok(downloaded)
+}
+
+/// The packet-line sent to open a blob transfer for a repository.
+pub fn request(rid: RepoId) -> Vec<u8> {
+    let line = format!("{COMMAND} /{}\0", rid.canonical());
+    let mut pktline = format!("{:04x}", line.len() + 4).into_bytes();
+    pktline.extend_from_slice(line.as_bytes());
+    pktline
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::arbitrary;
+
+    /// Writer that discards the end of the stream signal.
+    struct Writer<'a>(&'a mut Vec<u8>);
+
+    impl Write for Writer<'_> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SignalEof for Writer<'_> {
+        type Error = io::Error;
+
+        fn eof(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_blob_transfer() {
+        let tmp = tempfile::tempdir().expect("temporary directory can be created");
+        let rid = arbitrary::gen::<RepoId>(1);
+        let ours = Blobs::open(tmp.path().join("ours"));
+        let theirs = Blobs::open(tmp.path().join("theirs"));
+        let data = (0..CHUNK_SIZE * 2 + 7).map(|i| i as u8).collect::<Vec<_>>();
+        let oid = theirs.put(data.as_slice()).expect("blob can be stored");
+        let unknown = Oid::from(
+            radicle::git::raw::Oid::hash_object(radicle::git::raw::ObjectType::Blob, b"unknown")
+                .expect("hash can be computed"),
+        );
+
+        // Resume a download that was interrupted after the first chunk.
+        let mut partial = ours.download(oid).expect("download can be started");
+        partial
+            .write(&data[..CHUNK_SIZE])
+            .expect("chunk can be written");
+        drop(partial);
+
+        // Serve the requests the fetcher is expected to send.
+        let mut requests = Vec::new();
+        requests.extend_from_slice(oid.as_bytes());
+        requests.extend_from_slice(&(CHUNK_SIZE as u64).to_be_bytes());
+        requests.extend_from_slice(unknown.as_bytes());
+        requests.extend_from_slice(&0u64.to_be_bytes());
+
+        let mut responses = Vec::new();
+        let served = upload(
+            &theirs,
+            &BTreeSet::from([oid]),
+            requests.as_slice(),
+            &mut responses,
+        )
+        .expect("upload succeeds");
+        assert_eq!(served, 1);
+
+        let mut sent = Vec::new();
+        let downloaded = download(
+            rid,
+            &ours,
+            &[oid, unknown],
+            responses.as_slice(),
+            &mut Writer(&mut sent),
+        )
+        .expect("download succeeds");
+
+        let mut expected = request(rid);
+        expected.extend_from_slice(&requests);
+        assert_eq!(sent, expected);
+        assert_eq!(downloaded, vec![oid]);
+        assert!(ours.contains(&oid));
+        assert!(!ours.contains(&unknown));
+    }
+}
@@ -1,13 +1,18 @@
 use std::convert::Infallible;
 use std::io::{Read, Write};
 use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::{fmt, io, time};
 
 use crossbeam_channel as chan;
 use radicle::node::NodeId;
 
 use crate::runtime::Handle;
-use crate::wire::StreamId;
+use crate::wire::{StreamId, MAX_STREAM_FRAME_SIZE};
+
+/// Default number of buffers kept for reuse by a [`BufferPool`].
+pub const DEFAULT_BUFFER_POOL_SIZE: usize = 256;
 
 /// A reader and writer pair that can be used in the fetch protocol.
 ///
@@ -16,17 +21,20 @@ use crate::wire::StreamId;
 pub struct ChannelsFlush {
     receiver: ChannelReader,
     sender: ChannelFlushWriter,
+    closed: Arc<AtomicBool>,
 }
 
 impl ChannelsFlush {
     pub fn new(handle: Handle, channels: Channels, remote: NodeId, stream: StreamId) -> Self {
         Self {
+            closed: channels.closed,
             receiver: channels.receiver,
             sender: ChannelFlushWriter {
                 writer: channels.sender,
                 stream,
                 handle,
                 remote,
+                sent: 0,
             },
         }
     }
@@ -34,6 +42,22 @@ impl ChannelsFlush {
     pub fn split(&mut self) -> (&mut ChannelReader, &mut ChannelFlushWriter) {
         (&mut self.receiver, &mut self.sender)
     }
+
+    /// Number of bytes sent so far.
+    pub fn sent(&self) -> u64 {
+        self.sender.sent
+    }
+
+    /// Whether the stream was closed by the other end, eg. because the session with the
+    /// remote was disconnected.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    /// Flag set when the stream is closed. Long-running tasks check it to abort promptly.
+    pub fn interrupt(&self) -> Arc<AtomicBool> {
+        self.closed.clone()
+    }
 }
 
 impl radicle_fetch::transport::ConnectionStream for ChannelsFlush {
@@ -73,10 +97,65 @@ impl<T> fmt::Debug for ChannelEvent<T> {
     }
 }
 
+/// A pool of stream data buffers, shared by the wire and the workers.
+///
+/// Workers fill buffers taken from the pool and hand them over to the wire,
+/// which returns them once their data is encoded. This way, large transfers
+/// don't allocate a new buffer for every frame sent.
+#[derive(Clone)]
+pub struct BufferPool {
+    sender: chan::Sender<Vec<u8>>,
+    receiver: chan::Receiver<Vec<u8>>,
+}
+
+impl BufferPool {
+    /// Create a pool keeping at most `size` buffers around.
+    pub fn new(size: usize) -> Self {
+        let (sender, receiver) = chan::bounded(size);
+
+        Self { sender, receiver }
+    }
+
+    /// Get an empty buffer, large enough to hold one stream frame.
+    pub fn get(&self) -> Vec<u8> {
+        self.receiver
+            .try_recv()
+            .unwrap_or_else(|_| Vec::with_capacity(MAX_STREAM_FRAME_SIZE))
+    }
+
+    /// Return a buffer to the pool. The buffer is dropped if the pool is full,
+    /// or if it isn't a stream frame buffer.
+    pub fn put(&self, mut buf: Vec<u8>) {
+        if !(MAX_STREAM_FRAME_SIZE..=MAX_STREAM_FRAME_SIZE * 2).contains(&buf.capacity()) {
+            return;
+        }
+        buf.clear();
+        self.sender.try_send(buf).ok();
+    }
+
+    /// Number of buffers available for reuse.
+    pub fn len(&self) -> usize {
+        self.receiver.len()
+    }
+
+    /// Whether there are no buffers available for reuse.
+    pub fn is_empty(&self) -> bool {
+        self.receiver.is_empty()
+    }
+}
+
+impl Default for BufferPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUFFER_POOL_SIZE)
+    }
+}
+
 /// Worker channels for communicating through the git stream with the remote.
 pub struct Channels<T = Vec<u8>> {
     sender: ChannelWriter<T>,
     receiver: ChannelReader<T>,
+    /// Set when either end of the channels is closed.
+    closed: Arc<AtomicBool>,
 }
 
 impl<T: AsRef<[u8]>> Channels<T> {
@@ -84,19 +163,36 @@ impl<T: AsRef<[u8]>> Channels<T> {
         sender: chan::Sender<ChannelEvent<T>>,
         receiver: chan::Receiver<ChannelEvent<T>>,
         timeout: time::Duration,
+        pool: BufferPool,
     ) -> Self {
-        let sender = ChannelWriter { sender, timeout };
+        let sender = ChannelWriter {
+            sender,
+            timeout,
+            pool,
+        };
         let receiver = ChannelReader::new(receiver, timeout);
 
-        Self { sender, receiver }
+        Self {
+            sender,
+            receiver,
+            closed: Arc::default(),
+        }
     }
 
-    pub fn pair(timeout: time::Duration) -> io::Result<(Channels<T>, Channels<T>)> {
+    pub fn pair(
+        timeout: time::Duration,
+        pool: BufferPool,
+    ) -> io::Result<(Channels<T>, Channels<T>)> {
         let (l_send, r_recv) = chan::unbounded::<ChannelEvent<T>>();
         let (r_send, l_recv) = chan::unbounded::<ChannelEvent<T>>();
 
-        let l = Channels::new(l_send, l_recv, timeout);
-        let r = Channels::new(r_send, r_recv, timeout);
+        let mut l = Channels::new(l_send, l_recv, timeout, pool.clone());
+        let mut r = Channels::new(r_send, r_recv, timeout, pool);
+
+        // N.b. both ends share the flag, so that closing one interrupts the other.
+        let closed = Arc::new(AtomicBool::new(false));
+        l.closed = closed.clone();
+        r.closed = closed;
 
         Ok((l, r))
     }
@@ -109,9 +205,16 @@ impl<T: AsRef<[u8]>> Channels<T> {
         self.sender.send(event)
     }
 
+    /// Close the channels, notifying the other end.
     pub fn close(self) -> Result<(), chan::SendError<ChannelEvent<T>>> {
+        self.closed.store(true, Ordering::Relaxed);
         self.sender.close()
     }
+
+    /// Whether the channels were closed by either end.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
 }
 
 /// Wraps a [`chan::Receiver`] and provides it with [`io::Read`].
@@ -172,6 +275,8 @@ impl Read for ChannelReader<Vec<u8>> {
 struct ChannelWriter<T = Vec<u8>> {
     sender: chan::Sender<ChannelEvent<T>>,
     timeout: time::Duration,
+    /// Buffers used to send data.
+    pool: BufferPool,
 }
 
 /// Wraps a [`ChannelWriter`] alongside the associated [`Handle`] and [`NodeId`].
@@ -184,6 +289,43 @@ pub struct ChannelFlushWriter<T = Vec<u8>> {
     handle: Handle,
     stream: StreamId,
     remote: NodeId,
+    /// Number of bytes sent.
+    sent: u64,
+}
+
+impl ChannelFlushWriter<Vec<u8>> {
+    /// Send everything read from `reader` to the remote, until EOF.
+    ///
+    /// Unlike [`io::copy`], data is read straight into pooled buffers which are
+    /// handed over to the wire as-is, avoiding intermediate copies and allocations.
+    pub fn copy_from<R: Read + ?Sized>(&mut self, reader: &mut R) -> io::Result<u64> {
+        let mut copied = 0;
+
+        loop {
+            let mut buf = self.writer.pool.get();
+            buf.resize(MAX_STREAM_FRAME_SIZE, 0);
+
+            let n = match reader.read(&mut buf) {
+                Ok(0) => {
+                    self.writer.pool.put(buf);
+                    return Ok(copied);
+                }
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                    self.writer.pool.put(buf);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            buf.truncate(n);
+
+            self.writer.send(buf)?;
+            self.sent += n as u64;
+            self.flush()?;
+
+            copied += n as u64;
+        }
+    }
 }
 
 impl radicle_fetch::transport::SignalEof for ChannelFlushWriter<Vec<u8>> {
@@ -197,8 +339,15 @@ impl radicle_fetch::transport::SignalEof for ChannelFlushWriter<Vec<u8>> {
 
 impl Write for ChannelFlushWriter<Vec<u8>> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        let n = buf.len();
-        self.writer.send(buf.to_vec())?;
+        // N.b. writes are capped to the frame size, so that pooled buffers never
+        // need to grow, and the wire never needs to split them.
+        let n = buf.len().min(MAX_STREAM_FRAME_SIZE);
+        let mut data = self.writer.pool.get();
+        data.extend_from_slice(&buf[..n]);
+
+        self.writer.send(data)?;
+        self.sent += n as u64;
+
         Ok(n)
     }
 
@@ -244,3 +393,22 @@ impl<T: AsRef<[u8]>> ChannelWriter<T> {
         self.sender.send(ChannelEvent::Close)
     }
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_close_interrupts_other_end() {
+        let (wire, mut worker) =
+            Channels::<Vec<u8>>::pair(time::Duration::from_secs(1), BufferPool::default()).unwrap();
+        assert!(!worker.is_closed());
+
+        wire.close().unwrap();
+        assert!(worker.is_closed());
+
+        let err = worker.receiver.read(&mut [0; 8]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionReset);
+    }
+}
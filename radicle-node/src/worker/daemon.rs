@@ -0,0 +1,231 @@
+//! A read-only `git://` frontend to the node's storage.
+//!
+//! Allows regular Git clients to clone and fetch public repositories
+//! directly from the node, eg. `git clone git://127.0.0.1:9418/<rid>`,
+//! without going through the `rad` remote helper.
+use std::io::Write as _;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::{io, net, time};
+
+use radicle::identity::RepoId;
+use radicle::prelude::NodeId;
+use radicle::Storage;
+
+use crate::runtime::thread;
+use crate::service::policy;
+
+use super::resolve::Resolver;
+use super::{access, upload_pack, Defaults, UploadError};
+
+/// How long to wait for a client to send its request, before dropping the connection.
+pub const REQUEST_TIMEOUT: time::Duration = time::Duration::from_secs(9);
+
+/// Git daemon configuration.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Git storage.
+    pub storage: Storage,
+    /// Default policy and scope.
+    pub defaults: Defaults,
+    /// Path to the policies database.
+    pub policies_db: PathBuf,
+    /// Resource limits of the upload-pack processes serving requests.
+    pub limits: upload_pack::Limits,
+    /// Resolves the repository names requested by clients.
+    pub resolver: Resolver,
+    /// Connections being served.
+    pub connections: Connections,
+}
+
+/// Counts the connections being served, up to a maximum.
+#[derive(Debug, Clone)]
+pub struct Connections {
+    /// Number of connections being served.
+    active: Arc<AtomicUsize>,
+    /// Maximum number of connections served at once.
+    max: usize,
+}
+
+impl Connections {
+    /// Create a new counter, allowing up to `max` connections at once.
+    pub fn new(max: usize) -> Self {
+        Self {
+            active: Arc::default(),
+            max,
+        }
+    }
+
+    /// Take a connection slot, if any is free. The slot is freed when the returned
+    /// [`Permit`] is dropped.
+    pub fn acquire(&self) -> Option<Permit> {
+        self.active
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                (n < self.max).then_some(n + 1)
+            })
+            .ok()
+            .map(|_| Permit(self.active.clone()))
+    }
+}
+
+/// A connection slot, held while the connection is served.
+#[derive(Debug)]
+pub struct Permit(Arc<AtomicUsize>);
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Serves repositories over the `git://` protocol.
+///
+/// Only public repositories that aren't blocked by the node's seeding
+/// policy are served.
+pub struct Daemon {
+    nid: NodeId,
+    listener: net::TcpListener,
+    config: Config,
+}
+
+impl Daemon {
+    /// Bind the daemon to the given address.
+    pub fn bind(nid: NodeId, addr: net::SocketAddr, config: Config) -> io::Result<Self> {
+        let listener = net::TcpListener::bind(addr)?;
+
+        Ok(Self {
+            nid,
+            listener,
+            config,
+        })
+    }
+
+    /// The address the daemon is listening on.
+    pub fn local_addr(&self) -> io::Result<net::SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accept connections, serving each one in its own thread. Connections beyond the
+    /// limit are rejected.
+    ///
+    /// Blocks until the listener returns an error.
+    pub fn run(self) -> io::Result<()> {
+        log::info!(target: "daemon", "Serving git:// requests on {}..", self.local_addr()?);
+
+        loop {
+            let (mut stream, addr) = self.listener.accept()?;
+            let Some(permit) = self.config.connections.acquire() else {
+                log::warn!(target: "daemon", "Rejecting connection from {addr}: too many connections");
+                stream.write_all(&pktline_err("too many connections")).ok();
+
+                continue;
+            };
+            let nid = self.nid;
+            let config = self.config.clone();
+
+            log::debug!(target: "daemon", "Accepted connection from {addr}");
+
+            thread::spawn(&self.nid, "git-daemon", move || {
+                let _permit = permit;
+
+                match serve(&nid, stream, &config) {
+                    Ok(rid) => {
+                        log::debug!(target: "daemon", "Served {rid} to {addr}");
+                    }
+                    Err(e) => {
+                        log::warn!(target: "daemon", "Failed to serve request from {addr}: {e}");
+                    }
+                }
+            });
+        }
+    }
+}
+
+/// Serve a single `git-upload-pack` request on the given stream.
+fn serve(nid: &NodeId, mut stream: net::TcpStream, config: &Config) -> Result<RepoId, UploadError> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+
+    let header = upload_pack::pktline::git_request(&mut stream, |name| {
+        config.resolver.resolve(name, &config.storage)
+    })
+    .map_err(UploadError::PacketLine)?;
+    if let Err(e) = authorize(header.repo, config) {
+        // N.b. we don't want to leak the existence of a repository, so the
+        // same error is sent whether it's missing or not authorized.
+        stream
+            .write_all(&pktline_err("access denied or repository not exported"))
+            .ok();
+        return Err(e);
+    }
+    // N.b. once the request is read, the client may stay silent for a while,
+    // eg. while the pack is being generated.
+    stream.set_read_timeout(None)?;
+
+    let send = stream.try_clone()?;
+    let usage = upload_pack::upload_pack(
+        nid,
+        &config.storage,
+        &header,
+        config.limits,
+        &AtomicBool::new(false),
+        stream,
+        send,
+    )?;
+    if let Some(exceeded) = usage.exceeded {
+        return Err(UploadError::Exceeded(exceeded));
+    }
+    Ok(header.repo)
+}
+
+/// Check whether an anonymous client may fetch the given repository.
+/// See [`access::authorize`].
+pub(super) fn authorize(rid: RepoId, config: &Config) -> Result<(), UploadError> {
+    let (policy, scope) = config.defaults.get();
+    let policies = policy::Config::new(policy, scope, policy::Store::reader(&config.policies_db)?);
+
+    access::authorize(
+        access::Requester::Anonymous,
+        rid,
+        &config.storage,
+        &policies,
+    )
+}
+
+/// Encode an `ERR` packet-line, which Git clients display to the user.
+fn pktline_err(msg: &str) -> Vec<u8> {
+    let line = format!("ERR {msg}\n");
+    let mut pkt = format!("{:04x}", line.len() + upload_pack::pktline::HEADER_LEN).into_bytes();
+    pkt.extend_from_slice(line.as_bytes());
+    pkt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pktline_err() {
+        assert_eq!(
+            pktline_err("access denied"),
+            b"0016ERR access denied\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_connections() {
+        let connections = Connections::new(2);
+        let first = connections.acquire().unwrap();
+        let second = connections.clone().acquire().unwrap();
+
+        assert!(connections.acquire().is_none());
+        drop(first);
+
+        let third = connections.acquire().unwrap();
+        assert!(connections.acquire().is_none());
+        drop(second);
+        drop(third);
+
+        assert_eq!(connections.active.load(Ordering::Acquire), 0);
+    }
+}
@@ -1,6 +1,6 @@
 pub mod error;
 
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::str::FromStr;
 
 use localtime::LocalTime;
@@ -8,14 +8,16 @@ use localtime::LocalTime;
 use radicle::crypto::PublicKey;
 use radicle::identity::DocAt;
 use radicle::prelude::RepoId;
+use radicle::storage::git::{Repository, Validation};
 use radicle::storage::refs::RefsAt;
-use radicle::storage::{
-    ReadRepository, ReadStorage as _, RefUpdate, RemoteRepository, WriteRepository as _,
-};
-use radicle::{cob, git, node, Storage};
-use radicle_fetch::{Allowed, BlockList, FetchLimit};
+use radicle::storage::{ReadRepository, RefUpdate, RemoteRepository, WriteRepository as _};
+use radicle::{cob, git, node};
+use radicle_fetch::git::refs::{Reason, Rejected};
+use radicle_fetch::{Allowed, BlockList, DeniedRefs, FetchLimit};
 
 use super::channels::ChannelsFlush;
+use super::Backend;
+use crate::runtime;
 
 #[derive(Debug, Clone)]
 pub struct FetchResult {
@@ -27,6 +29,10 @@ pub struct FetchResult {
     pub clone: bool,
     /// Identity doc of fetched repo.
     pub doc: DocAt,
+    /// Blobs referenced by the repository that we don't have yet.
+    pub blobs: Vec<git::Oid>,
+    /// Summary of the updated references, per namespace.
+    pub summary: BTreeMap<PublicKey, node::NamespaceUpdates>,
 }
 
 impl FetchResult {
@@ -36,6 +42,8 @@ impl FetchResult {
             namespaces: HashSet::new(),
             clone: false,
             doc,
+            blobs: vec![],
+            summary: BTreeMap::new(),
         }
     }
 }
@@ -44,6 +52,8 @@ pub enum Handle {
     Clone {
         handle: radicle_fetch::Handle<ChannelsFlush>,
         tmp: tempfile::TempDir,
+        /// If set, the clone is only kept if one of these nodes is a delegate.
+        delegates: Option<BTreeSet<PublicKey>>,
     },
     Pull {
         handle: radicle_fetch::Handle<ChannelsFlush>,
@@ -55,42 +65,148 @@ impl Handle {
     pub fn new(
         rid: RepoId,
         local: PublicKey,
-        storage: &Storage,
+        storage: &dyn Backend,
         follow: Allowed,
         blocked: BlockList,
         channels: ChannelsFlush,
         notifications: node::notifications::StoreWriter,
+        pack_threads: Option<usize>,
     ) -> Result<Self, error::Handle> {
         let exists = storage.contains(&rid)?;
+        let interrupt = channels.interrupt();
         if exists {
             let repo = storage.repository(rid)?;
-            let handle = radicle_fetch::Handle::new(local, repo, follow, blocked, channels)?;
+            let handle = radicle_fetch::Handle::new(local, repo, follow, blocked, channels)?
+                .with_pack_threads(pack_threads)
+                .with_interrupt(interrupt);
             Ok(Handle::Pull {
                 handle,
                 notifications,
             })
         } else {
-            let (repo, tmp) = storage.lock_repository(rid)?;
-            let handle = radicle_fetch::Handle::new(local, repo, follow, blocked, channels)?;
-            Ok(Handle::Clone { handle, tmp })
+            let (repo, tmp) = storage.create(rid)?;
+            let handle = radicle_fetch::Handle::new(local, repo, follow, blocked, channels)?
+                .with_pack_threads(pack_threads)
+                .with_interrupt(interrupt);
+            Ok(Handle::Clone {
+                handle,
+                tmp,
+                delegates: None,
+            })
+        }
+    }
+
+    /// See [`radicle_fetch::Handle::with_known_sigrefs`].
+    pub fn with_known_sigrefs(self, tips: BTreeMap<PublicKey, git::Oid>, refuse: bool) -> Self {
+        self.map(|h| h.with_known_sigrefs(tips, refuse))
+    }
+
+    /// See [`radicle_fetch::Handle::with_denied_refs`].
+    pub fn with_denied_refs(self, denied: DeniedRefs) -> Self {
+        self.map(|h| h.with_denied_refs(denied))
+    }
+
+    /// See [`radicle_fetch::Handle::with_max_refs`].
+    pub fn with_max_refs(self, limit: Option<usize>) -> Self {
+        self.map(|h| h.with_max_refs(limit))
+    }
+
+    /// See [`radicle_fetch::Handle::with_update_batch_size`].
+    pub fn with_update_batch_size(self, size: Option<usize>) -> Self {
+        self.map(|h| h.with_update_batch_size(size))
+    }
+
+    /// See [`radicle_fetch::Handle::with_recovery`]. Has no effect on clones.
+    pub fn with_recovery(self, recover: bool) -> Self {
+        self.map(|h| h.with_recovery(recover))
+    }
+
+    /// Only keep a clone if one of the given nodes is a delegate of the repository. Has no
+    /// effect on pulls.
+    pub fn with_required_delegates(self, nodes: BTreeSet<PublicKey>) -> Self {
+        match self {
+            Self::Clone { handle, tmp, .. } => Self::Clone {
+                handle,
+                tmp,
+                delegates: Some(nodes),
+            },
+            pull => pull,
+        }
+    }
+
+    fn map(
+        self,
+        f: impl FnOnce(radicle_fetch::Handle<ChannelsFlush>) -> radicle_fetch::Handle<ChannelsFlush>,
+    ) -> Self {
+        match self {
+            Self::Clone {
+                handle,
+                tmp,
+                delegates,
+            } => Self::Clone {
+                handle: f(handle),
+                tmp,
+                delegates,
+            },
+            Self::Pull {
+                handle,
+                notifications,
+            } => Self::Pull {
+                handle: f(handle),
+                notifications,
+            },
         }
     }
 
-    pub fn fetch<D: node::refs::Store>(
+    pub fn fetch<
+        D: node::refs::Store
+            + node::sigrefs::Store
+            + node::address::Store
+            + node::confirmations::Store,
+    >(
         self,
         rid: RepoId,
-        storage: &Storage,
+        storage: &dyn Backend,
         cache: &mut cob::cache::StoreWriter,
         refsdb: &mut D,
+        events: &runtime::Handle,
         limit: FetchLimit,
         remote: PublicKey,
         refs_at: Option<Vec<RefsAt>>,
+        confirmation: node::confirmations::Policy,
     ) -> Result<FetchResult, error::Fetch> {
         let (result, clone, notifs) = match self {
-            Self::Clone { mut handle, tmp } => {
+            Self::Clone {
+                handle,
+                tmp,
+                delegates,
+            } => {
                 log::debug!(target: "worker", "{} cloning from {remote}", handle.local());
+                let emitter = events.clone();
+                let mut handle = handle.with_metadata_callback(move |metadata| {
+                    emitter.emit(node::Event::CloneMetadataFetched {
+                        remote,
+                        rid,
+                        doc: metadata.doc.clone(),
+                        sigrefs: metadata
+                            .sigrefs
+                            .iter()
+                            .map(|(nid, refs)| (*nid, refs.at))
+                            .collect(),
+                    });
+                });
                 let result = radicle_fetch::clone(&mut handle, limit, remote)?;
-                mv(tmp, storage, &rid)?;
+
+                if let Some(nodes) = delegates.filter(|_| result.is_success()) {
+                    let doc = handle.repository().identity_doc()?;
+                    if !doc.delegates.iter().any(|did| nodes.contains(did.as_key())) {
+                        // N.b. the temporary clone is discarded when `tmp` is dropped.
+                        return Err(error::Fetch::NotDelegated { rid });
+                    }
+                }
+                // N.b. the clone was fetched into a temporary repository, so that no
+                // concurrent operations see an empty repository.
+                storage.install(tmp, &rid)?;
                 (result, true, None)
             }
             Self::Pull {
@@ -103,8 +219,47 @@ impl Handle {
             }
         };
 
-        for rejected in result.rejected() {
-            log::warn!(target: "worker", "Rejected update for {}", rejected.refname())
+        for Rejected { update, reason } in result.rejected() {
+            match reason {
+                Reason::Superseded => {
+                    log::debug!(target: "worker", "Skipped update for {}: {reason}", update.refname())
+                }
+                _ => {
+                    log::warn!(target: "worker", "Rejected update for {}: {reason}", update.refname())
+                }
+            }
+        }
+        for divergence in result.divergences() {
+            events.emit(node::Event::SigrefsDiverged {
+                remote,
+                rid,
+                namespace: divergence.remote,
+                known: divergence.known,
+                received: divergence.received,
+                deviation: divergence.deviation,
+                refused: divergence.refused,
+            });
+        }
+        for (namespace, divergence) in result.ref_divergences() {
+            events.emit(node::Event::RefDiverged {
+                remote,
+                rid,
+                namespace: *namespace,
+                name: divergence.name.strip_namespace().to_ref_string(),
+                local: divergence.local,
+                received: divergence.received,
+                base: divergence.base,
+                sigrefs_diverged: divergence.sigrefs_diverged,
+            });
+        }
+        for validation in result.validations() {
+            if let Validation::TooManyRefs { remote: owner, .. } = validation {
+                log::warn!(target: "worker", "Penalizing {owner}: {validation}");
+
+                if let Err(e) = refsdb.penalize(owner, node::Severity::High) {
+                    log::error!(target: "worker", "Failed to penalize {owner}: {e}");
+                }
+            }
         }
 
         match result {
@@ -112,6 +267,7 @@ impl Handle {
                 threshold,
                 delegates,
                 validations,
+                ..
             } => {
                 for fail in validations.iter() {
                     log::error!(target: "worker", "Validation error: {}", fail);
@@ -121,19 +277,22 @@ impl Handle {
                     delegates: delegates.into_iter().map(|key| key.to_string()).collect(),
                 })
             }
-            radicle_fetch::FetchResult::Success {
-                applied,
-                remotes,
-                validations,
-            } => {
+            radicle_fetch::FetchResult::Success { remotes, .. } => {
+                let radicle_fetch::Summary {
+                    applied,
+                    remotes,
+                    validations,
+                } = remotes.into_iter().collect();
+
                 for warn in validations {
                     log::warn!(target: "worker", "Validation error: {}", warn);
                 }
 
                 // N.b. We do not go through handle for this since the cloning handle
-                // points to a repository that is temporary and gets moved by [`mv`].
+                // points to a repository that is temporary and gets moved by
+                // [`Backend::install`].
                 let repo = storage.repository(rid)?;
-                repo.set_identity_head()?;
+                set_identity_head(&rid, &repo, confirmation, refsdb, events, remote)?;
                 repo.set_head()?;
 
                 // Notifications are only posted for pulls, not clones.
@@ -148,43 +307,86 @@ impl Handle {
 
                 cache_cobs(&rid, &applied.updated, &repo, cache)?;
                 cache_refs(&rid, &applied.updated, refsdb)?;
+                save_sigref_tips(&rid, &applied.updated, refsdb);
+                invalidate_sigrefs(&rid, &applied.updated, storage);
+
+                let doc = repo.identity_doc()?;
+                let default_branch = doc
+                    .project()
+                    .ok()
+                    .map(|p| git::refs::branch(p.default_branch()));
+                let summary =
+                    node::NamespaceUpdates::summarize(&applied.updated, default_branch.as_ref());
 
                 Ok(FetchResult {
                     updated: applied.updated,
                     namespaces: remotes.into_iter().collect(),
-                    doc: repo.identity_doc()?,
+                    doc,
                     clone,
+                    blobs: vec![],
+                    summary,
                 })
             }
         }
     }
 }
 
-/// In the case of cloning, we have performed the fetch into a
-/// temporary directory -- ensuring that no concurrent operations
-/// see an empty repository.
-///
-/// At the end of the clone, we perform a rename of the temporary
-/// directory to the storage repository.
-///
-/// # Errors
-///   - Will fail if `storage` contains `rid` already.
-fn mv(tmp: tempfile::TempDir, storage: &Storage, rid: &RepoId) -> Result<(), error::Fetch> {
-    use std::io::{Error, ErrorKind};
-
-    let from = tmp.path();
-    let to = storage.path_of(rid);
-
-    if !to.exists() {
-        std::fs::rename(from, to)?;
-    } else {
-        log::warn!(target: "worker", "Refusing to move cloned repository {rid} already exists");
-        return Err(Error::new(
-            ErrorKind::AlreadyExists,
-            format!("repository already exists {:?}", to),
-        )
-        .into());
+/// Adopt the canonical identity of a repository after a fetch. Updates requiring
+/// confirmation are only adopted if the repository's `policy` accepts them.
+fn set_identity_head<D: node::confirmations::Store>(
+    rid: &RepoId,
+    repo: &Repository,
+    policy: node::confirmations::Policy,
+    db: &mut D,
+    events: &runtime::Handle,
+    remote: PublicKey,
+) -> Result<(), error::Fetch> {
+    // N.b. when cloning, there is no current identity, and the canonical one is returned.
+    let current = repo.identity_head()?;
+    let update = repo.canonical_identity_head()?;
+
+    if current == update {
+        repo.set_identity_head_to(update)?;
+        return Ok(());
+    }
+    let doc = match repo.identity_doc_at(current) {
+        Ok(doc) => doc,
+        Err(e) => {
+            // N.b. the current identity can't be loaded, eg. if `rad/id` points to a missing
+            // object, so there is nothing to compare the update to. This happens when a
+            // pull recovers a repository's identity.
+            log::warn!(target: "worker", "Replacing invalid identity head {current} of {rid}: {e}");
+            repo.set_identity_head_to(update)?;
+            return Ok(());
+        }
+    };
+    let identity = repo.identity()?;
+    let revision = identity.current();
+
+    if !node::confirmations::requires_confirmation(&doc.doc, &revision.doc) {
+        repo.set_identity_head_to(update)?;
+        return Ok(());
+    }
+    let now = LocalTime::now().into();
+    let since = db.first_seen(rid, &update, now)?;
+    let decision = policy.decide(&doc.doc, revision, since, now);
+
+    log::info!(
+        target: "worker",
+        "Identity update {update} of {rid} from {remote} requires confirmation: {decision:?} by policy {policy:?}"
+    );
+    if decision.is_accepted() {
+        repo.set_identity_head_to(update)?;
+        db.clear(rid)?;
     }
+    events.emit(node::Event::IdentityUpdateDecided {
+        remote,
+        rid: *rid,
+        current,
+        update,
+        policy,
+        decision,
+    });
 
     Ok(())
 }
@@ -268,6 +470,49 @@ where
     Ok(())
 }
 
+/// Remember the fetched `rad/sigrefs` tips, to check the next fetched tips against.
+///
+/// N.b. deleted references are not forgotten, so that a remote can't rewind its history by
+/// deleting its `rad/sigrefs` first.
+fn save_sigref_tips<D>(repo: &RepoId, refs: &[RefUpdate], db: &mut D)
+where
+    D: node::sigrefs::Store,
+{
+    let timestamp = LocalTime::now().into();
+
+    for r in refs {
+        let oid = match r {
+            RefUpdate::Updated { new, .. } => *new,
+            RefUpdate::Created { oid, .. } => *oid,
+            RefUpdate::Deleted { .. } | RefUpdate::Skipped { .. } => continue,
+        };
+        let Ok((namespace, qualified)) = radicle::git::parse_ref_namespaced(r.name()) else {
+            continue;
+        };
+        if qualified != *git::refs::storage::SIGREFS_BRANCH {
+            continue;
+        }
+        if let Err(e) = db.set_tip(repo, &namespace, node::sigrefs::Tip { oid, timestamp }) {
+            log::error!(target: "worker", "Error saving `rad/sigrefs` tip of {namespace} in {repo}: {e}");
+        }
+    }
+}
+
+/// Evict the cached signed refs of the remotes whose `rad/sigrefs` were updated.
+fn invalidate_sigrefs(rid: &RepoId, refs: &[RefUpdate], storage: &dyn Backend) {
+    for r in refs {
+        if r.is_skipped() {
+            continue;
+        }
+        let Ok((namespace, qualified)) = radicle::git::parse_ref_namespaced(r.name()) else {
+            continue;
+        };
+        if qualified == *git::refs::storage::SIGREFS_BRANCH {
+            storage.invalidate_sigrefs(rid, &namespace);
+        }
+    }
+}
+
 /// Write new `RefUpdate`s that are related a `Patch` or an `Issue`
 /// COB to the COB cache.
 fn cache_cobs<S, C>(
@@ -19,11 +19,15 @@ pub enum Fetch {
     Repository(#[from] radicle::storage::RepositoryError),
     #[error(transparent)]
     RefsDb(#[from] radicle::node::refs::Error),
+    #[error(transparent)]
+    Confirmations(#[from] radicle::node::confirmations::Error),
     #[error("validation of the storage repository failed: the delegates {delegates:?} failed to validate to meet a threshold of {threshold}")]
     Validation {
         threshold: usize,
         delegates: Vec<String>,
     },
+    #[error("repository {rid} isn't delegated to by any of the required nodes")]
+    NotDelegated { rid: identity::RepoId },
     #[error(transparent)]
     Cache(#[from] Cache),
 }
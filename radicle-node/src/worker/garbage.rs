@@ -2,7 +2,8 @@ use std::process::{Command, ExitStatus, Stdio};
 use std::{fmt, io};
 
 use radicle::prelude::RepoId;
-use radicle::storage::ReadStorage;
+
+use super::Backend;
 
 /// Default expiry time for objects.
 pub const EXPIRY_DEFAULT: Expiry = Expiry::Hours(1);
@@ -36,7 +37,7 @@ impl fmt::Display for Expiry {
 }
 
 /// Run Git garbage collector.
-pub fn collect(storage: &impl ReadStorage, rid: RepoId, expiry: Expiry) -> io::Result<ExitStatus> {
+pub fn collect(storage: &dyn Backend, rid: RepoId, expiry: Expiry) -> io::Result<ExitStatus> {
     let git_dir = storage.path_of(&rid);
     let mut gc = Command::new("git");
     gc.current_dir(git_dir)
@@ -0,0 +1,285 @@
+//! Hooks run after fetches.
+//!
+//! After a fetch updates a repository, the configured hook commands for that repository are
+//! run with the result of the fetch as JSON on their standard input, and the registered
+//! callbacks are called with it. Hooks run on their own thread, one at a time, so that slow
+//! hooks don't hold up workers. Commands that don't exit in time are killed, and callbacks
+//! that panic are logged and otherwise ignored.
+use std::io::Write as _;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use std::{io, process, thread};
+
+use crossbeam_channel as chan;
+use serde::Serialize;
+
+use radicle::identity::RepoId;
+use radicle::node::config;
+use radicle::prelude::NodeId;
+use radicle::storage::RefUpdate;
+
+/// How often a running hook command is checked for completion.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Hook error.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("i/o error: {0}")]
+    Io(#[from] io::Error),
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("command timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("command exited with {0}")]
+    Failed(process::ExitStatus),
+}
+
+/// Result of a fetch that updated a repository, as passed to hooks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Fetched {
+    /// The repository fetched.
+    pub rid: RepoId,
+    /// The node fetched from.
+    pub remote: NodeId,
+    /// The updated references.
+    pub updated: Vec<RefUpdate>,
+    /// Whether the fetch was a full clone.
+    pub clone: bool,
+}
+
+/// A hook callback.
+pub type Callback = Box<dyn Fn(&Fetched) + Send + Sync>;
+
+/// A callback, with the repository it's registered for, if any.
+type Registered = (Option<RepoId>, Callback);
+
+/// Callbacks registered for fetches, optionally of a specific repository. Shared with the
+/// hooks thread, so that callbacks can be registered while the node is running.
+#[derive(Clone, Default)]
+pub struct Callbacks(Arc<RwLock<Vec<Registered>>>);
+
+impl std::fmt::Debug for Callbacks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let len = match self.0.read() {
+            Ok(callbacks) => callbacks.len(),
+            Err(e) => e.into_inner().len(),
+        };
+        f.debug_struct("Callbacks").field("len", &len).finish()
+    }
+}
+
+impl Callbacks {
+    /// Register a callback, called after fetches of the given repository, or of any
+    /// repository if `None`. Callbacks should return quickly, as they hold up other hooks.
+    pub fn register(
+        &self,
+        rid: Option<RepoId>,
+        callback: impl Fn(&Fetched) + Send + Sync + 'static,
+    ) {
+        match self.0.write() {
+            Ok(mut callbacks) => callbacks.push((rid, Box::new(callback))),
+            Err(e) => e.into_inner().push((rid, Box::new(callback))),
+        }
+    }
+
+    /// Call the callbacks registered for the fetched repository.
+    fn call(&self, fetched: &Fetched) {
+        let callbacks = match self.0.read() {
+            Ok(callbacks) => callbacks,
+            Err(e) => e.into_inner(),
+        };
+        for (_, callback) in callbacks
+            .iter()
+            .filter(|(rid, _)| rid.map_or(true, |rid| rid == fetched.rid))
+        {
+            if panic::catch_unwind(AssertUnwindSafe(|| callback(fetched))).is_err() {
+                log::error!(target: "hooks", "Callback panicked for fetch of {}", fetched.rid);
+            }
+        }
+    }
+}
+
+/// Runs hooks for fetches received from workers.
+pub struct Hooks {
+    hooks: Vec<config::Hook>,
+    callbacks: Callbacks,
+    /// Fetches that updated repositories.
+    fetched: chan::Receiver<Fetched>,
+}
+
+impl Hooks {
+    /// Create a new hook runner.
+    pub fn new(
+        hooks: Vec<config::Hook>,
+        callbacks: Callbacks,
+        fetched: chan::Receiver<Fetched>,
+    ) -> Self {
+        Self {
+            hooks,
+            callbacks,
+            fetched,
+        }
+    }
+
+    /// Run hooks until all senders are dropped.
+    pub fn run(self) {
+        while let Ok(fetched) = self.fetched.recv() {
+            for hook in self.hooks.iter().filter(|h| h.rid == fetched.rid) {
+                let start = Instant::now();
+
+                match run(hook, &fetched) {
+                    Ok(()) => {
+                        log::debug!(
+                            target: "hooks",
+                            "Ran hook {} for {} in {}ms",
+                            hook.command.display(),
+                            fetched.rid,
+                            start.elapsed().as_millis()
+                        );
+                    }
+                    Err(e) => {
+                        log::error!(
+                            target: "hooks",
+                            "Hook {} failed for {}: {e}",
+                            hook.command.display(),
+                            fetched.rid
+                        );
+                    }
+                }
+            }
+            self.callbacks.call(&fetched);
+        }
+        log::debug!(target: "hooks", "Hook runner shutting down..");
+    }
+}
+
+/// Run a hook command with the given fetch result on its standard input, killing it if it
+/// doesn't exit within the hook's timeout.
+pub fn run(hook: &config::Hook, fetched: &Fetched) -> Result<(), Error> {
+    let input = serde_json::to_vec(fetched)?;
+    let timeout = Duration::from_millis(hook.timeout.as_millis() as u64);
+    let mut child = process::Command::new(&hook.command)
+        .args(&hook.args)
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::null())
+        .spawn()?;
+
+    // Write the input from its own thread, so that a command that doesn't read its input
+    // can't block us once the pipe is full.
+    if let Some(mut stdin) = child.stdin.take() {
+        thread::Builder::new()
+            .name(String::from("hook-stdin"))
+            .spawn(move || {
+                // The command may exit without reading its input.
+                stdin.write_all(&input).ok();
+            })?;
+    }
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            if status.success() {
+                return Ok(());
+            }
+            return Err(Error::Failed(status));
+        }
+        if Instant::now() >= deadline {
+            child.kill()?;
+            child.wait()?;
+
+            return Err(Error::Timeout(timeout));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+    use crate::test::arbitrary;
+    use localtime::LocalDuration;
+
+    fn hook(rid: RepoId, script: &str, timeout: LocalDuration) -> config::Hook {
+        config::Hook {
+            rid,
+            command: "sh".into(),
+            args: vec!["-c".to_owned(), script.to_owned()],
+            timeout,
+        }
+    }
+
+    fn fetched(rid: RepoId) -> Fetched {
+        Fetched {
+            rid,
+            remote: arbitrary::gen(1),
+            updated: vec![],
+            clone: true,
+        }
+    }
+
+    #[test]
+    fn test_run() {
+        let tmp = tempfile::tempdir().unwrap();
+        let out = tmp.path().join("out.json");
+        let rid = arbitrary::gen::<RepoId>(1);
+        let fetched = fetched(rid);
+        let hook = hook(
+            rid,
+            &format!("cat > {}", out.display()),
+            LocalDuration::from_secs(10),
+        );
+
+        run(&hook, &fetched).unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&out).unwrap()).unwrap();
+        assert_eq!(json["rid"], rid.to_string());
+        assert_eq!(json["remote"], fetched.remote.to_string());
+        assert_eq!(json["clone"], true);
+    }
+
+    #[test]
+    fn test_run_failure() {
+        let rid = arbitrary::gen::<RepoId>(1);
+        let fetched = fetched(rid);
+
+        let failed = hook(rid, "exit 3", LocalDuration::from_secs(10));
+        assert!(matches!(run(&failed, &fetched), Err(Error::Failed(s)) if s.code() == Some(3)));
+
+        let start = Instant::now();
+        let slow = hook(rid, "sleep 10", LocalDuration::from_millis(100));
+        assert!(matches!(run(&slow, &fetched), Err(Error::Timeout(_))));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_callbacks() {
+        let rid = arbitrary::gen::<RepoId>(1);
+        let other = arbitrary::gen::<RepoId>(1);
+        let callbacks = Callbacks::default();
+        let (send, recv) = chan::unbounded();
+        let (calls, called) = chan::unbounded();
+
+        callbacks.register(Some(rid), {
+            let calls = calls.clone();
+            move |f| calls.send(("rid", f.rid)).unwrap()
+        });
+        callbacks.register(None, move |f| calls.send(("any", f.rid)).unwrap());
+        callbacks.register(None, |_| panic!("callback panicked"));
+
+        send.send(fetched(rid)).unwrap();
+        send.send(fetched(other)).unwrap();
+        drop(send);
+
+        Hooks::new(vec![], callbacks, recv).run();
+
+        assert_eq!(
+            called.try_iter().collect::<Vec<_>>(),
+            vec![("rid", rid), ("any", rid), ("any", other)]
+        );
+    }
+}
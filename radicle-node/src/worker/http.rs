@@ -0,0 +1,478 @@
+//! A read-only Git "smart" HTTP frontend to the node's storage.
+//!
+//! Allows regular Git clients and web-based tooling to clone and fetch public
+//! repositories from the node, eg. `git clone http://127.0.0.1:8080/<rid>.git`.
+//!
+//! Only the subset of HTTP/1.1 needed by Git clients is implemented. To serve
+//! repositories over HTTPS, put the node behind a reverse proxy.
+use std::io::{BufRead, Read, Write};
+use std::{io, net, str};
+
+use flate2::read::GzDecoder;
+use radicle::prelude::NodeId;
+use thiserror::Error;
+
+use crate::runtime::thread;
+
+use super::daemon::{self, Config};
+use super::{upload_pack, UploadError};
+
+/// Maximum size of a request header, including the request line.
+pub const MAX_HEADER_SIZE: usize = 8 * 1024;
+/// Maximum number of request headers.
+pub const MAX_HEADERS: usize = 32;
+/// Maximum size of a request body, after decompression. Request bodies are buffered in
+/// memory, and only hold the client's negotiation, ie. the objects it wants and has, so
+/// this is kept small.
+pub const MAX_BODY_SIZE: usize = 4 * 1024 * 1024;
+
+/// Content type of the upload-pack capability advertisement.
+const ADVERTISEMENT: &str = "application/x-git-upload-pack-advertisement";
+/// Content type of the upload-pack result.
+const RESULT: &str = "application/x-git-upload-pack-result";
+
+/// Error returned when serving an HTTP request.
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Upload(#[from] UploadError),
+    #[error("malformed request: {0}")]
+    Malformed(#[from] httparse::Error),
+    #[error("bad request: {0}")]
+    BadRequest(&'static str),
+}
+
+/// Serves repositories over Git's "smart" HTTP protocol.
+///
+/// Only public repositories that aren't blocked by the node's seeding
+/// policy are served, and only protocol version 2 is supported.
+pub struct Gateway {
+    nid: NodeId,
+    listener: net::TcpListener,
+    config: Config,
+}
+
+impl Gateway {
+    /// Bind the gateway to the given address.
+    pub fn bind(nid: NodeId, addr: net::SocketAddr, config: Config) -> io::Result<Self> {
+        let listener = net::TcpListener::bind(addr)?;
+
+        Ok(Self {
+            nid,
+            listener,
+            config,
+        })
+    }
+
+    /// The address the gateway is listening on.
+    pub fn local_addr(&self) -> io::Result<net::SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accept connections, serving each one in its own thread. Connections beyond the
+    /// limit, which is shared with the `git://` daemon, are rejected.
+    ///
+    /// Blocks until the listener returns an error.
+    pub fn run(self) -> io::Result<()> {
+        log::info!(target: "http", "Serving HTTP requests on {}..", self.local_addr()?);
+
+        loop {
+            let (mut stream, addr) = self.listener.accept()?;
+            let Some(permit) = self.config.connections.acquire() else {
+                log::warn!(target: "http", "Rejecting connection from {addr}: too many connections");
+                respond(
+                    &mut stream,
+                    503,
+                    "Service Unavailable",
+                    b"Too many connections",
+                )
+                .ok();
+
+                continue;
+            };
+            let nid = self.nid;
+            let config = self.config.clone();
+
+            log::debug!(target: "http", "Accepted connection from {addr}");
+
+            thread::spawn(&self.nid, "git-http", move || {
+                let _permit = permit;
+
+                if let Err(e) = serve(&nid, stream, &config) {
+                    log::warn!(target: "http", "Failed to serve request from {addr}: {e}");
+                }
+            });
+        }
+    }
+}
+
+/// An HTTP request.
+#[derive(Debug)]
+struct Request {
+    method: String,
+    path: String,
+    query: Option<String>,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    /// Read a request from the given reader.
+    ///
+    /// Returns `None` if the connection was closed before a request was sent.
+    fn read<R: BufRead>(reader: &mut R) -> Result<Option<Self>, Error> {
+        let mut head = Vec::new();
+        loop {
+            let n = reader
+                .by_ref()
+                .take((MAX_HEADER_SIZE - head.len()) as u64)
+                .read_until(b'\n', &mut head)?;
+            if n == 0 {
+                if head.is_empty() {
+                    return Ok(None);
+                }
+                return Err(Error::BadRequest("incomplete request header"));
+            }
+            if head.ends_with(b"\r\n\r\n") || head.ends_with(b"\n\n") {
+                break;
+            }
+        }
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut req = httparse::Request::new(&mut headers);
+        if req.parse(&head)?.is_partial() {
+            return Err(Error::BadRequest("incomplete request header"));
+        }
+        let (Some(method), Some(target)) = (req.method, req.path) else {
+            return Err(Error::BadRequest("missing method or path"));
+        };
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path.to_owned(), Some(query.to_owned())),
+            None => (target.to_owned(), None),
+        };
+        let headers = req
+            .headers
+            .iter()
+            .map(|h| {
+                str::from_utf8(h.value)
+                    .map(|v| (h.name.to_ascii_lowercase(), v.trim().to_owned()))
+                    .map_err(|_| Error::BadRequest("invalid header value"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut request = Self {
+            method: method.to_owned(),
+            path,
+            query,
+            headers,
+            body: Vec::new(),
+        };
+        request.body = request.read_body(reader)?;
+
+        Ok(Some(request))
+    }
+
+    /// Get the value of a header, by its lower-case name.
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find_map(|(k, v)| (k == name).then_some(v.as_str()))
+    }
+
+    /// Whether the client asked to close the connection after this request.
+    fn is_close(&self) -> bool {
+        self.header("connection")
+            .map_or(false, |v| v.eq_ignore_ascii_case("close"))
+    }
+
+    /// Read the request body, de-chunking and decompressing it if necessary.
+    fn read_body<R: BufRead>(&self, reader: &mut R) -> Result<Vec<u8>, Error> {
+        let mut body = Vec::new();
+
+        if self
+            .header("transfer-encoding")
+            .map_or(false, |v| v.eq_ignore_ascii_case("chunked"))
+        {
+            loop {
+                let mut line = String::new();
+                reader.by_ref().take(64).read_line(&mut line)?;
+
+                let size = line.trim_end().split(';').next().unwrap_or_default();
+                let size = usize::from_str_radix(size, 16)
+                    .map_err(|_| Error::BadRequest("invalid chunk size"))?;
+                if size == 0 {
+                    // Skip any trailers, up to the final empty line.
+                    loop {
+                        line.clear();
+                        reader.by_ref().take(1024).read_line(&mut line)?;
+                        if line.trim_end().is_empty() {
+                            break;
+                        }
+                    }
+                    break;
+                }
+                if body.len() + size > MAX_BODY_SIZE {
+                    return Err(Error::BadRequest("request body too large"));
+                }
+                let start = body.len();
+                body.resize(start + size, 0);
+                reader.read_exact(&mut body[start..])?;
+
+                let mut crlf = [0; 2];
+                reader.read_exact(&mut crlf)?;
+            }
+        } else if let Some(len) = self.header("content-length") {
+            let len = len
+                .parse::<usize>()
+                .map_err(|_| Error::BadRequest("invalid content length"))?;
+            if len > MAX_BODY_SIZE {
+                return Err(Error::BadRequest("request body too large"));
+            }
+            body.resize(len, 0);
+            reader.read_exact(&mut body)?;
+        }
+
+        match self.header("content-encoding") {
+            None | Some("identity") => Ok(body),
+            Some("gzip") | Some("x-gzip") => {
+                let mut decoded = Vec::new();
+                GzDecoder::new(body.as_slice())
+                    .take(MAX_BODY_SIZE as u64 + 1)
+                    .read_to_end(&mut decoded)?;
+                if decoded.len() > MAX_BODY_SIZE {
+                    return Err(Error::BadRequest("request body too large"));
+                }
+                Ok(decoded)
+            }
+            Some(_) => Err(Error::BadRequest("unsupported content encoding")),
+        }
+    }
+}
+
+/// Serve requests on the given stream until the client disconnects.
+fn serve(nid: &NodeId, stream: net::TcpStream, config: &Config) -> Result<(), Error> {
+    // N.b. the client is never expected to be silent while we're reading from it,
+    // since we only read requests and their bodies.
+    stream.set_read_timeout(Some(daemon::REQUEST_TIMEOUT))?;
+
+    let mut reader = io::BufReader::new(&stream);
+    let mut writer = &stream;
+
+    while let Some(req) = Request::read(&mut reader)? {
+        log::debug!(target: "http", "{} {}", req.method, req.path);
+
+        handle(nid, &req, config, &mut writer)?;
+
+        if req.is_close() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Handle a single request, writing the response to `writer`.
+fn handle<W: Write>(
+    nid: &NodeId,
+    req: &Request,
+    config: &Config,
+    writer: &mut W,
+) -> Result<(), Error> {
+    let Some((name, service)) = route(&req.path) else {
+        return respond(writer, 404, "Not Found", b"Not Found");
+    };
+    let Some(rid) = config.resolver.resolve(name, &config.storage) else {
+        return respond(writer, 404, "Not Found", b"Not Found");
+    };
+    let query = req.query.as_deref().unwrap_or_default();
+
+    if service == "git-receive-pack" || query == "service=git-receive-pack" {
+        return respond(writer, 403, "Forbidden", b"Pushing is not supported");
+    }
+    let advertise = match (req.method.as_str(), service) {
+        ("GET", "info/refs") if query == "service=git-upload-pack" => true,
+        ("POST", "git-upload-pack") => false,
+        ("GET", "info/refs") => {
+            // N.b. "dumb" HTTP clients don't send the service parameter.
+            return respond(
+                writer,
+                403,
+                "Forbidden",
+                b"Only smart HTTP clients are supported",
+            );
+        }
+        _ => return respond(writer, 404, "Not Found", b"Not Found"),
+    };
+    if !req
+        .header("git-protocol")
+        .map_or(false, |p| p.split(':').any(|v| v == "version=2"))
+    {
+        return respond(
+            writer,
+            400,
+            "Bad Request",
+            b"Only Git protocol version 2 is supported",
+        );
+    }
+    if let Err(e) = daemon::authorize(rid, config) {
+        // N.b. we don't want to leak the existence of a repository, so the
+        // same response is sent whether it's missing or not authorized.
+        log::debug!(target: "http", "Rejecting request for {rid}: {e}");
+        return respond(writer, 404, "Not Found", b"Not Found");
+    }
+
+    write!(
+        writer,
+        "HTTP/1.1 200 OK\r\n\
+         Content-Type: {}\r\n\
+         Cache-Control: no-cache\r\n\
+         Transfer-Encoding: chunked\r\n\r\n",
+        if advertise { ADVERTISEMENT } else { RESULT }
+    )?;
+    let mut chunked = Chunked(io::BufWriter::new(writer));
+    let usage = upload_pack::stateless(
+        nid,
+        &config.storage,
+        &rid,
+        advertise,
+        &req.body,
+        config.limits,
+        &mut chunked,
+    )?;
+    chunked.finish()?;
+
+    if let Some(exceeded) = usage.exceeded {
+        log::warn!(target: "http", "Upload-pack process for {rid} reached its {exceeded}");
+    } else if !usage.status.success() {
+        log::warn!(target: "http", "Upload-pack process for {rid} exited with {}", usage.status);
+    }
+    Ok(())
+}
+
+/// Split a request path into the name of a repository and the Git service being requested.
+/// See [`super::resolve::Resolver`].
+///
+/// Eg. `/z3gqcJUoA1n9HaHKufZs5FCSGazv5.git/info/refs`.
+fn route(path: &str) -> Option<(&str, &str)> {
+    let (repo, service) = path.strip_prefix('/')?.split_once('/')?;
+    let repo = repo.strip_suffix(".git").unwrap_or(repo);
+
+    Some((repo, service))
+}
+
+/// Write a plain-text response with the given status.
+fn respond<W: Write>(writer: &mut W, status: u16, reason: &str, body: &[u8]) -> Result<(), Error> {
+    write!(
+        writer,
+        "HTTP/1.1 {status} {reason}\r\n\
+         Content-Type: text/plain\r\n\
+         Content-Length: {}\r\n\r\n",
+        body.len()
+    )?;
+    writer.write_all(body)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Writer for a chunked response body.
+struct Chunked<W: Write>(W);
+
+impl<W: Write> Chunked<W> {
+    /// Write the final chunk.
+    fn finish(mut self) -> io::Result<()> {
+        self.0.write_all(b"0\r\n\r\n")?;
+        self.0.flush()
+    }
+}
+
+impl<W: Write> Write for Chunked<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // N.b. an empty chunk would mark the end of the body.
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        write!(self.0, "{:x}\r\n", buf.len())?;
+        self.0.write_all(buf)?;
+        self.0.write_all(b"\r\n")?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route() {
+        assert_eq!(
+            route("/z3gqcJUoA1n9HaHKufZs5FCSGazv5.git/info/refs"),
+            Some(("z3gqcJUoA1n9HaHKufZs5FCSGazv5", "info/refs"))
+        );
+        assert_eq!(
+            route("/rad:z3gqcJUoA1n9HaHKufZs5FCSGazv5/git-upload-pack"),
+            Some(("rad:z3gqcJUoA1n9HaHKufZs5FCSGazv5", "git-upload-pack"))
+        );
+        assert_eq!(
+            route("/heartwood.git/info/refs"),
+            Some(("heartwood", "info/refs"))
+        );
+        assert_eq!(route("/z3gqcJUoA1n9HaHKufZs5FCSGazv5.git"), None);
+    }
+
+    #[test]
+    fn test_request_too_large() {
+        let mut input = io::Cursor::new(
+            format!(
+                "POST /z3gqcJUoA1n9HaHKufZs5FCSGazv5.git/git-upload-pack HTTP/1.1\r\n\
+                 Host: localhost\r\n\
+                 Content-Length: {}\r\n\r\n",
+                MAX_BODY_SIZE + 1
+            )
+            .into_bytes(),
+        );
+        assert!(matches!(
+            Request::read(&mut input),
+            Err(Error::BadRequest("request body too large"))
+        ));
+    }
+
+    #[test]
+    fn test_request_chunked() {
+        let mut input = io::Cursor::new(
+            b"POST /z3gqcJUoA1n9HaHKufZs5FCSGazv5.git/git-upload-pack HTTP/1.1\r\n\
+              Host: localhost\r\n\
+              Transfer-Encoding: chunked\r\n\
+              Git-Protocol: version=2\r\n\r\n\
+              5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n"
+                .to_vec(),
+        );
+        let req = Request::read(&mut input).unwrap().unwrap();
+
+        assert_eq!(req.method, "POST");
+        assert_eq!(
+            req.path,
+            "/z3gqcJUoA1n9HaHKufZs5FCSGazv5.git/git-upload-pack"
+        );
+        assert_eq!(req.header("git-protocol"), Some("version=2"));
+        assert_eq!(req.body, b"hello world");
+        assert!(Request::read(&mut input).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_chunked_writer() {
+        let mut buf = Vec::new();
+        let mut chunked = Chunked(&mut buf);
+
+        chunked.write_all(b"hello").unwrap();
+        chunked.write_all(b"").unwrap();
+        chunked.finish().unwrap();
+
+        assert_eq!(buf, b"5\r\nhello\r\n0\r\n\r\n");
+    }
+}
@@ -0,0 +1,256 @@
+//! Export of repositories to external Git remotes.
+//!
+//! After a fetch updates a mirrored repository, its canonical default branch is pushed to
+//! the configured remotes. Pushes run on their own thread, since they involve the network
+//! and shouldn't hold up workers. Failed pushes are retried with an exponential backoff.
+use std::collections::HashMap;
+use std::io;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel as chan;
+
+use radicle::git;
+use radicle::identity::RepoId;
+use radicle::node::config;
+use radicle::storage::{ReadRepository, ReadStorage, RepositoryError};
+use radicle::Storage;
+
+/// Delay before the first retry of a failed push. Doubles with every attempt.
+pub const RETRY_DELAY: Duration = Duration::from_secs(30);
+/// Maximum delay between retries.
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+/// Number of attempts after which a push is given up on, until the next update.
+pub const MAX_ATTEMPTS: u32 = 8;
+
+/// Mirror error.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+    #[error(transparent)]
+    Ref(#[from] git::fmt::Error),
+    #[error("git push failed: {0}")]
+    Push(#[from] io::Error),
+}
+
+/// A failed push, to be retried.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Retry {
+    /// Number of attempts so far.
+    attempts: u32,
+    /// When to retry.
+    at: Instant,
+}
+
+/// Delay before retrying a push that failed the given number of times.
+fn backoff(attempts: u32) -> Duration {
+    RETRY_DELAY
+        .saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+        .min(MAX_RETRY_DELAY)
+}
+
+/// Pushes mirrored repositories to their remotes.
+pub struct Mirrors {
+    storage: Storage,
+    mirrors: Vec<config::Mirror>,
+    /// Repositories updated by fetches.
+    updates: chan::Receiver<RepoId>,
+    /// Last commit pushed, per mirror.
+    pushed: HashMap<usize, git::Oid>,
+    /// Pushes to retry, per mirror.
+    retries: HashMap<usize, Retry>,
+}
+
+impl Mirrors {
+    /// Create a new mirror exporter.
+    pub fn new(
+        storage: Storage,
+        mirrors: Vec<config::Mirror>,
+        updates: chan::Receiver<RepoId>,
+    ) -> Self {
+        Self {
+            storage,
+            mirrors,
+            updates,
+            pushed: HashMap::new(),
+            retries: HashMap::new(),
+        }
+    }
+
+    /// Push updated repositories until all senders are dropped.
+    pub fn run(mut self) {
+        // Remotes may be out of date from before the node started.
+        for ix in 0..self.mirrors.len() {
+            self.push(ix);
+        }
+        loop {
+            let update = match self.retries.values().map(|r| r.at).min() {
+                Some(at) => self
+                    .updates
+                    .recv_timeout(at.saturating_duration_since(Instant::now())),
+                None => self
+                    .updates
+                    .recv()
+                    .map_err(|_| chan::RecvTimeoutError::Disconnected),
+            };
+            match update {
+                Ok(rid) => {
+                    for ix in self.mirrors_of(rid) {
+                        // A new update supersedes any pending retry.
+                        self.retries.remove(&ix);
+                        self.push(ix);
+                    }
+                }
+                Err(chan::RecvTimeoutError::Timeout) => {
+                    let now = Instant::now();
+                    let due = self
+                        .retries
+                        .iter()
+                        .filter(|(_, r)| r.at <= now)
+                        .map(|(ix, _)| *ix)
+                        .collect::<Vec<_>>();
+
+                    for ix in due {
+                        self.push(ix);
+                    }
+                }
+                Err(chan::RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        log::debug!(target: "mirror", "Mirror exporter shutting down..");
+    }
+
+    /// Indexes of the mirrors of the given repository.
+    fn mirrors_of(&self, rid: RepoId) -> Vec<usize> {
+        self.mirrors
+            .iter()
+            .enumerate()
+            .filter(|(_, m)| m.rid == rid)
+            .map(|(ix, _)| ix)
+            .collect()
+    }
+
+    /// Push the mirror at the given index, scheduling a retry on failure.
+    fn push(&mut self, ix: usize) {
+        let mirror = &self.mirrors[ix];
+        let retry = self.retries.remove(&ix);
+
+        match push(&self.storage, mirror, self.pushed.get(&ix).copied()) {
+            Ok(Some(oid)) => {
+                log::info!(target: "mirror", "Pushed {oid} of {} to {}", mirror.rid, mirror.url);
+                self.pushed.insert(ix, oid);
+            }
+            Ok(None) => {
+                log::trace!(target: "mirror", "Mirror {} of {} is up to date", mirror.url, mirror.rid);
+            }
+            Err(e) => {
+                let attempts = retry.map_or(0, |r| r.attempts) + 1;
+                if attempts >= MAX_ATTEMPTS {
+                    log::error!(
+                        target: "mirror",
+                        "Failed to push {} to {} after {attempts} attempt(s), giving up: {e}",
+                        mirror.rid,
+                        mirror.url
+                    );
+                    return;
+                }
+                let delay = backoff(attempts);
+                log::warn!(
+                    target: "mirror",
+                    "Failed to push {} to {}, retrying in {}s: {e}",
+                    mirror.rid,
+                    mirror.url,
+                    delay.as_secs()
+                );
+                self.retries.insert(
+                    ix,
+                    Retry {
+                        attempts,
+                        at: Instant::now() + delay,
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Push the canonical default branch of a mirrored repository to its remote, unless it
+/// is the given, previously pushed, commit. Returns the commit pushed, if any.
+pub fn push(
+    storage: &Storage,
+    mirror: &config::Mirror,
+    pushed: Option<git::Oid>,
+) -> Result<Option<git::Oid>, Error> {
+    let repo = storage.repository(mirror.rid)?;
+    let (head, oid) = repo.canonical_head()?;
+
+    if pushed == Some(oid) {
+        return Ok(None);
+    }
+    let dst = match &mirror.branch {
+        Some(branch) => git::refs::branch(git::RefStr::try_from_str(branch)?).to_string(),
+        None => head.to_string(),
+    };
+    git::run::<_, _, &str, &str>(
+        storage.path_of(&mirror.rid),
+        [
+            "push",
+            "--force",
+            "--quiet",
+            &mirror.url,
+            &format!("{oid}:{dst}"),
+        ],
+        [],
+    )?;
+
+    Ok(Some(oid))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+    use radicle::test::fixtures;
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1), RETRY_DELAY);
+        assert_eq!(backoff(2), RETRY_DELAY * 2);
+        assert_eq!(backoff(3), RETRY_DELAY * 4);
+        assert_eq!(backoff(MAX_ATTEMPTS * 4), MAX_RETRY_DELAY);
+    }
+
+    #[test]
+    fn test_push() {
+        let tmp = tempfile::tempdir().unwrap();
+        let signer = radicle::crypto::test::signer::MockSigner::default();
+        let storage = fixtures::storage(tmp.path().join("storage"), &signer).unwrap();
+        let rid = storage.repositories().unwrap()[0].rid;
+        let remote = tmp.path().join("mirror.git");
+        git::raw::Repository::init_bare(&remote).unwrap();
+
+        let mut mirror = config::Mirror {
+            rid,
+            url: format!("file://{}", remote.display()),
+            branch: None,
+        };
+        let repo = storage.repository(rid).unwrap();
+        let (head, oid) = repo.canonical_head().unwrap();
+
+        assert_eq!(push(&storage, &mirror, None).unwrap(), Some(oid));
+        assert_eq!(push(&storage, &mirror, Some(oid)).unwrap(), None);
+
+        let remote = git::raw::Repository::open_bare(&remote).unwrap();
+        assert_eq!(
+            remote.refname_to_id(head.as_str()).unwrap(),
+            git::raw::Oid::from(oid)
+        );
+
+        mirror.branch = Some(String::from("radicle"));
+        push(&storage, &mirror, None).unwrap();
+        assert_eq!(
+            remote.refname_to_id("refs/heads/radicle").unwrap(),
+            git::raw::Oid::from(oid)
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! Queries of the tips of references, without fetching.
+//!
+//! Queries are made on Git streams, which are authenticated by the session with the
+//! remote. The querying side opens the stream with a `rad-ref-query /<rid>` request
+//! packet-line instead of a `git-upload-pack` one, and then queries references one at a
+//! time:
+//!
+//! 1. The querier sends the length of the reference name (2 bytes, big-endian), followed
+//!    by the name, eg. `refs/namespaces/<nid>/refs/heads/master`.
+//! 2. The server replies with [`PRESENT`] followed by the tip of the reference (20
+//!    bytes), or with [`ABSENT`] if it doesn't have the reference.
+//!
+//! When the querier is done, it signals the end of the stream. At most [`MAX_REFS`]
+//! references are answered per stream.
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+
+use radicle::git;
+use radicle::git::{Oid, RefString};
+use radicle::prelude::RepoId;
+use radicle::storage::git::Repository;
+use radicle_fetch::transport::SignalEof;
+
+/// Command of the request packet-line used to open a reference query.
+pub const COMMAND: &str = "rad-ref-query";
+/// Maximum number of references answered per stream.
+pub const MAX_REFS: usize = 1024;
+/// Maximum length of a queried reference name, in bytes.
+pub const MAX_REF_LEN: usize = 1024;
+/// Sent by the server when it has the queried reference.
+pub const PRESENT: u8 = 1;
+/// Sent by the server when it doesn't have the queried reference.
+pub const ABSENT: u8 = 0;
+
+/// Tips of the queried references, or `None` for references the remote doesn't have.
+pub type Tips = BTreeMap<RefString, Option<Oid>>;
+
+/// Error returned when querying references.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("peer is not connected")]
+    NotConnected,
+    #[error("peer doesn't answer reference queries")]
+    Unsupported,
+    #[error("too many references queried: {0} (max. {MAX_REFS})")]
+    TooManyRefs(usize),
+    #[error("reference name '{0}' is too long")]
+    RefTooLong(RefString),
+    #[error("invalid reply {0} from remote")]
+    InvalidReply(u8),
+    #[error("worker panicked: {0}")]
+    Panicked(String),
+}
+
+/// Answer the references queried by a remote. Returns the number of references that
+/// were answered.
+pub fn serve<R, W>(repo: &Repository, mut recv: R, mut send: W) -> io::Result<usize>
+where
+    R: Read,
+    W: Write,
+{
+    let mut served = 0;
+    let mut name = vec![0; MAX_REF_LEN];
+
+    loop {
+        let mut len = [0u8; 2];
+        match recv.read_exact(&mut len) {
+            Ok(()) => {}
+            // The querier is done.
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(served),
+            Err(e) => return Err(e),
+        }
+        let len = u16::from_be_bytes(len) as usize;
+        if len > MAX_REF_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("reference name of {len} bytes exceeds the maximum length"),
+            ));
+        }
+        if served >= MAX_REFS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("more than {MAX_REFS} references queried"),
+            ));
+        }
+        recv.read_exact(&mut name[..len])?;
+
+        let refname = std::str::from_utf8(&name[..len])
+            .ok()
+            .and_then(|n| RefString::try_from(n).ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid reference name"))?;
+
+        match repo.backend.refname_to_id(refname.as_str()) {
+            Ok(oid) => {
+                send.write_all(&[PRESENT])?;
+                send.write_all(oid.as_bytes())?;
+            }
+            Err(e) if git::is_not_found_err(&e) => send.write_all(&[ABSENT])?,
+            Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e)),
+        }
+        served += 1;
+    }
+}
+
+/// Query the tips of references of a repository from a remote.
+pub fn query<R, W>(
+    rid: RepoId,
+    refs: &[RefString],
+    mut recv: R,
+    send: &mut W,
+) -> Result<Tips, Error>
+where
+    R: Read,
+    W: Write + SignalEof<Error = io::Error>,
+{
+    if refs.len() > MAX_REFS {
+        return Err(Error::TooManyRefs(refs.len()));
+    }
+    let mut tips = Tips::new();
+
+    send.write_all(&request(rid))?;
+
+    for name in refs {
+        let len = u16::try_from(name.len())
+            .ok()
+            .filter(|len| *len as usize <= MAX_REF_LEN)
+            .ok_or_else(|| Error::RefTooLong(name.clone()))?;
+        send.write_all(&len.to_be_bytes())?;
+        send.write_all(name.as_str().as_bytes())?;
+
+        let mut reply = [0u8; 1];
+        recv.read_exact(&mut reply)?;
+
+        let tip = match reply[0] {
+            PRESENT => {
+                let mut oid = [0u8; 20];
+                recv.read_exact(&mut oid)?;
+                Some(Oid::from(git::raw::Oid::from_bytes(&oid).map_err(|e| {
+                    io::Error::new(io::ErrorKind::InvalidData, e)
+                })?))
+            }
+            ABSENT => None,
+            other => return Err(Error::InvalidReply(other)),
+        };
+        tips.insert(name.clone(), tip);
+    }
+    send.eof()?;
+
+    Ok(tips)
+}
+
+/// The packet-line sent to open a reference query for a repository.
+pub fn request(rid: RepoId) -> Vec<u8> {
+    let line = format!("{COMMAND} /{}\0", rid.canonical());
+    let mut pktline = format!("{:04x}", line.len() + 4).into_bytes();
+    pktline.extend_from_slice(line.as_bytes());
+    pktline
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use radicle::crypto::Signer as _;
+    use radicle::git::refname;
+    use radicle::storage::ReadStorage as _;
+    use radicle::test::{arbitrary, fixtures};
+
+    use super::*;
+
+    /// Writer that discards the end of the stream signal.
+    struct Writer<'a>(&'a mut Vec<u8>);
+
+    impl Write for Writer<'_> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SignalEof for Writer<'_> {
+        type Error = io::Error;
+
+        fn eof(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_ref_query() {
+        let tmp = tempfile::tempdir().unwrap();
+        let signer = radicle::crypto::test::signer::MockSigner::default();
+        let storage = fixtures::storage(tmp.path().join("storage"), &signer).unwrap();
+        let rid = storage.repositories().unwrap()[0].rid;
+        let repo = storage.repository(rid).unwrap();
+        let sigrefs = RefString::try_from(format!(
+            "refs/namespaces/{}/refs/rad/sigrefs",
+            signer.public_key()
+        ))
+        .unwrap();
+        let tip = repo.backend.refname_to_id(sigrefs.as_str()).unwrap();
+        let refs = [sigrefs, refname!("refs/heads/missing")];
+
+        // Serve the queries the querier is expected to send.
+        let mut queries = Vec::new();
+        for name in &refs {
+            queries.extend_from_slice(&(name.len() as u16).to_be_bytes());
+            queries.extend_from_slice(name.as_str().as_bytes());
+        }
+        let mut replies = Vec::new();
+        let served = serve(&repo, queries.as_slice(), &mut replies).unwrap();
+        assert_eq!(served, 2);
+
+        let mut sent = Vec::new();
+        let tips = query(rid, &refs, replies.as_slice(), &mut Writer(&mut sent)).unwrap();
+
+        let mut expected = request(rid);
+        expected.extend_from_slice(&queries);
+        assert_eq!(sent, expected);
+        assert_eq!(
+            tips,
+            Tips::from([(refs[0].clone(), Some(tip.into())), (refs[1].clone(), None)])
+        );
+    }
+
+    #[test]
+    fn test_ref_query_limit() {
+        let rid = arbitrary::gen::<RepoId>(1);
+        let refs = vec![refname!("refs/heads/master"); MAX_REFS + 1];
+        let mut sent = Vec::new();
+
+        assert!(matches!(
+            query(rid, &refs, io::empty(), &mut Writer(&mut sent)),
+            Err(Error::TooManyRefs(n)) if n == MAX_REFS + 1
+        ));
+        assert!(sent.is_empty());
+    }
+}
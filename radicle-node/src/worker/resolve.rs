@@ -0,0 +1,128 @@
+//! Resolution of the repository names used by Git clients.
+//!
+//! Clients of the `git://` daemon and HTTP gateway may name a repository by its identifier,
+//! by a configured alias, or by a prefix of its identifier, to make cloning by hand practical.
+use std::collections::BTreeMap;
+
+use radicle::identity::RepoId;
+
+use super::Backend;
+
+/// Minimum length of an identifier prefix, not counting the `rad:` prefix.
+pub const MIN_PREFIX_LEN: usize = 6;
+
+/// Resolves repository names to identifiers.
+#[derive(Debug, Clone, Default)]
+pub struct Resolver {
+    /// Repository aliases, from the node configuration.
+    aliases: BTreeMap<String, RepoId>,
+}
+
+impl Resolver {
+    /// Create a new resolver, with the given aliases.
+    pub fn new(aliases: BTreeMap<String, RepoId>) -> Self {
+        Self { aliases }
+    }
+
+    /// Resolve a repository name to an identifier. In order, the name may be:
+    ///
+    ///   1. A repository identifier, with or without the `rad:` prefix.
+    ///   2. A configured alias.
+    ///   3. A prefix of the identifier of exactly one stored repository, of at least
+    ///      [`MIN_PREFIX_LEN`] characters.
+    ///
+    /// N.b. access to the resolved repository must still be authorized.
+    pub fn resolve(&self, name: &str, storage: &dyn Backend) -> Option<RepoId> {
+        let name = name.strip_suffix(".git").unwrap_or(name);
+
+        if let Ok(rid) = name.parse() {
+            return Some(rid);
+        }
+        if let Some(rid) = self.aliases.get(name) {
+            return Some(*rid);
+        }
+        let prefix = name.strip_prefix("rad:").unwrap_or(name);
+        if prefix.len() < MIN_PREFIX_LEN {
+            return None;
+        }
+        let rids = match storage.repository_ids() {
+            Ok(rids) => rids,
+            Err(e) => {
+                log::warn!(target: "worker", "Failed to list repositories to resolve '{name}': {e}");
+                return None;
+            }
+        };
+        let mut matching = rids
+            .into_iter()
+            .filter(|rid| rid.canonical().starts_with(prefix));
+
+        match (matching.next(), matching.next()) {
+            (Some(rid), None) => Some(rid),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use radicle::storage::ReadStorage as _;
+    use radicle::test::{arbitrary, fixtures};
+
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let tmp = tempfile::tempdir().unwrap();
+        let signer = radicle::crypto::test::signer::MockSigner::default();
+        let storage = fixtures::storage(tmp.path().join("storage"), &signer).unwrap();
+        let rids = storage
+            .repositories()
+            .unwrap()
+            .into_iter()
+            .map(|r| r.rid)
+            .collect::<Vec<_>>();
+        let rid = rids[0];
+        let unknown = arbitrary::gen::<RepoId>(1);
+        let resolver = Resolver::new(BTreeMap::from([
+            (String::from("heartwood"), rid),
+            (String::from("unknown"), unknown),
+        ]));
+
+        assert_eq!(resolver.resolve(&rid.urn(), &storage), Some(rid));
+        assert_eq!(resolver.resolve(&rid.canonical(), &storage), Some(rid));
+        assert_eq!(
+            resolver.resolve(&format!("{}.git", rid.canonical()), &storage),
+            Some(rid)
+        );
+        assert_eq!(resolver.resolve("heartwood", &storage), Some(rid));
+        assert_eq!(resolver.resolve("heartwood.git", &storage), Some(rid));
+        // Aliases aren't checked against storage; that's left to authorization.
+        assert_eq!(resolver.resolve("unknown", &storage), Some(unknown));
+        assert_eq!(resolver.resolve("acme", &storage), None);
+
+        // Prefixes must be long enough, and match a single repository.
+        for rid in &rids {
+            let canonical = rid.canonical();
+            let unique = (MIN_PREFIX_LEN..canonical.len())
+                .map(|len| &canonical[..len])
+                .find(|prefix| {
+                    rids.iter()
+                        .filter(|r| r.canonical().starts_with(prefix))
+                        .count()
+                        == 1
+                })
+                .unwrap();
+
+            assert_eq!(resolver.resolve(unique, &storage), Some(*rid));
+            assert_eq!(
+                resolver.resolve(&format!("rad:{unique}"), &storage),
+                Some(*rid)
+            );
+        }
+        assert_eq!(
+            resolver.resolve(&rid.canonical()[..MIN_PREFIX_LEN - 1], &storage),
+            None
+        );
+    }
+}
@@ -1,12 +1,118 @@
-use std::io;
-use std::io::Write;
-use std::process::{Command, ExitStatus, Stdio};
+use std::io::{Read as _, Write};
+use std::os::unix::process::{CommandExt as _, ExitStatusExt as _};
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{fmt, io, mem, net, time};
 
+use radicle::identity::RepoId;
+use radicle::node::config::UploadLimits;
 use radicle::node::NodeId;
-use radicle::storage::git::paths;
-use radicle::Storage;
 
 use crate::runtime::thread;
+use crate::worker::channels::ChannelFlushWriter;
+use crate::worker::Backend;
+
+/// Destination of the upload-pack output.
+pub trait Sink: io::Write {
+    /// Copy everything read from `reader` to this sink, until EOF.
+    fn copy_from<R: io::Read>(&mut self, reader: &mut R) -> io::Result<u64>;
+}
+
+impl Sink for ChannelFlushWriter {
+    fn copy_from<R: io::Read>(&mut self, reader: &mut R) -> io::Result<u64> {
+        ChannelFlushWriter::copy_from(self, reader)
+    }
+}
+
+impl Sink for net::TcpStream {
+    fn copy_from<R: io::Read>(&mut self, reader: &mut R) -> io::Result<u64> {
+        // N.b. on Linux, copying from a pipe to a socket doesn't go through
+        // userspace, as `io::copy` uses `splice` in that case.
+        io::copy(reader, self)
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for &mut S {
+    fn copy_from<R: io::Read>(&mut self, reader: &mut R) -> io::Result<u64> {
+        (**self).copy_from(reader)
+    }
+}
+
+/// Resource limits of a `git upload-pack` process, so that a single pathological request
+/// can't take down the host.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum virtual memory of the process, in bytes.
+    pub memory: Option<u64>,
+    /// Maximum CPU time of the process, in seconds.
+    pub cpu_time: Option<u64>,
+    /// Maximum number of bytes output by the process.
+    pub output: Option<u64>,
+}
+
+impl From<&UploadLimits> for Limits {
+    fn from(limits: &UploadLimits) -> Self {
+        Self {
+            memory: limits.max_memory,
+            cpu_time: limits.max_cpu_time,
+            output: limits.max_output,
+        }
+    }
+}
+
+impl Limits {
+    /// Set the resource limits of the current process.
+    ///
+    /// N.b. this runs in the forked child before `exec`, and must therefore not allocate.
+    fn apply(&self) -> io::Result<()> {
+        for (resource, limit) in [
+            (libc::RLIMIT_AS, self.memory),
+            (libc::RLIMIT_CPU, self.cpu_time),
+        ] {
+            let Some(limit) = limit else {
+                continue;
+            };
+            let rlim = libc::rlimit {
+                rlim_cur: limit as libc::rlim_t,
+                rlim_max: limit as libc::rlim_t,
+            };
+            if unsafe { libc::setrlimit(resource, &rlim) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A resource limit that was reached by a `git upload-pack` process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exceeded {
+    /// The process used all of its CPU time, in seconds, and was killed.
+    CpuTime(u64),
+    /// The process output this many bytes, and was killed.
+    Output(u64),
+}
+
+impl fmt::Display for Exceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CpuTime(secs) => write!(f, "CPU time limit of {secs} second(s)"),
+            Self::Output(bytes) => write!(f, "output limit of {bytes} byte(s)"),
+        }
+    }
+}
+
+/// Resources used by a `git upload-pack` process.
+#[derive(Debug, Clone, Copy)]
+pub struct Usage {
+    /// Exit status of the process.
+    pub status: ExitStatus,
+    /// CPU time used by the process, in user and kernel mode.
+    pub cpu: time::Duration,
+    /// Resource limit that was reached, if any.
+    pub exceeded: Option<Exceeded>,
+}
 
 /// Perform the Git upload-pack process, given that the Git request
 /// `header` has already been read and parsed.
@@ -14,16 +120,21 @@ use crate::runtime::thread;
 /// N.b. The upload-pack process itself is strict, i.e. it will read
 /// requests from the client indefinitely, and so the client side MUST
 /// send the EOF file message.
+///
+/// The process is killed as soon as `interrupt` is set, eg. when the
+/// stream is closed.
 pub fn upload_pack<R, W>(
     nid: &NodeId,
-    storage: &Storage,
+    storage: &dyn Backend,
     header: &pktline::GitRequest,
+    limits: Limits,
+    interrupt: &AtomicBool,
     mut recv: R,
     mut send: W,
-) -> io::Result<ExitStatus>
+) -> io::Result<Usage>
 where
     R: io::Read + Send,
-    W: io::Write + Send,
+    W: Sink + Send,
 {
     let protocol_version = header
         .extra
@@ -48,38 +159,29 @@ where
         ));
     }
 
-    let git_dir = paths::repository(storage, &header.repo);
-    let mut child = {
-        let mut cmd = Command::new("git");
-        cmd.current_dir(git_dir)
-            .env_clear()
-            .envs(std::env::vars().filter(|(key, _)| key == "PATH" || key.starts_with("GIT_TRACE")))
-            .env("GIT_PROTOCOL", format!("version={protocol_version}"))
-            .args([
-                "-c",
-                "uploadpack.allowAnySha1InWant=true",
-                "-c",
-                "uploadpack.allowRefInWant=true",
-                "-c",
-                "lsrefs.unborn=ignore",
-                "upload-pack",
-                "--strict",
-                ".",
-            ])
-            .stdout(Stdio::piped())
-            .stdin(Stdio::piped())
-            .stderr(Stdio::inherit());
-
-        cmd.spawn()?
-    };
+    let git_dir = storage.path_of(&header.repo);
+    let mut child = command(&git_dir, protocol_version, &["--strict", "."], limits)
+        .stdout(Stdio::piped())
+        .stdin(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()?;
 
     let mut stdin = child.stdin.take().unwrap();
-    let mut stdout = io::BufReader::new(child.stdout.take().unwrap());
+    let mut stdout = child.stdout.take().unwrap();
+    let truncated = AtomicBool::new(false);
     thread::scope(|s| {
         thread::spawn_scoped(nid, "upload-pack", s, || {
             // N.b. we indefinitely copy stdout to the sender,
             // i.e. there's no need for a loop.
-            match io::copy(&mut stdout, &mut send) {
+            let result = match limits.output {
+                Some(max) => send.copy_from(&mut (&mut stdout).take(max)),
+                None => send.copy_from(&mut stdout),
+            };
+            match result {
+                Ok(n) if Some(n) == limits.output => {
+                    log::warn!(target: "worker", "Upload-pack output limit reached for {}; aborting", header.repo);
+                    truncated.store(true, Ordering::Relaxed);
+                }
                 Ok(_) => {}
                 Err(e) => {
                     log::error!(target: "worker", "Worker channel disconnected for {}; aborting: {e}", header.repo);
@@ -110,10 +212,14 @@ where
             }
         });
 
-        // N.b. we only care if the `reader` is finished. We then kill
-        // the child which will end the thread for the sender.
+        // N.b. we only care if the `reader` is finished, if the output
+        // limit was reached, or if we were interrupted. We then kill the
+        // child which will end the thread for the sender.
         loop {
-            if reader.is_finished() {
+            if reader.is_finished()
+                || truncated.load(Ordering::Relaxed)
+                || interrupt.load(Ordering::Relaxed)
+            {
                 child.kill()?;
                 break;
             } else {
@@ -123,8 +229,183 @@ where
         Ok::<_, io::Error>(())
     })?;
 
-    let status = child.wait()?;
-    Ok(status)
+    let mut usage = wait(&mut child, limits)?;
+    if truncated.into_inner() {
+        usage.exceeded = limits.output.map(Exceeded::Output);
+    }
+    Ok(usage)
+}
+
+/// Perform the Git upload-pack process in stateless mode, as used by the
+/// "smart" HTTP transport.
+///
+/// If `advertise` is set, only the capability advertisement is written to
+/// `send`. Otherwise, the full client `request` is passed to the process and
+/// its response is written to `send`.
+///
+/// N.b. Only Git protocol version 2 is supported.
+pub fn stateless<W>(
+    nid: &NodeId,
+    storage: &dyn Backend,
+    rid: &RepoId,
+    advertise: bool,
+    request: &[u8],
+    limits: Limits,
+    mut send: W,
+) -> io::Result<Usage>
+where
+    W: io::Write,
+{
+    let git_dir = storage.path_of(rid);
+    let args: &[&str] = if advertise {
+        &["--stateless-rpc", "--http-backend-info-refs", "."]
+    } else {
+        &["--stateless-rpc", "."]
+    };
+    let mut child = command(&git_dir, 2, args, limits)
+        .stdout(Stdio::piped())
+        .stdin(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+    thread::scope(|s| {
+        // N.b. the request is written from a separate thread, so that we
+        // don't deadlock if the child starts writing before it has read all
+        // of its input.
+        thread::spawn_scoped(nid, "upload-pack", s, move || {
+            if let Err(e) = stdin.write_all(request) {
+                log::warn!(target: "worker", "Error writing to upload-pack stdin: {e}");
+            }
+        });
+        let copied = match limits.output {
+            Some(max) => io::copy(&mut (&mut stdout).take(max), &mut send)?,
+            None => io::copy(&mut stdout, &mut send)?,
+        };
+        if Some(copied) == limits.output {
+            child.kill()?;
+        }
+        Ok::<_, io::Error>(copied)
+    })
+    .and_then(|copied| {
+        let mut usage = wait(&mut child, limits)?;
+        if Some(copied) == limits.output {
+            usage.exceeded = limits.output.map(Exceeded::Output);
+        }
+        Ok(usage)
+    })
+}
+
+/// Wait for an upload-pack process to exit, and get the resources it used.
+fn wait(child: &mut Child, limits: Limits) -> io::Result<Usage> {
+    let pid = child.id() as libc::pid_t;
+    let mut status = 0;
+    // SAFETY: `rusage` is a plain C struct, for which all zeroes is a valid value.
+    let mut rusage: libc::rusage = unsafe { mem::zeroed() };
+
+    // N.b. unlike `Child::wait`, this also gets the resource usage of the child.
+    while unsafe { libc::wait4(pid, &mut status, 0, &mut rusage) } == -1 {
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+    let status = ExitStatus::from_raw(status);
+    let cpu = duration(rusage.ru_utime) + duration(rusage.ru_stime);
+    // N.b. once the CPU time limit is reached, the process is killed by the kernel.
+    let exceeded = limits
+        .cpu_time
+        .filter(|secs| status.signal() == Some(libc::SIGKILL) && cpu.as_secs() >= *secs)
+        .map(Exceeded::CpuTime);
+
+    Ok(Usage {
+        status,
+        cpu,
+        exceeded,
+    })
+}
+
+fn duration(tv: libc::timeval) -> time::Duration {
+    time::Duration::new(tv.tv_sec as u64, tv.tv_usec as u32 * 1000)
+}
+
+/// Build the `git upload-pack` command for the repository at `git_dir`,
+/// with the given resource limits.
+fn command(git_dir: &Path, protocol_version: u8, args: &[&str], limits: Limits) -> Command {
+    let mut cmd = Command::new("git");
+    cmd.current_dir(git_dir)
+        .env_clear()
+        .envs(std::env::vars().filter(|(key, _)| key == "PATH" || key.starts_with("GIT_TRACE")))
+        .env("GIT_PROTOCOL", format!("version={protocol_version}"))
+        .args([
+            "-c",
+            "uploadpack.allowAnySha1InWant=true",
+            "-c",
+            "uploadpack.allowRefInWant=true",
+            "-c",
+            "lsrefs.unborn=ignore",
+            "upload-pack",
+        ])
+        .args(args);
+
+    if limits.memory.is_some() || limits.cpu_time.is_some() {
+        // SAFETY: only `setrlimit` is called in the child, which is async-signal-safe.
+        unsafe {
+            cmd.pre_exec(move || limits.apply());
+        }
+    }
+    cmd
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+    use radicle::storage::ReadStorage as _;
+    use radicle::test::{arbitrary, fixtures};
+
+    #[test]
+    fn test_stateless_limits() {
+        let tmp = tempfile::tempdir().unwrap();
+        let signer = radicle::crypto::test::signer::MockSigner::default();
+        let storage = fixtures::storage(tmp.path().join("storage"), &signer).unwrap();
+        let rid = storage.repositories().unwrap()[0].rid;
+        let nid = arbitrary::gen::<NodeId>(1);
+
+        let mut output = Vec::new();
+        let usage = stateless(
+            &nid,
+            &storage,
+            &rid,
+            true,
+            &[],
+            Limits::default(),
+            &mut output,
+        )
+        .unwrap();
+        assert!(usage.status.success());
+        assert_eq!(usage.exceeded, None);
+        assert!(output.len() > 16);
+
+        // The output is cut off once the limit is reached.
+        let limits = Limits {
+            output: Some(16),
+            ..Limits::default()
+        };
+        let mut truncated = Vec::new();
+        let usage = stateless(&nid, &storage, &rid, true, &[], limits, &mut truncated).unwrap();
+        assert_eq!(usage.exceeded, Some(Exceeded::Output(16)));
+        assert_eq!(truncated, output[..16]);
+
+        // The process can't run with too little memory.
+        let limits = Limits {
+            memory: Some(1024 * 1024),
+            ..Limits::default()
+        };
+        let result = stateless(&nid, &storage, &rid, true, &[], limits, io::sink());
+        assert!(result.map_or(true, |usage| !usage.status.success()));
+    }
 }
 
 pub(super) mod pktline {
@@ -136,16 +417,69 @@ pub(super) mod pktline {
 
     pub const HEADER_LEN: usize = 4;
 
-    /// Read and parse the `GitRequest` data from the client side.
-    pub fn git_request<R>(reader: &mut R) -> io::Result<GitRequest>
+    /// Read and parse the `GitRequest` data from the client side. The repository named in
+    /// the request path is resolved with `resolve`.
+    pub fn git_request<R>(
+        reader: &mut R,
+        resolve: impl Fn(&str) -> Option<RepoId>,
+    ) -> io::Result<GitRequest>
     where
         R: io::Read,
     {
         let mut reader = Reader::new(reader);
-        let (header, _) = reader.read_request_pktline()?;
+        let (header, _) = reader.read_request_pktline(resolve)?;
         Ok(header)
     }
 
+    /// Read and parse the request sent on a Git stream, which is either a Git
+    /// request, a blob transfer request, or a reference query. See
+    /// [`crate::worker::blob`] and [`crate::worker::query`].
+    pub fn request<R>(reader: &mut R) -> io::Result<Request>
+    where
+        R: io::Read,
+    {
+        let mut reader = Reader::new(reader);
+        let mut pktline = [0u8; 1024];
+        let length = reader.read_pktline(&mut pktline)?;
+        let input = &pktline[HEADER_LEN..length];
+
+        if let Some(rid) = command_request(input, crate::worker::blob::COMMAND) {
+            return Ok(Request::Blobs(rid));
+        }
+        if let Some(rid) = command_request(input, crate::worker::query::COMMAND) {
+            return Ok(Request::Refs(rid));
+        }
+        // N.b. peers always request repositories by identifier.
+        let Some(cmd) = GitRequest::parse(input, |path| path.parse().ok()) else {
+            return Err(io::ErrorKind::InvalidInput.into());
+        };
+        Ok(Request::Git(cmd))
+    }
+
+    /// Parse a request for the given command, returning the repository requested.
+    ///
+    /// Example: `0037rad-blob-upload /rad:z3gqcJUoA1n9HaHKufZs5FCSGazv5\0`
+    fn command_request(input: &[u8], command: &str) -> Option<RepoId> {
+        let input = str::from_utf8(input).ok()?;
+        let path = input
+            .strip_prefix(command)?
+            .strip_prefix(' ')?
+            .strip_suffix('\0')?;
+
+        path.strip_prefix('/')?.parse().ok()
+    }
+
+    /// A request on a Git stream.
+    #[derive(Debug)]
+    pub enum Request {
+        /// Git upload-pack request.
+        Git(GitRequest),
+        /// Blob transfer request for a repository.
+        Blobs(RepoId),
+        /// Reference query for a repository.
+        Refs(RepoId),
+    }
+
     struct Reader<'a, R> {
         stream: &'a mut R,
     }
@@ -160,10 +494,13 @@ pub(super) mod pktline {
         ///
         /// Example: `0032git-upload-pack /project.git\0host=myserver.com\0`
         ///
-        fn read_request_pktline(&mut self) -> io::Result<(GitRequest, Vec<u8>)> {
+        fn read_request_pktline(
+            &mut self,
+            resolve: impl Fn(&str) -> Option<RepoId>,
+        ) -> io::Result<(GitRequest, Vec<u8>)> {
             let mut pktline = [0u8; 1024];
             let length = self.read_pktline(&mut pktline)?;
-            let Some(cmd) = GitRequest::parse(&pktline[4..length]) else {
+            let Some(cmd) = GitRequest::parse(&pktline[4..length], resolve) else {
                 return Err(io::ErrorKind::InvalidInput.into());
             };
             Ok((cmd, Vec::from(&pktline[..length])))
@@ -202,15 +539,16 @@ pub(super) mod pktline {
     }
 
     impl GitRequest {
-        /// Parse a Git command from a packet-line.
-        fn parse(input: &[u8]) -> Option<Self> {
+        /// Parse a Git command from a packet-line, resolving the repository named in the
+        /// request path with `resolve`.
+        fn parse(input: &[u8], resolve: impl Fn(&str) -> Option<RepoId>) -> Option<Self> {
             let input = str::from_utf8(input).ok()?;
             let mut parts = input
                 .strip_prefix("git-upload-pack ")?
                 .split_terminator('\0');
 
             let path = parts.next()?.to_owned();
-            let repo = path.strip_prefix('/')?.parse().ok()?;
+            let repo = resolve(path.strip_prefix('/')?)?;
             let host = match parts.next() {
                 None | Some("") => None,
                 Some(host) => {
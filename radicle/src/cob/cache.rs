@@ -23,7 +23,7 @@ const DB_WRITE_TIMEOUT: time::Duration = time::Duration::from_secs(6);
 
 /// Database migrations.
 /// The first migration is the creation of the initial tables.
-const MIGRATIONS: &[&str] = &[include_str!("cache/migrations/1.sql")];
+pub(crate) const MIGRATIONS: &[&str] = &[include_str!("cache/migrations/1.sql")];
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -32,6 +32,10 @@ pub static PATH: Lazy<&Path> = Lazy::new(|| Path::new("radicle.json"));
 pub const MAX_STRING_LENGTH: usize = 255;
 /// Maximum number of a delegates in the identity document.
 pub const MAX_DELEGATES: usize = 255;
+/// Maximum size in bytes of an encoded identity document.
+pub const MAX_DOC_SIZE: usize = 64 * 1024;
+/// Maximum nesting depth of arrays and objects in an encoded identity document.
+pub const MAX_DOC_DEPTH: usize = 32;
 
 #[derive(Error, Debug)]
 pub enum DocError {
@@ -47,6 +51,10 @@ pub enum DocError {
     Git(#[from] git2::Error),
     #[error("missing identity document")]
     Missing,
+    #[error("identity document of {0} bytes exceeds the maximum of {MAX_DOC_SIZE} bytes")]
+    TooLarge(usize),
+    #[error("identity document exceeds the maximum nesting depth of {MAX_DOC_DEPTH}")]
+    TooDeep,
 }
 
 impl DocError {
@@ -341,6 +349,9 @@ impl Doc<Verified> {
 
     pub fn load_at<R: ReadRepository>(commit: Oid, repo: &R) -> Result<DocAt, DocError> {
         let blob = Self::blob_at(commit, repo)?;
+        if blob.size() > MAX_DOC_SIZE {
+            return Err(DocError::TooLarge(blob.size()));
+        }
         let doc = Doc::from_blob(&blob)?;
 
         Ok(DocAt {
@@ -401,7 +412,15 @@ impl Doc<Unverified> {
         }
     }
 
+    /// Parse a document, rejecting documents that exceed the size and nesting limits
+    /// before they are parsed.
     pub fn from_json(bytes: &[u8]) -> Result<Self, DocError> {
+        if bytes.len() > MAX_DOC_SIZE {
+            return Err(DocError::TooLarge(bytes.len()));
+        }
+        if exceeds_depth(bytes, MAX_DOC_DEPTH) {
+            return Err(DocError::TooDeep);
+        }
         serde_json::from_slice(bytes).map_err(DocError::from)
     }
 
@@ -435,12 +454,45 @@ impl Doc<Unverified> {
     }
 }
 
+/// Check whether the JSON arrays and objects in `bytes` are nested deeper than `max`.
+/// Brackets inside strings are ignored. Doesn't otherwise validate the input.
+fn exceeds_depth(bytes: &[u8], max: usize) -> bool {
+    let mut depth = 0usize;
+    let mut string = false;
+    let mut escaped = false;
+
+    for b in bytes {
+        if string {
+            match b {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match b {
+            b'"' => string = true,
+            b'[' | b'{' => {
+                depth += 1;
+                if depth > max {
+                    return true;
+                }
+            }
+            b']' | b'}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    false
+}
+
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
 mod test {
     use radicle_crypto::test::signer::MockSigner;
     use radicle_crypto::Signer as _;
 
+    use crate::assert_matches;
     use crate::rad;
     use crate::storage::git::transport;
     use crate::storage::git::Storage;
@@ -528,6 +580,34 @@ mod test {
         assert_eq!(Doc::from_json(&bytes).unwrap().verified().unwrap(), doc);
     }
 
+    #[test]
+    fn test_limits() {
+        let delegate = Did::from(arbitrary::gen::<PublicKey>(1));
+        let doc = |payload: serde_json::Value| {
+            serde_json::to_vec(&serde_json::json!({
+                "payload": { "xyz.radicle.test": payload },
+                "delegates": [delegate],
+                "threshold": 1,
+            }))
+            .unwrap()
+        };
+        let nested =
+            |depth: usize| (0..depth).fold(serde_json::json!("[{"), |v, _| serde_json::json!([v]));
+
+        Doc::from_json(&doc(nested(MAX_DOC_DEPTH - 4)))
+            .unwrap()
+            .verified()
+            .unwrap();
+        assert_matches!(
+            Doc::from_json(&doc(nested(MAX_DOC_DEPTH))),
+            Err(DocError::TooDeep)
+        );
+        assert_matches!(
+            Doc::from_json(&doc(serde_json::json!("x".repeat(MAX_DOC_SIZE)))),
+            Err(DocError::TooLarge(_))
+        );
+    }
+
     #[test]
     fn test_visibility_json() {
         use std::str::FromStr;
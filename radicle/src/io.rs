@@ -1,4 +1,5 @@
-use std::io;
+use std::path::Path;
+use std::{ffi, io, mem};
 
 use libc::{getrlimit, rlimit, setrlimit, RLIMIT_NOFILE};
 
@@ -26,3 +27,37 @@ pub fn set_file_limit(n: u64) -> io::Result<u64> {
     }
     Ok(rlim.rlim_cur)
 }
+
+/// Get the space available to unprivileged users on the filesystem of the given path,
+/// in bytes.
+pub fn available_space(path: &Path) -> io::Result<u64> {
+    use std::os::unix::ffi::OsStrExt as _;
+
+    let path = ffi::CString::new(path.as_os_str().as_bytes())?;
+    let mut stat = mem::MaybeUninit::<libc::statvfs>::uninit();
+
+    // SAFETY: `path` is a valid C string, and `stat` is only read if the call succeeds,
+    // in which case it was initialized.
+    let stat = unsafe {
+        if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        stat.assume_init()
+    };
+    #[allow(clippy::useless_conversion)]
+    Ok(u64::from(stat.f_bavail).saturating_mul(u64::from(stat.f_frsize)))
+}
+
+/// Get the total size of the files under the given path, in bytes. Symbolic links are
+/// not followed.
+pub fn disk_usage(path: &Path) -> io::Result<u64> {
+    let meta = path.symlink_metadata()?;
+    if !meta.is_dir() {
+        return Ok(meta.len());
+    }
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)? {
+        size += disk_usage(&entry?.path())?;
+    }
+    Ok(size)
+}
@@ -12,14 +12,12 @@ use chrono::prelude::*;
 use colored::*;
 use log::{Level, Log, Metadata, Record, SetLoggerError};
 
-/// A logger that logs to `stdout`.
-pub struct Logger {
-    level: Level,
-}
+/// A logger that logs to `stdout`, up to the maximum level set with [`set_level`].
+pub struct Logger;
 
 impl Log for Logger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= self.level
+        metadata.level() <= log::max_level()
     }
 
     fn log(&self, record: &Record) {
@@ -86,7 +84,12 @@ impl Log for StderrLogger {
 
 /// Initialize a new logger.
 pub fn init(level: Level) -> Result<(), SetLoggerError> {
-    set(Logger { level }, level)
+    set(Logger, level)
+}
+
+/// Change the log level of the logger set with [`init`].
+pub fn set_level(level: Level) {
+    log::set_max_level(level.to_level_filter());
 }
 
 /// Set a logger.
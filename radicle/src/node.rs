@@ -3,23 +3,31 @@
 mod features;
 
 pub mod address;
+pub mod autoseed;
 pub mod config;
+pub mod confirmations;
 pub mod db;
 pub mod events;
+pub mod failures;
+pub mod migrate;
 pub mod notifications;
 pub mod policy;
 pub mod refs;
+pub mod repos;
 pub mod routing;
 pub mod seed;
+pub mod sigrefs;
+pub mod stats;
 pub mod timestamp;
+pub mod uploads;
 
-use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::io::{BufRead, BufReader};
 use std::ops::{ControlFlow, Deref};
 use std::os::unix::net::UnixStream;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::{fmt, io, net, thread, time};
+use std::{env, fmt, io, net, thread, time};
 
 use amplify::WrapperMut;
 use cyphernet::addr::NetAddr;
@@ -32,6 +40,7 @@ use crate::crypto::PublicKey;
 use crate::git;
 use crate::identity::RepoId;
 use crate::profile;
+use crate::storage::git::{bundle, verify};
 use crate::storage::refs::RefsAt;
 use crate::storage::RefUpdate;
 
@@ -50,6 +59,10 @@ pub const DEFAULT_SOCKET_NAME: &str = "control.sock";
 pub const DEFAULT_PORT: u16 = 8776;
 /// Default timeout when waiting for the node to respond with data.
 pub const DEFAULT_TIMEOUT: time::Duration = time::Duration::from_secs(30);
+/// Maximum number of seeds fetched from when repairing a repository.
+pub const MAX_REPAIR_SEEDS: usize = 3;
+/// Timeout when exporting or importing a repository bundle.
+pub const BUNDLE_TIMEOUT: time::Duration = time::Duration::from_secs(60 * 10);
 /// Maximum length in bytes of a node alias.
 pub const MAX_ALIAS_LENGTH: usize = 32;
 /// Penalty threshold at which point we avoid connecting to this node.
@@ -315,18 +328,137 @@ impl Default for ConnectOptions {
     }
 }
 
+/// Options passed to the "init" node command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitOptions {
+    /// Project name. Defaults to the name of the repository directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Project description.
+    #[serde(default)]
+    pub description: String,
+    /// Default branch. Defaults to the branch `HEAD` points to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_branch: Option<crate::storage::BranchName>,
+    /// Repository visibility.
+    #[serde(default)]
+    pub visibility: crate::identity::Visibility,
+    /// Seeding scope of the repository. The node always seeds the repositories it
+    /// initializes, so that messages relating to them are relayed to it.
+    #[serde(default = "InitOptions::scope")]
+    pub scope: policy::Scope,
+}
+
+impl Default for InitOptions {
+    fn default() -> Self {
+        Self {
+            name: None,
+            description: String::new(),
+            default_branch: None,
+            visibility: crate::identity::Visibility::default(),
+            scope: Self::scope(),
+        }
+    }
+}
+
+impl InitOptions {
+    fn scope() -> policy::Scope {
+        policy::Scope::All
+    }
+}
+
+/// Kind of error returned by a node operation.
+///
+/// Sent alongside error messages on the control socket and in events, so that clients can
+/// handle specific errors without parsing the message.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorKind {
+    /// The requested entity, eg. a repository or a task, was not found.
+    NotFound,
+    /// The entity already exists.
+    AlreadyExists,
+    /// The repository is not seeded by the node.
+    NotSeeding,
+    /// No seeds were found or available for the repository.
+    NoSeeds,
+    /// The operation timed out.
+    Timeout,
+    /// The peer is not connected, or the connection was lost.
+    Disconnected,
+    /// Fetched or supplied data failed validation.
+    ValidationFailed,
+    /// The node ran out of storage space.
+    StorageFull,
+    /// The input to the operation was invalid.
+    InvalidInput,
+    /// The operation is not supported by the node.
+    Unsupported,
+    /// Any other error.
+    #[default]
+    Other,
+}
+
+impl ErrorKind {
+    /// Get the error kind of an I/O error.
+    pub fn from_io(err: &io::Error) -> Self {
+        if err.raw_os_error() == Some(libc::ENOSPC) {
+            return Self::StorageFull;
+        }
+        match err.kind() {
+            io::ErrorKind::NotFound => Self::NotFound,
+            io::ErrorKind::AlreadyExists => Self::AlreadyExists,
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => Self::Timeout,
+            io::ErrorKind::InvalidInput => Self::InvalidInput,
+            io::ErrorKind::InvalidData => Self::ValidationFailed,
+            io::ErrorKind::Unsupported => Self::Unsupported,
+            io::ErrorKind::NotConnected
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe => Self::Disconnected,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::NotFound => "not found",
+            Self::AlreadyExists => "already exists",
+            Self::NotSeeding => "not seeding",
+            Self::NoSeeds => "no seeds",
+            Self::Timeout => "timeout",
+            Self::Disconnected => "disconnected",
+            Self::ValidationFailed => "validation failed",
+            Self::StorageFull => "storage full",
+            Self::InvalidInput => "invalid input",
+            Self::Unsupported => "unsupported",
+            Self::Other => "other",
+        };
+        f.write_str(s)
+    }
+}
+
 /// Result of a command, on the node control socket.
+///
+/// N.b. the error variant is listed first, since it is tried first when deserializing, and
+/// success types with only optional fields would otherwise match error responses.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum CommandResult<T> {
-    /// Response on node socket indicating that a command was carried out successfully.
-    Okay(T),
     /// Response on node socket indicating that an error occured.
     Error {
         /// The reason for the error.
         #[serde(rename = "error")]
         reason: String,
+        /// The kind of error.
+        #[serde(default, skip_serializing_if = "crate::serde_ext::is_default")]
+        kind: ErrorKind,
     },
+    /// Response on node socket indicating that a command was carried out successfully.
+    Okay(T),
 }
 
 /// A success response.
@@ -352,8 +484,14 @@ impl CommandResult<Success> {
 impl CommandResult<()> {
     /// Create an error result.
     pub fn error(err: impl std::error::Error) -> Self {
+        Self::error_kind(ErrorKind::Other, err)
+    }
+
+    /// Create an error result of the given kind.
+    pub fn error_kind(kind: ErrorKind, err: impl std::error::Error) -> Self {
         Self::Error {
             reason: err.to_string(),
+            kind,
         }
     }
 }
@@ -444,9 +582,15 @@ pub enum Command {
     /// Get the current node condiguration.
     Config,
 
+    /// Reload the node configuration from disk.
+    Reload,
+
     /// Get the node's listen addresses.
     ListenAddrs,
 
+    /// Get the addresses the node announces, along with their labels.
+    AnnouncedAddrs,
+
     /// Connect to node with the given address.
     #[serde(rename_all = "camelCase")]
     Connect {
@@ -489,6 +633,81 @@ pub enum Command {
     #[serde(rename_all = "camelCase")]
     Unfollow { nid: NodeId },
 
+    /// Reset the fetch backoff of the given repository.
+    #[serde(rename_all = "camelCase")]
+    ResetBackoff { rid: RepoId },
+
+    /// Allow or block a node from fetching the given repository.
+    /// Clears the node's fetching policy if no policy is given.
+    #[serde(rename_all = "camelCase")]
+    SetFetcherPolicy {
+        rid: RepoId,
+        nid: NodeId,
+        policy: Option<policy::Policy>,
+    },
+
+    /// Get the fetching policies of the given repository.
+    #[serde(rename_all = "camelCase")]
+    Fetchers { rid: RepoId },
+
+    /// Get the fetches served to other nodes, optionally of the given repository only.
+    #[serde(rename_all = "camelCase")]
+    Uploads { rid: Option<RepoId> },
+
+    /// Get the stored repositories, with their sync status.
+    Repos,
+
+    /// Get the statistics of the given repository, or of all stored repositories.
+    #[serde(rename_all = "camelCase")]
+    Stats { rid: Option<RepoId> },
+
+    /// Check the integrity of the given repository, or of all stored repositories.
+    #[serde(rename_all = "camelCase")]
+    Verify { rid: Option<RepoId> },
+
+    /// Re-fetch the namespaces of a repository that fail verification.
+    #[serde(rename_all = "camelCase")]
+    Repair { rid: RepoId },
+
+    /// Export a repository to a bundle file.
+    #[serde(rename_all = "camelCase")]
+    Export { rid: RepoId, path: PathBuf },
+
+    /// Import a repository from a bundle file.
+    #[serde(rename_all = "camelCase")]
+    Import { path: PathBuf },
+
+    /// Initialize a repository from an existing Git repository, with the node as its
+    /// delegate.
+    #[serde(rename_all = "camelCase")]
+    Init { path: PathBuf, opts: InitOptions },
+
+    /// Get the fetches and uploads running on workers.
+    Tasks,
+
+    /// Abort a running fetch or upload.
+    #[serde(rename_all = "camelCase")]
+    Abort { id: TaskId },
+
+    /// Get the connected peers that advertise the given features.
+    #[serde(rename_all = "camelCase")]
+    Peers { features: Features },
+
+    /// Get the status of our subscription filter.
+    Filter,
+
+    /// Check that the node is reachable and serves repositories, by connecting to it as a
+    /// remote peer would. Dials the first listen address if no address is given, and
+    /// requests a small public repository if none is given.
+    #[serde(rename_all = "camelCase")]
+    SelfCheck {
+        addr: Option<Address>,
+        rid: Option<RepoId>,
+    },
+
+    /// Get the health of the node, by subsystem.
+    Health,
+
     /// Get the node's status.
     Status,
 
@@ -520,6 +739,212 @@ pub enum Link {
     Inbound,
 }
 
+/// Identifier of a fetch or upload running on a worker.
+pub type TaskId = u64;
+
+/// Direction of a worker task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TaskDirection {
+    /// We're fetching from the remote.
+    Fetch,
+    /// The remote is fetching from us.
+    Upload,
+}
+
+/// A fetch or upload running on a worker, or waiting for one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Task {
+    /// Task identifier, used to abort the task.
+    pub id: TaskId,
+    /// Repository transferred. Not known for uploads until the remote sends its request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rid: Option<RepoId>,
+    /// Remote node.
+    pub remote: NodeId,
+    /// Direction of the transfer.
+    pub direction: TaskDirection,
+    /// Time since the task was started, in milliseconds.
+    pub elapsed: u64,
+    /// Bytes sent to the remote.
+    pub sent: usize,
+    /// Bytes received from the remote.
+    pub received: usize,
+}
+
+/// Status of the filter of repositories we subscribe to, sent to peers so that they
+/// only relay what we're interested in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FilterStatus {
+    /// Size of the filter, in bytes.
+    pub size: usize,
+    /// Number of repositories seeded.
+    pub seeded: usize,
+    /// Probability that a repository we don't seed matches the filter.
+    pub false_positive_rate: f64,
+}
+
+/// Outcome of a successful self-check, with the time taken by each step, in milliseconds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SelfCheck {
+    /// Address the node was dialed on.
+    pub addr: Address,
+    /// Repository requested.
+    pub rid: RepoId,
+    /// Time taken to establish the TCP connection.
+    pub connect: u64,
+    /// Time taken by the handshake.
+    pub handshake: u64,
+    /// Time taken for the subscription to be processed.
+    pub subscribe: u64,
+    /// Time taken to list the references of the repository with `git upload-pack`.
+    pub upload: u64,
+    /// Number of references listed.
+    pub refs: usize,
+}
+
+/// Health of the node, or of one of its subsystems.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "state")]
+pub enum HealthState {
+    /// Working as expected.
+    Ok,
+    /// Working, but not as expected, eg. because it's overloaded or can't reach peers.
+    #[serde(rename_all = "camelCase")]
+    Degraded { reason: String },
+    /// Not working.
+    #[serde(rename_all = "camelCase")]
+    Failed { reason: String },
+}
+
+impl HealthState {
+    /// Create a degraded health, with the given reason.
+    pub fn degraded(reason: impl ToString) -> Self {
+        Self::Degraded {
+            reason: reason.to_string(),
+        }
+    }
+
+    /// Create a failed health, with the given reason.
+    pub fn failed(reason: impl ToString) -> Self {
+        Self::Failed {
+            reason: reason.to_string(),
+        }
+    }
+
+    /// Whether this is a healthy state.
+    pub fn is_ok(&self) -> bool {
+        matches!(self, Self::Ok)
+    }
+
+    /// Why the state isn't healthy, if it isn't.
+    pub fn reason(&self) -> Option<&str> {
+        match self {
+            Self::Ok => None,
+            Self::Degraded { reason } | Self::Failed { reason } => Some(reason),
+        }
+    }
+
+    /// How bad the state is, from zero for a healthy state.
+    fn severity(&self) -> u8 {
+        match self {
+            Self::Ok => 0,
+            Self::Degraded { .. } => 1,
+            Self::Failed { .. } => 2,
+        }
+    }
+}
+
+impl fmt::Display for HealthState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ok => write!(f, "ok"),
+            Self::Degraded { .. } => write!(f, "degraded"),
+            Self::Failed { .. } => write!(f, "failed"),
+        }
+    }
+}
+
+/// Health of the node, by subsystem.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeHealth {
+    /// Overall health, ie. the health of the least healthy subsystem, with the reason
+    /// prefixed by the subsystem name.
+    pub overall: HealthState,
+    /// The reactor, which runs the service and performs network I/O.
+    pub reactor: HealthState,
+    /// Gossip with connected peers.
+    pub gossip: HealthState,
+    /// The address book, ie. the nodes we know how to connect to.
+    pub addresses: HealthState,
+    /// The worker pool, which runs fetches and uploads.
+    pub workers: HealthState,
+    /// Periodic maintenance of the node databases, eg. pruning of expired gossip.
+    pub maintenance: HealthState,
+    /// Number of times a worker recovered from a panic while processing a task, since the
+    /// node started.
+    #[serde(default)]
+    pub worker_restarts: u64,
+}
+
+impl NodeHealth {
+    /// Aggregate the health of the subsystems into the health of the node.
+    pub fn new(
+        reactor: HealthState,
+        gossip: HealthState,
+        addresses: HealthState,
+        workers: HealthState,
+        maintenance: HealthState,
+    ) -> Self {
+        let mut health = Self {
+            overall: HealthState::Ok,
+            reactor,
+            gossip,
+            addresses,
+            workers,
+            maintenance,
+            worker_restarts: 0,
+        };
+        let mut worst = HealthState::Ok;
+        for (name, subsystem) in health.subsystems() {
+            if subsystem.severity() > worst.severity() {
+                worst = match subsystem {
+                    HealthState::Ok => HealthState::Ok,
+                    HealthState::Degraded { reason } => {
+                        HealthState::degraded(format!("{name}: {reason}"))
+                    }
+                    HealthState::Failed { reason } => {
+                        HealthState::failed(format!("{name}: {reason}"))
+                    }
+                };
+            }
+        }
+        health.overall = worst;
+        health
+    }
+
+    /// Set the number of times a worker recovered from a panic.
+    pub fn with_worker_restarts(mut self, restarts: u64) -> Self {
+        self.worker_restarts = restarts;
+        self
+    }
+
+    /// The subsystems, by name.
+    pub fn subsystems(&self) -> [(&'static str, &HealthState); 5] {
+        [
+            ("reactor", &self.reactor),
+            ("gossip", &self.gossip),
+            ("addresses", &self.addresses),
+            ("workers", &self.workers),
+            ("maintenance", &self.maintenance),
+        ]
+    }
+}
+
 /// An established network connection with a peer.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Session {
@@ -550,6 +975,9 @@ pub struct Seed {
     /// The seed's sync status, if any.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub sync: Option<SyncStatus>,
+    /// The seed's health, if it announced it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health: Option<Health>,
 }
 
 impl Seed {
@@ -574,10 +1002,27 @@ impl Seed {
             addrs,
             state,
             sync,
+            health: None,
         }
     }
 }
 
+/// Health of a seed, as announced by the seed itself.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Health {
+    /// How long the seed has been running for.
+    #[serde(with = "crate::serde_ext::localtime::duration")]
+    pub uptime: LocalDuration,
+    /// Free space in the seed's storage, in bytes.
+    pub storage_free: u64,
+    /// Size of the largest repository the seed accepts, in bytes, if limited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_repo_size: Option<u64>,
+    /// When the seed announced its health.
+    pub timestamp: Timestamp,
+}
+
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 /// Represents a set of seeds with associated metadata. Uses an RNG
 /// underneath, so every iteration returns a different ordering.
@@ -690,9 +1135,10 @@ pub enum FetchResult {
         namespaces: HashSet<NodeId>,
         clone: bool,
     },
-    // TODO: Create enum for reason.
     Failed {
         reason: String,
+        #[serde(default, skip_serializing_if = "crate::serde_ext::is_default")]
+        kind: ErrorKind,
     },
 }
 
@@ -731,11 +1177,63 @@ impl<S: ToString> From<Result<(Vec<RefUpdate>, HashSet<NodeId>, bool), S>> for F
             },
             Err(err) => Self::Failed {
                 reason: err.to_string(),
+                kind: ErrorKind::Other,
             },
         }
     }
 }
 
+/// Summary of the reference updates of a fetch, for a single namespace.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NamespaceUpdates {
+    /// Number of references created.
+    pub created: usize,
+    /// Number of references updated.
+    pub updated: usize,
+    /// Number of references deleted.
+    pub deleted: usize,
+    /// Number of references that were already up to date.
+    pub skipped: usize,
+    /// New tip of the repository's default branch, if it changed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub head: Option<git::Oid>,
+}
+
+impl NamespaceUpdates {
+    /// Summarize the given updates, per namespace. Updates of references outside of a
+    /// namespace are ignored.
+    pub fn summarize<'a>(
+        updates: impl IntoIterator<Item = &'a RefUpdate>,
+        default_branch: Option<&git::Qualified>,
+    ) -> BTreeMap<NodeId, Self> {
+        let mut summaries = BTreeMap::<NodeId, Self>::new();
+
+        for update in updates {
+            let Ok((nid, refname)) = git::parse_ref_namespaced::<NodeId>(update.name()) else {
+                continue;
+            };
+            let summary = summaries.entry(nid).or_default();
+
+            match update {
+                RefUpdate::Created { .. } => summary.created += 1,
+                RefUpdate::Updated { .. } => summary.updated += 1,
+                RefUpdate::Deleted { .. } => summary.deleted += 1,
+                RefUpdate::Skipped { .. } => summary.skipped += 1,
+            }
+            if Some(&refname) == default_branch {
+                summary.head = update.new();
+            }
+        }
+        summaries
+    }
+
+    /// Check whether any reference changed.
+    pub fn is_changed(&self) -> bool {
+        self.created + self.updated + self.deleted > 0
+    }
+}
+
 /// Holds multiple fetch results.
 #[derive(Debug, Default)]
 pub struct FetchResults(Vec<(NodeId, FetchResult)>);
@@ -775,7 +1273,7 @@ impl FetchResults {
     /// Iterate over failed fetches.
     pub fn failed(&self) -> impl Iterator<Item = (&NodeId, &str)> {
         self.0.iter().filter_map(|(nid, r)| {
-            if let FetchResult::Failed { reason } = r {
+            if let FetchResult::Failed { reason, .. } = r {
                 Some((nid, reason.as_str()))
             } else {
                 None
@@ -819,7 +1317,7 @@ pub enum Error {
     #[error("failed to open node control socket {0:?} ({1})")]
     Connect(PathBuf, io::ErrorKind),
     #[error("command error: {reason}")]
-    Command { reason: String },
+    Command { reason: String, kind: ErrorKind },
     #[error("received invalid json `{response}` in response to command: {error}")]
     InvalidJson {
         response: String,
@@ -834,6 +1332,17 @@ impl Error {
     pub fn is_connection_err(&self) -> bool {
         matches!(self, Self::Connect { .. })
     }
+
+    /// Get the kind of error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Io(e) => ErrorKind::from_io(e),
+            Self::TimedOut => ErrorKind::Timeout,
+            Self::Command { kind, .. } => *kind,
+            Self::InvalidJson { .. } => ErrorKind::InvalidInput,
+            Self::Node(_) | Self::Connect(..) | Self::EmptyResponse => ErrorKind::Other,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -856,8 +1365,13 @@ pub trait Handle: Clone + Sync + Send {
     fn is_running(&self) -> bool;
     /// Get the node's bound listen addresses.
     fn listen_addrs(&self) -> Result<Vec<net::SocketAddr>, Self::Error>;
+    /// Get the addresses the node announces to the network, along with their labels.
+    fn announced_addrs(&self) -> Result<Vec<(Address, address::Label)>, Self::Error>;
     /// Get the current node configuration.
     fn config(&self) -> Result<config::Config, Self::Error>;
+    /// Reload the node configuration from disk. Settings that can be changed while the node is
+    /// running are applied, the others take effect on restart.
+    fn reload(&mut self) -> Result<config::ConfigDiff, Self::Error>;
     /// Connect to a peer.
     fn connect(
         &mut self,
@@ -885,6 +1399,62 @@ pub trait Handle: Clone + Sync + Send {
     fn unseed(&mut self, id: RepoId) -> Result<bool, Self::Error>;
     /// Unfollow the given peer.
     fn unfollow(&mut self, id: NodeId) -> Result<bool, Self::Error>;
+    /// Reset the fetch backoff of the given repo, so that it is fetched on the next
+    /// announcement, even if previous fetches failed.
+    fn reset_backoff(&mut self, id: RepoId) -> Result<bool, Self::Error>;
+    /// Allow or block the given node from fetching the given repo from us, or clear its
+    /// fetching policy if `None` is passed.
+    fn set_fetcher_policy(
+        &mut self,
+        id: RepoId,
+        nid: NodeId,
+        policy: Option<policy::Policy>,
+    ) -> Result<bool, Self::Error>;
+    /// Get the fetching policies of the given repo.
+    fn fetchers(&self, id: RepoId) -> Result<Vec<policy::FetcherPolicy>, Self::Error>;
+    /// Get the fetches served to other nodes, most recent first. If a repo is given, only
+    /// fetches of that repo are returned.
+    fn uploads(&self, id: Option<RepoId>) -> Result<Vec<uploads::Upload>, Self::Error>;
+    /// Get the stored repositories, with their seeding policy and sync status.
+    fn repos(&self) -> Result<Vec<repos::Repo>, Self::Error>;
+    /// Get the statistics of the given repository, or the cached statistics of all stored
+    /// repositories, largest first.
+    fn stats(&self, id: Option<RepoId>) -> Result<Vec<stats::Stats>, Self::Error>;
+    /// Check the integrity of the given repository, or of all stored repositories.
+    /// A report is returned for each repository as it is checked.
+    fn verify(
+        &self,
+        id: Option<RepoId>,
+    ) -> Result<Box<dyn Iterator<Item = Result<verify::Report, Self::Error>>>, Self::Error>;
+    /// Repair the given repository, by re-fetching the namespaces that fail verification
+    /// from connected seeds.
+    fn repair(&mut self, id: RepoId) -> Result<verify::Repair, Self::Error>;
+    /// Export the given repository, with all its namespaces, to a bundle file.
+    fn export(&self, id: RepoId, path: PathBuf) -> Result<(), Self::Error>;
+    /// Import a repository from a bundle file. The bundle is verified before it is
+    /// stored. Fails if the repository is already stored.
+    fn import(&mut self, path: PathBuf) -> Result<bundle::Imported, Self::Error>;
+    /// Initialize a repository from the Git repository at the given path, with the node
+    /// key as its only delegate, and announce it. The Git repository isn't modified.
+    fn init(&mut self, path: PathBuf, opts: InitOptions) -> Result<RepoId, Self::Error>;
+    /// Get the fetches and uploads running on workers, or waiting for a worker.
+    fn tasks(&self) -> Result<Vec<Task>, Self::Error>;
+    /// Abort a running fetch or upload, by closing its stream. Returns `false` if the task
+    /// wasn't found, eg. because it already finished.
+    fn abort(&mut self, id: TaskId) -> Result<bool, Self::Error>;
+    /// Get the connected peers that advertise the given features, eg. to find bridges.
+    fn peers(&self, features: Features) -> Result<Vec<NodeId>, Self::Error>;
+    /// Get the status of our subscription filter.
+    fn filter(&self) -> Result<FilterStatus, Self::Error>;
+    /// Get the health of the node, by subsystem.
+    fn health(&self) -> Result<NodeHealth, Self::Error>;
+    /// Connect to the node as a remote peer, on the given address or on its first listen
+    /// address, and request the given repository, or a small public repository.
+    fn self_check(
+        &self,
+        addr: Option<Address>,
+        rid: Option<RepoId>,
+    ) -> Result<SelfCheck, Self::Error>;
     /// Notify the service that a project has been updated, and announce local refs.
     fn announce_refs(&mut self, id: RepoId) -> Result<RefsAt, Self::Error>;
     /// Announce local inventory.
@@ -944,7 +1514,7 @@ impl Node {
 
             match result {
                 CommandResult::Okay(result) => Ok(result),
-                CommandResult::Error { reason } => Err(Error::Command { reason }),
+                CommandResult::Error { reason, kind } => Err(Error::Command { reason, kind }),
             }
         }))
     }
@@ -1036,6 +1606,13 @@ impl Handle for Node {
             .map_err(Error::from)
     }
 
+    fn announced_addrs(&self) -> Result<Vec<(Address, address::Label)>, Error> {
+        self.call::<Vec<(Address, address::Label)>>(Command::AnnouncedAddrs, DEFAULT_TIMEOUT)?
+            .next()
+            .ok_or(Error::EmptyResponse)?
+            .map_err(Error::from)
+    }
+
     fn is_running(&self) -> bool {
         let Ok(mut lines) = self.call::<Success>(Command::Status, DEFAULT_TIMEOUT) else {
             return false;
@@ -1053,6 +1630,13 @@ impl Handle for Node {
             .map_err(Error::from)
     }
 
+    fn reload(&mut self) -> Result<config::ConfigDiff, Error> {
+        self.call::<config::ConfigDiff>(Command::Reload, DEFAULT_TIMEOUT)?
+            .next()
+            .ok_or(Error::EmptyResponse)?
+            .map_err(Error::from)
+    }
+
     fn connect(
         &mut self,
         nid: NodeId,
@@ -1133,6 +1717,166 @@ impl Handle for Node {
         Ok(response.updated)
     }
 
+    fn reset_backoff(&mut self, rid: RepoId) -> Result<bool, Error> {
+        let mut line = self.call::<Success>(Command::ResetBackoff { rid }, DEFAULT_TIMEOUT)?;
+        let response = line.next().ok_or(Error::EmptyResponse)??;
+
+        Ok(response.updated)
+    }
+
+    fn set_fetcher_policy(
+        &mut self,
+        rid: RepoId,
+        nid: NodeId,
+        policy: Option<policy::Policy>,
+    ) -> Result<bool, Error> {
+        let mut line = self.call::<Success>(
+            Command::SetFetcherPolicy { rid, nid, policy },
+            DEFAULT_TIMEOUT,
+        )?;
+        let response = line.next().ok_or(Error::EmptyResponse)??;
+
+        Ok(response.updated)
+    }
+
+    fn fetchers(&self, rid: RepoId) -> Result<Vec<policy::FetcherPolicy>, Error> {
+        let fetchers = self
+            .call::<Vec<policy::FetcherPolicy>>(Command::Fetchers { rid }, DEFAULT_TIMEOUT)?
+            .next()
+            .ok_or(Error::EmptyResponse)??;
+
+        Ok(fetchers)
+    }
+
+    fn uploads(&self, rid: Option<RepoId>) -> Result<Vec<uploads::Upload>, Error> {
+        let uploads = self
+            .call::<Vec<uploads::Upload>>(Command::Uploads { rid }, DEFAULT_TIMEOUT)?
+            .next()
+            .ok_or(Error::EmptyResponse)??;
+
+        Ok(uploads)
+    }
+
+    fn repos(&self) -> Result<Vec<repos::Repo>, Error> {
+        let repos = self
+            .call::<Vec<repos::Repo>>(Command::Repos, DEFAULT_TIMEOUT)?
+            .next()
+            .ok_or(Error::EmptyResponse)??;
+
+        Ok(repos)
+    }
+
+    fn stats(&self, rid: Option<RepoId>) -> Result<Vec<stats::Stats>, Error> {
+        let stats = self
+            .call::<Vec<stats::Stats>>(Command::Stats { rid }, DEFAULT_TIMEOUT)?
+            .next()
+            .ok_or(Error::EmptyResponse)??;
+
+        Ok(stats)
+    }
+
+    fn verify(
+        &self,
+        rid: Option<RepoId>,
+    ) -> Result<Box<dyn Iterator<Item = Result<verify::Report, Error>>>, Error> {
+        let reports = self.call(Command::Verify { rid }, DEFAULT_TIMEOUT)?;
+
+        Ok(Box::new(reports))
+    }
+
+    fn repair(&mut self, rid: RepoId) -> Result<verify::Repair, Error> {
+        // Each seed is fetched from with the default timeout.
+        let timeout = DEFAULT_TIMEOUT * (MAX_REPAIR_SEEDS as u32 + 1);
+        let repair = self
+            .call::<verify::Repair>(Command::Repair { rid }, timeout)?
+            .next()
+            .ok_or(Error::EmptyResponse)??;
+
+        Ok(repair)
+    }
+
+    fn export(&self, rid: RepoId, path: PathBuf) -> Result<(), Error> {
+        // The path is relative to our working directory, not the node's.
+        let path = env::current_dir()?.join(path);
+
+        for line in self.call::<Success>(Command::Export { rid, path }, BUNDLE_TIMEOUT)? {
+            line?;
+        }
+        Ok(())
+    }
+
+    fn import(&mut self, path: PathBuf) -> Result<bundle::Imported, Error> {
+        let path = env::current_dir()?.join(path);
+        let imported = self
+            .call::<bundle::Imported>(Command::Import { path }, BUNDLE_TIMEOUT)?
+            .next()
+            .ok_or(Error::EmptyResponse)??;
+
+        Ok(imported)
+    }
+
+    fn init(&mut self, path: PathBuf, opts: InitOptions) -> Result<RepoId, Error> {
+        let path = env::current_dir()?.join(path);
+        let rid = self
+            .call::<RepoId>(Command::Init { path, opts }, BUNDLE_TIMEOUT)?
+            .next()
+            .ok_or(Error::EmptyResponse)??;
+
+        Ok(rid)
+    }
+
+    fn tasks(&self) -> Result<Vec<Task>, Error> {
+        let tasks = self
+            .call::<Vec<Task>>(Command::Tasks, DEFAULT_TIMEOUT)?
+            .next()
+            .ok_or(Error::EmptyResponse)??;
+
+        Ok(tasks)
+    }
+
+    fn abort(&mut self, id: TaskId) -> Result<bool, Error> {
+        let mut line = self.call::<Success>(Command::Abort { id }, DEFAULT_TIMEOUT)?;
+        let response = line.next().ok_or(Error::EmptyResponse)??;
+
+        Ok(response.updated)
+    }
+
+    fn peers(&self, features: Features) -> Result<Vec<NodeId>, Error> {
+        let peers = self
+            .call::<Vec<NodeId>>(Command::Peers { features }, DEFAULT_TIMEOUT)?
+            .next()
+            .ok_or(Error::EmptyResponse)??;
+
+        Ok(peers)
+    }
+
+    fn filter(&self) -> Result<FilterStatus, Error> {
+        let status = self
+            .call::<FilterStatus>(Command::Filter, DEFAULT_TIMEOUT)?
+            .next()
+            .ok_or(Error::EmptyResponse)??;
+
+        Ok(status)
+    }
+
+    fn health(&self) -> Result<NodeHealth, Error> {
+        let health = self
+            .call::<NodeHealth>(Command::Health, DEFAULT_TIMEOUT)?
+            .next()
+            .ok_or(Error::EmptyResponse)??;
+
+        Ok(health)
+    }
+
+    fn self_check(&self, addr: Option<Address>, rid: Option<RepoId>) -> Result<SelfCheck, Error> {
+        let check = self
+            .call::<SelfCheck>(Command::SelfCheck { addr, rid }, DEFAULT_TIMEOUT)?
+            .next()
+            .ok_or(Error::EmptyResponse)??;
+
+        Ok(check)
+    }
+
     fn unseed(&mut self, rid: RepoId) -> Result<bool, Error> {
         let mut line = self.call::<Success>(Command::Unseed { rid }, DEFAULT_TIMEOUT)?;
         let response = line.next().ok_or(Error::EmptyResponse {})??;
@@ -1225,6 +1969,62 @@ mod test {
         assert!(Alias::from_str("cloudhead\n").is_err());
     }
 
+    #[test]
+    fn test_node_health() {
+        let health = NodeHealth::new(
+            HealthState::Ok,
+            HealthState::degraded("not connected to any peer"),
+            HealthState::Ok,
+            HealthState::failed("no progress in 5 minutes"),
+            HealthState::degraded("last run 2 hours ago"),
+        );
+        // The least healthy subsystem determines the health of the node.
+        assert_eq!(
+            health.overall,
+            HealthState::failed("workers: no progress in 5 minutes")
+        );
+        assert_eq!(
+            json::to_value(&health.gossip).unwrap(),
+            json::json!({ "state": "degraded", "reason": "not connected to any peer" })
+        );
+        assert_eq!(
+            json::to_value(HealthState::Ok).unwrap(),
+            json::json!({ "state": "ok" })
+        );
+
+        let health = NodeHealth::new(
+            HealthState::Ok,
+            HealthState::degraded("not connected to any peer"),
+            HealthState::degraded("no known nodes"),
+            HealthState::Ok,
+            HealthState::Ok,
+        );
+        assert_eq!(
+            health.overall,
+            HealthState::degraded("gossip: not connected to any peer")
+        );
+        assert!(NodeHealth::new(
+            HealthState::Ok,
+            HealthState::Ok,
+            HealthState::Ok,
+            HealthState::Ok,
+            HealthState::Ok
+        )
+        .overall
+        .is_ok());
+
+        let health = health.with_worker_restarts(3);
+        let mut value = json::to_value(&health).unwrap();
+        assert_eq!(value["workerRestarts"], 3);
+
+        // Reported by nodes that don't count restarts.
+        value.as_object_mut().unwrap().remove("workerRestarts");
+        assert_eq!(
+            json::from_value::<NodeHealth>(value).unwrap(),
+            health.with_worker_restarts(0)
+        );
+    }
+
     #[test]
     fn test_command_result() {
         #[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -1253,6 +2053,29 @@ mod test {
             .unwrap(),
             "{\"error\":\"entity not found\"}"
         );
+        assert_eq!(
+            json::to_string(&CommandResult::error_kind(
+                ErrorKind::from_io(&io::Error::from(io::ErrorKind::TimedOut)),
+                io::Error::from(io::ErrorKind::TimedOut)
+            ))
+            .unwrap(),
+            "{\"error\":\"timed out\",\"kind\":\"timeout\"}"
+        );
+        assert_eq!(
+            json::from_str::<CommandResult<Success>>("{\"error\":\"oops\",\"kind\":\"notFound\"}")
+                .unwrap(),
+            CommandResult::Error {
+                reason: String::from("oops"),
+                kind: ErrorKind::NotFound,
+            }
+        );
+        assert_eq!(
+            json::from_str::<CommandResult<Success>>("{\"error\":\"oops\"}").unwrap(),
+            CommandResult::Error {
+                reason: String::from("oops"),
+                kind: ErrorKind::Other,
+            }
+        );
 
         json::from_str::<CommandResult<State>>(
             &serde_json::to_string(&CommandResult::Okay(State::Connected {
@@ -1278,4 +2101,63 @@ mod test {
             Ok(CommandResult::Okay(_))
         );
     }
+
+    #[test]
+    fn test_namespace_updates() {
+        let alice = crate::test::arbitrary::gen::<NodeId>(1);
+        let bob = crate::test::arbitrary::gen::<NodeId>(1);
+        let head = crate::test::arbitrary::oid();
+        let oid = crate::test::arbitrary::oid();
+        let name = |nid: &NodeId, name: &str| {
+            git::fmt::RefString::try_from(format!("refs/namespaces/{nid}/{name}")).unwrap()
+        };
+        let master = git::refs::branch(git::refname!("master").as_refstr());
+        let updates = [
+            RefUpdate::Updated {
+                name: name(&alice, "refs/heads/master"),
+                old: oid,
+                new: head,
+            },
+            RefUpdate::Created {
+                name: name(&alice, "refs/heads/dev"),
+                oid,
+            },
+            RefUpdate::Skipped {
+                name: name(&bob, "refs/heads/master"),
+                oid,
+            },
+            RefUpdate::Deleted {
+                name: name(&bob, "refs/heads/dev"),
+                oid,
+            },
+            RefUpdate::Created {
+                name: git::refname!("refs/rad/id"),
+                oid,
+            },
+        ];
+        let summary = NamespaceUpdates::summarize(&updates, Some(&master));
+
+        assert_eq!(summary.len(), 2);
+        assert_eq!(
+            summary[&alice],
+            NamespaceUpdates {
+                created: 1,
+                updated: 1,
+                deleted: 0,
+                skipped: 0,
+                head: Some(head),
+            }
+        );
+        assert_eq!(
+            summary[&bob],
+            NamespaceUpdates {
+                created: 0,
+                updated: 0,
+                deleted: 1,
+                skipped: 1,
+                head: None,
+            }
+        );
+        assert!(summary[&alice].is_changed());
+    }
 }
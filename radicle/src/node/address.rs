@@ -150,6 +150,9 @@ pub struct KnownAddress {
     pub last_attempt: Option<LocalTime>,
     /// Whether this address has been banned.
     pub banned: bool,
+    /// How the node that announced this address came by it, if it said.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<Label>,
 }
 
 impl KnownAddress {
@@ -161,6 +164,70 @@ impl KnownAddress {
             last_success: None,
             last_attempt: None,
             banned: false,
+            label: None,
+        }
+    }
+
+    /// Add a label to this address.
+    #[must_use]
+    pub fn labeled(self, label: Option<Label>) -> Self {
+        Self { label, ..self }
+    }
+
+    /// The label this address is dialed with. Addresses without a label, eg. bootstrap
+    /// addresses or addresses of nodes that don't label them, are treated as manually
+    /// configured.
+    pub fn preference(&self) -> Label {
+        self.label.unwrap_or(Label::Manual)
+    }
+}
+
+/// Address label. Specifies how a node came by an address it announces, and thus how
+/// confident it is that the address is reachable.
+///
+/// Labels are ordered by preference, the most reliable first.
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum Label {
+    /// An address configured by the node operator.
+    Manual,
+    /// An address mapped on the node's gateway with UPnP.
+    Upnp,
+    /// An address the node's peers observed it connecting from.
+    Observed,
+}
+
+impl std::fmt::Display for Label {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Manual => write!(f, "manual"),
+            Self::Upnp => write!(f, "upnp"),
+            Self::Observed => write!(f, "observed"),
+        }
+    }
+}
+
+impl From<Label> for u8 {
+    fn from(other: Label) -> Self {
+        match other {
+            Label::Manual => 1,
+            Label::Upnp => 2,
+            Label::Observed => 3,
+        }
+    }
+}
+
+impl TryFrom<u8> for Label {
+    type Error = u8;
+
+    fn try_from(other: u8) -> Result<Self, Self::Error> {
+        match other {
+            1 => Ok(Label::Manual),
+            2 => Ok(Label::Upnp),
+            3 => Ok(Label::Observed),
+            _ => Err(other),
         }
     }
 }
@@ -243,7 +310,17 @@ pub fn is_local(addr: &net::IpAddr) -> bool {
         net::IpAddr::V4(addr) => {
             addr.is_private() || addr.is_loopback() || addr.is_link_local() || addr.is_unspecified()
         }
-        net::IpAddr::V6(_) => false,
+        net::IpAddr::V6(addr) => {
+            if let Some(addr) = addr.to_ipv4_mapped() {
+                return is_local(&net::IpAddr::V4(addr));
+            }
+            addr.is_loopback()
+                || addr.is_unspecified()
+                // Unique local addresses, ie. `fc00::/7`.
+                || (addr.segments()[0] & 0xfe00) == 0xfc00
+                // Unicast link-local addresses, ie. `fe80::/10`.
+                || (addr.segments()[0] & 0xffc0) == 0xfe80
+        }
     }
 }
 
@@ -261,6 +338,7 @@ fn ipv4_is_routable(addr: &net::Ipv4Addr) -> bool {
         && !addr.is_loopback()
         && !addr.is_link_local()
         && !addr.is_broadcast()
+        && !addr.is_multicast()
         && !addr.is_documentation()
         // Make sure the address is not in 0.0.0.0/8.
         && addr.octets()[0] != 0
@@ -268,8 +346,52 @@ fn ipv4_is_routable(addr: &net::Ipv4Addr) -> bool {
 
 /// Check whether an IPv6 address is globally routable.
 ///
-/// For now, this always returns `true`, as IPv6 addresses
-/// are not fully supported.
-fn ipv6_is_routable(_addr: &net::Ipv6Addr) -> bool {
-    true
+/// Like [`ipv4_is_routable`], this is a subset of `net::Ipv6Addr::is_global`. IPv4-mapped
+/// addresses are checked as IPv4 addresses.
+fn ipv6_is_routable(addr: &net::Ipv6Addr) -> bool {
+    if let Some(addr) = addr.to_ipv4_mapped() {
+        return ipv4_is_routable(&addr);
+    }
+    // Make sure the address is not in the documentation range, ie. `2001:db8::/32`.
+    let documentation = addr.segments()[0] == 0x2001 && addr.segments()[1] == 0xdb8;
+
+    !is_local(&net::IpAddr::V6(*addr)) && !addr.is_multicast() && !documentation
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_routable() {
+        for ip in [
+            "1.1.1.1",
+            "192.0.0.9",
+            "2606:4700:4700::1111",
+            "::ffff:1.1.1.1",
+        ] {
+            let ip = ip.parse::<net::IpAddr>().unwrap();
+            assert!(is_routable(&ip), "{ip} should be routable");
+            assert!(!is_local(&ip), "{ip} should not be local");
+        }
+        for ip in [
+            "10.0.0.1",
+            "127.0.0.1",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:192.168.1.1",
+        ] {
+            let ip = ip.parse::<net::IpAddr>().unwrap();
+            assert!(!is_routable(&ip), "{ip} should not be routable");
+            assert!(is_local(&ip), "{ip} should be local");
+        }
+        for ip in ["224.0.0.1", "255.255.255.255", "ff02::1", "2001:db8::1"] {
+            let ip = ip.parse::<net::IpAddr>().unwrap();
+            assert!(!is_routable(&ip), "{ip} should not be routable");
+            assert!(!is_local(&ip), "{ip} should not be local");
+        }
+    }
 }
@@ -5,7 +5,7 @@ use sqlite as sql;
 use thiserror::Error;
 
 use crate::node;
-use crate::node::address::{AddressType, KnownAddress, Node, Source};
+use crate::node::address::{AddressType, KnownAddress, Label, Node, Source};
 use crate::node::{Address, Alias, AliasError, AliasStore, Database, NodeId, Penalty, Severity};
 use crate::prelude::Timestamp;
 use crate::sql::transaction;
@@ -76,6 +76,11 @@ pub trait Store {
         addr: &Address,
         severity: Severity,
     ) -> Result<(), Error>;
+    /// Penalize a node for misbehaving, without it being disconnected, eg. for relaying
+    /// invalid messages.
+    fn penalize(&mut self, nid: &NodeId, severity: Severity) -> Result<(), Error>;
+    /// Mark all addresses of a node as banned, so that we don't try to connect to it.
+    fn ban(&mut self, nid: &NodeId) -> Result<(), Error>;
 }
 
 impl Store for Database {
@@ -111,7 +116,7 @@ impl Store for Database {
     fn addresses_of(&self, node: &NodeId) -> Result<Vec<KnownAddress>, Error> {
         let mut addrs = Vec::new();
         let mut stmt = self.db.prepare(
-            "SELECT type, value, source, last_attempt, last_success, banned FROM addresses WHERE node = ?",
+            "SELECT type, value, source, last_attempt, last_success, banned, label FROM addresses WHERE node = ?",
         )?;
         stmt.bind((1, node))?;
 
@@ -127,6 +132,10 @@ impl Store for Database {
                 .read::<Option<i64>, _>("last_success")
                 .map(|t| LocalTime::from_millis(t as u128));
             let banned = row.read::<i64, _>("banned").is_positive();
+            let label = match &row["label"] {
+                sql::Value::Null => None,
+                value => Some(Label::try_from(value)?),
+            };
 
             addrs.push(KnownAddress {
                 addr,
@@ -134,6 +143,7 @@ impl Store for Database {
                 last_success,
                 last_attempt,
                 banned,
+                label,
             });
         }
         Ok(addrs)
@@ -190,10 +200,10 @@ impl Store for Database {
 
             for addr in addrs {
                 let mut stmt = db.prepare(
-                    "INSERT INTO addresses (node, type, value, source, timestamp)
-                     VALUES (?1, ?2, ?3, ?4, ?5)
+                    "INSERT INTO addresses (node, type, value, source, timestamp, label)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)
                      ON CONFLICT DO UPDATE
-                     SET timestamp = ?5
+                     SET timestamp = ?5, label = ?6
                      WHERE timestamp < ?5",
                 )?;
                 stmt.bind((1, node))?;
@@ -201,6 +211,7 @@ impl Store for Database {
                 stmt.bind((3, &addr.addr))?;
                 stmt.bind((4, addr.source))?;
                 stmt.bind((5, &timestamp))?;
+                stmt.bind((6, addr.label))?;
                 stmt.next()?;
             }
             Ok::<_, Error>(db.change_count() > 0)
@@ -220,7 +231,7 @@ impl Store for Database {
         let mut stmt = self
             .db
             .prepare(
-                "SELECT a.node, a.type, a.value, a.source, a.last_success, a.last_attempt, a.banned, n.penalty
+                "SELECT a.node, a.type, a.value, a.source, a.last_success, a.last_attempt, a.banned, a.label, n.penalty
                  FROM addresses AS a
                  JOIN nodes AS n ON a.node = n.id
                  ORDER BY n.penalty ASC, n.id ASC",
@@ -238,6 +249,10 @@ impl Store for Database {
             let last_success = last_success.map(|t| LocalTime::from_millis(t as u128));
             let last_attempt = last_attempt.map(|t| LocalTime::from_millis(t as u128));
             let banned = row.read::<i64, _>("banned").is_positive();
+            let label = match &row["label"] {
+                sql::Value::Null => None,
+                value => Some(Label::try_from(value)?),
+            };
             let penalty = row.read::<i64, _>("penalty");
             let penalty = Penalty(penalty as u8); // Clamped at `u8::MAX`.
 
@@ -250,6 +265,7 @@ impl Store for Database {
                     last_success,
                     last_attempt,
                     banned,
+                    label,
                 },
             });
         }
@@ -306,6 +322,10 @@ impl Store for Database {
         _addr: &Address,
         severity: Severity,
     ) -> Result<(), Error> {
+        self.penalize(nid, severity)
+    }
+
+    fn penalize(&mut self, nid: &NodeId, severity: Severity) -> Result<(), Error> {
         let mut stmt = self.db.prepare(
             "UPDATE `nodes`
              SET penalty = penalty + ?2
@@ -318,6 +338,19 @@ impl Store for Database {
 
         Ok(())
     }
+
+    fn ban(&mut self, nid: &NodeId) -> Result<(), Error> {
+        let mut stmt = self.db.prepare(
+            "UPDATE `addresses`
+             SET banned = 1
+             WHERE node = ?1",
+        )?;
+
+        stmt.bind((1, nid))?;
+        stmt.next()?;
+
+        Ok(())
+    }
 }
 
 impl<T> AliasStore for T
@@ -363,6 +396,36 @@ impl sql::BindableWithIndex for Source {
     }
 }
 
+impl TryFrom<&sql::Value> for Label {
+    type Error = sql::Error;
+
+    fn try_from(value: &sql::Value) -> Result<Self, Self::Error> {
+        let err = sql::Error {
+            code: None,
+            message: Some("sql: invalid address label".to_owned()),
+        };
+        match value {
+            sql::Value::String(s) => match s.as_str() {
+                "manual" => Ok(Label::Manual),
+                "upnp" => Ok(Label::Upnp),
+                "observed" => Ok(Label::Observed),
+                _ => Err(err),
+            },
+            _ => Err(err),
+        }
+    }
+}
+
+impl sql::BindableWithIndex for Label {
+    fn bind<I: sql::ParameterIndex>(self, stmt: &mut sql::Statement<'_>, i: I) -> sql::Result<()> {
+        match self {
+            Self::Manual => "manual".bind(stmt, i),
+            Self::Upnp => "upnp".bind(stmt, i),
+            Self::Observed => "observed".bind(stmt, i),
+        }
+    }
+}
+
 impl TryFrom<&sql::Value> for AddressType {
     type Error = sql::Error;
 
@@ -463,6 +526,7 @@ mod test {
             last_success: None,
             last_attempt: None,
             banned: false,
+            label: None,
         };
         let inserted = cache
             .insert(
@@ -499,6 +563,7 @@ mod test {
             last_success: None,
             last_attempt: None,
             banned: false,
+            label: None,
         };
         let inserted = cache
             .insert(&alice, features, alias.clone(), 0, timestamp, [ka.clone()])
@@ -527,6 +592,7 @@ mod test {
             last_success: None,
             last_attempt: None,
             banned: false,
+            label: None,
         };
 
         let updated = cache
@@ -588,6 +654,7 @@ mod test {
                 last_success: None,
                 last_attempt: None,
                 banned: false,
+                label: None,
             };
             cache
                 .insert(
@@ -634,6 +701,7 @@ mod test {
                 last_success: None,
                 last_attempt: None,
                 banned: false,
+                label: Some(Label::Observed),
             };
             expected.push(AddressEntry {
                 node: id,
@@ -683,5 +751,98 @@ mod test {
         cache.connected(&alice, &addr, timestamp + 1).unwrap();
         let node = cache.get(&alice).unwrap().unwrap();
         assert_eq!(node.penalty, Penalty(4));
+
+        cache.penalize(&alice, Severity::High).unwrap();
+        let node = cache.get(&alice).unwrap().unwrap();
+        assert_eq!(node.penalty, Penalty(12));
+    }
+
+    #[test]
+    fn test_ban() {
+        let alice = arbitrary::gen::<NodeId>(1);
+        let mut cache = Database::memory().unwrap();
+        let timestamp = Timestamp::from(LocalTime::now());
+        let ka = KnownAddress {
+            addr: net::SocketAddr::from(([4, 4, 4, 4], 8776)).into(),
+            source: Source::Peer,
+            last_success: None,
+            last_attempt: None,
+            banned: false,
+            label: None,
+        };
+
+        cache
+            .insert(
+                &alice,
+                node::Features::SEED,
+                Alias::new("alice"),
+                16,
+                timestamp,
+                [ka.clone()],
+            )
+            .unwrap();
+        assert!(cache.entries().unwrap().all(|e| !e.address.banned));
+
+        cache.ban(&alice).unwrap();
+        assert!(cache.entries().unwrap().all(|e| e.address.banned));
+
+        // Re-inserting the node doesn't lift the ban.
+        cache
+            .insert(
+                &alice,
+                node::Features::SEED,
+                Alias::new("alice"),
+                16,
+                timestamp + 1,
+                [ka],
+            )
+            .unwrap();
+        assert!(cache.entries().unwrap().all(|e| e.address.banned));
+    }
+
+    #[test]
+    fn test_label() {
+        let alice = arbitrary::gen::<NodeId>(1);
+        let mut cache = Database::memory().unwrap();
+        let timestamp = Timestamp::from(LocalTime::now());
+        let ka = KnownAddress::new(
+            net::SocketAddr::from(([4, 4, 4, 4], 8776)).into(),
+            Source::Peer,
+        );
+
+        for (timestamp, label) in [
+            (timestamp, Some(Label::Observed)),
+            // The label of the most recent announcement wins.
+            (timestamp + 1, Some(Label::Manual)),
+            (timestamp, Some(Label::Upnp)),
+            (timestamp + 2, None),
+        ] {
+            cache
+                .insert(
+                    &alice,
+                    node::Features::SEED,
+                    Alias::new("alice"),
+                    16,
+                    timestamp,
+                    [ka.clone().labeled(label)],
+                )
+                .unwrap();
+        }
+        let addrs = cache.addresses_of(&alice).unwrap();
+        assert_eq!(addrs, vec![ka.clone()]);
+        assert_eq!(addrs[0].preference(), Label::Manual);
+
+        cache
+            .insert(
+                &alice,
+                node::Features::SEED,
+                Alias::new("alice"),
+                16,
+                timestamp + 3,
+                [ka.clone().labeled(Some(Label::Upnp))],
+            )
+            .unwrap();
+        let entry = cache.entries().unwrap().next().unwrap();
+        assert_eq!(entry.address.label, Some(Label::Upnp));
     }
 }
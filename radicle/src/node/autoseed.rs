@@ -0,0 +1,225 @@
+//! Repositories seeded automatically.
+//!
+//! When enabled, the node seeds repositories that followed nodes are active in, as seen in
+//! refs announcements, up to a budget. Once cloned, repositories that aren't delegated to
+//! by any followed node are rejected and no longer seeded.
+use std::num::TryFromIntError;
+
+use sqlite as sql;
+use thiserror::Error;
+
+use crate::node::Database;
+use crate::prelude::{NodeId, RepoId, Timestamp};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    /// An Internal error.
+    #[error("internal error: {0}")]
+    Internal(#[from] sql::Error),
+    /// Unit overflow.
+    #[error("unit overflow: {0}")]
+    UnitOverflow(#[from] TryFromIntError),
+}
+
+/// A repository seeded automatically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    /// Followed node whose activity the repository was discovered through.
+    pub nid: NodeId,
+    /// Size of the repository in storage, in bytes. Not known until the repository
+    /// is cloned.
+    pub size: Option<u64>,
+    /// Whether the repository was rejected.
+    pub rejected: bool,
+    /// When the repository was seeded.
+    pub timestamp: Timestamp,
+}
+
+impl Entry {
+    /// Whether the repository is seeded, but wasn't cloned yet.
+    pub fn is_pending(&self) -> bool {
+        self.size.is_none() && !self.rejected
+    }
+}
+
+/// Resources used by the repositories seeded automatically, excluding rejected ones.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    /// Number of repositories.
+    pub repos: usize,
+    /// Total size of the cloned repositories, in bytes.
+    pub size: u64,
+}
+
+/// Auto-seeded repositories store.
+pub trait Store {
+    /// Get the entry of a repository, if it was ever seeded automatically.
+    fn auto_seeded(&self, rid: &RepoId) -> Result<Option<Entry>, Error>;
+    /// Record a repository as seeded automatically. Returns `false` if it was already known.
+    fn auto_seed(
+        &mut self,
+        rid: &RepoId,
+        nid: &NodeId,
+        timestamp: Timestamp,
+    ) -> Result<bool, Error>;
+    /// Record the size of an auto-seeded repository, once cloned.
+    fn set_auto_seed_size(&mut self, rid: &RepoId, size: u64) -> Result<bool, Error>;
+    /// Reject an auto-seeded repository, so that it isn't seeded automatically again.
+    fn reject_auto_seed(&mut self, rid: &RepoId) -> Result<bool, Error>;
+    /// Forget an auto-seeded repository, eg. because it was seeded explicitly.
+    fn forget_auto_seed(&mut self, rid: &RepoId) -> Result<bool, Error>;
+    /// Get the resources used by auto-seeded repositories.
+    fn auto_seed_usage(&self) -> Result<Usage, Error>;
+}
+
+impl Store for Database {
+    fn auto_seeded(&self, rid: &RepoId) -> Result<Option<Entry>, Error> {
+        let mut stmt = self
+            .db
+            .prepare("SELECT node, size, rejected, timestamp FROM `auto-seeds` WHERE repo = ?1")?;
+        stmt.bind((1, rid))?;
+
+        if let Some(Ok(row)) = stmt.into_iter().next() {
+            let nid = row.try_read::<NodeId, _>("node")?;
+            let size = row
+                .try_read::<Option<i64>, _>("size")?
+                .map(u64::try_from)
+                .transpose()?;
+            let rejected = row.try_read::<i64, _>("rejected")? != 0;
+            let timestamp = row.try_read::<Timestamp, _>("timestamp")?;
+
+            return Ok(Some(Entry {
+                nid,
+                size,
+                rejected,
+                timestamp,
+            }));
+        }
+        Ok(None)
+    }
+
+    fn auto_seed(
+        &mut self,
+        rid: &RepoId,
+        nid: &NodeId,
+        timestamp: Timestamp,
+    ) -> Result<bool, Error> {
+        let mut stmt = self.db.prepare(
+            "INSERT INTO `auto-seeds` (repo, node, timestamp)
+             VALUES (?1, ?2, ?3)
+             ON CONFLICT DO NOTHING",
+        )?;
+        stmt.bind((1, rid))?;
+        stmt.bind((2, nid))?;
+        stmt.bind((3, &timestamp))?;
+        stmt.next()?;
+
+        Ok(self.db.change_count() > 0)
+    }
+
+    fn set_auto_seed_size(&mut self, rid: &RepoId, size: u64) -> Result<bool, Error> {
+        let mut stmt = self
+            .db
+            .prepare("UPDATE `auto-seeds` SET size = ?2 WHERE repo = ?1")?;
+        stmt.bind((1, rid))?;
+        stmt.bind((2, i64::try_from(size)?))?;
+        stmt.next()?;
+
+        Ok(self.db.change_count() > 0)
+    }
+
+    fn reject_auto_seed(&mut self, rid: &RepoId) -> Result<bool, Error> {
+        let mut stmt = self.db.prepare(
+            "UPDATE `auto-seeds` SET rejected = 1, size = NULL
+             WHERE repo = ?1 AND rejected = 0",
+        )?;
+        stmt.bind((1, rid))?;
+        stmt.next()?;
+
+        Ok(self.db.change_count() > 0)
+    }
+
+    fn forget_auto_seed(&mut self, rid: &RepoId) -> Result<bool, Error> {
+        let mut stmt = self
+            .db
+            .prepare("DELETE FROM `auto-seeds` WHERE repo = ?1")?;
+        stmt.bind((1, rid))?;
+        stmt.next()?;
+
+        Ok(self.db.change_count() > 0)
+    }
+
+    fn auto_seed_usage(&self) -> Result<Usage, Error> {
+        let stmt = self.db.prepare(
+            "SELECT COUNT(*) AS repos, COALESCE(SUM(size), 0) AS size
+             FROM `auto-seeds` WHERE rejected = 0",
+        )?;
+
+        if let Some(Ok(row)) = stmt.into_iter().next() {
+            let repos = usize::try_from(row.try_read::<i64, _>("repos")?)?;
+            let size = u64::try_from(row.try_read::<i64, _>("size")?)?;
+
+            return Ok(Usage { repos, size });
+        }
+        Ok(Usage::default())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+    use crate::test::arbitrary;
+
+    #[test]
+    fn test_auto_seeds() {
+        let mut db = Database::memory().unwrap();
+        let alice = arbitrary::gen::<NodeId>(1);
+        let first = arbitrary::gen::<RepoId>(1);
+        let second = arbitrary::gen::<RepoId>(1);
+        let now = Timestamp::from(1_000);
+
+        assert_eq!(db.auto_seeded(&first).unwrap(), None);
+        assert_eq!(db.auto_seed_usage().unwrap(), Usage::default());
+
+        assert!(db.auto_seed(&first, &alice, now).unwrap());
+        assert!(!db.auto_seed(&first, &alice, now).unwrap());
+        assert!(db.auto_seed(&second, &alice, now).unwrap());
+        assert!(db.auto_seeded(&first).unwrap().unwrap().is_pending());
+        assert_eq!(db.auto_seed_usage().unwrap(), Usage { repos: 2, size: 0 });
+
+        assert!(db.set_auto_seed_size(&first, 1024).unwrap());
+        assert!(db.set_auto_seed_size(&second, 512).unwrap());
+        assert_eq!(
+            db.auto_seeded(&first).unwrap(),
+            Some(Entry {
+                nid: alice,
+                size: Some(1024),
+                rejected: false,
+                timestamp: now,
+            })
+        );
+        assert_eq!(
+            db.auto_seed_usage().unwrap(),
+            Usage {
+                repos: 2,
+                size: 1536
+            }
+        );
+
+        assert!(db.reject_auto_seed(&second).unwrap());
+        assert!(!db.reject_auto_seed(&second).unwrap());
+        assert!(!db.auto_seeded(&second).unwrap().unwrap().is_pending());
+        assert_eq!(
+            db.auto_seed_usage().unwrap(),
+            Usage {
+                repos: 1,
+                size: 1024
+            }
+        );
+
+        assert!(db.forget_auto_seed(&first).unwrap());
+        assert_eq!(db.auto_seeded(&first).unwrap(), None);
+        assert_eq!(db.auto_seed_usage().unwrap(), Usage::default());
+    }
+}
@@ -1,10 +1,13 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::net;
 use std::ops::Deref;
+use std::path::PathBuf;
 
 use cyphernet::addr::PeerAddr;
 use localtime::LocalDuration;
+use serde_json as json;
 
+use crate::identity::RepoId;
 use crate::node;
 use crate::node::policy::{Policy, Scope};
 use crate::node::{Address, Alias, NodeId};
@@ -13,6 +16,14 @@ use crate::node::{Address, Alias, NodeId};
 pub const TARGET_OUTBOUND_PEERS: usize = 8;
 /// Default number of workers to spawn.
 pub const DEFAULT_WORKERS: usize = 8;
+/// Default number of seeds we want each seeded repository to be available from.
+pub const DEFAULT_REPLICATION_FACTOR: usize = 3;
+/// Default maximum number of concurrent streams per peer.
+pub const DEFAULT_STREAM_CONCURRENCY: usize = 2;
+/// Default maximum number of hops announcements are relayed over.
+pub const DEFAULT_RELAY_HOPS: u8 = 8;
+/// Default time without progress after which a component is considered stalled.
+pub const DEFAULT_WATCHDOG_TIMEOUT: LocalDuration = LocalDuration::from_mins(5);
 
 /// Configured public seeds.
 pub mod seeds {
@@ -104,6 +115,15 @@ pub struct Limits {
     pub gossip_max_age: LocalDuration,
     /// Maximum number of concurrent fetches per peer connection.
     pub fetch_concurrency: usize,
+    /// Maximum number of concurrent streams per peer, in either direction. Streams opened
+    /// by the peer beyond this are refused, and ours are queued until one is closed.
+    #[serde(default = "defaults::stream_concurrency")]
+    pub stream_concurrency: usize,
+    /// Maximum number of hops announcements are relayed over. Announcements received
+    /// after this many hops are processed, but not relayed further, which bounds how far
+    /// they propagate in densely connected networks.
+    #[serde(default = "defaults::relay_hops")]
+    pub relay_hops: u8,
     /// Maximum number of open files.
     pub max_open_files: usize,
     /// Rate limitter settings.
@@ -112,6 +132,26 @@ pub struct Limits {
     /// Connection limits.
     #[serde(default)]
     pub connection: ConnectionLimits,
+    /// Limits of fetches served to other nodes, and of their log.
+    #[serde(default)]
+    pub uploads: UploadLimits,
+    /// Maximum number of threads used to index and verify a fetched packfile. Each worker
+    /// uses up to this many threads while indexing. Uses all cores if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pack_threads: Option<usize>,
+    /// Niceness of worker threads, from `-20` (highest priority) to `19` (lowest). Raising
+    /// it keeps fetches from starving other processes on shared machines. Only supported
+    /// on Linux. Inherited from the node process if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worker_niceness: Option<i32>,
+    /// Maximum number of references a single remote namespace may sign. Namespaces with
+    /// more references are not fetched, and their owner is penalized. Unlimited if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_namespace_refs: Option<usize>,
+    /// Maximum number of references written per transaction when applying a fetch. The
+    /// references of a single namespace are always written together. Defaults to `1024`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ref_update_batch_size: Option<usize>,
 }
 
 impl Default for Limits {
@@ -121,9 +161,16 @@ impl Default for Limits {
             routing_max_age: LocalDuration::from_mins(7 * 24 * 60), // One week
             gossip_max_age: LocalDuration::from_mins(2 * 7 * 24 * 60), // Two weeks
             fetch_concurrency: 1,
+            stream_concurrency: defaults::stream_concurrency(),
+            relay_hops: defaults::relay_hops(),
             max_open_files: 4096,
             rate: RateLimits::default(),
             connection: ConnectionLimits::default(),
+            uploads: UploadLimits::default(),
+            pack_threads: None,
+            worker_niceness: None,
+            max_namespace_refs: None,
+            ref_update_batch_size: None,
         }
     }
 }
@@ -137,6 +184,23 @@ pub struct ConnectionLimits {
     /// Max outbound connections. Note that this is higher than the *target* number
     /// in [`TARGET_OUTBOUND_PEERS`].
     pub outbound: usize,
+    /// How long a peer has to complete the transport handshake before it is disconnected.
+    #[serde(
+        default = "defaults::handshake_timeout",
+        with = "crate::serde_ext::localtime::duration"
+    )]
+    pub handshake_timeout: LocalDuration,
+    /// How long a connected peer has to subscribe to our gossip messages before it is
+    /// disconnected.
+    #[serde(
+        default = "defaults::negotiation_timeout",
+        with = "crate::serde_ext::localtime::duration"
+    )]
+    pub negotiation_timeout: LocalDuration,
+    /// Max connections served at once by the `git://` daemon and the HTTP gateway,
+    /// together. Connections beyond this are rejected.
+    #[serde(default = "defaults::git_connections")]
+    pub git: usize,
 }
 
 impl Default for ConnectionLimits {
@@ -144,6 +208,45 @@ impl Default for ConnectionLimits {
         Self {
             inbound: 128,
             outbound: 16,
+            handshake_timeout: defaults::handshake_timeout(),
+            negotiation_timeout: defaults::negotiation_timeout(),
+            git: defaults::git_connections(),
+        }
+    }
+}
+
+/// Limits of uploads, ie. fetches served to other nodes, and of the upload log.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadLimits {
+    /// Number of upload log entries before we start pruning.
+    pub max_size: usize,
+    /// How long to keep an upload log entry before pruning it.
+    #[serde(with = "crate::serde_ext::localtime::duration")]
+    pub max_age: LocalDuration,
+    /// Maximum virtual memory of a `git upload-pack` process, in bytes. Nb. packfiles are
+    /// memory-mapped, so this should be well above the size of the largest repository.
+    /// Unlimited if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_memory: Option<u64>,
+    /// Maximum CPU time of a `git upload-pack` process, in seconds. The process is killed
+    /// once it is reached. Unlimited if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_cpu_time: Option<u64>,
+    /// Maximum number of bytes sent by a `git upload-pack` process. The upload is aborted
+    /// once it is reached. Unlimited if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output: Option<u64>,
+}
+
+impl Default for UploadLimits {
+    fn default() -> Self {
+        Self {
+            max_size: 10_000,
+            max_age: LocalDuration::from_mins(30 * 24 * 60), // One month
+            max_memory: None,
+            max_cpu_time: None,
+            max_output: None,
         }
     }
 }
@@ -156,6 +259,17 @@ pub struct RateLimit {
     pub capacity: usize,
 }
 
+/// Limits of the data relayed when acting as a bridge, in bytes, with the fill rate
+/// given in bytes per second.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BridgeLimits {
+    /// Limit of the data relayed on behalf of each peer.
+    pub peer: RateLimit,
+    /// Limit of the data relayed on behalf of all peers together.
+    pub total: RateLimit,
+}
+
 /// Rate limits for inbound and outbound connections.
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -234,6 +348,212 @@ impl Default for PeerConfig {
     }
 }
 
+/// Heartbeat announcement settings.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Heartbeat {
+    /// How often to announce our health. Heartbeats are never sent more often than
+    /// every ten minutes.
+    #[serde(
+        default = "defaults::heartbeat_interval",
+        with = "crate::serde_ext::localtime::duration"
+    )]
+    pub interval: LocalDuration,
+    /// Size of the largest repository we accept, in bytes. No limit if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_repo_size: Option<u64>,
+}
+
+/// Automatic seeding of repositories that followed nodes are active in, as delegates.
+/// See [`node::autoseed`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutoSeed {
+    /// Maximum number of repositories seeded automatically.
+    #[serde(default = "defaults::auto_seed_max_repos")]
+    pub max_repos: usize,
+    /// Maximum total size of the repositories seeded automatically, in bytes.
+    #[serde(default = "defaults::auto_seed_max_size")]
+    pub max_size: u64,
+}
+
+/// Watchdog settings.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Watchdog {
+    /// How long the service or the worker pool may go without making progress before
+    /// it's considered stalled.
+    #[serde(
+        default = "defaults::watchdog_timeout",
+        with = "crate::serde_ext::localtime::duration"
+    )]
+    pub timeout: LocalDuration,
+    /// Exit when a stall is detected, so that the node can be restarted by its
+    /// supervisor, eg. systemd. Otherwise, stalls are only logged.
+    #[serde(default, skip_serializing_if = "crate::serde_ext::is_default")]
+    pub exit: bool,
+}
+
+/// Export of a repository's canonical default branch to an external Git remote.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Mirror {
+    /// Repository to export.
+    pub rid: RepoId,
+    /// URL of the Git remote to push to, eg. `git@github.com:cloudhead/radicle.git`.
+    /// Authentication is left to Git, eg. via SSH keys or credential helpers.
+    pub url: String,
+    /// Name of the branch to push to on the remote. Defaults to the repository's
+    /// default branch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+}
+
+/// Command run after a fetch updates a repository, eg. to trigger a CI build.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Hook {
+    /// Repository the hook runs for.
+    pub rid: RepoId,
+    /// Program to run. It receives the result of the fetch as JSON on its standard input.
+    pub command: PathBuf,
+    /// Arguments passed to the program.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// How long the program may run before it's killed.
+    #[serde(
+        default = "defaults::hook_timeout",
+        with = "crate::serde_ext::localtime::duration"
+    )]
+    pub timeout: LocalDuration,
+}
+
+/// Handling of identity updates requiring confirmation. See [`node::confirmations`].
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Confirmations {
+    /// Policy of repositories without one of their own.
+    #[serde(default, skip_serializing_if = "crate::serde_ext::is_default")]
+    pub default: node::confirmations::Policy,
+    /// Per-repository policies.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub repos: Vec<Confirmation>,
+}
+
+impl Confirmations {
+    /// Get the policy of a repository.
+    pub fn policy(&self, rid: &RepoId) -> node::confirmations::Policy {
+        self.repos
+            .iter()
+            .find(|c| &c.rid == rid)
+            .map_or(self.default, |c| c.policy)
+    }
+}
+
+/// Policy for the identity updates of a repository requiring confirmation.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Confirmation {
+    /// Repository the policy applies to.
+    pub rid: RepoId,
+    /// Policy applied.
+    pub policy: node::confirmations::Policy,
+}
+
+/// Endpoint that node events are posted to, as JSON.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Webhook {
+    /// URL to post events to, eg. `https://ci.example.com/radicle`. Must use `https`, unless
+    /// the endpoint is on the local host.
+    pub url: String,
+    /// Secret used to sign requests with HMAC-SHA256. The signature of the request body is
+    /// sent in the `X-Radicle-Signature` header, eg. `sha256=<hex>`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// Types of events to post, eg. `refsFetched` or `sigrefsDiverged`. All events are
+    /// posted if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<String>,
+}
+
+impl Webhook {
+    /// Check whether events of the given type are posted to this endpoint.
+    pub fn accepts(&self, event: &str) -> bool {
+        self.events.is_empty() || self.events.iter().any(|e| e == event)
+    }
+
+    /// Check whether requests to this endpoint are encrypted, or stay on the local host.
+    pub fn is_secure(&self) -> bool {
+        let url = self.url.to_ascii_lowercase();
+        if url.starts_with("https://") {
+            return true;
+        }
+        let Some(rest) = url.strip_prefix("http://") else {
+            return false;
+        };
+        let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+        let host = match authority.strip_prefix('[') {
+            Some(v6) => v6.split(']').next().unwrap_or_default(),
+            None => authority.split(':').next().unwrap_or_default(),
+        };
+        host == "localhost"
+            || host
+                .parse::<std::net::IpAddr>()
+                .is_ok_and(|ip| ip.is_loopback())
+    }
+}
+
+/// A reference name pattern, eg. `refs/namespaces/*/refs/heads/tmp/*`.
+///
+/// Unlike Git refspec patterns, any number of `*` may be used, and each one matches any
+/// sequence of characters, including `/`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct RefPattern(String);
+
+impl RefPattern {
+    /// Check whether the given reference name matches this pattern.
+    pub fn matches(&self, refname: &str) -> bool {
+        let mut parts = self.0.split('*');
+        let first = parts.next().unwrap_or_default();
+        let Some(mut rest) = refname.strip_prefix(first) else {
+            return false;
+        };
+        let mut parts = parts.collect::<Vec<_>>();
+        let Some(last) = parts.pop() else {
+            // There was no `*` in the pattern.
+            return rest.is_empty();
+        };
+        for part in parts {
+            match rest.find(part) {
+                Some(i) => rest = &rest[i + part.len()..],
+                None => return false,
+            }
+        }
+        rest.ends_with(last)
+    }
+}
+
+impl TryFrom<String> for RefPattern {
+    type Error = String;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        if !pattern.starts_with("refs/") {
+            return Err(format!(
+                "invalid ref pattern '{pattern}': must start with 'refs/'"
+            ));
+        }
+        Ok(Self(pattern))
+    }
+}
+
+impl From<RefPattern> for String {
+    fn from(pattern: RefPattern) -> Self {
+        pattern.0
+    }
+}
+
 /// Service configuration.
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -271,6 +591,88 @@ pub struct Config {
     /// Default seeding scope.
     #[serde(default)]
     pub scope: Scope,
+    /// Number of other seeds we'd like each seeded repository to be available from.
+    /// When a repository has fewer reachable seeds than this, we connect to and fetch from
+    /// all the seeds we know of.
+    #[serde(default = "defaults::replication_factor")]
+    pub replication_factor: usize,
+    /// Address to serve public repositories on, read-only, over the `git://` protocol.
+    /// For example, `127.0.0.1:9418`. Disabled if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_daemon: Option<net::SocketAddr>,
+    /// Address to serve public repositories on, read-only, over Git's "smart" HTTP protocol.
+    /// For example, `127.0.0.1:8080`. HTTPS is not supported directly, and requires a
+    /// reverse proxy. Disabled if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_http: Option<net::SocketAddr>,
+    /// Names by which repositories can be cloned from the `git://` daemon and HTTP gateway,
+    /// in place of their identifiers, eg. `git clone git://127.0.0.1:9418/heartwood`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub repo_aliases: BTreeMap<String, RepoId>,
+    /// Act as a bridge, forwarding opaque streams between connected peers that can't
+    /// reach each other directly, eg. because they are both behind NAT. The limits
+    /// apply to the bytes relayed on behalf of each peer, and of all peers together.
+    /// Disabled if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bridge: Option<BridgeLimits>,
+    /// Serve and fetch large files referenced by repositories, over a separate chunked
+    /// transfer instead of inside Git packs. See [`crate::storage::blobs`].
+    #[serde(default, skip_serializing_if = "crate::serde_ext::is_default")]
+    pub blobs: bool,
+    /// Periodically announce the health of our node to peers, so that they can pick
+    /// healthy seeds to clone from. Disabled if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub heartbeat: Option<Heartbeat>,
+    /// Automatically seed repositories that followed nodes are active in, as delegates,
+    /// when their refs are announced. Disabled if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auto_seed: Option<AutoSeed>,
+    /// Features peers must advertise to stay connected, eg. `256` for
+    /// [`node::Features::HTTP_GATEWAY`]. Peers are checked once their features are known.
+    /// Configured peers are exempt.
+    #[serde(default, skip_serializing_if = "crate::serde_ext::is_default")]
+    pub required_features: node::Features,
+    /// Accept announced addresses that aren't globally routable, eg. private network
+    /// addresses, from any peer. By default, they are only accepted from peers on the
+    /// local network.
+    #[serde(default, skip_serializing_if = "crate::serde_ext::is_default")]
+    pub local_addresses: bool,
+    /// Refuse the updates of remotes whose fetched `rad/sigrefs` were rewound or rewritten
+    /// since we last fetched them. Such remotes are always reported, regardless.
+    #[serde(default, skip_serializing_if = "crate::serde_ext::is_default")]
+    pub refuse_diverged_sigrefs: bool,
+    /// References that are never fetched, eg. `refs/namespaces/*/refs/heads/tmp/*`.
+    /// Patterns are matched against the full reference name, including the namespace.
+    /// N.b. denied references are still listed in their remote's signed refs, and are
+    /// skipped by nodes fetching that remote from us. The delegates' default branches are
+    /// never denied.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny_refs: Vec<RefPattern>,
+    /// How fetched identity updates that change the delegates, threshold or visibility
+    /// of a repository are decided on. By default, they are accepted if a quorum of the
+    /// current delegates accepted them.
+    #[serde(default, skip_serializing_if = "crate::serde_ext::is_default")]
+    pub confirmations: Confirmations,
+    /// Log level. Overridden by the `--log` command-line option. Defaults to `info`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log: Option<log::Level>,
+    /// Watch the service and the worker pool for stalls. Disabled if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watchdog: Option<Watchdog>,
+    /// Repositories exported to external Git remotes after their canonical default
+    /// branch is updated by a fetch.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<Mirror>,
+    /// Commands run after a fetch updates a repository.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hooks: Vec<Hook>,
+    /// Endpoints that node events are posted to.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub webhooks: Vec<Webhook>,
+    /// Record inbound protocol sessions to the given file, so that they can be replayed.
+    /// Overridden by the `--record` command-line option. Disabled if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub record: Option<PathBuf>,
 }
 
 impl Config {
@@ -294,6 +696,25 @@ impl Config {
             workers: DEFAULT_WORKERS,
             policy: Policy::default(),
             scope: Scope::default(),
+            replication_factor: DEFAULT_REPLICATION_FACTOR,
+            git_daemon: None,
+            git_http: None,
+            repo_aliases: BTreeMap::new(),
+            bridge: None,
+            blobs: false,
+            heartbeat: None,
+            auto_seed: None,
+            required_features: node::Features::NONE,
+            local_addresses: false,
+            refuse_diverged_sigrefs: false,
+            deny_refs: Vec::new(),
+            confirmations: Confirmations::default(),
+            log: None,
+            watchdog: None,
+            mirrors: Vec::new(),
+            hooks: Vec::new(),
+            webhooks: Vec::new(),
+            record: None,
         }
     }
 
@@ -309,14 +730,335 @@ impl Config {
     }
 
     pub fn features(&self) -> node::Features {
-        node::Features::SEED
+        let mut features = node::Features::SEED
+            | node::Features::HEARTBEAT
+            | node::Features::SEQUENCE
+            | node::Features::INVENTORY_DIFF
+            | node::Features::COMPRESSION
+            | node::Features::INVENTORY_PAGES
+            | node::Features::REF_QUERY
+            | node::Features::ADDRESS_LABELS
+            | node::Features::RELAY_HOPS;
+
+        if self.bridge.is_some() {
+            features |= node::Features::BRIDGE;
+        }
+        if self.blobs {
+            features |= node::Features::BLOBS;
+        }
+        if self.relay {
+            features |= node::Features::RELAY;
+        }
+        if self.git_http.is_some() {
+            features |= node::Features::HTTP_GATEWAY;
+        }
+        features
+    }
+
+    /// Get the settings that differ between this configuration and the given one, as dotted
+    /// paths, eg. `limits.connection.inbound`.
+    pub fn diff(&self, other: &Config) -> ConfigDiff {
+        let mut diff = ConfigDiff::default();
+        let (Ok(old), Ok(new)) = (json::to_value(self), json::to_value(other)) else {
+            return diff;
+        };
+        let mut changed = Vec::new();
+        json_diff(String::new(), &old, &new, &mut changed);
+
+        for path in changed {
+            if Self::live(&path).is_some() {
+                diff.live.push(path);
+            } else {
+                diff.restart.push(path);
+            }
+        }
+        diff
+    }
+
+    /// Apply the settings of the given configuration that can be changed while the node is
+    /// running, leaving the others as they are. Returns all the settings that changed.
+    pub fn reload(&mut self, other: &Config) -> ConfigDiff {
+        let diff = self.diff(other);
+        if diff.live.is_empty() {
+            return diff;
+        }
+        let (Ok(mut old), Ok(new)) = (json::to_value(&*self), json::to_value(other)) else {
+            return diff;
+        };
+        let prefixes = diff
+            .live
+            .iter()
+            .filter_map(|p| Self::live(p))
+            .collect::<BTreeSet<_>>();
+
+        for prefix in prefixes {
+            json_set(&mut old, prefix, json_get(&new, prefix).cloned());
+        }
+        match json::from_value(old) {
+            Ok(config) => *self = config,
+            Err(e) => log::error!(target: "node", "Failed to reload configuration: {e}"),
+        }
+        diff
+    }
+
+    /// Get the live setting the given path belongs to, if any.
+    fn live(path: &str) -> Option<&'static str> {
+        Self::LIVE.iter().copied().find(|prefix| {
+            path == *prefix
+                || path
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| rest.starts_with('.'))
+        })
+    }
+
+    /// Settings that can be changed while the node is running. Other settings, eg. the
+    /// listen addresses or the settings of the worker pool, only take effect on restart.
+    const LIVE: &'static [&'static str] = &[
+        "connect",
+        "peers",
+        "relay",
+        "policy",
+        "scope",
+        "replicationFactor",
+        "localAddresses",
+        "log",
+        "limits.routingMaxSize",
+        "limits.routingMaxAge",
+        "limits.gossipMaxAge",
+        "limits.fetchConcurrency",
+        "limits.relayHops",
+        "limits.rate",
+        "limits.connection.inbound",
+        "limits.connection.outbound",
+        "limits.connection.negotiationTimeout",
+        "limits.uploads",
+    ];
+}
+
+/// Settings that differ between two configurations.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigDiff {
+    /// Settings that can be applied while the node is running.
+    pub live: Vec<String>,
+    /// Settings that only take effect once the node is restarted.
+    pub restart: Vec<String>,
+}
+
+impl ConfigDiff {
+    /// Whether nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.live.is_empty() && self.restart.is_empty()
     }
 }
 
+/// Collect the paths of the values that differ between two JSON values.
+/// Objects are compared key by key, other values as a whole.
+fn json_diff(path: String, old: &json::Value, new: &json::Value, changed: &mut Vec<String>) {
+    let (json::Value::Object(old), json::Value::Object(new)) = (old, new) else {
+        if old != new {
+            changed.push(path);
+        }
+        return;
+    };
+    let keys = old
+        .keys()
+        .chain(new.keys().filter(|k| !old.contains_key(*k)));
+
+    for key in keys {
+        let path = if path.is_empty() {
+            key.to_owned()
+        } else {
+            format!("{path}.{key}")
+        };
+        match (old.get(key), new.get(key)) {
+            (Some(o), Some(n)) => json_diff(path, o, n, changed),
+            _ => changed.push(path),
+        }
+    }
+}
+
+/// Get the value at the given dotted path.
+fn json_get<'a>(value: &'a json::Value, path: &str) -> Option<&'a json::Value> {
+    path.split('.').try_fold(value, |v, key| v.get(key))
+}
+
+/// Set or remove the value at the given dotted path. Does nothing if its parent is missing.
+fn json_set(value: &mut json::Value, path: &str, new: Option<json::Value>) {
+    let (parent, key) = match path.rsplit_once('.') {
+        Some((parent, key)) => (
+            parent.split('.').try_fold(value, |v, key| v.get_mut(key)),
+            key,
+        ),
+        None => (Some(value), path),
+    };
+    let Some(json::Value::Object(parent)) = parent else {
+        return;
+    };
+    match new {
+        Some(new) => parent.insert(key.to_owned(), new),
+        None => parent.remove(key),
+    };
+}
+
 /// Defaults as functions, for serde.
 mod defaults {
+    use localtime::LocalDuration;
+
     /// Worker count.
     pub fn workers() -> usize {
         super::DEFAULT_WORKERS
     }
+
+    /// Replication factor.
+    pub fn replication_factor() -> usize {
+        super::DEFAULT_REPLICATION_FACTOR
+    }
+
+    /// Maximum number of concurrent streams per peer.
+    pub fn stream_concurrency() -> usize {
+        super::DEFAULT_STREAM_CONCURRENCY
+    }
+
+    /// Maximum number of hops announcements are relayed over.
+    pub fn relay_hops() -> u8 {
+        super::DEFAULT_RELAY_HOPS
+    }
+
+    /// Handshake timeout.
+    pub fn handshake_timeout() -> LocalDuration {
+        LocalDuration::from_secs(30)
+    }
+
+    /// Negotiation timeout.
+    pub fn negotiation_timeout() -> LocalDuration {
+        LocalDuration::from_mins(1)
+    }
+
+    /// Max connections served by the `git://` daemon and the HTTP gateway.
+    pub fn git_connections() -> usize {
+        32
+    }
+
+    /// Heartbeat interval.
+    pub fn heartbeat_interval() -> LocalDuration {
+        LocalDuration::from_mins(60)
+    }
+
+    /// Maximum number of auto-seeded repositories.
+    pub fn auto_seed_max_repos() -> usize {
+        64
+    }
+
+    /// Maximum total size of auto-seeded repositories: 1 GiB.
+    pub fn auto_seed_max_size() -> u64 {
+        1024 * 1024 * 1024
+    }
+
+    /// Watchdog timeout.
+    pub fn watchdog_timeout() -> LocalDuration {
+        super::DEFAULT_WATCHDOG_TIMEOUT
+    }
+
+    /// Hook timeout.
+    pub fn hook_timeout() -> LocalDuration {
+        LocalDuration::from_secs(30)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_reload() {
+        let mut config = Config::new(Alias::new("alice"));
+        let mut new = config.clone();
+
+        assert!(config.diff(&new).is_empty());
+
+        new.limits.connection.inbound = 42;
+        new.limits.connection.handshake_timeout = LocalDuration::from_secs(9);
+        new.limits.max_open_files = 1;
+        new.policy = Policy::Allow;
+        new.log = Some(log::Level::Debug);
+        new.listen = vec![net::SocketAddr::from(([0, 0, 0, 0], 8776))];
+
+        let diff = config.reload(&new);
+        assert_eq!(
+            diff.live,
+            vec!["limits.connection.inbound", "policy", "log",]
+        );
+        assert_eq!(
+            diff.restart,
+            vec![
+                "listen",
+                "limits.maxOpenFiles",
+                "limits.connection.handshakeTimeout",
+            ]
+        );
+
+        // Live settings were applied, the others were not.
+        assert_eq!(config.limits.connection.inbound, 42);
+        assert_eq!(config.policy, Policy::Allow);
+        assert_eq!(config.log, Some(log::Level::Debug));
+        assert!(config.listen.is_empty());
+        assert_eq!(
+            config.limits.max_open_files,
+            Limits::default().max_open_files
+        );
+        assert_eq!(
+            config.limits.connection.handshake_timeout,
+            defaults::handshake_timeout()
+        );
+
+        // Only the settings that need a restart remain.
+        let diff = config.diff(&new);
+        assert!(diff.live.is_empty());
+        assert_eq!(diff.restart.len(), 3);
+
+        // Optional settings can be removed.
+        new.log = None;
+        assert_eq!(config.reload(&new).live, vec!["log"]);
+        assert_eq!(config.log, None);
+    }
+
+    #[test]
+    fn test_ref_pattern() {
+        let pattern =
+            RefPattern::try_from(String::from("refs/namespaces/*/refs/heads/tmp/*")).unwrap();
+        assert!(pattern.matches("refs/namespaces/z6Mk/refs/heads/tmp/a"));
+        assert!(pattern.matches("refs/namespaces/z6Mk/refs/heads/tmp/a/b"));
+        assert!(!pattern.matches("refs/namespaces/z6Mk/refs/heads/master"));
+        assert!(!pattern.matches("refs/namespaces/z6Mk/refs/heads/tmp"));
+
+        let pattern = RefPattern::try_from(String::from("refs/heads/master")).unwrap();
+        assert!(pattern.matches("refs/heads/master"));
+        assert!(!pattern.matches("refs/heads/master2"));
+
+        let pattern = RefPattern::try_from(String::from("refs/*/ci")).unwrap();
+        assert!(pattern.matches("refs/heads/ci"));
+        assert!(!pattern.matches("refs/heads/ci/x"));
+
+        assert!(RefPattern::try_from(String::from("heads/*")).is_err());
+    }
+
+    #[test]
+    fn test_webhook_is_secure() {
+        let webhook = |url: &str| Webhook {
+            url: url.to_owned(),
+            secret: None,
+            events: vec![],
+        };
+        assert!(webhook("https://ci.example.com/radicle").is_secure());
+        assert!(webhook("HTTPS://ci.example.com").is_secure());
+        assert!(webhook("http://localhost:8080/hook").is_secure());
+        assert!(webhook("http://127.0.0.1:8080").is_secure());
+        assert!(webhook("http://[::1]:8080/hook").is_secure());
+        assert!(!webhook("http://ci.example.com/radicle").is_secure());
+        assert!(!webhook("http://localhost.example.com/hook").is_secure());
+        assert!(!webhook("http://192.168.1.2/hook").is_secure());
+        assert!(!webhook("ftp://localhost/hook").is_secure());
+    }
 }
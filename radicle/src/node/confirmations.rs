@@ -0,0 +1,264 @@
+//! Identity updates requiring confirmation.
+//!
+//! Fetched identity updates that change who controls a repository, ie. its delegates,
+//! threshold or visibility, aren't necessarily adopted as the canonical identity. Instead,
+//! the node decides whether to adopt them according to the repository's [`Policy`], so that
+//! headless seeds keep replicating without an operator having to confirm every update.
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use sqlite as sql;
+use thiserror::Error;
+
+use crate::cob::identity::Revision;
+use crate::crypto::Verified;
+use crate::git::Oid;
+use crate::identity::Doc;
+use crate::node::Database;
+use crate::prelude::{RepoId, Timestamp};
+
+/// Milliseconds in a day.
+const DAY: u64 = 24 * 60 * 60 * 1000;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    /// An Internal error.
+    #[error("internal error: {0}")]
+    Internal(#[from] sql::Error),
+    /// Invalid object ID.
+    #[error("invalid oid '{0}'")]
+    Oid(String),
+}
+
+/// How identity updates requiring confirmation are decided on.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum Policy {
+    /// Accept the update if it was accepted by a quorum of the current delegates,
+    /// otherwise reject it.
+    #[default]
+    AcceptIfQuorum,
+    /// Reject the update, keeping the current identity.
+    Reject,
+    /// Hold the update for the given number of days after it was first fetched,
+    /// then accept it.
+    Hold { days: u32 },
+}
+
+impl Policy {
+    /// Decide on an `update` to the `current` identity document, first fetched at `since`.
+    pub fn decide(
+        &self,
+        current: &Doc<Verified>,
+        update: &Revision,
+        since: Timestamp,
+        now: Timestamp,
+    ) -> Decision {
+        match self {
+            Self::AcceptIfQuorum => {
+                let votes = update
+                    .accepted()
+                    .filter(|did| current.delegates.contains(did))
+                    .count();
+
+                if votes >= current.threshold {
+                    Decision::Accepted
+                } else {
+                    Decision::Rejected
+                }
+            }
+            Self::Reject => Decision::Rejected,
+            Self::Hold { days } => {
+                let until = since + *days as u64 * DAY;
+
+                if *now >= *until {
+                    Decision::Accepted
+                } else {
+                    Decision::Held { until }
+                }
+            }
+        }
+    }
+}
+
+/// Decision on an identity update requiring confirmation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum Decision {
+    /// The update was adopted as the canonical identity.
+    Accepted,
+    /// The update was rejected. The current identity is kept.
+    Rejected,
+    /// The update is held until the given time. The current identity is kept meanwhile.
+    Held { until: Timestamp },
+}
+
+impl Decision {
+    /// Whether the update is adopted.
+    pub fn is_accepted(&self) -> bool {
+        matches!(self, Self::Accepted)
+    }
+}
+
+/// Check whether going from the `current` identity document to `update` requires
+/// confirmation, ie. whether it changes the delegates, threshold or visibility.
+pub fn requires_confirmation<V>(current: &Doc<V>, update: &Doc<V>) -> bool {
+    current.delegates != update.delegates
+        || current.threshold != update.threshold
+        || current.visibility != update.visibility
+}
+
+/// Pending identity updates store.
+///
+/// Keeps when identity updates requiring confirmation were first fetched, so that
+/// updates can be held for some time across fetches and restarts.
+pub trait Store {
+    /// Get when an identity update was first fetched, recording it as fetched `now` if it
+    /// wasn't known.
+    fn first_seen(&mut self, rid: &RepoId, head: &Oid, now: Timestamp) -> Result<Timestamp, Error>;
+    /// Get the pending identity updates of a repository, with when they were first fetched.
+    fn pending(&self, rid: &RepoId) -> Result<Vec<(Oid, Timestamp)>, Error>;
+    /// Forget the pending identity updates of a repository, eg. once one of them is
+    /// accepted. Returns `true` if any were forgotten.
+    fn clear(&mut self, rid: &RepoId) -> Result<bool, Error>;
+}
+
+impl Store for Database {
+    fn first_seen(&mut self, rid: &RepoId, head: &Oid, now: Timestamp) -> Result<Timestamp, Error> {
+        let mut stmt = self.db.prepare(
+            "INSERT INTO `identity-updates` (repo, oid, timestamp)
+             VALUES (?1, ?2, ?3)
+             ON CONFLICT DO NOTHING",
+        )?;
+        stmt.bind((1, rid))?;
+        stmt.bind((2, head.to_string().as_str()))?;
+        stmt.bind((3, &now))?;
+        stmt.next()?;
+
+        let mut stmt = self
+            .db
+            .prepare("SELECT timestamp FROM `identity-updates` WHERE repo = ?1 AND oid = ?2")?;
+        stmt.bind((1, rid))?;
+        stmt.bind((2, head.to_string().as_str()))?;
+
+        if let Some(Ok(row)) = stmt.into_iter().next() {
+            return Ok(row.try_read::<Timestamp, _>("timestamp")?);
+        }
+        Ok(now)
+    }
+
+    fn pending(&self, rid: &RepoId) -> Result<Vec<(Oid, Timestamp)>, Error> {
+        let mut stmt = self.db.prepare(
+            "SELECT oid, timestamp FROM `identity-updates` WHERE repo = ?1 ORDER BY timestamp",
+        )?;
+        stmt.bind((1, rid))?;
+
+        let mut pending = Vec::new();
+        for row in stmt.into_iter() {
+            let row = row?;
+            let oid = row.try_read::<&str, _>("oid")?;
+            let oid = Oid::from_str(oid).map_err(|_| Error::Oid(oid.to_owned()))?;
+            let timestamp = row.try_read::<Timestamp, _>("timestamp")?;
+
+            pending.push((oid, timestamp));
+        }
+        Ok(pending)
+    }
+
+    fn clear(&mut self, rid: &RepoId) -> Result<bool, Error> {
+        let mut stmt = self
+            .db
+            .prepare("DELETE FROM `identity-updates` WHERE repo = ?1")?;
+        stmt.bind((1, rid))?;
+        stmt.next()?;
+
+        Ok(self.db.change_count() > 0)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use nonempty::NonEmpty;
+
+    use super::*;
+    use crate::crypto::test::signer::MockSigner;
+    use crate::crypto::Signer as _;
+    use crate::identity::{Did, Identity};
+    use crate::test::arbitrary;
+    use crate::test::setup::NodeWithRepo;
+
+    #[test]
+    fn test_decide() {
+        let NodeWithRepo { node, repo } = NodeWithRepo::default();
+        let bob = MockSigner::default();
+        let mut identity = Identity::load_mut(&*repo).unwrap();
+        let current = identity.doc().clone();
+        let mut doc = current.clone();
+
+        doc.delegate(bob.public_key());
+        let r1 = identity.update("Add Bob", "", &doc, &node.signer).unwrap();
+        let update = identity.revision(&r1).unwrap().clone();
+        let since = Timestamp::from(1_000);
+        let now = since + DAY;
+
+        assert!(requires_confirmation(&current, &update.doc));
+        assert!(!requires_confirmation(&current, &current));
+
+        assert_eq!(
+            Policy::AcceptIfQuorum.decide(&current, &update, since, since),
+            Decision::Accepted
+        );
+        let mut other = current.clone();
+        other.delegates = NonEmpty::new(Did::from(*bob.public_key()));
+        assert_eq!(
+            Policy::AcceptIfQuorum.decide(&other, &update, since, since),
+            Decision::Rejected
+        );
+        assert_eq!(
+            Policy::Reject.decide(&current, &update, since, now),
+            Decision::Rejected
+        );
+        assert_eq!(
+            Policy::Hold { days: 1 }.decide(&current, &update, since, now - 1),
+            Decision::Held { until: now }
+        );
+        assert_eq!(
+            Policy::Hold { days: 1 }.decide(&current, &update, since, now),
+            Decision::Accepted
+        );
+    }
+
+    #[test]
+    fn test_first_seen() {
+        let mut db = Database::memory().unwrap();
+        let rid = arbitrary::gen::<RepoId>(1);
+        let first = arbitrary::oid();
+        let second = arbitrary::oid();
+
+        assert!(db.pending(&rid).unwrap().is_empty());
+        assert_eq!(
+            db.first_seen(&rid, &first, Timestamp::from(1_000)).unwrap(),
+            Timestamp::from(1_000)
+        );
+        assert_eq!(
+            db.first_seen(&rid, &first, Timestamp::from(2_000)).unwrap(),
+            Timestamp::from(1_000)
+        );
+        assert_eq!(
+            db.first_seen(&rid, &second, Timestamp::from(3_000))
+                .unwrap(),
+            Timestamp::from(3_000)
+        );
+        assert_eq!(
+            db.pending(&rid).unwrap(),
+            vec![
+                (first, Timestamp::from(1_000)),
+                (second, Timestamp::from(3_000))
+            ]
+        );
+        assert!(db.clear(&rid).unwrap());
+        assert!(!db.clear(&rid).unwrap());
+        assert!(db.pending(&rid).unwrap().is_empty());
+    }
+}
@@ -24,10 +24,23 @@ const DB_WRITE_TIMEOUT: time::Duration = time::Duration::from_secs(6);
 
 /// Database migrations.
 /// The first migration is the creation of the initial tables.
-const MIGRATIONS: &[&str] = &[
+pub(crate) const MIGRATIONS: &[&str] = &[
     include_str!("db/migrations/1.sql"),
     include_str!("db/migrations/2.sql"),
     include_str!("db/migrations/3.sql"),
+    include_str!("db/migrations/4.sql"),
+    include_str!("db/migrations/5.sql"),
+    include_str!("db/migrations/6.sql"),
+    include_str!("db/migrations/7.sql"),
+    include_str!("db/migrations/8.sql"),
+    include_str!("db/migrations/9.sql"),
+    include_str!("db/migrations/10.sql"),
+    include_str!("db/migrations/11.sql"),
+    include_str!("db/migrations/12.sql"),
+    include_str!("db/migrations/13.sql"),
+    include_str!("db/migrations/14.sql"),
+    include_str!("db/migrations/15.sql"),
+    include_str!("db/migrations/16.sql"),
 ];
 
 #[derive(Error, Debug)]
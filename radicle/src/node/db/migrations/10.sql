@@ -0,0 +1,11 @@
+-- Last successful fetch, per repository.
+-- Used to report how up-to-date seeded repositories are.
+create table if not exists "repo-fetches" (
+  -- Repository ID.
+  "repo"                 text      not null,
+  -- When the repository was last fetched successfully.
+  "timestamp"            integer   not null,
+  --
+  unique ("repo")
+  --
+) strict;
@@ -0,0 +1,13 @@
+-- Statistics of stored repositories.
+-- Cached, since they are expensive to compute for large repositories.
+create table if not exists "repo-stats" (
+  -- Repository ID.
+  "repo"                 text      not null,
+  -- Statistics, in JSON.
+  "stats"                text      not null,
+  -- When the statistics were computed.
+  "timestamp"            integer   not null,
+  --
+  unique ("repo")
+  --
+) strict;
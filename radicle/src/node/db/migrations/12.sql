@@ -0,0 +1,3 @@
+-- CPU time used by the upload-pack process serving a fetch, in milliseconds.
+-- Not set for uploads that didn't spawn a process, eg. blob transfers.
+alter table "uploads" add column "cpu" integer;
@@ -0,0 +1,3 @@
+-- Node we received an announcement from, so that it isn't sent back to it.
+-- For our own announcements, this is our own node.
+alter table "announcements" add column "relayer" text;
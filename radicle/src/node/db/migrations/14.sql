@@ -0,0 +1,9 @@
+-- Latest timestamp of the gossip messages relayed to us by each peer.
+-- Used to subscribe to only the messages we haven't received when reconnecting.
+create table if not exists "gossip-delivered" (
+  -- Peer that relayed the messages.
+  "peer"                 text      primary key not null,
+  -- Latest timestamp of the messages relayed, in milliseconds.
+  "timestamp"            integer   not null
+  --
+) strict;
@@ -0,0 +1,4 @@
+-- How the announcing node came by each of its addresses, eg. `manual` or `observed`.
+alter table "addresses" add column "label" text;
+-- Address labels of node announcements, and their signature, as sent on the wire.
+alter table "announcements" add column "labels" blob;
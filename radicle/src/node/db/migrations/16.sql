@@ -0,0 +1,2 @@
+-- Number of hops announcements were relayed over before we received them.
+alter table "announcements" add column "hops" integer not null default 0;
@@ -0,0 +1,15 @@
+-- Fetch failures, per repository and remote node.
+-- Used to back off from fetches that keep failing.
+create table if not exists "fetch-failures" (
+  -- Repository ID.
+  "repo"                 text      not null,
+  -- Node we failed to fetch from.
+  "node"                 text      not null,
+  -- Number of consecutive failed fetches.
+  "attempts"             integer   not null,
+  -- When the fetch can next be attempted.
+  "retry_at"             integer   not null,
+  --
+  unique ("repo", "node")
+  --
+) strict;
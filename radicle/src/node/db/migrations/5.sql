@@ -0,0 +1,19 @@
+-- Fetches served to other nodes.
+-- Used to audit who fetches repositories from us.
+create table if not exists "uploads" (
+  -- Repository ID.
+  "repo"                 text      not null,
+  -- Node that fetched the repository.
+  "node"                 text      not null,
+  -- When the upload finished.
+  "timestamp"            integer   not null,
+  -- Number of bytes sent.
+  "sent"                 integer   not null,
+  -- Time taken to serve the fetch, in milliseconds.
+  "elapsed"              integer   not null,
+  -- Error, if the upload failed.
+  "error"                text
+  --
+) strict;
+
+create index if not exists "uploads_timestamp" on "uploads" ("timestamp");
@@ -0,0 +1,15 @@
+-- Announcement sequence numbers, for sequenced announcements.
+alter table "announcements" add column "seq" integer;
+-- Signature over the announcement message and sequence number.
+alter table "announcements" add column "seq_signature" blob;
+
+-- Highest announcement sequence number seen, per node.
+-- Unlike announcements, these are not pruned, so that sequence numbers can't be
+-- replayed once the announcements that carried them have expired.
+create table if not exists "sequences" (
+  -- Node ID.
+  "node"                 text      primary key not null,
+  -- Highest sequence number seen.
+  "seq"                  integer   not null
+  --
+) strict;
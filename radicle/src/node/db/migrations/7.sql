@@ -0,0 +1,16 @@
+-- Last fetched `rad/sigrefs` tip, per repository and remote namespace.
+-- Unlike the refs cache, these are kept when the reference is deleted, so that
+-- rewound or rewritten histories can be detected across fetches.
+create table if not exists "sigref-tips" (
+  -- Repository ID.
+  "repo"                 text      not null,
+  -- Remote namespace.
+  "remote"               text      not null,
+  -- Tip of the remote's `rad/sigrefs`.
+  "oid"                  text      not null,
+  -- When the tip was fetched.
+  "timestamp"            integer   not null,
+  --
+  unique ("repo", "remote")
+  --
+) strict;
@@ -0,0 +1,14 @@
+-- Identity updates requiring confirmation, per repository, with when they were
+-- first fetched. These are kept until an update is accepted, so that updates can
+-- be held for some time across fetches.
+create table if not exists "identity-updates" (
+  -- Repository ID.
+  "repo"                 text      not null,
+  -- Head of the identity update.
+  "oid"                  text      not null,
+  -- When the update was first fetched.
+  "timestamp"            integer   not null,
+  --
+  unique ("repo", "oid")
+  --
+) strict;
@@ -0,0 +1,15 @@
+-- Repositories seeded automatically, because a followed node was active in them.
+create table if not exists "auto-seeds" (
+  -- Repository ID.
+  "repo"                 text      primary key not null,
+  -- Followed node whose activity the repository was discovered through.
+  "node"                 text      not null,
+  -- Size of the repository in storage, in bytes, once cloned.
+  "size"                 integer,
+  -- Whether the repository was rejected, eg. because it isn't delegated to by any
+  -- followed node. Rejected repositories are no longer seeded.
+  "rejected"             integer   not null default 0,
+  -- When the repository was seeded.
+  "timestamp"            integer   not null
+  --
+) strict;
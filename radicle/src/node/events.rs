@@ -1,8 +1,10 @@
+use std::collections::BTreeMap;
 use std::ops::Deref;
 use std::time;
 
 use crossbeam_channel as chan;
 
+use crate::git;
 use crate::git::Oid;
 use crate::node;
 use crate::prelude::*;
@@ -16,12 +18,71 @@ pub enum Event {
         remote: NodeId,
         rid: RepoId,
         updated: Vec<RefUpdate>,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        namespaces: BTreeMap<NodeId, node::NamespaceUpdates>,
+    },
+    RefsFetchFailed {
+        remote: NodeId,
+        rid: RepoId,
+        reason: String,
+        kind: node::ErrorKind,
+    },
+    SigrefsDiverged {
+        remote: NodeId,
+        rid: RepoId,
+        namespace: NodeId,
+        known: Oid,
+        received: Oid,
+        deviation: node::sigrefs::Deviation,
+        refused: bool,
+    },
+    /// A fetched reference wasn't updated because it doesn't descend from the
+    /// local tip, eg. because it was force-pushed.
+    RefDiverged {
+        remote: NodeId,
+        rid: RepoId,
+        namespace: NodeId,
+        name: git::RefString,
+        /// Local tip of the reference.
+        local: Oid,
+        /// Fetched tip of the reference.
+        received: Oid,
+        /// Best common ancestor of the local and fetched tips.
+        base: Option<Oid>,
+        /// Whether the `rad/sigrefs` of the namespace diverged as well.
+        sigrefs_diverged: bool,
+    },
+    /// A fetched identity update requiring confirmation was decided on by policy.
+    /// See [`node::confirmations`].
+    IdentityUpdateDecided {
+        remote: NodeId,
+        rid: RepoId,
+        /// Head of the current identity.
+        current: Oid,
+        /// Head of the fetched identity update.
+        update: Oid,
+        policy: node::confirmations::Policy,
+        decision: node::confirmations::Decision,
+    },
+    /// The identity and signed references of a repository being cloned were
+    /// fetched. Its data is still being fetched.
+    CloneMetadataFetched {
+        remote: NodeId,
+        rid: RepoId,
+        doc: Doc<Verified>,
+        sigrefs: BTreeMap<NodeId, Oid>,
     },
     RefsSynced {
         remote: NodeId,
         rid: RepoId,
         at: Oid,
     },
+    /// A repository was seeded automatically, because a followed node was active in it.
+    /// See [`node::autoseed`].
+    RepoAutoSeeded {
+        rid: RepoId,
+        nid: NodeId,
+    },
     SeedDiscovered {
         rid: RepoId,
         nid: NodeId,
@@ -0,0 +1,134 @@
+use std::num::TryFromIntError;
+
+use sqlite as sql;
+use thiserror::Error;
+
+use crate::node::Database;
+use crate::prelude::{NodeId, RepoId, Timestamp};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    /// An Internal error.
+    #[error("internal error: {0}")]
+    Internal(#[from] sql::Error),
+    /// Unit overflow.
+    #[error("unit overflow: {0}")]
+    UnitOverflow(#[from] TryFromIntError),
+}
+
+/// Failed fetches of a repository from a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Failure {
+    /// Number of consecutive failed fetches.
+    pub attempts: usize,
+    /// When the fetch can next be attempted.
+    pub retry_at: Timestamp,
+}
+
+/// Fetch failure store.
+///
+/// Used to back off from fetching repositories that keep failing, eg. because they don't
+/// pass validation. Entries are kept until a fetch succeeds, or they are reset.
+pub trait Store {
+    /// Get the failures of fetching a repository from a node, if any.
+    fn failure(&self, rid: &RepoId, nid: &NodeId) -> Result<Option<Failure>, Error>;
+    /// Record a failed fetch of a repository from a node.
+    fn failed(&mut self, rid: &RepoId, nid: &NodeId, failure: Failure) -> Result<(), Error>;
+    /// Record a successful fetch of a repository from a node, clearing its failures.
+    fn succeeded(&mut self, rid: &RepoId, nid: &NodeId) -> Result<bool, Error>;
+    /// Clear all failures of a repository. Returns the number of entries removed.
+    fn reset(&mut self, rid: &RepoId) -> Result<usize, Error>;
+}
+
+impl Store for Database {
+    fn failure(&self, rid: &RepoId, nid: &NodeId) -> Result<Option<Failure>, Error> {
+        let mut stmt = self.db.prepare(
+            "SELECT attempts, retry_at FROM `fetch-failures` WHERE repo = ?1 AND node = ?2",
+        )?;
+        stmt.bind((1, rid))?;
+        stmt.bind((2, nid))?;
+
+        if let Some(Ok(row)) = stmt.into_iter().next() {
+            let attempts = usize::try_from(row.try_read::<i64, _>("attempts")?)?;
+            let retry_at = row.try_read::<Timestamp, _>("retry_at")?;
+
+            return Ok(Some(Failure { attempts, retry_at }));
+        }
+        Ok(None)
+    }
+
+    fn failed(&mut self, rid: &RepoId, nid: &NodeId, failure: Failure) -> Result<(), Error> {
+        let mut stmt = self.db.prepare(
+            "INSERT INTO `fetch-failures` (repo, node, attempts, retry_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT DO UPDATE
+             SET attempts = ?3, retry_at = ?4",
+        )?;
+        stmt.bind((1, rid))?;
+        stmt.bind((2, nid))?;
+        stmt.bind((3, i64::try_from(failure.attempts)?))?;
+        stmt.bind((4, &failure.retry_at))?;
+        stmt.next()?;
+
+        Ok(())
+    }
+
+    fn succeeded(&mut self, rid: &RepoId, nid: &NodeId) -> Result<bool, Error> {
+        let mut stmt = self
+            .db
+            .prepare("DELETE FROM `fetch-failures` WHERE repo = ?1 AND node = ?2")?;
+        stmt.bind((1, rid))?;
+        stmt.bind((2, nid))?;
+        stmt.next()?;
+
+        Ok(self.db.change_count() > 0)
+    }
+
+    fn reset(&mut self, rid: &RepoId) -> Result<usize, Error> {
+        let mut stmt = self
+            .db
+            .prepare("DELETE FROM `fetch-failures` WHERE repo = ?1")?;
+        stmt.bind((1, rid))?;
+        stmt.next()?;
+
+        Ok(self.db.change_count())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::arbitrary;
+
+    #[test]
+    fn test_failures() {
+        let mut db = Database::memory().unwrap();
+        let rid = arbitrary::gen::<RepoId>(1);
+        let alice = arbitrary::gen::<NodeId>(1);
+        let bob = arbitrary::gen::<NodeId>(1);
+        let failure = Failure {
+            attempts: 1,
+            retry_at: Timestamp::from(1_000),
+        };
+
+        assert_eq!(db.failure(&rid, &alice).unwrap(), None);
+
+        db.failed(&rid, &alice, failure).unwrap();
+        db.failed(&rid, &bob, failure).unwrap();
+        assert_eq!(db.failure(&rid, &alice).unwrap(), Some(failure));
+
+        let failure = Failure {
+            attempts: 2,
+            retry_at: Timestamp::from(2_000),
+        };
+        db.failed(&rid, &alice, failure).unwrap();
+        assert_eq!(db.failure(&rid, &alice).unwrap(), Some(failure));
+
+        assert!(db.succeeded(&rid, &alice).unwrap());
+        assert!(!db.succeeded(&rid, &alice).unwrap());
+        assert_eq!(db.failure(&rid, &alice).unwrap(), None);
+
+        assert_eq!(db.reset(&rid).unwrap(), 1);
+        assert_eq!(db.failure(&rid, &bob).unwrap(), None);
+    }
+}
@@ -14,6 +14,70 @@ impl Features {
     /// `SEED` is the base feature set all seed nodes must support.
     pub const SEED: Features = Features(0b00000001);
 
+    /// `BRIDGE` is supported by publicly reachable nodes that forward opaque streams
+    /// between connected peers which can't reach each other directly, eg. because
+    /// they are both behind NAT.
+    pub const BRIDGE: Features = Features(0b00000010);
+
+    /// `BLOBS` is supported by nodes that serve large files referenced by repositories
+    /// over a separate, resumable transfer protocol, instead of inside Git packs.
+    pub const BLOBS: Features = Features(0b00000100);
+
+    /// `HEARTBEAT` is supported by nodes that understand heartbeat announcements, which
+    /// seeds use to advertise their health. Heartbeats are only sent to these nodes.
+    pub const HEARTBEAT: Features = Features(0b00001000);
+
+    /// `SEQUENCE` is supported by nodes that understand sequenced announcements, which
+    /// carry a sequence number to protect against replays. Other nodes are sent
+    /// announcements without their sequence number.
+    pub const SEQUENCE: Features = Features(0b00010000);
+
+    /// `RELAY` is supported by nodes that relay gossip messages received from their peers
+    /// to their other peers.
+    pub const RELAY: Features = Features(0b00100000);
+
+    /// `SHALLOW_FETCH` is supported by nodes that can serve fetches limited to the most
+    /// recent history of a repository.
+    pub const SHALLOW_FETCH: Features = Features(0b01000000);
+
+    /// `DELTA_REFS` is supported by nodes that can announce and serve only the references
+    /// that changed since a previous announcement.
+    pub const DELTA_REFS: Features = Features(0b10000000);
+
+    /// `HTTP_GATEWAY` is supported by nodes that serve their repositories over HTTP.
+    pub const HTTP_GATEWAY: Features = Features(0b1_00000000);
+
+    /// `INVENTORY_DIFF` is supported by nodes that understand inventory announcements sent
+    /// as the difference with a previous inventory of the same node.
+    pub const INVENTORY_DIFF: Features = Features(0b10_00000000);
+
+    /// `COMPRESSION` is supported by nodes that understand compressed messages. Large
+    /// announcements are only sent compressed to these nodes.
+    pub const COMPRESSION: Features = Features(0b100_00000000);
+
+    /// `INVENTORY_PAGES` is supported by nodes that understand inventories announced in
+    /// multiple pages, which nodes with more repositories than fit in a single inventory
+    /// announcement send instead.
+    pub const INVENTORY_PAGES: Features = Features(0b1000_00000000);
+
+    /// `REF_QUERY` is supported by nodes that answer queries of the tips of references of
+    /// a repository, without a fetch.
+    pub const REF_QUERY: Features = Features(0b10000_00000000);
+
+    /// `ADDRESS_LABELS` is supported by nodes that understand node announcements labeling
+    /// where each announced address comes from. Other nodes are sent node announcements
+    /// without labels.
+    pub const ADDRESS_LABELS: Features = Features(0b100000_00000000);
+
+    /// `RELAY_HOPS` is supported by nodes that understand relayed announcements carrying
+    /// the number of hops they were relayed over, which bounds how far they propagate.
+    /// Other nodes are sent announcements without their hop count.
+    pub const RELAY_HOPS: Features = Features(0b1000000_00000000);
+
+    /// All features known to this version of the protocol. Bits outside of this set may be
+    /// defined by newer versions, and are preserved as-is.
+    pub const KNOWN: Features = Features(0b1111111_11111111);
+
     /// Returns [`Features`] with the other features added.
     #[must_use]
     pub fn with(self, other: Features) -> Features {
@@ -23,13 +87,25 @@ impl Features {
     /// Returns [`Features`] without the other features.
     #[must_use]
     pub fn without(self, other: Features) -> Features {
-        Self(self.0 ^ other.0)
+        Self(self.0 & !other.0)
     }
 
     /// Check whether [`Features`] are included.
     pub fn has(self, flags: Features) -> bool {
         (self.0 | flags.0) == self.0
     }
+
+    /// Returns the required [`Features`] that are not included.
+    #[must_use]
+    pub fn missing(self, required: Features) -> Features {
+        required.without(self)
+    }
+
+    /// Returns the features that aren't known to this version of the protocol.
+    #[must_use]
+    pub fn unknown(self) -> Features {
+        self.without(Self::KNOWN)
+    }
 }
 
 impl Default for Features {
@@ -128,5 +204,33 @@ mod test {
             Features::NONE.with(Features::SEED).without(Features::SEED),
             Features::NONE
         );
+
+        let features = Features::SEED.with(Features::BRIDGE);
+        assert!(features.has(Features::SEED));
+        assert!(features.has(Features::BRIDGE));
+        assert!(!features.without(Features::BRIDGE).has(Features::BRIDGE));
+        assert_eq!(features.without(Features::BLOBS), features);
+    }
+
+    #[test]
+    fn test_missing() {
+        let features = Features::SEED | Features::RELAY;
+
+        assert_eq!(features.missing(Features::SEED), Features::NONE);
+        assert_eq!(
+            features.missing(Features::SEED | Features::HTTP_GATEWAY),
+            Features::HTTP_GATEWAY
+        );
+        assert_eq!(Features::NONE.missing(Features::NONE), Features::NONE);
+    }
+
+    #[test]
+    fn test_unknown() {
+        let future = Features::from(1 << 40);
+        let features = Features::SEED | Features::DELTA_REFS | future;
+
+        assert_eq!(features.unknown(), future);
+        assert!(features.has(future));
+        assert_eq!(Features::KNOWN.unknown(), Features::NONE);
     }
 }
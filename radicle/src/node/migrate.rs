@@ -0,0 +1,338 @@
+//! Migrations of the node's on-disk state.
+//!
+//! Each component of the on-disk state, eg. the node database or the storage layout, has its
+//! own format version, and a list of migration steps to bring it to the latest version. For
+//! databases, the version is kept in the `user_version` header field, and each step is an SQL
+//! script. For storage, the version is kept in a file at the root of the storage directory.
+//!
+//! Migrations are run when the node starts, before anything else is opened. They can also be
+//! checked without being applied, with [`check`].
+use std::path::{Path, PathBuf};
+use std::{fmt, fs, io};
+
+use sqlite as sql;
+use thiserror::Error;
+
+use crate::cob;
+use crate::node;
+use crate::profile::Home;
+use crate::sql::transaction;
+
+/// Name of the file holding the storage layout version.
+pub const STORAGE_VERSION_FILE: &str = ".version";
+
+/// Storage migrations. Each step migrates the storage from one version to the next.
+const STORAGE_MIGRATIONS: &[fn(&Path) -> io::Result<()>] = &[
+    // Storage was unversioned until this step, which only records the version.
+    |_| Ok(()),
+];
+
+/// Migration error.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// A database error.
+    #[error("{0}: database error: {1}")]
+    Database(Component, sql::Error),
+    /// An I/O error.
+    #[error("{0}: i/o error: {1}")]
+    Io(Component, io::Error),
+    /// The on-disk version is invalid.
+    #[error("{0}: invalid version {1:?}")]
+    InvalidVersion(Component, String),
+    /// The on-disk state was written by a newer version of the software.
+    #[error("{component}: version {version} is newer than the latest supported version {latest}")]
+    Unsupported {
+        component: Component,
+        version: usize,
+        latest: usize,
+    },
+}
+
+/// A component of the on-disk state, with its own format version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Component {
+    /// Node database, with the address book, routing table and gossip store.
+    Database,
+    /// Seeding and follow policies.
+    Policies,
+    /// Notifications.
+    Notifications,
+    /// COB cache.
+    Cobs,
+    /// Repository storage layout.
+    Storage,
+}
+
+impl Component {
+    /// All components, in the order they are migrated.
+    pub const ALL: [Component; 5] = [
+        Component::Database,
+        Component::Policies,
+        Component::Notifications,
+        Component::Cobs,
+        Component::Storage,
+    ];
+
+    /// Latest version of this component.
+    pub fn latest(&self) -> usize {
+        match self {
+            Self::Storage => STORAGE_MIGRATIONS.len(),
+            _ => self.migrations().len(),
+        }
+    }
+
+    /// Path of this component, under the given home.
+    pub fn path(&self, home: &Home) -> PathBuf {
+        match self {
+            Self::Database => home.node().join(node::NODE_DB_FILE),
+            Self::Policies => home.node().join(node::POLICIES_DB_FILE),
+            Self::Notifications => home.node().join(node::NOTIFICATIONS_DB_FILE),
+            Self::Cobs => home.cobs().join(cob::cache::COBS_DB_FILE),
+            Self::Storage => home.storage(),
+        }
+    }
+
+    /// SQL migrations of this component. Empty for storage.
+    fn migrations(&self) -> &'static [&'static str] {
+        match self {
+            Self::Database => node::db::MIGRATIONS,
+            Self::Policies => node::policy::store::MIGRATIONS,
+            Self::Notifications => node::notifications::store::MIGRATIONS,
+            Self::Cobs => cob::cache::MIGRATIONS,
+            Self::Storage => &[],
+        }
+    }
+}
+
+impl fmt::Display for Component {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Database => write!(f, "database"),
+            Self::Policies => write!(f, "policies"),
+            Self::Notifications => write!(f, "notifications"),
+            Self::Cobs => write!(f, "cobs"),
+            Self::Storage => write!(f, "storage"),
+        }
+    }
+}
+
+/// Migration status of a component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Status {
+    /// The component.
+    pub component: Component,
+    /// On-disk version, or `None` if the component doesn't exist yet.
+    pub version: Option<usize>,
+    /// Latest version.
+    pub latest: usize,
+}
+
+impl Status {
+    /// Number of migrations to apply to bring the component up to date.
+    pub fn pending(&self) -> usize {
+        self.version
+            .map(|v| self.latest.saturating_sub(v))
+            .unwrap_or_default()
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.version {
+            None => write!(f, "{}: not created yet", self.component),
+            Some(v) if v == self.latest => write!(f, "{}: version {v}, up to date", self.component),
+            Some(v) => write!(
+                f,
+                "{}: version {v}, {} migration(s) pending to version {}",
+                self.component,
+                self.pending(),
+                self.latest
+            ),
+        }
+    }
+}
+
+/// Get the migration status of all components, without migrating anything.
+pub fn check(home: &Home) -> Result<Vec<Status>, Error> {
+    Component::ALL
+        .iter()
+        .map(|c| status(*c, &c.path(home)))
+        .collect()
+}
+
+/// Migrate all components to their latest version.
+/// Returns the status of each component before migration.
+///
+/// Fails without migrating anything if a component is newer than supported.
+pub fn run(home: &Home) -> Result<Vec<Status>, Error> {
+    let statuses = check(home)?;
+
+    for s in &statuses {
+        if let Some(version) = s.version {
+            if version > s.latest {
+                return Err(Error::Unsupported {
+                    component: s.component,
+                    version,
+                    latest: s.latest,
+                });
+            }
+        }
+    }
+    for s in &statuses {
+        let (Some(version), pending @ 1..) = (s.version, s.pending()) else {
+            continue;
+        };
+        let path = s.component.path(home);
+
+        log::info!(
+            target: "node",
+            "Migrating {} from version {version} to {} ({pending} step(s))..",
+            s.component,
+            s.latest
+        );
+        match s.component {
+            Component::Storage => migrate_storage(&path, version),
+            c => migrate_database(c, &path),
+        }?;
+    }
+    Ok(statuses)
+}
+
+/// Get the status of a component at the given path.
+fn status(component: Component, path: &Path) -> Result<Status, Error> {
+    let version = match component {
+        Component::Storage => storage_version(path)?,
+        c if path.exists() => {
+            let db = sql::Connection::open_with_flags(path, sql::OpenFlags::new().with_read_only())
+                .map_err(|e| Error::Database(c, e))?;
+
+            Some(database_version(&db).map_err(|e| Error::Database(c, e))?)
+        }
+        _ => None,
+    };
+    Ok(Status {
+        component,
+        version,
+        latest: component.latest(),
+    })
+}
+
+/// Get the `user_version` value from the database header.
+fn database_version(db: &sql::Connection) -> Result<usize, sql::Error> {
+    let mut stmt = db.prepare("PRAGMA user_version")?;
+    stmt.next()?;
+
+    Ok(stmt.read::<i64, _>(0)? as usize)
+}
+
+/// Apply the pending migrations of a database, each in its own transaction.
+fn migrate_database(component: Component, path: &Path) -> Result<(), Error> {
+    let db = sql::Connection::open(path).map_err(|e| Error::Database(component, e))?;
+    let version = database_version(&db).map_err(|e| Error::Database(component, e))?;
+
+    for (i, migration) in component.migrations().iter().enumerate().skip(version) {
+        transaction(&db, |db| {
+            db.execute(migration)?;
+            db.execute(format!("PRAGMA user_version = {}", i + 1))
+        })
+        .map_err(|e| Error::Database(component, e))?;
+    }
+    Ok(())
+}
+
+/// Get the storage layout version. Storage that exists but has no version file is at
+/// version `0`.
+fn storage_version(path: &Path) -> Result<Option<usize>, Error> {
+    let err = |e| Error::Io(Component::Storage, e);
+
+    if !path.exists() {
+        return Ok(None);
+    }
+    match fs::read_to_string(path.join(STORAGE_VERSION_FILE)) {
+        Ok(s) => s
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| Error::InvalidVersion(Component::Storage, s)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Some(0)),
+        Err(e) => Err(err(e)),
+    }
+}
+
+/// Apply the pending storage migrations, recording the version after each step.
+fn migrate_storage(path: &Path, version: usize) -> Result<(), Error> {
+    let err = |e| Error::Io(Component::Storage, e);
+
+    for (i, migration) in STORAGE_MIGRATIONS.iter().enumerate().skip(version) {
+        migration(path).map_err(err)?;
+        fs::write(path.join(STORAGE_VERSION_FILE), format!("{}\n", i + 1)).map_err(err)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_migrate() {
+        let tmp = tempfile::tempdir().unwrap();
+        let home = Home::new(tmp.path()).unwrap();
+
+        // Only the storage directory exists, and it's unversioned.
+        let statuses = check(&home).unwrap();
+        assert!(
+            statuses
+                .iter()
+                .all(|s| s.version.is_none() || s.component == Component::Storage),
+            "{statuses:?}"
+        );
+
+        // A database was created with an older version.
+        fs::create_dir_all(home.node()).unwrap();
+        {
+            let db = sql::Connection::open(Component::Database.path(&home)).unwrap();
+            db.execute(node::db::MIGRATIONS[0]).unwrap();
+            db.execute("PRAGMA user_version = 1").unwrap();
+        }
+        let statuses = check(&home).unwrap();
+        let pending = statuses
+            .iter()
+            .filter(|s| s.pending() > 0)
+            .map(|s| (s.component, s.pending()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            pending,
+            vec![
+                (Component::Database, node::db::MIGRATIONS.len() - 1),
+                (Component::Storage, 1)
+            ]
+        );
+
+        // Migrating doesn't create anything, and brings everything up to date.
+        assert_eq!(run(&home).unwrap(), statuses);
+        let statuses = check(&home).unwrap();
+        assert!(statuses.iter().all(|s| s.pending() == 0), "{statuses:?}");
+        assert_eq!(
+            statuses.iter().filter(|s| s.version.is_some()).count(),
+            2,
+            "{statuses:?}"
+        );
+        assert_eq!(
+            home.database().unwrap().version().unwrap(),
+            node::db::MIGRATIONS.len()
+        );
+
+        // Newer versions are refused.
+        fs::write(home.storage().join(STORAGE_VERSION_FILE), "99").unwrap();
+        assert!(matches!(
+            run(&home),
+            Err(Error::Unsupported {
+                component: Component::Storage,
+                version: 99,
+                ..
+            })
+        ));
+    }
+}
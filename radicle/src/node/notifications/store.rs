@@ -23,6 +23,9 @@ use super::{
 const DB_READ_TIMEOUT: time::Duration = time::Duration::from_secs(3);
 /// How long to wait for the database lock to be released before failing a write.
 const DB_WRITE_TIMEOUT: time::Duration = time::Duration::from_secs(6);
+/// Database migrations, applied by [`crate::node::migrate`].
+/// The first migration is the initial schema, which is also applied when the store is opened.
+pub(crate) const MIGRATIONS: &[&str] = &[include_str!("schema.sql")];
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -27,6 +27,66 @@ pub struct FollowPolicy {
     pub policy: Policy,
 }
 
+/// Policy on which nodes can fetch a repository from us.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FetcherPolicy {
+    pub rid: RepoId,
+    pub nid: NodeId,
+    pub policy: Policy,
+}
+
+/// Categories of references that are fetched for a repository, eg. to seed its code without
+/// its tags or notes, and save space. Identity references, ie. `rad/*` and identity COBs,
+/// are always fetched, as are the delegates' default branches, which the canonical head is
+/// computed from.
+///
+/// N.b. references that aren't fetched are still listed in their remote's signed refs, and
+/// are skipped by nodes fetching that remote from us.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RefCategories {
+    /// Branches, ie. `refs/heads/*`.
+    pub heads: bool,
+    /// Tags, ie. `refs/tags/*`.
+    pub tags: bool,
+    /// Git notes, ie. `refs/notes/*`.
+    pub notes: bool,
+    /// Collaborative objects, eg. issues and patches, ie. `refs/cobs/*`.
+    pub cobs: bool,
+}
+
+impl Default for RefCategories {
+    fn default() -> Self {
+        Self {
+            heads: true,
+            tags: true,
+            notes: true,
+            cobs: true,
+        }
+    }
+}
+
+impl RefCategories {
+    /// Check whether the given reference name, without namespace, is in a category that
+    /// is fetched. References that are in no category are always fetched.
+    pub fn is_fetched(&self, refname: &str) -> bool {
+        if refname.starts_with("refs/heads/") {
+            self.heads
+        } else if refname.starts_with("refs/tags/") {
+            self.tags
+        } else if refname.starts_with("refs/notes/") {
+            self.notes
+        } else if let Some(cob) = refname.strip_prefix("refs/cobs/") {
+            self.cobs
+                || cob
+                    .strip_prefix(crate::cob::identity::TYPENAME.as_str())
+                    .is_some_and(|id| id.starts_with('/'))
+        } else {
+            true
+        }
+    }
+}
+
 /// Resource policy.
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -12,7 +12,7 @@ use crate::storage::{Namespaces, ReadRepository as _, ReadStorage, RepositoryErr
 pub use crate::node::policy::store;
 pub use crate::node::policy::store::Error;
 pub use crate::node::policy::store::Store;
-pub use crate::node::policy::{Alias, FollowPolicy, Policy, Scope, SeedPolicy};
+pub use crate::node::policy::{Alias, FetcherPolicy, FollowPolicy, Policy, Scope, SeedPolicy};
 
 #[derive(Debug, Error)]
 pub enum NamespacesError {
@@ -74,6 +74,12 @@ impl<T> Config<T> {
         }
     }
 
+    /// Change the default policy and scope, eg. after the node configuration was reloaded.
+    pub fn set_defaults(&mut self, policy: Policy, scope: Scope) {
+        self.policy = policy;
+        self.scope = scope;
+    }
+
     /// Check if a repository is seeded.
     pub fn is_seeding(&self, rid: &RepoId) -> Result<bool, Error> {
         self.seed_policy(rid)
@@ -86,6 +92,14 @@ impl<T> Config<T> {
             .map(|entry| entry.policy == Policy::Allow)
     }
 
+    /// Check if a node is explicitly blocked.
+    pub fn is_blocked(&self, nid: &NodeId) -> Result<bool, Error> {
+        Ok(self
+            .store
+            .follow_policy(nid)?
+            .is_some_and(|entry| entry.policy == Policy::Block))
+    }
+
     /// Get a node's following information.
     /// Returns the default policy if the node isn't found.
     pub fn follow_policy(&self, nid: &NodeId) -> Result<FollowPolicy, Error> {
@@ -155,6 +169,17 @@ impl<T> Config<T> {
     }
 }
 
+impl Config<store::Write> {
+    /// Make several policy changes atomically, eg. to change a policy depending on its
+    /// current value without racing other writers. See [`Store::transaction`].
+    pub fn transaction<T>(
+        &mut self,
+        f: impl FnMut(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        store::transaction(self, |config| &mut config.store, f)
+    }
+}
+
 impl<T> ops::Deref for Config<T> {
     type Target = Store<T>;
 
@@ -29,3 +29,50 @@ create table if not exists "seeding" (
   "policy"             text      default 'allow'
   --
 ) strict;
+
+-- Private repositories. These are never announced to the network, and are only
+-- served to the nodes in the `private-access` table.
+create table if not exists "private" (
+  -- Repository ID.
+  "id"                 text      primary key not null
+  --
+) strict;
+
+-- Nodes allowed to fetch private repositories.
+create table if not exists "private-access" (
+  -- Repository ID.
+  "id"                 text      not null,
+  -- Node ID.
+  "node"               text      not null,
+  --
+  primary key ("id", "node")
+) strict;
+
+-- Nodes allowed or blocked from fetching a repository. If any node is allowed to
+-- fetch a repository, all other nodes are blocked from fetching it.
+create table if not exists "fetchers" (
+  -- Repository ID.
+  "id"                 text      not null,
+  -- Node ID.
+  "node"               text      not null,
+  -- Fetching policy for this node and repository.
+  "policy"             text      not null,
+  --
+  primary key ("id", "node")
+) strict;
+
+-- Categories of references fetched for a repository. All references of repositories
+-- that aren't in this table are fetched.
+create table if not exists "ref-categories" (
+  -- Repository ID.
+  "id"                 text      primary key not null,
+  -- Whether branches are fetched.
+  "heads"              integer   not null default 1,
+  -- Whether tags are fetched.
+  "tags"               integer   not null default 1,
+  -- Whether git notes are fetched.
+  "notes"              integer   not null default 1,
+  -- Whether collaborative objects are fetched.
+  "cobs"               integer   not null default 1
+  --
+) strict;
@@ -1,20 +1,33 @@
 #![allow(clippy::type_complexity)]
 use std::marker::PhantomData;
 use std::path::Path;
-use std::{fmt, io, ops::Not as _, str::FromStr, time};
+use std::{fmt, io, ops::Not as _, str::FromStr, thread, time};
 
+use log::warn;
 use sqlite as sql;
 use thiserror::Error;
 
 use crate::node::{Alias, AliasStore};
 use crate::prelude::{NodeId, RepoId};
 
-use super::{FollowPolicy, Policy, Scope, SeedPolicy};
+use super::{FetcherPolicy, FollowPolicy, Policy, RefCategories, Scope, SeedPolicy};
 
 /// How long to wait for the database lock to be released before failing a read.
 const DB_READ_TIMEOUT: time::Duration = time::Duration::from_secs(3);
 /// How long to wait for the database lock to be released before failing a write.
 const DB_WRITE_TIMEOUT: time::Duration = time::Duration::from_secs(6);
+/// How many times a write is attempted, when the database stays locked by another writer
+/// for longer than the write timeout.
+const DB_WRITE_ATTEMPTS: u32 = 3;
+/// How long to wait before retrying a write, multiplied by the number of attempts so far.
+const DB_WRITE_BACKOFF: time::Duration = time::Duration::from_millis(250);
+/// SQLite result code when the database file is locked by another connection.
+const SQLITE_BUSY: isize = 5;
+/// SQLite result code when a table is locked by another connection to the same database.
+const SQLITE_LOCKED: isize = 6;
+/// Database migrations, applied by [`crate::node::migrate`].
+/// The first migration is the initial schema, which is also applied when the store is opened.
+pub(crate) const MIGRATIONS: &[&str] = &[include_str!("schema.sql")];
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -26,6 +39,20 @@ pub enum Error {
     Internal(#[from] sql::Error),
 }
 
+impl Error {
+    /// Whether the database was locked by another writer, and the operation may succeed
+    /// if retried.
+    pub fn is_busy(&self) -> bool {
+        match self {
+            // Nb. Extended result codes carry the primary result code in their lowest byte.
+            Self::Internal(sql::Error {
+                code: Some(code), ..
+            }) => matches!(code & 0xff, SQLITE_BUSY | SQLITE_LOCKED),
+            Self::Io(_) | Self::Internal(_) => false,
+        }
+    }
+}
+
 /// Read-only type witness.
 pub struct Read;
 /// Read-write type witness.
@@ -39,6 +66,9 @@ pub type StoreWriter = Store<Write>;
 /// Policy configuration.
 pub struct Store<T> {
     db: sql::Connection,
+    /// Whether a write transaction is in progress. Writes made while one is in progress
+    /// are part of it.
+    writing: bool,
     _marker: PhantomData<T>,
 }
 
@@ -61,6 +91,7 @@ impl Store<Read> {
 
         Ok(Self {
             db,
+            writing: false,
             _marker: PhantomData,
         })
     }
@@ -75,6 +106,7 @@ impl Store<Read> {
 
         Ok(Self {
             db,
+            writing: false,
             _marker: PhantomData,
         })
     }
@@ -92,6 +124,7 @@ impl Store<Write> {
 
         Ok(Self {
             db,
+            writing: false,
             _marker: PhantomData,
         })
     }
@@ -103,6 +136,7 @@ impl Store<Write> {
 
         Ok(Self {
             db,
+            writing: false,
             _marker: PhantomData,
         })
     }
@@ -111,95 +145,320 @@ impl Store<Write> {
     pub fn read_only(self) -> StoreReader {
         Store {
             db: self.db,
+            writing: false,
             _marker: PhantomData,
         }
     }
 
+    /// Make several writes atomically, in a single transaction.
+    ///
+    /// The node and the CLI may write to the store at the same time. To keep writers from
+    /// failing half-way through, the transaction takes the write lock up-front, and is
+    /// retried a few times if another writer holds it for longer than the busy timeout.
+    /// Hence, `f` may be called more than once. Its writes are rolled back if it fails.
+    ///
+    /// Writes made by `f`, including nested transactions, are part of the transaction.
+    pub fn transaction<T>(
+        &mut self,
+        f: impl FnMut(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        transaction(self, |store| store, f)
+    }
+
     /// Follow a node.
     pub fn follow(&mut self, id: &NodeId, alias: Option<&str>) -> Result<bool, Error> {
-        let mut stmt = self.db.prepare(
-            "INSERT INTO `following` (id, alias)
-             VALUES (?1, ?2)
-             ON CONFLICT DO UPDATE
-             SET alias = ?2 WHERE alias != ?2",
-        )?;
-
-        stmt.bind((1, id))?;
-        stmt.bind((2, alias.unwrap_or_default()))?;
-        stmt.next()?;
-
-        Ok(self.db.change_count() > 0)
+        self.write(|db| {
+            let mut stmt = db.prepare(
+                "INSERT INTO `following` (id, alias)
+                 VALUES (?1, ?2)
+                 ON CONFLICT DO UPDATE
+                 SET alias = ?2 WHERE alias != ?2",
+            )?;
+
+            stmt.bind((1, id))?;
+            stmt.bind((2, alias.unwrap_or_default()))?;
+            stmt.next()?;
+
+            Ok(db.change_count() > 0)
+        })
     }
 
     /// Seed a repository.
     pub fn seed(&mut self, id: &RepoId, scope: Scope) -> Result<bool, Error> {
-        let mut stmt = self.db.prepare(
-            "INSERT INTO `seeding` (id, scope)
-             VALUES (?1, ?2)
-             ON CONFLICT DO UPDATE
-             SET scope = ?2 WHERE scope != ?2",
-        )?;
-
-        stmt.bind((1, id))?;
-        stmt.bind((2, scope))?;
-        stmt.next()?;
-
-        Ok(self.db.change_count() > 0)
+        self.write(|db| {
+            let mut stmt = db.prepare(
+                "INSERT INTO `seeding` (id, scope)
+                 VALUES (?1, ?2)
+                 ON CONFLICT DO UPDATE
+                 SET scope = ?2 WHERE scope != ?2",
+            )?;
+
+            stmt.bind((1, id))?;
+            stmt.bind((2, scope))?;
+            stmt.next()?;
+
+            Ok(db.change_count() > 0)
+        })
     }
 
     /// Set a node's follow policy.
     pub fn set_follow_policy(&mut self, id: &NodeId, policy: Policy) -> Result<bool, Error> {
-        let mut stmt = self.db.prepare(
-            "INSERT INTO `following` (id, policy)
-             VALUES (?1, ?2)
-             ON CONFLICT DO UPDATE
-             SET policy = ?2 WHERE policy != ?2",
-        )?;
-
-        stmt.bind((1, id))?;
-        stmt.bind((2, policy))?;
-        stmt.next()?;
-
-        Ok(self.db.change_count() > 0)
+        self.write(|db| {
+            let mut stmt = db.prepare(
+                "INSERT INTO `following` (id, policy)
+                 VALUES (?1, ?2)
+                 ON CONFLICT DO UPDATE
+                 SET policy = ?2 WHERE policy != ?2",
+            )?;
+
+            stmt.bind((1, id))?;
+            stmt.bind((2, policy))?;
+            stmt.next()?;
+
+            Ok(db.change_count() > 0)
+        })
     }
 
     /// Set a repository's seeding policy.
     pub fn set_seed_policy(&mut self, id: &RepoId, policy: Policy) -> Result<bool, Error> {
-        let mut stmt = self.db.prepare(
-            "INSERT INTO `seeding` (id, policy)
-             VALUES (?1, ?2)
-             ON CONFLICT DO UPDATE
-             SET policy = ?2 WHERE policy != ?2",
-        )?;
-
-        stmt.bind((1, id))?;
-        stmt.bind((2, policy))?;
-        stmt.next()?;
-
-        Ok(self.db.change_count() > 0)
+        self.write(|db| {
+            let mut stmt = db.prepare(
+                "INSERT INTO `seeding` (id, policy)
+                 VALUES (?1, ?2)
+                 ON CONFLICT DO UPDATE
+                 SET policy = ?2 WHERE policy != ?2",
+            )?;
+
+            stmt.bind((1, id))?;
+            stmt.bind((2, policy))?;
+            stmt.next()?;
+
+            Ok(db.change_count() > 0)
+        })
     }
 
     /// Unfollow a node.
     pub fn unfollow(&mut self, id: &NodeId) -> Result<bool, Error> {
-        let mut stmt = self.db.prepare("DELETE FROM `following` WHERE id = ?")?;
+        self.write(|db| {
+            let mut stmt = db.prepare("DELETE FROM `following` WHERE id = ?")?;
 
-        stmt.bind((1, id))?;
-        stmt.next()?;
+            stmt.bind((1, id))?;
+            stmt.next()?;
 
-        Ok(self.db.change_count() > 0)
+            Ok(db.change_count() > 0)
+        })
     }
 
     /// Unseed a repository.
     pub fn unseed(&mut self, id: &RepoId) -> Result<bool, Error> {
-        let mut stmt = self.db.prepare("DELETE FROM `seeding` WHERE id = ?")?;
+        self.write(|db| {
+            let mut stmt = db.prepare("DELETE FROM `seeding` WHERE id = ?")?;
 
-        stmt.bind((1, id))?;
-        stmt.next()?;
+            stmt.bind((1, id))?;
+            stmt.next()?;
 
-        Ok(self.db.change_count() > 0)
+            Ok(db.change_count() > 0)
+        })
+    }
+
+    /// Mark a repository as private or public. Private repositories are never announced,
+    /// and are only served to the nodes allowed with [`Self::allow_private`].
+    ///
+    /// Making a repository public again also clears its list of allowed nodes.
+    pub fn set_private(&mut self, id: &RepoId, private: bool) -> Result<bool, Error> {
+        self.write(|db| {
+            if private {
+                let mut stmt =
+                    db.prepare("INSERT INTO `private` (id) VALUES (?1) ON CONFLICT DO NOTHING")?;
+
+                stmt.bind((1, id))?;
+                stmt.next()?;
+
+                return Ok(db.change_count() > 0);
+            }
+            let mut stmt = db.prepare("DELETE FROM `private` WHERE id = ?")?;
+
+            stmt.bind((1, id))?;
+            stmt.next()?;
+
+            let updated = db.change_count() > 0;
+            let mut stmt = db.prepare("DELETE FROM `private-access` WHERE id = ?")?;
+
+            stmt.bind((1, id))?;
+            stmt.next()?;
+
+            Ok(updated)
+        })
+    }
+
+    /// Allow a node to fetch a private repository.
+    pub fn allow_private(&mut self, id: &RepoId, nid: &NodeId) -> Result<bool, Error> {
+        self.write(|db| {
+            let mut stmt = db.prepare(
+                "INSERT INTO `private-access` (id, node) VALUES (?1, ?2) ON CONFLICT DO NOTHING",
+            )?;
+
+            stmt.bind((1, id))?;
+            stmt.bind((2, nid))?;
+            stmt.next()?;
+
+            Ok(db.change_count() > 0)
+        })
+    }
+
+    /// Set whether a node is allowed to fetch a repository. Once a node is allowed to
+    /// fetch a repository, nodes that aren't explicitly allowed can no longer fetch it.
+    pub fn set_fetcher_policy(
+        &mut self,
+        id: &RepoId,
+        nid: &NodeId,
+        policy: Policy,
+    ) -> Result<bool, Error> {
+        self.write(|db| {
+            let mut stmt = db.prepare(
+                "INSERT INTO `fetchers` (id, node, policy)
+                 VALUES (?1, ?2, ?3)
+                 ON CONFLICT DO UPDATE
+                 SET policy = ?3 WHERE policy != ?3",
+            )?;
+
+            stmt.bind((1, id))?;
+            stmt.bind((2, nid))?;
+            stmt.bind((3, policy))?;
+            stmt.next()?;
+
+            Ok(db.change_count() > 0)
+        })
+    }
+
+    /// Remove the fetching policy of a node for a repository.
+    pub fn unset_fetcher_policy(&mut self, id: &RepoId, nid: &NodeId) -> Result<bool, Error> {
+        self.write(|db| {
+            let mut stmt = db.prepare("DELETE FROM `fetchers` WHERE id = ?1 AND node = ?2")?;
+
+            stmt.bind((1, id))?;
+            stmt.bind((2, nid))?;
+            stmt.next()?;
+
+            Ok(db.change_count() > 0)
+        })
+    }
+
+    /// Set the categories of references fetched for a repository. Setting the default,
+    /// ie. fetching all references, removes the repository's entry.
+    pub fn set_ref_categories(
+        &mut self,
+        id: &RepoId,
+        categories: RefCategories,
+    ) -> Result<bool, Error> {
+        self.write(|db| {
+            if categories == RefCategories::default() {
+                let mut stmt = db.prepare("DELETE FROM `ref-categories` WHERE id = ?")?;
+
+                stmt.bind((1, id))?;
+                stmt.next()?;
+
+                return Ok(db.change_count() > 0);
+            }
+            let RefCategories {
+                heads,
+                tags,
+                notes,
+                cobs,
+            } = categories;
+            let mut stmt = db.prepare(
+                "INSERT INTO `ref-categories` (id, heads, tags, notes, cobs)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT DO UPDATE
+                 SET heads = ?2, tags = ?3, notes = ?4, cobs = ?5
+                 WHERE heads != ?2 OR tags != ?3 OR notes != ?4 OR cobs != ?5",
+            )?;
+
+            stmt.bind((1, id))?;
+            stmt.bind((2, heads as i64))?;
+            stmt.bind((3, tags as i64))?;
+            stmt.bind((4, notes as i64))?;
+            stmt.bind((5, cobs as i64))?;
+            stmt.next()?;
+
+            Ok(db.change_count() > 0)
+        })
+    }
+
+    /// Stop allowing a node to fetch a private repository.
+    pub fn disallow_private(&mut self, id: &RepoId, nid: &NodeId) -> Result<bool, Error> {
+        self.write(|db| {
+            let mut stmt =
+                db.prepare("DELETE FROM `private-access` WHERE id = ?1 AND node = ?2")?;
+
+            stmt.bind((1, id))?;
+            stmt.bind((2, nid))?;
+            stmt.next()?;
+
+            Ok(db.change_count() > 0)
+        })
+    }
+
+    /// Run a write query, as part of the current transaction if there is one, or in a
+    /// transaction of its own otherwise.
+    fn write<T>(
+        &mut self,
+        mut query: impl FnMut(&sql::Connection) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        self.transaction(|store| query(&store.db))
     }
 }
 
+/// Run `f` in a write transaction of the store reached through `state`. See
+/// [`Store::transaction`].
+pub(super) fn transaction<S, T>(
+    state: &mut S,
+    store: impl Fn(&mut S) -> &mut Store<Write>,
+    mut f: impl FnMut(&mut S) -> Result<T, Error>,
+) -> Result<T, Error> {
+    if store(state).writing {
+        return f(state);
+    }
+    let mut attempt = 1;
+
+    loop {
+        match try_transaction(state, &store, &mut f) {
+            Err(e) if e.is_busy() && attempt < DB_WRITE_ATTEMPTS => {
+                warn!(
+                    target: "node",
+                    "Policy database is busy, retrying write ({attempt}/{DB_WRITE_ATTEMPTS})"
+                );
+                thread::sleep(DB_WRITE_BACKOFF * attempt);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Make one attempt at running `f` in a write transaction.
+fn try_transaction<S, T>(
+    state: &mut S,
+    store: &impl Fn(&mut S) -> &mut Store<Write>,
+    f: &mut impl FnMut(&mut S) -> Result<T, Error>,
+) -> Result<T, Error> {
+    store(state).db.execute("BEGIN IMMEDIATE")?;
+    store(state).writing = true;
+
+    let result = f(state).and_then(|value| {
+        store(state).db.execute("COMMIT")?;
+        Ok(value)
+    });
+    let store = store(state);
+    store.writing = false;
+
+    if result.is_err() {
+        // Nb. SQLite may have rolled back the transaction already, in which case this fails.
+        store.db.execute("ROLLBACK").ok();
+    }
+    result
+}
+
 /// `Read` methods for `Config`. This implies that a
 /// `Config<Write>` can access these functions as well.
 impl<T> Store<T> {
@@ -225,6 +484,114 @@ impl<T> Store<T> {
         ))
     }
 
+    /// Check if a repository is private.
+    pub fn is_private(&self, id: &RepoId) -> Result<bool, Error> {
+        let mut stmt = self.db.prepare("SELECT 1 FROM `private` WHERE id = ?")?;
+
+        stmt.bind((1, id))?;
+
+        Ok(matches!(stmt.next()?, sql::State::Row))
+    }
+
+    /// Check if a repository can be served to a node. Public repositories can be served
+    /// to any node, while private repositories can only be served to allowed nodes.
+    pub fn is_served_to(&self, id: &RepoId, nid: &NodeId) -> Result<bool, Error> {
+        if !self.is_private(id)? {
+            return Ok(true);
+        }
+        let mut stmt = self
+            .db
+            .prepare("SELECT 1 FROM `private-access` WHERE id = ?1 AND node = ?2")?;
+
+        stmt.bind((1, id))?;
+        stmt.bind((2, nid))?;
+
+        Ok(matches!(stmt.next()?, sql::State::Row))
+    }
+
+    /// Check if a node may fetch a repository, according to the repository's fetching
+    /// policies. Nodes that are blocked may not fetch it, and if some nodes are allowed,
+    /// only those nodes may fetch it.
+    pub fn is_fetch_allowed(&self, id: &RepoId, nid: &NodeId) -> Result<bool, Error> {
+        let mut allowed = None;
+
+        for entry in self.fetcher_policies(id)? {
+            match entry.policy {
+                Policy::Allow if entry.nid == *nid => return Ok(true),
+                Policy::Block if entry.nid == *nid => return Ok(false),
+                Policy::Allow => allowed = Some(false),
+                Policy::Block => {}
+            }
+        }
+        Ok(allowed.unwrap_or(true))
+    }
+
+    /// Check if only some nodes may fetch a repository, ie. if its fetching policies allow
+    /// any node. Such repositories can't be served to clients that aren't authenticated.
+    pub fn is_fetch_restricted(&self, id: &RepoId) -> Result<bool, Error> {
+        Ok(self
+            .fetcher_policies(id)?
+            .iter()
+            .any(|entry| entry.policy == Policy::Allow))
+    }
+
+    /// Get the fetching policies of a repository.
+    pub fn fetcher_policies(&self, id: &RepoId) -> Result<Vec<FetcherPolicy>, Error> {
+        let mut stmt = self
+            .db
+            .prepare("SELECT node, policy FROM `fetchers` WHERE id = ?")?;
+
+        stmt.bind((1, id))?;
+
+        let mut entries = Vec::new();
+        for row in stmt.into_iter() {
+            let row = row?;
+
+            entries.push(FetcherPolicy {
+                rid: *id,
+                nid: row.read::<NodeId, _>("node"),
+                policy: row.read::<Policy, _>("policy"),
+            });
+        }
+        Ok(entries)
+    }
+
+    /// Get the categories of references fetched for a repository.
+    pub fn ref_categories(&self, id: &RepoId) -> Result<RefCategories, Error> {
+        let mut stmt = self
+            .db
+            .prepare("SELECT heads, tags, notes, cobs FROM `ref-categories` WHERE id = ?")?;
+
+        stmt.bind((1, id))?;
+
+        if let Some(row) = stmt.into_iter().next() {
+            let row = row?;
+
+            return Ok(RefCategories {
+                heads: row.read::<i64, _>("heads") != 0,
+                tags: row.read::<i64, _>("tags") != 0,
+                notes: row.read::<i64, _>("notes") != 0,
+                cobs: row.read::<i64, _>("cobs") != 0,
+            });
+        }
+        Ok(RefCategories::default())
+    }
+
+    /// Get the nodes allowed to fetch a private repository.
+    pub fn private_access(&self, id: &RepoId) -> Result<Vec<NodeId>, Error> {
+        let mut stmt = self
+            .db
+            .prepare("SELECT node FROM `private-access` WHERE id = ?")?;
+
+        stmt.bind((1, id))?;
+
+        let mut nodes = Vec::new();
+        for row in stmt.into_iter() {
+            nodes.push(row?.read::<NodeId, _>("node"));
+        }
+        Ok(nodes)
+    }
+
     /// Get a node's follow policy.
     pub fn follow_policy(&self, id: &NodeId) -> Result<Option<FollowPolicy>, Error> {
         let mut stmt = self
@@ -332,6 +699,8 @@ impl<T> AliasStore for Store<T> {
 
 #[cfg(test)]
 mod test {
+    use std::thread;
+
     use crate::assert_matches;
 
     use super::*;
@@ -431,6 +800,120 @@ mod test {
         assert_eq!(db.seed_policy(&id).unwrap().unwrap().policy, Policy::Block);
     }
 
+    #[test]
+    fn test_private_repo() {
+        let id = arbitrary::gen::<RepoId>(1);
+        let alice = arbitrary::gen::<NodeId>(1);
+        let bob = arbitrary::gen::<NodeId>(2);
+        let mut db = Store::open(":memory:").unwrap();
+
+        assert!(!db.is_private(&id).unwrap());
+        assert!(db.is_served_to(&id, &alice).unwrap());
+
+        assert!(db.set_private(&id, true).unwrap());
+        assert!(!db.set_private(&id, true).unwrap());
+        assert!(db.is_private(&id).unwrap());
+        assert!(!db.is_served_to(&id, &alice).unwrap());
+
+        assert!(db.allow_private(&id, &alice).unwrap());
+        assert!(!db.allow_private(&id, &alice).unwrap());
+        assert!(db.is_served_to(&id, &alice).unwrap());
+        assert!(!db.is_served_to(&id, &bob).unwrap());
+        assert_eq!(db.private_access(&id).unwrap(), vec![alice]);
+
+        assert!(db.disallow_private(&id, &alice).unwrap());
+        assert!(!db.is_served_to(&id, &alice).unwrap());
+
+        assert!(db.allow_private(&id, &bob).unwrap());
+        assert!(db.set_private(&id, false).unwrap());
+        assert!(db.is_served_to(&id, &alice).unwrap());
+        assert!(db.private_access(&id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_fetcher_policy() {
+        let id = arbitrary::gen::<RepoId>(1);
+        let alice = arbitrary::gen::<NodeId>(1);
+        let bob = arbitrary::gen::<NodeId>(2);
+        let eve = arbitrary::gen::<NodeId>(3);
+        let mut db = Store::open(":memory:").unwrap();
+
+        assert!(db.is_fetch_allowed(&id, &alice).unwrap());
+
+        assert!(db.set_fetcher_policy(&id, &eve, Policy::Block).unwrap());
+        assert!(!db.set_fetcher_policy(&id, &eve, Policy::Block).unwrap());
+        assert!(!db.is_fetch_allowed(&id, &eve).unwrap());
+        assert!(db.is_fetch_allowed(&id, &alice).unwrap());
+        assert!(!db.is_fetch_restricted(&id).unwrap());
+
+        // Once a node is allowed, others can no longer fetch.
+        assert!(db.set_fetcher_policy(&id, &alice, Policy::Allow).unwrap());
+        assert!(db.is_fetch_restricted(&id).unwrap());
+        assert!(db.is_fetch_allowed(&id, &alice).unwrap());
+        assert!(!db.is_fetch_allowed(&id, &bob).unwrap());
+        assert_eq!(db.fetcher_policies(&id).unwrap().len(), 2);
+
+        assert!(db.unset_fetcher_policy(&id, &alice).unwrap());
+        assert!(!db.unset_fetcher_policy(&id, &alice).unwrap());
+        assert!(db.is_fetch_allowed(&id, &bob).unwrap());
+        assert!(!db.is_fetch_allowed(&id, &eve).unwrap());
+        assert!(!db.is_fetch_restricted(&id).unwrap());
+    }
+
+    #[test]
+    fn test_ref_categories() {
+        let all = RefCategories::default();
+        let none = RefCategories {
+            heads: false,
+            tags: false,
+            notes: false,
+            cobs: false,
+        };
+        let refnames = [
+            "refs/heads/master",
+            "refs/tags/v1.0",
+            "refs/notes/commits",
+            "refs/cobs/xyz.radicle.issue/a1b2",
+        ];
+        for refname in refnames {
+            assert!(all.is_fetched(refname));
+            assert!(!none.is_fetched(refname));
+        }
+        for refname in [
+            "refs/rad/id",
+            "refs/rad/sigrefs",
+            "refs/cobs/xyz.radicle.id/a1b2",
+            "refs/heads",
+        ] {
+            assert!(none.is_fetched(refname));
+        }
+        assert!(!none.is_fetched("refs/cobs/xyz.radicle.identity/a1b2"));
+
+        // Repositories have all their references fetched, unless configured otherwise.
+        let id = arbitrary::gen::<RepoId>(1);
+        let mut db = Store::open(":memory:").unwrap();
+        let code = RefCategories {
+            tags: false,
+            notes: false,
+            ..RefCategories::default()
+        };
+        assert_eq!(db.ref_categories(&id).unwrap(), all);
+
+        assert!(db.set_ref_categories(&id, code).unwrap());
+        assert!(!db.set_ref_categories(&id, code).unwrap());
+        assert_eq!(db.ref_categories(&id).unwrap(), code);
+        assert_eq!(
+            db.ref_categories(&arbitrary::gen::<RepoId>(2)).unwrap(),
+            all
+        );
+
+        assert!(db.set_ref_categories(&id, none).unwrap());
+        assert_eq!(db.ref_categories(&id).unwrap(), none);
+        assert!(db.set_ref_categories(&id, all).unwrap());
+        assert!(!db.set_ref_categories(&id, all).unwrap());
+        assert_eq!(db.ref_categories(&id).unwrap(), all);
+    }
+
     #[test]
     fn test_node_policy() {
         let id = arbitrary::gen::<NodeId>(1);
@@ -447,4 +930,100 @@ mod test {
             Policy::Block
         );
     }
+
+    #[test]
+    fn test_transaction() {
+        let ids = arbitrary::vec::<RepoId>(2);
+        let mut db = Store::open(":memory:").unwrap();
+
+        // Failed transactions are rolled back.
+        let result = db.transaction(|db| {
+            db.seed(&ids[0], Scope::All)?;
+            db.set_private(&ids[0], true)?;
+            Err::<(), _>(Error::Io(io::ErrorKind::Other.into()))
+        });
+        assert!(result.is_err());
+        assert!(!db.is_seeding(&ids[0]).unwrap());
+        assert!(!db.is_private(&ids[0]).unwrap());
+
+        // Nested transactions are part of the outer transaction.
+        db.transaction(|db| {
+            db.seed(&ids[0], Scope::All)?;
+            db.transaction(|db| db.seed(&ids[1], Scope::All))?;
+            db.set_private(&ids[1], true)
+        })
+        .unwrap();
+        assert!(db.is_seeding(&ids[0]).unwrap());
+        assert!(db.is_seeding(&ids[1]).unwrap());
+        assert!(db.is_private(&ids[1]).unwrap());
+    }
+
+    #[test]
+    fn test_concurrent_writes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("policies.db");
+        let ids = arbitrary::vec::<RepoId>(64);
+        let writers = ids
+            .chunks(16)
+            .map(|ids| {
+                let mut db = Store::open(&path).unwrap();
+                let ids = ids.to_vec();
+
+                thread::spawn(move || {
+                    for id in ids {
+                        assert!(db.seed(&id, Scope::All).unwrap());
+                        assert!(db.set_private(&id, true).unwrap());
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        let db = Store::reader(&path).unwrap();
+        assert_eq!(db.seed_policies().unwrap().count(), ids.len());
+
+        for id in &ids {
+            assert!(db.is_private(id).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_busy_retry() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("policies.db");
+        let first = arbitrary::gen::<RepoId>(1);
+        let second = arbitrary::gen::<RepoId>(2);
+        let mut alice = Store::open(&path).unwrap();
+        let mut bob = Store::open(&path).unwrap();
+        let (locked, ready) = std::sync::mpsc::channel();
+
+        // Don't wait for the lock, so that Bob's first attempt fails.
+        bob.db.set_busy_timeout(0).unwrap();
+
+        let writer = thread::spawn(move || {
+            alice
+                .transaction(|db| {
+                    db.seed(&first, Scope::All)?;
+                    locked.send(()).ok();
+                    thread::sleep(DB_WRITE_BACKOFF * 2);
+
+                    Ok(())
+                })
+                .unwrap();
+        });
+        ready.recv().unwrap();
+
+        let err = bob
+            .db
+            .execute("BEGIN IMMEDIATE")
+            .map_err(Error::from)
+            .unwrap_err();
+        assert!(err.is_busy());
+        assert!(bob.seed(&second, Scope::All).unwrap());
+
+        writer.join().unwrap();
+        assert_eq!(bob.seed_policies().unwrap().count(), 2);
+    }
 }
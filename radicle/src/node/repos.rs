@@ -0,0 +1,103 @@
+//! Stored repositories and their sync status.
+use serde::{Deserialize, Serialize};
+use sqlite as sql;
+use thiserror::Error;
+
+use crate::node::policy::{Policy, Scope};
+use crate::node::Database;
+use crate::prelude::{RepoId, Timestamp};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    /// An Internal error.
+    #[error("internal error: {0}")]
+    Internal(#[from] sql::Error),
+}
+
+/// A stored repository, with its sync status.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Repo {
+    /// Repository ID.
+    pub rid: RepoId,
+    /// Seeding policy.
+    pub policy: Policy,
+    /// Seeding scope.
+    pub scope: Scope,
+    /// Number of remote namespaces, including our own.
+    pub namespaces: usize,
+    /// When the repository was last fetched successfully, if ever.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fetched: Option<Timestamp>,
+    /// When the repository's refs were last announced, by any node, as far as we know.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub announced: Option<Timestamp>,
+    /// Number of seeds known to be in sync with our local refs.
+    pub replicas: usize,
+    /// Whether our local refs are known to be replicated to at least as many seeds as
+    /// the configured replication factor. Always `false` if we don't have local refs.
+    pub replicated: bool,
+}
+
+/// Repository fetch store.
+///
+/// Keeps track of when repositories were last fetched successfully.
+pub trait Store {
+    /// Record a successful fetch of a repository.
+    fn set_last_fetch(&mut self, rid: &RepoId, timestamp: Timestamp) -> Result<(), Error>;
+    /// Get when a repository was last fetched successfully.
+    fn last_fetch(&self, rid: &RepoId) -> Result<Option<Timestamp>, Error>;
+}
+
+impl Store for Database {
+    fn set_last_fetch(&mut self, rid: &RepoId, timestamp: Timestamp) -> Result<(), Error> {
+        let mut stmt = self.db.prepare(
+            "INSERT INTO `repo-fetches` (repo, timestamp)
+             VALUES (?1, ?2)
+             ON CONFLICT DO UPDATE
+             SET timestamp = ?2
+             WHERE timestamp < ?2",
+        )?;
+        stmt.bind((1, rid))?;
+        stmt.bind((2, &timestamp))?;
+        stmt.next()?;
+
+        Ok(())
+    }
+
+    fn last_fetch(&self, rid: &RepoId) -> Result<Option<Timestamp>, Error> {
+        let mut stmt = self
+            .db
+            .prepare("SELECT timestamp FROM `repo-fetches` WHERE repo = ?1")?;
+        stmt.bind((1, rid))?;
+
+        if let Some(Ok(row)) = stmt.into_iter().next() {
+            return Ok(Some(row.try_read::<Timestamp, _>("timestamp")?));
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+    use crate::test::arbitrary;
+
+    #[test]
+    fn test_last_fetch() {
+        let mut db = Database::memory().unwrap();
+        let rid = arbitrary::gen::<RepoId>(1);
+
+        assert_eq!(db.last_fetch(&rid).unwrap(), None);
+
+        db.set_last_fetch(&rid, Timestamp::from(2_000)).unwrap();
+        assert_eq!(db.last_fetch(&rid).unwrap(), Some(Timestamp::from(2_000)));
+
+        db.set_last_fetch(&rid, Timestamp::from(1_000)).unwrap();
+        assert_eq!(db.last_fetch(&rid).unwrap(), Some(Timestamp::from(2_000)));
+
+        db.set_last_fetch(&rid, Timestamp::from(3_000)).unwrap();
+        assert_eq!(db.last_fetch(&rid).unwrap(), Some(Timestamp::from(3_000)));
+    }
+}
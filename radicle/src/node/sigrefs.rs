@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use sqlite as sql;
+use thiserror::Error;
+
+use crate::git::Oid;
+use crate::node::Database;
+use crate::prelude::{NodeId, RepoId, Timestamp};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    /// An Internal error.
+    #[error("internal error: {0}")]
+    Internal(#[from] sql::Error),
+    /// Invalid object ID.
+    #[error("invalid oid '{0}'")]
+    Oid(String),
+}
+
+/// How a remote's fetched `rad/sigrefs` deviate from the tip we last fetched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Deviation {
+    /// The fetched tip is an ancestor of the last fetched tip, ie. the history was rewound.
+    Rewound,
+    /// The fetched tip doesn't share the history of the last fetched tip.
+    Diverged,
+}
+
+/// A fetched `rad/sigrefs` tip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tip {
+    /// Tip of the remote's `rad/sigrefs`.
+    pub oid: Oid,
+    /// When the tip was fetched.
+    pub timestamp: Timestamp,
+}
+
+/// Sigref tips store.
+///
+/// Keeps the last fetched `rad/sigrefs` tip of each remote, so that fetched tips can be
+/// checked against it, even if the reference was since removed from storage.
+pub trait Store {
+    /// Get the last fetched `rad/sigrefs` tip of a remote.
+    fn tip(&self, rid: &RepoId, remote: &NodeId) -> Result<Option<Tip>, Error>;
+    /// Get the last fetched `rad/sigrefs` tips of all remotes of a repository.
+    fn tips(&self, rid: &RepoId) -> Result<BTreeMap<NodeId, Oid>, Error>;
+    /// Record a fetched `rad/sigrefs` tip of a remote. Returns `true` if it changed.
+    fn set_tip(&mut self, rid: &RepoId, remote: &NodeId, tip: Tip) -> Result<bool, Error>;
+}
+
+impl Store for Database {
+    fn tip(&self, rid: &RepoId, remote: &NodeId) -> Result<Option<Tip>, Error> {
+        let mut stmt = self
+            .db
+            .prepare("SELECT oid, timestamp FROM `sigref-tips` WHERE repo = ?1 AND remote = ?2")?;
+        stmt.bind((1, rid))?;
+        stmt.bind((2, remote))?;
+
+        if let Some(Ok(row)) = stmt.into_iter().next() {
+            let oid = row.try_read::<&str, _>("oid")?;
+            let oid = Oid::from_str(oid).map_err(|_| Error::Oid(oid.to_owned()))?;
+            let timestamp = row.try_read::<Timestamp, _>("timestamp")?;
+
+            return Ok(Some(Tip { oid, timestamp }));
+        }
+        Ok(None)
+    }
+
+    fn tips(&self, rid: &RepoId) -> Result<BTreeMap<NodeId, Oid>, Error> {
+        let mut stmt = self
+            .db
+            .prepare("SELECT remote, oid FROM `sigref-tips` WHERE repo = ?1")?;
+        stmt.bind((1, rid))?;
+
+        let mut tips = BTreeMap::new();
+        for row in stmt.into_iter() {
+            let row = row?;
+            let remote = row.try_read::<NodeId, _>("remote")?;
+            let oid = row.try_read::<&str, _>("oid")?;
+            let oid = Oid::from_str(oid).map_err(|_| Error::Oid(oid.to_owned()))?;
+
+            tips.insert(remote, oid);
+        }
+        Ok(tips)
+    }
+
+    fn set_tip(&mut self, rid: &RepoId, remote: &NodeId, tip: Tip) -> Result<bool, Error> {
+        let mut stmt = self.db.prepare(
+            "INSERT INTO `sigref-tips` (repo, remote, oid, timestamp)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT DO UPDATE
+             SET oid = ?3, timestamp = ?4
+             WHERE oid <> ?3",
+        )?;
+        stmt.bind((1, rid))?;
+        stmt.bind((2, remote))?;
+        stmt.bind((3, tip.oid.to_string().as_str()))?;
+        stmt.bind((4, &tip.timestamp))?;
+        stmt.next()?;
+
+        Ok(self.db.change_count() > 0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::arbitrary;
+
+    #[test]
+    fn test_tips() {
+        let mut db = Database::memory().unwrap();
+        let rid = arbitrary::gen::<RepoId>(1);
+        let alice = arbitrary::gen::<NodeId>(1);
+        let bob = arbitrary::gen::<NodeId>(1);
+        let first = Tip {
+            oid: arbitrary::oid(),
+            timestamp: Timestamp::from(1_000),
+        };
+        let second = Tip {
+            oid: arbitrary::oid(),
+            timestamp: Timestamp::from(2_000),
+        };
+
+        assert_eq!(db.tip(&rid, &alice).unwrap(), None);
+        assert!(db.set_tip(&rid, &alice, first).unwrap());
+        assert!(!db.set_tip(&rid, &alice, first).unwrap());
+        assert_eq!(db.tip(&rid, &alice).unwrap(), Some(first));
+
+        assert!(db.set_tip(&rid, &alice, second).unwrap());
+        assert!(db.set_tip(&rid, &bob, first).unwrap());
+        assert_eq!(db.tip(&rid, &alice).unwrap(), Some(second));
+        assert_eq!(
+            db.tips(&rid).unwrap(),
+            BTreeMap::from([(alice, second.oid), (bob, first.oid)])
+        );
+        assert!(db.tips(&arbitrary::gen::<RepoId>(1)).unwrap().is_empty());
+    }
+}
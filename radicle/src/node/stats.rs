@@ -0,0 +1,129 @@
+//! Cached repository statistics.
+//!
+//! Statistics are computed by [`crate::storage::git::stats`] after repositories are fetched,
+//! and cached here so that they can be queried without walking the object database.
+use serde_json as json;
+use sqlite as sql;
+use thiserror::Error;
+
+use crate::node::Database;
+use crate::prelude::RepoId;
+pub use crate::storage::git::stats::Stats;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    /// An Internal error.
+    #[error("internal error: {0}")]
+    Internal(#[from] sql::Error),
+    /// Invalid cached statistics.
+    #[error("invalid statistics: {0}")]
+    Json(#[from] json::Error),
+}
+
+/// Repository statistics store.
+pub trait Store {
+    /// Cache the statistics of a repository, replacing older ones.
+    fn set_stats(&mut self, stats: &Stats) -> Result<(), Error>;
+    /// Get the cached statistics of a repository.
+    fn stats(&self, rid: &RepoId) -> Result<Option<Stats>, Error>;
+    /// Get the cached statistics of all repositories, largest first.
+    fn all_stats(&self) -> Result<Vec<Stats>, Error>;
+}
+
+impl Store for Database {
+    fn set_stats(&mut self, stats: &Stats) -> Result<(), Error> {
+        let mut stmt = self.db.prepare(
+            "INSERT INTO `repo-stats` (repo, stats, timestamp)
+             VALUES (?1, ?2, ?3)
+             ON CONFLICT DO UPDATE
+             SET stats = ?2, timestamp = ?3
+             WHERE timestamp <= ?3",
+        )?;
+        stmt.bind((1, &stats.rid))?;
+        stmt.bind((2, json::to_string(stats)?.as_str()))?;
+        stmt.bind((3, &stats.timestamp))?;
+        stmt.next()?;
+
+        Ok(())
+    }
+
+    fn stats(&self, rid: &RepoId) -> Result<Option<Stats>, Error> {
+        let mut stmt = self
+            .db
+            .prepare("SELECT stats FROM `repo-stats` WHERE repo = ?1")?;
+        stmt.bind((1, rid))?;
+
+        if let Some(Ok(row)) = stmt.into_iter().next() {
+            let stats = row.try_read::<&str, _>("stats")?;
+
+            return Ok(Some(json::from_str(stats)?));
+        }
+        Ok(None)
+    }
+
+    fn all_stats(&self) -> Result<Vec<Stats>, Error> {
+        let stmt = self.db.prepare("SELECT stats FROM `repo-stats`")?;
+
+        let mut all = Vec::new();
+        for row in stmt.into_iter() {
+            let row = row?;
+            let stats = row.try_read::<&str, _>("stats")?;
+
+            all.push(json::from_str::<Stats>(stats)?);
+        }
+        all.sort_by_key(|s| std::cmp::Reverse(s.size()));
+
+        Ok(all)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::prelude::{NodeId, Timestamp};
+    use crate::storage::git::stats::Blob;
+    use crate::test::arbitrary;
+
+    fn stats(rid: RepoId, packed: u64, timestamp: u64) -> Stats {
+        Stats {
+            rid,
+            packs: 1,
+            packed,
+            loose: 0,
+            loose_size: 0,
+            refs: BTreeMap::from([(arbitrary::gen::<NodeId>(1), 3)]),
+            largest: vec![Blob {
+                size: 42,
+                oid: arbitrary::oid(),
+            }],
+            timestamp: Timestamp::from(timestamp),
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut db = Database::memory().unwrap();
+        let first = arbitrary::gen::<RepoId>(1);
+        let second = arbitrary::gen::<RepoId>(1);
+
+        assert_eq!(db.stats(&first).unwrap(), None);
+
+        let old = stats(first, 1024, 1_000);
+        let new = stats(first, 2048, 2_000);
+        db.set_stats(&old).unwrap();
+        assert_eq!(db.stats(&first).unwrap(), Some(old.clone()));
+        db.set_stats(&new).unwrap();
+        assert_eq!(db.stats(&first).unwrap(), Some(new.clone()));
+
+        // Older statistics don't replace newer ones.
+        db.set_stats(&old).unwrap();
+        assert_eq!(db.stats(&first).unwrap(), Some(new.clone()));
+
+        let other = stats(second, 4096, 1_000);
+        db.set_stats(&other).unwrap();
+        assert_eq!(db.all_stats().unwrap(), vec![other, new]);
+    }
+}
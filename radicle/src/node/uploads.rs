@@ -0,0 +1,174 @@
+use std::num::TryFromIntError;
+
+use serde::{Deserialize, Serialize};
+use sqlite as sql;
+use thiserror::Error;
+
+use crate::node::Database;
+use crate::prelude::{NodeId, RepoId, Timestamp};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    /// An Internal error.
+    #[error("internal error: {0}")]
+    Internal(#[from] sql::Error),
+    /// Unit overflow.
+    #[error("unit overflow: {0}")]
+    UnitOverflow(#[from] TryFromIntError),
+}
+
+/// A fetch served to a remote node, ie. an upload.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Upload {
+    /// Repository fetched.
+    pub rid: RepoId,
+    /// Node that fetched the repository.
+    pub nid: NodeId,
+    /// When the upload finished.
+    pub timestamp: Timestamp,
+    /// Number of bytes sent.
+    pub sent: u64,
+    /// Time taken to serve the fetch, in milliseconds.
+    pub elapsed: u64,
+    /// CPU time used by the `git upload-pack` process, in milliseconds. Not set if no
+    /// process was spawned, eg. for blob transfers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<u64>,
+    /// Error that caused the upload to fail, if it failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Upload log.
+///
+/// Keeps track of who fetched which repository from us, so that seed operators can see
+/// what their bandwidth is used for.
+pub trait Store {
+    /// Record a served fetch.
+    fn uploaded(&mut self, upload: &Upload) -> Result<(), Error>;
+    /// Get the served fetches, optionally only of the given repository, most recent first.
+    fn uploads(&self, rid: Option<&RepoId>) -> Result<Vec<Upload>, Error>;
+    /// Remove uploads older than the given time, and the oldest uploads in excess of
+    /// `max_size`. Returns the number of entries removed.
+    fn prune_uploads(&mut self, oldest: &Timestamp, max_size: usize) -> Result<usize, Error>;
+}
+
+impl Store for Database {
+    fn uploaded(&mut self, upload: &Upload) -> Result<(), Error> {
+        let mut stmt = self.db.prepare(
+            "INSERT INTO `uploads` (repo, node, timestamp, sent, elapsed, cpu, error)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        )?;
+        stmt.bind((1, &upload.rid))?;
+        stmt.bind((2, &upload.nid))?;
+        stmt.bind((3, &upload.timestamp))?;
+        stmt.bind((4, i64::try_from(upload.sent)?))?;
+        stmt.bind((5, i64::try_from(upload.elapsed)?))?;
+        stmt.bind((6, upload.cpu.map(i64::try_from).transpose()?))?;
+        stmt.bind((7, upload.error.as_deref()))?;
+        stmt.next()?;
+
+        Ok(())
+    }
+
+    fn uploads(&self, rid: Option<&RepoId>) -> Result<Vec<Upload>, Error> {
+        let mut stmt = self.db.prepare(
+            "SELECT repo, node, timestamp, sent, elapsed, cpu, error FROM `uploads`
+             WHERE ?1 IS NULL OR repo = ?1
+             ORDER BY timestamp DESC, rowid DESC",
+        )?;
+        stmt.bind((1, rid.map(|rid| rid.to_string()).as_deref()))?;
+
+        let mut uploads = Vec::new();
+        for row in stmt.into_iter() {
+            let row = row?;
+
+            uploads.push(Upload {
+                rid: row.try_read::<RepoId, _>("repo")?,
+                nid: row.try_read::<NodeId, _>("node")?,
+                timestamp: row.try_read::<Timestamp, _>("timestamp")?,
+                sent: u64::try_from(row.try_read::<i64, _>("sent")?)?,
+                elapsed: u64::try_from(row.try_read::<i64, _>("elapsed")?)?,
+                cpu: row
+                    .try_read::<Option<i64>, _>("cpu")?
+                    .map(u64::try_from)
+                    .transpose()?,
+                error: row
+                    .try_read::<Option<&str>, _>("error")?
+                    .map(ToOwned::to_owned),
+            });
+        }
+        Ok(uploads)
+    }
+
+    fn prune_uploads(&mut self, oldest: &Timestamp, max_size: usize) -> Result<usize, Error> {
+        let mut stmt = self.db.prepare(
+            "DELETE FROM `uploads` WHERE timestamp < ?1 OR rowid NOT IN (
+                SELECT rowid FROM `uploads` ORDER BY timestamp DESC, rowid DESC LIMIT ?2
+             )",
+        )?;
+        stmt.bind((1, oldest))?;
+        stmt.bind((2, i64::try_from(max_size)?))?;
+        stmt.next()?;
+
+        Ok(self.db.change_count())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::arbitrary;
+
+    #[test]
+    fn test_uploads() {
+        let mut db = Database::memory().unwrap();
+        let rid = arbitrary::gen::<RepoId>(1);
+        let other = arbitrary::gen::<RepoId>(1);
+        let alice = arbitrary::gen::<NodeId>(1);
+        let upload = |rid: RepoId, timestamp: u64| Upload {
+            rid,
+            nid: alice,
+            timestamp: Timestamp::from(timestamp),
+            sent: 1024,
+            elapsed: 300,
+            cpu: Some(120),
+            error: None,
+        };
+
+        assert!(db.uploads(None).unwrap().is_empty());
+
+        db.uploaded(&upload(rid, 1_000)).unwrap();
+        db.uploaded(&upload(other, 2_000)).unwrap();
+        db.uploaded(&Upload {
+            cpu: None,
+            error: Some(String::from("unauthorized")),
+            ..upload(rid, 3_000)
+        })
+        .unwrap();
+
+        let uploads = db.uploads(None).unwrap();
+        assert_eq!(uploads.len(), 3);
+        assert_eq!(uploads[0].timestamp, Timestamp::from(3_000));
+        assert_eq!(uploads[0].error.as_deref(), Some("unauthorized"));
+        assert_eq!(uploads[0].cpu, None);
+        assert_eq!(uploads[1].cpu, Some(120));
+        assert_eq!(db.uploads(Some(&rid)).unwrap().len(), 2);
+        assert_eq!(
+            db.uploads(Some(&other)).unwrap(),
+            vec![upload(other, 2_000)]
+        );
+
+        // Nothing is old enough, and all entries fit.
+        assert_eq!(db.prune_uploads(&Timestamp::from(1_000), 3).unwrap(), 0);
+        // The oldest entry is too old.
+        assert_eq!(db.prune_uploads(&Timestamp::from(2_000), 3).unwrap(), 1);
+        // Only the most recent entry fits.
+        assert_eq!(db.prune_uploads(&Timestamp::from(0), 1).unwrap(), 1);
+        assert_eq!(
+            db.uploads(None).unwrap()[0].timestamp,
+            Timestamp::from(3_000)
+        );
+    }
+}
@@ -41,6 +41,10 @@ pub enum InitError {
     Io(#[from] io::Error),
     #[error("storage: {0}")]
     Storage(#[from] storage::Error),
+    #[error("branch `{0}` was not found in the repository")]
+    BranchNotFound(BranchName),
+    #[error("the repository has no default branch, `HEAD` is detached or unborn")]
+    NoDefaultBranch,
 }
 
 /// Initialize a new radicle project from a git repository.
@@ -55,21 +59,7 @@ pub fn init<G: Signer, S: WriteStorage>(
 ) -> Result<(RepoId, identity::Doc<Verified>, SignedRefs<Verified>), InitError> {
     // TODO: Better error when project id already exists in storage, but remote doesn't.
     let pk = signer.public_key();
-    let delegate = identity::Did::from(*pk);
-    let proj = Project::new(
-        name.to_owned(),
-        description.to_owned(),
-        default_branch.clone(),
-    )
-    .map_err(|errs| {
-        InitError::ProjectPayload(
-            errs.into_iter()
-                .map(|err| err.to_string())
-                .collect::<Vec<_>>()
-                .join(", "),
-        )
-    })?;
-    let doc = identity::Doc::initial(proj, delegate, visibility).verified()?;
+    let doc = initial_doc(name, description, default_branch.clone(), visibility, pk)?;
     let (project, _) = Repository::init(&doc, &storage, signer)?;
     let url = git::Url::from(project.id);
 
@@ -92,6 +82,101 @@ pub fn init<G: Signer, S: WriteStorage>(
     }
 }
 
+/// Import an existing git repository into storage as a new radicle project, with the
+/// signer as its only delegate.
+///
+/// Unlike [`init`], the repository is left untouched: its branches and tags are copied
+/// to the signer's namespace, but no `rad` remote is configured. If no default branch is
+/// given, the branch `HEAD` points to is used.
+pub fn import<G: Signer, S: WriteStorage>(
+    repo: &git2::Repository,
+    name: &str,
+    description: &str,
+    default_branch: Option<BranchName>,
+    visibility: Visibility,
+    signer: &G,
+    storage: S,
+) -> Result<(RepoId, identity::Doc<Verified>, SignedRefs<Verified>), InitError> {
+    let default_branch = match default_branch {
+        Some(branch) => branch,
+        None => {
+            let head = repo.head().map_err(|_| InitError::NoDefaultBranch)?;
+            if !head.is_branch() {
+                return Err(InitError::NoDefaultBranch);
+            }
+            let name = head.shorthand().ok_or(InitError::NoDefaultBranch)?;
+
+            BranchName::try_from(name).map_err(|_| InitError::NoDefaultBranch)?
+        }
+    };
+    if repo
+        .find_branch(default_branch.as_str(), git2::BranchType::Local)
+        .is_err()
+    {
+        return Err(InitError::BranchNotFound(default_branch));
+    }
+    let pk = signer.public_key();
+    let doc = initial_doc(name, description, default_branch, visibility, pk)?;
+    let (project, _) = Repository::init(&doc, &storage, signer)?;
+
+    storage.insert(project.id);
+
+    match import_refs(repo, &project, pk, signer) {
+        Ok(signed) => Ok((project.id, doc, signed)),
+        Err(err) => {
+            if let Err(e) = project.remove() {
+                log::warn!(target: "radicle", "Failed to remove project during `rad::import` cleanup: {e}");
+            }
+            Err(err)
+        }
+    }
+}
+
+fn initial_doc(
+    name: &str,
+    description: &str,
+    default_branch: BranchName,
+    visibility: Visibility,
+    pk: &crypto::PublicKey,
+) -> Result<identity::Doc<Verified>, InitError> {
+    let delegate = identity::Did::from(*pk);
+    let proj =
+        Project::new(name.to_owned(), description.to_owned(), default_branch).map_err(|errs| {
+            InitError::ProjectPayload(
+                errs.into_iter()
+                    .map(|err| err.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            )
+        })?;
+    identity::Doc::initial(proj, delegate, visibility)
+        .verified()
+        .map_err(InitError::from)
+}
+
+fn import_refs<G>(
+    repo: &git2::Repository,
+    project: &Repository,
+    pk: &crypto::PublicKey,
+    signer: &G,
+) -> Result<SignedRefs<Verified>, InitError>
+where
+    G: crypto::Signer,
+{
+    let path = repo.path().to_string_lossy();
+    let mut remote = project.raw().remote_anonymous(&path)?;
+    let refspecs =
+        ["heads", "tags"].map(|kind| format!("+refs/{kind}/*:refs/namespaces/{pk}/refs/{kind}/*"));
+
+    remote.fetch(&refspecs, None, None)?;
+
+    let signed = project.sign_refs(signer)?;
+    let _head = project.set_identity_head()?;
+    let _head = project.set_head()?;
+
+    Ok(signed)
+}
+
 fn init_configure<G>(
     repo: &git2::Repository,
     project: &Repository,
@@ -452,6 +537,58 @@ mod tests {
         assert_eq!(doc.delegates.first(), &Did::from(public_key));
     }
 
+    #[test]
+    fn test_import() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let signer = MockSigner::default();
+        let public_key = *signer.public_key();
+        let storage = Storage::open(tempdir.path().join("storage"), fixtures::user()).unwrap();
+
+        let (repo, oid) = fixtures::repository(tempdir.path().join("working"));
+        repo.tag_lightweight("v1", &repo.find_object(oid, None).unwrap(), false)
+            .unwrap();
+
+        assert!(matches!(
+            import(
+                &repo,
+                "acme",
+                "",
+                Some(git::refname!("main")),
+                Visibility::default(),
+                &signer,
+                &storage,
+            ),
+            Err(InitError::BranchNotFound(_))
+        ));
+
+        let (proj, _, refs) = import(
+            &repo,
+            "acme",
+            "Acme's repo",
+            None,
+            Visibility::default(),
+            &signer,
+            &storage,
+        )
+        .unwrap();
+
+        let doc = storage.get(proj).unwrap().unwrap();
+        let project_repo = storage.repository(proj).unwrap();
+        let (_, head) = project_repo.head().unwrap();
+
+        assert_eq!(
+            doc.project().unwrap().default_branch(),
+            &git::refname!("master")
+        );
+        assert_eq!(head, oid.into());
+        assert_eq!(refs.head(component!("master")).unwrap(), head);
+        assert_eq!(refs.get(&qualified!("refs/tags/v1")).unwrap(), oid.into());
+        assert_eq!(doc.delegates.first(), &Did::from(public_key));
+        assert!(storage.contains(&proj).unwrap());
+        // The working copy isn't modified.
+        assert!(repo.find_remote(&REMOTE_NAME).is_err());
+    }
+
     #[test]
     fn test_fork() {
         let mut rng = fastrand::Rng::new();
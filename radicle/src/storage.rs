@@ -1,5 +1,8 @@
+pub mod blobs;
+pub mod cache;
 pub mod git;
 pub mod refs;
+pub mod updates;
 
 use std::collections::{hash_map, BTreeSet, HashSet};
 use std::ops::Deref;
@@ -163,6 +166,21 @@ impl Error {
             _ => false,
         }
     }
+
+    /// Get the kind of error, as reported to node clients.
+    pub fn kind(&self) -> crate::node::ErrorKind {
+        use crate::node::ErrorKind;
+
+        if self.is_not_found() {
+            return ErrorKind::NotFound;
+        }
+        match self {
+            Self::Io(e) | Self::Inventory(e) => ErrorKind::from_io(e),
+            Self::InvalidRef | Self::InvalidId(_) => ErrorKind::InvalidInput,
+            Self::Doc(_) | Self::Refs(_) => ErrorKind::ValidationFailed,
+            _ => ErrorKind::Other,
+        }
+    }
 }
 
 /// Fetch error.
@@ -590,6 +608,11 @@ pub trait ReadRepository: Sized + ValidateRepository {
     /// Get the repository's identity document at a specific commit.
     fn identity_doc_at(&self, head: Oid) -> Result<DocAt, DocError>;
 
+    /// Get the read cache shared with the other repositories of this storage, if any.
+    fn cache(&self) -> Option<&cache::Cache> {
+        None
+    }
+
     /// Get the merge base of two commits.
     fn merge_base(&self, left: &Oid, right: &Oid) -> Result<Oid, git::ext::Error>;
 }
@@ -0,0 +1,232 @@
+//! Content-addressed storage for large files.
+//!
+//! Large files are kept outside of Git packs, so that they don't have to be
+//! indexed and delta-compressed on every fetch. Repositories reference them by
+//! hash, using refs of the form `refs/blobs/<oid>` in their namespaces, where
+//! `<oid>` is the Git blob hash of the file contents, ie. what
+//! `git hash-object <file>` outputs.
+//!
+//! Blobs are stored in the `blobs` directory of the repository they belong to.
+//! Blobs that are being downloaded are written to a `.partial` file alongside,
+//! which is only moved into place once its hash is verified. This allows
+//! interrupted downloads to be resumed from where they stopped.
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::git::Oid;
+
+/// Name of the directory blobs are stored in, under the repository path.
+pub const BLOBS_DIR: &str = "blobs";
+/// Extension of blobs that are still being downloaded.
+pub const PARTIAL_EXT: &str = "partial";
+/// Glob matching the blob references of all namespaces.
+pub const BLOB_REFS_GLOB: &str = "refs/namespaces/*/refs/blobs/*";
+/// Size of the chunks blobs are transferred in.
+pub const CHUNK_SIZE: usize = 256 * 1024;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    /// An I/O error.
+    #[error("i/o error: {0}")]
+    Io(#[from] io::Error),
+    /// A Git error.
+    #[error("git: {0}")]
+    Git(#[from] git2::Error),
+    /// The downloaded blob doesn't match its hash.
+    #[error("blob hash mismatch: expected {expected}, got {actual}")]
+    Mismatch { expected: Oid, actual: Oid },
+}
+
+/// Blob store of a repository.
+#[derive(Debug, Clone)]
+pub struct Blobs {
+    path: PathBuf,
+}
+
+impl Blobs {
+    /// Open the blob store at the given path. The directory is created lazily.
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The path of the blob store.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Check whether a blob is fully stored.
+    pub fn contains(&self, oid: &Oid) -> bool {
+        self.blob_path(oid).is_file()
+    }
+
+    /// Open a stored blob for reading. Returns the file and its size, or `None` if the
+    /// blob isn't stored.
+    pub fn get(&self, oid: &Oid) -> Result<Option<(File, u64)>, Error> {
+        match File::open(self.blob_path(oid)) {
+            Ok(file) => {
+                let size = file.metadata()?.len();
+                Ok(Some((file, size)))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Store the contents of a reader as a blob, returning its hash.
+    pub fn put(&self, mut reader: impl io::Read) -> Result<Oid, Error> {
+        fs::create_dir_all(&self.path)?;
+
+        let mut tmp = tempfile::NamedTempFile::new_in(&self.path)?;
+        io::copy(&mut reader, &mut tmp)?;
+        tmp.as_file().sync_all()?;
+
+        let oid = Oid::from(git2::Oid::hash_file(git2::ObjectType::Blob, tmp.path())?);
+        tmp.persist(self.blob_path(&oid)).map_err(|e| e.error)?;
+
+        Ok(oid)
+    }
+
+    /// Start or resume downloading a blob.
+    pub fn download(&self, oid: Oid) -> Result<Partial, Error> {
+        fs::create_dir_all(&self.path)?;
+
+        let path = self.blob_path(&oid).with_extension(PARTIAL_EXT);
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
+        let offset = file.metadata()?.len();
+
+        Ok(Partial {
+            oid,
+            file,
+            offset,
+            path,
+            target: self.blob_path(&oid),
+        })
+    }
+
+    /// Path of a stored blob.
+    fn blob_path(&self, oid: &Oid) -> PathBuf {
+        self.path.join(oid.to_string())
+    }
+}
+
+/// A blob being downloaded.
+#[derive(Debug)]
+pub struct Partial {
+    oid: Oid,
+    file: File,
+    offset: u64,
+    path: PathBuf,
+    target: PathBuf,
+}
+
+impl Partial {
+    /// Number of bytes already downloaded.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Append a chunk of the blob. Chunks are synced to disk, so that they survive
+    /// a crash and the download can be resumed after it.
+    pub fn write(&mut self, chunk: &[u8]) -> Result<(), Error> {
+        self.file.write_all(chunk)?;
+        self.file.sync_data()?;
+        self.offset += chunk.len() as u64;
+
+        Ok(())
+    }
+
+    /// Verify the downloaded blob and move it into place. If it doesn't match its hash,
+    /// it is discarded, and the next download starts over.
+    pub fn finish(self) -> Result<(), Error> {
+        let actual = Oid::from(git2::Oid::hash_file(git2::ObjectType::Blob, &self.path)?);
+        if actual != self.oid {
+            fs::remove_file(&self.path)?;
+
+            return Err(Error::Mismatch {
+                expected: self.oid,
+                actual,
+            });
+        }
+        fs::rename(&self.path, &self.target)?;
+
+        Ok(())
+    }
+
+    /// Discard the downloaded data.
+    pub fn discard(self) -> Result<(), Error> {
+        fs::remove_file(self.path)?;
+
+        Ok(())
+    }
+}
+
+/// Get the blobs referenced by any namespace of a repository.
+pub fn referenced(repo: &git2::Repository) -> Result<BTreeSet<Oid>, git2::Error> {
+    let mut blobs = BTreeSet::new();
+
+    for r in repo.references_glob(BLOB_REFS_GLOB)? {
+        let r = r?;
+        let Some(name) = r.name() else {
+            continue;
+        };
+        let Some((_, oid)) = name.rsplit_once('/') else {
+            continue;
+        };
+        if let Ok(oid) = oid.parse::<Oid>() {
+            blobs.insert(oid);
+        }
+    }
+    Ok(blobs)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_blobs_download_resume() {
+        let tmp = tempfile::tempdir().unwrap();
+        let blobs = Blobs::open(tmp.path().join(BLOBS_DIR));
+        let data = vec![7u8; CHUNK_SIZE + 1024];
+        let oid = Oid::from(git2::Oid::hash_object(git2::ObjectType::Blob, &data).unwrap());
+
+        assert!(!blobs.contains(&oid));
+        assert!(blobs.get(&oid).unwrap().is_none());
+
+        // Download part of the blob, then resume where we stopped.
+        let mut partial = blobs.download(oid).unwrap();
+        assert_eq!(partial.offset(), 0);
+        partial.write(&data[..CHUNK_SIZE]).unwrap();
+        drop(partial);
+
+        let mut partial = blobs.download(oid).unwrap();
+        assert_eq!(partial.offset(), CHUNK_SIZE as u64);
+        partial.write(&data[CHUNK_SIZE..]).unwrap();
+        partial.finish().unwrap();
+
+        assert!(blobs.contains(&oid));
+        assert_eq!(blobs.get(&oid).unwrap().unwrap().1, data.len() as u64);
+        assert_eq!(blobs.put(data.as_slice()).unwrap(), oid);
+    }
+
+    #[test]
+    fn test_blobs_mismatch() {
+        let tmp = tempfile::tempdir().unwrap();
+        let blobs = Blobs::open(tmp.path().join(BLOBS_DIR));
+        let oid = Oid::from(git2::Oid::hash_object(git2::ObjectType::Blob, b"hello").unwrap());
+
+        let mut partial = blobs.download(oid).unwrap();
+        partial.write(b"world").unwrap();
+        assert!(matches!(partial.finish(), Err(Error::Mismatch { .. })));
+        assert!(!blobs.contains(&oid));
+        assert_eq!(blobs.download(oid).unwrap().offset(), 0);
+    }
+}
@@ -0,0 +1,153 @@
+//! In-memory cache of verified signed refs and identity documents.
+//!
+//! Busy seeds load the same signed refs and identity documents over and over, eg. to answer
+//! announcements and upload-pack requests. Since both are addressed by the commit they were
+//! loaded from, verified copies can be kept in memory and shared by all the repositories of a
+//! storage. Entries of a remote are evicted when its references are updated, since older tips
+//! are unlikely to be asked for again.
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crypto::Verified;
+use lru::LruCache;
+
+use crate::identity::{DocAt, RepoId};
+use crate::storage::refs::SignedRefs;
+use crate::storage::{Oid, RemoteId};
+
+/// Default number of entries kept, for each kind of entry.
+pub const DEFAULT_CAPACITY: NonZeroUsize = match NonZeroUsize::new(1024) {
+    Some(n) => n,
+    None => unreachable!(),
+};
+
+/// Cache hit and miss counts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// Lookups that were answered from the cache.
+    pub hits: usize,
+    /// Lookups that had to go to disk.
+    pub misses: usize,
+}
+
+#[derive(Debug)]
+struct Inner {
+    /// Signed refs, keyed by repository, remote and `rad/sigrefs` commit.
+    sigrefs: LruCache<(RepoId, RemoteId, Oid), SignedRefs<Verified>>,
+    /// Identity documents, keyed by repository and identity commit.
+    docs: LruCache<(RepoId, Oid), DocAt>,
+    stats: Stats,
+}
+
+/// Read cache, shared by clones.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl Cache {
+    /// Create a new cache holding up to `capacity` signed refs and `capacity` documents.
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                sigrefs: LruCache::new(capacity),
+                docs: LruCache::new(capacity),
+                stats: Stats::default(),
+            })),
+        }
+    }
+
+    /// Get the signed refs of a remote at the given `rad/sigrefs` commit.
+    pub fn sigrefs(&self, rid: RepoId, remote: RemoteId, at: Oid) -> Option<SignedRefs<Verified>> {
+        let mut inner = self.lock();
+        let refs = inner.sigrefs.get(&(rid, remote, at)).cloned();
+
+        inner.stats.record(refs.is_some());
+        refs
+    }
+
+    /// Cache the signed refs of a remote, loaded from the given `rad/sigrefs` commit.
+    pub fn insert_sigrefs(&self, rid: RepoId, at: Oid, refs: SignedRefs<Verified>) {
+        self.lock().sigrefs.put((rid, refs.id, at), refs);
+    }
+
+    /// Get the identity document at the given commit.
+    pub fn doc(&self, rid: RepoId, at: Oid) -> Option<DocAt> {
+        let mut inner = self.lock();
+        let doc = inner.docs.get(&(rid, at)).cloned();
+
+        inner.stats.record(doc.is_some());
+        doc
+    }
+
+    /// Cache the identity document at the given commit.
+    pub fn insert_doc(&self, rid: RepoId, doc: DocAt) {
+        self.lock().docs.put((rid, doc.commit), doc);
+    }
+
+    /// Evict the signed refs of a remote, eg. after its references were updated.
+    pub fn invalidate_remote(&self, rid: &RepoId, remote: &RemoteId) {
+        let mut inner = self.lock();
+        let stale = inner
+            .sigrefs
+            .iter()
+            .map(|(k, _)| *k)
+            .filter(|(r, n, _)| r == rid && n == remote)
+            .collect::<Vec<_>>();
+
+        for key in stale {
+            inner.sigrefs.pop(&key);
+        }
+    }
+
+    /// Evict all entries of a repository, eg. after it was removed.
+    pub fn invalidate(&self, rid: &RepoId) {
+        let mut inner = self.lock();
+        let sigrefs = inner
+            .sigrefs
+            .iter()
+            .map(|(k, _)| *k)
+            .filter(|(r, _, _)| r == rid)
+            .collect::<Vec<_>>();
+        let docs = inner
+            .docs
+            .iter()
+            .map(|(k, _)| *k)
+            .filter(|(r, _)| r == rid)
+            .collect::<Vec<_>>();
+
+        for key in sigrefs {
+            inner.sigrefs.pop(&key);
+        }
+        for key in docs {
+            inner.docs.pop(&key);
+        }
+    }
+
+    /// Get the cache hit and miss counts.
+    pub fn stats(&self) -> Stats {
+        self.lock().stats
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Stats {
+    fn record(&mut self, hit: bool) {
+        if hit {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+    }
+}
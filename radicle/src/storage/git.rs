@@ -1,6 +1,9 @@
 #![warn(clippy::unwrap_used)]
+pub mod bundle;
 pub mod cob;
+pub mod stats;
 pub mod transport;
+pub mod verify;
 
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::ops::{Deref, DerefMut};
@@ -9,6 +12,7 @@ use std::sync::{Arc, Mutex};
 use std::{fs, io};
 
 use crypto::{Signer, Verified};
+use localtime::LocalTime;
 use once_cell::sync::Lazy;
 use tempfile::TempDir;
 
@@ -29,6 +33,7 @@ pub use crate::git::{
 };
 pub use crate::storage::Error;
 
+use super::cache::Cache;
 use super::refs::RefsAt;
 use super::{RemoteId, RemoteRepository, ValidateRepository};
 
@@ -78,6 +83,7 @@ pub struct Storage {
     info: UserInfo,
     /// Inventory cache. Set to `None` until the cache is populated.
     inventory: Arc<Mutex<Option<BTreeSet<RepoId>>>>,
+    cache: Cache,
 }
 
 impl ReadStorage for Storage {
@@ -134,31 +140,13 @@ impl ReadStorage for Storage {
 
     fn repository(&self, rid: RepoId) -> Result<Self::Repository, RepositoryError> {
         Repository::open(paths::repository(self, &rid), rid)
+            .map(|r| r.with_cache(self.cache.clone()))
     }
 
     fn repositories(&self) -> Result<Vec<RepositoryInfo<Verified>>, Error> {
         let mut repos = Vec::new();
 
-        for result in fs::read_dir(&self.path)? {
-            let path = result?;
-
-            // Skip non-directories.
-            if !path.file_type()?.is_dir() {
-                continue;
-            }
-            // Skip hidden files.
-            if path.file_name().to_string_lossy().starts_with('.') {
-                continue;
-            }
-            // Skip lock files.
-            if let Some(ext) = path.path().extension() {
-                if ext == "lock" {
-                    continue;
-                }
-            }
-            let rid = RepoId::try_from(path.file_name())
-                .map_err(|_| Error::InvalidId(path.file_name()))?;
-
+        for rid in self.repository_ids()? {
             let repo = match self.repository(rid) {
                 Ok(repo) => repo,
                 Err(e) => {
@@ -201,10 +189,12 @@ impl WriteStorage for Storage {
 
     fn repository_mut(&self, rid: RepoId) -> Result<Self::RepositoryMut, RepositoryError> {
         Repository::open(paths::repository(self, &rid), rid)
+            .map(|r| r.with_cache(self.cache.clone()))
     }
 
     fn create(&self, rid: RepoId) -> Result<Self::RepositoryMut, Error> {
         Repository::create(paths::repository(self, &rid), rid, &self.info)
+            .map(|r| r.with_cache(self.cache.clone()))
     }
 
     fn clean(&self, rid: RepoId) -> Result<Vec<RemoteId>, RepositoryError> {
@@ -236,9 +226,15 @@ impl Storage {
             path,
             info,
             inventory: Default::default(),
+            cache: Cache::default(),
         })
     }
 
+    /// Get the read cache shared by this storage's repositories.
+    pub fn cache(&self) -> &Cache {
+        &self.cache
+    }
+
     /// Create a [`Repository`] in a temporary directory.
     ///
     /// N.b. it is important to keep the [`TempDir`] in scope while
@@ -264,6 +260,35 @@ impl Storage {
         self.path.as_path()
     }
 
+    /// Get the IDs of all stored repositories, without checking the repositories.
+    pub fn repository_ids(&self) -> Result<Vec<RepoId>, Error> {
+        let mut rids = Vec::new();
+
+        for result in fs::read_dir(&self.path)? {
+            let path = result?;
+
+            // Skip non-directories.
+            if !path.file_type()?.is_dir() {
+                continue;
+            }
+            // Skip hidden files.
+            if path.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            // Skip lock files.
+            if let Some(ext) = path.path().extension() {
+                if ext == "lock" {
+                    continue;
+                }
+            }
+            let rid = RepoId::try_from(path.file_name())
+                .map_err(|_| Error::InvalidId(path.file_name()))?;
+
+            rids.push(rid);
+        }
+        Ok(rids)
+    }
+
     pub fn repositories_by_id<'a>(
         &self,
         mut rids: impl Iterator<Item = &'a RepoId>,
@@ -298,7 +323,30 @@ impl Storage {
         Ok(())
     }
 
-    fn public_repositories(&self) -> Result<impl Iterator<Item = RepoId>, Error> {
+    /// Migrate the signer's signed refs to the given version of the signed refs format,
+    /// in all repositories where they use an older version. Returns the migrated repositories.
+    pub fn migrate_sigrefs<G: Signer>(
+        &self,
+        signer: &G,
+        version: refs::Version,
+    ) -> Result<Vec<RepoId>, RepositoryError> {
+        let mut migrated = Vec::new();
+
+        for rid in self.repository_ids()? {
+            let repo = self.repository(rid)?;
+            let Some(current) = SignedRefsAt::load(*signer.public_key(), &repo)? else {
+                continue;
+            };
+            if current.sigrefs.refs.version() < version {
+                repo.sign_refs_with(signer, version)?;
+                migrated.push(rid);
+            }
+        }
+        Ok(migrated)
+    }
+
+    /// Get the identifiers of the public repositories in storage.
+    pub fn public_repositories(&self) -> Result<impl Iterator<Item = RepoId>, Error> {
         let repos = self.repositories()?;
         Ok(repos
             .into_iter()
@@ -313,6 +361,8 @@ pub struct Repository {
     pub id: RepoId,
     /// The backing Git repository.
     pub backend: git2::Repository,
+    /// Read cache, shared with the other repositories of the storage.
+    cache: Option<Cache>,
 }
 
 /// A set of [`Validation`] errors that a caller **must use**.
@@ -369,14 +419,33 @@ pub enum Validation {
     },
     #[error("missing `refs/namespaces/{0}/refs/rad/sigrefs`")]
     MissingRadSigRefs(RemoteId),
+    #[error("`refs/namespaces/{remote}/HEAD` points to unsigned ref `{target}`")]
+    DanglingHead { remote: RemoteId, target: RefString },
+    #[error("`refs/namespaces/{remote}/HEAD` points directly to {oid}, instead of a signed ref")]
+    DirectHead { remote: RemoteId, oid: Oid },
+    #[error("`refs/namespaces/{remote}` signs {count} refs, exceeding the limit of {limit}")]
+    TooManyRefs {
+        remote: RemoteId,
+        count: usize,
+        limit: usize,
+    },
 }
 
 impl Repository {
+    /// Get the store of large files referenced by this repository.
+    pub fn blobs(&self) -> super::blobs::Blobs {
+        super::blobs::Blobs::open(self.backend.path().join(super::blobs::BLOBS_DIR))
+    }
+
     /// Open an existing repository.
     pub fn open<P: AsRef<Path>>(path: P, id: RepoId) -> Result<Self, RepositoryError> {
         let backend = git2::Repository::open_bare(path.as_ref())?;
 
-        Ok(Self { id, backend })
+        Ok(Self {
+            id,
+            backend,
+            cache: None,
+        })
     }
 
     /// Create a new repository.
@@ -393,7 +462,17 @@ impl Repository {
         config.set_str("user.name", &info.name())?;
         config.set_str("user.email", &info.email())?;
 
-        Ok(Self { id, backend })
+        Ok(Self {
+            id,
+            backend,
+            cache: None,
+        })
+    }
+
+    /// Use the given read cache for signed refs and identity documents.
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
     }
 
     /// Remove an existing repository
@@ -402,6 +481,24 @@ impl Repository {
         if path.exists() {
             fs::remove_dir_all(path)?;
         }
+        if let Some(cache) = &self.cache {
+            cache.invalidate(&self.id);
+        }
+        Ok(())
+    }
+
+    /// Remove all the references of a remote, including its signed refs.
+    pub fn remove_remote(&self, remote: &RemoteId) -> Result<(), Error> {
+        let glob = git::refname!("refs/namespaces")
+            .join(git::Component::from(remote))
+            .with_pattern(git::refspec::STAR);
+
+        for (refname, _) in self.references_glob(&glob)? {
+            self.backend.find_reference(refname.as_str())?.delete()?;
+        }
+        if let Some(cache) = &self.cache {
+            cache.invalidate_remote(&self.id, remote);
+        }
         Ok(())
     }
 
@@ -451,6 +548,9 @@ impl Repository {
                     log::error!(target: "storage", "Failed to clean up reference '{refname}'");
                 }
             }
+            if let Some(cache) = &self.cache {
+                cache.invalidate_remote(&self.id, &id);
+            }
             deleted.push(id);
         }
 
@@ -619,6 +719,10 @@ impl ValidateRepository for Repository {
             });
         }
 
+        if let Some(head) = validate_head(&self.backend, remote)? {
+            failures.push(head);
+        }
+
         // Nb. As it stands, it doesn't make sense to verify a single remote's identity branch,
         // since it is a COB.
 
@@ -710,7 +814,6 @@ impl ReadRepository for Repository {
             let e = e?;
             let name = e.name().ok_or(Error::InvalidRef)?;
             let (_, refname) = git::parse_ref::<RemoteId>(name)?;
-            let oid = e.resolve()?.target().ok_or(Error::InvalidRef)?;
             let (_, category, _, _) = refname.non_empty_components();
 
             if [
@@ -722,6 +825,7 @@ impl ReadRepository for Repository {
             ]
             .contains(&category.as_ref())
             {
+                let oid = e.resolve()?.target().ok_or(Error::InvalidRef)?;
                 refs.insert(refname.into(), oid.into());
             }
         }
@@ -750,7 +854,20 @@ impl ReadRepository for Repository {
     }
 
     fn identity_doc_at(&self, head: Oid) -> Result<DocAt, DocError> {
-        Doc::<Verified>::load_at(head, self)
+        let Some(cache) = &self.cache else {
+            return Doc::<Verified>::load_at(head, self);
+        };
+        if let Some(doc) = cache.doc(self.id, head) {
+            return Ok(doc);
+        }
+        let doc = Doc::<Verified>::load_at(head, self)?;
+        cache.insert_doc(self.id, doc.clone());
+
+        Ok(doc)
+    }
+
+    fn cache(&self) -> Option<&Cache> {
+        self.cache.as_ref()
     }
 
     fn head(&self) -> Result<(Qualified, Oid), RepositoryError> {
@@ -906,14 +1023,28 @@ impl WriteRepository for Repository {
     }
 }
 
-impl SignRepository for Repository {
-    fn sign_refs<G: Signer>(&self, signer: &G) -> Result<SignedRefs<Verified>, Error> {
+impl Repository {
+    /// Sign the repository's refs using the given version of the signed refs format.
+    ///
+    /// Since [`SignRepository::sign_refs`] keeps the version of the existing signed refs,
+    /// this is how signed refs are migrated to a newer version.
+    pub fn sign_refs_with<G: Signer>(
+        &self,
+        signer: &G,
+        version: refs::Version,
+    ) -> Result<SignedRefs<Verified>, Error> {
         let remote = signer.public_key();
         let mut refs = self.references_of(remote)?;
         // Don't sign the `rad/sigrefs` ref itself, and don't sign invalid OIDs.
         refs.retain(|name, oid| {
             name.as_refstr() != refs::SIGREFS_BRANCH.as_ref() && !oid.is_zero()
         });
+        if version >= refs::Version::V2 {
+            let previous = SignedRefsAt::load(*remote, self)?
+                .map(|r| r.sigrefs.refs)
+                .unwrap_or_default();
+            refs = refs.with_history(&previous, LocalTime::now().into());
+        }
         let signed = refs.signed(signer)?;
 
         signed.save(self)?;
@@ -922,6 +1053,50 @@ impl SignRepository for Repository {
     }
 }
 
+impl SignRepository for Repository {
+    fn sign_refs<G: Signer>(&self, signer: &G) -> Result<SignedRefs<Verified>, Error> {
+        let version = SignedRefsAt::load(*signer.public_key(), self)?
+            .map(|r| r.sigrefs.refs.version())
+            .unwrap_or_default();
+
+        self.sign_refs_with(signer, version)
+    }
+}
+
+/// Validate the `HEAD` of a remote's namespace against its signed refs. A namespace
+/// doesn't need a `HEAD`, but if it has one, it must be a symbolic reference to one of
+/// the signed refs.
+pub fn validate_head(
+    repo: &git2::Repository,
+    remote: &Remote<Verified>,
+) -> Result<Option<Validation>, Error> {
+    let namespace = format!("refs/namespaces/{}/", remote.id);
+    let head = match repo.find_reference(&format!("{namespace}HEAD")) {
+        Ok(head) => head,
+        Err(e) if git::is_not_found_err(&e) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let Some(target) = head.symbolic_target() else {
+        let oid = head.target().ok_or(Error::InvalidRef)?;
+
+        return Ok(Some(Validation::DirectHead {
+            remote: remote.id,
+            oid: oid.into(),
+        }));
+    };
+    let target = target.strip_prefix(namespace.as_str()).unwrap_or(target);
+    let target = RefString::try_from(target).map_err(|_| Error::InvalidRef)?;
+
+    if remote.refs.contains_key(&target) {
+        Ok(None)
+    } else {
+        Ok(Some(Validation::DanglingHead {
+            remote: remote.id,
+            target,
+        }))
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum QuorumError {
     #[error("no quorum was found")]
@@ -1340,6 +1515,131 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_read_cache() {
+        let tmp = tempfile::tempdir().unwrap();
+        let signer = MockSigner::default();
+        let alice = *signer.public_key();
+        let storage = Storage::open(tmp.path().join("storage"), fixtures::user()).unwrap();
+        let (rid, _, working, _) =
+            fixtures::project(tmp.path().join("project"), &storage, &signer).unwrap();
+        // Start with an empty cache.
+        let storage = Storage::open(storage.path(), fixtures::user()).unwrap();
+        let repo = storage.repository(rid).unwrap();
+
+        // Loading twice only reads from disk once.
+        let doc = repo.identity_doc().unwrap();
+        assert_eq!(repo.identity_doc().unwrap(), doc);
+        let refs = SignedRefs::load(alice, &repo).unwrap();
+        assert_eq!(SignedRefs::load(alice, &repo).unwrap(), refs);
+
+        let stats = storage.cache().stats();
+        assert_eq!(stats.hits, 2);
+        assert_eq!(stats.misses, 2);
+
+        // Updating our refs evicts the old entry, and the new refs are loaded.
+        let head = working.head().unwrap().peel_to_commit().unwrap();
+        let branch = git::refs::storage::branch_of(&alice, &git::refname!("feature"));
+        repo.raw()
+            .reference(branch.as_str(), head.id(), false, "test")
+            .unwrap();
+        let updated = repo.sign_refs(&signer).unwrap();
+
+        assert_ne!(updated, refs);
+        assert_eq!(SignedRefs::load(alice, &repo).unwrap(), updated);
+        assert_eq!(storage.cache().stats().misses, stats.misses + 1);
+    }
+
+    #[test]
+    fn test_external_ref_updates() {
+        let tmp = tempfile::tempdir().unwrap();
+        let signer = MockSigner::default();
+        let alice = *signer.public_key();
+        let storage = Storage::open(tmp.path().join("storage"), fixtures::user()).unwrap();
+        let (rid, _, _, _) =
+            fixtures::project(tmp.path().join("project"), &storage, &signer).unwrap();
+        // A long-lived handle, eg. held by a worker, and one used by another process.
+        let repo = storage.repository(rid).unwrap();
+        let other = git2::Repository::open(repo.path()).unwrap();
+        let pack = || {
+            let status = std::process::Command::new("git")
+                .current_dir(repo.path())
+                .args(["pack-refs", "--all"])
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        let master = git::refs::storage::branch_of(&alice, &git::refname!("master"));
+        let feature = git::refs::storage::branch_of(&alice, &git::refname!("feature"));
+        let head = repo.backend.refname_to_id(master.as_str()).unwrap();
+        let id = repo.identity_head().unwrap();
+
+        // References written and packed by the other process are seen.
+        other
+            .reference(feature.as_str(), head, false, "test")
+            .unwrap();
+        pack();
+        assert_eq!(repo.backend.refname_to_id(feature.as_str()).unwrap(), head);
+
+        // Packed references updated by the other process are seen.
+        other.reference(master.as_str(), *id, true, "test").unwrap();
+        pack();
+        assert_eq!(repo.backend.refname_to_id(master.as_str()).unwrap(), *id);
+
+        // Packed references deleted by the other process are gone.
+        other
+            .find_reference(feature.as_str())
+            .unwrap()
+            .delete()
+            .unwrap();
+        assert!(repo.backend.find_reference(feature.as_str()).is_err());
+    }
+
+    #[test]
+    fn test_sigrefs_migration() {
+        let tmp = tempfile::tempdir().unwrap();
+        let signer = MockSigner::default();
+        let alice = *signer.public_key();
+        let storage = Storage::open(tmp.path().join("storage"), fixtures::user()).unwrap();
+        let (rid, refs, working, _) =
+            fixtures::project(tmp.path().join("project"), &storage, &signer).unwrap();
+        let repo = storage.repository(rid).unwrap();
+        let head = working.head().unwrap().peel_to_commit().unwrap();
+        let feature = git::refname!("refs/heads/feature");
+        let branch = git::refs::storage::branch_of(&alice, &git::refname!("feature"));
+
+        assert_eq!(refs.refs.version(), refs::Version::V1);
+        assert_eq!(
+            storage.migrate_sigrefs(&signer, refs::Version::V2).unwrap(),
+            vec![rid]
+        );
+        assert_eq!(
+            storage.migrate_sigrefs(&signer, refs::Version::V2).unwrap(),
+            vec![]
+        );
+
+        // Signing keeps the version, and records deletions.
+        repo.raw()
+            .reference(branch.as_str(), head.id(), false, "test")
+            .unwrap();
+        let signed = repo.sign_refs(&signer).unwrap();
+        assert_eq!(signed.refs.version(), refs::Version::V2);
+        assert!(signed.refs.updated_at(&feature).is_some());
+
+        repo.raw()
+            .find_reference(branch.as_str())
+            .unwrap()
+            .delete()
+            .unwrap();
+        let signed = repo.sign_refs(&signer).unwrap();
+        assert!(signed.refs.is_deleted(&feature));
+        assert_eq!(SignedRefs::load(alice, &repo).unwrap(), signed);
+        assert!(repo
+            .validate_remote(&repo.remote(&alice).unwrap())
+            .unwrap()
+            .is_empty());
+    }
+
     #[test]
     fn test_sign_refs() {
         let tmp = tempfile::tempdir().unwrap();
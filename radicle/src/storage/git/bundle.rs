@@ -0,0 +1,271 @@
+//! Repository bundles.
+//!
+//! A bundle is a single file holding a stored repository, with all its namespaces, refs and
+//! identity. Bundles can be carried over to another node and imported there, eg. for
+//! backups, or to seed a repository without network access.
+//!
+//! Bundles use the `git bundle` format, and are created and read with the `git` CLI.
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::{fs, io};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::crypto::Unverified;
+use crate::git;
+use crate::identity::doc::{Doc, DocError};
+use crate::identity::RepoId;
+use crate::storage::refs;
+use crate::storage::{ReadRepository, ReadStorage, RemoteId, RepositoryError, WriteRepository};
+
+use super::verify::{self, Failure};
+use super::{Repository, Storage};
+
+/// Prefix of temporary directories used to import bundles.
+/// Starts with a `.`, so that it isn't taken for a repository.
+const IMPORT_PREFIX: &str = ".import";
+
+/// Bundle error.
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("i/o: {0}")]
+    Io(#[from] io::Error),
+    #[error("git: {0}")]
+    Git(#[from] git::raw::Error),
+    #[error("storage: {0}")]
+    Storage(#[from] super::Error),
+    #[error("repository: {0}")]
+    Repository(#[from] RepositoryError),
+    #[error("identity doc: {0}")]
+    Doc(#[from] DocError),
+    #[error(transparent)]
+    Refs(#[from] refs::Error),
+    #[error("repository {0} was not found")]
+    NotFound(RepoId),
+    #[error("repository {0} already exists")]
+    Exists(RepoId),
+    #[error("bundle failed verification with {} failure(s)", .0.len())]
+    Verification(Vec<Failure>),
+    #[error("git {0}: {1}")]
+    Command(&'static str, String),
+}
+
+/// A repository imported from a bundle.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Imported {
+    /// Repository imported.
+    pub rid: RepoId,
+    /// Namespaces imported.
+    pub remotes: Vec<RemoteId>,
+}
+
+/// Export a stored repository to a bundle file at the given path.
+pub fn export(storage: &Storage, rid: RepoId, path: &Path) -> Result<(), Error> {
+    if !storage.contains(&rid)? {
+        return Err(Error::NotFound(rid));
+    }
+    let path = absolute(path)?;
+
+    git(
+        "bundle",
+        storage.path_of(&rid),
+        [
+            "bundle".as_ref(),
+            "create".as_ref(),
+            path.as_os_str(),
+            "--all".as_ref(),
+        ],
+    )
+}
+
+/// Import a repository from a bundle file.
+///
+/// The bundle is first unpacked into a temporary repository, and only moved into storage if
+/// its identity and signed refs verify, and all its objects are present. Repositories that are
+/// already stored are not overwritten.
+pub fn import(storage: &Storage, path: &Path) -> Result<Imported, Error> {
+    import_locked(storage, path, |_| ())
+}
+
+/// Import a repository from a bundle file, like [`import`], holding the value returned by
+/// `lock` while the repository is moved into storage.
+///
+/// This allows the caller to lock the repository, eg. so that it isn't fetched
+/// concurrently, once its ID is known.
+pub fn import_locked<G>(
+    storage: &Storage,
+    path: &Path,
+    lock: impl FnOnce(RepoId) -> G,
+) -> Result<Imported, Error> {
+    let path = absolute(path)?;
+    let tmp = tempfile::Builder::new()
+        .prefix(IMPORT_PREFIX)
+        .tempdir_in(storage.path())?;
+    let backend = git::raw::Repository::init_opts(
+        tmp.path(),
+        git::raw::RepositoryInitOptions::new()
+            .bare(true)
+            .no_reinit(true)
+            .external_template(false),
+    )?;
+
+    git(
+        "fetch",
+        tmp.path(),
+        [
+            "fetch".as_ref(),
+            "--quiet".as_ref(),
+            path.as_os_str(),
+            "refs/*:refs/*".as_ref(),
+        ],
+    )?;
+
+    // The repository ID is the ID of the initial identity document.
+    let rid = {
+        let repo = Repository {
+            id: RepoId::from(git::raw::Oid::zero()),
+            backend,
+            cache: None,
+        };
+        let root = repo.identity_root()?;
+        let blob = Doc::<Unverified>::blob_at(root, &repo)?;
+
+        RepoId::from(blob.id())
+    };
+    let _guard = lock(rid);
+
+    if storage.contains(&rid)? {
+        return Err(Error::Exists(rid));
+    }
+    let repo = Repository::open(tmp.path(), rid)?;
+    let failures = verify::check(&repo)?;
+
+    if !failures.is_empty() {
+        return Err(Error::Verification(failures));
+    }
+    let remotes = repo.remote_ids()?.collect::<Result<Vec<_>, _>>()?;
+    drop(repo);
+
+    // If this fails, the temporary repository is removed when dropped.
+    fs::rename(tmp.path(), storage.path_of(&rid))?;
+    storage.insert(rid);
+
+    let repo = storage.repository(rid)?;
+    repo.set_identity_head()?;
+    repo.set_head()?;
+
+    Ok(Imported { rid, remotes })
+}
+
+/// Make a path absolute, since `git` is run from the repository directory.
+fn absolute(path: &Path) -> io::Result<PathBuf> {
+    if path.is_absolute() {
+        Ok(path.to_path_buf())
+    } else {
+        Ok(std::env::current_dir()?.join(path))
+    }
+}
+
+/// Run a `git` command in the given directory.
+fn git<'a>(
+    name: &'static str,
+    dir: impl AsRef<Path>,
+    args: impl IntoIterator<Item = &'a std::ffi::OsStr>,
+) -> Result<(), Error> {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .env_clear()
+        .envs(std::env::vars().filter(|(key, _)| key == "PATH" || key.starts_with("GIT_TRACE")))
+        .args(args)
+        .output()?;
+
+    if !output.status.success() {
+        return Err(Error::Command(
+            name,
+            String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+    use crate::crypto::test::signer::MockSigner;
+    use crate::crypto::Signer as _;
+    use crate::test::fixtures;
+
+    #[test]
+    fn test_export_import() {
+        let tmp = tempfile::tempdir().unwrap();
+        let signer = MockSigner::default();
+        let alice = fixtures::storage(tmp.path().join("alice"), &signer).unwrap();
+        let bob = Storage::open(tmp.path().join("bob"), alice.info().clone()).unwrap();
+        let rid = alice.repository_ids().unwrap()[0];
+        let bundle = tmp.path().join("acme.bundle");
+
+        export(&alice, rid, &bundle).unwrap();
+
+        let imported = import(&bob, &bundle).unwrap();
+        let expected = alice.repository(rid).unwrap();
+        let actual = bob.repository(rid).unwrap();
+
+        assert_eq!(imported.rid, rid);
+        assert_eq!(
+            imported.remotes,
+            expected
+                .remote_ids()
+                .unwrap()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        );
+        assert_eq!(
+            actual.identity_doc().unwrap(),
+            expected.identity_doc().unwrap()
+        );
+        assert_eq!(actual.head().unwrap(), expected.head().unwrap());
+        assert!(verify::repository(&bob, rid).is_ok());
+
+        // Importing the same repository again fails.
+        assert!(matches!(import(&bob, &bundle), Err(Error::Exists(r)) if r == rid));
+        // No temporary directories are left behind.
+        assert_eq!(bob.repository_ids().unwrap(), vec![rid]);
+        assert_eq!(fs::read_dir(bob.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_import_unsigned() {
+        let tmp = tempfile::tempdir().unwrap();
+        let signer = MockSigner::default();
+        let alice = fixtures::storage(tmp.path().join("alice"), &signer).unwrap();
+        let bob = Storage::open(tmp.path().join("bob"), alice.info().clone()).unwrap();
+        let rid = alice.repository_ids().unwrap()[0];
+        let bundle = tmp.path().join("acme.bundle");
+        let repo = alice.repository(rid).unwrap();
+        let head = repo.head().unwrap().1;
+
+        // Add a ref that isn't signed.
+        repo.backend
+            .reference(
+                &format!(
+                    "refs/namespaces/{}/refs/heads/unsigned",
+                    signer.public_key()
+                ),
+                *head,
+                false,
+                "test",
+            )
+            .unwrap();
+        export(&alice, rid, &bundle).unwrap();
+
+        assert!(matches!(
+            import(&bob, &bundle),
+            Err(Error::Verification(failures))
+            if matches!(failures.as_slice(), [Failure::UnsignedRef { .. }])
+        ));
+        assert!(!bob.contains(&rid).unwrap());
+    }
+}
@@ -0,0 +1,220 @@
+//! Repository statistics.
+//!
+//! Statistics about the resources used by a stored repository, so that seed operators can
+//! find out which repositories consume the most disk space.
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::fs;
+use std::path::Path;
+
+use localtime::LocalTime;
+use serde::{Deserialize, Serialize};
+
+use crate::git;
+use crate::identity::RepoId;
+use crate::node::Timestamp;
+use crate::storage::{ReadStorage, RemoteId, RepositoryError};
+
+use super::{Error, Ref, Repository, Storage};
+
+/// Maximum number of blobs listed in [`Stats::largest`].
+pub const MAX_LARGEST_BLOBS: usize = 10;
+
+/// Statistics of a stored repository.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Stats {
+    /// Repository the statistics are of.
+    pub rid: RepoId,
+    /// Number of packfiles.
+    pub packs: usize,
+    /// Size of the packfiles on disk, in bytes.
+    pub packed: u64,
+    /// Number of loose objects.
+    pub loose: usize,
+    /// Size of the loose objects on disk, in bytes.
+    pub loose_size: u64,
+    /// Number of refs, per namespace.
+    pub refs: BTreeMap<RemoteId, usize>,
+    /// Largest blobs, largest first.
+    pub largest: Vec<Blob>,
+    /// When the statistics were computed.
+    pub timestamp: Timestamp,
+}
+
+impl Stats {
+    /// Size of the repository's objects on disk, in bytes.
+    pub fn size(&self) -> u64 {
+        self.packed + self.loose_size
+    }
+}
+
+/// A blob and its size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Blob {
+    /// Uncompressed size of the blob, in bytes.
+    pub size: u64,
+    /// Blob object ID.
+    pub oid: git::Oid,
+}
+
+/// Compute the statistics of a stored repository.
+pub fn repository(storage: &Storage, rid: RepoId) -> Result<Stats, RepositoryError> {
+    let repo = storage.repository(rid)?;
+
+    Ok(compute(&repo)?)
+}
+
+/// Compute the statistics of a repository.
+pub fn compute(repo: &Repository) -> Result<Stats, Error> {
+    let objects = repo.backend.path().join("objects");
+    let (packs, packed) = packs(&objects.join("pack"))?;
+    let (loose, loose_size) = loose(&objects)?;
+
+    let mut refs = BTreeMap::<RemoteId, usize>::new();
+    for r in repo.backend.references_glob("refs/namespaces/*")? {
+        let r = r?;
+        if r.kind() == Some(git::raw::ReferenceType::Symbolic) {
+            continue;
+        }
+        if let Some(remote) = Ref::try_from(r)?.namespace {
+            *refs.entry(remote).or_default() += 1;
+        }
+    }
+
+    Ok(Stats {
+        rid: repo.id,
+        packs,
+        packed,
+        loose,
+        loose_size,
+        refs,
+        largest: largest(repo, MAX_LARGEST_BLOBS)?,
+        timestamp: LocalTime::now().into(),
+    })
+}
+
+/// Get the number and total size of the packfiles in the given directory.
+fn packs(dir: &Path) -> Result<(usize, u64), Error> {
+    let mut count = 0;
+    let mut size = 0;
+
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok((0, 0)),
+        Err(e) => return Err(e.into()),
+    };
+    for entry in entries {
+        let entry = entry?;
+        if entry.path().extension().is_some_and(|ext| ext == "pack") {
+            count += 1;
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok((count, size))
+}
+
+/// Get the number and total size of the loose objects in the given object directory.
+fn loose(objects: &Path) -> Result<(usize, u64), Error> {
+    let mut count = 0;
+    let mut size = 0;
+
+    for entry in fs::read_dir(objects)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        // Loose objects are stored in directories named after the first byte of their ID.
+        let is_fanout = name.len() == 2
+            && name
+                .to_str()
+                .is_some_and(|n| n.chars().all(|c| c.is_ascii_hexdigit()));
+
+        if !is_fanout || !entry.file_type()?.is_dir() {
+            continue;
+        }
+        for object in fs::read_dir(entry.path())? {
+            count += 1;
+            size += object?.metadata()?.len();
+        }
+    }
+    Ok((count, size))
+}
+
+/// Get the `n` largest blobs of a repository, largest first.
+fn largest(repo: &Repository, n: usize) -> Result<Vec<Blob>, Error> {
+    let odb = repo.backend.odb()?;
+    let mut heap = BinaryHeap::<Reverse<Blob>>::with_capacity(n + 1);
+    let mut error = None;
+
+    odb.foreach(|oid| {
+        let (size, kind) = match odb.read_header(*oid) {
+            Ok(header) => header,
+            Err(e) => {
+                error = Some(e);
+                return false;
+            }
+        };
+        if kind != git::raw::ObjectType::Blob {
+            return true;
+        }
+        let blob = Blob {
+            size: size as u64,
+            oid: (*oid).into(),
+        };
+        // Nb. Objects that are both packed and loose, or in several packs, are visited
+        // more than once.
+        if heap.iter().any(|Reverse(b)| b.oid == blob.oid) {
+            return true;
+        }
+        heap.push(Reverse(blob));
+        if heap.len() > n {
+            heap.pop();
+        }
+        true
+    })?;
+
+    if let Some(e) = error {
+        return Err(e.into());
+    }
+    Ok(heap
+        .into_sorted_vec()
+        .into_iter()
+        .map(|Reverse(b)| b)
+        .collect())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+    use crate::crypto::test::signer::MockSigner;
+    use crate::crypto::Signer as _;
+    use crate::test::fixtures;
+
+    #[test]
+    fn test_stats() {
+        let tmp = tempfile::tempdir().unwrap();
+        let signer = MockSigner::default();
+        let storage = fixtures::storage(tmp.path(), &signer).unwrap();
+        let rid = *storage.inventory().unwrap().first().unwrap();
+        let repo = storage.repository(rid).unwrap();
+        let blob = repo.backend.blob(&[0; 4096]).unwrap();
+
+        let stats = repository(&storage, rid).unwrap();
+        let (remote, count) = stats.refs.first_key_value().unwrap();
+
+        assert_eq!(stats.rid, rid);
+        assert_eq!(remote, signer.public_key());
+        assert!(*count > 0);
+        assert!(stats.loose > 0);
+        assert_eq!(stats.size(), stats.packed + stats.loose_size);
+        assert_eq!(
+            stats.largest.first(),
+            Some(&Blob {
+                size: 4096,
+                oid: blob.into()
+            })
+        );
+        assert!(stats.largest.windows(2).all(|w| w[0].size >= w[1].size));
+    }
+}
@@ -0,0 +1,392 @@
+//! Storage integrity checks.
+//!
+//! Checks that the refs of each namespace match its signed refs, that the identity
+//! document verifies, and that every object reachable from a ref is in the object database.
+//! This is meant to be run offline, eg. to diagnose a corrupted storage.
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::git;
+use crate::identity::RepoId;
+use crate::node::NodeId;
+use crate::storage::{ReadRepository, ReadStorage, RemoteId, RemoteRepository, ValidateRepository};
+
+use super::{Ref, Repository, Storage, Validation};
+
+/// An integrity failure found in a repository.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum Failure {
+    /// The repository couldn't be opened or read.
+    #[serde(rename_all = "camelCase")]
+    Repository { error: String },
+    /// The identity document is missing or doesn't verify.
+    #[serde(rename_all = "camelCase")]
+    Identity { error: String },
+    /// A namespace has refs, but no signed refs.
+    #[serde(rename_all = "camelCase")]
+    MissingSigrefs { remote: RemoteId },
+    /// The signed refs of a namespace couldn't be loaded, or don't verify.
+    #[serde(rename_all = "camelCase")]
+    InvalidSigrefs { remote: RemoteId, error: String },
+    /// A ref isn't in the signed refs of its namespace.
+    #[serde(rename_all = "camelCase")]
+    UnsignedRef {
+        remote: RemoteId,
+        refname: git::RefString,
+    },
+    /// A ref doesn't point to what was signed.
+    #[serde(rename_all = "camelCase")]
+    MismatchedRef {
+        remote: RemoteId,
+        refname: git::RefString,
+        expected: git::Oid,
+        actual: git::Oid,
+    },
+    /// A signed ref is missing.
+    #[serde(rename_all = "camelCase")]
+    MissingRef {
+        remote: RemoteId,
+        refname: git::RefString,
+    },
+    /// The `HEAD` of a namespace points to a ref that isn't signed.
+    #[serde(rename_all = "camelCase")]
+    DanglingHead {
+        remote: RemoteId,
+        target: git::RefString,
+    },
+    /// The `HEAD` of a namespace points to an object, instead of a signed ref.
+    #[serde(rename_all = "camelCase")]
+    DirectHead { remote: RemoteId, oid: git::Oid },
+    /// An object reachable from a ref is missing from the object database.
+    #[serde(rename_all = "camelCase")]
+    MissingObject {
+        remote: RemoteId,
+        refname: git::RefString,
+        oid: git::Oid,
+    },
+}
+
+impl Failure {
+    /// The namespace affected by this failure, if it's specific to a namespace.
+    pub fn remote(&self) -> Option<&RemoteId> {
+        match self {
+            Self::Repository { .. } | Self::Identity { .. } => None,
+            Self::MissingSigrefs { remote }
+            | Self::InvalidSigrefs { remote, .. }
+            | Self::UnsignedRef { remote, .. }
+            | Self::MismatchedRef { remote, .. }
+            | Self::MissingRef { remote, .. }
+            | Self::DanglingHead { remote, .. }
+            | Self::DirectHead { remote, .. }
+            | Self::MissingObject { remote, .. } => Some(remote),
+        }
+    }
+
+    fn from_validation(remote: RemoteId, validation: Validation) -> Self {
+        match validation {
+            Validation::UnsignedRef(refname) => Self::UnsignedRef { remote, refname },
+            Validation::MismatchedRef {
+                expected,
+                actual,
+                refname,
+            } => Self::MismatchedRef {
+                remote,
+                refname,
+                expected,
+                actual,
+            },
+            Validation::MissingRef { remote, refname } => Self::MissingRef { remote, refname },
+            Validation::MissingRadSigRefs(remote) => Self::MissingSigrefs { remote },
+            Validation::DanglingHead { remote, target } => Self::DanglingHead { remote, target },
+            Validation::DirectHead { remote, oid } => Self::DirectHead { remote, oid },
+            v @ Validation::TooManyRefs { .. } => Self::InvalidSigrefs {
+                remote,
+                error: v.to_string(),
+            },
+        }
+    }
+}
+
+/// Integrity report of a repository.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    /// Repository checked.
+    pub rid: RepoId,
+    /// Failures found. Empty if the repository is intact.
+    pub failures: Vec<Failure>,
+}
+
+impl Report {
+    /// Whether the repository is intact.
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Outcome of a repository repair.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Repair {
+    /// Repository repaired.
+    pub rid: RepoId,
+    /// Namespaces that were removed, to be fetched again.
+    pub removed: Vec<RemoteId>,
+    /// Namespaces that were removed, but couldn't be fetched again.
+    pub lost: Vec<RemoteId>,
+    /// Seeds that were fetched from.
+    pub fetched: Vec<NodeId>,
+    /// Failures that were fixed.
+    pub fixed: Vec<Failure>,
+    /// Failures that remain.
+    pub remaining: Vec<Failure>,
+}
+
+impl Repair {
+    /// Create a repair of the given repository, that didn't fix anything yet.
+    pub fn new(report: Report) -> Self {
+        Self {
+            rid: report.rid,
+            removed: Vec::new(),
+            lost: Vec::new(),
+            fetched: Vec::new(),
+            fixed: Vec::new(),
+            remaining: report.failures,
+        }
+    }
+
+    /// Whether the repository is intact after the repair.
+    pub fn is_ok(&self) -> bool {
+        self.remaining.is_empty() && self.lost.is_empty()
+    }
+}
+
+/// Check the integrity of a stored repository.
+pub fn repository(storage: &Storage, rid: RepoId) -> Report {
+    let failures = match storage.repository(rid) {
+        Ok(repo) => check(&repo).unwrap_or_else(|e| {
+            vec![Failure::Repository {
+                error: e.to_string(),
+            }]
+        }),
+        Err(e) => vec![Failure::Repository {
+            error: e.to_string(),
+        }],
+    };
+    Report { rid, failures }
+}
+
+/// Check the integrity of all stored repositories, one at a time.
+pub fn storage(storage: &Storage) -> Result<impl Iterator<Item = Report> + '_, super::Error> {
+    let rids = storage.repository_ids()?;
+
+    Ok(rids.into_iter().map(|rid| repository(storage, rid)))
+}
+
+pub(super) fn check(repo: &Repository) -> Result<Vec<Failure>, super::Error> {
+    let mut failures = Vec::new();
+
+    if let Err(e) = repo.identity_doc() {
+        failures.push(Failure::Identity {
+            error: e.to_string(),
+        });
+    }
+
+    // All refs, by namespace.
+    let mut namespaces = BTreeMap::<RemoteId, Vec<(git::RefString, git::Oid)>>::new();
+    for r in repo.backend.references_glob("refs/namespaces/*")? {
+        let r = r?;
+        // Symbolic refs, ie. namespace `HEAD`s, are checked against the signed refs.
+        if r.kind() == Some(git::raw::ReferenceType::Symbolic) {
+            continue;
+        }
+        let r = Ref::try_from(r)?;
+        if let Some(remote) = r.namespace {
+            namespaces.entry(remote).or_default().push((r.name, r.oid));
+        }
+    }
+    let signed = repo.remote_ids()?.collect::<Result<BTreeSet<_>, _>>()?;
+
+    // Check refs against the signed refs of their namespace.
+    for remote in namespaces.keys() {
+        if !signed.contains(remote) {
+            failures.push(Failure::MissingSigrefs { remote: *remote });
+            continue;
+        }
+        match repo.remote(remote) {
+            Ok(r) => failures.extend(
+                repo.validate_remote(&r)?
+                    .into_iter()
+                    .map(|v| Failure::from_validation(*remote, v)),
+            ),
+            Err(e) => failures.push(Failure::InvalidSigrefs {
+                remote: *remote,
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    // Check that everything reachable from a ref is there.
+    let mut seen = HashSet::new();
+    for (remote, refs) in namespaces {
+        for (refname, oid) in refs {
+            if let Some(missing) = missing_object(&repo.backend, *oid, &mut seen)? {
+                failures.push(Failure::MissingObject {
+                    remote,
+                    refname,
+                    oid: missing.into(),
+                });
+            }
+        }
+    }
+    Ok(failures)
+}
+
+/// Walk the object graph from the given object, and return the first object that is
+/// missing, if any. Objects in `seen` are skipped.
+fn missing_object(
+    repo: &git::raw::Repository,
+    oid: git::raw::Oid,
+    seen: &mut HashSet<git::raw::Oid>,
+) -> Result<Option<git::raw::Oid>, git::raw::Error> {
+    let odb = repo.odb()?;
+    let mut queue = vec![oid];
+
+    while let Some(oid) = queue.pop() {
+        if !seen.insert(oid) {
+            continue;
+        }
+        let object = match repo.find_object(oid, None) {
+            Ok(object) => object,
+            Err(e) if e.code() == git::raw::ErrorCode::NotFound => return Ok(Some(oid)),
+            Err(e) => return Err(e),
+        };
+        match object.kind() {
+            Some(git::raw::ObjectType::Commit) => {
+                let commit = object.peel_to_commit()?;
+
+                queue.push(commit.tree_id());
+                queue.extend(commit.parent_ids());
+            }
+            Some(git::raw::ObjectType::Tree) => {
+                let tree = object.peel_to_tree()?;
+
+                for entry in tree.iter() {
+                    match entry.kind() {
+                        // Blobs can be large, so we only check that they exist.
+                        Some(git::raw::ObjectType::Blob) => {
+                            if seen.insert(entry.id()) && !odb.exists(entry.id()) {
+                                return Ok(Some(entry.id()));
+                            }
+                        }
+                        // Submodule commits aren't expected to be in the repository.
+                        Some(git::raw::ObjectType::Commit) => {}
+                        _ => queue.push(entry.id()),
+                    }
+                }
+            }
+            Some(git::raw::ObjectType::Tag) => {
+                if let Some(tag) = object.as_tag() {
+                    queue.push(tag.target_id());
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use super::*;
+    use crate::crypto::test::signer::MockSigner;
+    use crate::test::fixtures;
+
+    #[test]
+    fn test_verify() {
+        let tmp = tempfile::tempdir().unwrap();
+        let storage = fixtures::storage(tmp.path(), &MockSigner::default()).unwrap();
+        let reports = self::storage(&storage).unwrap().collect::<Vec<_>>();
+
+        assert_eq!(reports.len(), 3);
+        assert!(reports.iter().all(|r| r.is_ok()), "{reports:?}");
+
+        // Move a signed ref back by one commit.
+        let rid = reports[0].rid;
+        let repo = storage.repository(rid).unwrap();
+        let (remote, refname, head) = repo
+            .references()
+            .unwrap()
+            .map(Result::unwrap)
+            .find(|r| r.namespace.is_some() && r.name.as_str() == "refs/heads/master")
+            .map(|r| (r.namespace.unwrap(), r.name, r.oid))
+            .unwrap();
+        let parent = repo.commit(head).unwrap().parent_id(0).unwrap();
+
+        repo.backend
+            .reference(
+                &format!("refs/namespaces/{remote}/{refname}"),
+                parent,
+                true,
+                "test",
+            )
+            .unwrap();
+
+        assert_eq!(
+            repository(&storage, rid).failures,
+            vec![Failure::MismatchedRef {
+                remote,
+                refname,
+                expected: head,
+                actual: parent.into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_verify_head() {
+        let tmp = tempfile::tempdir().unwrap();
+        let storage = fixtures::storage(tmp.path(), &MockSigner::default()).unwrap();
+        let rid = storage.repositories().unwrap()[0].rid;
+        let repo = storage.repository(rid).unwrap();
+        let (remote, head) = repo
+            .references()
+            .unwrap()
+            .map(Result::unwrap)
+            .find(|r| r.namespace.is_some() && r.name.as_str() == "refs/heads/master")
+            .map(|r| (r.namespace.unwrap(), r.oid))
+            .unwrap();
+        let name = format!("refs/namespaces/{remote}/HEAD");
+
+        // A `HEAD` pointing to a signed ref is fine.
+        repo.backend
+            .reference_symbolic(
+                &name,
+                &format!("refs/namespaces/{remote}/refs/heads/master"),
+                true,
+                "test",
+            )
+            .unwrap();
+        assert!(repository(&storage, rid).is_ok());
+
+        repo.backend
+            .reference_symbolic(&name, "refs/heads/unsigned", true, "test")
+            .unwrap();
+        assert_eq!(
+            repository(&storage, rid).failures,
+            vec![Failure::DanglingHead {
+                remote,
+                target: git::refname!("refs/heads/unsigned"),
+            }]
+        );
+
+        repo.backend.reference(&name, *head, true, "test").unwrap();
+        assert_eq!(
+            repository(&storage, rid).failures,
+            vec![Failure::DirectHead { remote, oid: head }]
+        );
+    }
+}
@@ -14,6 +14,7 @@ use thiserror::Error;
 use crate::git;
 use crate::git::ext as git_ext;
 use crate::git::Oid;
+use crate::node::Timestamp;
 use crate::storage;
 use crate::storage::{ReadRepository, RemoteId, WriteRepository};
 
@@ -23,6 +24,22 @@ pub use crate::git::refs::storage::*;
 pub const REFS_BLOB_PATH: &str = "refs";
 /// File in which the signature over the references is stored in the `refs/rad/sigrefs` branch.
 pub const SIGNATURE_BLOB_PATH: &str = "signature";
+/// Byte with which the canonical representation of version 2 signed refs starts.
+/// Version 1 refs start with an object id, and so can never start with this byte.
+pub const VERSION_2: u8 = 2;
+
+/// Version of the signed refs format.
+#[derive(
+    Default, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum Version {
+    /// References and their targets.
+    #[default]
+    V1,
+    /// References with the time at which they were last updated, and deletion tombstones.
+    V2,
+}
 
 #[derive(Debug)]
 pub enum Updated {
@@ -63,11 +80,26 @@ impl Error {
     }
 }
 
+/// Reference history, recorded by version 2 signed refs.
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
+pub struct History {
+    /// When each signed reference was last updated.
+    updated: BTreeMap<git::RefString, Timestamp>,
+    /// References that were deleted, and when.
+    deleted: BTreeMap<git::RefString, Timestamp>,
+}
+
 // TODO(finto): we should turn `git::RefString` to `git::Qualified`,
 // since all these refs SHOULD be `Qualified`.
 /// The published state of a local repository.
 #[derive(Default, Clone, Debug, PartialEq, Eq, Serialize)]
-pub struct Refs(BTreeMap<git::RefString, Oid>);
+#[serde(transparent)]
+pub struct Refs {
+    refs: BTreeMap<git::RefString, Oid>,
+    /// Only set for version 2 refs.
+    #[serde(skip)]
+    history: Option<History>,
+}
 
 impl Refs {
     /// Verify the given signature on these refs, and return [`SignedRefs`] on success.
@@ -109,17 +141,136 @@ impl Refs {
 
     /// Get a particular ref.
     pub fn get(&self, name: &git::Qualified) -> Option<Oid> {
-        self.0.get(name.to_ref_string().as_refstr()).copied()
+        self.refs.get(name.to_ref_string().as_refstr()).copied()
     }
 
     /// Get a particular head ref.
     pub fn head(&self, name: impl AsRef<git::RefStr>) -> Option<Oid> {
         let branch = git::refname!("refs/heads").join(name);
-        self.0.get(&branch).copied()
+        self.refs.get(&branch).copied()
+    }
+
+    /// Get the version of the signed refs format these refs use.
+    pub fn version(&self) -> Version {
+        if self.history.is_some() {
+            Version::V2
+        } else {
+            Version::V1
+        }
+    }
+
+    /// Get the time at which a reference was last updated. Only known for version 2 refs.
+    pub fn updated_at(&self, name: &git::RefStr) -> Option<Timestamp> {
+        let history = self.history.as_ref()?;
+
+        self.refs
+            .contains_key(name)
+            .then(|| history.updated.get(name).copied())
+            .flatten()
+    }
+
+    /// Get the time at which a reference was deleted, if it has a tombstone.
+    /// Only version 2 refs have tombstones.
+    pub fn deleted_at(&self, name: &git::RefStr) -> Option<Timestamp> {
+        let history = self.history.as_ref()?;
+
+        if self.refs.contains_key(name) {
+            return None;
+        }
+        history.deleted.get(name).copied()
     }
 
-    /// Create refs from a canonical representation.
+    /// Whether a reference was intentionally deleted, ie. it has a tombstone.
+    pub fn is_deleted(&self, name: &git::RefStr) -> bool {
+        self.deleted_at(name).is_some()
+    }
+
+    /// Iterate over the tombstones of deleted references.
+    pub fn tombstones(&self) -> impl Iterator<Item = (&git::RefString, Timestamp)> {
+        self.history
+            .iter()
+            .flat_map(|h| h.deleted.iter())
+            .filter(|(name, _)| !self.refs.contains_key(name.as_refstr()))
+            .map(|(name, t)| (name, *t))
+    }
+
+    /// Turn these refs into version 2 refs, given the `previous` refs of the same remote.
+    ///
+    /// References whose target is unchanged keep their previous update time, if known; other
+    /// references are marked as updated `now`. References that were in `previous` but are
+    /// not in these refs get a tombstone, and previous tombstones are kept, unless the
+    /// reference was re-created.
+    pub fn with_history(mut self, previous: &Refs, now: Timestamp) -> Self {
+        let mut history = History::default();
+
+        for (name, oid) in self.refs.iter() {
+            let updated = match previous.refs.get(name) {
+                Some(prev) if prev == oid => previous.updated_at(name).unwrap_or(now),
+                _ => now,
+            };
+            history.updated.insert(name.clone(), updated);
+        }
+        for (name, deleted) in previous.tombstones() {
+            if !self.refs.contains_key(name) {
+                history.deleted.insert(name.clone(), deleted);
+            }
+        }
+        for name in previous.refs.keys() {
+            if !self.refs.contains_key(name) {
+                history.deleted.insert(name.clone(), now);
+            }
+        }
+        self.history = Some(history);
+        self
+    }
+
+    /// Create refs from a canonical representation, of either version.
     pub fn from_canonical(bytes: &[u8]) -> Result<Self, canonical::Error> {
+        match bytes {
+            [VERSION_2, b'\n', rest @ ..] => Self::from_canonical_v2(rest),
+            _ => Self::from_canonical_v1(bytes),
+        }
+    }
+
+    /// Create version 2 refs from their canonical representation, without the version header.
+    /// Each line holds an object id, a reference name and a timestamp. Tombstones have the zero
+    /// object id.
+    fn from_canonical_v2(bytes: &[u8]) -> Result<Self, canonical::Error> {
+        let reader = BufReader::new(bytes);
+        let mut refs = BTreeMap::new();
+        let mut history = History::default();
+
+        for line in reader.lines() {
+            let line = line?;
+            let mut parts = line.split(' ');
+            let (Some(oid), Some(name), Some(timestamp), None) =
+                (parts.next(), parts.next(), parts.next(), parts.next())
+            else {
+                return Err(canonical::Error::InvalidFormat);
+            };
+            let name = git::RefString::try_from(name)?;
+            let oid = Oid::from_str(oid)?;
+            let timestamp = Timestamp::from(
+                timestamp
+                    .parse::<u64>()
+                    .map_err(|_| canonical::Error::InvalidFormat)?,
+            );
+
+            if oid.is_zero() {
+                history.deleted.insert(name, timestamp);
+            } else {
+                history.updated.insert(name.clone(), timestamp);
+                refs.insert(name, oid);
+            }
+        }
+        Ok(Self {
+            refs,
+            history: Some(history),
+        })
+    }
+
+    /// Create version 1 refs from their canonical representation.
+    fn from_canonical_v1(bytes: &[u8]) -> Result<Self, canonical::Error> {
         let reader = BufReader::new(bytes);
         let mut refs = BTreeMap::new();
 
@@ -137,16 +288,47 @@ impl Refs {
             }
             refs.insert(name, oid);
         }
-        Ok(Self(refs))
+        Ok(Self::from(refs))
     }
 
+    /// Get the canonical representation of these refs, which is what gets signed.
     pub fn canonical(&self) -> Vec<u8> {
-        let mut buf = String::new();
-
-        for (name, oid) in self.iter() {
+        let Some(history) = &self.history else {
+            let mut buf = String::new();
+
+            for (name, oid) in self.iter() {
+                buf.push_str(&oid.to_string());
+                buf.push(' ');
+                buf.push_str(name);
+                buf.push('\n');
+            }
+            return buf.into_bytes();
+        };
+        let zero = Oid::from(git2::Oid::zero());
+        let mut lines = self
+            .refs
+            .iter()
+            .map(|(name, oid)| {
+                let updated = history
+                    .updated
+                    .get(name)
+                    .copied()
+                    .unwrap_or(Timestamp::EPOCH);
+                (name, *oid, updated)
+            })
+            .chain(self.tombstones().map(|(name, t)| (name, zero, t)))
+            .collect::<Vec<_>>();
+        lines.sort_by(|(a, _, _), (b, _, _)| a.cmp(b));
+
+        let mut buf = String::from(char::from(VERSION_2));
+        buf.push('\n');
+
+        for (name, oid, timestamp) in lines {
             buf.push_str(&oid.to_string());
             buf.push(' ');
             buf.push_str(name);
+            buf.push(' ');
+            buf.push_str(&timestamp.to_string());
             buf.push('\n');
         }
         buf.into_bytes()
@@ -158,13 +340,13 @@ impl IntoIterator for Refs {
     type IntoIter = std::collections::btree_map::IntoIter<git::RefString, Oid>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.0.into_iter()
+        self.refs.into_iter()
     }
 }
 
 impl From<Refs> for BTreeMap<git::RefString, Oid> {
     fn from(refs: Refs) -> Self {
-        refs.0
+        refs.refs
     }
 }
 
@@ -176,7 +358,10 @@ impl<V> From<SignedRefs<V>> for Refs {
 
 impl From<BTreeMap<git::RefString, Oid>> for Refs {
     fn from(refs: BTreeMap<git::RefString, Oid>) -> Self {
-        Self(refs)
+        Self {
+            refs,
+            history: None,
+        }
     }
 }
 
@@ -184,13 +369,13 @@ impl Deref for Refs {
     type Target = BTreeMap<git::RefString, Oid>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.refs
     }
 }
 
 impl DerefMut for Refs {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.refs
     }
 }
 
@@ -260,20 +445,26 @@ impl SignedRefs<Verified> {
     where
         S: storage::ReadRepository,
     {
+        let cache = repo.cache();
+        if let Some(refs) = cache.and_then(|c| c.sigrefs(repo.id(), remote, oid)) {
+            return Ok(refs);
+        }
         let refs = repo.blob_at(oid, Path::new(REFS_BLOB_PATH))?;
         let signature = repo.blob_at(oid, Path::new(SIGNATURE_BLOB_PATH))?;
         let signature: crypto::Signature = signature.content().try_into()?;
 
         match remote.verify(refs.content(), &signature) {
             Ok(()) => {
-                let refs = Refs::from_canonical(refs.content())?;
-
-                Ok(Self {
-                    refs,
+                let refs = Self {
+                    refs: Refs::from_canonical(refs.content())?,
                     signature,
                     id: remote,
                     _verified: PhantomData,
-                })
+                };
+                if let Some(cache) = cache {
+                    cache.insert_sigrefs(repo.id(), oid, refs.clone());
+                }
+                Ok(refs)
             }
             Err(e) => Err(e.into()),
         }
@@ -332,7 +523,12 @@ impl SignedRefs<Verified> {
         );
 
         match commit {
-            Ok(oid) => Ok(Updated::Updated { oid: oid.into() }),
+            Ok(oid) => {
+                if let Some(cache) = repo.cache() {
+                    cache.invalidate_remote(&repo.id(), remote);
+                }
+                Ok(Updated::Updated { oid: oid.into() })
+            }
             Err(e) => match (e.class(), e.code()) {
                 (git2::ErrorClass::Object, git2::ErrorCode::Modified) => {
                     log::warn!("Concurrent modification of refs: {:?}", e);
@@ -469,4 +665,58 @@ mod tests {
 
         assert_eq!(refs, decoded);
     }
+
+    #[test]
+    fn test_history() {
+        let master = git::refname!("refs/heads/master");
+        let feature = git::refname!("refs/heads/feature");
+        let fix = git::refname!("refs/heads/fix");
+        let a = Oid::from_str("a8f0a3b9b3c1b6e1c4d2f1e0a9b8c7d6e5f4a3b2").unwrap();
+        let b = Oid::from_str("b8f0a3b9b3c1b6e1c4d2f1e0a9b8c7d6e5f4a3b2").unwrap();
+
+        let v1 = Refs::from(BTreeMap::from_iter([
+            (master.clone(), a),
+            (feature.clone(), a),
+        ]));
+        assert_eq!(v1.version(), Version::V1);
+        assert_eq!(v1.updated_at(&master), None);
+
+        // Migrating marks all refs as updated.
+        let v2 = v1.clone().with_history(&v1, Timestamp::from(1));
+        assert_eq!(v2.version(), Version::V2);
+        assert_eq!(v2.updated_at(&master), Some(Timestamp::from(1)));
+        assert_eq!(v2.tombstones().count(), 0);
+
+        // Unchanged refs keep their timestamp, deleted refs get a tombstone.
+        let next = Refs::from(BTreeMap::from_iter([(master.clone(), a), (fix.clone(), b)]))
+            .with_history(&v2, Timestamp::from(2));
+        assert_eq!(next.updated_at(&master), Some(Timestamp::from(1)));
+        assert_eq!(next.updated_at(&fix), Some(Timestamp::from(2)));
+        assert_eq!(next.deleted_at(&feature), Some(Timestamp::from(2)));
+        assert!(!next.is_deleted(&fix));
+
+        // Tombstones are kept until the ref is re-created.
+        let next = Refs::from(BTreeMap::from_iter([(master.clone(), b)]))
+            .with_history(&next, Timestamp::from(3));
+        assert_eq!(next.updated_at(&master), Some(Timestamp::from(3)));
+        assert_eq!(next.deleted_at(&feature), Some(Timestamp::from(2)));
+        assert_eq!(next.deleted_at(&fix), Some(Timestamp::from(3)));
+
+        let next = Refs::from(BTreeMap::from_iter([
+            (master.clone(), b),
+            (feature.clone(), b),
+        ]))
+        .with_history(&next, Timestamp::from(4));
+        assert!(!next.is_deleted(&feature));
+        assert_eq!(
+            next.tombstones().collect::<Vec<_>>(),
+            vec![(&fix, Timestamp::from(3))]
+        );
+
+        // Both versions can be read back.
+        let encoded = next.canonical();
+        assert_eq!(encoded[0], VERSION_2);
+        assert_eq!(Refs::from_canonical(&encoded).unwrap(), next);
+        assert_eq!(Refs::from_canonical(&v1.canonical()).unwrap(), v1);
+    }
 }
@@ -0,0 +1,254 @@
+//! Notifications of reference updates, for other local processes.
+//!
+//! Every time the node applies fetched reference updates to a repository, it appends a
+//! line to the [`UPDATES_FILE`] at the root of storage. Other processes, eg. a web
+//! interface or a CLI watching a repository, follow the file with a [`Subscriber`] to
+//! learn about updates as they happen, instead of polling repositories. Each line is a
+//! JSON-encoded [`Update`].
+//!
+//! Once the file reaches [`MAX_SIZE`], it's moved to [`ROTATED_FILE`] and a new one is
+//! started. Subscribers notice, and follow the new file.
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read as _, Seek as _, Write as _};
+use std::os::unix::fs::MetadataExt as _;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::{thread, time};
+
+use localtime::LocalTime;
+use serde::{Deserialize, Serialize};
+
+use crate::node::{NodeId, Timestamp};
+use crate::prelude::RepoId;
+use crate::storage::RefUpdate;
+
+/// Name of the file updates are written to, under the storage path.
+pub const UPDATES_FILE: &str = ".updates";
+/// Name of the file holding the updates written before the last rotation.
+pub const ROTATED_FILE: &str = ".updates.old";
+/// Size the updates file can reach before it's rotated.
+pub const MAX_SIZE: u64 = 4 * 1024 * 1024;
+/// How often subscribers check for new updates while waiting.
+pub const POLL_INTERVAL: time::Duration = time::Duration::from_millis(100);
+
+/// Reference updates applied to a repository.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Update {
+    /// The repository updated.
+    pub rid: RepoId,
+    /// The node the updates were fetched from.
+    pub remote: NodeId,
+    /// When the updates were applied.
+    pub timestamp: Timestamp,
+    /// The updated references.
+    pub updated: Vec<RefUpdate>,
+}
+
+impl Update {
+    /// Create a new update, applied now.
+    pub fn new(rid: RepoId, remote: NodeId, updated: Vec<RefUpdate>) -> Self {
+        Self {
+            rid,
+            remote,
+            timestamp: LocalTime::now().into(),
+            updated,
+        }
+    }
+}
+
+/// Writes updates for subscribers. Clones share the same file.
+#[derive(Debug, Clone)]
+pub struct Notifier {
+    path: PathBuf,
+    /// Held while writing, so that the file isn't rotated under a concurrent write.
+    lock: Arc<Mutex<()>>,
+}
+
+impl Notifier {
+    /// Create a notifier writing to the updates file of the given storage path.
+    pub fn new(storage: impl AsRef<Path>) -> Self {
+        Self {
+            path: storage.as_ref().join(UPDATES_FILE),
+            lock: Arc::default(),
+        }
+    }
+
+    /// Notify subscribers of an update.
+    pub fn notify(&self, update: &Update) -> io::Result<()> {
+        let mut line = serde_json::to_vec(update)?;
+        line.push(b'\n');
+
+        let _guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+        match fs::metadata(&self.path) {
+            Ok(meta) if meta.len() >= MAX_SIZE => {
+                fs::rename(&self.path, self.path.with_file_name(ROTATED_FILE))?;
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        // N.b. the line is written at once, so that subscribers never see it interleaved
+        // with another one.
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(&line)
+    }
+}
+
+/// Follows the updates written by a [`Notifier`].
+#[derive(Debug)]
+pub struct Subscriber {
+    path: PathBuf,
+    /// The file being followed, if it exists.
+    file: Option<File>,
+    /// Data read that doesn't end with a newline yet.
+    partial: Vec<u8>,
+}
+
+impl Subscriber {
+    /// Follow the updates file of the given storage path. Only updates written from now
+    /// on are returned.
+    pub fn new(storage: impl AsRef<Path>) -> io::Result<Self> {
+        let path = storage.as_ref().join(UPDATES_FILE);
+        let file = match File::open(&path) {
+            Ok(mut file) => {
+                file.seek(io::SeekFrom::End(0))?;
+                Some(file)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            path,
+            file,
+            partial: Vec::new(),
+        })
+    }
+
+    /// Get the updates written since the last call, without blocking.
+    pub fn updates(&mut self) -> io::Result<Vec<Update>> {
+        let mut updates = Vec::new();
+
+        // Read what's left of the file we're following first, since it may have been
+        // rotated since.
+        if let Some(file) = &mut self.file {
+            read(file, &mut self.partial, &mut updates)?;
+        }
+        let current = match fs::metadata(&self.path) {
+            Ok(meta) => Some(meta.ino()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        let following = match &self.file {
+            Some(file) => Some(file.metadata()?.ino()),
+            None => None,
+        };
+        if current.is_some() && current != following {
+            let mut file = File::open(&self.path)?;
+
+            self.partial.clear();
+            read(&mut file, &mut self.partial, &mut updates)?;
+            self.file = Some(file);
+        }
+        Ok(updates)
+    }
+
+    /// Wait for updates, for up to the given timeout. Returns an empty list if there were
+    /// none.
+    pub fn wait(&mut self, timeout: time::Duration) -> io::Result<Vec<Update>> {
+        let start = time::Instant::now();
+        loop {
+            let updates = self.updates()?;
+            if !updates.is_empty() || start.elapsed() >= timeout {
+                return Ok(updates);
+            }
+            thread::sleep(POLL_INTERVAL.min(timeout.saturating_sub(start.elapsed())));
+        }
+    }
+}
+
+/// Read the complete lines available from `file`, keeping any trailing partial line.
+fn read(file: &mut File, partial: &mut Vec<u8>, updates: &mut Vec<Update>) -> io::Result<()> {
+    file.read_to_end(partial)?;
+
+    let Some(end) = partial.iter().rposition(|b| *b == b'\n') else {
+        return Ok(());
+    };
+    for line in partial[..end].split(|b| *b == b'\n') {
+        match serde_json::from_slice(line) {
+            Ok(update) => updates.push(update),
+            Err(e) => log::warn!(target: "storage", "Skipping invalid update notification: {e}"),
+        }
+    }
+    partial.drain(..=end);
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod test {
+    use crate::git::refname;
+    use crate::test::arbitrary;
+
+    use super::*;
+
+    fn update(seed: usize) -> Update {
+        Update::new(
+            arbitrary::gen(seed),
+            arbitrary::gen(seed),
+            vec![RefUpdate::Created {
+                name: refname!("refs/heads/master"),
+                oid: arbitrary::oid(),
+            }],
+        )
+    }
+
+    #[test]
+    fn test_updates() {
+        let tmp = tempfile::tempdir().unwrap();
+        let notifier = Notifier::new(tmp.path());
+        let first = update(1);
+
+        // Updates written before subscribing are not returned.
+        notifier.notify(&first).unwrap();
+        let mut subscriber = Subscriber::new(tmp.path()).unwrap();
+        assert!(subscriber.updates().unwrap().is_empty());
+
+        let (second, third) = (update(2), update(3));
+        notifier.notify(&second).unwrap();
+        notifier.notify(&third).unwrap();
+        assert_eq!(subscriber.updates().unwrap(), vec![second, third]);
+        assert!(subscriber
+            .wait(time::Duration::from_millis(1))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_updates_rotation() {
+        let tmp = tempfile::tempdir().unwrap();
+        let notifier = Notifier::new(tmp.path());
+        let mut subscriber = Subscriber::new(tmp.path()).unwrap();
+        let (first, second, third) = (update(1), update(2), update(3));
+
+        notifier.notify(&first).unwrap();
+        assert_eq!(subscriber.updates().unwrap(), vec![first]);
+
+        // Fill up the file, so that the next update rotates it.
+        File::options()
+            .write(true)
+            .open(tmp.path().join(UPDATES_FILE))
+            .unwrap()
+            .set_len(MAX_SIZE)
+            .unwrap();
+        notifier.notify(&second).unwrap();
+        notifier.notify(&third).unwrap();
+
+        assert!(tmp.path().join(ROTATED_FILE).exists());
+        assert_eq!(subscriber.updates().unwrap(), vec![second, third]);
+    }
+}